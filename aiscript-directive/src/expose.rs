@@ -0,0 +1,91 @@
+use serde_json::Value;
+
+use crate::{Directive, DirectiveParams, FromDirective, route::Auth};
+
+/// An agent served as a chat endpoint by the server, declared with
+/// `@expose("/support")` or `@expose(path="/support", auth="jwt")`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expose {
+    pub path: String,
+    // The auth of the endpoint, "jwt" or "basic", public if unset.
+    pub auth: Auth,
+}
+
+impl FromDirective for Expose {
+    fn from_directive(directive: Directive) -> Result<Self, String> {
+        let (path, auth) = match &directive.params {
+            DirectiveParams::Array(values) => match values.as_slice() {
+                [Value::String(path)] => (path, None),
+                _ => return Err("Expect @expose(\"<path>\").".into()),
+            },
+            DirectiveParams::KeyValue(params) => {
+                if let Some(key) = params
+                    .keys()
+                    .find(|key| !matches!(key.as_str(), "path" | "auth"))
+                {
+                    return Err(format!(
+                        "Invalid @expose parameter '{key}', expect path or auth."
+                    ));
+                }
+                match directive.get_arg_value("path") {
+                    Some(Value::String(path)) => (path, directive.get_arg_value("auth")),
+                    _ => return Err("Expect @expose(path=\"<path>\").".into()),
+                }
+            }
+            DirectiveParams::Directives(_) => return Err("Expect @expose(\"<path>\").".into()),
+        };
+        if !path.starts_with('/') {
+            return Err(format!(
+                "The path of @expose must start with '/', got '{path}'."
+            ));
+        }
+        let auth = match auth {
+            None => Auth::None,
+            Some(Value::String(auth)) if auth == "jwt" => Auth::Jwt,
+            Some(Value::String(auth)) if auth == "basic" => Auth::Basic,
+            Some(_) => return Err("The auth of @expose must be \"jwt\" or \"basic\".".into()),
+        };
+        Ok(Expose {
+            path: path.trim_end_matches('/').to_owned(),
+            auth,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_lexer::Scanner;
+
+    use super::*;
+    use crate::DirectiveParser;
+
+    fn parse(source: &str) -> Result<Expose, String> {
+        let mut scanner = Scanner::new(source);
+        let directive = DirectiveParser::new(&mut scanner)
+            .parse_directive()
+            .unwrap();
+        Expose::from_directive(directive)
+    }
+
+    #[test]
+    fn test_expose_directive() {
+        assert_eq!(
+            parse(r#"@expose("/support/")"#),
+            Ok(Expose {
+                path: "/support".into(),
+                auth: Auth::None,
+            })
+        );
+        assert_eq!(
+            parse(r#"@expose(path="/chat", auth="jwt")"#),
+            Ok(Expose {
+                path: "/chat".into(),
+                auth: Auth::Jwt,
+            })
+        );
+        assert!(parse(r#"@expose("chat")"#).is_err());
+        assert!(parse(r#"@expose(path="/chat", auth="oauth")"#).is_err());
+        assert!(parse(r#"@expose(path="/chat", history=true)"#).is_err());
+        assert!(parse(r#"@expose(path="/chat", stream=true)"#).is_err());
+    }
+}
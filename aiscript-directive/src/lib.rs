@@ -5,7 +5,9 @@ use aiscript_lexer::{Scanner, TokenType};
 use serde_json::Value;
 
 pub use validator::Validator;
+pub mod expose;
 pub mod route;
+pub mod schedule;
 pub mod validator;
 
 pub trait FromDirective {
@@ -129,6 +131,25 @@ impl<'a, 'b> DirectiveParser<'a, 'b> {
                 self.scanner.advance(); // consume ','
             }
             Some(DirectiveParams::Directives(directives))
+        } else if self.scanner.check(TokenType::String) || self.scanner.check(TokenType::Number) {
+            // Parse positional values, e.g. @schedule("0 9 * * 1") or @retry(3)
+            let mut values = Vec::new();
+            loop {
+                values.push(self.parse_value()?);
+                if !self.scanner.check(TokenType::Comma) {
+                    break;
+                }
+                self.scanner.advance(); // consume ','
+            }
+            Some(DirectiveParams::Array(values))
+        } else if self.scanner.check(TokenType::Identifier)
+            && self.scanner.check_next(TokenType::CloseParen)
+        {
+            // A single name, e.g. @fallback(cached_reply)
+            self.scanner.advance();
+            Some(DirectiveParams::Array(vec![Value::String(
+                self.scanner.previous.lexeme.to_owned(),
+            )]))
         } else if self.scanner.check(TokenType::Identifier) {
             // Parse key-value parameters
             let mut params = HashMap::new();
@@ -170,6 +191,29 @@ impl<'a, 'b> DirectiveParser<'a, 'b> {
         Some(values)
     }
 
+    // Parse the entries of an object after its '{', e.g. `{id: 1, "name": "a"}`.
+    fn parse_object(&mut self) -> Option<Value> {
+        let mut object = serde_json::Map::new();
+        while !self.scanner.check(TokenType::CloseBrace) {
+            if !self.scanner.match_token(TokenType::Identifier)
+                && !self.scanner.match_token(TokenType::String)
+            {
+                self.scanner.error_at_current("Expect object key.");
+                return None;
+            }
+            let key = self.scanner.previous.lexeme.to_owned();
+            self.scanner
+                .consume(TokenType::Colon, "Expect ':' after object key.");
+            object.insert(key, self.parse_value()?);
+            if !self.scanner.match_token(TokenType::Comma) {
+                break;
+            }
+        }
+        self.scanner
+            .consume(TokenType::CloseBrace, "Expect '}' at the end of object.");
+        Some(Value::Object(object))
+    }
+
     fn parse_value(&mut self) -> Option<Value> {
         let token = self.scanner.current;
         self.scanner.advance();
@@ -199,6 +243,8 @@ impl<'a, 'b> DirectiveParser<'a, 'b> {
             }
             TokenType::True => Some(Value::Bool(true)),
             TokenType::False => Some(Value::Bool(false)),
+            TokenType::Nil => Some(Value::Null),
+            TokenType::OpenBrace => self.parse_object(),
             TokenType::OpenBracket => {
                 let values = self.parse_array()?;
                 Some(Value::Array(values))
@@ -261,6 +307,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_directive_with_positional_values() {
+        let directive = parse_single_directive("@retry(3)").unwrap();
+        assert_eq!(directive.name, "retry");
+        match directive.params {
+            DirectiveParams::Array(values) => assert_eq!(values, vec![json!(3)]),
+            _ => panic!("Expected Array parameters"),
+        }
+    }
+
     #[test]
     fn test_directive_with_key_value() {
         let directive = parse_single_directive(r#"@validate(min=1, max=10, name="test")"#).unwrap();
@@ -1,17 +1,62 @@
+use std::{net::IpAddr, time::Duration};
+
 use docs::Docs;
+use ipnet::IpNet;
 use serde_json::Value;
 
 mod docs;
-use crate::{Directive, FromDirective};
+use crate::{Directive, DirectiveParams, FromDirective};
+
+// The longest `@timeout` of a route, a day.
+const MAX_TIMEOUT_SECS: f64 = 86_400.0;
+
+// The most slots or queued requests of `@concurrency`, within the permits of
+// a semaphore.
+const MAX_CONCURRENCY: usize = 1 << 20;
 
 #[derive(Debug, Clone, Default)]
 pub struct RouteAnnotation {
     pub auth: Auth,
     pub docs: Option<Docs>,
     pub sso_provider: Option<SsoProvider>,
+    // The deadline of the request, set by `@timeout(seconds=N)`.
+    pub timeout: Option<Duration>,
+    // The function serving a degraded response when the handler fails,
+    // set by `@fallback(fn)`.
+    pub fallback: Option<String>,
+    // The client networks allowed by `@allow_ips([...])`, everyone if unset.
+    pub allow_ips: Option<Vec<IpNet>>,
+    // The client networks rejected by `@deny_ips([...])`.
+    pub deny_ips: Option<Vec<IpNet>>,
+    // The bound of simultaneous executions, set by `@concurrency(max=N)`.
+    pub concurrency: Option<Concurrency>,
+    // The `Link` values sent in a `103 Early Hints` response before the
+    // handler runs, set by `@early_hints([...])`.
+    pub early_hints: Option<Vec<String>>,
+    // The response served instead of running the handler when the server
+    // runs with `--mock`, set by `@mock(example=..., status=N)`.
+    pub mock: Option<Mock>,
+    // Whether the JSON responses are wrapped in `{data, error, meta}`, set by
+    // `@envelope` or `@envelope(enabled=false)`, `[responses]` decides if unset.
+    pub envelope: Option<bool>,
+}
+
+/// The example response of an endpoint in mock mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mock {
+    pub example: Value,
+    pub status: u16,
 }
 
-#[derive(Debug, Copy, Clone, Default)]
+/// At most `max` executions of the endpoint at once, `queue` more requests
+/// wait for a slot and the others are rejected.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Concurrency {
+    pub max: usize,
+    pub queue: usize,
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
 pub enum Auth {
     Jwt,
     Basic,
@@ -64,6 +109,24 @@ impl RouteAnnotation {
         matches!(self.auth, Auth::Jwt)
     }
 
+    pub fn has_ip_acl(&self) -> bool {
+        self.allow_ips.is_some() || self.deny_ips.is_some()
+    }
+
+    /// Whether the client IP passes the allow and deny lists, the deny list
+    /// wins. An unknown IP only passes if there is no allow list.
+    pub fn is_ip_allowed(&self, ip: Option<IpAddr>) -> bool {
+        let matches =
+            |nets: &[IpNet]| ip.is_some_and(|ip| nets.iter().any(|net| net.contains(&ip)));
+        if self.deny_ips.as_deref().is_some_and(matches) {
+            return false;
+        }
+        match &self.allow_ips {
+            Some(nets) => matches(nets),
+            None => true,
+        }
+    }
+
     pub fn or(mut self, other: &RouteAnnotation) -> Self {
         if matches!(self.auth, Auth::None) {
             self.auth = other.auth;
@@ -71,6 +134,27 @@ impl RouteAnnotation {
         if self.docs.is_none() {
             self.docs = other.docs.clone()
         }
+        if self.timeout.is_none() {
+            self.timeout = other.timeout;
+        }
+        if self.fallback.is_none() {
+            self.fallback = other.fallback.clone();
+        }
+        if self.allow_ips.is_none() {
+            self.allow_ips = other.allow_ips.clone();
+        }
+        if self.deny_ips.is_none() {
+            self.deny_ips = other.deny_ips.clone();
+        }
+        if self.concurrency.is_none() {
+            self.concurrency = other.concurrency;
+        }
+        if self.early_hints.is_none() {
+            self.early_hints = other.early_hints.clone();
+        }
+        if self.envelope.is_none() {
+            self.envelope = other.envelope;
+        }
         self
     }
 }
@@ -109,6 +193,112 @@ impl RouteAnnotation {
                     return Err("@sso required 'provider' argument.".into());
                 }
             }
+            "timeout" => {
+                if self.timeout.is_some() {
+                    return Err("Duplicate @timeout directive".into());
+                }
+                match directive.get_arg_value("seconds").and_then(|v| v.as_f64()) {
+                    Some(seconds) if seconds > 0.0 && seconds <= MAX_TIMEOUT_SECS => {
+                        self.timeout = Duration::try_from_secs_f64(seconds).ok();
+                    }
+                    Some(_) => {
+                        return Err(format!(
+                            "@timeout 'seconds' must be positive and at most {MAX_TIMEOUT_SECS}."
+                        ));
+                    }
+                    None => return Err("@timeout required 'seconds' argument.".into()),
+                }
+            }
+            "fallback" => {
+                if self.fallback.is_some() {
+                    return Err("Duplicate @fallback directive".into());
+                }
+                match &directive.params {
+                    DirectiveParams::Array(values) if values.len() == 1 => match &values[0] {
+                        Value::String(name) => self.fallback = Some(name.clone()),
+                        _ => return Err("@fallback required a function name.".into()),
+                    },
+                    _ => return Err("@fallback required a function name.".into()),
+                }
+            }
+            "allow_ips" | "deny_ips" => {
+                let list = if directive.name == "allow_ips" {
+                    &mut self.allow_ips
+                } else {
+                    &mut self.deny_ips
+                };
+                if list.is_some() {
+                    return Err(format!("Duplicate @{} directive", directive.name));
+                }
+                *list = Some(parse_ip_list(&directive)?);
+            }
+            "concurrency" => {
+                if self.concurrency.is_some() {
+                    return Err("Duplicate @concurrency directive".into());
+                }
+                let arg = |name: &'static str| {
+                    match directive.get_arg_value(name) {
+                    Some(value) => value
+                        .as_f64()
+                        .filter(|n| {
+                            n.fract() == 0.0 && *n >= 0.0 && *n <= MAX_CONCURRENCY as f64
+                        })
+                        .map(|n| Some(n as usize))
+                        .ok_or_else(|| {
+                            format!(
+                                "@concurrency '{name}' must be a positive integer at most {MAX_CONCURRENCY}."
+                            )
+                        }),
+                    None => Ok(None),
+                }
+                };
+                let max = match arg("max")? {
+                    Some(0) => return Err("@concurrency 'max' must be a positive integer.".into()),
+                    Some(max) => max,
+                    None => return Err("@concurrency required 'max' argument.".into()),
+                };
+                // As many requests as the slots wait by default
+                let queue = arg("queue")?.unwrap_or(max);
+                self.concurrency = Some(Concurrency { max, queue });
+            }
+            "early_hints" => {
+                if self.early_hints.is_some() {
+                    return Err("Duplicate @early_hints directive".into());
+                }
+                self.early_hints = Some(parse_links(&directive)?);
+            }
+            "mock" => {
+                if self.mock.is_some() {
+                    return Err("Duplicate @mock directive".into());
+                }
+                let Some(example) = directive.get_arg_value("example") else {
+                    return Err("@mock required 'example' argument.".into());
+                };
+                let status = match directive.get_arg_value("status") {
+                    Some(status) => status
+                        .as_u64()
+                        .filter(|status| (100..600).contains(status))
+                        .ok_or("@mock 'status' must be an HTTP status code.")?
+                        as u16,
+                    None => 200,
+                };
+                self.mock = Some(Mock {
+                    example: example.clone(),
+                    status,
+                });
+            }
+            "envelope" => {
+                if self.envelope.is_some() {
+                    return Err("Duplicate @envelope directive".into());
+                }
+                match (&directive.params, directive.get_arg_value("enabled")) {
+                    (DirectiveParams::KeyValue(params), None) if params.is_empty() => {
+                        self.envelope = Some(true);
+                    }
+                    (_, Some(Value::Bool(enabled))) => self.envelope = Some(*enabled),
+                    _ => return Err("@envelope 'enabled' must be a boolean.".into()),
+                }
+            }
             _ => {
                 return Err(format!("Invalid directive: @{}", directive.name));
             }
@@ -116,3 +306,272 @@ impl RouteAnnotation {
         Ok(())
     }
 }
+
+/// Parse a network in CIDR notation, a single IP is a network of its own.
+pub fn parse_ip_net(s: &str) -> Result<IpNet, String> {
+    s.parse::<IpNet>()
+        .or_else(|_| s.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid IP or CIDR: `{s}`"))
+}
+
+fn parse_ip_list(directive: &Directive) -> Result<Vec<IpNet>, String> {
+    match &directive.params {
+        DirectiveParams::Array(values) if !values.is_empty() => values
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => parse_ip_net(s),
+                _ => Err(format!("@{} required IP or CIDR strings.", directive.name)),
+            })
+            .collect(),
+        _ => Err(format!(
+            "@{} required a list of IPs or CIDRs.",
+            directive.name
+        )),
+    }
+}
+
+// The values of `Link` headers, e.g. `</app.css>; rel=preload; as=style`.
+fn parse_links(directive: &Directive) -> Result<Vec<String>, String> {
+    match &directive.params {
+        DirectiveParams::Array(values) if !values.is_empty() => values
+            .iter()
+            .map(|value| match value {
+                Value::String(link) if link.starts_with('<') && !link.contains(['\r', '\n']) => {
+                    Ok(link.clone())
+                }
+                _ => Err(format!(
+                    "@early_hints required links like '</app.css>; rel=preload', got {value}."
+                )),
+            })
+            .collect(),
+        _ => Err("@early_hints required a list of links.".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectiveParser;
+    use aiscript_lexer::Scanner;
+    use serde_json::json;
+
+    fn timeout_directive(seconds: Value) -> Directive {
+        Directive {
+            name: "timeout".into(),
+            params: DirectiveParams::KeyValue([("seconds".to_string(), seconds)].into()),
+            line: 1,
+        }
+    }
+
+    #[test]
+    fn test_timeout_directive() {
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(timeout_directive(json!(2.5)))
+            .unwrap();
+        assert_eq!(annotation.timeout, Some(Duration::from_millis(2500)));
+        assert!(
+            annotation
+                .parse_directive(timeout_directive(json!(1)))
+                .is_err()
+        );
+
+        let mut annotation = RouteAnnotation::default();
+        assert!(
+            annotation
+                .parse_directive(timeout_directive(json!(0)))
+                .is_err()
+        );
+        assert!(
+            annotation
+                .parse_directive(timeout_directive(json!(1e300)))
+                .is_err()
+        );
+        let route = RouteAnnotation {
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        assert_eq!(
+            RouteAnnotation::default().or(&route).timeout,
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn test_fallback_directive() {
+        let mut scanner = Scanner::new("@fallback(cached_reply) @fallback(\"reply\") @fallback");
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut annotation = RouteAnnotation::default();
+        let mut directives = directives.into_iter();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(annotation.fallback.as_deref(), Some("cached_reply"));
+        assert!(
+            annotation
+                .parse_directive(directives.next().unwrap())
+                .is_err()
+        );
+
+        let mut annotation = RouteAnnotation::default();
+        assert!(
+            annotation
+                .parse_directive(directives.next().unwrap())
+                .is_err()
+        );
+        assert_eq!(
+            annotation
+                .or(&RouteAnnotation {
+                    fallback: Some("reply".into()),
+                    ..Default::default()
+                })
+                .fallback
+                .as_deref(),
+            Some("reply")
+        );
+    }
+
+    #[test]
+    fn test_concurrency_directive() {
+        let mut scanner = Scanner::new(
+            "@concurrency(max=4) @concurrency(max=2, queue=0) @concurrency(max=0) @concurrency(queue=1) @concurrency(max=10000000000000000000) @concurrency(max=1, queue=2000000)",
+        );
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut directives = directives.into_iter();
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(
+            annotation.concurrency,
+            Some(Concurrency { max: 4, queue: 4 })
+        );
+
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(
+            annotation.concurrency,
+            Some(Concurrency { max: 2, queue: 0 })
+        );
+        for directive in directives {
+            assert!(
+                RouteAnnotation::default()
+                    .parse_directive(directive)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_early_hints_directive() {
+        let mut scanner = Scanner::new(
+            r#"@early_hints(["</app.css>; rel=preload; as=style", "</app.js>; rel=preload; as=script"]) @early_hints(["/app.css"]) @early_hints([])"#,
+        );
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut directives = directives.into_iter();
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(
+            annotation.early_hints.as_deref(),
+            Some(
+                &[
+                    "</app.css>; rel=preload; as=style".to_string(),
+                    "</app.js>; rel=preload; as=script".to_string()
+                ][..]
+            )
+        );
+        for directive in directives {
+            assert!(
+                RouteAnnotation::default()
+                    .parse_directive(directive)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_mock_directive() {
+        let mut scanner = Scanner::new(
+            r#"@mock(example={id: 1, "name": "Ada", tags: ["admin"], manager: nil}, status=201) @mock(status=200) @mock(example=1, status=42)"#,
+        );
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut directives = directives.into_iter();
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(
+            annotation.mock,
+            Some(Mock {
+                example: json!({"id": 1, "name": "Ada", "tags": ["admin"], "manager": null}),
+                status: 201,
+            })
+        );
+        for directive in directives {
+            assert!(
+                RouteAnnotation::default()
+                    .parse_directive(directive)
+                    .is_err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_envelope_directive() {
+        let mut scanner =
+            Scanner::new(r#"@envelope @envelope(enabled=false) @envelope(enabled="no")"#);
+        let mut directives = DirectiveParser::new(&mut scanner)
+            .parse_directives()
+            .into_iter();
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(annotation.envelope, Some(true));
+        let mut annotation = RouteAnnotation::default();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert_eq!(annotation.envelope, Some(false));
+        assert!(
+            RouteAnnotation::default()
+                .parse_directive(directives.next().unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_ip_acl_directives() {
+        let mut scanner = Scanner::new(
+            r#"@allow_ips(["10.0.0.0/8", "::1"]) @deny_ips(["10.0.0.13"]) @allow_ips(["nope"])"#,
+        );
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut directives = directives.into_iter();
+        let mut annotation = RouteAnnotation::default();
+        assert!(annotation.is_ip_allowed(None));
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        annotation
+            .parse_directive(directives.next().unwrap())
+            .unwrap();
+        assert!(annotation.has_ip_acl());
+
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        assert!(annotation.is_ip_allowed(ip("10.1.2.3")));
+        assert!(annotation.is_ip_allowed(ip("::1")));
+        assert!(!annotation.is_ip_allowed(ip("10.0.0.13")));
+        assert!(!annotation.is_ip_allowed(ip("192.168.1.1")));
+        assert!(!annotation.is_ip_allowed(None));
+
+        let mut annotation = RouteAnnotation::default();
+        assert_eq!(
+            annotation.parse_directive(directives.next().unwrap()),
+            Err("Invalid IP or CIDR: `nope`".to_string())
+        );
+    }
+}
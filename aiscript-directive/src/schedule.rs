@@ -0,0 +1,255 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use serde_json::Value;
+
+use crate::{Directive, DirectiveParams, FromDirective};
+
+const MONTH_NAMES: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// A function or agent run periodically, declared with
+/// `@schedule("0 9 * * 1")` or `@schedule("0 9 * * 1", "<agent input>")`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub cron: Cron,
+    // The input message of a scheduled agent.
+    pub input: Option<String>,
+}
+
+impl FromDirective for Schedule {
+    fn from_directive(directive: Directive) -> Result<Self, String> {
+        let (expression, input) = match &directive.params {
+            DirectiveParams::Array(values) => match values.as_slice() {
+                [Value::String(expression)] => (expression, None),
+                [Value::String(expression), Value::String(input)] => (expression, Some(input)),
+                _ => return Err("Expect @schedule(\"<cron expression>\").".into()),
+            },
+            DirectiveParams::KeyValue(_) => {
+                match (
+                    directive.get_arg_value("cron"),
+                    directive.get_arg_value("input"),
+                ) {
+                    (Some(Value::String(expression)), None) => (expression, None),
+                    (Some(Value::String(expression)), Some(Value::String(input))) => {
+                        (expression, Some(input))
+                    }
+                    _ => return Err("Expect @schedule(\"<cron expression>\").".into()),
+                }
+            }
+            DirectiveParams::Directives(_) => {
+                return Err("Expect @schedule(\"<cron expression>\").".into());
+            }
+        };
+        Ok(Schedule {
+            cron: Cron::parse(expression)?,
+            input: input.cloned(),
+        })
+    }
+}
+
+/// A standard 5-field cron expression: minute, hour, day of month, month and day of week.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+    pub expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Whether the day of month or day of week field is `*`.
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> Result<Self, String> {
+        let fields = expression.split_whitespace().collect::<Vec<_>>();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!(
+                "Invalid cron expression '{expression}', expect 5 fields: minute hour day month weekday."
+            ));
+        };
+        let field = |value: &str, min, max, names: &[&str]| {
+            parse_field(value, min, max, names)
+                .map_err(|err| format!("Invalid cron expression '{expression}': {err}"))
+        };
+        let weekdays = field(weekday, 0, 7, &WEEKDAY_NAMES)?;
+        Ok(Cron {
+            expression: expression.to_owned(),
+            minutes: field(minute, 0, 59, &[])?,
+            hours: field(hour, 0, 23, &[])?,
+            days: field(day, 1, 31, &[])?,
+            months: field(month, 1, 12, &MONTH_NAMES)?,
+            // 7 is Sunday as well
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            any_day: *day == "*",
+            any_weekday: *weekday == "*",
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        // Like cron, a day matches either field if both are restricted
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /// The next time after the given one the schedule fires.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut time = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        // Bounded so that an expression that never fires (e.g. Feb 30) terminates.
+        for _ in 0..100_000 {
+            let date = time.date();
+            if self.months & (1 << date.month()) == 0 {
+                let (year, month) = if date.month() == 12 {
+                    (date.year() + 1, 1)
+                } else {
+                    (date.year(), date.month() + 1)
+                };
+                time = NaiveDate::from_ymd_opt(year, month, 1)?.and_time(NaiveTime::MIN);
+            } else if !self.matches_day(date) {
+                time = date.succ_opt()?.and_time(NaiveTime::MIN);
+            } else if self.hours & (1 << time.hour()) == 0 {
+                time = time.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << time.minute()) == 0 {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+}
+
+// Parse a cron field into a bit set, e.g. `*/15`, `1-5`, `MON,WED`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |value: &str| -> Result<u32, String> {
+        let value = names
+            .iter()
+            .position(|name| name.eq_ignore_ascii_case(value))
+            .map(|i| i as u32 + min)
+            .or_else(|| value.parse::<u32>().ok())
+            .ok_or_else(|| format!("invalid value '{value}'"))?;
+        if value < min || value > max {
+            return Err(format!("value {value} is out of range {min}-{max}"));
+        }
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<u32>()
+                    .ok()
+                    .filter(|step| *step > 0)
+                    .ok_or_else(|| format!("invalid step '{step}'"))?,
+            ),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // `5/15` means from 5 to the max
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return Err(format!("invalid range '{range}'"));
+        }
+        for i in (start..=end).step_by(step as usize) {
+            bits |= 1 << i;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DirectiveParser;
+    use aiscript_lexer::Scanner;
+
+    fn datetime(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(Cron::parse("* * * * *").is_ok());
+        assert!(Cron::parse("*/15 9-17 * JAN-MAR mon,fri").is_ok());
+        assert!(Cron::parse("0 9 * *").is_err());
+        assert!(Cron::parse("60 * * * *").is_err());
+        assert!(Cron::parse("0 0 * * FOO").is_err());
+        assert!(Cron::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn test_next_after() {
+        // Every Monday at 9:00, 2025-01-01 is a Wednesday
+        let cron = Cron::parse("0 9 * * 1").unwrap();
+        assert_eq!(
+            cron.next_after(datetime("2025-01-01 10:30")),
+            Some(datetime("2025-01-06 09:00"))
+        );
+        assert_eq!(
+            cron.next_after(datetime("2025-01-06 09:00")),
+            Some(datetime("2025-01-13 09:00"))
+        );
+
+        let cron = Cron::parse("*/15 * * * *").unwrap();
+        assert_eq!(
+            cron.next_after(datetime("2025-01-01 10:50")),
+            Some(datetime("2025-01-01 11:00"))
+        );
+
+        // Sunday can be written as 0 or 7
+        let cron = Cron::parse("0 0 * * 7").unwrap();
+        assert_eq!(
+            cron.next_after(datetime("2025-01-01 00:00")),
+            Some(datetime("2025-01-05 00:00"))
+        );
+
+        // Either the day of month or the day of week matches
+        let cron = Cron::parse("0 0 15 * MON").unwrap();
+        assert_eq!(
+            cron.next_after(datetime("2025-01-07 00:00")),
+            Some(datetime("2025-01-13 00:00"))
+        );
+
+        assert_eq!(
+            Cron::parse("0 0 30 2 *")
+                .unwrap()
+                .next_after(datetime("2025-01-01 00:00")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_schedule_directive() {
+        let mut scanner = Scanner::new(r#"@schedule("0 9 * * 1", "Write the weekly report")"#);
+        let directive = DirectiveParser::new(&mut scanner)
+            .parse_directive()
+            .unwrap();
+        let schedule = Schedule::from_directive(directive).unwrap();
+        assert_eq!(schedule.cron.expression, "0 9 * * 1");
+        assert_eq!(schedule.input.as_deref(), Some("Write the weekly report"));
+
+        let mut scanner = Scanner::new(r#"@schedule(cron="0 9 * * 1")"#);
+        let directive = DirectiveParser::new(&mut scanner)
+            .parse_directive()
+            .unwrap();
+        assert!(Schedule::from_directive(directive).unwrap().input.is_none());
+
+        let mut scanner = Scanner::new(r#"@schedule([1])"#);
+        let directive = DirectiveParser::new(&mut scanner)
+            .parse_directive()
+            .unwrap();
+        assert!(Schedule::from_directive(directive).is_err());
+    }
+}
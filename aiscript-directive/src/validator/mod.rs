@@ -73,9 +73,9 @@ pub struct StringValidator {
 }
 
 pub struct NumberValidator {
-    min: Option<f64>,
-    max: Option<f64>,
-    equal: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub equal: Option<f64>,
     strict_int: Option<bool>,
     strict_float: Option<bool>,
 }
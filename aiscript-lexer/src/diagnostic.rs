@@ -0,0 +1,232 @@
+use std::fmt::Write;
+
+use crate::{Token, TokenType};
+
+/// The code of an error at an invalid token, e.g. an unterminated string.
+pub const INVALID_TOKEN: &str = "E0001";
+/// The code of an error at the end of the source, e.g. a missing '}'.
+pub const UNEXPECTED_EOF: &str = "E0002";
+/// The code of a syntax error at a token.
+pub const SYNTAX_ERROR: &str = "E0003";
+/// The code of an error reported at a line rather than a token.
+pub const LINE_ERROR: &str = "E0004";
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// A compile error with its location, rendered with the source line
+/// and a caret under the offending token, e.g.
+///
+/// ```text
+/// error[E0003]: Expect ';' after expression.
+///  --> line 1:11
+///   |
+/// 1 | let a = 1 print(a);
+///   |           ^^^^^
+///   = help: try adding ';'
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub message: String,
+    pub line: u32,
+    // The byte offset and length of the token in the source, if known
+    pub span: Option<(usize, usize)>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    /// The diagnostic of an error at the token, the token is located by
+    /// its lexeme, which is a slice of the source.
+    pub fn at_token(source: &str, token: Token<'_>, message: &str) -> Self {
+        let code = match token.kind {
+            TokenType::Invalid => INVALID_TOKEN,
+            TokenType::Eof => UNEXPECTED_EOF,
+            _ => SYNTAX_ERROR,
+        };
+        let start = source.as_ptr() as usize;
+        let offset = (token.lexeme.as_ptr() as usize).wrapping_sub(start);
+        let span = if token.kind == TokenType::Eof {
+            Some((source.trim_end().len(), 1))
+        } else if token.kind != TokenType::Invalid && offset + token.lexeme.len() <= source.len() {
+            Some((offset, token.lexeme.len()))
+        } else {
+            None
+        };
+        Self {
+            code,
+            message: message.to_owned(),
+            line: token.line,
+            span,
+            help: suggest(message),
+        }
+    }
+
+    /// The diagnostic of an error at the line.
+    pub fn at_line(line: u32, message: &str) -> Self {
+        Self {
+            code: LINE_ERROR,
+            message: message.to_owned(),
+            line,
+            span: None,
+            help: suggest(message),
+        }
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Render the diagnostic with the snippet of the source, with the
+    /// ANSI colors of a terminal if requested.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{style}{text}{RESET}")
+            } else {
+                text.to_owned()
+            }
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(RED, &format!("error[{}]", self.code)),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        // The line of the span, or the reported line
+        let (line_number, line_start) = match self.span {
+            Some((offset, _)) => {
+                let offset = offset.min(source.len());
+                let line_start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+                (source[..offset].matches('\n').count() + 1, line_start)
+            }
+            None => {
+                let line_number = self.line as usize;
+                let line_start = source
+                    .split_inclusive('\n')
+                    .take(line_number.saturating_sub(1))
+                    .map(str::len)
+                    .sum();
+                (line_number, line_start)
+            }
+        };
+        let text = source[line_start.min(source.len())..]
+            .lines()
+            .next()
+            .unwrap_or_default();
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        let bar = paint(BLUE, "|");
+        match self.span {
+            Some((offset, _)) => {
+                let column = source[line_start..offset.min(source.len())].chars().count() + 1;
+                let _ = writeln!(
+                    out,
+                    "{gutter}{} line {line_number}:{column}",
+                    paint(BLUE, "-->")
+                );
+            }
+            None => {
+                let _ = writeln!(out, "{gutter}{} line {line_number}", paint(BLUE, "-->"));
+            }
+        }
+        let _ = writeln!(out, "{gutter} {bar}");
+        let _ = writeln!(
+            out,
+            "{} {bar} {text}",
+            paint(BLUE, &line_number.to_string())
+        );
+
+        if let Some((offset, len)) = self.span {
+            let offset = offset.min(source.len());
+            // Keep the tabs of the line so the caret lines up with the token
+            let padding: String = source[line_start..offset]
+                .chars()
+                .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect();
+            let width = source[offset..]
+                .lines()
+                .next()
+                .map_or(0, |rest| rest[..len.min(rest.len())].chars().count())
+                .max(1);
+            let _ = writeln!(
+                out,
+                "{gutter} {bar} {padding}{}",
+                paint(RED, &"^".repeat(width))
+            );
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{gutter} {} {help}", paint(CYAN, "= help:"));
+        }
+        out
+    }
+}
+
+// The help of the common "Expect 'x' ..." errors.
+fn suggest(message: &str) -> Option<String> {
+    let expected = message.strip_prefix("Expect '")?;
+    let (token, _) = expected.split_once('\'')?;
+    Some(format!("try adding '{token}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Scanner;
+
+    #[test]
+    fn test_render_at_token() {
+        let source = "let a = 1;\nlet b = 2 print(b);\n";
+        let mut scanner = Scanner::new(source);
+        // Advance to the `print` token
+        for _ in 0..10 {
+            scanner.advance();
+        }
+        assert_eq!(scanner.current.lexeme, "print");
+
+        let diagnostic = Diagnostic::at_token(
+            source,
+            scanner.current,
+            "Expect ';' after variable declaration.",
+        );
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0003]: Expect ';' after variable declaration.\n \
+             --> line 2:11\n  \
+             |\n\
+             2 | let b = 2 print(b);\n  \
+             |           ^^^^^\n  \
+             = help: try adding ';'\n"
+        );
+    }
+
+    #[test]
+    fn test_render_at_end() {
+        let source = "fn f() {\n  return 1;\n";
+        let diagnostic = Diagnostic::at_token(
+            source,
+            Token::new(TokenType::Eof, "", 3),
+            "Expect '}' after block.",
+        );
+        let rendered = diagnostic.render(source, false);
+        assert!(rendered.starts_with("error[E0002]: Expect '}' after block.\n --> line 2:12\n"));
+        assert!(rendered.contains("2 |   return 1;\n  |            ^\n"));
+    }
+
+    #[test]
+    fn test_render_at_line() {
+        let source = "let a = 1;\nlet b = 2;\n";
+        let diagnostic = Diagnostic::at_line(2, "Invalid statement.").with_help("remove it");
+        assert_eq!(
+            diagnostic.render(source, false),
+            "error[E0004]: Invalid statement.\n --> line 2\n  |\n2 | let b = 2;\n  = help: remove it\n"
+        );
+    }
+}
@@ -1,33 +1,79 @@
-use crate::{Token, TokenType};
+use std::io::IsTerminal;
+
+use crate::{Token, TokenType, diagnostic::Diagnostic};
 
 #[derive(Default)]
-pub struct ErrorReporter {
+pub struct ErrorReporter<'a> {
     pub panic_mode: bool,
     pub had_error: bool,
+    // The warnings are collected rather than printed, they don't fail the
+    // compilation and the caller decides whether to report them.
+    pub warnings: Vec<Warning>,
+    // The source the tokens are scanned from, the errors are rendered
+    // with a snippet of it on terminals.
+    source: &'a str,
+}
+
+/// A compile-time warning, e.g. an unused variable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Warning {
+    pub line: u32,
+    pub message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.line, self.message)
+    }
 }
 
-impl ErrorReporter {
+impl<'a> ErrorReporter<'a> {
     pub fn new() -> Self {
+        Self::with_source("")
+    }
+
+    pub fn with_source(source: &'a str) -> Self {
         Self {
             panic_mode: false,
             had_error: false,
+            warnings: Vec::new(),
+            source,
         }
     }
 
+    pub fn warning_with_line(&mut self, line: u32, message: String) {
+        self.warnings.push(Warning { line, message });
+    }
+
     pub fn error_with_line(&mut self, line: u32, message: &str) {
         if self.panic_mode {
             return;
         }
         self.panic_mode = true;
         self.had_error = true;
-        eprintln!("[line {}] Error: {}", line, message);
+        if !self.report(Diagnostic::at_line(line, message)) {
+            eprintln!("[line {}] Error: {}", line, message);
+        }
     }
 
     pub fn error_at(&mut self, token: Token<'_>, message: &str) {
+        self.error_at_with_help(token, message, None);
+    }
+
+    /// Report the error at the token, with the help suggesting how to fix it.
+    pub fn error_at_with_help(&mut self, token: Token<'_>, message: &str, help: Option<&str>) {
         if self.panic_mode {
             return;
         }
         self.panic_mode = true;
+        self.had_error = true;
+        let mut diagnostic = Diagnostic::at_token(self.source, token, message);
+        if let Some(help) = help {
+            diagnostic = diagnostic.with_help(help);
+        }
+        if self.report(diagnostic) {
+            return;
+        }
         eprint!("[line {}] Error", token.line);
         if token.kind == TokenType::Eof {
             eprint!(" at end");
@@ -37,6 +83,17 @@ impl ErrorReporter {
             eprint!(" at '{}'", token.lexeme);
         }
         eprintln!(": {message}");
-        self.had_error = true;
+    }
+
+    // Print the diagnostic with the source snippet if stderr is a terminal,
+    // returns false to fall back to the plain `[line N] Error` message.
+    fn report(&self, diagnostic: Diagnostic) -> bool {
+        let stderr = std::io::stderr();
+        if self.source.is_empty() || !stderr.is_terminal() {
+            return false;
+        }
+        let color = std::env::var_os("NO_COLOR").is_none();
+        eprint!("{}", diagnostic.render(self.source, color));
+        true
     }
 }
@@ -5,9 +5,11 @@ use std::{
     str::CharIndices,
 };
 
-pub use error_reporter::ErrorReporter;
+pub use diagnostic::Diagnostic;
+pub use error_reporter::{ErrorReporter, Warning};
 
 mod character_tests;
+mod diagnostic;
 mod error_reporter;
 mod fstring_tests;
 mod peakable;
@@ -35,6 +37,10 @@ pub enum TokenType {
     Colon,      // :
     Percent,    // %
     Pipe,       // |
+    Ampersand,  // &
+    Caret,      // ^
+    Tilde,      // ~
+    TildeSlash, // ~/
     Bang,       // !
     Question,   // ?
     At,         // @
@@ -57,6 +63,10 @@ pub enum TokenType {
     Less,         // <
     LessEqual,    // <=
 
+    // Bitwise shifts
+    LessLess,       // <<
+    GreaterGreater, // >>
+
     // Compound assignment
     PlusEqual,    // +=
     MinusEqual,   // -=
@@ -99,6 +109,7 @@ pub enum TokenType {
     Let,
     Use,
     While,
+    Yield,
 
     // AI-specific keywords
     AI,
@@ -106,6 +117,7 @@ pub enum TokenType {
     Agent,
 
     // Special tokens
+    Pragma,  // #strict
     Invalid, // Invalid token error
     Eof,     // End of file
 }
@@ -182,6 +194,7 @@ impl<'a> Token<'a> {
                 | TokenType::Return
                 | TokenType::Use
                 | TokenType::While
+                | TokenType::Yield
         )
     }
 
@@ -204,11 +217,13 @@ impl<'a> Token<'a> {
                 | TokenType::Self_
                 | TokenType::Super
                 | TokenType::Pipe
+                | TokenType::Tilde
         )
     }
 }
 
 // Lexer for tokenizing source code
+#[derive(Clone)]
 struct Lexer<'a> {
     // The complete source code being scanned
     source: &'a str,
@@ -226,13 +241,13 @@ struct Lexer<'a> {
 
 pub struct Scanner<'a> {
     lexer: peakable::Peekable<Lexer<'a>>,
-    error_reporter: ErrorReporter,
+    error_reporter: ErrorReporter<'a>,
     pub current: Token<'a>,
     pub previous: Token<'a>,
 }
 
-impl Deref for Scanner<'_> {
-    type Target = ErrorReporter;
+impl<'a> Deref for Scanner<'a> {
+    type Target = ErrorReporter<'a>;
 
     fn deref(&self) -> &Self::Target {
         &self.error_reporter
@@ -313,13 +328,10 @@ impl<'a> Lexer<'a> {
                     self.line += 1;
                     self.advance();
                 }
-                '/' => {
-                    if self.next2() == "//" {
-                        while matches!(self.peek(), Some(c) if c != '\n') {
-                            self.advance();
-                        }
-                    } else {
-                        return;
+                // `//` always starts a comment, floor division is `~/`
+                '/' if self.next2() == "//" => {
+                    while matches!(self.peek(), Some(c) if c != '\n') {
+                        self.advance();
                     }
                 }
                 _ => return,
@@ -455,6 +467,7 @@ impl<'a> Lexer<'a> {
             "let" => TokenType::Let,
             "use" => TokenType::Use,
             "while" => TokenType::While,
+            "yield" => TokenType::Yield,
             _ => TokenType::Identifier,
         };
 
@@ -484,6 +497,20 @@ impl<'a> Lexer<'a> {
             '@' => self.make_token(TokenType::At),
             '$' => self.make_token(TokenType::Dollar),
             '?' => self.make_token(TokenType::Question),
+            '&' => self.make_token(TokenType::Ampersand),
+            '^' => self.make_token(TokenType::Caret),
+            // Floor division, `//` starts a comment
+            '~' if self.peek() == Some('/') => {
+                self.advance();
+                self.make_token(TokenType::TildeSlash)
+            }
+            '~' => self.make_token(TokenType::Tilde),
+            '#' if matches!(self.peek(), Some(c) if c.is_alphabetic()) => {
+                while matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_') {
+                    self.advance();
+                }
+                self.make_token(TokenType::Pragma)
+            }
             '_' => {
                 // Check if the next character is not alphanumeric or another underscore
                 if !matches!(self.peek(), Some(c) if c.is_alphanumeric() || c == '_') {
@@ -601,18 +628,26 @@ impl<'a> Lexer<'a> {
                 self.make_token(kind)
             }
             '<' => {
-                let kind = if self.peek() == Some('=') {
+                let p = self.peek();
+                let kind = if p == Some('=') {
                     self.advance();
                     TokenType::LessEqual
+                } else if p == Some('<') {
+                    self.advance();
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
                 self.make_token(kind)
             }
             '>' => {
-                let kind = if self.peek() == Some('=') {
+                let p = self.peek();
+                let kind = if p == Some('=') {
                     self.advance();
                     TokenType::GreaterEqual
+                } else if p == Some('>') {
+                    self.advance();
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -791,7 +826,7 @@ impl<'a> Scanner<'a> {
             lexer: peakable::Peekable::new(Lexer::new(source)),
             current: Token::default(),
             previous: Token::default(),
-            error_reporter: ErrorReporter::new(),
+            error_reporter: ErrorReporter::with_source(source),
         }
     }
 
@@ -822,7 +857,12 @@ impl<'a> Scanner<'a> {
                     Some('\"') => '\"',
                     Some('0') => '\0',
                     Some(ch) => {
-                        self.error(&format!("Invalid escape sequence: \\{}", ch));
+                        let previous = self.previous;
+                        self.error_at_with_help(
+                            previous,
+                            &format!("Invalid escape sequence: \\{}", ch),
+                            Some("the valid escapes are \\n \\r \\t \\\\ \\' \\\" \\0"),
+                        );
                         return None;
                     }
                     None => {
@@ -894,6 +934,16 @@ impl<'a> Scanner<'a> {
         self.peek_next().map(|t| t.kind == kind) == Some(true)
     }
 
+    /// The nth token after the current one without consuming them,
+    /// the next token is the 0th.
+    pub fn peek_nth(&mut self, n: usize) -> Option<Token<'a>> {
+        let next = *self.lexer.peek()?;
+        match n {
+            0 => Some(next),
+            n => self.lexer.iter.clone().nth(n - 1),
+        }
+    }
+
     pub fn is_at_end(&self) -> bool {
         self.current.kind == TokenType::Eof
     }
@@ -220,6 +220,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bitwise_operators() {
+        let source = "& | ^ ~ ~/ << >> < >";
+        let scanner = Lexer::new(source);
+        let operators: Vec<TokenType> = scanner
+            .map(|t| t.kind)
+            .filter(|k| *k != TokenType::Eof)
+            .collect();
+
+        assert_eq!(
+            operators,
+            vec![
+                TokenType::Ampersand,
+                TokenType::Pipe,
+                TokenType::Caret,
+                TokenType::Tilde,
+                TokenType::TildeSlash,
+                TokenType::LessLess,
+                TokenType::GreaterGreater,
+                TokenType::Less,
+                TokenType::Greater,
+            ]
+        );
+    }
+
     #[test]
     fn test_line_counting() {
         let source = "line1\nline2\n\nline4";
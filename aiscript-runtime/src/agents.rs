@@ -0,0 +1,344 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aiscript_directive::{
+    DirectiveParser, FromDirective, Validator, expose::Expose, route::RouteAnnotation,
+};
+use serde_json::Value;
+use walkdir::WalkDir;
+
+use crate::{
+    ast::{
+        BodyKind, Endpoint, Field, FieldType, HttpMethod, PathSpec, RequestBody, Route, RouteMeta,
+    },
+    lexer::{Scanner, TokenType},
+    parser::HANDLER_SIGNATURE,
+};
+
+pub(crate) const AGENTS_DIR: &str = "agents";
+
+/// An agent declared with `@expose` in the agents directory.
+#[derive(Debug)]
+struct ExposedAgent {
+    name: String,
+    expose: Expose,
+}
+
+/// The chat endpoints of the agents declared with `@expose` in the agents
+/// directory, each file is served as a route.
+pub(crate) fn read_routes() -> Vec<(PathBuf, Route)> {
+    let mut routes = Vec::new();
+    for entry in WalkDir::new(AGENTS_DIR)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|ext| ext == "ai"))
+    {
+        match read_file_route(entry.path()) {
+            Ok(Some(route)) => routes.push((entry.path().to_owned(), route)),
+            Ok(None) => {}
+            Err(e) => eprintln!("Error reading agent file {:?}: {}", entry.path(), e),
+        }
+    }
+    routes
+}
+
+fn read_file_route(path: &Path) -> Result<Option<Route>, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let agents = parse_agents(&source)?;
+    if agents.is_empty() {
+        return Ok(None);
+    }
+    check_declarations(&source)?;
+    Ok(Some(Route {
+        annotation: RouteAnnotation::default(),
+        prefix: "/".to_owned(),
+        params: Vec::new(),
+        endpoints: agents
+            .iter()
+            .map(|agent| endpoint(agent, &source))
+            .collect(),
+        docs: String::new(),
+        meta: RouteMeta::default(),
+    }))
+}
+
+// Find the agents declared with `@expose`, the rest of the script is
+// checked when the endpoints are compiled.
+fn parse_agents(source: &str) -> Result<Vec<ExposedAgent>, String> {
+    let mut agents = Vec::new();
+    let mut scanner = Scanner::new(source);
+    scanner.advance();
+    while !scanner.is_at_end() {
+        if !scanner.check(TokenType::At) {
+            scanner.advance();
+            continue;
+        }
+        let mut exposes = Vec::new();
+        for directive in DirectiveParser::new(&mut scanner).parse_directives() {
+            if directive.name == "expose" {
+                exposes.push(Expose::from_directive(directive)?);
+            }
+        }
+        if exposes.is_empty() {
+            continue;
+        }
+        scanner.match_token(TokenType::Pub);
+        if !scanner.match_token(TokenType::Agent) {
+            return Err("@expose can only be applied to agents.".into());
+        }
+        scanner.consume(TokenType::Identifier, "Expect agent name.");
+        let name = scanner.previous.lexeme.to_owned();
+        agents.extend(exposes.into_iter().map(|expose| ExposedAgent {
+            name: name.clone(),
+            expose,
+        }));
+    }
+    Ok(agents)
+}
+
+// Reject the statements at the top level of the file, only the declarations
+// are allowed. The file is the script of the endpoints, its top level would
+// run again on every request.
+fn check_declarations(source: &str) -> Result<(), String> {
+    let mut scanner = Scanner::new(source);
+    scanner.advance();
+    let mut depth = 0usize;
+    // Whether the next token at the top level starts a new item
+    let mut item_start = true;
+    while !scanner.is_at_end() {
+        let token = scanner.current;
+        match token.kind {
+            TokenType::OpenParen | TokenType::OpenBrace | TokenType::OpenBracket => depth += 1,
+            TokenType::CloseParen | TokenType::CloseBracket => depth = depth.saturating_sub(1),
+            TokenType::CloseBrace => {
+                depth = depth.saturating_sub(1);
+                item_start = depth == 0;
+                scanner.advance();
+                continue;
+            }
+            TokenType::Semicolon if depth == 0 => {
+                item_start = true;
+                scanner.advance();
+                continue;
+            }
+            _ => {}
+        }
+        if depth == 0 && item_start {
+            match token.kind {
+                // The annotations and the docs of the next declaration
+                TokenType::Pragma | TokenType::Doc => {}
+                TokenType::At
+                | TokenType::Pub
+                | TokenType::Use
+                | TokenType::Const
+                | TokenType::Fn
+                | TokenType::AI
+                | TokenType::Agent
+                | TokenType::Class
+                | TokenType::Enum => item_start = false,
+                _ => {
+                    return Err(format!(
+                        "[line {}] Only declarations are allowed at the top level of an agents file, \
+                        got '{}', it would run on every request.",
+                        token.line, token.lexeme
+                    ));
+                }
+            }
+        }
+        scanner.advance();
+    }
+    Ok(())
+}
+
+// The chat endpoint of the agent. The client sends the message with the
+// history of the conversation, the reply comes back with the updated history.
+fn endpoint(agent: &ExposedAgent, source: &str) -> Endpoint {
+    let name = &agent.name;
+    let script = format!(
+        "let history = body.history;
+        let reply = {name}.run(input=body.message, history=history);
+        history.append({{role: \"user\", content: body.message}});
+        history.append({{role: \"assistant\", content: reply.message}});
+        return {{message: reply.message, history}};"
+    );
+    Endpoint {
+        annotation: RouteAnnotation {
+            auth: agent.expose.auth,
+            ..Default::default()
+        },
+        path_specs: vec![PathSpec {
+            method: HttpMethod::Post,
+            path: agent.expose.path.clone(),
+            params: Vec::new(),
+            line: 0,
+        }],
+        return_type: None,
+        path: Vec::new(),
+        query: Vec::new(),
+        body: RequestBody {
+            kind: BodyKind::Json,
+            fields: vec![
+                Field {
+                    name: "message".to_owned(),
+                    _type: FieldType::Str,
+                    required: true,
+                    default: None,
+                    validators: Box::new([]),
+                    docs: "The message of the user".to_owned(),
+                },
+                Field {
+                    name: "history".to_owned(),
+                    _type: FieldType::Array,
+                    required: false,
+                    default: Some(serde_json::json!([])),
+                    validators: Box::new([Box::new(HistoryValidator)]),
+                    docs: "The previous messages of the conversation, as {role, content}"
+                        .to_owned(),
+                },
+            ],
+        },
+        // The handler is declared after the agents and tools of the file
+        statements: format!("{source}\n{HANDLER_SIGNATURE}{{{script}}}"),
+        line: 0,
+        docs: format!("Chat with the {name} agent."),
+    }
+}
+
+// The max number of messages of the history sent by the client.
+const MAX_HISTORY_LEN: usize = 100;
+
+// Check the history sent by the client, so an invalid message is rejected
+// with a 400 instead of failing the agent run.
+struct HistoryValidator;
+
+impl Validator for HistoryValidator {
+    fn name(&self) -> &'static str {
+        "@history"
+    }
+
+    fn validate(&self, value: &Value) -> Result<(), String> {
+        let Value::Array(messages) = value else {
+            return Err("The history must be an array of {role, content} objects.".into());
+        };
+        if messages.len() > MAX_HISTORY_LEN {
+            return Err(format!(
+                "The history can't have more than {MAX_HISTORY_LEN} messages, got {}.",
+                messages.len()
+            ));
+        }
+        for message in messages {
+            match message.get("role").and_then(Value::as_str) {
+                Some("user" | "assistant") => {}
+                role => {
+                    return Err(format!(
+                        "The role of a history message must be \"user\" or \"assistant\", got {}.",
+                        role.map_or_else(|| "none".to_owned(), |role| format!("\"{role}\""))
+                    ));
+                }
+            }
+            if !message.get("content").is_some_and(Value::is_string) {
+                return Err("The content of a history message must be a string.".into());
+            }
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_directive::route::Auth;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_agents() {
+        let source = r#"
+            @schedule("0 9 * * 1")
+            fn weekly_report() {}
+
+            @expose(path="/support", auth="jwt")
+            pub agent Support {
+                instructions: "Help the customers.",
+            }
+
+            agent Helper {
+                instructions: "Help.",
+            }
+        "#;
+        let agents = parse_agents(source).unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].name, "Support");
+        assert_eq!(agents[0].expose.auth, Auth::Jwt);
+
+        let endpoint = endpoint(&agents[0], source);
+        assert_eq!(endpoint.path_specs[0].path, "/support");
+        assert!(endpoint.statements.starts_with(source));
+        assert!(
+            endpoint
+                .statements
+                .contains("Support.run(input=body.message, history=history)")
+        );
+
+        assert!(check_declarations(source).is_ok());
+        assert!(
+            check_declarations(
+                r#"
+                #strict
+                use std.http;
+                const LIMIT = 3;
+                """Search the web."""
+                fn search(query: str) { let n = LIMIT; return query; }
+                @expose("/chat")
+                agent Chat { instructions: "Chat.", tools: [search] }
+                enum Level { Low, High }
+                class Note { text: str }
+                "#
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            check_declarations("agent Chat {}\nprint(\"setup\");"),
+            Err(
+                "[line 2] Only declarations are allowed at the top level of an agents file, \
+                got 'print', it would run on every request."
+                    .into()
+            )
+        );
+        assert!(check_declarations("let client = 1;").is_err());
+
+        assert!(parse_agents("@expose(\"/report\") fn report() {}").is_err());
+        assert!(parse_agents("@expose(\"report\") agent Report {}").is_err());
+    }
+
+    #[test]
+    fn test_history_validator() {
+        let validate = |history| HistoryValidator.validate(&history);
+        assert!(validate(serde_json::json!([])).is_ok());
+        assert!(
+            validate(serde_json::json!([
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "Hello!"},
+            ]))
+            .is_ok()
+        );
+        assert_eq!(
+            validate(serde_json::json!([{"role": "system", "content": "hi"}])),
+            Err(
+                "The role of a history message must be \"user\" or \"assistant\", got \"system\"."
+                    .into()
+            )
+        );
+        assert!(validate(serde_json::json!([{"content": "hi"}])).is_err());
+        assert!(validate(serde_json::json!([{"role": "user", "content": 1}])).is_err());
+        assert!(validate(serde_json::json!("hi")).is_err());
+        let message = serde_json::json!({"role": "user", "content": "hi"});
+        assert!(validate(serde_json::json!(vec![message.clone(); MAX_HISTORY_LEN])).is_ok());
+        assert!(validate(serde_json::json!(vec![message; MAX_HISTORY_LEN + 1])).is_err());
+    }
+}
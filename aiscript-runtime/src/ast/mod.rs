@@ -1,5 +1,6 @@
 #![allow(unused)]
 use aiscript_directive::{Validator, route::RouteAnnotation};
+use serde::Deserialize;
 use serde_json::Value;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -26,6 +27,7 @@ pub struct PathSpec {
     pub method: HttpMethod,
     pub path: String,
     pub params: Vec<String>,
+    pub line: u32,
 }
 
 #[derive(Debug, Default)]
@@ -92,6 +94,8 @@ pub struct Endpoint {
     pub query: Vec<Field>,
     pub body: RequestBody,
     pub statements: String,
+    // The line of the route file the handler script starts at, 0 if generated.
+    pub line: u32,
     pub docs: String,
 }
 
@@ -102,4 +106,46 @@ pub struct Route {
     pub params: Vec<String>,
     pub endpoints: Vec<Endpoint>,
     pub docs: String,
+    pub meta: RouteMeta,
+}
+
+/// The front-matter of a route file, a TOML block between two `+++` lines
+/// at the top of the file:
+///
+/// ```text
+/// +++
+/// owner = "payments-team"
+/// description = "Refunds of the orders"
+/// tags = ["payments"]
+/// stability = "beta"
+/// +++
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteMeta {
+    pub owner: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub stability: Option<Stability>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stability {
+    Experimental,
+    Beta,
+    Stable,
+    Deprecated,
+}
+
+impl Stability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stability::Experimental => "experimental",
+            Stability::Beta => "beta",
+            Stability::Stable => "stable",
+            Stability::Deprecated => "deprecated",
+        }
+    }
 }
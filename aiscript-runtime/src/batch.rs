@@ -0,0 +1,348 @@
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    Json, Router,
+    body::{Body, Bytes, to_bytes},
+    extract::{ConnectInfo, FromRequest, Request, rejection::JsonRejection},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::Semaphore;
+use tower::ServiceExt;
+
+use crate::config::BatchConfig;
+
+pub(crate) const BATCH_PATH: &str = "/_batch";
+
+/// A request of a batch, its headers are added to the ones of the batch,
+/// e.g. the `Authorization` of the batch applies to all its requests.
+#[derive(Debug, Deserialize)]
+struct SubRequest {
+    #[serde(default = "default_method")]
+    method: String,
+    path: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    // Sent as JSON if set.
+    #[serde(default)]
+    body: Option<Value>,
+}
+
+fn default_method() -> String {
+    "GET".to_owned()
+}
+
+/// The response of a request of the batch, a JSON body is inlined, the
+/// other bodies are strings.
+#[derive(Debug, Serialize, PartialEq)]
+struct SubResponse {
+    status: u16,
+    body: Value,
+}
+
+impl SubResponse {
+    fn error(status: StatusCode, message: impl Into<String>) -> Self {
+        SubResponse {
+            status: status.as_u16(),
+            body: json!({ "error": message.into() }),
+        }
+    }
+}
+
+/// The `/_batch` endpoint, it runs the requests of the JSON array posted to
+/// it against the router and answers with their responses in the same order.
+/// The batch itself can't be batched.
+pub(crate) fn batch_router(config: &BatchConfig, router: Router) -> Router {
+    let config = Arc::new(config.clone());
+    Router::new().route(
+        BATCH_PATH,
+        post(move |request: Request| {
+            let config = config.clone();
+            let router = router.clone();
+            async move { run_batch(&config, router, request).await }
+        }),
+    )
+}
+
+async fn run_batch(config: &BatchConfig, router: Router, request: Request) -> Response {
+    let headers = request.headers().clone();
+    let connect_info = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .copied();
+    // The body is read within the DefaultBodyLimit of the router
+    let body = match Bytes::from_request(request, &()).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let items = match Json::<Vec<SubRequest>>::from_bytes(&body) {
+        Ok(Json(items)) => items,
+        Err(rejection) => return bad_request(rejection),
+    };
+    if items.len() > config.max_items {
+        return (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            Json(json!({
+                "error": format!(
+                    "A batch has at most {} requests, got {}.",
+                    config.max_items,
+                    items.len()
+                )
+            })),
+        )
+            .into_response();
+    }
+
+    let semaphore = Arc::new(Semaphore::new(
+        config.concurrency.clamp(1, Semaphore::MAX_PERMITS),
+    ));
+    let timeout = config.timeout.map(Duration::from_secs);
+    let handles = items
+        .into_iter()
+        .map(|item| {
+            let semaphore = semaphore.clone();
+            let router = router.clone();
+            let request = sub_request(&headers, connect_info, item);
+            tokio::spawn(async move {
+                let request = match request {
+                    Ok(request) => request,
+                    Err(response) => return response,
+                };
+                let _permit = semaphore.acquire_owned().await;
+                let response = router.oneshot(request);
+                match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, run(response))
+                        .await
+                        .unwrap_or_else(|_| {
+                            SubResponse::error(
+                                StatusCode::GATEWAY_TIMEOUT,
+                                "The request of the batch timed out.",
+                            )
+                        }),
+                    None => run(response).await,
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut responses = Vec::with_capacity(handles.len());
+    for handle in handles {
+        responses.push(handle.await.unwrap_or_else(|err| {
+            SubResponse::error(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+        }));
+    }
+    Json(responses).into_response()
+}
+
+fn bad_request(rejection: JsonRejection) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "error": format!(
+                "Expect an array of {{method, path, headers, body}} requests: {}",
+                rejection.body_text()
+            )
+        })),
+    )
+        .into_response()
+}
+
+// The request of the item with the headers of the batch, or the response
+// explaining why it's invalid.
+fn sub_request(
+    headers: &HeaderMap,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    item: SubRequest,
+) -> Result<Request, SubResponse> {
+    let method = Method::from_bytes(item.method.to_uppercase().as_bytes()).map_err(|_| {
+        SubResponse::error(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid method '{}'.", item.method),
+        )
+    })?;
+    if !item.path.starts_with('/') {
+        return Err(SubResponse::error(
+            StatusCode::BAD_REQUEST,
+            format!("The path must start with '/', got '{}'.", item.path),
+        ));
+    }
+    if item.path.split('?').next() == Some(BATCH_PATH) {
+        return Err(SubResponse::error(
+            StatusCode::BAD_REQUEST,
+            "A batch can't contain a batch.",
+        ));
+    }
+
+    let mut builder = Request::builder().method(method).uri(&item.path);
+    let request_headers = builder.headers_mut().unwrap();
+    for (name, value) in headers {
+        if ![
+            header::CONTENT_LENGTH,
+            header::CONTENT_TYPE,
+            header::TRANSFER_ENCODING,
+        ]
+        .contains(name)
+        {
+            request_headers.append(name, value.clone());
+        }
+    }
+    for (name, value) in &item.headers {
+        let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) else {
+            return Err(SubResponse::error(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid header '{name}'."),
+            ));
+        };
+        request_headers.insert(name, value);
+    }
+    let body = match item.body {
+        Some(body) => {
+            request_headers.insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    if let Some(connect_info) = connect_info {
+        builder = builder.extension(connect_info);
+    }
+    builder.body(body).map_err(|err| {
+        SubResponse::error(StatusCode::BAD_REQUEST, format!("Invalid request: {err}"))
+    })
+}
+
+// Run the request and read its response.
+async fn run(
+    response: impl Future<Output = Result<Response, std::convert::Infallible>>,
+) -> SubResponse {
+    let Ok(response) = response.await;
+    let status = response.status().as_u16();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let bytes = match to_bytes(response.into_body(), usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            return SubResponse::error(StatusCode::BAD_GATEWAY, err.to_string());
+        }
+    };
+    let body = match is_json {
+        true => serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned())),
+        false if bytes.is_empty() => Value::Null,
+        false => Value::String(String::from_utf8_lossy(&bytes).into_owned()),
+    };
+    SubResponse { status, body }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::get;
+
+    use super::*;
+
+    async fn call(router: Router, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(BATCH_PATH)
+            .header(header::AUTHORIZATION, "Bearer abc")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_batch() {
+        let router = Router::new()
+            .route(
+                "/auth",
+                get(|headers: HeaderMap| async move {
+                    Json(json!({ "auth": headers[header::AUTHORIZATION].to_str().unwrap() }))
+                }),
+            )
+            .route(
+                "/echo",
+                post(|Json(body): Json<Value>| async { Json(body) }),
+            )
+            .route("/text", get(|| async { "ok" }))
+            .route(
+                "/slow",
+                get(|| async {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    "late"
+                }),
+            );
+        let config = BatchConfig {
+            max_items: 5,
+            concurrency: 2,
+            timeout: Some(1),
+        };
+        let batch = batch_router(&config, router);
+
+        let (status, body) = call(
+            batch.clone(),
+            json!([
+                {"path": "/auth"},
+                {"method": "post", "path": "/echo", "body": {"n": 1}},
+                {"path": "/text"},
+                {"path": "/slow"},
+                {"path": "/_batch"},
+            ]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!([
+                {"status": 200, "body": {"auth": "Bearer abc"}},
+                {"status": 200, "body": {"n": 1}},
+                {"status": 200, "body": "ok"},
+                {"status": 504, "body": {"error": "The request of the batch timed out."}},
+                {"status": 400, "body": {"error": "A batch can't contain a batch."}},
+            ])
+        );
+
+        let (status, _) = call(
+            batch.clone(),
+            Value::Array(vec![json!({"path": "/text"}); 6]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        let (status, _) = call(batch.clone(), json!({"path": "/text"})).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The body of the batch is limited like the ones of the routes
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(BATCH_PATH)
+            .body(Body::from(vec![b' '; 3 * 1024 * 1024]))
+            .unwrap();
+        let response = batch.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+    #[tokio::test]
+    async fn test_batch_concurrency_beyond_permits() {
+        let router = Router::new().route("/text", get(|| async { "ok" }));
+        let config = BatchConfig {
+            max_items: 5,
+            concurrency: usize::MAX,
+            timeout: None,
+        };
+        let (status, body) = call(batch_router(&config, router), json!([{"path": "/text"}])).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!([{"status": 200, "body": "ok"}]));
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::VecDeque,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{config::BrownoutConfig, workers};
+
+// The header reporting the mode of the routes of `[brownout]`.
+pub(crate) const BROWNOUT_HEADER: &str = "x-brownout";
+// The latencies kept at most, whatever the window.
+const MAX_SAMPLES: usize = 10_000;
+
+// The end time and latency of the recent requests run by their handler.
+static LATENCIES: LazyLock<Mutex<VecDeque<(Instant, Duration)>>> = LazyLock::new(Default::default);
+
+/// How a route of `[brownout]` serves its requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Mode {
+    // The handler runs as usual.
+    Off,
+    // The handler runs its prompts and agents on the cheaper model.
+    Model,
+    // The `@fallback` of the endpoint is served, the handler doesn't run.
+    Fallback,
+}
+
+impl Mode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Mode::Off => "off",
+            Mode::Model => "model",
+            Mode::Fallback => "fallback",
+        }
+    }
+}
+
+/// Record the latency of a request run by its handler.
+pub(crate) fn record(config: &BrownoutConfig, latency: Duration) {
+    let now = Instant::now();
+    let mut latencies = LATENCIES.lock().unwrap();
+    prune(&mut latencies, config.window(), now);
+    if latencies.len() == MAX_SAMPLES {
+        latencies.pop_front();
+    }
+    latencies.push_back((now, latency));
+}
+
+/// The mode of the route, None if it isn't a route of `[brownout]`.
+pub(crate) fn mode(config: &BrownoutConfig, route: &str, has_fallback: bool) -> Option<Mode> {
+    if !config.routes.iter().any(|r| r == route) {
+        return None;
+    }
+    let latency = {
+        let mut latencies = LATENCIES.lock().unwrap();
+        prune(&mut latencies, config.window(), Instant::now());
+        average(&latencies)
+    };
+    let queued = workers::worker_stats().map_or(0, |stats| stats.queued);
+    Some(select(
+        config,
+        is_overloaded(config, latency, queued),
+        has_fallback,
+    ))
+}
+
+// Forget the latencies older than the window, so the load is measured
+// again once the routes stop running their handlers.
+fn prune(latencies: &mut VecDeque<(Instant, Duration)>, window: Duration, now: Instant) {
+    while latencies
+        .front()
+        .is_some_and(|(at, _)| now.duration_since(*at) > window)
+    {
+        latencies.pop_front();
+    }
+}
+
+fn average(latencies: &VecDeque<(Instant, Duration)>) -> Option<Duration> {
+    let total = latencies
+        .iter()
+        .map(|(_, latency)| *latency)
+        .sum::<Duration>();
+    Some(total / u32::try_from(latencies.len()).ok().filter(|len| *len > 0)?)
+}
+
+fn is_overloaded(config: &BrownoutConfig, latency: Option<Duration>, queued: usize) -> bool {
+    let slow = config
+        .latency_ms
+        .zip(latency)
+        .is_some_and(|(threshold, latency)| latency > Duration::from_millis(threshold));
+    let queuing = config
+        .queue_depth
+        .is_some_and(|threshold| queued > threshold);
+    slow || queuing
+}
+
+// The fallback is preferred over the cheaper model if the endpoint has one.
+fn select(config: &BrownoutConfig, overloaded: bool, has_fallback: bool) -> Mode {
+    if !overloaded {
+        Mode::Off
+    } else if config.fallback && has_fallback {
+        Mode::Fallback
+    } else if config.model.is_some() {
+        Mode::Model
+    } else {
+        Mode::Off
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(model: Option<&str>, fallback: bool) -> BrownoutConfig {
+        BrownoutConfig {
+            routes: vec!["/chat".into()],
+            latency_ms: Some(500),
+            queue_depth: Some(10),
+            window: 10,
+            model: model.map(Into::into),
+            fallback,
+        }
+    }
+
+    #[test]
+    fn test_is_overloaded() {
+        let config = config(Some("gpt-4o-mini"), false);
+        assert!(!is_overloaded(&config, None, 0));
+        assert!(!is_overloaded(
+            &config,
+            Some(Duration::from_millis(400)),
+            10
+        ));
+        assert!(is_overloaded(&config, Some(Duration::from_millis(600)), 0));
+        assert!(is_overloaded(&config, None, 11));
+    }
+
+    #[test]
+    fn test_select() {
+        let model = config(Some("gpt-4o-mini"), false);
+        assert_eq!(select(&model, false, true), Mode::Off);
+        assert_eq!(select(&model, true, true), Mode::Model);
+        let both = config(Some("gpt-4o-mini"), true);
+        assert_eq!(select(&both, true, true), Mode::Fallback);
+        assert_eq!(select(&both, true, false), Mode::Model);
+        let fallback = config(None, true);
+        assert_eq!(select(&fallback, true, false), Mode::Off);
+    }
+
+    #[test]
+    fn test_prune() {
+        let now = Instant::now();
+        let mut latencies = VecDeque::from([
+            (now - Duration::from_secs(20), Duration::from_millis(900)),
+            (now - Duration::from_secs(1), Duration::from_millis(100)),
+            (now, Duration::from_millis(300)),
+        ]);
+        prune(&mut latencies, Duration::from_secs(10), now);
+        assert_eq!(average(&latencies), Some(Duration::from_millis(200)));
+        prune(&mut latencies, Duration::ZERO, now + Duration::from_secs(1));
+        assert_eq!(average(&latencies), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+};
+
+use crate::config::TrustedProxies;
+
+/// The IP of the client who sent the request.
+///
+/// The forwarded header is only honored when the peer is a trusted proxy,
+/// it's walked from the right as the proxies append the address they got
+/// the request from, so the first untrusted address is the client. A peer
+/// connected through the unix socket is a local proxy, it's trusted if
+/// there are trusted proxies.
+pub(crate) fn client_ip(
+    request: &Request<Body>,
+    proxies: Option<&TrustedProxies>,
+) -> Option<IpAddr> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+    let Some(proxies) = proxies else {
+        return peer;
+    };
+    if peer.is_some_and(|peer| !proxies.contains(&peer)) {
+        return peer;
+    }
+
+    let mut client = peer;
+    let forwarded = request
+        .headers()
+        .get_all(proxies.header.as_str())
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>();
+    for hop in forwarded.into_iter().rev() {
+        // A malformed entry can't be trusted, nor anything on its left
+        let Ok(ip) = hop.trim().parse::<IpAddr>() else {
+            break;
+        };
+        client = Some(ip.to_canonical());
+        if !proxies.contains(&ip.to_canonical()) {
+            break;
+        }
+    }
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(peer: Option<&str>, forwarded: &[&str]) -> Request<Body> {
+        let mut request = Request::new(Body::empty());
+        if let Some(peer) = peer {
+            request
+                .extensions_mut()
+                .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        }
+        for value in forwarded {
+            request
+                .headers_mut()
+                .append("x-forwarded-for", value.parse().unwrap());
+        }
+        request
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_client_ip_without_proxies() {
+        let request = request(Some("1.2.3.4:5000"), &["6.6.6.6"]);
+        assert_eq!(client_ip(&request, None), ip("1.2.3.4"));
+        assert_eq!(client_ip(&Request::new(Body::empty()), None), None);
+    }
+
+    #[test]
+    fn test_client_ip_behind_proxies() {
+        let proxies = TrustedProxies {
+            ips: vec!["10.0.0.0/8".parse().unwrap()],
+            header: "x-forwarded-for".to_string(),
+        };
+        let proxies = Some(&proxies);
+
+        // Spoofed by a client connecting directly
+        let direct = request(Some("1.2.3.4:5000"), &["6.6.6.6"]);
+        assert_eq!(client_ip(&direct, proxies), ip("1.2.3.4"));
+
+        // The leftmost entries are set by the client and ignored
+        let proxied = request(Some("10.0.0.1:5000"), &["6.6.6.6, 1.2.3.4", "10.0.0.2"]);
+        assert_eq!(client_ip(&proxied, proxies), ip("1.2.3.4"));
+
+        let malformed = request(Some("10.0.0.1:5000"), &["1.2.3.4, bogus, 10.0.0.2"]);
+        assert_eq!(client_ip(&malformed, proxies), ip("10.0.0.2"));
+
+        let unix = request(None, &["1.2.3.4"]);
+        assert_eq!(client_ip(&unix, proxies), ip("1.2.3.4"));
+
+        let mapped = request(Some("[::ffff:10.0.0.1]:5000"), &["1.2.3.4"]);
+        assert_eq!(client_ip(&mapped, proxies), ip("1.2.3.4"));
+    }
+}
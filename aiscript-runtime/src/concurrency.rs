@@ -0,0 +1,97 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use aiscript_directive::route::Concurrency;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// The slots of an endpoint with `@concurrency`, shared by the paths it's
+/// routed on.
+pub(crate) struct ConcurrencyLimiter {
+    slots: Arc<Semaphore>,
+    queue: usize,
+    queued: AtomicUsize,
+}
+
+pub(crate) enum Slot {
+    Acquired(OwnedSemaphorePermit),
+    // Resolves once a slot is freed, the request leaves the queue when the
+    // future completes or is dropped.
+    Queued(Pin<Box<dyn Future<Output = OwnedSemaphorePermit> + Send>>),
+    // All the slots are busy and the queue is full
+    Rejected,
+}
+
+// Leaves the queue when dropped.
+struct QueueGuard(Arc<ConcurrencyLimiter>);
+
+impl Drop for QueueGuard {
+    fn drop(&mut self) {
+        self.0.queued.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl ConcurrencyLimiter {
+    pub(crate) fn new(concurrency: Concurrency) -> Self {
+        Self {
+            slots: Arc::new(Semaphore::new(concurrency.max)),
+            queue: concurrency.queue,
+            queued: AtomicUsize::new(0),
+        }
+    }
+
+    /// A slot to execute the request, held until the permit is dropped.
+    pub(crate) fn acquire(self: &Arc<Self>) -> Slot {
+        if let Ok(permit) = self.slots.clone().try_acquire_owned() {
+            return Slot::Acquired(permit);
+        }
+        let entered = self
+            .queued
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |queued| {
+                (queued < self.queue).then_some(queued + 1)
+            })
+            .is_ok();
+        if !entered {
+            return Slot::Rejected;
+        }
+        let guard = QueueGuard(self.clone());
+        let slots = self.slots.clone();
+        Slot::Queued(Box::pin(async move {
+            let _guard = guard;
+            slots
+                .acquire_owned()
+                .await
+                .expect("the semaphore is never closed")
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_and_overflow() {
+        let limiter = Arc::new(ConcurrencyLimiter::new(Concurrency { max: 1, queue: 1 }));
+        let Slot::Acquired(permit) = limiter.acquire() else {
+            panic!("expected a free slot");
+        };
+        let Slot::Queued(queued) = limiter.acquire() else {
+            panic!("expected to be queued");
+        };
+        assert!(matches!(limiter.acquire(), Slot::Rejected));
+
+        drop(permit);
+        let permit = queued.await;
+        assert_eq!(limiter.queued.load(Ordering::Acquire), 0);
+        // The slot is taken by the dequeued request, the queue is free again
+        assert!(matches!(limiter.acquire(), Slot::Queued(_)));
+        drop(permit);
+        assert!(matches!(limiter.acquire(), Slot::Acquired(_)));
+    }
+}
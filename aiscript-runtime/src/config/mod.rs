@@ -1,9 +1,20 @@
-use std::{fs, path::Path, sync::OnceLock};
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
+
+use aiscript_common::EnvString;
+use aiscript_directive::route::{Auth, RouteAnnotation, parse_ip_net};
 
 use auth::AuthConfig;
-use serde::Deserialize;
+use ipnet::IpNet;
+use serde::{Deserialize, Deserializer};
 
-use aiscript_vm::AiConfig;
+use aiscript_vm::{AiConfig, ChaosConfig, CircuitBreakerConfig};
 use db::DatabaseConfig;
 pub use sso::{SsoConfig, get_sso_fields};
 
@@ -13,7 +24,11 @@ mod sso;
 #[cfg(test)]
 mod tests;
 
-static CONFIG: OnceLock<Config> = OnceLock::new();
+pub(crate) const CONFIG_FILE: &str = "project.toml";
+
+// Swapped as a whole by a reload, the requests being served keep the
+// config they started with.
+static CONFIG: OnceLock<RwLock<Arc<Config>>> = OnceLock::new();
 
 #[derive(Debug, Deserialize, Default)]
 pub struct Config {
@@ -29,6 +44,103 @@ pub struct Config {
     pub sso: SsoConfig,
     #[serde(default)]
     pub network: NetworkConfig,
+    #[serde(default)]
+    pub server: ServerConfig,
+    // The circuit breaker of the AI providers, HTTP hosts and databases, off if unset.
+    #[serde(default)]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    // The faults injected into the dependencies by `serve --chaos`.
+    #[serde(default)]
+    pub chaos: Option<ChaosConfig>,
+    // The route directories served, `routes/` mounted at `/` if unset.
+    #[serde(default)]
+    pub routes: Vec<RouteRoot>,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    #[serde(default)]
+    pub json: JsonConfig,
+    #[serde(default)]
+    pub language: LanguageConfig,
+    #[serde(default)]
+    pub kv: KvConfig,
+    #[serde(default)]
+    pub template: TemplateConfig,
+    #[serde(default)]
+    pub responses: ResponsesConfig,
+    #[serde(default)]
+    pub errors: ErrorsConfig,
+    // The worker threads of the handlers, the blocking threads of tokio if unset.
+    #[serde(default)]
+    pub workers: Option<WorkersConfig>,
+    // The `/_batch` endpoint, off if unset.
+    #[serde(default)]
+    pub batch: Option<BatchConfig>,
+    // The brownout of the AI routes under load, off if unset.
+    #[serde(default)]
+    pub brownout: Option<BrownoutConfig>,
+}
+
+/// A directory of route files mounted at a path prefix, declared as
+/// `[[routes]]` in project.toml.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RouteRoot {
+    pub dir: PathBuf,
+    #[serde(default = "default_mount")]
+    pub mount: String,
+    // The auth of the routes without `@auth` or `@basic_auth` of their own.
+    #[serde(default)]
+    pub auth: Option<RouteAuth>,
+    // The timeout in seconds of the routes without `@timeout` of their own.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RouteAuth {
+    Jwt,
+    Basic,
+}
+
+fn default_mount() -> String {
+    "/".to_string()
+}
+
+impl Default for RouteRoot {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("routes"),
+            mount: default_mount(),
+            auth: None,
+            timeout: None,
+        }
+    }
+}
+
+impl RouteRoot {
+    /// The defaults of the routes in the directory.
+    pub fn annotation(&self) -> RouteAnnotation {
+        RouteAnnotation {
+            auth: match self.auth {
+                Some(RouteAuth::Jwt) => Auth::Jwt,
+                Some(RouteAuth::Basic) => Auth::Basic,
+                None => Auth::None,
+            },
+            timeout: self.timeout.map(Duration::from_secs),
+            ..Default::default()
+        }
+    }
+
+    /// The route prefix under the mount path.
+    pub fn mount_prefix(&self, prefix: &str) -> String {
+        match (self.mount.trim_end_matches('/'), prefix) {
+            ("", prefix) => prefix.to_owned(),
+            (mount, "/") => mount.to_owned(),
+            (mount, prefix) => format!("{mount}{prefix}"),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Default)]
@@ -37,6 +149,419 @@ pub struct NetworkConfig {
     pub host: String,
     #[serde(default = "default_port")]
     pub port: u16,
+    // The deadline in seconds of every request, overridden by `@timeout` of a route.
+    #[serde(default, deserialize_with = "deserialize_request_timeout")]
+    pub request_timeout: Option<u64>,
+}
+
+/// Where the server listens instead of `host:port` of `[network]`, and how
+/// the connections are served.
+#[derive(Debug, Deserialize)]
+pub struct ServerConfig {
+    // Bind to the unix socket at the path, e.g. for a local reverse proxy.
+    #[serde(default)]
+    pub unix_socket: Option<PathBuf>,
+    // Inherit the listening socket from systemd socket activation.
+    #[serde(default)]
+    pub systemd: bool,
+    // Serve HTTP/2 (with TLS offloaded or prior knowledge) along with HTTP/1.1.
+    #[serde(default)]
+    pub http2: bool,
+    // The maximum number of concurrent streams of an HTTP/2 connection.
+    #[serde(default)]
+    pub http2_max_concurrent_streams: Option<u32>,
+    // Keep HTTP/1.1 connections open between requests, on by default.
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive: bool,
+    // The seconds to wait for the request headers, which also closes idle
+    // keep-alive connections.
+    #[serde(default)]
+    pub header_read_timeout: Option<u64>,
+    // The seconds between the HTTP/2 keep-alive pings, no pings if unset.
+    #[serde(default)]
+    pub http2_keep_alive_interval: Option<u64>,
+    // The seconds to wait for a keep-alive ping to be acknowledged before
+    // closing the HTTP/2 connection.
+    #[serde(default)]
+    pub http2_keep_alive_timeout: Option<u64>,
+    // Disable Nagle's algorithm on the accepted TCP connections.
+    #[serde(default)]
+    pub tcp_nodelay: bool,
+    // The reverse proxies whose forwarded client IPs are trusted, the peer
+    // address is the client IP if unset.
+    #[serde(default)]
+    pub trusted_proxies: Option<TrustedProxies>,
+}
+
+/// The reverse proxies in front of the server, declared as
+/// `[server.trusted_proxies]` in project.toml.
+#[derive(Debug, Deserialize)]
+pub struct TrustedProxies {
+    // The proxy networks, as CIDRs or single IPs.
+    #[serde(deserialize_with = "deserialize_ip_nets")]
+    pub ips: Vec<IpNet>,
+    // The header listing the client IP and the proxies it went through.
+    #[serde(default = "default_forwarded_header")]
+    pub header: String,
+}
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: &std::net::IpAddr) -> bool {
+        self.ips.iter().any(|net| net.contains(ip))
+    }
+}
+
+fn default_forwarded_header() -> String {
+    "x-forwarded-for".to_string()
+}
+
+// The longest request timeout, a day.
+const MAX_REQUEST_TIMEOUT: u64 = 86_400;
+
+fn deserialize_request_timeout<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    match Option::<u64>::deserialize(deserializer)? {
+        Some(seconds) if seconds == 0 || seconds > MAX_REQUEST_TIMEOUT => {
+            Err(serde::de::Error::custom(format!(
+                "invalid request_timeout {seconds}, expect 1 to {MAX_REQUEST_TIMEOUT} seconds"
+            )))
+        }
+        timeout => Ok(timeout),
+    }
+}
+
+fn deserialize_ip_nets<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<IpNet>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| parse_ip_net(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            unix_socket: None,
+            systemd: false,
+            http2: false,
+            http2_max_concurrent_streams: None,
+            keep_alive: default_keep_alive(),
+            header_read_timeout: None,
+            http2_keep_alive_interval: None,
+            http2_keep_alive_timeout: None,
+            tcp_nodelay: false,
+            trusted_proxies: None,
+        }
+    }
+}
+
+fn default_keep_alive() -> bool {
+    true
+}
+
+/// The maintenance mode, which serves a 503 for every route but the allowed
+/// ones. It's toggled at runtime by the flag file, the `/_maintenance`
+/// endpoint or SIGUSR2.
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceConfig {
+    // Start the server in maintenance mode.
+    #[serde(default)]
+    pub enabled: bool,
+    // Maintenance mode is on while the file exists.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+    // The paths still served during maintenance, a trailing `*` matches a prefix.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    // The error message of the JSON response.
+    #[serde(default = "default_maintenance_message")]
+    pub message: String,
+    // The HTML page served to browsers instead of the JSON response.
+    #[serde(default)]
+    pub page: Option<PathBuf>,
+    // The seconds of the Retry-After header.
+    #[serde(default)]
+    pub retry_after: Option<u64>,
+    // The bearer token of the `/_maintenance` endpoint, not served if unset.
+    #[serde(default)]
+    pub token: Option<EnvString>,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            file: None,
+            allow: Vec::new(),
+            message: default_maintenance_message(),
+            page: None,
+            retry_after: None,
+            token: None,
+        }
+    }
+}
+
+/// The execution limits of the route handlers, a runaway script, e.g. an
+/// infinite loop, is stopped with a 503 instead of holding a worker or
+/// exhausting the memory of the server.
+#[derive(Debug, Deserialize, Default)]
+pub struct LimitsConfig {
+    // The maximum number of instructions a handler runs.
+    #[serde(default)]
+    pub fuel: Option<u64>,
+    // The maximum milliseconds a handler runs, unlike the request timeout
+    // the script is stopped rather than left running in the background.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    // The maximum megabytes a handler allocates, e.g. building an unbounded list.
+    #[serde(default)]
+    pub max_heap_mb: Option<usize>,
+}
+
+/// The JSON of the responses, declared as `[json]` in project.toml.
+#[derive(Debug, Deserialize, Default)]
+pub struct JsonConfig {
+    // Serialize the integers beyond 2^53 as strings, which a JavaScript
+    // client would round otherwise.
+    #[serde(default)]
+    pub big_int_strings: bool,
+}
+
+/// The language features, declared as `[language]` in project.toml.
+#[derive(Debug, Deserialize, Default)]
+pub struct LanguageConfig {
+    // Allow the calls of the `@experimental` functions and classes.
+    #[serde(default)]
+    pub experimental: bool,
+}
+
+/// The embedded store of `std.kv`, declared as `[kv]` in project.toml.
+#[derive(Debug, Deserialize)]
+pub struct KvConfig {
+    // The file of the store, relative to the project.
+    #[serde(default = "default_kv_path")]
+    pub path: PathBuf,
+}
+
+impl Default for KvConfig {
+    fn default() -> Self {
+        Self {
+            path: default_kv_path(),
+        }
+    }
+}
+
+fn default_kv_path() -> PathBuf {
+    PathBuf::from(aiscript_vm::DEFAULT_KV_PATH)
+}
+
+/// The templates of `std.template`, declared as `[template]` in project.toml.
+#[derive(Debug, Deserialize)]
+pub struct TemplateConfig {
+    // The directory of the template files and includes, relative to the project.
+    #[serde(default = "default_template_dir")]
+    pub dir: PathBuf,
+}
+
+impl Default for TemplateConfig {
+    fn default() -> Self {
+        Self {
+            dir: default_template_dir(),
+        }
+    }
+}
+
+fn default_template_dir() -> PathBuf {
+    PathBuf::from(aiscript_vm::DEFAULT_TEMPLATE_DIR)
+}
+
+/// The shape of the JSON responses, declared as `[responses]` in project.toml.
+#[derive(Debug, Deserialize, Default)]
+pub struct ResponsesConfig {
+    // Wrap the JSON responses of the endpoints in `{data, error, meta}`,
+    // overridden by `@envelope` of a route or an endpoint.
+    #[serde(default)]
+    pub envelope: bool,
+}
+
+/// The error types raised by the route handlers and their HTTP responses,
+/// declared as `[errors]` in project.toml:
+///
+/// ```toml
+/// [errors]
+/// "NotFound!" = 404
+/// "Unauthorized!" = { status = 401, description = "Missing or invalid token" }
+/// ```
+///
+/// The error types are declared for every handler, which can
+/// `raise NotFound! { message: "..." }`. An error type not listed here is
+/// served as a 500.
+#[derive(Debug, Deserialize, Default)]
+#[serde(try_from = "BTreeMap<String, ErrorType>")]
+pub struct ErrorsConfig {
+    types: BTreeMap<String, ErrorType>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "ErrorTypeRepr")]
+pub struct ErrorType {
+    pub status: u16,
+    // The description of the response in the OpenAPI docs.
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorTypeRepr {
+    Status(u16),
+    Full {
+        status: u16,
+        #[serde(default)]
+        description: Option<String>,
+    },
+}
+
+impl From<ErrorTypeRepr> for ErrorType {
+    fn from(repr: ErrorTypeRepr) -> Self {
+        match repr {
+            ErrorTypeRepr::Status(status) => ErrorType {
+                status,
+                description: None,
+            },
+            ErrorTypeRepr::Full {
+                status,
+                description,
+            } => ErrorType {
+                status,
+                description,
+            },
+        }
+    }
+}
+
+impl TryFrom<BTreeMap<String, ErrorType>> for ErrorsConfig {
+    type Error = String;
+
+    fn try_from(types: BTreeMap<String, ErrorType>) -> Result<Self, Self::Error> {
+        for (name, error_type) in &types {
+            let ident = name.strip_suffix('!').unwrap_or_default();
+            if ident.is_empty()
+                || ident.starts_with(|c: char| c.is_ascii_digit())
+                || !ident.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(format!(
+                    "Invalid error type '{name}', expect a name ending with '!', e.g. \"NotFound!\"."
+                ));
+            }
+            if !(400..=599).contains(&error_type.status) {
+                return Err(format!(
+                    "Invalid status {} of error type '{name}', expect 400 to 599.",
+                    error_type.status
+                ));
+            }
+        }
+        Ok(Self { types })
+    }
+}
+
+impl ErrorsConfig {
+    pub fn get(&self, name: &str) -> Option<&ErrorType> {
+        self.types.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ErrorType)> {
+        self.types
+            .iter()
+            .map(|(name, error_type)| (name.as_str(), error_type))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+}
+
+/// The pool of threads running the route handlers, declared as `[workers]`
+/// in project.toml. A request waits in the queue while all the threads are
+/// busy, and is rejected with a 503 once the queue is full.
+#[derive(Debug, Deserialize)]
+pub struct WorkersConfig {
+    // The number of threads, the number of CPUs by default.
+    #[serde(default)]
+    pub threads: Option<usize>,
+    // The maximum number of requests waiting for a thread.
+    #[serde(default = "default_worker_queue")]
+    pub queue: usize,
+    // The blocking calls of the natives (file IO, processes) running at
+    // once, twice the number of CPUs by default.
+    #[serde(default)]
+    pub blocking: Option<usize>,
+}
+
+fn default_worker_queue() -> usize {
+    1024
+}
+
+/// The `/_batch` endpoint running an array of requests against the routes
+/// in one round trip, declared as `[batch]` in project.toml.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchConfig {
+    // The maximum number of requests of a batch.
+    #[serde(default = "default_batch_max_items")]
+    pub max_items: usize,
+    // The requests of a batch running at once.
+    #[serde(default = "default_batch_concurrency")]
+    pub concurrency: usize,
+    // The seconds after which a request of the batch is answered with a 504.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+fn default_batch_max_items() -> usize {
+    20
+}
+
+fn default_batch_concurrency() -> usize {
+    4
+}
+
+/// The brownout of the AI routes under load, declared as `[brownout]` in
+/// project.toml. While the average latency of the recent requests or the
+/// depth of the worker queue is over its threshold, the routes run their
+/// prompts and agents on the cheaper `model`, or serve their `@fallback`
+/// with `fallback = true`. The mode is reported in the `X-Brownout` header.
+#[derive(Debug, Deserialize)]
+pub struct BrownoutConfig {
+    // The routes browned out, as matched, e.g. "/chat".
+    pub routes: Vec<String>,
+    // The average latency in milliseconds over which the routes are browned out.
+    #[serde(default)]
+    pub latency_ms: Option<u64>,
+    // The requests waiting for a worker over which the routes are browned out.
+    #[serde(default)]
+    pub queue_depth: Option<usize>,
+    // The seconds of the recent requests the latency is averaged over.
+    #[serde(default = "default_brownout_window")]
+    pub window: u64,
+    // The model of the prompts and agents while browned out.
+    #[serde(default)]
+    pub model: Option<String>,
+    // Serve the `@fallback` of the endpoints while browned out.
+    #[serde(default)]
+    pub fallback: bool,
+}
+
+impl BrownoutConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+}
+
+fn default_brownout_window() -> u64 {
+    10
+}
+
+fn default_maintenance_message() -> String {
+    "Service is under maintenance, please retry later.".to_string()
 }
 
 fn default_host() -> String {
@@ -89,16 +614,45 @@ impl Config {
         }
     }
 
-    pub fn load() -> &'static Config {
-        CONFIG.get_or_init(|| {
-            Config::new("project.toml").unwrap_or_else(|e| {
-                eprintln!("Error loading config file: {}", e);
-                Config::default()
+    pub fn load() -> Arc<Config> {
+        CONFIG
+            .get_or_init(|| {
+                let config = Config::new(CONFIG_FILE).unwrap_or_else(|e| {
+                    eprintln!("Error loading config file: {}", e);
+                    Config::default()
+                });
+                RwLock::new(Arc::new(config))
             })
-        })
+            .read()
+            .unwrap()
+            .clone()
+    }
+
+    /// Read the config file again, the current config is kept if it's invalid.
+    pub(crate) fn reload() -> Result<(), Box<dyn std::error::Error>> {
+        let config = Config::new(CONFIG_FILE)?;
+        *CONFIG
+            .get()
+            .expect("Config not initialized")
+            .write()
+            .unwrap() = Arc::new(config);
+        Ok(())
+    }
+
+    pub fn route_roots(&self) -> Cow<'_, [RouteRoot]> {
+        if self.routes.is_empty() {
+            Cow::Owned(vec![RouteRoot::default()])
+        } else {
+            Cow::Borrowed(&self.routes)
+        }
     }
 
-    pub fn get() -> &'static Config {
-        CONFIG.get().expect("Config not initialized")
+    pub fn get() -> Arc<Config> {
+        CONFIG
+            .get()
+            .expect("Config not initialized")
+            .read()
+            .unwrap()
+            .clone()
     }
 }
@@ -1,5 +1,6 @@
+use super::ErrorType;
 use crate::Config;
-use std::env;
+use std::{env, path::PathBuf, time::Duration};
 
 #[test]
 fn test_config_with_env_vars() {
@@ -65,3 +66,357 @@ fn test_database_config_with_env_vars() {
         env::remove_var("DB_URL");
     };
 }
+
+#[test]
+fn test_ai_rate_limit_config() {
+    let config_str = r#"
+        [ai.openai]
+        api_key = "key"
+        rate_limit = { requests_per_minute = 500, tokens_per_minute = 30000 }
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let rate_limit = config.ai.openai.unwrap().rate_limit.unwrap();
+    assert_eq!(rate_limit.requests_per_minute, Some(500));
+    assert_eq!(rate_limit.tokens_per_minute, Some(30000));
+    assert_eq!(rate_limit.max_queue_wait, 30);
+}
+
+#[test]
+fn test_ai_limits_config() {
+    let config_str = r#"
+        [ai.limits]
+        ollama = { concurrency = 4 }
+        "gpt-4o" = { concurrency = 50, max_queue = 500, max_queue_wait = 5 }
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let limits = config.ai.limits;
+    assert_eq!(limits["ollama"].concurrency, 4);
+    assert_eq!(limits["ollama"].max_queue, 100);
+    assert_eq!(limits["ollama"].max_queue_wait, 30);
+    assert_eq!(limits["gpt-4o"].max_queue, 500);
+    assert_eq!(limits["gpt-4o"].max_queue_wait, 5);
+}
+
+#[test]
+fn test_ai_budget_config() {
+    let config_str = r#"
+        [ai.budget]
+        daily = 50.0
+
+        [ai.budget.prices]
+        "gpt-4o" = { input = 2.5, output = 10.0 }
+
+        [ai.budget.routes]
+        "/chat" = { monthly = 100.0 }
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let budget = config.ai.budget.unwrap();
+    assert_eq!(budget.total.daily, Some(50.0));
+    assert_eq!(budget.total.monthly, None);
+    assert_eq!(budget.prices["gpt-4o"].output, 10.0);
+    assert_eq!(budget.routes["/chat"].monthly, Some(100.0));
+    assert!(budget.principal.is_none());
+}
+
+#[test]
+fn test_ai_search_config() {
+    let config_str = r#"
+        [ai.search]
+        provider = "tavily"
+        tavily = { api_key = "key" }
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let search = config.ai.search.unwrap();
+    assert_eq!(search.provider.as_deref(), Some("tavily"));
+    assert_eq!(search.tavily.unwrap().api_key.as_str(), "key");
+    assert!(search.brave.is_none());
+}
+
+#[test]
+fn test_ai_prompt_history_config() {
+    let config_str = r#"
+        [ai]
+        prompt_history = ".aiscript/prompts"
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    assert_eq!(
+        config.ai.prompt_history,
+        Some(std::path::PathBuf::from(".aiscript/prompts"))
+    );
+}
+
+#[test]
+fn test_network_request_timeout_config() {
+    let config_str = r#"
+        [network]
+        request_timeout = 30
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    assert_eq!(config.network.request_timeout, Some(30));
+    assert_eq!(config.network.port, 8080);
+    assert!(Config::default().network.request_timeout.is_none());
+
+    for timeout in [0, 86_401] {
+        let config_str = format!("[network]\nrequest_timeout = {timeout}");
+        assert!(toml::from_str::<Config>(&config_str).is_err());
+    }
+}
+
+#[test]
+fn test_limits_config() {
+    let config_str = r#"
+        [limits]
+        fuel = 1000000
+        timeout_ms = 500
+        max_heap_mb = 64
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    assert_eq!(config.limits.fuel, Some(1_000_000));
+    assert_eq!(config.limits.timeout_ms, Some(500));
+    assert_eq!(config.limits.max_heap_mb, Some(64));
+    assert!(Config::default().limits.fuel.is_none());
+    assert!(Config::default().limits.timeout_ms.is_none());
+    assert!(Config::default().limits.max_heap_mb.is_none());
+}
+
+#[test]
+fn test_circuit_breaker_config() {
+    let config_str = r#"
+        [circuit_breaker]
+        failure_threshold = 3
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let circuit_breaker = config.circuit_breaker.unwrap();
+    assert_eq!(circuit_breaker.failure_threshold, 3);
+    assert_eq!(circuit_breaker.reset_timeout, 30);
+    assert_eq!(circuit_breaker.half_open_probes, 1);
+    assert!(Config::default().circuit_breaker.is_none());
+}
+
+#[test]
+fn test_json_config() {
+    let config: Config = toml::from_str("[json]\nbig_int_strings = true").unwrap();
+    assert!(config.json.big_int_strings);
+    assert!(!Config::default().json.big_int_strings);
+}
+
+#[test]
+fn test_language_config() {
+    let config: Config = toml::from_str("[language]\nexperimental = true").unwrap();
+    assert!(config.language.experimental);
+    assert!(!Config::default().language.experimental);
+}
+
+#[test]
+fn test_kv_config() {
+    let config: Config = toml::from_str("[kv]\npath = \"data/store.redb\"").unwrap();
+    assert_eq!(config.kv.path, PathBuf::from("data/store.redb"));
+    assert_eq!(
+        Config::default().kv.path,
+        PathBuf::from(aiscript_vm::DEFAULT_KV_PATH)
+    );
+}
+
+#[test]
+fn test_template_config() {
+    let config: Config = toml::from_str("[template]\ndir = \"views\"").unwrap();
+    assert_eq!(config.template.dir, PathBuf::from("views"));
+    assert_eq!(
+        Config::default().template.dir,
+        PathBuf::from(aiscript_vm::DEFAULT_TEMPLATE_DIR)
+    );
+}
+
+#[test]
+fn test_responses_config() {
+    let config: Config = toml::from_str("[responses]\nenvelope = true").unwrap();
+    assert!(config.responses.envelope);
+    assert!(!Config::default().responses.envelope);
+}
+
+#[test]
+fn test_errors_config() {
+    let config_str = r#"
+        [errors]
+        "NotFound!" = 404
+        "Unauthorized!" = { status = 401, description = "Missing or invalid token" }
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    assert_eq!(
+        config.errors.get("NotFound!"),
+        Some(&ErrorType {
+            status: 404,
+            description: None
+        })
+    );
+    assert_eq!(
+        config.errors.get("Unauthorized!"),
+        Some(&ErrorType {
+            status: 401,
+            description: Some("Missing or invalid token".into())
+        })
+    );
+    assert!(config.errors.get("Forbidden!").is_none());
+    assert!(Config::default().errors.is_empty());
+
+    assert!(toml::from_str::<Config>("[errors]\nNotFound = 404").is_err());
+    assert!(toml::from_str::<Config>("[errors]\n\"NotFound!\" = 200").is_err());
+}
+
+#[test]
+fn test_workers_config() {
+    let config_str = r#"
+        [workers]
+        threads = 4
+        blocking = 8
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let workers = config.workers.unwrap();
+    assert_eq!(workers.threads, Some(4));
+    assert_eq!(workers.queue, 1024);
+    assert_eq!(workers.blocking, Some(8));
+    assert!(Config::default().workers.is_none());
+}
+
+#[test]
+fn test_batch_config() {
+    let config: Config = toml::from_str("[batch]\ntimeout = 10").unwrap();
+    let batch = config.batch.unwrap();
+    assert_eq!(batch.max_items, 20);
+    assert_eq!(batch.concurrency, 4);
+    assert_eq!(batch.timeout, Some(10));
+    assert!(Config::default().batch.is_none());
+}
+
+#[test]
+fn test_brownout_config() {
+    let config_str = r#"
+        [brownout]
+        routes = ["/chat"]
+        latency_ms = 2000
+        model = "gpt-4o-mini"
+    "#;
+    let config: Config = toml::from_str(config_str).unwrap();
+    let brownout = config.brownout.unwrap();
+    assert_eq!(brownout.routes, ["/chat"]);
+    assert_eq!(brownout.latency_ms, Some(2000));
+    assert_eq!(brownout.queue_depth, None);
+    assert_eq!(brownout.window(), Duration::from_secs(10));
+    assert_eq!(brownout.model.as_deref(), Some("gpt-4o-mini"));
+    assert!(!brownout.fallback);
+    assert!(Config::default().brownout.is_none());
+}
+
+#[test]
+fn test_route_roots() {
+    let config: Config = toml::from_str("").unwrap();
+    let roots = config.route_roots();
+    assert_eq!(roots.len(), 1);
+    assert_eq!(roots[0].dir.to_str(), Some("routes"));
+    assert_eq!(roots[0].mount_prefix("/users"), "/users");
+
+    let config_str = r#"
+        [[routes]]
+        dir = "routes"
+
+        [[routes]]
+        dir = "admin_routes"
+        mount = "/admin/"
+        auth = "jwt"
+        timeout = 5
+    "#;
+    let config: Config = toml::from_str(config_str).unwrap();
+    let roots = config.route_roots();
+    assert_eq!(roots.len(), 2);
+    let admin = &roots[1];
+    assert_eq!(admin.mount_prefix("/"), "/admin");
+    assert_eq!(admin.mount_prefix("/users"), "/admin/users");
+    let annotation = admin.annotation();
+    assert!(annotation.is_jwt_auth());
+    assert_eq!(annotation.timeout, Some(std::time::Duration::from_secs(5)));
+}
+
+#[test]
+fn test_server_http_config() {
+    let config: Config = toml::from_str("").unwrap();
+    assert!(!config.server.http2);
+    assert!(config.server.keep_alive);
+    assert!(!config.server.tcp_nodelay);
+
+    let config_str = r#"
+        [server]
+        http2 = true
+        http2_max_concurrent_streams = 250
+        http2_keep_alive_interval = 20
+        keep_alive = false
+        tcp_nodelay = true
+    "#;
+    let config: Config = toml::from_str(config_str).unwrap();
+    assert!(config.server.http2);
+    assert_eq!(config.server.http2_max_concurrent_streams, Some(250));
+    assert_eq!(config.server.http2_keep_alive_interval, Some(20));
+    assert!(config.server.http2_keep_alive_timeout.is_none());
+    assert!(!config.server.keep_alive);
+    assert!(config.server.tcp_nodelay);
+}
+
+#[test]
+fn test_trusted_proxies_config() {
+    let config_str = r#"
+        [server.trusted_proxies]
+        ips = ["10.0.0.0/8", "127.0.0.1"]
+    "#;
+    let config: Config = toml::from_str(config_str).unwrap();
+    let proxies = config.server.trusted_proxies.unwrap();
+    assert_eq!(proxies.header, "x-forwarded-for");
+    assert!(proxies.contains(&"10.20.30.40".parse().unwrap()));
+    assert!(proxies.contains(&"127.0.0.1".parse().unwrap()));
+    assert!(!proxies.contains(&"127.0.0.2".parse().unwrap()));
+
+    let config_str = r#"
+        [server.trusted_proxies]
+        ips = ["10.0.0.0/33"]
+    "#;
+    assert!(toml::from_str::<Config>(config_str).is_err());
+}
+
+#[test]
+fn test_maintenance_config() {
+    let config: Config = toml::from_str("").unwrap();
+    assert!(!config.maintenance.enabled);
+    assert!(config.maintenance.token.is_none());
+
+    let config_str = r#"
+        [maintenance]
+        file = ".maintenance"
+        allow = ["/health", "/admin/*"]
+        retry_after = 300
+        token = "secret"
+    "#;
+    let config: Config = toml::from_str(config_str).unwrap();
+    let maintenance = config.maintenance;
+    assert_eq!(
+        maintenance.file,
+        Some(std::path::PathBuf::from(".maintenance"))
+    );
+    assert_eq!(maintenance.allow, ["/health", "/admin/*"]);
+    assert_eq!(maintenance.retry_after, Some(300));
+    assert_eq!(
+        maintenance.message,
+        "Service is under maintenance, please retry later."
+    );
+    assert_eq!(
+        maintenance.token.as_deref().map(String::as_str),
+        Some("secret")
+    );
+}
@@ -0,0 +1,146 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use crate::ast::{HttpMethod, Route};
+
+// An endpoint path registered to the router.
+struct Registration<'a> {
+    file: &'a Path,
+    line: u32,
+    method: &'a HttpMethod,
+    path: String,
+}
+
+impl fmt::Display for Registration<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} ({}:{})",
+            self.method.as_str(),
+            self.path,
+            self.file.display(),
+            self.line
+        )
+    }
+}
+
+// The parameter name if the path segment is a `{param}`.
+fn param(segment: &str) -> Option<&str> {
+    segment.strip_prefix('{')?.strip_suffix('}')
+}
+
+// Why the two registrations can't live in the same router, if they can't.
+fn conflict(a: &Registration, b: &Registration) -> Option<&'static str> {
+    let a_segments: Vec<_> = a.path.split('/').filter(|s| !s.is_empty()).collect();
+    let b_segments: Vec<_> = b.path.split('/').filter(|s| !s.is_empty()).collect();
+    for (x, y) in a_segments.iter().zip(&b_segments) {
+        match (param(x), param(y)) {
+            // The router requires the same name for parameters at the same position,
+            // whatever the method and the rest of the path are.
+            (Some(p), Some(q)) if p != q => return Some("Conflicting path parameters"),
+            (Some(_), Some(_)) => {}
+            // Static segments take precedence over parameters
+            (None, None) if x == y => {}
+            _ => return None,
+        }
+    }
+    if a_segments.len() == b_segments.len() && a.method == b.method {
+        Some("Duplicate route")
+    } else {
+        None
+    }
+}
+
+/// Find the duplicate and conflicting endpoints across the route files,
+/// which would otherwise make the router panic or shadow each other.
+pub(crate) fn find_conflicts(routes: &[(PathBuf, Route)]) -> Vec<String> {
+    let mut registered: Vec<Registration> = Vec::new();
+    let mut errors = Vec::new();
+    for (file, route) in routes {
+        for endpoint in &route.endpoints {
+            for spec in &endpoint.path_specs {
+                // A nested `/` endpoint matches the route prefix only
+                let path = match (route.prefix.as_str(), spec.path.as_str()) {
+                    ("/", path) => path.to_owned(),
+                    (prefix, "/") => prefix.to_owned(),
+                    (prefix, path) => format!("{prefix}{path}"),
+                };
+                let registration = Registration {
+                    file,
+                    line: spec.line,
+                    method: &spec.method,
+                    path,
+                };
+                for other in &registered {
+                    if let Some(reason) = conflict(other, &registration) {
+                        errors.push(format!("{reason}: {other} and {registration}"));
+                    }
+                }
+                registered.push(registration);
+            }
+        }
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_route;
+
+    fn routes(files: &[(&str, &str)]) -> Vec<(PathBuf, Route)> {
+        files
+            .iter()
+            .map(|(file, source)| (PathBuf::from(file), parse_route(source).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_duplicate_routes() {
+        let routes = routes(&[
+            ("a.ai", "route /users {\n    get / { return 1; }\n}"),
+            ("b.ai", "get /users {\n    return 2;\n}"),
+        ]);
+        assert_eq!(
+            find_conflicts(&routes),
+            ["Duplicate route: GET /users (a.ai:2) and GET /users (b.ai:1)"]
+        );
+    }
+
+    #[test]
+    fn test_conflicting_params() {
+        let routes = routes(&[
+            (
+                "a.ai",
+                "get /users/:id {\n    path { id: int }\n    return id;\n}",
+            ),
+            (
+                "b.ai",
+                "post /users/:name/posts {\n    path { name: str }\n    return name;\n}",
+            ),
+        ]);
+        assert_eq!(
+            find_conflicts(&routes),
+            [
+                "Conflicting path parameters: GET /users/{id} (a.ai:1) and POST /users/{name}/posts (b.ai:1)"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_conflicts() {
+        let routes = routes(&[
+            (
+                "a.ai",
+                "get /users/:id {\n    path { id: int }\n    return id;\n}",
+            ),
+            (
+                "b.ai",
+                "post /users/:id {\n    path { id: int }\n    return id;\n}\nget /users/me {\n    return 1;\n}",
+            ),
+        ]);
+        assert!(find_conflicts(&routes).is_empty());
+    }
+}
@@ -0,0 +1,324 @@
+//! Contract tests replaying the examples of the generated OpenAPI document
+//! against the router, every operation is sent a request built from the
+//! examples of its parameters and body, and its response is checked
+//! against the documented status and response example.
+
+use std::path::PathBuf;
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode, header},
+};
+use oas3::{
+    Spec,
+    spec::{MediaTypeExamples, Operation, ParameterIn},
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// Run the contract tests of the routes, returns whether they all pass.
+pub async fn run_contract_tests(path: Option<PathBuf>) -> bool {
+    let Some(app) = crate::build_app(path.as_deref(), false).await else {
+        return false;
+    };
+    let router = app.router;
+    let spec = match fetch_spec(&router).await {
+        Ok(spec) => spec,
+        Err(err) => {
+            eprintln!("Failed to read the OpenAPI document: {err}");
+            return false;
+        }
+    };
+
+    let (mut passed, mut failed, mut skipped) = (0, 0, 0);
+    for (path, method, operation) in spec.operations() {
+        let name = format!("{method} {path}");
+        match check_operation(&router, &spec, &path, &method, operation).await {
+            Outcome::Passed => {
+                passed += 1;
+                println!("PASS {name}");
+            }
+            Outcome::Skipped(reason) => {
+                skipped += 1;
+                println!("SKIP {name}: {reason}");
+            }
+            Outcome::Failed(reason) => {
+                failed += 1;
+                println!("FAIL {name}: {reason}");
+            }
+        }
+    }
+    println!("\n{passed} passed, {failed} failed, {skipped} skipped");
+    if let Err(err) = aiscript_vm::save_coverage() {
+        eprintln!("Failed to save coverage: {err}");
+    }
+    failed == 0
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Passed,
+    Skipped(String),
+    Failed(String),
+}
+
+async fn fetch_spec(router: &Router) -> Result<Spec, String> {
+    let request = Request::get("/openapi.json")
+        .body(Body::empty())
+        .map_err(|err| err.to_string())?;
+    let response = router
+        .clone()
+        .oneshot(request)
+        .await
+        .map_err(|err| err.to_string())?;
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|err| err.to_string())?;
+    serde_json::from_slice(&body).map_err(|err| err.to_string())
+}
+
+async fn check_operation(
+    router: &Router,
+    spec: &Spec,
+    path: &str,
+    method: &Method,
+    operation: &Operation,
+) -> Outcome {
+    let request = match build_request(spec, path, method, operation) {
+        Ok(request) => request,
+        Err(reason) => return Outcome::Skipped(reason),
+    };
+    let response = match router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(err) => return Outcome::Failed(err.to_string()),
+    };
+    let status = response.status();
+    if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        return Outcome::Skipped("authentication required".to_string());
+    }
+    let body = match to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(err) => return Outcome::Failed(err.to_string()),
+    };
+
+    let documented = operation
+        .responses
+        .iter()
+        .flatten()
+        .find(|(code, _)| code.as_str() == status.as_str());
+    let Some((_, response)) = documented else {
+        let expected = operation
+            .responses
+            .iter()
+            .flatten()
+            .map(|(code, _)| code.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Outcome::Failed(format!(
+            "got status {}, documented {expected}: {}",
+            status.as_u16(),
+            String::from_utf8_lossy(&body)
+        ));
+    };
+    let example = response
+        .resolve(spec)
+        .ok()
+        .and_then(|response| response.content.get("application/json").cloned())
+        .and_then(|media| match media.examples {
+            Some(MediaTypeExamples::Example { example }) => Some(example),
+            _ => None,
+        });
+    if let Some(example) = example {
+        let value = match serde_json::from_slice::<Value>(&body) {
+            Ok(value) => value,
+            Err(err) => return Outcome::Failed(format!("the response isn't JSON: {err}")),
+        };
+        if let Err(mismatch) = match_shape(&example, &value, "$") {
+            return Outcome::Failed(mismatch);
+        }
+    }
+    Outcome::Passed
+}
+
+// The request of the operation from the examples of its parameters and body.
+fn build_request(
+    spec: &Spec,
+    path: &str,
+    method: &Method,
+    operation: &Operation,
+) -> Result<Request<Body>, String> {
+    let mut uri = path.to_string();
+    let mut query = Vec::new();
+    for parameter in &operation.parameters {
+        let parameter = parameter.resolve(spec).map_err(|err| err.to_string())?;
+        let example = parameter.example.or_else(|| {
+            parameter
+                .schema
+                .and_then(|schema| schema.resolve(spec).ok())
+                .and_then(|schema| schema.example.or(schema.default))
+        });
+        let Some(example) = example else {
+            if parameter.required == Some(true) {
+                return Err(format!("no example for parameter '{}'", parameter.name));
+            }
+            continue;
+        };
+        let value = match example {
+            Value::String(s) => s,
+            value => value.to_string(),
+        };
+        match parameter.location {
+            ParameterIn::Path => {
+                uri = uri.replace(&format!("{{{}}}", parameter.name), &value);
+            }
+            ParameterIn::Query => {
+                query.push(format!("{}={}", parameter.name, percent_encode(&value)))
+            }
+            _ => {}
+        }
+    }
+    if !query.is_empty() {
+        uri = format!("{uri}?{}", query.join("&"));
+    }
+
+    let mut builder = Request::builder().method(method.clone()).uri(uri);
+    let body = match &operation.request_body {
+        Some(body) => {
+            let body = body.resolve(spec).map_err(|err| err.to_string())?;
+            let (content_type, media) = body.content.into_iter().next().ok_or("no body content")?;
+            let schema = media
+                .schema
+                .ok_or("no body schema")?
+                .resolve(spec)
+                .map_err(|err| err.to_string())?;
+            let mut fields = serde_json::Map::new();
+            for (name, property) in schema.properties {
+                let property = property.resolve(spec).map_err(|err| err.to_string())?;
+                match property.example.or(property.default) {
+                    Some(example) => {
+                        fields.insert(name, example);
+                    }
+                    None if schema.required.contains(&name) => {
+                        return Err(format!("no example for body field '{name}'"));
+                    }
+                    None => {}
+                }
+            }
+            builder = builder.header(header::CONTENT_TYPE, &content_type);
+            if content_type == "application/x-www-form-urlencoded" {
+                let form = fields
+                    .into_iter()
+                    .map(|(name, value)| {
+                        let value = match value {
+                            Value::String(s) => s,
+                            value => value.to_string(),
+                        };
+                        format!("{name}={}", percent_encode(&value))
+                    })
+                    .collect::<Vec<_>>();
+                Body::from(form.join("&"))
+            } else {
+                Body::from(Value::Object(fields).to_string())
+            }
+        }
+        None => Body::empty(),
+    };
+    builder.body(body).map_err(|err| err.to_string())
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+// Check the value has the shape of the example: the same JSON types, and
+// the fields of the example objects. Arrays are checked against their first
+// example item, a null example matches anything, even a missing field.
+fn match_shape(example: &Value, value: &Value, at: &str) -> Result<(), String> {
+    match (example, value) {
+        (Value::Null, _) => Ok(()),
+        (Value::Bool(_), Value::Bool(_))
+        | (Value::Number(_), Value::Number(_))
+        | (Value::String(_), Value::String(_)) => Ok(()),
+        (Value::Array(example), Value::Array(values)) => match example.first() {
+            Some(example) => values
+                .iter()
+                .enumerate()
+                .try_for_each(|(i, value)| match_shape(example, value, &format!("{at}[{i}]"))),
+            None => Ok(()),
+        },
+        (Value::Object(example), Value::Object(object)) => {
+            example
+                .iter()
+                .try_for_each(|(name, example)| match object.get(name) {
+                    Some(value) => match_shape(example, value, &format!("{at}.{name}")),
+                    None if example.is_null() => Ok(()),
+                    None => Err(format!("missing field {at}.{name}")),
+                })
+        }
+        (example, value) => Err(format!(
+            "{at} is {}, expected {} like the example",
+            type_name(value),
+            type_name(example)
+        )),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_match_shape() {
+        let example = json!({"id": 1, "name": "Ada", "tags": ["admin"], "manager": null});
+        assert!(
+            match_shape(
+                &example,
+                &json!({"id": 2, "name": "Bob", "tags": [], "manager": {"id": 1}, "extra": true}),
+                "$"
+            )
+            .is_ok()
+        );
+        assert_eq!(
+            match_shape(&example, &json!({"id": 2, "tags": []}), "$"),
+            Err("missing field $.name".to_string())
+        );
+        assert_eq!(
+            match_shape(
+                &example,
+                &json!({"id": "2", "name": "Bob", "tags": [], "manager": null}),
+                "$"
+            ),
+            Err("$.id is a string, expected a number like the example".to_string())
+        );
+        assert_eq!(
+            match_shape(
+                &example,
+                &json!({"id": 2, "name": "Bob", "tags": ["a", 1], "manager": null}),
+                "$"
+            ),
+            Err("$.tags[1] is a number, expected a string like the example".to_string())
+        );
+    }
+}
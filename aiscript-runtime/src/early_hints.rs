@@ -0,0 +1,123 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The handle of an HTTP/1.1 connection to send a `103 Early Hints` response
+/// ahead of the final one, hyper has no API for informational responses.
+#[derive(Clone)]
+pub(crate) struct EarlyHints(Arc<Mutex<BytesMut>>);
+
+impl EarlyHints {
+    /// Queue the hints, they are written before the next bytes of the
+    /// connection, which is flushed once the handler yields.
+    pub(crate) fn send(&self, links: &[String]) {
+        let mut pending = self.0.lock().unwrap();
+        pending.extend_from_slice(b"HTTP/1.1 103 Early Hints\r\n");
+        for link in links {
+            pending.extend_from_slice(b"link: ");
+            pending.extend_from_slice(link.as_bytes());
+            pending.extend_from_slice(b"\r\n");
+        }
+        pending.extend_from_slice(b"\r\n");
+    }
+}
+
+/// The IO of a connection writing the queued early hints first.
+pub(crate) struct HintedIo<T> {
+    io: T,
+    pending: Arc<Mutex<BytesMut>>,
+}
+
+impl<T: AsyncWrite + Unpin> HintedIo<T> {
+    pub(crate) fn new(io: T) -> (Self, EarlyHints) {
+        let pending = Arc::new(Mutex::new(BytesMut::new()));
+        let hints = EarlyHints(pending.clone());
+        (Self { io, pending }, hints)
+    }
+
+    // Write the queued hints, ready once there are none left
+    fn poll_hints(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut pending = self.pending.lock().unwrap();
+        while !pending.is_empty() {
+            match Pin::new(&mut self.io).poll_write(cx, &pending)? {
+                Poll::Ready(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(n) => pending.advance(n),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for HintedIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for HintedIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        std::task::ready!(self.poll_hints(cx))?;
+        Pin::new(&mut self.io).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        std::task::ready!(self.poll_hints(cx))?;
+        Pin::new(&mut self.io).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        std::task::ready!(self.poll_hints(cx))?;
+        Pin::new(&mut self.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.io).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hints_before_response() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (mut server, hints) = HintedIo::new(server);
+        hints.send(&["</app.css>; rel=preload; as=style".to_string()]);
+        server.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+        drop(server);
+
+        let mut received = String::new();
+        let mut client = client;
+        client.read_to_string(&mut received).await.unwrap();
+        assert_eq!(
+            received,
+            "HTTP/1.1 103 Early Hints\r\nlink: </app.css>; rel=preload; as=style\r\n\r\nHTTP/1.1 200 OK\r\n\r\n"
+        );
+    }
+}
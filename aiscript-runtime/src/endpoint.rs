@@ -1,10 +1,10 @@
 use aiscript_directive::{Validator, route::RouteAnnotation};
-use aiscript_vm::{ReturnValue, Vm, VmError};
+use aiscript_vm::{Arg, BudgetScope, CompiledProgram, ReturnValue, Vm, VmError};
 use axum::{
     Form, Json, RequestExt,
-    body::Body,
-    extract::{self, FromRequest, RawPathParams, Request},
-    http::{HeaderName, HeaderValue},
+    body::{Body, Bytes},
+    extract::{self, FromRequest, MatchedPath, RawPathParams, Request},
+    http::{HeaderName, HeaderValue, header},
     response::{IntoResponse, Response},
 };
 use axum_extra::{
@@ -19,26 +19,44 @@ use jsonwebtoken::{Algorithm, DecodingKey, TokenData, Validation, decode};
 use serde_json::Value;
 use sqlx::{PgPool, SqlitePool};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::Infallible,
     future::Future,
     mem,
+    net::IpAddr,
     pin::Pin,
     sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, oneshot},
+    task::JoinHandle,
+    time::Sleep,
 };
-use tokio::task::{self, JoinHandle};
 use tower::Service;
 
 use crate::{
     Config,
     ast::{self, *},
+    brownout::{self, BROWNOUT_HEADER, Mode},
+    client_ip::client_ip,
+    concurrency::{ConcurrencyLimiter, Slot},
+    early_hints::EarlyHints,
+    json_body::{JsonFields, read_json_fields, response_json, send_json},
+    stream::{accepts_event_stream, stream},
+    workers,
 };
 
 use crate::error::ServerError;
 
 type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
+// The header flagging a response served by a fallback.
+const DEGRADED_HEADER: &str = "x-degraded";
+// The header carrying the request id, honored if the client sent one.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
 #[derive(Clone)]
 pub struct Field {
     name: String,
@@ -48,6 +66,12 @@ pub struct Field {
     validators: Arc<[Box<dyn Validator>]>,
 }
 
+impl Field {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 #[derive(Clone)]
 pub struct Endpoint {
     pub annotation: RouteAnnotation,
@@ -55,50 +79,306 @@ pub struct Endpoint {
     pub query_params: Vec<Field>,
     pub body_type: BodyKind,
     pub body_fields: Vec<Field>,
-    pub script: String,
+    // The handler script, compiled once when the routes are loaded.
+    pub program: CompiledProgram,
     pub path_specs: Vec<PathSpec>,
     // pub provider_manager: Arc<ProviderManager>,
     pub pg_connection: Option<PgPool>,
     pub sqlite_connection: Option<SqlitePool>,
     pub redis_connection: Option<redis::aio::MultiplexedConnection>,
+    // The slots of `@concurrency`, shared by the clones of the endpoint.
+    pub concurrency: Option<Arc<ConcurrencyLimiter>>,
+    // Serve the `@mock` example instead of running the handler, see `serve --mock`.
+    pub mock: bool,
 }
 
 enum ProcessingState {
+    ValidatingIp,
     ValidatingAuth,
     ValidatingPath,
     ValidatingQuery,
+    // Waiting in the `@concurrency` queue for a free slot.
+    Queuing(BoxFuture<OwnedSemaphorePermit>),
     ValidatingBody,
-    Executing(JoinHandle<Result<ReturnValue, VmError>>),
+    // Receiving the body, kept across the polls as a large upload comes
+    // in over several reads.
+    ReadingBody(BoxFuture<Result<JsonFields, ServerError>>),
+    // The script execution, and the timer of the request deadline if any.
+    // The script returns whether a fallback value was served along with its result.
+    // The body is received instead if the handler returns a generator, the script
+    // keeps running to stream its values.
+    Executing(
+        JoinHandle<Result<(ReturnValue, bool), VmError>>,
+        Option<Pin<Box<Sleep>>>,
+        Option<oneshot::Receiver<Response>>,
+    ),
+    // The `@fallback` function execution after the handler failed,
+    // the error response is served if the fallback fails too.
+    Degrading(JoinHandle<Result<ReturnValue, VmError>>, Option<Response>),
 }
 
 pub struct RequestProcessor {
     endpoint: Endpoint,
     request: Request<Body>,
     jwt_claim: Option<Value>,
+    // The authenticated user, AI costs of the request are charged to it.
+    principal: Option<String>,
     path_data: HashMap<String, Value>,
     query_data: HashMap<String, Value>,
-    body_data: HashMap<String, Value>,
+    body_data: JsonFields,
+    // The id correlating the logs, AI provider calls and database queries of the request.
+    request_id: String,
+    // The client IP, forwarded by a trusted proxy or the peer address.
+    client_ip: Option<IpAddr>,
+    // The `@concurrency` slot, released when the script finishes.
+    permit: Option<OwnedSemaphorePermit>,
+    // When the request came in, the latency of the handler is recorded for `[brownout]`.
+    started: Instant,
+    // The mode of a route of `[brownout]`, set once the handler is about to run.
+    brownout: Option<Mode>,
     state: ProcessingState,
 }
 
 impl RequestProcessor {
     fn new(endpoint: Endpoint, request: Request<Body>) -> Self {
-        let state = if endpoint.annotation.is_auth_required() {
+        let state = if endpoint.annotation.has_ip_acl() {
+            ProcessingState::ValidatingIp
+        } else if endpoint.annotation.is_auth_required() {
             ProcessingState::ValidatingAuth
         } else {
             ProcessingState::ValidatingPath
         };
+        let request_id = Self::request_id(&request);
+        let client_ip = client_ip(&request, Config::get().server.trusted_proxies.as_ref());
         Self {
             endpoint,
             request,
             jwt_claim: None,
+            principal: None,
             path_data: HashMap::new(),
             query_data: HashMap::new(),
-            body_data: HashMap::new(),
+            body_data: JsonFields::new(),
+            request_id,
+            client_ip,
+            permit: None,
+            started: Instant::now(),
+            brownout: None,
             state,
         }
     }
 
+    // The incoming request id if it's well formed, a random one otherwise.
+    // The id ends up in SQL comments, so only a safe charset is accepted.
+    fn request_id(request: &Request<Body>) -> String {
+        request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= 128
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+            })
+            .map(str::to_owned)
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()))
+    }
+
+    fn deadline_exceeded() -> Response {
+        (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(serde_json::json!({ "error": "Request timed out." })),
+        )
+            .into_response()
+    }
+
+    // The response with the status code and headers of a `Response`, its
+    // JSON body is written by `send_json()` or `value_response()`.
+    fn response_head(mut fields: BTreeMap<String, Value>) -> Response {
+        let mut response = [(header::CONTENT_TYPE, "application/json")].into_response();
+        *response.status_mut() = StatusCode::from_u16(
+            fields
+                .remove("status_code")
+                .map(|v| v.as_f64().unwrap())
+                .unwrap_or(200f64) as u16,
+        )
+        .unwrap();
+        if let Some(headers) = fields.remove("headers") {
+            response
+                .headers_mut()
+                .extend(
+                    headers
+                        .as_object()
+                        .unwrap()
+                        .into_iter()
+                        .map(|(name, value)| {
+                            (
+                                HeaderName::try_from(name).unwrap(),
+                                HeaderValue::from_str(value.as_str().unwrap()).unwrap(),
+                            )
+                        }),
+                );
+        }
+        response
+    }
+
+    // The response of a value returned by the fallback, or of a string or
+    // a number returned by the handler.
+    fn value_response(value: ReturnValue) -> Response {
+        let (fields, body) = match value {
+            ReturnValue::Response(mut fields) => {
+                let body = fields.remove("body").unwrap_or_default();
+                (fields, body)
+            }
+            value => (
+                BTreeMap::new(),
+                serde_json::to_value(value).unwrap_or_default(),
+            ),
+        };
+        let body = response_json(body, Config::get().json.big_int_strings);
+        let mut response = Self::response_head(fields);
+        *response.body_mut() = Body::from(serde_json::to_vec(&body).unwrap_or_default());
+        response
+    }
+
+    // The `@mock` example of the endpoint, the request is validated but the
+    // handler doesn't run.
+    fn mock_response(annotation: &RouteAnnotation) -> Response {
+        match &annotation.mock {
+            Some(mock) => (
+                StatusCode::from_u16(mock.status).unwrap_or(StatusCode::OK),
+                Json(mock.example.clone()),
+            )
+                .into_response(),
+            None => (
+                StatusCode::NOT_IMPLEMENTED,
+                Json(serde_json::json!({
+                    "error": "No mock example for this endpoint, add @mock(example=...)."
+                })),
+            )
+                .into_response(),
+        }
+    }
+
+    fn error_response(err: VmError) -> Response {
+        match err {
+            VmError::CompileError => "Compile Error".into_response(),
+            VmError::RuntimeError(message) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({ "error": message })),
+            )
+                .into_response(),
+            VmError::Traced(error) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": error.message,
+                    "backtrace": error.backtrace,
+                })),
+            )
+                .into_response(),
+            VmError::RateLimited { retry_after } => {
+                // Shed the request when the AI provider is saturated
+                let mut response = (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(serde_json::json!({
+                        "error": "AI provider is rate limited, please retry later."
+                    })),
+                )
+                    .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                response
+            }
+            VmError::BudgetExceeded(message) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(serde_json::json!({ "error": message })),
+            )
+                .into_response(),
+            VmError::DeadlineExceeded => Self::deadline_exceeded(),
+            // The script is stopped, e.g. an infinite loop
+            VmError::LimitExceeded(message) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({ "error": message })),
+            )
+                .into_response(),
+            err @ VmError::CircuitOpen { retry_after, .. } => {
+                // Fail fast while a dependency is down
+                let mut response = (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    Json(serde_json::json!({ "error": err.to_string() })),
+                )
+                    .into_response();
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                response
+            }
+            VmError::Raised(error) => {
+                // The status of the error type in `[errors]`
+                let status = Config::get()
+                    .errors
+                    .get(&error.name)
+                    .and_then(|error_type| StatusCode::from_u16(error_type.status).ok())
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                let mut fields = error.fields;
+                let message = match fields.remove("message") {
+                    Some(serde_json::Value::String(message)) if !message.is_empty() => message,
+                    _ => error.name.clone(),
+                };
+                let mut body = serde_json::Map::new();
+                body.insert("error".into(), message.into());
+                body.insert("type".into(), error.name.into());
+                body.extend(fields);
+                (status, Json(serde_json::Value::Object(body))).into_response()
+            }
+        }
+    }
+
+    // All the workers are busy and their queue is full.
+    fn overloaded() -> Response {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "error": "The server is overloaded, please retry later."
+            })),
+        )
+            .into_response()
+    }
+
+    // Flag the response served by a fallback instead of the failed call.
+    fn degraded(mut response: Response) -> Response {
+        response
+            .headers_mut()
+            .insert(DEGRADED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+
+    // Run the `@fallback` function of the endpoint in a new VM, as the failed
+    // one may still be blocked on the call. None if there is no fallback,
+    // or no free worker to run it.
+    fn spawn_fallback(&self) -> Option<JoinHandle<Result<ReturnValue, VmError>>> {
+        let name = self.endpoint.annotation.fallback.clone()?;
+        let program = self.endpoint.program.clone();
+        let request_id = self.request_id.clone();
+        let pg_connection = self.endpoint.pg_connection.clone();
+        let sqlite_connection = self.endpoint.sqlite_connection.clone();
+        let redis_connection = self.endpoint.redis_connection.clone();
+        workers::spawn_handler(move || {
+            let mut vm = Vm::new(
+                pg_connection,
+                sqlite_connection,
+                redis_connection,
+                Config::load().ai.clone(),
+            );
+            vm.set_request_id(request_id);
+            vm.register_extra_native_functions();
+            vm.load(&program)?;
+            vm.eval_fallback(&name)
+        })
+    }
+
     fn validate_field(field: &Field, value: &Value) -> Result<Value, ServerError> {
         // Try to convert the value if it doesn't match the expected type
         let converted_value = match (field.field_type, value) {
@@ -186,18 +466,53 @@ impl RequestProcessor {
         Ok(converted_value)
     }
 
-    async fn process_json_body(request: Request<Body>) -> Result<Value, ServerError> {
-        Json::<Value>::from_request(request, &())
-            .await
-            .map(|Json(body)| body)
-            .map_err(ServerError::JsonParseError)
+    // Validate the JSON text of a body field. It's only parsed if it has
+    // validators or isn't of the type of the field, which its first byte
+    // tells otherwise.
+    fn validate_json_field(field: &Field, json: &[u8]) -> Result<(), ServerError> {
+        let of_type = matches!(
+            (field.field_type, json.trim_ascii_start().first()),
+            (FieldType::Str, Some(b'"'))
+                | (FieldType::Number, Some(b'-' | b'0'..=b'9'))
+                | (FieldType::Bool, Some(b't' | b'f'))
+                | (FieldType::Array, Some(b'['))
+        );
+        if of_type && field.validators.is_empty() {
+            return Ok(());
+        }
+        let value = serde_json::from_slice(json)?;
+        Self::validate_field(field, &value).map(|_| ())
+    }
+
+    // A request with the body and the head of the processed one, which is
+    // kept to describe the request to the handler.
+    fn take_body_request(&mut self) -> Request<Body> {
+        let mut request = Request::new(mem::take(self.request.body_mut()));
+        *request.method_mut() = self.request.method().clone();
+        *request.uri_mut() = self.request.uri().clone();
+        *request.headers_mut() = self.request.headers().clone();
+        request
     }
 
-    async fn process_form_body(request: Request<Body>) -> Result<Value, ServerError> {
-        Form::<Value>::from_request(request, &())
-            .await
-            .map(|Form(body)| body)
-            .map_err(ServerError::FormParseError)
+    // Send the `@early_hints` links in a `103 Early Hints` response, only
+    // possible on an HTTP/1.1 connection.
+    fn send_early_hints(&self) {
+        if let Some(links) = &self.endpoint.annotation.early_hints
+            && let Some(hints) = self.request.extensions().get::<EarlyHints>()
+        {
+            hints.send(links);
+        }
+    }
+
+    async fn process_form_body(request: Request<Body>) -> Result<JsonFields, ServerError> {
+        match Form::<Value>::from_request(request, &()).await {
+            Ok(Form(Value::Object(body))) => Ok(body
+                .into_iter()
+                .map(|(name, value)| (name, Bytes::from(value.to_string())))
+                .collect()),
+            Ok(_) => Ok(JsonFields::new()),
+            Err(err) => Err(ServerError::FormParseError(err)),
+        }
     }
 
     fn get_request(&self) -> HashMap<&'static str, Value> {
@@ -218,6 +533,7 @@ impl RequestProcessor {
             ("query", uri.query().into()),
             ("protocol", uri.scheme_str().into()),
             ("port", uri.port_u16().into()),
+            ("ip", self.client_ip.map(|ip| ip.to_string()).into()),
             // ("fragment", uri.fragment().into()),
         ]
         .into_iter()
@@ -242,9 +558,68 @@ impl Future for RequestProcessor {
     type Output = Result<Response, Infallible>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let result = match self.as_mut().process(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        // The handler ran, unless the brownout served the fallback
+        if let Some(config) = &Config::get().brownout
+            && matches!(
+                self.state,
+                ProcessingState::Executing(..) | ProcessingState::Degrading(..)
+            )
+            && self.brownout != Some(Mode::Fallback)
+        {
+            brownout::record(config, self.started.elapsed());
+        }
+        let brownout = self.brownout;
+        let request_id = HeaderValue::from_str(&self.request_id).unwrap();
+        let links = self
+            .endpoint
+            .annotation
+            .early_hints
+            .as_deref()
+            .unwrap_or_default();
+        Poll::Ready(result.map(|mut response| {
+            response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+            if let Some(mode) = brownout {
+                response
+                    .headers_mut()
+                    .insert(BROWNOUT_HEADER, HeaderValue::from_static(mode.as_str()));
+            }
+            // The hints are repeated in the final response, which is all the
+            // HTTP/2 clients get
+            for link in links {
+                if let Ok(link) = HeaderValue::from_str(link) {
+                    response.headers_mut().append(header::LINK, link);
+                }
+            }
+            response
+        }))
+    }
+}
+
+impl RequestProcessor {
+    fn process(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Response, Infallible>> {
         let config = Config::get();
         loop {
             match &mut self.state {
+                ProcessingState::ValidatingIp => {
+                    if !self.endpoint.annotation.is_ip_allowed(self.client_ip) {
+                        return Poll::Ready(Ok(ServerError::Forbidden(
+                            "Client IP is not allowed".to_string(),
+                        )
+                        .into_response()));
+                    }
+                    self.state = if self.endpoint.annotation.is_auth_required() {
+                        ProcessingState::ValidatingAuth
+                    } else {
+                        ProcessingState::ValidatingPath
+                    };
+                }
                 ProcessingState::ValidatingAuth => {
                     if self.endpoint.annotation.is_jwt_auth() {
                         self.jwt_claim = {
@@ -271,39 +646,51 @@ impl Future for RequestProcessor {
                                 }
                             }
                         };
+                        self.principal = self
+                            .jwt_claim
+                            .as_ref()
+                            .and_then(|claims| claims.get("sub"))
+                            .and_then(|sub| sub.as_str())
+                            .map(|sub| sub.to_owned());
                     } else {
                         // Baisc auth
                         // Extract the token from the authorization header
-                        let future = self
-                            .request
-                            .extract_parts::<TypedHeader<Authorization<Basic>>>();
-                        tokio::pin!(future);
-                        match future.poll(cx) {
-                            Poll::Pending => return Poll::Pending,
-                            Poll::Ready(Ok(basic)) => {
-                                if let Some(b) = config.auth.basic.as_ref() {
-                                    if *b.username != basic.username()
-                                        || *b.password != basic.password()
-                                    {
+                        self.principal = {
+                            let future = self
+                                .request
+                                .extract_parts::<TypedHeader<Authorization<Basic>>>();
+                            tokio::pin!(future);
+                            match future.poll(cx) {
+                                Poll::Pending => return Poll::Pending,
+                                Poll::Ready(Ok(basic)) => {
+                                    if let Some(b) = config.auth.basic.as_ref() {
+                                        if *b.username != basic.username()
+                                            || *b.password != basic.password()
+                                        {
+                                            return Poll::Ready(Ok(
+                                                ServerError::AuthenticationError {
+                                                    message: "Invalid username or password"
+                                                        .to_string(),
+                                                }
+                                                .into_response(),
+                                            ));
+                                        }
+                                    } else {
                                         return Poll::Ready(Ok(ServerError::AuthenticationError {
-                                            message: "Invalid username or password".to_string(),
+                                            message: "Basic auth is not configured".to_string(),
                                         }
                                         .into_response()));
                                     }
-                                } else {
+                                    Some(basic.username().to_owned())
+                                }
+                                Poll::Ready(Err(e)) => {
                                     return Poll::Ready(Ok(ServerError::AuthenticationError {
-                                        message: "Basic auth is not configured".to_string(),
+                                        message: e.to_string(),
                                     }
                                     .into_response()));
                                 }
                             }
-                            Poll::Ready(Err(e)) => {
-                                return Poll::Ready(Ok(ServerError::AuthenticationError {
-                                    message: e.to_string(),
-                                }
-                                .into_response()));
-                            }
-                        }
+                        };
                     }
                     self.state = ProcessingState::ValidatingPath;
                 }
@@ -428,55 +815,98 @@ impl Future for RequestProcessor {
                         return Poll::Ready(Ok(error.into_response()));
                     }
 
+                    // A client waiting for `100 Continue` is only answered by hyper
+                    // once the body is read, after the concurrency queue.
+                    if let Some(expect) = self.request.headers().get(header::EXPECT)
+                        && !expect.as_bytes().eq_ignore_ascii_case(b"100-continue")
+                    {
+                        return Poll::Ready(Ok(StatusCode::EXPECTATION_FAILED.into_response()));
+                    }
+
+                    // The request is accepted, the client can preload while it waits
+                    self.send_early_hints();
+
+                    // The body is read once the request got a slot
+                    let limiter = self.endpoint.concurrency.clone();
+                    self.state = match limiter.map(|limiter| limiter.acquire()) {
+                        None => ProcessingState::ValidatingBody,
+                        Some(Slot::Acquired(permit)) => {
+                            self.permit = Some(permit);
+                            ProcessingState::ValidatingBody
+                        }
+                        Some(Slot::Queued(wait)) => ProcessingState::Queuing(wait),
+                        Some(Slot::Rejected) => {
+                            return Poll::Ready(Ok(ServerError::TooManyRequests(
+                                "the endpoint is at its concurrency limit, please retry later"
+                                    .to_string(),
+                            )
+                            .into_response()));
+                        }
+                    };
+                }
+                ProcessingState::Queuing(wait) => {
+                    let permit = match wait.as_mut().poll(cx) {
+                        Poll::Ready(permit) => permit,
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    self.permit = Some(permit);
                     self.state = ProcessingState::ValidatingBody;
                 }
                 ProcessingState::ValidatingBody => {
-                    let request_obj = self.get_request();
-                    let header_obj = self.get_header();
+                    // The fields are taken once validated, the body is read once
                     if !self.endpoint.body_fields.is_empty() {
-                        let request = mem::take(&mut self.request);
-                        let body_fut: BoxFuture<Result<Value, ServerError>> =
-                            match self.endpoint.body_type {
-                                BodyKind::Json => Box::pin(Self::process_json_body(request)),
-                                BodyKind::Form => Box::pin(Self::process_form_body(request)),
-                            };
+                        let request = self.take_body_request();
+                        self.state = ProcessingState::ReadingBody(match self.endpoint.body_type {
+                            BodyKind::Json => Box::pin(read_json_fields(
+                                request,
+                                self.endpoint
+                                    .body_fields
+                                    .iter()
+                                    .map(|field| field.name.clone())
+                                    .collect(),
+                            )),
+                            BodyKind::Form => Box::pin(Self::process_form_body(request)),
+                        });
+                        continue;
+                    }
 
-                        tokio::pin!(body_fut);
+                    if self.endpoint.mock {
+                        return Poll::Ready(Ok(Self::mock_response(&self.endpoint.annotation)));
+                    }
 
-                        let body = match body_fut.poll(cx) {
-                            Poll::Pending => return Poll::Pending,
-                            Poll::Ready(Ok(value)) => value,
-                            Poll::Ready(Err(e)) => {
-                                return Poll::Ready(Ok(
-                                    format!("Body parsing error: {:?}", e).into_response()
-                                ));
-                            }
+                    let route = self
+                        .request
+                        .extensions()
+                        .get::<MatchedPath>()
+                        .map(|path| path.as_str().to_owned());
+                    self.brownout = config.brownout.as_ref().zip(route.as_deref()).and_then(
+                        |(brownout, route)| {
+                            brownout::mode(
+                                brownout,
+                                route,
+                                self.endpoint.annotation.fallback.is_some(),
+                            )
+                        },
+                    );
+                    if self.brownout == Some(Mode::Fallback) {
+                        let Some(handle) = self.spawn_fallback() else {
+                            return Poll::Ready(Ok(Self::overloaded()));
                         };
-
-                        let mut failed_validation = None;
-                        for field in mem::take(&mut self.endpoint.body_fields) {
-                            if let Some(value) = body.get(&field.name) {
-                                if let Err(e) = Self::validate_field(&field, value) {
-                                    failed_validation = Some(e);
-                                    break;
-                                }
-                                self.body_data.insert(field.name.clone(), value.clone());
-                            } else if let Some(default) = &field.default {
-                                self.body_data.insert(field.name.clone(), default.clone());
-                            } else if field.required {
-                                failed_validation =
-                                    Some(ServerError::MissingField(field.name.clone()));
-                                break;
-                            }
-                        }
-
-                        if let Some(error) = failed_validation {
-                            return Poll::Ready(Ok(error.into_response()));
-                        }
+                        // The server is overloaded if the fallback fails
+                        self.state = ProcessingState::Degrading(handle, Some(Self::overloaded()));
+                        continue;
                     }
+                    let model_override = match self.brownout {
+                        Some(Mode::Model) => config
+                            .brownout
+                            .as_ref()
+                            .and_then(|brownout| brownout.model.clone()),
+                        _ => None,
+                    };
 
-                    let script = mem::take(&mut self.endpoint.script);
-                    let script = Box::leak(script.into_boxed_str());
+                    let request_obj = self.get_request();
+                    let header_obj = self.get_header();
+                    let program = self.endpoint.program.clone();
                     let sso_fields = if let Some(provider) = self.endpoint.annotation.sso_provider {
                         match crate::config::get_sso_fields(provider) {
                             Some(fields) => Some(fields),
@@ -497,8 +927,29 @@ impl Future for RequestProcessor {
                     let pg_connection = self.endpoint.pg_connection.clone();
                     let sqlite_connection = self.endpoint.sqlite_connection.clone();
                     let redis_connection = self.endpoint.redis_connection.clone();
-                    let handle: JoinHandle<Result<ReturnValue, VmError>> =
-                        task::spawn_blocking(move || {
+                    let request_id = self.request_id.clone();
+                    let permit = self.permit.take();
+                    let budget_scope = BudgetScope {
+                        route,
+                        principal: self.principal.take(),
+                    };
+                    // The route timeout takes precedence over the server-wide one
+                    let deadline = self
+                        .endpoint
+                        .annotation
+                        .timeout
+                        .or(config.network.request_timeout.map(Duration::from_secs))
+                        .and_then(|timeout| Instant::now().checked_add(timeout));
+                    let fuel = config.limits.fuel;
+                    let timeout = config.limits.timeout_ms.map(Duration::from_millis);
+                    let max_heap = config.limits.max_heap_mb.map(|mb| mb * 1024 * 1024);
+                    let (body_sender, body) = oneshot::channel();
+                    let event_stream = accepts_event_stream(self.request.headers());
+                    let handle: Option<JoinHandle<Result<(ReturnValue, bool), VmError>>> =
+                        workers::spawn_handler(move || {
+                            // The slot is freed when the script finishes, even
+                            // past the deadline
+                            let _permit = permit;
                             let ai_config = Config::load().ai.clone();
                             let mut vm = Vm::new(
                                 pg_connection,
@@ -506,76 +957,181 @@ impl Future for RequestProcessor {
                                 redis_connection,
                                 ai_config,
                             );
+                            let ctx_obj = serde_json::json!({ "request_id": request_id });
+                            vm.set_request_id(request_id.clone());
+                            vm.set_budget_scope(budget_scope);
+                            if let Some(model) = model_override {
+                                vm.set_model_override(model);
+                            }
+                            if let Some(deadline) = deadline {
+                                vm.set_deadline(deadline);
+                            }
+                            vm.set_fuel_limit(fuel);
+                            vm.set_timeout(timeout);
+                            vm.set_memory_limit(max_heap);
                             if let Some(fields) = sso_fields {
                                 vm.inject_sso_instance(fields);
                             }
                             vm.register_extra_native_functions();
-                            vm.compile(script)?;
-                            vm.eval_function(
-                                0,
+                            vm.load(&program)?;
+                            // Define the error types declared before the handler
+                            vm.interpret()?;
+                            let handler = vm.function_id("handler").unwrap_or_default();
+                            let value = vm.eval_handler(
+                                handler,
                                 &[
-                                    Value::Object(path_data.into_iter().collect()),
-                                    Value::Object(query_data.into_iter().collect()),
-                                    Value::Object(body_data.into_iter().collect()),
-                                    Value::Object(
+                                    Arg::Json(&Value::Object(path_data.into_iter().collect())),
+                                    Arg::Json(&Value::Object(query_data.into_iter().collect())),
+                                    Arg::JsonFields(
+                                        body_data
+                                            .iter()
+                                            .map(|(name, json)| (name.as_str(), &json[..]))
+                                            .collect(),
+                                    ),
+                                    Arg::Json(&Value::Object(
                                         request_obj
                                             .into_iter()
                                             .map(|(k, v)| (k.to_owned(), v))
                                             .collect(),
-                                    ),
-                                    Value::Object(header_obj.into_iter().collect()),
+                                    )),
+                                    Arg::Json(&Value::Object(header_obj.into_iter().collect())),
+                                    Arg::Json(&ctx_obj),
                                 ],
-                            )
+                            )?;
+                            match value {
+                                ReturnValue::Stream => {
+                                    stream(&mut vm, body_sender, event_stream, &request_id);
+                                }
+                                ReturnValue::Json | ReturnValue::Response(_) => {
+                                    let fields = match value {
+                                        ReturnValue::Response(fields) => fields,
+                                        _ => BTreeMap::new(),
+                                    };
+                                    let mut head = Self::response_head(fields);
+                                    if vm.is_degraded() {
+                                        head = Self::degraded(head);
+                                    }
+                                    send_json(
+                                        &mut vm,
+                                        head,
+                                        body_sender,
+                                        Config::get().json.big_int_strings,
+                                        &request_id,
+                                    )?;
+                                    // The response is sent through the body channel
+                                    return Ok((ReturnValue::Nil, false));
+                                }
+                                _ => {}
+                            }
+                            Ok((value, vm.is_degraded()))
                         });
-                    self.state = ProcessingState::Executing(handle);
+                    let Some(handle) = handle else {
+                        return Poll::Ready(Ok(Self::overloaded()));
+                    };
+                    let timer = deadline
+                        .map(|deadline| Box::pin(tokio::time::sleep_until(deadline.into())));
+                    self.state = ProcessingState::Executing(handle, timer, Some(body));
                 }
-                ProcessingState::Executing(handle) => {
-                    return match Pin::new(handle).poll(cx) {
-                        Poll::Ready(Ok(result)) => match result {
-                            Ok(value) => {
-                                if let ReturnValue::Response(mut fields) = value {
-                                    let mut response =
-                                        Json(fields.remove("body").unwrap_or_default())
-                                            .into_response();
-                                    *response.status_mut() = StatusCode::from_u16(
-                                        fields
-                                            .remove("status_code")
-                                            .map(|v| v.as_f64().unwrap())
-                                            .unwrap_or(200f64)
-                                            as u16,
-                                    )
-                                    .unwrap();
-                                    if let Some(headers) = fields.remove("headers") {
-                                        response.headers_mut().extend(
-                                            headers.as_object().unwrap().into_iter().map(
-                                                |(name, value)| {
-                                                    (
-                                                        HeaderName::try_from(name).unwrap(),
-                                                        HeaderValue::from_str(
-                                                            value.as_str().unwrap(),
-                                                        )
-                                                        .unwrap(),
-                                                    )
-                                                },
-                                            ),
-                                        );
-                                    }
+                ProcessingState::ReadingBody(body_fut) => {
+                    let mut body = match body_fut.as_mut().poll(cx) {
+                        Poll::Pending => return Poll::Pending,
+                        Poll::Ready(Ok(value)) => value,
+                        Poll::Ready(Err(e)) => {
+                            return Poll::Ready(Ok(
+                                format!("Body parsing error: {:?}", e).into_response()
+                            ));
+                        }
+                    };
 
-                                    Poll::Ready(Ok(response))
-                                } else {
-                                    Poll::Ready(Ok(Json(value).into_response()))
-                                }
+                    let mut failed_validation = None;
+                    for field in mem::take(&mut self.endpoint.body_fields) {
+                        // Moved out of the body, not copied
+                        if let Some(json) = body.remove(&field.name) {
+                            if let Err(e) = Self::validate_json_field(&field, &json) {
+                                failed_validation = Some(e);
+                                break;
                             }
-                            Err(VmError::CompileError) => {
-                                Poll::Ready(Ok("Compile Error".into_response()))
+                            self.body_data.insert(field.name.clone(), json);
+                        } else if let Some(default) = &field.default {
+                            self.body_data
+                                .insert(field.name.clone(), Bytes::from(default.to_string()));
+                        } else if field.required {
+                            failed_validation = Some(ServerError::MissingField(field.name.clone()));
+                            break;
+                        }
+                    }
+
+                    if let Some(error) = failed_validation {
+                        return Poll::Ready(Ok(error.into_response()));
+                    }
+                    self.state = ProcessingState::ValidatingBody;
+                }
+                ProcessingState::Executing(handle, timer, body) => {
+                    if let Some(receiver) = body {
+                        match Pin::new(receiver).poll(cx) {
+                            Poll::Ready(Ok(body)) => {
+                                return Poll::Ready(Ok(body.into_response()));
                             }
-                            Err(VmError::RuntimeError(err)) => {
-                                Poll::Ready(Ok(format!("Runtime Error: {err}",).into_response()))
+                            // The handler returned without streaming
+                            Poll::Ready(Err(_)) => *body = None,
+                            Poll::Pending => {}
+                        }
+                    }
+                    if timer
+                        .as_mut()
+                        .is_some_and(|timer| timer.as_mut().poll(cx).is_ready())
+                    {
+                        // The script keeps running on the blocking thread until
+                        // its next AI, HTTP or database call fails on the deadline.
+                        eprintln!("[{}] Request timed out", self.request_id);
+                        let response = Self::deadline_exceeded();
+                        match self.spawn_fallback() {
+                            Some(handle) => {
+                                self.state = ProcessingState::Degrading(handle, Some(response));
+                                continue;
                             }
-                        },
+                            None => return Poll::Ready(Ok(response)),
+                        }
+                    }
+                    let response = match Pin::new(handle).poll(cx) {
+                        Poll::Ready(Ok(Ok((value, degraded)))) => {
+                            let response = Self::value_response(value);
+                            return Poll::Ready(Ok(if degraded {
+                                Self::degraded(response)
+                            } else {
+                                response
+                            }));
+                        }
+                        Poll::Ready(Ok(Err(VmError::CompileError))) => {
+                            return Poll::Ready(Ok(Self::error_response(VmError::CompileError)));
+                        }
+                        // The error raised by the script is its response, not a failure
+                        Poll::Ready(Ok(Err(err @ VmError::Raised(_)))) => {
+                            return Poll::Ready(Ok(Self::error_response(err)));
+                        }
+                        Poll::Ready(Ok(Err(err))) => {
+                            eprintln!("[{}] {err}", self.request_id);
+                            Self::error_response(err)
+                        }
                         Poll::Ready(Err(err)) => {
-                            Poll::Ready(Ok(format!("Error:: {err}").into_response()))
+                            eprintln!("[{}] {err}", self.request_id);
+                            format!("Error:: {err}").into_response()
                         }
+                        Poll::Pending => return Poll::Pending,
+                    };
+                    match self.spawn_fallback() {
+                        Some(handle) => {
+                            self.state = ProcessingState::Degrading(handle, Some(response));
+                        }
+                        None => return Poll::Ready(Ok(response)),
+                    }
+                }
+                ProcessingState::Degrading(handle, response) => {
+                    return match Pin::new(handle).poll(cx) {
+                        Poll::Ready(Ok(Ok(value))) => {
+                            Poll::Ready(Ok(Self::degraded(Self::value_response(value))))
+                        }
+                        Poll::Ready(_) => Poll::Ready(Ok(response.take().unwrap_or_default())),
                         Poll::Pending => Poll::Pending,
                     };
                 }
@@ -607,3 +1163,48 @@ pub(crate) fn convert_field(field: ast::Field) -> Field {
         validators: Arc::from(field.validators),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_id(id: &str) -> Request<Body> {
+        Request::builder()
+            .header(REQUEST_ID_HEADER, id)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_request_id() {
+        assert_eq!(
+            RequestProcessor::request_id(&request_with_id("abc-123")),
+            "abc-123"
+        );
+
+        // Malformed ids are replaced by a generated one
+        let id = RequestProcessor::request_id(&request_with_id("x */ DROP TABLE users"));
+        assert_eq!(id.len(), 32);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+
+        let first = RequestProcessor::request_id(&Request::new(Body::empty()));
+        let second = RequestProcessor::request_id(&Request::new(Body::empty()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_mock_response() {
+        let annotation = RouteAnnotation {
+            mock: Some(aiscript_directive::route::Mock {
+                example: serde_json::json!({"id": 1}),
+                status: 201,
+            }),
+            ..Default::default()
+        };
+        let response = RequestProcessor::mock_response(&annotation);
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = RequestProcessor::mock_response(&RouteAnnotation::default());
+        assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    }
+}
@@ -0,0 +1,197 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::Request,
+    http::{StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use hyper::body::{Frame, SizeHint};
+use serde_json::{Map, Value, json};
+
+use crate::endpoint::REQUEST_ID_HEADER;
+
+// The largest error body read to be reshaped, a larger one is answered with
+// a 500.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+/// Wrap the JSON response of an endpoint in the envelope enabled by
+/// `[responses]` or `@envelope`:
+/// `{"data": ..., "error": ..., "meta": {"request_id": ...}}`.
+/// The other responses, e.g. streams or files, are served as is.
+pub(crate) async fn wrap(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let status = response.status();
+    if !is_json || matches!(status, StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED) {
+        return response;
+    }
+
+    let meta = json!({
+        "request_id": response
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok()),
+    });
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if !status.is_client_error() && !status.is_server_error() {
+        // The data is streamed between the head and the tail of the envelope
+        let body = Enveloped {
+            head: Some(Bytes::from_static(b"{\"data\":")),
+            body,
+            tail: Some(Bytes::from(format!(",\"error\":null,\"meta\":{meta}}}"))),
+        };
+        return Response::from_parts(parts, Body::new(body));
+    }
+
+    // The error bodies are small, they are read to be reshaped
+    let Ok(bytes) = to_bytes(body, MAX_ERROR_BODY).await else {
+        parts.status = StatusCode::INTERNAL_SERVER_ERROR;
+        return Response::from_parts(parts, Body::empty());
+    };
+    let error = match serde_json::from_slice(&bytes) {
+        // The `{"error": message, ...}` of the server errors
+        Ok(Value::Object(mut fields)) if fields.contains_key("error") => {
+            let mut error = Map::new();
+            error.insert("message".into(), fields.remove("error").unwrap_or_default());
+            error.extend(fields);
+            Value::Object(error)
+        }
+        Ok(value) => value,
+        Err(_) => json!({ "message": String::from_utf8_lossy(&bytes) }),
+    };
+    let envelope = json!({ "data": null, "error": error, "meta": meta });
+    Response::from_parts(parts, Body::from(envelope.to_string()))
+}
+
+struct Enveloped {
+    head: Option<Bytes>,
+    body: Body,
+    tail: Option<Bytes>,
+}
+
+impl hyper::body::Body for Enveloped {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(head) = self.head.take() {
+            return Poll::Ready(Some(Ok(Frame::data(head))));
+        }
+        if !self.body.is_end_stream() {
+            match Pin::new(&mut self.body).poll_frame(cx) {
+                Poll::Ready(None) => {}
+                frame => return frame,
+            }
+        }
+        Poll::Ready(self.tail.take().map(|tail| Ok(Frame::data(tail))))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.head.is_none() && self.tail.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let extra = (self.head.as_ref().map_or(0, Bytes::len)
+            + self.tail.as_ref().map_or(0, Bytes::len)) as u64;
+        let hint = self.body.size_hint();
+        let mut enveloped = SizeHint::new();
+        enveloped.set_lower(hint.lower() + extra);
+        if let Some(upper) = hint.upper() {
+            enveloped.set_upper(upper + extra);
+        }
+        enveloped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, middleware, response::IntoResponse, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn call(router: Router, uri: &str) -> (StatusCode, Value) {
+        let response = router
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_envelope() {
+        let router = Router::new()
+            .route(
+                "/ok",
+                get(|| async {
+                    let mut response = Json(json!([1, 2])).into_response();
+                    response
+                        .headers_mut()
+                        .insert(REQUEST_ID_HEADER, "abc".parse().unwrap());
+                    response
+                }),
+            )
+            .route(
+                "/large",
+                get(|| async {
+                    let message = "x".repeat(MAX_ERROR_BODY);
+                    (StatusCode::BAD_REQUEST, Json(json!({"error": message})))
+                }),
+            )
+            .route(
+                "/err",
+                get(|| async {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({"error": "Missing required field: id", "field": "id"})),
+                    )
+                }),
+            )
+            .layer(middleware::from_fn(wrap));
+
+        assert_eq!(
+            call(router.clone(), "/ok").await,
+            (
+                StatusCode::OK,
+                json!({"data": [1, 2], "error": null, "meta": {"request_id": "abc"}})
+            )
+        );
+        let response = router
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/large")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(
+            call(router, "/err").await,
+            (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "data": null,
+                    "error": {"message": "Missing required field: id", "field": "id"},
+                    "meta": {"request_id": null},
+                })
+            )
+        );
+    }
+}
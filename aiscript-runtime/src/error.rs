@@ -10,6 +10,10 @@ use thiserror::Error;
 pub enum ServerError {
     #[error("Authentication error: {message}")]
     AuthenticationError { message: String },
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
     #[error("Field validation failed: {field}: {message}")]
     ValidationError { field: String, message: String },
 
@@ -25,6 +29,9 @@ pub enum ServerError {
     #[error("Failed to parse JSON body: {0}")]
     JsonParseError(#[from] rejection::JsonRejection),
 
+    #[error("Failed to parse JSON body: {0}")]
+    JsonBodyError(#[from] serde_json::Error),
+
     #[error("Failed to parse Form body: {0}")]
     FormParseError(#[from] rejection::FormRejection),
 
@@ -41,7 +48,11 @@ impl IntoResponse for ServerError {
             "error": self.to_string()
         });
 
-        // Return as a JSON response with BAD_REQUEST status
-        (axum::http::StatusCode::BAD_REQUEST, Json(error_json)).into_response()
+        let status = match self {
+            ServerError::Forbidden(_) => axum::http::StatusCode::FORBIDDEN,
+            ServerError::TooManyRequests(_) => axum::http::StatusCode::TOO_MANY_REQUESTS,
+            _ => axum::http::StatusCode::BAD_REQUEST,
+        };
+        (status, Json(error_json)).into_response()
     }
 }
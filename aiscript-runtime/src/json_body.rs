@@ -0,0 +1,310 @@
+use std::{
+    collections::HashMap,
+    fmt, io, mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use aiscript_vm::{Vm, VmError};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, Request, rejection::MissingJsonContentType},
+    http::{HeaderMap, header},
+    response::Response,
+};
+use hyper::body::Frame;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde_json::{Value, value::RawValue};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::error::ServerError;
+
+/// The JSON of a value sent to the client, with the integers beyond 2^53 as
+/// strings if `big_int_strings`, see `[json]` in project.toml.
+pub(crate) fn response_json(mut value: Value, big_int_strings: bool) -> Value {
+    if big_int_strings {
+        aiscript_vm::big_ints_to_strings(&mut value);
+    }
+    value
+}
+
+/// The JSON text of the fields of a body, parsed into VM values by the
+/// handler.
+pub(crate) type JsonFields = HashMap<String, Bytes>;
+
+/// Read the fields of the JSON body, each one as its JSON text sliced from
+/// the body without copying it. The other fields are skipped while parsing,
+/// none of the values are built.
+pub(crate) async fn read_json_fields(
+    request: Request,
+    fields: Vec<String>,
+) -> Result<JsonFields, ServerError> {
+    if !json_content_type(request.headers()) {
+        return Err(ServerError::JsonParseError(
+            MissingJsonContentType::default().into(),
+        ));
+    }
+    let bytes = Bytes::from_request(request, &())
+        .await
+        .map_err(|err| ServerError::JsonParseError(err.into()))?;
+    let mut deserializer = serde_json::Deserializer::from_slice(&bytes);
+    let body = DeclaredFields(&fields).deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(body
+        .into_iter()
+        .map(|(name, json)| (name, bytes.slice_ref(json.get().as_bytes())))
+        .collect())
+}
+
+// Like the `Json` extractor, `application/json` or a `+json` type.
+fn json_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json"
+                || mime.starts_with("application/") && mime.ends_with("+json")
+        })
+}
+
+// The JSON text of the fields with these names, borrowed from the body.
+struct DeclaredFields<'a>(&'a [String]);
+
+impl<'de> DeserializeSeed<'de> for DeclaredFields<'_> {
+    type Value = HashMap<String, &'de RawValue>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DeclaredFields<'_> {
+    type Value = HashMap<String, &'de RawValue>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut fields = HashMap::new();
+        // Borrowed from the body unless escaped
+        while let Some(key) = map.next_key::<std::borrow::Cow<'de, str>>()? {
+            if self.0.iter().any(|name| *name == key) {
+                fields.insert(key.into_owned(), map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(fields)
+    }
+}
+
+// The size of the chunks of a written response.
+const CHUNK_SIZE: usize = 64 * 1024;
+// The chunks written ahead of a slow client, the script waits once it's full.
+const BUFFERED_CHUNKS: usize = 4;
+
+/// Write the JSON returned by the handler as the body of the response,
+/// straight from the VM values in chunks as the client reads them, instead
+/// of into a `serde_json::Value` and a string of the whole response. A body
+/// fitting in one chunk is sent at once with its length.
+///
+/// The response is handed to the request processor once its first chunk is
+/// written, a failure before that is returned to be the response instead.
+/// A failure after it aborts the connection.
+pub(crate) fn send_json(
+    vm: &mut Vm,
+    head: Response,
+    sender: oneshot::Sender<Response>,
+    big_int_strings: bool,
+    request_id: &str,
+) -> Result<(), VmError> {
+    let mut writer = ChunkWriter {
+        buf: Vec::with_capacity(CHUNK_SIZE),
+        head: Some((head, sender)),
+        chunks: None,
+    };
+    let result = vm.write_json(&mut writer, big_int_strings);
+    if let Some((mut head, sender)) = writer.head.take() {
+        result?;
+        *head.body_mut() = Body::from(writer.buf);
+        // Unless the request has already been answered, e.g. on timeout
+        let _ = sender.send(head);
+        return Ok(());
+    }
+    let Some(chunks) = writer.chunks.filter(|chunks| !chunks.is_closed()) else {
+        // The client has left
+        return Ok(());
+    };
+    let chunk = match result {
+        Ok(()) if writer.buf.is_empty() => return Ok(()),
+        Ok(()) => Ok(Bytes::from(writer.buf)),
+        Err(err) => {
+            eprintln!("[{request_id}] {err}");
+            Err(err)
+        }
+    };
+    let _ = chunks.blocking_send(chunk);
+    Ok(())
+}
+
+// Buffers the JSON into chunks, the response is sent with the first one.
+struct ChunkWriter {
+    buf: Vec<u8>,
+    head: Option<(Response, oneshot::Sender<Response>)>,
+    chunks: Option<mpsc::Sender<Result<Bytes, VmError>>>,
+}
+
+impl ChunkWriter {
+    fn send_chunk(&mut self) -> io::Result<()> {
+        let left = || io::Error::new(io::ErrorKind::BrokenPipe, "the client has left");
+        let chunk = Bytes::from(mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE)));
+        if let Some((mut head, sender)) = self.head.take() {
+            let (chunks, receiver) = mpsc::channel(BUFFERED_CHUNKS);
+            *head.body_mut() = Body::new(JsonChunks(receiver));
+            sender.send(head).map_err(|_| left())?;
+            self.chunks = Some(chunks);
+        }
+        match &self.chunks {
+            Some(chunks) => chunks.blocking_send(Ok(chunk)).map_err(|_| left()),
+            None => Err(left()),
+        }
+    }
+}
+
+impl io::Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The body of the chunks after the first one.
+struct JsonChunks(mpsc::Receiver<Result<Bytes, VmError>>);
+
+impl hyper::body::Body for JsonChunks {
+    type Data = Bytes;
+    type Error = VmError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.0
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use hyper::body::Body as _;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_json_fields() {
+        let request = |content_type: &str, body: &'static str| {
+            Request::builder()
+                .header(header::CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let fields = vec!["name".to_owned(), "tags".to_owned()];
+        let body = r#"{"name": "a\"b", "skipped": {"deep": [1, {"x": 2}]}, "tags": ["x"]}"#;
+        let parsed = read_json_fields(request("application/json", body), fields.clone())
+            .await
+            .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed["name"], r#""a\"b""#);
+        assert_eq!(parsed["tags"], r#"["x"]"#);
+        assert!(
+            read_json_fields(request("application/vnd.api+json", "{}"), fields.clone())
+                .await
+                .is_ok()
+        );
+
+        // The skipped fields are still checked
+        let err = read_json_fields(request("application/json", r#"{"x": [}"#), fields.clone())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServerError::JsonBodyError(_)));
+        let err = read_json_fields(request("application/json", "[1]"), fields.clone())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("expected a JSON object"));
+        let err = read_json_fields(request("text/json", "{}"), fields)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ServerError::JsonParseError(_)));
+    }
+
+    // The response of the handler of the source, written on a thread like
+    // the one of the VM.
+    async fn handler_response(source: &'static str) -> Response {
+        let (sender, receiver) = oneshot::channel();
+        std::thread::spawn(move || {
+            let mut vm = Vm::default();
+            vm.compile(source).unwrap();
+            vm.interpret().unwrap();
+            let handler = vm.function_id("handler").unwrap();
+            assert_eq!(
+                vm.eval_handler(handler, &[]).unwrap(),
+                aiscript_vm::ReturnValue::Json
+            );
+            send_json(&mut vm, Response::default(), sender, true, "test").unwrap();
+        });
+        receiver.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_json() {
+        let response = handler_response(
+            r#"
+            fn handler() {
+                return {b: [1, 2.5, {c: nil}], a: "x\"y", d: [], e: {}, id: int("9007199254740993")};
+            }
+            "#,
+        )
+        .await;
+        let expected =
+            r#"{"a":"x\"y","b":[1.0,2.5,{"c":null}],"d":[],"e":{},"id":"9007199254740993"}"#;
+        // Sent with its length
+        assert_eq!(
+            response.body().size_hint().exact(),
+            Some(expected.len() as u64)
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, expected);
+
+        // Streamed in chunks
+        let response = handler_response(
+            r#"
+            fn handler() {
+                let items = [];
+                for let i = 0; i < 100000; i += 1 {
+                    items.append({id: int(i)});
+                }
+                return items;
+            }
+            "#,
+        )
+        .await;
+        assert_eq!(response.body().size_hint().exact(), None);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let expected = Value::Array((0..100_000).map(|i| json!({"id": i})).collect());
+        assert_eq!(body, expected.to_string());
+    }
+}
@@ -1,38 +1,89 @@
 use ast::HttpMethod;
 use axum::Json;
 use axum::response::IntoResponse;
+use axum::serve::ListenerExt;
 use axum::{response::Html, routing::*};
 use hyper::StatusCode;
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::sqlite::SqlitePoolOptions;
 use sqlx::{PgPool, SqlitePool};
+use std::collections::HashSet;
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use std::{fs, net::SocketAddr, path::PathBuf};
-use tokio::net::TcpListener;
-use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use walkdir::WalkDir;
 
+use crate::concurrency::ConcurrencyLimiter;
 use crate::endpoint::{Endpoint, convert_field};
 pub use config::Config;
+pub use contract::run_contract_tests;
+pub use loadtest::{LoadTest, run_load_test};
+pub use preflight::run_preflight;
+pub use workspace::{Member, WORKSPACE_FILE, Workspace};
+mod agents;
 mod ast;
+mod batch;
+mod brownout;
+mod client_ip;
+mod concurrency;
 mod config;
+mod conflict;
+mod contract;
+mod early_hints;
 mod endpoint;
+mod envelope;
 mod error;
+mod json_body;
+mod listener;
+mod loadtest;
+mod maintenance;
+mod metrics;
 mod openapi;
 mod parser;
+mod preflight;
+mod schedule;
+mod server;
+mod stream;
 mod utils;
+mod workers;
+mod workspace;
 
 use aiscript_lexer as lexer;
+use aiscript_vm::CompiledProgram;
 
 #[derive(Debug, Clone)]
 struct ReloadSignal;
 
-fn read_routes() -> Vec<ast::Route> {
+fn read_routes(roots: &[config::RouteRoot]) -> Vec<(PathBuf, ast::Route)> {
     let mut routes = Vec::new();
-    for entry in WalkDir::new("routes")
+    for root in roots {
+        let defaults = root.annotation();
+        for (file_path, mut route) in read_route_dir(&root.dir) {
+            route.prefix = root.mount_prefix(&route.prefix);
+            route.annotation = route.annotation.or(&defaults);
+            routes.push((file_path, route));
+        }
+    }
+    routes
+}
+
+fn read_route_dir(dir: &Path) -> Vec<(PathBuf, ast::Route)> {
+    let mut routes = Vec::new();
+    for file_path in route_files(dir) {
+        if let Some(route) = read_single_route(&file_path) {
+            routes.push((file_path, route));
+        }
+    }
+    routes
+}
+
+// The route files of the directory.
+fn route_files(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    WalkDir::new(dir)
         .contents_first(true)
         .into_iter()
         .filter_entry(|e| {
@@ -43,13 +94,7 @@ fn read_routes() -> Vec<ast::Route> {
                     .unwrap_or(false)
         })
         .filter_map(|e| e.ok())
-    {
-        let file_path = entry.path();
-        if let Some(route) = read_single_route(file_path) {
-            routes.push(route);
-        }
-    }
-    routes
+        .map(|entry| entry.into_path())
 }
 
 fn read_single_route(file_path: &Path) -> Option<ast::Route> {
@@ -64,51 +109,194 @@ fn read_single_route(file_path: &Path) -> Option<ast::Route> {
     None
 }
 
-pub async fn run(path: Option<PathBuf>, port: u16, reload: bool) {
-    if !reload {
-        // Run without reload functionality
-        run_server(path, port, None).await;
+/// Serve the routes, the handlers are replaced by their `@mock` examples
+/// with `mock`.
+pub async fn run(path: Option<PathBuf>, port: u16, reload: bool, mock: bool, chaos: bool) {
+    maintenance::init(&Config::get().maintenance);
+    if chaos {
+        install_chaos();
+    }
+
+    // The watcher stops watching when it's dropped
+    let (mut watcher, mut changes) = if reload {
+        let (watcher, changes) = watch_changes();
+        (Some(watcher), Some(changes))
+    } else {
+        (None, None)
+    };
+
+    let app = loop {
+        if let Some(app) = build_app(path.as_deref(), mock).await {
+            if let Some(watcher) = &mut watcher {
+                watcher.watch_files(&app.files);
+            }
+            break app;
+        }
+        // Without reload there is nothing to serve, otherwise wait for a fix
+        let Some(changes) = &mut changes else {
+            return;
+        };
+        if !wait_for_changes(changes).await {
+            return;
+        }
+    };
+    let router = server::SharedRouter::new(app.router);
+    let mut scheduled_jobs = app.scheduled_jobs;
+    let server_handle = tokio::spawn(serve(port, router.clone()));
+
+    let Some(mut changes) = changes else {
+        let _ = server_handle.await;
         return;
+    };
+    // Blue/green reload: the new router is built while the current one keeps
+    // serving, then swapped in without dropping any connection.
+    while wait_for_changes(&mut changes).await {
+        println!("📑 Routes, prompts or config changed, reloading server...");
+        if let Err(e) = Config::reload() {
+            eprintln!(
+                "Error loading config file: {}, keeping the current config",
+                e
+            );
+        }
+        if chaos {
+            install_chaos();
+        }
+        match build_app(path.as_deref(), mock).await {
+            Some(app) => {
+                if let Some(watcher) = &mut watcher {
+                    watcher.watch_files(&app.files);
+                }
+                router.swap(app.router);
+                // The jobs of the previous routes stop when their set is dropped
+                scheduled_jobs = app.scheduled_jobs;
+                println!("✅ Server reloaded");
+            }
+            None => eprintln!("Reload failed, still serving the previous routes"),
+        }
+    }
+    drop(scheduled_jobs);
+}
+
+// Inject the faults of `[chaos]` into the dependencies, for development only
+// so they are never injected without `serve --chaos`.
+fn install_chaos() {
+    let config = Config::get().chaos.clone().unwrap_or_default();
+    if config.faults.is_empty() {
+        eprintln!("Warning: no [chaos] faults in project.toml, nothing is injected");
+    }
+    for (dependency, fault) in &config.faults {
+        println!(
+            "💥 Injecting faults into {dependency}: {}ms latency, {}% errors",
+            fault.latency_ms,
+            fault.error_rate * 100.0
+        );
+    }
+    config.install();
+}
+
+// The watcher of the changes, with the files read by the compiler.
+struct ChangeWatcher {
+    watcher: RecommendedWatcher,
+    // The files read by the last app, e.g. the instructions loaded with
+    // file("path"), with the directories watched for them.
+    files: Arc<Mutex<HashSet<PathBuf>>>,
+    file_dirs: HashSet<PathBuf>,
+}
+
+impl ChangeWatcher {
+    // Watch the files read when compiling the app in place of the previous ones.
+    fn watch_files(&mut self, files: &[PathBuf]) {
+        let files = files
+            .iter()
+            .filter_map(|file| fs::canonicalize(file).ok())
+            .collect::<HashSet<_>>();
+        // The directory is watched as editors replace the file when saving it
+        let dirs = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect::<HashSet<_>>();
+        for dir in self.file_dirs.difference(&dirs) {
+            let _ = self.watcher.unwatch(dir);
+        }
+        for dir in dirs.difference(&self.file_dirs) {
+            if let Err(e) = self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                eprintln!("Failed to watch {}: {e}", dir.display());
+            }
+        }
+        self.file_dirs = dirs;
+        *self.files.lock().unwrap() = files;
     }
+}
 
-    // Create a channel for reload coordination
-    let (tx, _) = broadcast::channel::<ReloadSignal>(1);
-    let tx = Arc::new(tx);
+// Watch the route directories, the schedules, the agents, the config file
+// and the files read by the compiler, a change is signaled for each
+// modified file.
+fn watch_changes() -> (ChangeWatcher, mpsc::UnboundedReceiver<ReloadSignal>) {
+    let (tx, rx) = mpsc::unbounded_channel();
 
     // Set up file watcher
-    let watcher_tx = tx.clone();
+    let files = Arc::new(Mutex::new(HashSet::new()));
+    let watched_files = files.clone();
     let mut watcher = setup_watcher(move |event| {
-        // Only trigger reload for .ai files
-        if let Some(path) = event.paths.first().and_then(|p| p.to_str()) {
-            if path.ends_with(".ai") {
-                watcher_tx.send(ReloadSignal).unwrap();
+        // Only trigger reload for .ai files, the read files and the config file
+        if let Some(path) = event.paths.first() {
+            let is_script = path.to_str().is_some_and(|p| p.ends_with(".ai"));
+            let is_read = watched_files.lock().unwrap().contains(path);
+            let is_config = path
+                .file_name()
+                .is_some_and(|name| name == config::CONFIG_FILE);
+            if is_script || is_read || is_config {
+                let _ = tx.send(ReloadSignal);
             }
         }
     })
     .expect("Failed to setup watcher");
 
-    // Watch the routes directory
-    watcher
-        .watch(Path::new("routes"), RecursiveMode::Recursive)
-        .expect("Failed to watch routes directory");
-
-    loop {
-        let mut rx = tx.subscribe();
-        let server_handle = tokio::spawn(run_server(path.clone(), port, Some(rx.resubscribe())));
-
-        // Wait for reload signal
-        match rx.recv().await {
-            Ok(_) => {
-                println!("📑 Routes changed, reloading server...");
-                // Give some time for pending requests to complete
-                tokio::time::sleep(Duration::from_millis(100)).await;
-                server_handle.abort();
-            }
-            Err(_) => {
-                break;
-            }
-        }
+    // Watch the route directories
+    for root in Config::get().route_roots().iter() {
+        watcher
+            .watch(&root.dir, RecursiveMode::Recursive)
+            .unwrap_or_else(|_| panic!("Failed to watch {} directory", root.dir.display()));
+    }
+
+    let schedules_dir = Path::new(schedule::SCHEDULES_DIR);
+    if schedules_dir.is_dir() {
+        watcher
+            .watch(schedules_dir, RecursiveMode::Recursive)
+            .expect("Failed to watch schedules directory");
+    }
+
+    let agents_dir = Path::new(agents::AGENTS_DIR);
+    if agents_dir.is_dir() {
+        watcher
+            .watch(agents_dir, RecursiveMode::Recursive)
+            .expect("Failed to watch agents directory");
+    }
+
+    // The directory is watched as editors replace the file when saving it
+    if Path::new(config::CONFIG_FILE).is_file() {
+        watcher
+            .watch(Path::new("."), RecursiveMode::NonRecursive)
+            .expect("Failed to watch the config file");
     }
+
+    let watcher = ChangeWatcher {
+        watcher,
+        files,
+        file_dirs: HashSet::new(),
+    };
+    (watcher, rx)
+}
+
+// Wait for the next change, the burst of events of a single save is
+// coalesced. Returns false once the watcher is gone.
+async fn wait_for_changes(changes: &mut mpsc::UnboundedReceiver<ReloadSignal>) -> bool {
+    if changes.recv().await.is_none() {
+        return false;
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    while changes.try_recv().is_ok() {}
+    true
 }
 
 fn setup_watcher<F>(mut callback: F) -> notify::Result<RecommendedWatcher>
@@ -165,26 +353,119 @@ pub async fn get_redis_connection() -> Option<redis::aio::MultiplexedConnection>
     }
 }
 
-async fn run_server(
-    path: Option<PathBuf>,
-    port: u16,
-    reload_rx: Option<broadcast::Receiver<ReloadSignal>>,
-) {
+// The router of the routes and the config, with the jobs it scheduled.
+struct App {
+    router: Router,
+    // The jobs are stopped when the app is replaced and the set is dropped.
+    scheduled_jobs: Option<JoinSet<()>>,
+    // The files read when compiling the endpoints, watched in reload mode.
+    files: Vec<PathBuf>,
+}
+
+// Compile the handler of the endpoint, the error is reported with its route.
+fn compile_endpoint(
+    config: &Config,
+    route_file: &Path,
+    prefix: &str,
+    endpoint_spec: &ast::Endpoint,
+) -> Option<CompiledProgram> {
+    let statements = parser::declare_error_types(
+        &endpoint_spec.statements,
+        config.errors.iter().map(|(name, _)| name),
+    );
+    match CompiledProgram::new(statements) {
+        Ok(program) if endpoint_spec.line > 0 => {
+            Some(program.with_origin(route_file, endpoint_spec.line))
+        }
+        Ok(program) => Some(program),
+        Err(err) => {
+            let spec = &endpoint_spec.path_specs[0];
+            eprintln!(
+                "Error: failed to compile {} {}{}: {err}",
+                spec.method.as_str(),
+                prefix.trim_end_matches('/'),
+                spec.path
+            );
+            None
+        }
+    }
+}
+
+/// Parse and compile the routes and the agents of the project without
+/// serving them, returns whether they all compile.
+pub fn check_routes() -> bool {
     let config = Config::get();
+    let roots = config.route_roots();
+    let mut routes = read_routes(&roots);
+    let files: usize = roots
+        .iter()
+        .map(|root| route_files(&root.dir).count())
+        .sum();
+    // The files which don't parse are reported by `read_routes`
+    let mut errors = files - routes.len();
+    routes.extend(agents::read_routes());
+
+    for conflict in conflict::find_conflicts(&routes) {
+        eprintln!("Error: {conflict}");
+        errors += 1;
+    }
+    let mut endpoints = 0;
+    for (route_file, route) in &routes {
+        for endpoint_spec in &route.endpoints {
+            endpoints += 1;
+            if compile_endpoint(&config, route_file, &route.prefix, endpoint_spec).is_none() {
+                errors += 1;
+            }
+        }
+    }
+    if errors > 0 {
+        eprintln!("{errors} error(s) in the routes");
+        return false;
+    }
+    println!(
+        "{endpoints} endpoint(s) of {} route file(s) compiled",
+        routes.len()
+    );
+    true
+}
 
-    let routes = if let Some(file_path) = path {
-        read_single_route(&file_path).into_iter().collect()
+// Compile the routes into a router, None if they can't be served.
+async fn build_app(path: Option<&Path>, mock: bool) -> Option<App> {
+    let config = Config::get();
+    aiscript_vm::set_experimental(config.language.experimental);
+    aiscript_vm::set_kv_path(config.kv.path.clone());
+    aiscript_vm::set_template_dir(config.template.dir.clone());
+
+    let routes: Vec<_> = if let Some(file_path) = path {
+        read_single_route(file_path)
+            .map(|route| (file_path.to_owned(), route))
+            .into_iter()
+            .collect()
     } else {
-        read_routes()
+        let mut routes = read_routes(&config.route_roots());
+        // The chat endpoints of the agents declared with `@expose`
+        routes.extend(agents::read_routes());
+        routes
     };
 
-    if routes.is_empty() {
+    let conflicts = conflict::find_conflicts(&routes);
+    if !conflicts.is_empty() {
+        for conflict in conflicts {
+            eprintln!("Error: {conflict}");
+        }
+        return None;
+    }
+    let (route_files, routes): (Vec<_>, Vec<_>) = routes.into_iter().unzip();
+
+    let jobs = schedule::read_jobs();
+
+    if routes.is_empty() && jobs.is_empty() {
         eprintln!("Warning: No valid routes found!");
-        return;
+        return None;
     }
 
     let mut router = Router::new();
-    let openapi = openapi::OpenAPIGenerator::generate(&routes);
+    let openapi = openapi::OpenAPIGenerator::generate(&routes, &config.errors);
     router = router.route("/openapi.json", get(move || async { Json(openapi) }));
 
     if config.apidoc.enabled {
@@ -204,14 +485,53 @@ async fn run_server(
         }
     }
 
+    if let Some(maintenance_router) = maintenance::maintenance_router(&config.maintenance) {
+        router = router.merge(maintenance_router);
+    }
+
+    if let Some(circuit_breaker) = &config.circuit_breaker {
+        circuit_breaker.clone().install();
+    }
+    if let Some(workers) = &config.workers {
+        workers::install(workers);
+    }
+    if config.circuit_breaker.is_some() || config.workers.is_some() {
+        router = router.merge(metrics::metrics_router());
+    }
+
     let pg_connection = get_pg_connection().await;
     let sqlite_connection = get_sqlite_connection().await;
     let redis_connection = get_redis_connection().await;
-    for route in routes {
+
+    let mut scheduled_jobs = None;
+    if !jobs.is_empty() {
+        match schedule::RunStore::new(pg_connection.clone(), sqlite_connection.clone()).await {
+            Ok(store) => {
+                router = router.merge(schedule::runs_router(store.clone()));
+                scheduled_jobs = Some(schedule::start(
+                    jobs,
+                    store,
+                    pg_connection.clone(),
+                    sqlite_connection.clone(),
+                    redis_connection.clone(),
+                ));
+            }
+            Err(err) => eprintln!("Failed to create the schedule runs table: {err}"),
+        }
+    }
+    let mut files = Vec::new();
+    for (route_file, route) in route_files.into_iter().zip(routes) {
         let mut r = Router::new();
         for endpoint_spec in route.endpoints {
+            let program = compile_endpoint(&config, &route_file, &route.prefix, &endpoint_spec)?;
+            files.extend_from_slice(program.files());
+            let annotation = endpoint_spec.annotation.or(&route.annotation);
+            let envelope = annotation.envelope.unwrap_or(config.responses.envelope);
+            let concurrency = annotation
+                .concurrency
+                .map(|concurrency| Arc::new(ConcurrencyLimiter::new(concurrency)));
             let endpoint = Endpoint {
-                annotation: endpoint_spec.annotation.or(&route.annotation),
+                annotation,
                 path_params: endpoint_spec.path.into_iter().map(convert_field).collect(),
                 query_params: endpoint_spec.query.into_iter().map(convert_field).collect(),
                 body_type: endpoint_spec.body.kind,
@@ -221,13 +541,33 @@ async fn run_server(
                     .into_iter()
                     .map(convert_field)
                     .collect(),
-                script: endpoint_spec.statements,
+                program,
                 path_specs: endpoint_spec.path_specs,
                 pg_connection: pg_connection.as_ref().cloned(),
                 sqlite_connection: sqlite_connection.as_ref().cloned(),
                 redis_connection: redis_connection.as_ref().cloned(),
+                concurrency,
+                mock,
             };
 
+            // The field names are interned by the VM of every request
+            aiscript_vm::share_names(
+                [
+                    &endpoint.path_params,
+                    &endpoint.query_params,
+                    &endpoint.body_fields,
+                ]
+                .into_iter()
+                .flatten()
+                .map(|field| field.name()),
+            );
+            let with_envelope = |service: MethodRouter| {
+                if envelope {
+                    service.layer(axum::middleware::from_fn(envelope::wrap))
+                } else {
+                    service
+                }
+            };
             for path_spec in &endpoint.path_specs[..endpoint.path_specs.len() - 1] {
                 let service_fn = match path_spec.method {
                     HttpMethod::Get => get_service,
@@ -235,7 +575,7 @@ async fn run_server(
                     HttpMethod::Put => put_service,
                     HttpMethod::Delete => delete_service,
                 };
-                r = r.route(&path_spec.path, service_fn(endpoint.clone()));
+                r = r.route(&path_spec.path, with_envelope(service_fn(endpoint.clone())));
             }
 
             // avoid clone the last one
@@ -246,7 +586,10 @@ async fn run_server(
                 HttpMethod::Put => put_service,
                 HttpMethod::Delete => delete_service,
             };
-            r = r.route(&last_path_specs.path.clone(), service_fn(endpoint));
+            r = r.route(
+                &last_path_specs.path.clone(),
+                with_envelope(service_fn(endpoint)),
+            );
         }
 
         if route.prefix == "/" {
@@ -268,39 +611,43 @@ async fn run_server(
 
     // Add the fallback handler to the router
     router = router.fallback(handle_404);
+    if let Some(batch) = &config.batch {
+        // The requests of a batch are run by the router without the batch
+        let batch_router = batch::batch_router(batch, router.clone());
+        router = router.merge(batch_router);
+    }
+    router = router.layer(axum::middleware::from_fn(maintenance::guard));
+
+    Some(App {
+        router,
+        scheduled_jobs,
+        files,
+    })
+}
 
+// Bind the listener and serve the shared router until the process exits.
+async fn serve(port: u16, router: server::SharedRouter) {
+    let config = Config::get();
     let addr = format!("{}:{}", config.network.host, port)
         .parse::<SocketAddr>()
         .unwrap();
-    println!("Server listening on http://{}", addr);
-
-    let listener = TcpListener::bind(addr).await.unwrap();
-
-    match reload_rx {
-        Some(mut rx) => {
-            // Create a shutdown signal for reload case
-            let (close_tx, close_rx) = tokio::sync::oneshot::channel();
-
-            // Handle reload messages
-            let shutdown_task = tokio::spawn(async move {
-                if rx.recv().await.is_ok() {
-                    close_tx.send(()).unwrap();
+    let listener = listener::bind(&config.server, addr).await.unwrap();
+    println!("Server listening on {}", listener);
+
+    let shutdown = std::future::pending();
+    match listener {
+        listener::ServerListener::Tcp(listener) => {
+            let nodelay = config.server.tcp_nodelay;
+            let listener = listener.tap_io(move |tcp| {
+                if nodelay && let Err(e) = tcp.set_nodelay(true) {
+                    eprintln!("Failed to set TCP_NODELAY: {e}");
                 }
             });
-
-            // Run the server with graceful shutdown
-            axum::serve(listener, router)
-                .with_graceful_shutdown(async {
-                    let _ = close_rx.await;
-                })
-                .await
-                .unwrap();
-
-            shutdown_task.abort();
+            server::serve(listener, router, &config.server, shutdown).await
         }
-        None => {
-            // Run without reload capability
-            axum::serve(listener, router).await.unwrap();
+        #[cfg(unix)]
+        listener::ServerListener::Unix(listener) => {
+            server::serve(listener, router, &config.server, shutdown).await
         }
     }
 }
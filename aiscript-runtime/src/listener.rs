@@ -0,0 +1,135 @@
+use std::{fmt, io, net::SocketAddr};
+
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::config::ServerConfig;
+
+// The first file descriptor passed by systemd socket activation.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+pub(crate) enum ServerListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl fmt::Display for ServerListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerListener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => write!(f, "http://{addr}"),
+                Err(_) => write!(f, "tcp socket"),
+            },
+            #[cfg(unix)]
+            ServerListener::Unix(listener) => match listener
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|path| path.to_owned()))
+            {
+                Some(path) => write!(f, "unix:{}", path.display()),
+                None => write!(f, "unix socket"),
+            },
+        }
+    }
+}
+
+/// Bind the listener of the server, the socket inherited from systemd comes first,
+/// then the unix socket path, then the TCP address.
+pub(crate) async fn bind(config: &ServerConfig, addr: SocketAddr) -> io::Result<ServerListener> {
+    #[cfg(unix)]
+    {
+        if config.systemd {
+            match systemd_listener()? {
+                Some(listener) => return Ok(listener),
+                None => eprintln!(
+                    "Warning: No socket passed by systemd, falling back to the configured one"
+                ),
+            }
+        }
+        if let Some(path) = &config.unix_socket {
+            // Remove the socket file left by a previous run, binding fails otherwise.
+            // Any other file is kept, binding reports it's in use.
+            use std::os::unix::fs::FileTypeExt;
+            if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+                std::fs::remove_file(path)?;
+            }
+            return UnixListener::bind(path).map(ServerListener::Unix);
+        }
+    }
+    #[cfg(not(unix))]
+    if config.systemd || config.unix_socket.is_some() {
+        eprintln!("Warning: Unix sockets are not supported on this platform");
+    }
+    TcpListener::bind(addr).await.map(ServerListener::Tcp)
+}
+
+// The listener passed by systemd socket activation, see sd_listen_fds(3).
+// The descriptor is duplicated so it survives the server being reloaded.
+#[cfg(unix)]
+fn systemd_listener() -> io::Result<Option<ServerListener>> {
+    use std::os::fd::{BorrowedFd, OwnedFd};
+
+    let for_us = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+    let fds = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|fds| fds.parse::<u32>().ok())
+        .unwrap_or(0);
+    if !for_us || fds == 0 {
+        return Ok(None);
+    }
+
+    // SAFETY: systemd keeps the descriptor open for the lifetime of the process.
+    let fd: OwnedFd =
+        unsafe { BorrowedFd::borrow_raw(SD_LISTEN_FDS_START) }.try_clone_to_owned()?;
+    let listener = std::net::TcpListener::from(fd);
+    if listener.local_addr().is_ok() {
+        listener.set_nonblocking(true)?;
+        return TcpListener::from_std(listener).map(|l| Some(ServerListener::Tcp(l)));
+    }
+    // Not an inet socket, it must be a unix one
+    let listener = std::os::unix::net::UnixListener::from(OwnedFd::from(listener));
+    listener.set_nonblocking(true)?;
+    UnixListener::from_std(listener).map(|l| Some(ServerListener::Unix(l)))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn socket_config(name: &str) -> ServerConfig {
+        let path = std::env::temp_dir().join(format!("aiscript_listener_{name}"));
+        let _ = std::fs::remove_file(&path);
+        ServerConfig {
+            unix_socket: Some(path),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bind_stale_socket() {
+        let config = socket_config("stale.sock");
+        let path = config.unix_socket.as_ref().unwrap();
+        // The socket file stays after the listener is dropped
+        drop(std::os::unix::net::UnixListener::bind(path).unwrap());
+        assert!(path.exists());
+        let listener = bind(&config, "127.0.0.1:0".parse().unwrap()).await;
+        assert!(matches!(listener, Ok(ServerListener::Unix(_))));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_regular_file() {
+        let config = socket_config("regular.sock");
+        let path = config.unix_socket.as_ref().unwrap();
+        std::fs::write(path, "data").unwrap();
+        assert!(bind(&config, "127.0.0.1:0".parse().unwrap()).await.is_err());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "data");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,171 @@
+//! Load tests sending requests at a constant rate to the routes served in
+//! process or to a remote server, the latencies and the statuses of the
+//! responses are measured by the request metrics.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Method, Request, header},
+};
+use tokio::{task::JoinSet, time};
+use tower::ServiceExt;
+
+use crate::metrics::RequestMetrics;
+
+/// The requests of a load test.
+pub struct LoadTest {
+    /// The URL of a remote server, or the path of a route served in process.
+    pub target: String,
+    pub method: Method,
+    /// The JSON body of the requests.
+    pub body: Option<String>,
+    /// The requests sent per second.
+    pub rps: u32,
+    pub duration: Duration,
+}
+
+#[derive(Clone)]
+enum Target {
+    Router(Router),
+    Remote(reqwest::Client),
+}
+
+/// Run the load test and print its report, returns false if the target
+/// can't be load tested, e.g. the routes fail to compile.
+pub async fn run_load_test(test: LoadTest) -> bool {
+    if test.rps == 0 {
+        eprintln!("Error: the request rate must be positive.");
+        return false;
+    }
+    let remote = test.target.starts_with("http://") || test.target.starts_with("https://");
+    let target = if remote {
+        Target::Remote(reqwest::Client::new())
+    } else {
+        match crate::build_app(None, false).await {
+            Some(app) => Target::Router(app.router),
+            None => return false,
+        }
+    };
+
+    println!(
+        "Sending {} {} at {} req/s for {:.1}s",
+        test.method,
+        test.target,
+        test.rps,
+        test.duration.as_secs_f64()
+    );
+    let metrics = Arc::new(Mutex::new(RequestMetrics::default()));
+    let test = Arc::new(test);
+    let mut requests = JoinSet::new();
+    // The requests are sent at a constant rate whatever the latencies,
+    // a slow server doesn't slow the load down.
+    let mut ticks = time::interval(Duration::from_secs_f64(1.0 / test.rps as f64));
+    let started = Instant::now();
+    while started.elapsed() < test.duration {
+        ticks.tick().await;
+        while requests.try_join_next().is_some() {}
+        let (target, test, metrics) = (target.clone(), test.clone(), metrics.clone());
+        requests.spawn(async move {
+            let start = Instant::now();
+            let status = send(&target, &test).await;
+            metrics.lock().unwrap().record(start.elapsed(), status);
+        });
+    }
+    requests.join_all().await;
+    let elapsed = started.elapsed();
+
+    let mut metrics = metrics.lock().unwrap();
+    print!("{}", report(&mut metrics, elapsed));
+    true
+}
+
+// Send a request of the test, the status of its response if any.
+async fn send(target: &Target, test: &LoadTest) -> Option<u16> {
+    match target {
+        Target::Router(router) => {
+            let mut builder = Request::builder()
+                .method(test.method.clone())
+                .uri(&test.target);
+            if test.body.is_some() {
+                builder = builder.header(header::CONTENT_TYPE, "application/json");
+            }
+            let request = builder
+                .body(test.body.clone().map_or_else(Body::empty, Body::from))
+                .ok()?;
+            let response = router.clone().oneshot(request).await.ok()?;
+            let status = response.status().as_u16();
+            // The latency includes the streamed bodies
+            to_bytes(response.into_body(), usize::MAX).await.ok()?;
+            Some(status)
+        }
+        Target::Remote(client) => {
+            let mut request = client.request(test.method.clone(), &test.target);
+            if let Some(body) = &test.body {
+                request = request
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(body.clone());
+            }
+            let response = request.send().await.ok()?;
+            let status = response.status().as_u16();
+            response.bytes().await.ok()?;
+            Some(status)
+        }
+    }
+}
+
+fn report(metrics: &mut RequestMetrics, elapsed: Duration) -> String {
+    let count = metrics.count();
+    let statuses = metrics
+        .statuses()
+        .iter()
+        .map(|(status, count)| format!("{status}: {count}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let latencies = [50.0, 90.0, 99.0, 100.0]
+        .into_iter()
+        .map(|percent| {
+            let name = if percent == 100.0 {
+                "max".to_string()
+            } else {
+                format!("p{percent}")
+            };
+            let latency = metrics.percentile(percent).as_secs_f64() * 1000.0;
+            format!("{name} {latency:.2}ms")
+        })
+        .collect::<Vec<_>>()
+        .join("  ");
+    format!(
+        "\nRequests  {count} in {:.1}s ({:.1} req/s)\nLatency   {latencies}\nStatuses  {}\nErrors    {} ({:.2}%)\n",
+        elapsed.as_secs_f64(),
+        count as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        if statuses.is_empty() { "-" } else { &statuses },
+        metrics.errors(),
+        metrics.error_rate() * 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut metrics = RequestMetrics::default();
+        metrics.record(Duration::from_millis(10), Some(200));
+        metrics.record(Duration::from_millis(20), Some(200));
+        metrics.record(Duration::from_millis(30), Some(500));
+        metrics.record(Duration::from_millis(40), None);
+        assert_eq!(
+            report(&mut metrics, Duration::from_secs(2)),
+            "\nRequests  4 in 2.0s (2.0 req/s)\n\
+             Latency   p50 20.00ms  p90 30.00ms  p99 30.00ms  max 30.00ms\n\
+             Statuses  200: 2, 500: 1\n\
+             Errors    2 (50.00%)\n"
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use axum::{
+    Json, Router,
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use axum_extra::{
+    TypedHeader,
+    headers::{Authorization, authorization::Bearer},
+};
+use serde::Deserialize;
+
+use crate::{Config, config::MaintenanceConfig};
+
+const MAINTENANCE_PATH: &str = "/_maintenance";
+
+// Toggled by the endpoint and the signal, it outlives the server reloads.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+fn set_enabled(enabled: bool) {
+    if ENABLED.swap(enabled, Ordering::Relaxed) != enabled {
+        println!(
+            "🚧 Maintenance mode {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+    }
+}
+
+/// Apply `enabled` of the config and toggle maintenance mode on SIGUSR2,
+/// called once when the process starts.
+pub(crate) fn init(config: &MaintenanceConfig) {
+    set_enabled(config.enabled);
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut signals = match signal(SignalKind::user_defined2()) {
+            Ok(signals) => signals,
+            Err(err) => {
+                eprintln!("Failed to listen to SIGUSR2: {err}");
+                return;
+            }
+        };
+        while signals.recv().await.is_some() {
+            set_enabled(!ENABLED.load(Ordering::Relaxed));
+        }
+    });
+}
+
+fn is_active(config: &MaintenanceConfig) -> bool {
+    ENABLED.load(Ordering::Relaxed) || config.file.as_ref().is_some_and(|file| file.exists())
+}
+
+fn is_allowed(config: &MaintenanceConfig, path: &str) -> bool {
+    path == MAINTENANCE_PATH
+        || config
+            .allow
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == allowed,
+            })
+}
+
+// The 503 response, the maintenance page for browsers if there is one.
+fn unavailable(config: &MaintenanceConfig, headers: &HeaderMap) -> Response {
+    let accepts_html = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    let page = config
+        .page
+        .as_ref()
+        .filter(|_| accepts_html)
+        .and_then(|page| std::fs::read_to_string(page).ok());
+    let mut response = match page {
+        Some(page) => (StatusCode::SERVICE_UNAVAILABLE, Html(page)).into_response(),
+        None => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "error": config.message })),
+        )
+            .into_response(),
+    };
+    if let Some(retry_after) = config.retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    }
+    response
+}
+
+/// The middleware serving the 503 response during maintenance.
+pub(crate) async fn guard(request: Request, next: Next) -> Response {
+    let config = &Config::get().maintenance;
+    if is_active(config) && !is_allowed(config, request.uri().path()) {
+        return unavailable(config, request.headers());
+    }
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+struct Toggle {
+    enabled: bool,
+}
+
+fn authorize(bearer: &Bearer) -> Result<(), StatusCode> {
+    match &Config::get().maintenance.token {
+        Some(token) if **token == *bearer.token() => Ok(()),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+fn status() -> Json<serde_json::Value> {
+    let config = &Config::get().maintenance;
+    Json(serde_json::json!({
+        "enabled": is_active(config),
+        "flag_file": config.file.as_ref().is_some_and(|file| file.exists()),
+    }))
+}
+
+/// The endpoint reading and toggling maintenance mode, None if there
+/// is no token to protect it.
+pub(crate) fn maintenance_router(config: &MaintenanceConfig) -> Option<Router> {
+    config.token.as_ref()?;
+    Some(
+        Router::new().route(
+            MAINTENANCE_PATH,
+            get(
+                |TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>| async move {
+                    authorize(&bearer).map(|_| status())
+                },
+            )
+            .post(
+                |TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+                 Json(toggle): Json<Toggle>| async move {
+                    authorize(&bearer)?;
+                    set_enabled(toggle.enabled);
+                    Ok::<_, StatusCode>(status())
+                },
+            ),
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MaintenanceConfig {
+        MaintenanceConfig {
+            allow: vec!["/health".into(), "/admin/*".into()],
+            retry_after: Some(120),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_allowed_paths() {
+        let config = config();
+        assert!(is_allowed(&config, "/health"));
+        assert!(is_allowed(&config, "/admin/users"));
+        assert!(is_allowed(&config, MAINTENANCE_PATH));
+        assert!(!is_allowed(&config, "/health/db"));
+        assert!(!is_allowed(&config, "/users"));
+    }
+
+    #[test]
+    fn test_unavailable_response() {
+        let response = unavailable(&config(), &HeaderMap::new());
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "120");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+}
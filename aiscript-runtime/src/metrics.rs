@@ -0,0 +1,192 @@
+use std::{collections::BTreeMap, fmt::Write, time::Duration};
+
+use aiscript_vm::{BreakerState, BreakerStatus, circuit_breakers};
+use axum::{Router, routing::get};
+
+use crate::workers::{WorkerStats, worker_stats};
+
+const METRICS_PATH: &str = "/_metrics";
+
+// Render the breaker states in the Prometheus text format,
+// the state is 0 for closed, 1 for half-open and 2 for open.
+fn render(breakers: &[BreakerStatus]) -> String {
+    let mut output = String::new();
+    output.push_str("# TYPE aiscript_circuit_breaker_state gauge\n");
+    for breaker in breakers {
+        let state = match breaker.state {
+            BreakerState::Closed => 0,
+            BreakerState::HalfOpen => 1,
+            BreakerState::Open => 2,
+        };
+        let _ = writeln!(
+            output,
+            "aiscript_circuit_breaker_state{{dependency=\"{}\",state=\"{}\"}} {}",
+            breaker.dependency,
+            breaker.state.as_str(),
+            state
+        );
+    }
+    output.push_str("# TYPE aiscript_circuit_breaker_failures gauge\n");
+    for breaker in breakers {
+        let _ = writeln!(
+            output,
+            "aiscript_circuit_breaker_failures{{dependency=\"{}\"}} {}",
+            breaker.dependency, breaker.failures
+        );
+    }
+    output.push_str("# TYPE aiscript_circuit_breaker_rejected_total counter\n");
+    for breaker in breakers {
+        let _ = writeln!(
+            output,
+            "aiscript_circuit_breaker_rejected_total{{dependency=\"{}\"}} {}",
+            breaker.dependency, breaker.rejected
+        );
+    }
+    output
+}
+
+// Render the state of the worker pool in the Prometheus text format.
+fn render_workers(stats: &WorkerStats) -> String {
+    let mut output = String::new();
+    for (name, kind, value) in [
+        ("aiscript_worker_threads", "gauge", stats.threads as u64),
+        ("aiscript_worker_busy", "gauge", stats.busy as u64),
+        ("aiscript_worker_queue_depth", "gauge", stats.queued as u64),
+        ("aiscript_worker_rejected_total", "counter", stats.rejected),
+    ] {
+        let _ = writeln!(output, "# TYPE {name} {kind}\n{name} {value}");
+    }
+    output
+}
+
+/// The endpoint exposing the circuit breaker of every dependency and the
+/// state of the worker pool.
+pub(crate) fn metrics_router() -> Router {
+    Router::new().route(
+        METRICS_PATH,
+        get(|| async {
+            let mut output = render(&circuit_breakers());
+            if let Some(stats) = worker_stats() {
+                output.push_str(&render_workers(&stats));
+            }
+            output
+        }),
+    )
+}
+
+/// The latencies and the statuses of the requests, e.g. of a load test.
+#[derive(Debug, Default)]
+pub(crate) struct RequestMetrics {
+    latencies: Vec<Duration>,
+    statuses: BTreeMap<u16, usize>,
+    // The requests failing without a response, e.g. refused connections
+    failures: usize,
+}
+
+impl RequestMetrics {
+    /// Record a request, the status is None if it got no response.
+    pub fn record(&mut self, latency: Duration, status: Option<u16>) {
+        match status {
+            Some(status) => {
+                self.latencies.push(latency);
+                *self.statuses.entry(status).or_default() += 1;
+            }
+            None => self.failures += 1,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.latencies.len() + self.failures
+    }
+
+    /// The number of requests per status.
+    pub fn statuses(&self) -> &BTreeMap<u16, usize> {
+        &self.statuses
+    }
+
+    /// The requests without a response or with a server error response.
+    pub fn errors(&self) -> usize {
+        self.failures
+            + self
+                .statuses
+                .range(500..)
+                .map(|(_, count)| count)
+                .sum::<usize>()
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        self.errors() as f64 / self.count().max(1) as f64
+    }
+
+    /// The latency below which the percentage of the responses are, by the
+    /// nearest-rank method, zero if there is no response.
+    pub fn percentile(&mut self, percent: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        self.latencies.sort_unstable();
+        let rank = (percent / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let output = render(&[BreakerStatus {
+            dependency: "http:api.example.com".into(),
+            state: BreakerState::Open,
+            failures: 5,
+            rejected: 3,
+        }]);
+        assert!(output.contains(
+            "aiscript_circuit_breaker_state{dependency=\"http:api.example.com\",state=\"open\"} 2\n"
+        ));
+        assert!(output.contains(
+            "aiscript_circuit_breaker_failures{dependency=\"http:api.example.com\"} 5\n"
+        ));
+        assert!(output.contains(
+            "aiscript_circuit_breaker_rejected_total{dependency=\"http:api.example.com\"} 3\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_workers() {
+        let output = render_workers(&WorkerStats {
+            threads: 4,
+            busy: 4,
+            queued: 7,
+            rejected: 2,
+        });
+        assert!(
+            output.contains(
+                "# TYPE aiscript_worker_queue_depth gauge\naiscript_worker_queue_depth 7\n"
+            )
+        );
+        assert!(output.contains("aiscript_worker_busy 4\n"));
+        assert!(output.contains(
+            "# TYPE aiscript_worker_rejected_total counter\naiscript_worker_rejected_total 2\n"
+        ));
+    }
+
+    #[test]
+    fn test_request_metrics() {
+        let mut metrics = RequestMetrics::default();
+        for ms in 1..=100 {
+            metrics.record(Duration::from_millis(ms), Some(200));
+        }
+        metrics.record(Duration::from_millis(5), Some(503));
+        metrics.record(Duration::from_millis(5), Some(404));
+        metrics.record(Duration::from_secs(1), None);
+
+        assert_eq!(metrics.count(), 103);
+        assert_eq!(metrics.errors(), 2);
+        assert_eq!(metrics.statuses().get(&200), Some(&100));
+        assert_eq!(metrics.percentile(50.0), Duration::from_millis(49));
+        assert_eq!(metrics.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(RequestMetrics::default().percentile(99.0), Duration::ZERO);
+    }
+}
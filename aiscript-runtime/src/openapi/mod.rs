@@ -1,22 +1,24 @@
 use aiscript_directive::Validator;
 use aiscript_directive::route::{Auth, RouteAnnotation};
-use aiscript_directive::validator::{InValidator, StringValidator};
+use aiscript_directive::validator::{InValidator, NumberValidator, StringValidator};
 use oas3::{
     Spec,
     spec::{
-        Components, Info, MediaType, ObjectOrReference, ObjectSchema, Operation, Parameter,
-        ParameterIn, ParameterStyle, PathItem, RequestBody, Response, SchemaType as Type,
-        SchemaTypeSet, SecurityScheme, Server, Tag,
+        Components, Info, MediaType, MediaTypeExamples, ObjectOrReference, ObjectSchema, Operation,
+        Parameter, ParameterIn, ParameterStyle, PathItem, RequestBody, Response,
+        SchemaType as Type, SchemaTypeSet, SecurityScheme, Server, Tag,
     },
 };
 use std::collections::BTreeMap;
 
-use crate::ast::{BodyKind, Endpoint, Field, FieldType, HttpMethod, PathSpec, Route};
+use crate::ast::{BodyKind, Endpoint, Field, FieldType, HttpMethod, PathSpec, Route, Stability};
+use crate::config::ErrorsConfig;
+use crate::lexer::{Scanner, TokenType};
 
 pub struct OpenAPIGenerator;
 
 impl OpenAPIGenerator {
-    pub fn generate(routes: &[Route]) -> Spec {
+    pub fn generate(routes: &[Route], errors: &ErrorsConfig) -> Spec {
         let mut paths = BTreeMap::new();
         let mut tags = Vec::new();
 
@@ -24,16 +26,28 @@ impl OpenAPIGenerator {
             let route_name = route.prefix.trim_matches('/').to_string();
 
             if !route_name.is_empty() {
+                let description = match &route.meta.description {
+                    Some(description) if route.docs.is_empty() => description.clone(),
+                    _ => route.docs.clone(),
+                };
+                // The owner and the stability from the front-matter of the route file
+                let mut extensions = BTreeMap::new();
+                if let Some(owner) = &route.meta.owner {
+                    extensions.insert("owner".to_string(), owner.clone().into());
+                }
+                if let Some(stability) = route.meta.stability {
+                    extensions.insert("stability".to_string(), stability.as_str().into());
+                }
                 tags.push(Tag {
                     name: route_name.clone(),
-                    description: Some(route.docs.clone()),
-                    extensions: BTreeMap::new(),
+                    description: Some(description),
+                    extensions,
                 });
             }
 
             for endpoint in &route.endpoints {
                 for path_spec in &endpoint.path_specs {
-                    let path_item = Self::create_path_item(route, endpoint, path_spec);
+                    let path_item = Self::create_path_item(route, endpoint, path_spec, errors);
                     let path = if route.prefix.starts_with('/') {
                         route.prefix.clone()
                     } else {
@@ -48,7 +62,14 @@ impl OpenAPIGenerator {
                     if route.annotation.docs.as_ref().is_some_and(|d| d.hidden) {
                         continue;
                     }
-                    paths.insert(full_path.replace("//", "/"), path_item);
+                    // The root endpoint of a nested route is served without
+                    // the trailing slash
+                    let full_path = full_path.replace("//", "/");
+                    let full_path = match full_path.strip_suffix('/') {
+                        Some(path) if !path.is_empty() => path.to_string(),
+                        _ => full_path,
+                    };
+                    paths.insert(full_path, path_item);
                 }
             }
         }
@@ -115,14 +136,19 @@ impl OpenAPIGenerator {
         }
     }
 
-    fn create_path_item(route: &Route, endpoint: &Endpoint, path_spec: &PathSpec) -> PathItem {
+    fn create_path_item(
+        route: &Route,
+        endpoint: &Endpoint,
+        path_spec: &PathSpec,
+        errors: &ErrorsConfig,
+    ) -> PathItem {
         let mut path_item = PathItem {
             summary: Some(endpoint.docs.clone()),
             parameters: Self::create_path_parameters(endpoint, &path_spec.params),
             ..Default::default()
         };
 
-        let operation = Self::create_operation(route, endpoint, path_spec);
+        let operation = Self::create_operation(route, endpoint, path_spec, errors);
 
         match path_spec.method {
             HttpMethod::Get => path_item.get = Some(operation),
@@ -153,7 +179,12 @@ impl OpenAPIGenerator {
         }
     }
 
-    fn create_operation(route: &Route, endpoint: &Endpoint, path_spec: &PathSpec) -> Operation {
+    fn create_operation(
+        route: &Route,
+        endpoint: &Endpoint,
+        path_spec: &PathSpec,
+        errors: &ErrorsConfig,
+    ) -> Operation {
         let route_name = route.prefix.trim_matches('/').to_string();
         let mut tags = if route_name.is_empty() {
             vec!["default".to_string()]
@@ -167,6 +198,11 @@ impl OpenAPIGenerator {
                 tags = vec![tag.clone()];
             }
         }
+        for tag in &route.meta.tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
 
         // Create path parameters using our enhanced function that leverages endpoint.path fields
         let mut parameters = Self::create_path_parameters(endpoint, &path_spec.params);
@@ -196,8 +232,11 @@ impl OpenAPIGenerator {
             operation_id: Some(operation_id),
             parameters,
             request_body,
-            responses: Some(Self::create_default_responses()),
-            deprecated: route.annotation.docs.as_ref().map(|d| d.deprecated),
+            responses: Some(Self::create_responses(endpoint, errors)),
+            deprecated: match route.meta.stability {
+                Some(Stability::Deprecated) => Some(true),
+                _ => route.annotation.docs.as_ref().map(|d| d.deprecated),
+            },
             // security: Self::get_security_requirement(endpoint.annotation),
             ..Default::default()
         }
@@ -226,7 +265,7 @@ impl OpenAPIGenerator {
                         Some(field) => Self::create_schema_for_field(field),
                         None => Self::create_default_schema_for_path_param(),
                     }),
-                    example: path_field.and_then(|f| f.default.clone()),
+                    example: path_field.map(Self::example_for_field),
                     examples: BTreeMap::new(),
                     content: None,
                     extensions: BTreeMap::new(),
@@ -255,7 +294,7 @@ impl OpenAPIGenerator {
             explode: None,
             allow_reserved: None,
             schema: Some(Self::create_schema_for_field(field)),
-            example: field.default.clone(),
+            example: Some(Self::example_for_field(field)),
             examples: BTreeMap::new(),
             content: None,
             extensions: BTreeMap::new(),
@@ -309,6 +348,7 @@ impl OpenAPIGenerator {
             })),
             description: Some(field.docs.clone()),
             default: field.default.clone(),
+            example: Some(Self::example_for_field(field)),
             ..Default::default()
         };
 
@@ -329,15 +369,150 @@ impl OpenAPIGenerator {
         ObjectOrReference::Object(schema)
     }
 
-    fn create_default_responses() -> BTreeMap<String, ObjectOrReference<Response>> {
+    // A value passing the validators of the field, the contract tests send
+    // the examples of the fields.
+    fn example_for_field(field: &Field) -> serde_json::Value {
+        if let Some(default) = &field.default {
+            return default.clone();
+        }
+        let mut number = 1.0;
+        let (mut prefix, mut suffix) = ("", "");
+        let (mut min_len, mut max_len) = (6, usize::MAX);
+        for validator in field.validators.iter() {
+            if let Some(in_validator) = validator.downcast_ref::<InValidator>()
+                && let Some(value) = in_validator.0.first()
+            {
+                return value.clone();
+            }
+            if let Some(number_validator) = validator.downcast_ref::<NumberValidator>() {
+                number = match number_validator {
+                    NumberValidator {
+                        equal: Some(equal), ..
+                    } => *equal,
+                    NumberValidator { min: Some(min), .. } => min.ceil(),
+                    NumberValidator { max: Some(max), .. } => max.floor().min(number),
+                    _ => number,
+                };
+            }
+            if let Some(string_validator) = validator.downcast_ref::<StringValidator>() {
+                prefix = string_validator.start_with.as_deref().unwrap_or_default();
+                suffix = string_validator.end_with.as_deref().unwrap_or_default();
+                if let Some(len) = string_validator.exact_len {
+                    (min_len, max_len) = (len as usize, len as usize);
+                }
+                min_len = string_validator.min_len.map_or(min_len, |len| len as usize);
+                max_len = string_validator.max_len.map_or(max_len, |len| len as usize);
+            }
+        }
+        match field._type {
+            FieldType::Str => {
+                let len = min_len
+                    .min(max_len)
+                    .saturating_sub(prefix.len() + suffix.len());
+                format!("{prefix}{}{suffix}", "x".repeat(len)).into()
+            }
+            FieldType::Number if number.fract() == 0.0 => (number as i64).into(),
+            FieldType::Number => number.into(),
+            FieldType::Bool => true.into(),
+            FieldType::Array => serde_json::Value::Array(Vec::new()),
+        }
+    }
+
+    fn create_responses(
+        endpoint: &Endpoint,
+        errors: &ErrorsConfig,
+    ) -> BTreeMap<String, ObjectOrReference<Response>> {
         let mut responses = BTreeMap::new();
+        // The example of `@mock` documents the response
+        let (status, content) = match &endpoint.annotation.mock {
+            Some(mock) => (
+                mock.status.to_string(),
+                BTreeMap::from([(
+                    "application/json".to_string(),
+                    MediaType {
+                        examples: Some(MediaTypeExamples::Example {
+                            example: mock.example.clone(),
+                        }),
+                        ..Default::default()
+                    },
+                )]),
+            ),
+            None => ("200".to_string(), BTreeMap::new()),
+        };
         responses.insert(
-            "200".to_string(),
+            status,
             ObjectOrReference::Object(Response {
                 description: Some("Successful operation".to_string()),
+                content,
                 ..Default::default()
             }),
         );
+        // The error types of `[errors]` raised by the script
+        for (name, error_type) in errors.iter() {
+            if !mentions(&endpoint.statements, name) {
+                continue;
+            }
+            let description = error_type.description.as_deref().unwrap_or(name);
+            let response = responses
+                .entry(error_type.status.to_string())
+                .or_insert_with(|| {
+                    ObjectOrReference::Object(Response {
+                        description: None,
+                        content: BTreeMap::from([(
+                            "application/json".to_string(),
+                            MediaType {
+                                examples: Some(MediaTypeExamples::Example {
+                                    example: serde_json::json!({
+                                        "error": description,
+                                        "type": name,
+                                    }),
+                                }),
+                                ..Default::default()
+                            },
+                        )]),
+                        ..Default::default()
+                    })
+                });
+            // The error types sharing a status are listed in its description
+            if let ObjectOrReference::Object(response) = response {
+                response.description = Some(match response.description.take() {
+                    Some(other) => format!("{other}, {description}"),
+                    None => description.to_string(),
+                });
+            }
+        }
         responses
     }
 }
+
+// Whether the script uses the error type, e.g. `raise NotFound! {...}`. The
+// tokens are compared, the comments and strings naming it don't count.
+fn mentions(script: &str, name: &str) -> bool {
+    let mut scanner = Scanner::new(script);
+    scanner.advance();
+    while !scanner.is_at_end() {
+        if scanner.check(TokenType::Error) && scanner.current.lexeme == name {
+            return true;
+        }
+        scanner.advance();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mentions() {
+        assert!(mentions(
+            r#"raise NotFound! { message: "no user" };"#,
+            "NotFound!"
+        ));
+        assert!(!mentions(r#"raise UserNotFound! {};"#, "NotFound!"));
+        assert!(!mentions(
+            "// Never raises NotFound!\nreturn \"NotFound!\";",
+            "NotFound!"
+        ));
+    }
+}
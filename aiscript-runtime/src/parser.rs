@@ -6,6 +6,31 @@ use std::ops::{Deref, DerefMut};
 use crate::ast::*;
 use crate::lexer::{Scanner, TokenType};
 
+// The function wrapping the script of an endpoint.
+pub(crate) const HANDLER_SIGNATURE: &str = "ai fn handler(path, query, body, request, header, ctx)";
+
+/// Declare the error types of `[errors]` for the handler of an endpoint, as
+/// classes with a message, so the script can raise them. The declarations
+/// share the first line of the handler, the lines of the script are kept.
+pub(crate) fn declare_error_types<'a>(
+    statements: &str,
+    names: impl Iterator<Item = &'a str>,
+) -> String {
+    let names = names.collect::<Vec<_>>();
+    let script = match statements.strip_prefix(HANDLER_SIGNATURE) {
+        Some(script) if !names.is_empty() => script,
+        _ => return statements.to_owned(),
+    };
+    let classes = names
+        .iter()
+        .map(|name| format!("class {name} {{ message: str = \"\", }} "))
+        .collect::<String>();
+    format!(
+        "{classes}{HANDLER_SIGNATURE} -> {}{script}",
+        names.join(" | ")
+    )
+}
+
 pub struct Parser<'a> {
     scanner: Scanner<'a>,
 }
@@ -74,6 +99,7 @@ impl<'a> Parser<'a> {
             params: path.1,
             endpoints,
             docs,
+            meta: RouteMeta::default(),
         })
     }
 
@@ -129,11 +155,9 @@ impl<'a> Parser<'a> {
             return Err("Route without handler script is not allowed.".to_string());
         }
         // Parse the handler function body
+        let line = self.current.line;
         let script = self.read_raw_script()?;
-        let statements = format!(
-            "ai fn handler(path, query, body, request, header){{{}}}",
-            script
-        );
+        let statements = format!("{HANDLER_SIGNATURE}{{{}}}", script);
         self.consume(TokenType::CloseBrace, "Expect '}' after endpoint")?;
 
         let endpoint = Endpoint {
@@ -144,6 +168,7 @@ impl<'a> Parser<'a> {
             query,
             body,
             statements,
+            line,
             docs,
         };
         // Validate path parameters
@@ -271,6 +296,7 @@ impl<'a> Parser<'a> {
                 return Err("Expected HTTP method".to_string());
             }
 
+            let line = self.current.line;
             let method = match self.current.lexeme {
                 "get" => HttpMethod::Get,
                 "post" => HttpMethod::Post,
@@ -287,6 +313,7 @@ impl<'a> Parser<'a> {
                 method,
                 path,
                 params,
+                line,
             });
 
             // Check for more paths
@@ -465,8 +492,40 @@ impl<'a> Parser<'a> {
 }
 
 pub fn parse_route(input: &str) -> Result<Route, String> {
-    let mut parser = Parser::new(input);
-    parser.parse_route()
+    let (meta, input) = parse_front_matter(input)?;
+    let mut parser = Parser::new(&input);
+    let mut route = parser.parse_route()?;
+    route.meta = meta;
+    Ok(route)
+}
+
+// Parse the front-matter at the top of the route file, returns the source
+// with the front-matter blanked out so the lines of the route stay the same.
+fn parse_front_matter(input: &str) -> Result<(RouteMeta, String), String> {
+    let lines = input.lines().collect::<Vec<_>>();
+    let Some(start) = lines
+        .iter()
+        .position(|line| !line.trim().is_empty())
+        .filter(|&start| lines[start].trim() == "+++")
+    else {
+        return Ok((RouteMeta::default(), input.to_owned()));
+    };
+    let Some(end) = lines[start + 1..]
+        .iter()
+        .position(|line| line.trim() == "+++")
+        .map(|len| start + 1 + len)
+    else {
+        return Err("Unterminated front-matter, expect a closing '+++' line.".to_string());
+    };
+    let meta = toml::from_str(&lines[start + 1..end].join("\n"))
+        .map_err(|e| format!("Invalid front-matter: {}", e.message()))?;
+    let route = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| if i <= end { "" } else { line })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok((meta, route))
 }
 
 #[cfg(test)]
@@ -478,6 +537,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_declare_error_types() {
+        let statements = format!("{HANDLER_SIGNATURE}{{\n    return 1;\n}}");
+        assert_eq!(
+            declare_error_types(&statements, ["NotFound!", "Unauthorized!"].into_iter()),
+            format!(
+                "class NotFound! {{ message: str = \"\", }} class Unauthorized! {{ message: str = \"\", }} \
+                {HANDLER_SIGNATURE} -> NotFound! | Unauthorized!{{\n    return 1;\n}}"
+            )
+        );
+        assert_eq!(
+            declare_error_types(&statements, std::iter::empty()),
+            statements
+        );
+    }
+
     #[test]
     fn test_basic_route() {
         let input = r#"
@@ -545,6 +620,7 @@ mod tests {
         // Verify script capture
         assert!(endpoint.statements.contains("let greeting"));
         assert!(endpoint.statements.contains("return greeting"));
+        assert_eq!(endpoint.line, 21);
 
         // Verify endpoint2
         let endpoint2 = &route.endpoints[1];
@@ -966,4 +1042,76 @@ mod tests {
             "/user-profile/update-settings"
         );
     }
+
+    #[test]
+    fn test_front_matter() {
+        let input = r#"
+            +++
+            owner = "payments-team"
+            description = "Refunds of the orders"
+            tags = ["payments", "billing"]
+            stability = "beta"
+            +++
+            route /refunds {
+                get / {
+                    return 1 + nil;
+                }
+            }
+        "#;
+        let route = parse_route(input).unwrap();
+        assert_eq!(route.meta.owner.as_deref(), Some("payments-team"));
+        assert_eq!(
+            route.meta.description.as_deref(),
+            Some("Refunds of the orders")
+        );
+        assert_eq!(route.meta.tags, ["payments", "billing"]);
+        assert_eq!(route.meta.stability, Some(Stability::Beta));
+        assert_eq!(route.prefix, "/refunds");
+        // The lines of the route are kept
+        assert_eq!(route.endpoints[0].path_specs[0].line, 9);
+
+        let route = parse_route("route /a {\n  get / {\n    return 1;\n  }\n}").unwrap();
+        assert!(route.meta.owner.is_none() && route.meta.tags.is_empty());
+
+        let err = parse_route("+++\nowner = \"a\"\nroute /a {}").unwrap_err();
+        assert_eq!(
+            err,
+            "Unterminated front-matter, expect a closing '+++' line."
+        );
+        let err = parse_route("+++\nstability = \"alpha\"\n+++\nroute /a {}").unwrap_err();
+        assert!(err.starts_with("Invalid front-matter: "));
+        let err = parse_route("+++\nteam = \"a\"\n+++\nroute /a {}").unwrap_err();
+        assert!(err.contains("unknown field `team`"));
+    }
+
+    #[test]
+    fn test_fallback() {
+        let input = r#"
+            route /chat {
+                @fallback(cached_reply)
+                post / {
+                    fn cached_reply() {
+                        return "Please retry later.";
+                    }
+                    return missing_upstream();
+                }
+            }
+        "#;
+
+        let route = Parser::new(input).parse_route().unwrap();
+        let endpoint = &route.endpoints[0];
+        assert_eq!(
+            endpoint.annotation.fallback.as_deref(),
+            Some("cached_reply")
+        );
+
+        let script = Box::leak(endpoint.statements.clone().into_boxed_str());
+        let mut vm = aiscript_vm::Vm::default();
+        vm.compile(script).unwrap();
+        assert_eq!(
+            vm.eval_fallback("cached_reply").unwrap(),
+            aiscript_vm::ReturnValue::String("Please retry later.".into())
+        );
+        assert!(vm.eval_fallback("unknown").is_err());
+    }
 }
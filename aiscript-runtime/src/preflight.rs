@@ -0,0 +1,100 @@
+//! Preflight checks of `serve --preflight`: the AI providers, the database
+//! and Redis configured in project.toml are connected to before the server
+//! accepts traffic, a misconfigured dependency fails the start rather than
+//! the first request using it.
+
+use std::{future::Future, time::Duration};
+
+use sqlx::{postgres::PgPoolOptions, sqlite::SqlitePoolOptions};
+use tokio::time;
+
+use crate::Config;
+
+// The time a dependency has to answer.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run the preflight checks and print their report, returns false if a
+/// dependency isn't available.
+pub async fn run_preflight() -> bool {
+    let config = Config::get();
+    let mut checks = Vec::new();
+    for provider in aiscript_vm::PROVIDERS {
+        if let Some(model_config) = config.ai.provider(provider) {
+            let result = check(aiscript_vm::ping_provider(provider, model_config)).await;
+            checks.push((provider, result));
+        }
+    }
+    if let Some(url) = config.database.get_postgres_url() {
+        checks.push(("postgresql", check(ping_postgres(&url)).await));
+    }
+    if let Some(url) = config.database.get_sqlite_url() {
+        checks.push(("sqlite", check(ping_sqlite(&url)).await));
+    }
+    if let Some(url) = config.database.get_redis_url() {
+        checks.push(("redis", check(ping_redis(&url)).await));
+    }
+
+    if checks.is_empty() {
+        println!("🛫 Preflight: no AI provider, database or Redis configured");
+        return true;
+    }
+    println!("🛫 Preflight:");
+    let mut ok = true;
+    for (name, result) in &checks {
+        match result {
+            Ok(()) => println!("  ✅ {name}"),
+            Err(e) => {
+                eprintln!("  ❌ {name}: {e}");
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+async fn check(ping: impl Future<Output = Result<(), String>>) -> Result<(), String> {
+    time::timeout(TIMEOUT, ping)
+        .await
+        .unwrap_or_else(|_| Err(format!("no answer in {}s", TIMEOUT.as_secs())))
+}
+
+async fn ping_postgres(url: &str) -> Result<(), String> {
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .acquire_timeout(TIMEOUT)
+        .connect(url)
+        .await
+        .map_err(|e| format!("can't connect ({e}), check [database.postgresql] in project.toml"))?;
+    sqlx::query("SELECT 1")
+        .execute(&pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    pool.close().await;
+    Ok(())
+}
+
+async fn ping_sqlite(url: &str) -> Result<(), String> {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect(url)
+        .await
+        .map_err(|e| format!("can't open {url} ({e}), check [database.sqlite] in project.toml"))?;
+    pool.close().await;
+    Ok(())
+}
+
+async fn ping_redis(url: &str) -> Result<(), String> {
+    let unavailable = |e: redis::RedisError| {
+        format!("can't connect ({e}), check [database.redis] in project.toml")
+    };
+    let client = redis::Client::open(url).map_err(unavailable)?;
+    let mut conn = client
+        .get_multiplexed_async_connection()
+        .await
+        .map_err(unavailable)?;
+    redis::cmd("PING")
+        .query_async::<String>(&mut conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use aiscript_directive::{DirectiveParser, FromDirective, schedule::Schedule};
+use aiscript_vm::{ReturnValue, Vm, VmError};
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    routing::get,
+};
+use chrono::{Local, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, SqlitePool};
+use tokio::task::{self, JoinSet};
+use walkdir::WalkDir;
+
+use crate::{Config, lexer::Scanner, lexer::TokenType};
+
+pub(crate) const SCHEDULES_DIR: &str = "schedules";
+// The endpoint listing the history of scheduled runs.
+const RUNS_PATH: &str = "/_schedules/runs";
+// Runs kept when there's no database to persist them to.
+const MAX_MEMORY_RUNS: usize = 1000;
+
+/// A function or agent declared with `@schedule` in the schedules directory.
+#[derive(Debug, Clone)]
+pub(crate) struct Job {
+    pub name: String,
+    pub is_agent: bool,
+    pub schedule: Schedule,
+    pub source: String,
+}
+
+impl Job {
+    // The script running the job: the file followed by a call of the function or agent.
+    fn script(&self) -> String {
+        let call = if self.is_agent {
+            format!(
+                "{}.run({:?})",
+                self.name,
+                self.schedule.input.as_deref().unwrap_or_default()
+            )
+        } else {
+            format!("{}()", self.name)
+        };
+        format!("{}\nreturn {call};\n", self.source)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Run {
+    pub id: i64,
+    pub job: String,
+    pub started_at: String,
+    pub finished_at: String,
+    // "success" or "error"
+    pub status: String,
+    pub output: serde_json::Value,
+    pub trace: serde_json::Value,
+}
+
+// id, job, started_at, finished_at, status, output, trace
+type RunRow = (
+    i64,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+);
+
+/// Where the runs are persisted, the database if one is configured.
+#[derive(Clone)]
+pub(crate) enum RunStore {
+    Postgres(PgPool),
+    Sqlite(SqlitePool),
+    Memory(Arc<Mutex<VecDeque<Run>>>),
+}
+
+impl RunStore {
+    pub async fn new(pg: Option<PgPool>, sqlite: Option<SqlitePool>) -> Result<Self, sqlx::Error> {
+        if let Some(pool) = pg {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS schedule_runs (
+                    id BIGSERIAL PRIMARY KEY, job TEXT NOT NULL, started_at TEXT NOT NULL,
+                    finished_at TEXT NOT NULL, status TEXT NOT NULL, output TEXT, trace TEXT)",
+            )
+            .execute(&pool)
+            .await?;
+            Ok(RunStore::Postgres(pool))
+        } else if let Some(pool) = sqlite {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS schedule_runs (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT, job TEXT NOT NULL, started_at TEXT NOT NULL,
+                    finished_at TEXT NOT NULL, status TEXT NOT NULL, output TEXT, trace TEXT)",
+            )
+            .execute(&pool)
+            .await?;
+            Ok(RunStore::Sqlite(pool))
+        } else {
+            Ok(RunStore::Memory(Default::default()))
+        }
+    }
+
+    async fn insert(&self, mut run: Run) -> Result<(), sqlx::Error> {
+        const INSERT: &str = "INSERT INTO schedule_runs (job, started_at, finished_at, status, output, trace) VALUES ";
+        let output = run.output.to_string();
+        let trace = run.trace.to_string();
+        match self {
+            RunStore::Postgres(pool) => {
+                sqlx::query(&format!("{INSERT}($1, $2, $3, $4, $5, $6)"))
+                    .bind(&run.job)
+                    .bind(&run.started_at)
+                    .bind(&run.finished_at)
+                    .bind(&run.status)
+                    .bind(output)
+                    .bind(trace)
+                    .execute(pool)
+                    .await?;
+            }
+            RunStore::Sqlite(pool) => {
+                sqlx::query(&format!("{INSERT}(?, ?, ?, ?, ?, ?)"))
+                    .bind(&run.job)
+                    .bind(&run.started_at)
+                    .bind(&run.finished_at)
+                    .bind(&run.status)
+                    .bind(output)
+                    .bind(trace)
+                    .execute(pool)
+                    .await?;
+            }
+            RunStore::Memory(runs) => {
+                let mut runs = runs.lock().unwrap();
+                run.id = runs.back().map_or(1, |last| last.id + 1);
+                if runs.len() == MAX_MEMORY_RUNS {
+                    runs.pop_front();
+                }
+                runs.push_back(run);
+            }
+        }
+        Ok(())
+    }
+
+    // The latest runs first, optionally only the runs of the job.
+    async fn list(&self, job: Option<&str>, limit: i64) -> Result<Vec<Run>, sqlx::Error> {
+        const SELECT: &str =
+            "SELECT id, job, started_at, finished_at, status, output, trace FROM schedule_runs";
+        let rows: Vec<RunRow> = match self {
+            RunStore::Postgres(pool) => {
+                let sql = match job {
+                    Some(_) => format!("{SELECT} WHERE job = $1 ORDER BY id DESC LIMIT $2"),
+                    None => format!("{SELECT} ORDER BY id DESC LIMIT $1"),
+                };
+                let mut query = sqlx::query_as(&sql);
+                if let Some(job) = job {
+                    query = query.bind(job);
+                }
+                query.bind(limit).fetch_all(pool).await?
+            }
+            RunStore::Sqlite(pool) => {
+                let sql = match job {
+                    Some(_) => format!("{SELECT} WHERE job = ? ORDER BY id DESC LIMIT ?"),
+                    None => format!("{SELECT} ORDER BY id DESC LIMIT ?"),
+                };
+                let mut query = sqlx::query_as(&sql);
+                if let Some(job) = job {
+                    query = query.bind(job);
+                }
+                query.bind(limit).fetch_all(pool).await?
+            }
+            RunStore::Memory(runs) => {
+                return Ok(runs
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .rev()
+                    .filter(|run| job.is_none_or(|job| run.job == job))
+                    .take(limit.max(0) as usize)
+                    .cloned()
+                    .collect());
+            }
+        };
+        let json = |value: Option<String>| {
+            value
+                .and_then(|value| serde_json::from_str(&value).ok())
+                .unwrap_or_default()
+        };
+        Ok(rows
+            .into_iter()
+            .map(
+                |(id, job, started_at, finished_at, status, output, trace)| Run {
+                    id,
+                    job,
+                    started_at,
+                    finished_at,
+                    status,
+                    output: json(output),
+                    trace: json(trace),
+                },
+            )
+            .collect())
+    }
+}
+
+pub(crate) fn read_jobs() -> Vec<Job> {
+    let mut jobs = Vec::new();
+    for entry in WalkDir::new(SCHEDULES_DIR)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file() && e.path().extension().is_some_and(|ext| ext == "ai"))
+    {
+        match read_file_jobs(entry.path()) {
+            Ok(file_jobs) => jobs.extend(file_jobs),
+            Err(e) => eprintln!("Error reading schedule file {:?}: {}", entry.path(), e),
+        }
+    }
+    jobs
+}
+
+fn read_file_jobs(path: &Path) -> Result<Vec<Job>, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse_jobs(&source)
+}
+
+// Find the functions and agents declared with `@schedule`,
+// the rest of the script is checked when the job is compiled.
+fn parse_jobs(source: &str) -> Result<Vec<Job>, String> {
+    let mut jobs = Vec::new();
+    let mut scanner = Scanner::new(source);
+    scanner.advance();
+    while !scanner.is_at_end() {
+        if !scanner.check(TokenType::At) {
+            scanner.advance();
+            continue;
+        }
+        let directives = DirectiveParser::new(&mut scanner).parse_directives();
+        let mut schedules = Vec::new();
+        for directive in directives {
+            if directive.name == "schedule" {
+                schedules.push(Schedule::from_directive(directive)?);
+            }
+        }
+        scanner.match_token(TokenType::Pub);
+        scanner.match_token(TokenType::AI);
+        let is_agent = if scanner.match_token(TokenType::Agent) {
+            true
+        } else if scanner.match_token(TokenType::Fn) {
+            false
+        } else {
+            return Err("@schedule can only be applied to functions and agents.".into());
+        };
+        scanner.consume(TokenType::Identifier, "Expect name.");
+        let name = scanner.previous.lexeme.to_owned();
+        for schedule in schedules {
+            if is_agent && schedule.input.is_none() {
+                return Err(format!(
+                    "Scheduled agent '{name}' requires an input, e.g. @schedule(\"{}\", \"<input>\").",
+                    schedule.cron.expression
+                ));
+            }
+            jobs.push(Job {
+                name: name.clone(),
+                is_agent,
+                schedule,
+                source: source.to_owned(),
+            });
+        }
+    }
+    Ok(jobs)
+}
+
+async fn run_job(
+    job: &Job,
+    pg_connection: Option<PgPool>,
+    sqlite_connection: Option<SqlitePool>,
+    redis_connection: Option<redis::aio::MultiplexedConnection>,
+) -> Run {
+    let started_at = Utc::now().to_rfc3339();
+    let script: &'static str = Box::leak(job.script().into_boxed_str());
+    let result = task::spawn_blocking(move || {
+        let ai_config = Config::load().ai.clone();
+        let mut vm = Vm::new(
+            pg_connection,
+            sqlite_connection,
+            redis_connection,
+            ai_config,
+        );
+        vm.record_trace_in_memory();
+        vm.register_extra_native_functions();
+        let result = vm.compile(script).and_then(|_| vm.interpret());
+        let trace = vm
+            .take_trace()
+            .map(|trace| serde_json::to_value(trace.events()).unwrap_or_default())
+            .unwrap_or_default();
+        (result, trace)
+    })
+    .await;
+    let (status, output, trace) = match result {
+        Ok((Ok(value), trace)) => ("success", return_value_to_json(value), trace),
+        Ok((Err(err), trace)) => (
+            "error",
+            serde_json::Value::String(match err {
+                VmError::CompileError => "Compile Error".to_owned(),
+                VmError::RuntimeError(message) => message,
+                VmError::Traced(error) => error.to_string(),
+                err => err.to_string(),
+            }),
+            trace,
+        ),
+        Err(err) => (
+            "error",
+            serde_json::Value::String(err.to_string()),
+            serde_json::Value::Null,
+        ),
+    };
+    Run {
+        id: 0,
+        job: job.name.clone(),
+        started_at,
+        finished_at: Utc::now().to_rfc3339(),
+        status: status.to_owned(),
+        output,
+        trace,
+    }
+}
+
+fn return_value_to_json(value: ReturnValue) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or_default()
+}
+
+/// Run each job on its schedule, the jobs are aborted when the set is dropped.
+pub(crate) fn start(
+    jobs: Vec<Job>,
+    store: RunStore,
+    pg_connection: Option<PgPool>,
+    sqlite_connection: Option<SqlitePool>,
+    redis_connection: Option<redis::aio::MultiplexedConnection>,
+) -> JoinSet<()> {
+    let mut set = JoinSet::new();
+    for job in jobs {
+        println!(
+            "Scheduled {} '{}' at \"{}\"",
+            if job.is_agent { "agent" } else { "function" },
+            job.name,
+            job.schedule.cron.expression
+        );
+        let store = store.clone();
+        let pg_connection = pg_connection.clone();
+        let sqlite_connection = sqlite_connection.clone();
+        let redis_connection = redis_connection.clone();
+        set.spawn(async move {
+            loop {
+                let now = Local::now();
+                let Some(next) = job
+                    .schedule
+                    .cron
+                    .next_after(now.naive_local())
+                    .and_then(|next| Local.from_local_datetime(&next).earliest())
+                else {
+                    eprintln!(
+                        "Schedule \"{}\" of '{}' never fires.",
+                        job.schedule.cron.expression, job.name
+                    );
+                    return;
+                };
+                tokio::time::sleep((next - now).to_std().unwrap_or(Duration::ZERO)).await;
+
+                let run = run_job(
+                    &job,
+                    pg_connection.clone(),
+                    sqlite_connection.clone(),
+                    redis_connection.clone(),
+                )
+                .await;
+                if let Err(err) = store.insert(run).await {
+                    eprintln!("Failed to save the run of '{}': {err}", job.name);
+                }
+            }
+        });
+    }
+    set
+}
+
+#[derive(Debug, Deserialize)]
+struct RunsQuery {
+    job: Option<String>,
+    limit: Option<i64>,
+}
+
+async fn list_runs(
+    State(store): State<RunStore>,
+    Query(query): Query<RunsQuery>,
+) -> Result<Json<Vec<Run>>, (axum::http::StatusCode, String)> {
+    store
+        .list(query.job.as_deref(), query.limit.unwrap_or(50))
+        .await
+        .map(Json)
+        .map_err(|err| {
+            (
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                err.to_string(),
+            )
+        })
+}
+
+/// The endpoint listing the history of scheduled runs.
+pub(crate) fn runs_router(store: RunStore) -> Router {
+    Router::new()
+        .route(RUNS_PATH, get(list_runs))
+        .with_state(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jobs() {
+        let source = r#"
+            @schedule("0 9 * * 1")
+            fn weekly_report() {
+                return "report";
+            }
+
+            @schedule("*/30 * * * *", "Summarize the news")
+            pub agent Reporter {
+                instructions: "You are a reporter.",
+            }
+
+            fn helper() {}
+        "#;
+        let jobs = parse_jobs(source).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].name, "weekly_report");
+        assert!(!jobs[0].is_agent);
+        assert!(jobs[0].script().ends_with("\nreturn weekly_report();\n"));
+        assert_eq!(jobs[1].name, "Reporter");
+        assert!(jobs[1].is_agent);
+        assert!(
+            jobs[1]
+                .script()
+                .ends_with("\nreturn Reporter.run(\"Summarize the news\");\n")
+        );
+
+        assert!(parse_jobs("@schedule(\"0 9 * * 1\") agent Reporter {}").is_err());
+        assert!(parse_jobs("@schedule(\"0 9 * *\") fn f() {}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        let store = RunStore::new(None, None).await.unwrap();
+        for job in ["a", "b", "a"] {
+            let run = Run {
+                id: 0,
+                job: job.to_owned(),
+                started_at: String::new(),
+                finished_at: String::new(),
+                status: "success".to_owned(),
+                output: serde_json::Value::Null,
+                trace: serde_json::Value::Null,
+            };
+            store.insert(run).await.unwrap();
+        }
+        let runs = store.list(Some("a"), 10).await.unwrap();
+        assert_eq!(runs.iter().map(|run| run.id).collect::<Vec<_>>(), [3, 1]);
+        assert_eq!(store.list(None, 1).await.unwrap()[0].id, 3);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    Router,
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, Version},
+    serve::Listener,
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tower::ServiceExt;
+
+use crate::{config::ServerConfig, early_hints::HintedIo};
+
+// The address of a connected peer, which is only known for TCP connections.
+pub(crate) trait PeerAddr {
+    fn socket_addr(&self) -> Option<SocketAddr>;
+}
+
+impl PeerAddr for SocketAddr {
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        Some(*self)
+    }
+}
+
+#[cfg(unix)]
+impl PeerAddr for tokio::net::unix::SocketAddr {
+    fn socket_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+}
+
+/// The router serving the requests, a reload swaps in a new one while the
+/// connections stay open. The requests in flight finish with the router
+/// they were routed by.
+#[derive(Clone)]
+pub(crate) struct SharedRouter(Arc<RwLock<Router>>);
+
+impl SharedRouter {
+    pub(crate) fn new(router: Router) -> Self {
+        Self(Arc::new(RwLock::new(router)))
+    }
+
+    pub(crate) fn swap(&self, router: Router) {
+        *self.0.write().unwrap() = router;
+    }
+
+    fn current(&self) -> Router {
+        self.0.read().unwrap().clone()
+    }
+}
+
+// The connection builder with the HTTP/1.1 and HTTP/2 options of `[server]`,
+// hyper defaults are kept for the unset ones.
+fn connection_builder(config: &ServerConfig) -> Builder<TokioExecutor> {
+    let mut builder = Builder::new(TokioExecutor::new());
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(config.keep_alive)
+        .header_read_timeout(config.header_read_timeout.map(Duration::from_secs));
+    let mut http2 = builder.http2();
+    http2
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(config.http2_max_concurrent_streams)
+        .keep_alive_interval(config.http2_keep_alive_interval.map(Duration::from_secs));
+    if let Some(timeout) = config.http2_keep_alive_timeout {
+        http2.keep_alive_timeout(Duration::from_secs(timeout));
+    }
+    if config.http2 {
+        builder
+    } else {
+        builder.http1_only()
+    }
+}
+
+/// Serve the router on the listener until the shutdown future completes,
+/// then wait for the in-flight connections to finish.
+pub(crate) async fn serve<L>(
+    mut listener: L,
+    router: SharedRouter,
+    config: &ServerConfig,
+    shutdown: impl Future<Output = ()>,
+) where
+    L: Listener,
+    L::Addr: PeerAddr,
+{
+    let builder = connection_builder(config);
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (io, addr) = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = &mut shutdown => break,
+        };
+        // The peer address, the client IP is derived from it
+        let peer = addr.socket_addr();
+        let router = router.clone();
+        let (io, hints) = HintedIo::new(io);
+        let service = tower::service_fn(move |mut request: Request<Incoming>| {
+            if let Some(peer) = peer {
+                request.extensions_mut().insert(ConnectInfo(peer));
+            }
+            // Informational responses can't be sent to HTTP/1.0 clients, and
+            // the raw ones would break the framing of HTTP/2
+            if request.version() == Version::HTTP_11 {
+                request.extensions_mut().insert(hints.clone());
+            }
+            // Routed by the current router, keep-alive connections see the reloads
+            router.current().oneshot(request.map(Body::new))
+        });
+        let service = TowerToHyperService::new(service);
+        let conn = builder
+            .serve_connection(TokioIo::new(io), service)
+            .into_owned();
+        let conn = graceful.watch(conn);
+        tokio::spawn(async move {
+            // The client closing or resetting the connection isn't a server error
+            let _ = conn.await;
+        });
+    }
+    // Stop accepting new connections while the others are drained
+    drop(listener);
+    graceful.shutdown().await;
+}
@@ -0,0 +1,166 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use aiscript_vm::{ReturnValue, Vm, VmError};
+use axum::{
+    body::{Body, Bytes},
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+};
+use hyper::body::Frame;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{config::Config, json_body::response_json};
+
+// The chunks buffered ahead of a slow client, the script waits once it's full.
+const BUFFERED_CHUNKS: usize = 16;
+
+/// The body of a handler returning a generator, a chunk is sent as soon as
+/// a value is yielded. The connection is aborted if the generator fails.
+pub(crate) struct StreamBody {
+    chunks: mpsc::Receiver<Result<Bytes, VmError>>,
+    // Whether the values are sent as Server-Sent Events.
+    event_stream: bool,
+}
+
+impl hyper::body::Body for StreamBody {
+    type Data = Bytes;
+    type Error = VmError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.chunks
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data)))
+    }
+}
+
+impl IntoResponse for StreamBody {
+    fn into_response(self) -> Response {
+        let content_type = if self.event_stream {
+            "text/event-stream"
+        } else {
+            "text/plain; charset=utf-8"
+        };
+        ([(header::CONTENT_TYPE, content_type)], Body::new(self)).into_response()
+    }
+}
+
+// Strings are sent as is, the other values as a line of JSON.
+fn chunk(value: ReturnValue, big_int_strings: bool) -> Bytes {
+    match value {
+        ReturnValue::String(s) => Bytes::from(s),
+        value => {
+            let value = response_json(
+                serde_json::to_value(value).unwrap_or_default(),
+                big_int_strings,
+            );
+            let mut line = serde_json::to_vec(&value).unwrap_or_default();
+            line.push(b'\n');
+            Bytes::from(line)
+        }
+    }
+}
+
+// A Server-Sent Event of the value, a line of the string or JSON is a data line.
+fn event(value: ReturnValue, big_int_strings: bool) -> Bytes {
+    let data = match value {
+        ReturnValue::String(s) => s,
+        value => response_json(
+            serde_json::to_value(value).unwrap_or_default(),
+            big_int_strings,
+        )
+        .to_string(),
+    };
+    let mut event = String::new();
+    for line in data.split('\n') {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    event.push('\n');
+    Bytes::from(event)
+}
+
+/// Whether the client asks for Server-Sent Events, e.g. an `EventSource`.
+pub(crate) fn accepts_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"))
+}
+
+/// Hand the body to the request processor, then send the values of the
+/// generator returned by the handler until it returns or the client leaves,
+/// as Server-Sent Events if `event_stream`.
+pub(crate) fn stream(
+    vm: &mut Vm,
+    sender: oneshot::Sender<Response>,
+    event_stream: bool,
+    request_id: &str,
+) {
+    let (chunks, receiver) = mpsc::channel(BUFFERED_CHUNKS);
+    let body = StreamBody {
+        chunks: receiver,
+        event_stream,
+    };
+    if sender.send(body.into_response()).is_err() {
+        // The request has already been answered, e.g. on timeout
+        return;
+    }
+    let big_int_strings = Config::get().json.big_int_strings;
+    loop {
+        let chunk = match vm.next_streamed() {
+            Ok(Some(value)) if event_stream => Ok(event(value, big_int_strings)),
+            Ok(Some(value)) => Ok(chunk(value, big_int_strings)),
+            Ok(None) => return,
+            Err(err) => {
+                eprintln!("[{request_id}] {err}");
+                Err(err)
+            }
+        };
+        let failed = chunk.is_err();
+        if chunks.blocking_send(chunk).is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk() {
+        assert_eq!(chunk(ReturnValue::String("Once".into()), false), "Once");
+        assert_eq!(chunk(ReturnValue::Int(1), false), "1\n");
+        assert_eq!(
+            chunk(ReturnValue::Array(vec![serde_json::json!("a")]), false),
+            "[\"a\"]\n"
+        );
+        assert_eq!(
+            chunk(ReturnValue::Int(i64::MAX), false),
+            "9223372036854775807\n"
+        );
+        assert_eq!(
+            chunk(ReturnValue::Int(i64::MAX), true),
+            "\"9223372036854775807\"\n"
+        );
+    }
+
+    #[test]
+    fn test_event() {
+        assert_eq!(
+            event(ReturnValue::String("a\nb".into()), false),
+            "data: a\ndata: b\n\n"
+        );
+        assert_eq!(
+            event(ReturnValue::Object([("n".into(), 1.into())].into()), true),
+            "data: {\"n\":1}\n\n"
+        );
+    }
+}
@@ -0,0 +1,196 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+};
+
+use tokio::{
+    runtime::Handle,
+    sync::oneshot,
+    task::{self, JoinHandle},
+};
+
+use crate::config::WorkersConfig;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Created by the first build of the routes, a reload doesn't resize it.
+static POOL: OnceLock<WorkerPool> = OnceLock::new();
+
+/// A fixed number of threads running the handler VMs, the requests wait in
+/// a bounded queue for a free thread and are rejected once it's full,
+/// instead of piling up on the unbounded blocking threads of tokio.
+pub(crate) struct WorkerPool {
+    sender: mpsc::SyncSender<Job>,
+    threads: usize,
+    queued: Arc<AtomicUsize>,
+    busy: Arc<AtomicUsize>,
+    rejected: AtomicU64,
+}
+
+/// A snapshot of the worker pool, see [`worker_stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct WorkerStats {
+    pub threads: usize,
+    pub busy: usize,
+    pub queued: usize,
+    pub rejected: u64,
+}
+
+impl WorkerPool {
+    fn new(threads: usize, queue: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let queued = Arc::new(AtomicUsize::new(0));
+        let busy = Arc::new(AtomicUsize::new(0));
+        // The handlers block on the runtime for their IO, like on the
+        // blocking threads of tokio
+        let runtime = Handle::try_current().ok();
+        for i in 0..threads {
+            let (receiver, queued, busy) = (receiver.clone(), queued.clone(), busy.clone());
+            let runtime = runtime.clone();
+            thread::Builder::new()
+                .name(format!("aiscript-worker-{i}"))
+                .spawn(move || {
+                    let _runtime = runtime.as_ref().map(Handle::enter);
+                    loop {
+                        let job = match receiver.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => return,
+                        };
+                        queued.fetch_sub(1, Ordering::AcqRel);
+                        busy.fetch_add(1, Ordering::AcqRel);
+                        // A panicking handler fails its request, not the worker
+                        let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                        busy.fetch_sub(1, Ordering::AcqRel);
+                    }
+                })
+                .expect("failed to spawn a worker thread");
+        }
+        Self {
+            sender,
+            threads,
+            queued,
+            busy,
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    /// Run the function on a worker, None if all the workers are busy and
+    /// the queue is full.
+    fn spawn<T, F>(&self, function: F) -> Option<JoinHandle<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move || {
+            let _ = sender.send(function());
+        });
+        // Counted before it's sent so a worker never dequeues it uncounted
+        self.queued.fetch_add(1, Ordering::AcqRel);
+        if self.sender.try_send(job).is_err() {
+            self.queued.fetch_sub(1, Ordering::AcqRel);
+            self.rejected.fetch_add(1, Ordering::AcqRel);
+            return None;
+        }
+        // The result is dropped with a panicking job, which panics the
+        // task like the panics of `spawn_blocking()`
+        Some(tokio::spawn(async move {
+            receiver.await.expect("the handler panicked")
+        }))
+    }
+
+    fn stats(&self) -> WorkerStats {
+        WorkerStats {
+            threads: self.threads,
+            busy: self.busy.load(Ordering::Acquire),
+            queued: self.queued.load(Ordering::Acquire),
+            rejected: self.rejected.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// Start the worker pool of `[workers]`, the handlers run on it from now on.
+pub(crate) fn install(config: &WorkersConfig) {
+    if let Some(blocking) = config.blocking {
+        aiscript_vm::set_blocking_limit(blocking);
+    }
+    POOL.get_or_init(|| {
+        let threads = config
+            .threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, |threads| threads.get()));
+        WorkerPool::new(threads.max(1), config.queue)
+    });
+}
+
+/// Run a handler on the worker pool, or on the blocking threads of tokio
+/// without `[workers]`. None if the pool is overloaded.
+pub(crate) fn spawn_handler<T, F>(function: F) -> Option<JoinHandle<T>>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match POOL.get() {
+        Some(pool) => pool.spawn(function),
+        None => Some(task::spawn_blocking(function)),
+    }
+}
+
+/// The state of the worker pool, None without `[workers]`.
+pub(crate) fn worker_stats() -> Option<WorkerStats> {
+    POOL.get().map(WorkerPool::stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Barrier;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_queue_and_backpressure() {
+        let pool = WorkerPool::new(1, 1);
+        let (started, release) = (Arc::new(Barrier::new(2)), Arc::new(Barrier::new(2)));
+        let running = pool
+            .spawn({
+                let (started, release) = (started.clone(), release.clone());
+                move || {
+                    started.wait();
+                    release.wait();
+                    1
+                }
+            })
+            .unwrap();
+        started.wait();
+        let queued = pool.spawn(|| 2).unwrap();
+        assert!(pool.spawn(|| 3).is_none());
+        assert_eq!(
+            pool.stats(),
+            WorkerStats {
+                threads: 1,
+                busy: 1,
+                queued: 1,
+                rejected: 1,
+            }
+        );
+
+        release.wait();
+        assert_eq!(running.await.unwrap(), 1);
+        assert_eq!(queued.await.unwrap(), 2);
+        assert!(pool.spawn(|| panic!("boom")).unwrap().await.is_err());
+        // The worker survives the panic
+        assert_eq!(pool.spawn(|| 4).unwrap().await.unwrap(), 4);
+        // The IO of the handlers runs on the runtime
+        assert!(
+            pool.spawn(|| Handle::try_current().is_ok())
+                .unwrap()
+                .await
+                .unwrap()
+        );
+    }
+}
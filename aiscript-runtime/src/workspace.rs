@@ -0,0 +1,165 @@
+//! A workspace of several projects in one repository, declared by a
+//! `workspace.toml` at its root:
+//!
+//! ```toml
+//! [workspace]
+//! members = ["services/api", "services/worker"]
+//! ```
+//!
+//! Each member is a project with its own project.toml and routes, named
+//! by its directory, e.g. `aiscript serve --member api`. The script modules
+//! of the `lib/` at the root are shared by the members as `use lib.<name>`.
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+pub const WORKSPACE_FILE: &str = "workspace.toml";
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceFile {
+    workspace: WorkspaceSection,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorkspaceSection {
+    #[serde(default)]
+    members: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub members: Vec<Member>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Member {
+    pub name: String,
+    /// The directory of the member, relative to the root.
+    pub path: PathBuf,
+}
+
+impl Workspace {
+    /// Read the workspace of the directory, the members are checked to be
+    /// projects with distinct names.
+    pub fn load(root: &Path) -> Result<Self, String> {
+        let file = root.join(WORKSPACE_FILE);
+        let content = fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        let WorkspaceFile { workspace } =
+            toml::from_str(&content).map_err(|e| format!("Invalid {}: {}", file.display(), e))?;
+
+        let mut members: Vec<Member> = Vec::new();
+        for path in workspace.members {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                return Err(format!("Invalid member path '{}'", path.display()));
+            };
+            if !root.join(&path).is_dir() {
+                return Err(format!(
+                    "The member '{}' isn't a directory of the workspace",
+                    path.display()
+                ));
+            }
+            if let Some(other) = members.iter().find(|member| member.name == name) {
+                return Err(format!(
+                    "The members '{}' and '{}' have the same name '{name}'",
+                    other.path.display(),
+                    path.display()
+                ));
+            }
+            members.push(Member {
+                name: name.to_owned(),
+                path,
+            });
+        }
+        Ok(Workspace {
+            root: root.to_owned(),
+            members,
+        })
+    }
+
+    /// The workspace of the directory or of its closest parent with a
+    /// workspace.toml, None outside of a workspace.
+    pub fn find(dir: &Path) -> Option<Result<Self, String>> {
+        let dir = dir.canonicalize().ok()?;
+        dir.ancestors()
+            .find(|dir| dir.join(WORKSPACE_FILE).is_file())
+            .map(Workspace::load)
+    }
+
+    /// The workspace of the current directory, see [`Workspace::find`].
+    pub fn current() -> Option<Result<Self, String>> {
+        Workspace::find(&env::current_dir().ok()?)
+    }
+
+    pub fn member(&self, name: &str) -> Result<&Member, String> {
+        self.members
+            .iter()
+            .find(|member| member.name == name)
+            .ok_or_else(|| {
+                let names: Vec<_> = self.members.iter().map(|m| m.name.as_str()).collect();
+                format!(
+                    "No member '{name}' in the workspace, the members are: {}",
+                    names.join(", ")
+                )
+            })
+    }
+
+    pub fn member_dir(&self, member: &Member) -> PathBuf {
+        self.root.join(&member.path)
+    }
+
+    /// Whether the directory is the root of the workspace rather than one
+    /// of its members.
+    pub fn is_root(&self, dir: &Path) -> bool {
+        dir.canonicalize().is_ok_and(|dir| dir == self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace() {
+        let root = env::temp_dir().join("aiscript_test_workspace");
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("services/api/routes")).unwrap();
+        fs::create_dir_all(root.join("worker")).unwrap();
+        fs::write(
+            root.join(WORKSPACE_FILE),
+            "[workspace]\nmembers = [\"services/api\", \"worker\"]\n",
+        )
+        .unwrap();
+
+        let workspace = Workspace::find(&root.join("services/api/routes"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(workspace.root, root.canonicalize().unwrap());
+        assert_eq!(
+            workspace.member("api").unwrap(),
+            &Member {
+                name: "api".into(),
+                path: "services/api".into()
+            }
+        );
+        assert!(workspace.is_root(&root));
+        assert!(!workspace.is_root(&root.join("worker")));
+        let err = workspace.member("web").unwrap_err();
+        assert!(err.ends_with("the members are: api, worker"), "{err}");
+
+        fs::write(
+            root.join(WORKSPACE_FILE),
+            "[workspace]\nmembers = [\"services/api\", \"api\"]\n",
+        )
+        .unwrap();
+        fs::create_dir_all(root.join("api")).unwrap();
+        let err = Workspace::load(&root).unwrap_err();
+        assert!(err.contains("have the same name 'api'"), "{err}");
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -6,12 +6,20 @@ use openai_api_rs::v1::types::JSONSchemaType;
 use openai_api_rs::v1::{
     chat_completion::{
         ChatCompletionMessage, ChatCompletionMessageForResponse, ChatCompletionRequest, Content,
-        MessageRole, Tool, ToolCall, ToolChoiceType, ToolType,
+        ContentType, ImageUrl, ImageUrlType, MessageRole, Tool, ToolCall, ToolChoiceType, ToolType,
     },
     types::{self, FunctionParameters, JSONSchemaDefine},
 };
 use tokio::runtime::Handle;
 
+use super::{OpenApiTools, PromptHistory};
+#[cfg(not(feature = "ai_test"))]
+use super::{
+    TraceEvent,
+    openapi::{self, Operation},
+};
+#[cfg(not(feature = "ai_test"))]
+use crate::vm::with_deadline;
 use crate::{
     Chunk, Value,
     ast::{Expr, FnDef, Literal},
@@ -19,16 +27,20 @@ use crate::{
     object::{Function, Object, Parameter},
     string::InternedString,
     ty::PrimitiveType,
-    vm::{Context, State},
+    vm::{Context, State, VmError},
 };
 
 #[derive(Collect)]
 #[collect(no_drop)]
 pub struct Agent<'gc> {
     pub name: InternedString<'gc>,
+    pub doc: Option<InternedString<'gc>>,
     pub instructions: InternedString<'gc>,
+    // The path of the instructions read with `file("path")`.
+    pub instructions_file: Option<InternedString<'gc>>,
     pub model: InternedString<'gc>,
     pub tools: HashMap<String, FnDef>,
+    pub openapi: Vec<OpenApiTools>,
     pub tool_choice: ToolChoice,
     pub methods: HashMap<InternedString<'gc>, Gc<'gc, Function<'gc>>>,
 }
@@ -39,7 +51,7 @@ pub struct Agent<'gc> {
 // required means the model must call one or more tools.
 // Specifying a particular tool via {"type": "function", "function": {"name": "my_function"}}
 // forces the model to call that tool.
-#[derive(Debug, Collect)]
+#[derive(Debug, Clone, Copy, Collect)]
 #[collect(no_drop)]
 pub enum ToolChoice {
     None,
@@ -54,6 +66,122 @@ struct Response<'gc> {
     messages: Vec<ChatCompletionMessage>,
 }
 
+// The content of a tool function result sent back to the model.
+// Strings are sent as plain text, explicit content objects
+// ({type: "text" | "image" | "error", ...}) keep their type,
+// error values are reported as errors and any other value is
+// serialized as JSON.
+#[cfg(not(feature = "ai_test"))]
+#[derive(Debug, PartialEq)]
+enum ToolContent {
+    Text(String),
+    Json(serde_json::Value),
+    Image(String),
+    Error(serde_json::Value),
+}
+
+#[cfg(not(feature = "ai_test"))]
+impl ToolContent {
+    fn from_value(value: Value<'_>) -> Self {
+        match value {
+            Value::String(s) => ToolContent::Text(s.to_string()),
+            Value::IoString(s) => ToolContent::Text(s.to_string()),
+            Value::Object(obj) => {
+                let obj = obj.borrow();
+                let field = |name: &str| {
+                    obj.fields
+                        .iter()
+                        .find(|(key, _)| **key == name)
+                        .map(|(_, value)| value.to_string())
+                };
+                match field("type").as_deref() {
+                    Some("text") => ToolContent::Text(field("text").unwrap_or_default()),
+                    Some("image") => ToolContent::Image(field("url").unwrap_or_default()),
+                    Some("error") => {
+                        ToolContent::Error(field("message").unwrap_or_default().into())
+                    }
+                    _ => ToolContent::Json(value.to_serde_value()),
+                }
+            }
+            v if v.is_error() => match v.to_serde_value() {
+                serde_json::Value::Null => ToolContent::Error(v.to_string().into()),
+                detail => ToolContent::Error(detail),
+            },
+            v => ToolContent::Json(v.to_serde_value()),
+        }
+    }
+
+    // The response of an API operation, or its recorded result in replay mode.
+    fn from_json(result: Result<serde_json::Value, serde_json::Value>) -> Self {
+        match result {
+            Ok(serde_json::Value::String(text)) => ToolContent::Text(text),
+            Ok(json) => ToolContent::Json(json),
+            Err(error) => ToolContent::Error(error),
+        }
+    }
+
+    fn to_serde_value(&self) -> serde_json::Value {
+        match self {
+            ToolContent::Text(text) => text.clone().into(),
+            ToolContent::Json(json) => json.clone(),
+            ToolContent::Image(url) => serde_json::json!({ "type": "image", "url": url }),
+            ToolContent::Error(error) => serde_json::json!({ "error": error }),
+        }
+    }
+
+    // Tool messages only accept text, so non-text content is described
+    // in the tool message and the image itself is attached as a follow-up
+    // user message.
+    fn into_messages(self, tool_call: &ToolCall) -> Vec<ChatCompletionMessage> {
+        let tool_message = |content: String| ChatCompletionMessage {
+            role: MessageRole::tool,
+            content: Content::Text(content),
+            name: tool_call.function.name.clone(),
+            tool_calls: None,
+            tool_call_id: Some(tool_call.id.clone()),
+        };
+        match self {
+            ToolContent::Text(text) => vec![tool_message(text)],
+            ToolContent::Json(json) => vec![tool_message(json.to_string())],
+            ToolContent::Error(error) => {
+                vec![tool_message(
+                    serde_json::json!({ "error": error }).to_string(),
+                )]
+            }
+            ToolContent::Image(url) => vec![
+                tool_message(serde_json::json!({ "type": "image", "url": url }).to_string()),
+                ChatCompletionMessage {
+                    role: MessageRole::user,
+                    content: Content::ImageUrl(vec![ImageUrl {
+                        r#type: ContentType::image_url,
+                        text: None,
+                        image_url: Some(ImageUrlType { url }),
+                    }]),
+                    name: None,
+                    tool_calls: None,
+                    tool_call_id: None,
+                },
+            ],
+        }
+    }
+}
+
+// The JSON arguments of a tool call as the positional arguments of the
+// tool function, they keep their JSON types.
+#[cfg(not(feature = "ai_test"))]
+fn tool_arguments<'gc>(
+    ctx: Context<'gc>,
+    tool_def: &FnDef,
+    arguments: Option<&str>,
+) -> Result<Vec<Value<'gc>>, serde_json::Error> {
+    let arguments = serde_json::from_str::<serde_json::Value>(arguments.unwrap_or("{}"))?;
+    Ok(tool_def
+        .params
+        .keys()
+        .filter_map(|key| arguments.get(key).map(|v| Value::from_serde_value(ctx, v)))
+        .collect())
+}
+
 fn make_response_object<'gc>(
     state: &mut State<'gc>,
     agent: Gc<'gc, Agent<'gc>>,
@@ -78,20 +206,34 @@ fn agent_methods<'gc>(ctx: &Context<'gc>) -> HashMap<InternedString<'gc>, Gc<'gc
             ctx,
             Function {
                 arity: 1,
-                max_arity: 2,
-                params: [("input", Value::Nil), ("debug", Value::Boolean(false))]
-                    .into_iter()
-                    .enumerate()
-                    .map(|(i, (name, default))| {
-                        (
-                            InternedString::from_static(ctx, name),
-                            Parameter::new(i as u8, default),
-                        )
-                    })
-                    .collect(),
+                max_arity: 3,
+                keyword_only: 0,
+                variadic: false,
+                kwargs: false,
+                params: [
+                    ("input", Value::Nil),
+                    ("debug", Value::Boolean(false)),
+                    ("history", Value::Nil),
+                ]
+                .into_iter()
+                .enumerate()
+                .map(|(i, (name, default))| {
+                    let param = Parameter::new(i as u8, default);
+                    (
+                        InternedString::from_static(ctx, name),
+                        if i == 0 { param.required() } else { param },
+                    )
+                })
+                .collect(),
                 chunk: Chunk::new(),
                 name: None,
                 upvalues: Vec::new(),
+                capture_by_value: false,
+                module: None,
+                is_generator: false,
+                doc: None,
+                signature: None,
+                attributes: Default::default(),
             },
         ),
     )]
@@ -103,21 +245,38 @@ impl<'gc> Agent<'gc> {
     pub fn new(ctx: &Context<'gc>, name: InternedString<'gc>) -> Self {
         Agent {
             name,
+            doc: None,
             instructions: InternedString::from_static(ctx, ""),
+            instructions_file: None,
             model: InternedString::from_static(ctx, "gpt-4"),
             tools: HashMap::new(),
+            openapi: Vec::new(),
             tool_choice: ToolChoice::Auto,
             methods: agent_methods(ctx),
         }
     }
 
-    pub fn parse_instructions(mut self, fields: &HashMap<&'gc str, Expr<'gc>>) -> Self {
-        if let Some(Expr::Literal {
-            value: Literal::String(value),
-            ..
-        }) = fields.get("instructions")
-        {
-            self.instructions = *value;
+    pub fn parse_instructions<F>(mut self, fields: &HashMap<&'gc str, Expr<'gc>>, mut f: F) -> Self
+    where
+        F: FnMut(Token<'gc>, InternedString<'gc>) -> Option<InternedString<'gc>>,
+    {
+        match fields.get("instructions") {
+            Some(Expr::Literal {
+                value: Literal::String(value),
+                ..
+            }) => {
+                self.instructions = *value;
+            }
+            // instructions: file("prompts/triage.md")
+            Some(expr) => {
+                if let Some((token, path)) = expr.as_file_path()
+                    && let Some(instructions) = f(token, path)
+                {
+                    self.instructions = instructions;
+                    self.instructions_file = Some(path);
+                }
+            }
+            None => {}
         }
         self
     }
@@ -135,17 +294,44 @@ impl<'gc> Agent<'gc> {
 
     pub fn parse_tools<F>(mut self, fields: &HashMap<&'gc str, Expr<'gc>>, mut f: F) -> Self
     where
-        F: FnMut(&Token<'gc>) -> Option<FnDef>,
+        F: FnMut(ToolRef<'_, 'gc>) -> Option<FnDef>,
     {
         if let Some(Expr::List { elements, .. }) = fields.get("tools") {
             for element in elements {
                 match element {
+                    // tools: [web_search]
                     Expr::Variable { name, .. } => {
-                        if let Some(fn_def) = f(name) {
+                        if let Some(fn_def) = f(ToolRef::Function { module: None, name }) {
                             self.tools.insert(name.lexeme.to_owned(), fn_def);
                         }
                     }
-                    _ => panic!("Expected string literal"),
+                    // tools: [search.web_search]
+                    Expr::Get { object, name, .. } => {
+                        let Expr::Variable { name: module, .. } = &**object else {
+                            f(ToolRef::Invalid(element));
+                            continue;
+                        };
+                        let tool = ToolRef::Function {
+                            module: Some(module),
+                            name,
+                        };
+                        if let Some(fn_def) = f(tool) {
+                            self.tools.insert(name.lexeme.to_owned(), fn_def);
+                        }
+                    }
+                    // tools: [tools_from_openapi("openapi.json", allow=["getUser"])]
+                    Expr::Call { .. } => match element.as_openapi_tools() {
+                        Some((spec, allow)) => self.openapi.push(OpenApiTools {
+                            spec: spec.to_string(),
+                            allow: allow.iter().map(|id| id.to_string()).collect(),
+                        }),
+                        None => {
+                            f(ToolRef::Invalid(element));
+                        }
+                    },
+                    _ => {
+                        f(ToolRef::Invalid(element));
+                    }
                 }
             }
         }
@@ -153,6 +339,17 @@ impl<'gc> Agent<'gc> {
     }
 }
 
+/// A tool in the `tools` of an agent declaration, resolved by the compiler.
+pub enum ToolRef<'a, 'gc> {
+    // A function in scope, or imported from the module: [search.web_search]
+    Function {
+        module: Option<&'a Token<'gc>>,
+        name: &'a Token<'gc>,
+    },
+    // An element which doesn't name a function
+    Invalid(&'a Expr<'gc>),
+}
+
 #[cfg(not(feature = "ai_test"))]
 impl<'gc> Agent<'gc> {
     fn get_instruction_message(&self) -> ChatCompletionMessage {
@@ -165,8 +362,12 @@ impl<'gc> Agent<'gc> {
         }
     }
 
-    fn get_tools(&self) -> Vec<Tool> {
-        let mut tool_calls = Vec::new();
+    fn get_tools(&self, operations: &HashMap<String, Operation>) -> Vec<Tool> {
+        let mut tool_calls = operations
+            .values()
+            .filter(|operation| !self.tools.contains_key(&operation.id))
+            .map(Operation::to_tool)
+            .collect::<Vec<_>>();
         for (name, fn_def) in &self.tools {
             let properties = fn_def
                 .params
@@ -198,51 +399,89 @@ impl<'gc> Agent<'gc> {
         tool_calls
     }
 
-    fn handle_tool_call(
+    async fn handle_tool_call(
         &self,
         state: &mut State<'gc>,
         tool_calls: &Option<Vec<ToolCall>>,
+        operations: &HashMap<String, Operation>,
     ) -> Result<Response<'gc>, String> {
         let mut response = Response::default();
         for tool_call in tool_calls.as_ref().unwrap() {
             let name = tool_call.function.name.as_ref().unwrap();
             if let Some(tool_def) = self.tools.get(name) {
-                let arguments = serde_json::from_str::<serde_json::Value>(
-                    tool_call.function.arguments.as_ref().unwrap(),
+                let params = tool_arguments(
+                    state.get_context(),
+                    tool_def,
+                    tool_call.function.arguments.as_deref(),
                 )
-                .unwrap();
-                // Pass params as positional arguments
-                let params = tool_def
-                    .params
-                    .keys()
-                    .filter_map(|key| {
-                        arguments
-                            .get(key)
-                            .map(|v| Value::String(state.intern(v.as_str().unwrap().as_bytes())))
-                    })
-                    .collect::<Vec<_>>();
-                let result = state
-                    .eval_function_with_id(tool_def.chunk_id, &params)
-                    .unwrap();
-                // println!(
-                //     "call tool {name} params: {:?}, result: {}",
-                //     params.iter().map(|i| format!("{i}")).collect::<Vec<_>>(),
-                //     result
-                // );
-                let content = if let Value::Agent(agent) = result {
-                    let agent_name = agent.name;
-                    response.agent = state.get_global(agent_name).map(|v| v.as_agent().unwrap());
-                    format!("{{\"assistant\": {}}}", agent_name)
-                } else {
-                    result.to_string()
+                .map_err(|err| format!("Warning: invalid arguments of tool {name}: {err}"))?;
+                let result = match &tool_def.module {
+                    Some(module) => {
+                        let module = state.intern(module.as_bytes());
+                        let export = state.intern(name.as_bytes());
+                        match state.module_manager.get_export(module, export) {
+                            Some(Value::Closure(closure)) => {
+                                state.try_eval_closure(closure, &params)
+                            }
+                            _ => {
+                                return Err(format!(
+                                    "Warning: tool function {name} is not exported from module {module}"
+                                ));
+                            }
+                        }
+                    }
+                    None => state.try_eval_function_with_id(tool_def.chunk_id, &params),
                 };
-                response.messages.push(ChatCompletionMessage {
-                    role: MessageRole::tool,
-                    content: Content::Text(content),
-                    name: tool_call.function.name.clone(),
-                    tool_calls: None,
-                    tool_call_id: Some(tool_call.id.clone()),
-                });
+                let content = match result {
+                    Ok(Value::Agent(agent)) => {
+                        let agent_name = agent.name;
+                        response.agent =
+                            state.get_global(agent_name).and_then(|v| v.as_agent().ok());
+                        ToolContent::Json(
+                            serde_json::json!({ "assistant": agent_name.to_string() }),
+                        )
+                    }
+                    Ok(result) => ToolContent::from_value(result),
+                    // The failure is the result of the call, the model can retry or explain it
+                    Err(err) => ToolContent::Error(err.to_string().into()),
+                };
+                if let Some(trace) = state.ai_trace.as_mut() {
+                    let result = content.to_serde_value();
+                    if trace.is_replay() {
+                        trace.replay_tool(name, &result)?;
+                    } else {
+                        trace.record_event(TraceEvent::Tool {
+                            name: name.clone(),
+                            result,
+                        });
+                    }
+                }
+                response.messages.extend(content.into_messages(tool_call));
+            } else if let Some(operation) = operations.get(name) {
+                let content = match state.ai_trace.as_mut() {
+                    // Use the recorded response instead of calling the API again
+                    Some(trace) if trace.is_replay() => {
+                        ToolContent::from_json(Ok(trace.replay_tool_result(name)?))
+                    }
+                    trace => {
+                        let arguments = serde_json::from_str::<serde_json::Value>(
+                            tool_call.function.arguments.as_deref().unwrap_or("{}"),
+                        )
+                        .unwrap_or_default();
+                        let result = with_deadline(state.deadline, operation.call(&arguments))
+                            .await
+                            .map_err(|err| err.to_string())?;
+                        let content = ToolContent::from_json(result);
+                        if let Some(trace) = trace {
+                            trace.record_event(TraceEvent::Tool {
+                                name: name.clone(),
+                                result: content.to_serde_value(),
+                            });
+                        }
+                        content
+                    }
+                };
+                response.messages.extend(content.into_messages(tool_call));
             } else {
                 return Err(format!("Warning: unknow tool function: {name}"));
             }
@@ -257,18 +496,22 @@ pub async fn _run_agent<'gc>(
     state: &mut State<'gc>,
     agent: Gc<'gc, Agent<'gc>>,
     args: Vec<Value<'gc>>,
-) -> Value<'gc> {
+) -> Result<Value<'gc>, VmError> {
     let message = args[0];
     let debug = args[1].as_boolean();
+    let history = parse_history(args[2])?;
     println!("debug: {debug}");
-    make_response_object(
-        state,
-        agent,
-        format!(
-            "input: {},instructions: {}, model: {}, tools: {:?}",
-            message, agent.instructions, agent.model, agent.tools
-        ),
-    )
+    let mut message = format!(
+        "input: {},instructions: {}, model: {}, tools: {:?}",
+        message, agent.instructions, agent.model, agent.tools
+    );
+    if !agent.openapi.is_empty() {
+        message.push_str(&format!(", openapi: {:?}", agent.openapi));
+    }
+    if !history.is_empty() {
+        message.push_str(&format!(", history: {} messages", history.len()));
+    }
+    Ok(make_response_object(state, agent, message))
 }
 
 #[cfg(not(feature = "ai_test"))]
@@ -276,10 +519,24 @@ pub async fn _run_agent<'gc>(
     state: &mut State<'gc>,
     mut agent: Gc<'gc, Agent<'gc>>,
     args: Vec<Value<'gc>>,
-) -> Value<'gc> {
+) -> Result<Value<'gc>, VmError> {
     let message = args[0];
     let debug = args[1].as_boolean();
-    let mut history = Vec::new();
+    // The previous messages of the conversation, e.g. sent by the client of a chat endpoint
+    let mut history = parse_history(args[2])?
+        .into_iter()
+        .map(|(is_user, content)| ChatCompletionMessage {
+            role: if is_user {
+                MessageRole::user
+            } else {
+                MessageRole::assistant
+            },
+            content: Content::Text(content),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        })
+        .collect::<Vec<_>>();
     history.push(ChatCompletionMessage {
         role: MessageRole::user,
         content: Content::Text(message.to_string()),
@@ -287,57 +544,149 @@ pub async fn _run_agent<'gc>(
         tool_calls: None,
         tool_call_id: None,
     });
-    let model_config = state.ai_config.get_model_config(None).unwrap();
-    let mut client = super::openai_client(&model_config);
-    let model = model_config.model.unwrap();
+    let model_config = state.model_config(None)?;
+    let mut client = super::openai_client(&model_config, state.request_id.as_deref());
+    let budget = state.ai_budget();
+    let model = model_config.model.clone().unwrap();
+    let mut operations =
+        with_deadline(state.deadline, openapi::load_operations(&agent.openapi)).await??;
     loop {
         let mut messages = vec![agent.get_instruction_message()];
         messages.extend(history.clone());
         let mut req = ChatCompletionRequest::new(model.0.clone(), messages);
-        let tools = agent.get_tools();
+        let tools = agent.get_tools(&operations);
         if !tools.is_empty() {
             req = req
-                .tools(agent.get_tools())
+                .tools(tools)
                 .tool_choice(ToolChoiceType::Auto)
                 .parallel_tool_calls(true);
         }
         if debug {
             println!("Request: {}", serde_json::to_string(&req).unwrap());
         }
-        let result = client.chat_completion(req).await.unwrap();
-        let response = &result.choices[0].message;
+        let response = match state.ai_trace.as_mut() {
+            Some(trace) if trace.is_replay() => match trace.replay_completion() {
+                Ok(message) => serde_json::from_value(message).map_err(|err| {
+                    VmError::RuntimeError(format!("Invalid completion in the trace: {err}"))
+                })?,
+                Err(message) => return Ok(make_response_object(state, agent, message)),
+            },
+            trace => {
+                let mut result = super::chat_completion(
+                    &mut client,
+                    &model_config,
+                    budget.as_ref(),
+                    state.deadline,
+                    req,
+                )
+                .await?;
+                let message = result.choices.swap_remove(0).message;
+                if let Some(trace) = trace {
+                    trace.record_event(TraceEvent::Completion {
+                        message: serde_json::to_value(&message).unwrap(),
+                    });
+                }
+                message
+            }
+        };
+        let response = &response;
         if debug {
             println!("Response: {}", serde_json::to_string(&response).unwrap());
         }
         history.push(convert_chat_response_message(response));
         if response.tool_calls.is_none() {
-            return make_response_object(
+            return Ok(make_response_object(
                 state,
                 agent,
                 response.content.clone().unwrap_or_default(),
-            );
+            ));
         } else {
-            match agent.handle_tool_call(state, &response.tool_calls) {
+            match agent
+                .handle_tool_call(state, &response.tool_calls, &operations)
+                .await
+            {
                 Ok(response) => {
                     if let Some(handoff_agent) = response.agent {
                         agent = handoff_agent;
+                        // The agent handed off to has its own API tools
+                        operations =
+                            with_deadline(state.deadline, openapi::load_operations(&agent.openapi))
+                                .await??;
                     }
                     // if debug {
                     //     println!("tool function call response: {:?}", response);
                     // }
                     history.extend(response.messages);
                 }
-                Err(message) => return make_response_object(state, agent, message),
+                Err(message) => return Ok(make_response_object(state, agent, message)),
             }
         }
     }
 }
 
+// The messages of the `history` argument of `run()`, a list of
+// `{role, content}` objects whose role is "user" or "assistant". The flag
+// tells whether a message is from the user.
+fn parse_history(history: Value) -> Result<Vec<(bool, String)>, VmError> {
+    let messages = match history {
+        Value::Nil => return Ok(Vec::new()),
+        Value::List(list) => list.borrow().data.clone(),
+        _ => {
+            return Err(VmError::RuntimeError(
+                "The history of an agent run must be a list of {role, content} objects.".into(),
+            ));
+        }
+    };
+    messages
+        .into_iter()
+        .map(|message| {
+            let Value::Object(message) = message else {
+                return Err(VmError::RuntimeError(
+                    "The history of an agent run must be a list of {role, content} objects.".into(),
+                ));
+            };
+            let (mut role, mut content) = (None, None);
+            for (key, value) in &message.borrow().fields {
+                match key.to_str().unwrap_or_default() {
+                    "role" => role = Some(value.to_string()),
+                    "content" => content = Some(value.as_string()?.to_string()),
+                    _ => {}
+                }
+            }
+            let is_user = match role.as_deref() {
+                Some("user") => true,
+                Some("assistant") => false,
+                role => {
+                    return Err(VmError::RuntimeError(format!(
+                        "The role of a history message must be \"user\" or \"assistant\", got \"{}\".",
+                        role.unwrap_or_default()
+                    )));
+                }
+            };
+            Ok((is_user, content.unwrap_or_default()))
+        })
+        .collect()
+}
+
 pub fn run_agent<'gc>(
     state: &mut State<'gc>,
     agent: Gc<'gc, Agent<'gc>>,
     args: Vec<Value<'gc>>,
-) -> Value<'gc> {
+) -> Result<Value<'gc>, VmError> {
+    if let Some(dir) = &state.ai_config.prompt_history {
+        // Failing to log the prompt version shouldn't fail the run
+        let history = PromptHistory::new(dir);
+        if let Err(err) = history.serve(
+            &agent.name.to_string(),
+            &agent.instructions.to_string(),
+            state.ai_budget_scope.route.as_deref(),
+        ) {
+            eprintln!(
+                "Failed to record the instructions of agent '{}': {err}",
+                agent.name
+            );
+        }
+    }
     if Handle::try_current().is_ok() {
         // We're in an async context, use await
         Handle::current().block_on(async { _run_agent(state, agent, args).await })
@@ -368,3 +717,118 @@ fn convert_chat_response_message(m: &ChatCompletionMessageForResponse) -> ChatCo
         tool_call_id: None,
     }
 }
+
+#[cfg(all(test, not(feature = "ai_test")))]
+mod tests {
+    use aiscript_arena::{Gc, RefLock, arena::rootless_mutate};
+    use indexmap::IndexMap;
+    use openai_api_rs::v1::chat_completion::ToolCallFunction;
+
+    use super::*;
+    use crate::string::InternedStringSet;
+
+    fn tool_call(arguments: &str) -> ToolCall {
+        ToolCall {
+            id: "call_1".into(),
+            r#type: "function".into(),
+            function: ToolCallFunction {
+                name: Some("get_weather".into()),
+                arguments: Some(arguments.into()),
+            },
+        }
+    }
+
+    #[test]
+    fn test_tool_arguments() {
+        rootless_mutate(|mutation| {
+            let ctx = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let params = IndexMap::from([
+                ("city".to_owned(), PrimitiveType::Str),
+                ("days".to_owned(), PrimitiveType::Int),
+                ("metric".to_owned(), PrimitiveType::Bool),
+            ]);
+            let tool_def = FnDef::new(0, &None, params);
+            let call = tool_call(r#"{"metric": true, "days": 3, "city": "Paris"}"#);
+
+            // The arguments are passed in the order of the parameters, with their JSON types
+            let args = tool_arguments(ctx, &tool_def, call.function.arguments.as_deref()).unwrap();
+            assert_eq!(args.len(), 3);
+            assert_eq!(args[0].to_string(), "Paris");
+            assert!(matches!(args[0], Value::String(_)));
+            assert!(args[1].equals(&Value::Int(3)));
+            assert!(args[2].equals(&Value::Boolean(true)));
+
+            // A missing argument is left to the defaults of the function
+            let args = tool_arguments(ctx, &tool_def, Some(r#"{"city": "Oslo"}"#)).unwrap();
+            assert_eq!(args.len(), 1);
+            assert!(tool_arguments(ctx, &tool_def, Some("{city")).is_err());
+        });
+    }
+
+    #[test]
+    fn test_tool_content() {
+        rootless_mutate(|mutation| {
+            let ctx = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let object = |fields: &[(&str, &str)]| {
+                let fields = fields
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            ctx.intern(key.as_bytes()),
+                            Value::from(ctx.intern(value.as_bytes())),
+                        )
+                    })
+                    .collect();
+                Value::Object(Gc::new(mutation, RefLock::new(Object { fields })))
+            };
+
+            let text = ToolContent::from_value(Value::from(ctx.intern(b"sunny")));
+            assert_eq!(text, ToolContent::Text("sunny".into()));
+            let json = ToolContent::from_value(object(&[("sky", "clear")]));
+            assert_eq!(json, ToolContent::Json(serde_json::json!({"sky": "clear"})));
+            let error =
+                ToolContent::from_value(object(&[("type", "error"), ("message", "no city")]));
+            assert_eq!(error, ToolContent::Error("no city".into()));
+            let image =
+                ToolContent::from_value(object(&[("type", "image"), ("url", "https://x/a.png")]));
+            assert_eq!(image, ToolContent::Image("https://x/a.png".into()));
+
+            // The recorded text and JSON results are replayed as the same content
+            for content in [text, json] {
+                assert_eq!(
+                    ToolContent::from_json(Ok(content.to_serde_value())),
+                    content
+                );
+            }
+
+            let call = tool_call("{}");
+            let messages =
+                ToolContent::Json(serde_json::json!({"sky": "clear"})).into_messages(&call);
+            assert_eq!(messages.len(), 1);
+            assert!(matches!(messages[0].role, MessageRole::tool));
+            assert_eq!(messages[0].tool_call_id.as_deref(), Some("call_1"));
+            assert!(
+                matches!(&messages[0].content, Content::Text(text) if text == r#"{"sky":"clear"}"#)
+            );
+
+            let messages = error.into_messages(&call);
+            assert!(
+                matches!(&messages[0].content, Content::Text(text) if text == r#"{"error":"no city"}"#)
+            );
+
+            // The image is attached in a user message after the tool message
+            let messages = image.into_messages(&call);
+            assert_eq!(messages.len(), 2);
+            assert!(matches!(messages[1].role, MessageRole::user));
+            assert!(
+                matches!(&messages[1].content, Content::ImageUrl(urls) if urls[0].image_url.as_ref().unwrap().url == "https://x/a.png")
+            );
+        });
+    }
+}
@@ -0,0 +1,276 @@
+// The provider client is stubbed out under the ai_test feature.
+#![cfg_attr(feature = "ai_test", allow(unused))]
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{NaiveDate, Utc};
+use redb::{ReadableTable, TableDefinition};
+use serde::Deserialize;
+
+use crate::{
+    VmError,
+    stdlib::{KvError, kv_path, open_database},
+};
+
+/// AI cost budgets, configured in project.toml:
+///
+/// ```toml
+/// [ai.budget]
+/// daily = 50.0
+/// monthly = 1000.0
+///
+/// [ai.budget.prices]
+/// "gpt-4o" = { input = 2.5, output = 10.0 }
+///
+/// [ai.budget.routes]
+/// "/chat" = { daily = 5.0 }
+///
+/// [ai.budget.principal]
+/// daily = 1.0
+/// ```
+///
+/// The spend is kept in `budget.redb` next to the `std.kv` store, so it
+/// survives restarts and is shared by the processes of the project.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BudgetConfig {
+    // Budget of all AI calls of the project.
+    #[serde(flatten)]
+    pub total: BudgetLimit,
+    // Price in USD per 1M tokens of each model, matched by the longest model name prefix.
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    // Budget of each route, keyed by the route path.
+    #[serde(default)]
+    pub routes: HashMap<String, BudgetLimit>,
+    // Budget of each authenticated principal.
+    pub principal: Option<BudgetLimit>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct BudgetLimit {
+    pub daily: Option<f64>,
+    pub monthly: Option<f64>,
+}
+
+/// The route and principal the AI calls of a request are charged to.
+#[derive(Debug, Clone, Default)]
+pub struct BudgetScope {
+    pub route: Option<String>,
+    pub principal: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    pub config: BudgetConfig,
+    pub scope: BudgetScope,
+}
+
+// The spend in USD of each budget by period, e.g. `total/2025-01-31` and
+// `principal alice/2025-01`.
+const SPEND: TableDefinition<&str, f64> = TableDefinition::new("spend");
+
+// The file of the spend in the directory of the `std.kv` store, kept across
+// restarts and shared by the processes of the project.
+const LEDGER_FILE: &str = "budget.redb";
+
+fn ledger_path() -> PathBuf {
+    kv_path().with_file_name(LEDGER_FILE)
+}
+
+// The keys of the spend of the day and of the month of a budget.
+fn periods(key: &str, today: NaiveDate) -> [String; 2] {
+    [
+        format!("{key}/{}", today.format("%Y-%m-%d")),
+        format!("{key}/{}", today.format("%Y-%m")),
+    ]
+}
+
+fn ledger_error(e: impl std::fmt::Display) -> VmError {
+    VmError::RuntimeError(format!("budget: {e}"))
+}
+
+impl Budget {
+    // The ledger keys and limits the calls are charged to.
+    fn limits(&self) -> Vec<(String, BudgetLimit)> {
+        let mut limits = vec![("total".to_owned(), self.config.total)];
+        if let Some((route, limit)) = self
+            .scope
+            .route
+            .as_ref()
+            .and_then(|route| Some((route, self.config.routes.get(route)?)))
+        {
+            limits.push((format!("route {route}"), *limit));
+        }
+        if let (Some(principal), Some(limit)) = (&self.scope.principal, self.config.principal) {
+            limits.push((format!("principal {principal}"), limit));
+        }
+        limits
+    }
+
+    /// Reject the call if any budget it's charged to is used up.
+    pub fn check(&self) -> Result<(), VmError> {
+        self.check_on(&ledger_path(), Utc::now().date_naive())
+    }
+
+    fn check_on(&self, path: &Path, today: NaiveDate) -> Result<(), VmError> {
+        let limits = self.limits();
+        let spends = (|| -> Result<Vec<[f64; 2]>, KvError> {
+            let db = open_database(path)?;
+            let txn = db.begin_read()?;
+            let table = match txn.open_table(SPEND) {
+                Ok(table) => table,
+                Err(redb::TableError::TableDoesNotExist(_)) => {
+                    return Ok(vec![[0.0; 2]; limits.len()]);
+                }
+                Err(e) => return Err(e.into()),
+            };
+            let mut spends = Vec::new();
+            for (key, _) in &limits {
+                let [daily, monthly] = periods(key, today).map(|period| {
+                    table
+                        .get(period.as_str())
+                        .map(|v| v.map_or(0.0, |v| v.value()))
+                });
+                spends.push([daily?, monthly?]);
+            }
+            Ok(spends)
+        })()
+        .map_err(ledger_error)?;
+        for ((key, limit), [daily_spend, monthly_spend]) in limits.iter().zip(spends) {
+            if let Some(daily) = limit.daily.filter(|daily| daily_spend >= *daily) {
+                return Err(VmError::BudgetExceeded(format!(
+                    "Daily AI budget of ${daily:.2} exceeded for {key}."
+                )));
+            }
+            if let Some(monthly) = limit.monthly.filter(|monthly| monthly_spend >= *monthly) {
+                return Err(VmError::BudgetExceeded(format!(
+                    "Monthly AI budget of ${monthly:.2} exceeded for {key}."
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the cost of the token usage and charge it to the budgets.
+    pub fn charge(
+        &self,
+        model: &str,
+        prompt_tokens: i32,
+        completion_tokens: i32,
+    ) -> Result<(), VmError> {
+        self.charge_on(
+            &ledger_path(),
+            Utc::now().date_naive(),
+            model,
+            prompt_tokens,
+            completion_tokens,
+        )
+    }
+
+    fn charge_on(
+        &self,
+        path: &Path,
+        today: NaiveDate,
+        model: &str,
+        prompt_tokens: i32,
+        completion_tokens: i32,
+    ) -> Result<(), VmError> {
+        let Some(price) = self.price(model) else {
+            return Ok(());
+        };
+        let cost = (prompt_tokens.max(0) as f64 * price.input
+            + completion_tokens.max(0) as f64 * price.output)
+            / 1_000_000.0;
+        (|| -> Result<(), KvError> {
+            let db = open_database(path)?;
+            let txn = db.begin_write()?;
+            {
+                let mut table = txn.open_table(SPEND)?;
+                for (key, _) in self.limits() {
+                    for period in periods(&key, today) {
+                        let spend = table.get(period.as_str())?.map_or(0.0, |v| v.value());
+                        table.insert(period.as_str(), spend + cost)?;
+                    }
+                }
+            }
+            txn.commit()?;
+            Ok(())
+        })()
+        .map_err(ledger_error)
+    }
+
+    fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.config
+            .prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_budget() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 31).unwrap();
+        let budget = Budget {
+            config: BudgetConfig {
+                prices: [(
+                    "gpt-4o".to_owned(),
+                    ModelPrice {
+                        input: 2.0,
+                        output: 10.0,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                routes: [(
+                    "/test_route_budget".to_owned(),
+                    BudgetLimit {
+                        daily: Some(1.0),
+                        monthly: None,
+                    },
+                )]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            },
+            scope: BudgetScope {
+                route: Some("/test_route_budget".to_owned()),
+                principal: None,
+            },
+        };
+        assert_eq!(budget.price("gpt-4o-mini").unwrap().input, 2.0);
+        assert!(budget.price("gpt-3.5-turbo").is_none());
+
+        let path = std::env::temp_dir().join("aiscript_test_route_budget.redb");
+        let _ = std::fs::remove_file(&path);
+        assert!(budget.check_on(&path, today).is_ok());
+        // 0.5 + 0.5 USD
+        budget
+            .charge_on(&path, today, "gpt-4o", 250_000, 0)
+            .unwrap();
+        budget.charge_on(&path, today, "gpt-4o", 0, 50_000).unwrap();
+        // The spend is read back from the file, e.g. after a restart
+        let restarted = budget.clone();
+        assert!(matches!(
+            restarted.check_on(&path, today),
+            Err(VmError::BudgetExceeded(_))
+        ));
+        // The daily budget is reset on the next day
+        assert!(budget.check_on(&path, today.succ_opt().unwrap()).is_ok());
+        let _ = std::fs::remove_file(&path);
+    }
+}
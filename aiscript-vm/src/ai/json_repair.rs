@@ -0,0 +1,137 @@
+use serde_json::Value;
+
+/// Parse the JSON of a model response, tolerating the common mistakes of models:
+/// fenced code blocks, surrounding text, trailing commas, single-quoted strings,
+/// unquoted keys and Python literals (`True`, `False`, `None`).
+pub(crate) fn repair_json(response: &str) -> Result<Value, String> {
+    let text = strip_code_fence(response);
+    if let Ok(value) = serde_json::from_str(text) {
+        return Ok(value);
+    }
+
+    let start = text
+        .find(['{', '['])
+        .ok_or_else(|| "no JSON object or array found in the response".to_string())?;
+    let end = text
+        .rfind(['}', ']'])
+        .filter(|end| *end > start)
+        .ok_or_else(|| "no JSON object or array found in the response".to_string())?;
+    let repaired = repair(&text[start..=end]);
+    serde_json::from_str(&repaired).map_err(|err| err.to_string())
+}
+
+// Take the content of the first fenced code block, e.g. ```json ... ```.
+fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    let Some(start) = text.find("```") else {
+        return text;
+    };
+    let content = &text[start + 3..];
+    // Skip the language tag
+    let content = match content.find('\n') {
+        Some(newline) => &content[newline + 1..],
+        None => content,
+    };
+    match content.find("```") {
+        Some(end) => content[..end].trim(),
+        None => content.trim(),
+    }
+}
+
+fn repair(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut output = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            quote @ ('"' | '\'') => {
+                output.push('"');
+                i += 1;
+                while i < chars.len() && chars[i] != quote {
+                    match chars[i] {
+                        '\\' if i + 1 < chars.len() => {
+                            // `\'` isn't a valid escape in JSON
+                            if chars[i + 1] != '\'' {
+                                output.push('\\');
+                            }
+                            output.push(chars[i + 1]);
+                            i += 1;
+                        }
+                        '"' => output.push_str("\\\""),
+                        '\n' => output.push_str("\\n"),
+                        c => output.push(c),
+                    }
+                    i += 1;
+                }
+                output.push('"');
+            }
+            ',' => {
+                // Drop the trailing comma before a closing bracket
+                let next = chars[i + 1..].iter().find(|c| !c.is_whitespace());
+                if !matches!(next, Some('}' | ']')) {
+                    output.push(',');
+                }
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                // Copy the number as is, so the exponent isn't taken as a key
+                output.push(c);
+                while i + 1 < chars.len()
+                    && (chars[i + 1].is_ascii_digit()
+                        || matches!(chars[i + 1], '.' | 'e' | 'E' | '+' | '-'))
+                {
+                    i += 1;
+                    output.push(chars[i]);
+                }
+            }
+            c if c.is_alphabetic() || c == '_' || c == '$' => {
+                let start = i;
+                while i + 1 < chars.len()
+                    && (chars[i + 1].is_alphanumeric() || matches!(chars[i + 1], '_' | '$' | '-'))
+                {
+                    i += 1;
+                }
+                let word = chars[start..=i].iter().collect::<String>();
+                match word.as_str() {
+                    "true" | "True" => output.push_str("true"),
+                    "false" | "False" => output.push_str("false"),
+                    "null" | "None" => output.push_str("null"),
+                    // An unquoted key
+                    _ => {
+                        output.push('"');
+                        output.push_str(&word);
+                        output.push('"');
+                    }
+                }
+            }
+            c => output.push(c),
+        }
+        i += 1;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_repair_json() {
+        assert_eq!(repair_json(r#"{"a": 1}"#), Ok(json!({"a": 1})));
+        assert_eq!(
+            repair_json("```json\n{\"a\": [1, 2]}\n```"),
+            Ok(json!({"a": [1, 2]}))
+        );
+        assert_eq!(
+            repair_json("Here is the result: {'name': 'Ada', tags: ['x',],} Hope it helps!"),
+            Ok(json!({"name": "Ada", "tags": ["x"]}))
+        );
+        assert_eq!(
+            repair_json(r#"{'quote': 'It\'s "fine"', ok: True, missing: None}"#),
+            Ok(json!({"quote": "It's \"fine\"", "ok": true, "missing": null}))
+        );
+        assert_eq!(repair_json("[1, -2.5, 1e3,]"), Ok(json!([1, -2.5, 1e3])));
+        assert!(repair_json("no json here").is_err());
+        assert!(repair_json("{\"a\": }").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+// The provider client is stubbed out under the ai_test feature.
+#![cfg_attr(feature = "ai_test", allow(unused))]
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Deserialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::VmError;
+
+fn default_max_queue() -> usize {
+    100
+}
+
+fn default_max_queue_wait() -> u64 {
+    30
+}
+
+/// The AI calls of a model or a provider running at once, configured in project.toml:
+///
+/// ```toml
+/// [ai.limits]
+/// ollama = { concurrency = 4 }
+/// openai = { concurrency = 50, max_queue = 500 }
+/// "gpt-4o" = { concurrency = 10, max_queue_wait = 5 }
+/// ```
+///
+/// The limit of a model takes precedence over the one of its provider. Each
+/// limit has its own queue, a slow local model doesn't hold the calls to
+/// the other providers. Calls are unbounded without a limit.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConcurrencyConfig {
+    pub concurrency: usize,
+    // Max AI calls waiting for a slot, the next ones are shed.
+    #[serde(default = "default_max_queue")]
+    pub max_queue: usize,
+    // Max seconds an AI call waits for a slot before it is shed.
+    #[serde(default = "default_max_queue_wait")]
+    pub max_queue_wait: u64,
+}
+
+/// The limit applying to the calls of a model, with the model or provider
+/// name it is configured under.
+#[derive(Debug, Clone)]
+pub(crate) struct ModelLimit {
+    pub key: String,
+    pub config: ConcurrencyConfig,
+}
+
+struct Limiter {
+    concurrency: usize,
+    slots: Arc<Semaphore>,
+    queued: AtomicUsize,
+}
+
+// Limiters are shared by all VMs in the process, keyed by model or provider name.
+static LIMITERS: LazyLock<Mutex<HashMap<String, Arc<Limiter>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The limit of the model, or else of its provider.
+pub(crate) fn resolve(
+    limits: &HashMap<String, ConcurrencyConfig>,
+    provider: &str,
+    model: Option<&str>,
+) -> Option<ModelLimit> {
+    model
+        .and_then(|model| limits.get_key_value(model))
+        .or_else(|| limits.get_key_value(provider))
+        .map(|(key, config)| ModelLimit {
+            key: key.clone(),
+            config: config.clone(),
+        })
+}
+
+fn limiter(limit: &ModelLimit) -> Arc<Limiter> {
+    let mut limiters = LIMITERS.lock().unwrap();
+    let concurrency = limit.config.concurrency.max(1);
+    match limiters.get(&limit.key) {
+        Some(limiter) if limiter.concurrency == concurrency => limiter.clone(),
+        // New, or resized by a config reload, the calls running
+        // on the previous one finish as usual.
+        _ => {
+            let limiter = Arc::new(Limiter {
+                concurrency,
+                slots: Arc::new(Semaphore::new(concurrency)),
+                queued: AtomicUsize::new(0),
+            });
+            limiters.insert(limit.key.clone(), limiter.clone());
+            limiter
+        }
+    }
+}
+
+/// Wait in the queue of the limit until a slot is free, the slot is released
+/// when the permit drops. The call is shed with [`VmError::RateLimited`] if
+/// the queue is full or it waits longer than `max_queue_wait`.
+pub(crate) async fn acquire(limit: &ModelLimit) -> Result<OwnedSemaphorePermit, VmError> {
+    let limiter = limiter(limit);
+    if let Ok(permit) = limiter.slots.clone().try_acquire_owned() {
+        return Ok(permit);
+    }
+    let max_queue_wait = limit.config.max_queue_wait;
+    let queued = limiter.queued.fetch_add(1, Ordering::SeqCst);
+    let result = if queued >= limit.config.max_queue {
+        Err(VmError::RateLimited { retry_after: 1 })
+    } else {
+        tokio::time::timeout(
+            Duration::from_secs(max_queue_wait),
+            limiter.slots.clone().acquire_owned(),
+        )
+        .await
+        .map(|permit| permit.expect("the semaphore is never closed"))
+        .map_err(|_| VmError::RateLimited {
+            retry_after: max_queue_wait.max(1),
+        })
+    };
+    limiter.queued.fetch_sub(1, Ordering::SeqCst);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(key: &str, concurrency: usize, max_queue: usize, max_queue_wait: u64) -> ModelLimit {
+        ModelLimit {
+            key: key.into(),
+            config: ConcurrencyConfig {
+                concurrency,
+                max_queue,
+                max_queue_wait,
+            },
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let limits = HashMap::from([
+            ("ollama".to_string(), limit("", 4, 100, 30).config),
+            ("gpt-4o".to_string(), limit("", 10, 100, 30).config),
+        ]);
+        let key = |provider, model| resolve(&limits, provider, model).map(|limit| limit.key);
+        assert_eq!(key("ollama", Some("llama3")).as_deref(), Some("ollama"));
+        assert_eq!(key("openai", Some("gpt-4o")).as_deref(), Some("gpt-4o"));
+        assert_eq!(key("openai", Some("gpt-4")), None);
+        assert_eq!(key("anthropic", None), None);
+    }
+
+    #[test]
+    fn test_acquire_queue() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let limit = limit("test_acquire_queue", 1, 1, 5);
+        let permit = runtime.block_on(acquire(&limit)).unwrap();
+        // The second call waits for the slot, the third one is shed
+        let waiting = runtime.spawn({
+            let limit = limit.clone();
+            async move { acquire(&limit).await.map(drop) }
+        });
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(matches!(
+            runtime.block_on(acquire(&limit)),
+            Err(VmError::RateLimited { retry_after: 1 })
+        ));
+        drop(permit);
+        assert!(runtime.block_on(waiting).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_acquire_timeout() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let limit = limit("test_acquire_timeout", 1, 10, 0);
+        let _permit = runtime.block_on(acquire(&limit)).unwrap();
+        assert!(matches!(
+            runtime.block_on(acquire(&limit)),
+            Err(VmError::RateLimited { retry_after: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_independent_limits() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let ollama = limit("test_independent_ollama", 1, 10, 0);
+        let openai = limit("test_independent_openai", 2, 10, 0);
+        let _permit = runtime.block_on(acquire(&ollama)).unwrap();
+        assert!(runtime.block_on(acquire(&ollama)).is_err());
+        let _first = runtime.block_on(acquire(&openai)).unwrap();
+        let _second = runtime.block_on(acquire(&openai)).unwrap();
+    }
+}
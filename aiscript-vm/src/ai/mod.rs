@@ -1,12 +1,41 @@
 mod agent;
+mod budget;
+mod json_repair;
+mod limits;
+mod openapi;
 mod prompt;
+mod rate_limit;
+mod trace;
+mod versioning;
 
+use crate::stdlib::SearchConfig;
+#[cfg(not(feature = "ai_test"))]
+use crate::{
+    VmError,
+    vm::{breaker, with_deadline},
+};
 use aiscript_common::EnvString;
-use std::env;
+#[cfg(not(feature = "ai_test"))]
+use std::time::Instant;
+use std::{collections::HashMap, env, path::PathBuf};
 
-pub use agent::{Agent, run_agent};
+pub(crate) use agent::ToolChoice;
+pub use agent::{Agent, ToolRef, run_agent};
+pub(crate) use budget::Budget;
+pub use budget::{BudgetConfig, BudgetScope};
+pub(crate) use json_repair::repair_json;
+pub use limits::ConcurrencyConfig;
 use openai_api_rs::v1::{api::OpenAIClient, common};
+#[cfg(not(feature = "ai_test"))]
+use openai_api_rs::v1::{
+    chat_completion::{ChatCompletionRequest, ChatCompletionResponse},
+    error::APIError,
+};
+pub use openapi::OpenApiTools;
 pub use prompt::{PromptConfig, prompt_with_config};
+pub use rate_limit::RateLimitConfig;
+pub use trace::{Trace, TraceEvent};
+pub use versioning::{PromptHistory, PromptVersion, ServedPrompt};
 
 use serde::Deserialize;
 
@@ -32,6 +61,16 @@ pub struct AiConfig {
     pub anthropic: Option<ModelConfig>,
     pub deepseek: Option<ModelConfig>,
     pub ollama: Option<ModelConfig>,
+    #[serde(default)]
+    pub budget: Option<BudgetConfig>,
+    #[serde(default)]
+    pub search: Option<SearchConfig>,
+    // The directory of the agent instructions changelog, see [`PromptHistory`].
+    #[serde(default)]
+    pub prompt_history: Option<PathBuf>,
+    // The concurrency limits keyed by model or provider name, see [`ConcurrencyConfig`].
+    #[serde(default)]
+    pub limits: HashMap<String, ConcurrencyConfig>,
 }
 
 impl Default for AiConfig {
@@ -41,16 +80,22 @@ impl Default for AiConfig {
                 api_key: key.into(),
                 api_endpoint: Some(OPENAI_API_ENDPOINT.into()),
                 model: Some(OPENAI_DEFAULT_MODEL.into()),
+                rate_limit: None,
+                limit: None,
             }),
             anthropic: env::var("CLAUDE_API_KEY").ok().map(|key| ModelConfig {
                 api_key: key.into(),
                 api_endpoint: Some(ANTHROPIC_API_ENDPOINT.into()),
                 model: Some(ANTHROPIC_DEFAULT_MODEL.into()),
+                rate_limit: None,
+                limit: None,
             }),
             deepseek: env::var("DEEPKSEEK_API_KEY").ok().map(|key| ModelConfig {
                 api_key: key.into(),
                 api_endpoint: Some(DEEPSEEK_API_ENDPOINT.into()),
                 model: Some(DEEPSEEK_DEFAULT_MODEL.into()),
+                rate_limit: None,
+                limit: None,
             }),
             ollama: env::var("OLLAMA_API_ENDPOINT")
                 .ok()
@@ -62,7 +107,13 @@ impl Default for AiConfig {
                         .map(|url: String| url.into())
                         .or(Some(OLLAMA_DEFAULT_API_ENDPOINT.into())),
                     model: Some(OLLAMA_DEFAULT_MODEL.into()),
+                    rate_limit: None,
+                    limit: None,
                 }),
+            budget: None,
+            search: None,
+            prompt_history: None,
+            limits: HashMap::new(),
         }
     }
 }
@@ -72,6 +123,11 @@ pub struct ModelConfig {
     pub api_key: EnvString,
     pub api_endpoint: Option<EnvString>,
     pub model: Option<EnvString>,
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    // Resolved from `[ai.limits]` by `get_model_config()`.
+    #[serde(skip)]
+    pub(crate) limit: Option<limits::ModelLimit>,
 }
 
 impl Default for ModelConfig {
@@ -83,20 +139,47 @@ impl Default for ModelConfig {
             api_key: EnvString(env::var("OPENAI_API_KEY").unwrap_or_default()),
             api_endpoint: Some(OPENAI_API_ENDPOINT.into()),
             model: Some(OPENAI_DEFAULT_MODEL.into()),
+            rate_limit: None,
+            limit: None,
         }
     }
 }
 
+/// The providers of the `[ai]` section of project.toml.
+pub const PROVIDERS: [&str; 4] = ["openai", "anthropic", "deepseek", "ollama"];
+
 impl AiConfig {
+    /// The config of the provider, None if it isn't configured.
+    pub fn provider(&self, name: &str) -> Option<&ModelConfig> {
+        match name {
+            "openai" => self.openai.as_ref(),
+            "anthropic" => self.anthropic.as_ref(),
+            "deepseek" => self.deepseek.as_ref(),
+            "ollama" => self.ollama.as_ref(),
+            _ => None,
+        }
+    }
+
     pub(crate) fn get_model_config(
         &self,
         model_name: Option<String>,
     ) -> Result<ModelConfig, String> {
+        let (provider, mut config) = self.provider_model_config(model_name)?;
+        let model = config.model.as_deref().map(|s| s.as_str());
+        config.limit = limits::resolve(&self.limits, provider, model);
+        Ok(config)
+    }
+
+    // The config of the model with the name of its provider.
+    fn provider_model_config(
+        &self,
+        model_name: Option<String>,
+    ) -> Result<(&'static str, ModelConfig), String> {
         if let Some(ollama) = self.ollama.as_ref() {
             let model = model_name.as_deref().unwrap_or(OLLAMA_DEFAULT_MODEL);
             let mut config = ollama.clone();
             config.model = Some(EnvString(model.to_string()));
-            return Ok(config);
+            return Ok(("ollama", config));
         }
         if let Some(model) = model_name {
             match model {
@@ -104,39 +187,49 @@ impl AiConfig {
                     if let Some(openai) = self.openai.as_ref() {
                         let mut config = openai.clone();
                         config.model = Some(EnvString(m));
-                        Ok(config)
+                        Ok(("openai", config))
                     } else {
-                        Ok(ModelConfig::default())
+                        Ok(("openai", ModelConfig::default()))
                     }
                 }
                 m if m.starts_with("claude") => {
                     if let Some(anthropic) = self.anthropic.as_ref() {
                         let mut config = anthropic.clone();
                         config.model = Some(EnvString(m));
-                        Ok(config)
+                        Ok(("anthropic", config))
                     } else {
-                        Ok(ModelConfig {
-                            api_key: env::var("CLAUDE_API_KEY")
-                                .expect("Expect `CLAUDE_API_KEY` environment variable.")
-                                .into(),
-                            api_endpoint: Some(ANTHROPIC_API_ENDPOINT.into()),
-                            model: Some(ANTHROPIC_DEFAULT_MODEL.into()),
-                        })
+                        Ok((
+                            "anthropic",
+                            ModelConfig {
+                                api_key: env::var("CLAUDE_API_KEY")
+                                    .expect("Expect `CLAUDE_API_KEY` environment variable.")
+                                    .into(),
+                                api_endpoint: Some(ANTHROPIC_API_ENDPOINT.into()),
+                                model: Some(ANTHROPIC_DEFAULT_MODEL.into()),
+                                rate_limit: None,
+                                limit: None,
+                            },
+                        ))
                     }
                 }
                 m if m.starts_with("deepseek") => {
                     if let Some(deepseek) = self.deepseek.as_ref() {
                         let mut config = deepseek.clone();
                         config.model = Some(EnvString(m));
-                        Ok(config)
+                        Ok(("deepseek", config))
                     } else {
-                        Ok(ModelConfig {
-                            api_key: env::var("DEEPSEEK_API_KEY")
-                                .expect("Expect `DEEPSEEK_API_KEY` environment variable.")
-                                .into(),
-                            api_endpoint: Some(DEEPSEEK_API_ENDPOINT.into()),
-                            model: Some(DEEPSEEK_DEFAULT_MODEL.into()),
-                        })
+                        Ok((
+                            "deepseek",
+                            ModelConfig {
+                                api_key: env::var("DEEPSEEK_API_KEY")
+                                    .expect("Expect `DEEPSEEK_API_KEY` environment variable.")
+                                    .into(),
+                                api_endpoint: Some(DEEPSEEK_API_ENDPOINT.into()),
+                                model: Some(DEEPSEEK_DEFAULT_MODEL.into()),
+                                rate_limit: None,
+                                limit: None,
+                            },
+                        ))
                     }
                 }
                 m => Err(format!("Unsupported model '{m}'.")),
@@ -145,22 +238,125 @@ impl AiConfig {
             if let Some(model) = model_name {
                 let mut config = ollama.clone();
                 config.model = Some(EnvString(model));
-                return Ok(config);
+                return Ok(("ollama", config));
             } else {
-                return Ok(ollama.clone());
+                return Ok(("ollama", ollama.clone()));
             }
         } else {
             // Default is OpenAI model
-            Ok(ModelConfig::default())
+            Ok(("openai", ModelConfig::default()))
         }
     }
 }
 
+// Send the chat completion request within the provider rate limit,
+// the request is retried if the provider rejects it with 429.
+#[cfg(not(feature = "ai_test"))]
+pub(crate) async fn chat_completion(
+    client: &mut OpenAIClient,
+    config: &ModelConfig,
+    budget: Option<&Budget>,
+    deadline: Option<Instant>,
+    req: ChatCompletionRequest,
+) -> Result<ChatCompletionResponse, VmError> {
+    if let Some(budget) = budget {
+        budget.check()?;
+    }
+    let provider = config.api_endpoint.as_deref().map_or("", |s| s.as_str());
+    let rate_limit = config.rate_limit.clone().unwrap_or_default();
+    let queue_deadline = rate_limit.queue_deadline();
+    let _slot = match &config.limit {
+        Some(limit) => Some(with_deadline(deadline, limits::acquire(limit)).await??),
+        None => None,
+    };
+    loop {
+        rate_limit::acquire(provider, &rate_limit, queue_deadline).await?;
+        let permit = breaker::acquire(format_args!("ai:{provider}"))?;
+        let result = with_deadline(deadline, client.chat_completion(req.clone())).await;
+        // Client errors (4xx) don't mean the provider is down
+        permit.report(match &result {
+            Ok(Ok(_)) => true,
+            Ok(Err(APIError::CustomError { message })) => message.starts_with('4'),
+            _ => false,
+        });
+        match result? {
+            Ok(response) => {
+                rate_limit::update(provider, client, response.usage.total_tokens);
+                if let Some(budget) = budget {
+                    let usage = &response.usage;
+                    budget.charge(&req.model, usage.prompt_tokens, usage.completion_tokens)?;
+                }
+                return Ok(response);
+            }
+            Err(APIError::CustomError { message }) if message.starts_with("429") => {
+                rate_limit::backoff(provider);
+            }
+            Err(err) => return Err(VmError::RuntimeError(err.to_string())),
+        }
+    }
+}
+
+/// Check the provider is reachable and accepts the API key by listing its
+/// models, the error tells what to fix in project.toml.
+#[cfg(not(feature = "ai_test"))]
+pub async fn ping_provider(provider: &str, config: &ModelConfig) -> Result<(), String> {
+    let endpoint = match config.api_endpoint.as_deref() {
+        Some(endpoint) => endpoint.as_str(),
+        None => match provider {
+            "anthropic" => ANTHROPIC_API_ENDPOINT,
+            "deepseek" => DEEPSEEK_API_ENDPOINT,
+            "ollama" => OLLAMA_DEFAULT_API_ENDPOINT,
+            _ => OPENAI_API_ENDPOINT,
+        },
+    };
+    if let Some(var) = config.api_key.strip_prefix('$') {
+        return Err(format!(
+            "the api_key of [ai.{provider}] reads the environment variable {var}, which isn't set"
+        ));
+    }
+    let mut request = reqwest::Client::new()
+        .get(format!("{}/models", endpoint.trim_end_matches('/')))
+        .timeout(std::time::Duration::from_secs(10));
+    if !config.api_key.is_empty() {
+        request = request
+            .bearer_auth(config.api_key.as_str())
+            // The native authentication of the Anthropic API
+            .header("x-api-key", config.api_key.as_str())
+            .header("anthropic-version", "2023-06-01");
+    }
+    let response = request.send().await.map_err(|e| {
+        format!(
+            "{provider} is unreachable at {endpoint} ({e}), check it is running \
+            and the api_endpoint of [ai.{provider}] in project.toml"
+        )
+    })?;
+    match response.status() {
+        status if status.is_success() => Ok(()),
+        status @ (reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => {
+            Err(format!(
+                "{provider} rejected the API key ({status}), check the api_key \
+                of [ai.{provider}] in project.toml"
+            ))
+        }
+        status => Err(format!("{provider} returned {status} at {endpoint}/models")),
+    }
+}
+
+// The providers are never called under the ai_test feature.
+#[cfg(feature = "ai_test")]
+pub async fn ping_provider(_provider: &str, _config: &ModelConfig) -> Result<(), String> {
+    Ok(())
+}
+
+// The request id is sent in the `X-Request-Id` header to correlate
+// the provider calls with the request.
 #[allow(unused)]
-pub(crate) fn openai_client(config: &ModelConfig) -> OpenAIClient {
-    OpenAIClient::builder()
+pub(crate) fn openai_client(config: &ModelConfig, request_id: Option<&str>) -> OpenAIClient {
+    let mut builder = OpenAIClient::builder()
         .with_api_key(&*config.api_key)
-        .with_endpoint(config.api_endpoint.as_deref().unwrap())
-        .build()
-        .unwrap()
+        .with_endpoint(config.api_endpoint.as_deref().unwrap());
+    if let Some(request_id) = request_id {
+        builder = builder.with_header("X-Request-Id", request_id);
+    }
+    builder.build().unwrap()
 }
@@ -0,0 +1,547 @@
+// The provider client is stubbed out under the ai_test feature.
+#![cfg_attr(feature = "ai_test", allow(unused))]
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use aiscript_arena::Collect;
+use openai_api_rs::v1::{
+    chat_completion::{Tool, ToolType},
+    types::{self, FunctionParameters, JSONSchemaDefine, JSONSchemaType},
+};
+use reqwest::{
+    Method, Url,
+    header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+};
+use serde_json::Value as Json;
+
+use crate::{stdlib::http::make_request, vm::VmError};
+
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+// Max depth of nested schemas converted to tool parameters, it also stops recursive $ref.
+const MAX_SCHEMA_DEPTH: usize = 8;
+
+/// Agent tools generated from the operations of an OpenAPI spec, declared as
+/// `tools: [tools_from_openapi("https://api.example.com/openapi.json", allow=["getUser"])]`.
+#[derive(Debug, Clone, Collect)]
+#[collect(require_static)]
+pub struct OpenApiTools {
+    // URL or file path of the spec in JSON.
+    pub spec: String,
+    // Operation ids exposed as tools, all operations are exposed if it's empty.
+    pub allow: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParamLocation {
+    Path,
+    Query,
+    Header,
+    Body,
+}
+
+#[derive(Debug, Clone)]
+struct OperationParam {
+    name: String,
+    location: ParamLocation,
+    schema: JSONSchemaDefine,
+    required: bool,
+}
+
+/// An API operation called by the agent as a tool.
+#[derive(Debug, Clone)]
+pub(crate) struct Operation {
+    pub id: String,
+    method: Method,
+    // The server url joined with the path template, e.g. https://api.example.com/users/{id}
+    url: String,
+    description: String,
+    params: Vec<OperationParam>,
+    // The request body isn't an object, it's sent from the `body` argument as is.
+    raw_body: bool,
+}
+
+// Specs are fetched once and shared by all VMs in the process.
+static SPECS: LazyLock<Mutex<HashMap<String, Arc<Json>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+async fn fetch_spec(spec: &str) -> Result<Arc<Json>, String> {
+    if let Some(json) = SPECS.lock().unwrap().get(spec) {
+        return Ok(json.clone());
+    }
+    let content = if spec.starts_with("http://") || spec.starts_with("https://") {
+        let response = make_request(Method::GET, spec, HeaderMap::new(), None)
+            .await
+            .map_err(|err| format!("Failed to fetch OpenAPI spec '{spec}': {err}"))?
+            .error_for_status()
+            .map_err(|err| format!("Failed to fetch OpenAPI spec '{spec}': {err}"))?;
+        response
+            .text()
+            .await
+            .map_err(|err| format!("Failed to fetch OpenAPI spec '{spec}': {err}"))?
+    } else {
+        fs::read_to_string(spec)
+            .map_err(|err| format!("Failed to read OpenAPI spec '{spec}': {err}"))?
+    };
+    let json = serde_json::from_str::<Json>(&content)
+        .map(Arc::new)
+        .map_err(|err| format!("Invalid OpenAPI spec '{spec}': {err}"))?;
+    SPECS.lock().unwrap().insert(spec.to_owned(), json.clone());
+    Ok(json)
+}
+
+/// Load the operations of the specs, keyed by operation id.
+pub(crate) async fn load_operations(
+    tools: &[OpenApiTools],
+) -> Result<HashMap<String, Operation>, VmError> {
+    let mut operations = HashMap::new();
+    for tools in tools {
+        let spec = fetch_spec(&tools.spec)
+            .await
+            .map_err(VmError::RuntimeError)?;
+        let parsed = parse_operations(&spec, &tools.allow)
+            .map_err(|err| VmError::RuntimeError(format!("{err} Spec: '{}'.", tools.spec)))?;
+        operations.extend(
+            parsed
+                .into_iter()
+                .map(|operation| (operation.id.clone(), operation)),
+        );
+    }
+    Ok(operations)
+}
+
+// Follow a local reference such as {"$ref": "#/components/schemas/User"}.
+fn resolve<'a>(spec: &'a Json, mut value: &'a Json) -> &'a Json {
+    for _ in 0..MAX_SCHEMA_DEPTH {
+        let Some(pointer) = value
+            .get("$ref")
+            .and_then(Json::as_str)
+            .and_then(|r| r.strip_prefix('#'))
+        else {
+            break;
+        };
+        match spec.pointer(pointer) {
+            Some(target) => value = target,
+            None => break,
+        }
+    }
+    value
+}
+
+fn schema_define(spec: &Json, schema: &Json, depth: usize) -> JSONSchemaDefine {
+    let schema = resolve(spec, schema);
+    let str_field = |name: &str| schema.get(name).and_then(Json::as_str).map(str::to_owned);
+    let mut define = JSONSchemaDefine {
+        schema_type: str_field("type").map(|ty| match ty.as_str() {
+            "integer" | "number" => JSONSchemaType::Number,
+            "boolean" => JSONSchemaType::Boolean,
+            "array" => JSONSchemaType::Array,
+            "object" => JSONSchemaType::Object,
+            "null" => JSONSchemaType::Null,
+            _ => JSONSchemaType::String,
+        }),
+        description: str_field("description"),
+        enum_values: schema.get("enum").and_then(Json::as_array).map(|values| {
+            values
+                .iter()
+                .map(|v| v.as_str().map_or_else(|| v.to_string(), str::to_owned))
+                .collect()
+        }),
+        ..Default::default()
+    };
+    if depth >= MAX_SCHEMA_DEPTH {
+        return define;
+    }
+    if let Some(properties) = schema.get("properties").and_then(Json::as_object) {
+        define.properties = Some(
+            properties
+                .iter()
+                .map(|(name, property)| {
+                    (
+                        name.clone(),
+                        Box::new(schema_define(spec, property, depth + 1)),
+                    )
+                })
+                .collect(),
+        );
+        define.required = required_names(schema);
+    }
+    if let Some(items) = schema.get("items") {
+        define.items = Some(Box::new(schema_define(spec, items, depth + 1)));
+    }
+    define
+}
+
+fn required_names(schema: &Json) -> Option<Vec<String>> {
+    schema
+        .get("required")
+        .and_then(Json::as_array)
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| name.as_str().map(str::to_owned))
+                .collect()
+        })
+}
+
+fn parse_operations(spec: &Json, allow: &[String]) -> Result<Vec<Operation>, String> {
+    let server = spec
+        .pointer("/servers/0/url")
+        .and_then(Json::as_str)
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let mut operations = Vec::new();
+    for (path, item) in spec
+        .get("paths")
+        .and_then(Json::as_object)
+        .into_iter()
+        .flatten()
+    {
+        let item = resolve(spec, item);
+        for method in METHODS {
+            let Some(op) = item.get(method) else {
+                continue;
+            };
+            let Some(id) = op.get("operationId").and_then(Json::as_str) else {
+                continue;
+            };
+            if !allow.is_empty() && !allow.iter().any(|allowed| allowed == id) {
+                continue;
+            }
+
+            let mut params = Vec::new();
+            // Parameters of the path are shared by all of its operations
+            for param in [item.get("parameters"), op.get("parameters")]
+                .into_iter()
+                .flatten()
+                .filter_map(Json::as_array)
+                .flatten()
+            {
+                let param = resolve(spec, param);
+                let (Some(name), Some(location)) = (
+                    param.get("name").and_then(Json::as_str),
+                    param.get("in").and_then(Json::as_str),
+                ) else {
+                    continue;
+                };
+                let location = match location {
+                    "path" => ParamLocation::Path,
+                    "query" => ParamLocation::Query,
+                    "header" => ParamLocation::Header,
+                    // Cookie parameters are not supported
+                    _ => continue,
+                };
+                let mut schema = schema_define(spec, param.get("schema").unwrap_or(&Json::Null), 0);
+                if let Some(description) = param.get("description").and_then(Json::as_str) {
+                    schema.description = Some(description.to_owned());
+                }
+                params.retain(|p: &OperationParam| p.name != name);
+                params.push(OperationParam {
+                    name: name.to_owned(),
+                    location,
+                    schema,
+                    required: location == ParamLocation::Path
+                        || param.get("required").and_then(Json::as_bool) == Some(true),
+                });
+            }
+
+            let mut raw_body = false;
+            if let Some(body) = op.get("requestBody").map(|body| resolve(spec, body)) {
+                let schema = body
+                    .pointer("/content/application~1json/schema")
+                    .map(|schema| resolve(spec, schema))
+                    .unwrap_or(&Json::Null);
+                match schema.get("properties").and_then(Json::as_object) {
+                    // The properties of an object body are flattened into the tool parameters
+                    Some(properties) => {
+                        let required = required_names(schema).unwrap_or_default();
+                        params.extend(properties.iter().map(|(name, property)| OperationParam {
+                            name: name.clone(),
+                            location: ParamLocation::Body,
+                            schema: schema_define(spec, property, 1),
+                            required: required.contains(name),
+                        }));
+                    }
+                    None => {
+                        raw_body = true;
+                        params.push(OperationParam {
+                            name: "body".to_owned(),
+                            location: ParamLocation::Body,
+                            schema: schema_define(spec, schema, 0),
+                            required: body.get("required").and_then(Json::as_bool) == Some(true),
+                        });
+                    }
+                }
+            }
+
+            let description = ["summary", "description"]
+                .iter()
+                .filter_map(|field| op.get(field).and_then(Json::as_str))
+                .collect::<Vec<_>>()
+                .join("\n");
+            operations.push(Operation {
+                id: id.to_owned(),
+                method: method.to_uppercase().parse().unwrap(),
+                url: format!("{server}{path}"),
+                description,
+                params,
+                raw_body,
+            });
+        }
+    }
+
+    if let Some(missing) = allow
+        .iter()
+        .find(|allowed| !operations.iter().any(|op| op.id == **allowed))
+    {
+        return Err(format!(
+            "Operation '{missing}' is not found in the OpenAPI spec."
+        ));
+    }
+    Ok(operations)
+}
+
+// Percent-encode a path parameter value.
+fn encode_path_segment(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+impl Operation {
+    pub fn to_tool(&self) -> Tool {
+        Tool {
+            r#type: ToolType::Function,
+            function: types::Function {
+                name: self.id.clone(),
+                description: Some(self.description.clone()),
+                parameters: FunctionParameters {
+                    schema_type: JSONSchemaType::Object,
+                    properties: Some(
+                        self.params
+                            .iter()
+                            .map(|param| (param.name.clone(), Box::new(param.schema.clone())))
+                            .collect(),
+                    ),
+                    required: Some(
+                        self.params
+                            .iter()
+                            .filter(|param| param.required)
+                            .map(|param| param.name.clone())
+                            .collect(),
+                    ),
+                },
+            },
+        }
+    }
+
+    // Build the url, headers and body of the request from the tool call arguments.
+    fn build_request(&self, arguments: &Json) -> Result<(Url, HeaderMap, Option<String>), String> {
+        let mut url = self.url.clone();
+        let mut query = Vec::new();
+        let mut headers = HeaderMap::new();
+        let mut body = serde_json::Map::new();
+        for param in &self.params {
+            let Some(value) = arguments.get(&param.name).filter(|v| !v.is_null()) else {
+                if param.required {
+                    return Err(format!("Missing required argument '{}'.", param.name));
+                }
+                continue;
+            };
+            let text = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_owned);
+            match param.location {
+                ParamLocation::Path => {
+                    url = url.replace(&format!("{{{}}}", param.name), &encode_path_segment(&text));
+                }
+                ParamLocation::Query => query.push((param.name.as_str(), text)),
+                ParamLocation::Header => {
+                    let (Ok(name), Ok(value)) = (
+                        HeaderName::from_bytes(param.name.as_bytes()),
+                        HeaderValue::from_str(&text),
+                    ) else {
+                        return Err(format!("Invalid header argument '{}'.", param.name));
+                    };
+                    headers.insert(name, value);
+                }
+                ParamLocation::Body => {
+                    body.insert(param.name.clone(), value.clone());
+                }
+            }
+        }
+
+        let mut url =
+            Url::parse(&url).map_err(|err| format!("Invalid operation url '{url}': {err}"))?;
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        let body = if self.raw_body {
+            body.remove("body").map(|body| body.to_string())
+        } else if !body.is_empty() {
+            Some(Json::Object(body).to_string())
+        } else {
+            None
+        };
+        if body.is_some() {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        }
+        Ok((url, headers, body))
+    }
+
+    /// Call the API with the tool call arguments, the response body is
+    /// returned as JSON if possible. Failed calls are returned as errors
+    /// so the model can see what went wrong.
+    pub async fn call(&self, arguments: &Json) -> Result<Json, Json> {
+        let (url, headers, body) = self.build_request(arguments)?;
+        let response = make_request(self.method.clone(), url.as_str(), headers, body)
+            .await
+            .map_err(|err| match err {
+                VmError::RuntimeError(message) => Json::from(message),
+                err => Json::from(err.to_string()),
+            })?;
+        let status = response.status();
+        let text = response
+            .text()
+            .await
+            .map_err(|err| Json::from(format!("Failed to read response body: {err}")))?;
+        let body = serde_json::from_str(&text).unwrap_or(Json::String(text));
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(serde_json::json!({ "status": status.as_u16(), "body": body }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> Json {
+        serde_json::json!({
+            "openapi": "3.0.0",
+            "servers": [{ "url": "https://api.example.com/v1/" }],
+            "paths": {
+                "/users/{id}": {
+                    "parameters": [{ "$ref": "#/components/parameters/UserId" }],
+                    "get": {
+                        "operationId": "getUser",
+                        "summary": "Get a user by id.",
+                        "parameters": [
+                            { "name": "fields", "in": "query", "schema": { "type": "string" } }
+                        ]
+                    },
+                    "put": {
+                        "operationId": "updateUser",
+                        "requestBody": {
+                            "required": true,
+                            "content": {
+                                "application/json": {
+                                    "schema": { "$ref": "#/components/schemas/User" }
+                                }
+                            }
+                        }
+                    }
+                },
+                "/users": {
+                    "get": { "operationId": "listUsers" }
+                }
+            },
+            "components": {
+                "parameters": {
+                    "UserId": {
+                        "name": "id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "integer" }
+                    }
+                },
+                "schemas": {
+                    "User": {
+                        "type": "object",
+                        "required": ["name"],
+                        "properties": {
+                            "name": { "type": "string" },
+                            "tags": { "type": "array", "items": { "type": "string" } }
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn test_parse_operations() {
+        let spec = spec();
+        let operations = parse_operations(&spec, &["getUser".into(), "updateUser".into()]).unwrap();
+        assert_eq!(operations.len(), 2);
+
+        let get_user = operations.iter().find(|op| op.id == "getUser").unwrap();
+        assert_eq!(get_user.method, Method::GET);
+        assert_eq!(get_user.url, "https://api.example.com/v1/users/{id}");
+        let tool = get_user.to_tool();
+        assert_eq!(
+            tool.function.description.as_deref(),
+            Some("Get a user by id.")
+        );
+        let properties = tool.function.parameters.properties.unwrap();
+        assert_eq!(properties["id"].schema_type, Some(JSONSchemaType::Number));
+        assert_eq!(
+            tool.function.parameters.required,
+            Some(vec!["id".to_owned()])
+        );
+
+        let update_user = operations.iter().find(|op| op.id == "updateUser").unwrap();
+        let tool = update_user.to_tool();
+        let properties = tool.function.parameters.properties.unwrap();
+        assert_eq!(
+            properties["tags"].items.as_ref().unwrap().schema_type,
+            Some(JSONSchemaType::String)
+        );
+        let mut required = tool.function.parameters.required.unwrap();
+        required.sort();
+        assert_eq!(required, vec!["id".to_owned(), "name".to_owned()]);
+
+        assert_eq!(parse_operations(&spec, &[]).unwrap().len(), 3);
+        assert!(parse_operations(&spec, &["deleteUser".into()]).is_err());
+    }
+
+    #[test]
+    fn test_build_request() {
+        let spec = spec();
+        let operations = parse_operations(&spec, &[]).unwrap();
+        let find = |id: &str| operations.iter().find(|op| op.id == id).unwrap();
+
+        let (url, _, body) = find("getUser")
+            .build_request(&serde_json::json!({ "id": "a/b", "fields": "name,email" }))
+            .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://api.example.com/v1/users/a%2Fb?fields=name%2Cemail"
+        );
+        assert!(body.is_none());
+
+        let (url, headers, body) = find("updateUser")
+            .build_request(&serde_json::json!({ "id": 1, "name": "Ada" }))
+            .unwrap();
+        assert_eq!(url.as_str(), "https://api.example.com/v1/users/1");
+        assert_eq!(headers[CONTENT_TYPE], "application/json");
+        assert_eq!(body.as_deref(), Some(r#"{"name":"Ada"}"#));
+
+        assert!(
+            find("updateUser")
+                .build_request(&serde_json::json!({ "id": 1 }))
+                .is_err()
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use std::time::Instant;
+
 use tokio::runtime::Handle;
 
-use super::ModelConfig;
+use super::{Budget, ModelConfig};
+use crate::VmError;
 
 #[derive(Default)]
 pub struct PromptConfig {
@@ -9,18 +12,21 @@ pub struct PromptConfig {
     pub max_tokens: Option<i64>,
     pub temperature: Option<f64>,
     pub system_prompt: Option<String>,
+    pub(crate) budget: Option<Budget>,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) request_id: Option<String>,
 }
 
 #[cfg(feature = "ai_test")]
-async fn _prompt_with_config(config: PromptConfig) -> String {
-    return format!("AI: {}", config.input);
+async fn _prompt_with_config(config: PromptConfig) -> Result<String, VmError> {
+    Ok(format!("AI: {}", config.input))
 }
 
 #[cfg(not(feature = "ai_test"))]
-async fn _prompt_with_config(mut config: PromptConfig) -> String {
+async fn _prompt_with_config(mut config: PromptConfig) -> Result<String, VmError> {
     use openai_api_rs::v1::chat_completion::{self, ChatCompletionRequest};
     let model = config.model_config.model.take().unwrap();
-    let mut client = super::openai_client(&config.model_config);
+    let mut client = super::openai_client(&config.model_config, config.request_id.as_deref());
 
     // Create system message if provided
     let mut messages = Vec::new();
@@ -54,15 +60,22 @@ async fn _prompt_with_config(mut config: PromptConfig) -> String {
         req.temperature = Some(temperature);
     }
 
-    let result = client.chat_completion(req).await.unwrap();
-    result.choices[0]
+    let result = super::chat_completion(
+        &mut client,
+        &config.model_config,
+        config.budget.as_ref(),
+        config.deadline,
+        req,
+    )
+    .await?;
+    Ok(result.choices[0]
         .message
         .content
         .clone()
-        .unwrap_or_default()
+        .unwrap_or_default())
 }
 
-pub fn prompt_with_config(config: PromptConfig) -> String {
+pub fn prompt_with_config(config: PromptConfig) -> Result<String, VmError> {
     if Handle::try_current().is_ok() {
         // We're in an async context, use await
         Handle::current().block_on(async { _prompt_with_config(config).await })
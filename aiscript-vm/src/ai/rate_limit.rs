@@ -0,0 +1,268 @@
+// The provider client is stubbed out under the ai_test feature.
+#![cfg_attr(feature = "ai_test", allow(unused))]
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use openai_api_rs::v1::api::OpenAIClient;
+use serde::Deserialize;
+
+use crate::VmError;
+
+const WINDOW: Duration = Duration::from_secs(60);
+// Backoff used when the provider rejects a request without telling us how long to wait.
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+
+fn default_max_queue_wait() -> u64 {
+    30
+}
+
+/// Request and token budget of a provider, configured in project.toml:
+///
+/// ```toml
+/// [ai.openai]
+/// api_key = "$OPENAI_API_KEY"
+/// rate_limit = { requests_per_minute = 500, tokens_per_minute = 30000, max_queue_wait = 10 }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimitConfig {
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+    // Max seconds an AI call waits in the queue before it is shed.
+    #[serde(default = "default_max_queue_wait")]
+    pub max_queue_wait: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            max_queue_wait: default_max_queue_wait(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ProviderBudget {
+    window_start: Instant,
+    requests: u32,
+    tokens: u32,
+    // Set from the provider rate-limit headers or a 429 response.
+    blocked_until: Option<Instant>,
+}
+
+impl ProviderBudget {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            requests: 0,
+            tokens: 0,
+            blocked_until: None,
+        }
+    }
+
+    // How long the next request has to wait, None if it can be sent now.
+    fn wait_time(&mut self, config: &RateLimitConfig, now: Instant) -> Option<Duration> {
+        if now.duration_since(self.window_start) >= WINDOW {
+            self.window_start = now;
+            self.requests = 0;
+            self.tokens = 0;
+        }
+        let window_reset = WINDOW.saturating_sub(now.duration_since(self.window_start));
+        let blocked = self
+            .blocked_until
+            .filter(|until| *until > now)
+            .map(|until| until - now);
+        let over_requests = config
+            .requests_per_minute
+            .is_some_and(|limit| self.requests >= limit);
+        let over_tokens = config
+            .tokens_per_minute
+            .is_some_and(|limit| self.tokens >= limit);
+        let budget = (over_requests || over_tokens).then_some(window_reset);
+        blocked.max(budget)
+    }
+}
+
+// Budgets are shared by all VMs in the process, keyed by provider endpoint.
+static BUDGETS: LazyLock<Mutex<HashMap<String, ProviderBudget>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+impl RateLimitConfig {
+    // None if `max_queue_wait` is too far in the future for an instant, the
+    // call then waits as long as the budget requires.
+    pub(crate) fn queue_deadline(&self) -> Option<Instant> {
+        Instant::now().checked_add(Duration::from_secs(self.max_queue_wait))
+    }
+}
+
+/// Wait in the queue until the provider budget allows another request.
+/// The call is shed with [`VmError::RateLimited`] if it would wait past the deadline.
+pub(crate) async fn acquire(
+    provider: &str,
+    config: &RateLimitConfig,
+    deadline: Option<Instant>,
+) -> Result<(), VmError> {
+    loop {
+        let now = Instant::now();
+        let wait = {
+            let mut budgets = BUDGETS.lock().unwrap();
+            let budget = budgets
+                .entry(provider.to_owned())
+                .or_insert_with(|| ProviderBudget::new(now));
+            let wait = budget.wait_time(config, now);
+            if wait.is_none() {
+                budget.requests += 1;
+            }
+            wait
+        };
+        match wait {
+            None => return Ok(()),
+            Some(wait) if deadline.is_some_and(|deadline| now + wait > deadline) => {
+                return Err(VmError::RateLimited {
+                    retry_after: wait.as_secs().max(1),
+                });
+            }
+            Some(wait) => tokio::time::sleep(wait).await,
+        }
+    }
+}
+
+/// Record the tokens used by a response and respect the
+/// rate-limit headers returned by the provider.
+pub(crate) fn update(provider: &str, client: &OpenAIClient, total_tokens: i32) {
+    let now = Instant::now();
+    let mut budgets = BUDGETS.lock().unwrap();
+    let budget = budgets
+        .entry(provider.to_owned())
+        .or_insert_with(|| ProviderBudget::new(now));
+    budget.tokens = budget.tokens.saturating_add(total_tokens.max(0) as u32);
+
+    let Some(headers) = &client.headers else {
+        return;
+    };
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    for kind in ["requests", "tokens"] {
+        let remaining =
+            header(&format!("x-ratelimit-remaining-{kind}")).and_then(|v| v.parse::<u64>().ok());
+        let until = header(&format!("x-ratelimit-reset-{kind}"))
+            .and_then(parse_duration)
+            .and_then(|reset| now.checked_add(reset));
+        if let (Some(0), Some(until)) = (remaining, until) {
+            budget.blocked_until = budget.blocked_until.max(Some(until));
+        }
+    }
+}
+
+/// Back off the provider after it rejected a request with 429 Too Many Requests.
+pub(crate) fn backoff(provider: &str) {
+    let now = Instant::now();
+    let mut budgets = BUDGETS.lock().unwrap();
+    let budget = budgets
+        .entry(provider.to_owned())
+        .or_insert_with(|| ProviderBudget::new(now));
+    budget.blocked_until = budget.blocked_until.max(Some(now + DEFAULT_BACKOFF));
+}
+
+// Parse the reset duration of rate-limit headers, e.g. "1s", "6m0s", "20ms".
+fn parse_duration(value: &str) -> Option<Duration> {
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if c.is_ascii_digit() || c == '.' {
+            number.push(c);
+            continue;
+        }
+        let n = number.parse::<f64>().ok()?;
+        number.clear();
+        let secs = match c {
+            'h' => n * 3600.0,
+            'm' if chars.peek() == Some(&'s') => {
+                chars.next();
+                n / 1000.0
+            }
+            'm' => n * 60.0,
+            's' => n,
+            _ => return None,
+        };
+        total = total.checked_add(Duration::try_from_secs_f64(secs).ok()?)?;
+    }
+    if !number.is_empty() {
+        // Plain seconds, as in the Retry-After header
+        let secs = number.parse::<f64>().ok()?;
+        total = total.checked_add(Duration::try_from_secs_f64(secs).ok()?)?;
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_duration("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_duration("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("30"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("abc"), None);
+        // A huge hint is no hint rather than a panic
+        assert_eq!(parse_duration("100000000000000000000000h"), None);
+        assert_eq!(parse_duration("100000000000000000000000"), None);
+    }
+
+    #[test]
+    fn test_budget_wait_time() {
+        let now = Instant::now();
+        let config = RateLimitConfig {
+            requests_per_minute: Some(2),
+            ..Default::default()
+        };
+        let mut budget = ProviderBudget::new(now);
+        assert_eq!(budget.wait_time(&config, now), None);
+        budget.requests = 2;
+        assert_eq!(
+            budget.wait_time(&config, now + Duration::from_secs(10)),
+            Some(Duration::from_secs(50))
+        );
+        // The budget is refilled after the window
+        assert_eq!(budget.wait_time(&config, now + WINDOW), None);
+    }
+
+    #[test]
+    fn test_queue_deadline_out_of_range() {
+        let config = RateLimitConfig {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+            max_queue_wait: u64::MAX,
+        };
+        assert_eq!(config.queue_deadline(), None);
+    }
+
+    #[test]
+    fn test_acquire_sheds_excess_calls() {
+        let config = RateLimitConfig {
+            requests_per_minute: Some(1),
+            tokens_per_minute: None,
+            max_queue_wait: 0,
+        };
+        let provider = "test_acquire_sheds_excess_calls";
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let deadline = config.queue_deadline();
+        assert!(
+            runtime
+                .block_on(acquire(provider, &config, deadline))
+                .is_ok()
+        );
+        assert!(matches!(
+            runtime.block_on(acquire(provider, &config, deadline)),
+            Err(VmError::RateLimited { .. })
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+use std::{fs, io, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// A provider response or tool result observed during an AI run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceEvent {
+    // The response text of a `prompt` expression.
+    Prompt {
+        response: String,
+    },
+    // The chat completion message returned to an agent.
+    Completion {
+        message: serde_json::Value,
+    },
+    // The result of a tool function called by an agent.
+    Tool {
+        name: String,
+        result: serde_json::Value,
+    },
+}
+
+impl TraceEvent {
+    fn kind(&self) -> &'static str {
+        match self {
+            TraceEvent::Prompt { .. } => "prompt",
+            TraceEvent::Completion { .. } => "completion",
+            TraceEvent::Tool { .. } => "tool",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TraceFile {
+    events: Vec<TraceEvent>,
+}
+
+#[derive(Debug)]
+enum TraceMode {
+    // Record into the trace file, or only in memory if there's no file.
+    Record(Option<PathBuf>),
+    Replay { cursor: usize },
+}
+
+/// Records the provider responses and tool results of a run into a trace file,
+/// or replays a recorded trace so the run is reproduced without calling the provider.
+#[derive(Debug)]
+pub struct Trace {
+    mode: TraceMode,
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn record(path: PathBuf) -> Self {
+        Trace {
+            mode: TraceMode::Record(Some(path)),
+            events: Vec::new(),
+        }
+    }
+
+    /// Record the events without writing a trace file, see [`Trace::events`].
+    pub fn record_in_memory() -> Self {
+        Trace {
+            mode: TraceMode::Record(None),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    pub fn replay(path: PathBuf) -> io::Result<Self> {
+        let content = fs::read_to_string(&path)?;
+        let file: TraceFile = serde_json::from_str(&content)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Trace {
+            mode: TraceMode::Replay { cursor: 0 },
+            events: file.events,
+        })
+    }
+
+    pub fn is_replay(&self) -> bool {
+        matches!(self.mode, TraceMode::Replay { .. })
+    }
+
+    /// Write the recorded events to the trace file, it's a no-op in replay mode.
+    pub fn save(&self) -> io::Result<()> {
+        if let TraceMode::Record(Some(path)) = &self.mode {
+            let file = TraceFile {
+                events: self.events.clone(),
+            };
+            fs::write(path, serde_json::to_string_pretty(&file)?)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn record_event(&mut self, event: TraceEvent) {
+        if !self.is_replay() {
+            self.events.push(event);
+        }
+    }
+
+    // Take the next recorded event, the run has diverged from
+    // the recording if its kind isn't the expected one.
+    fn next_event(&mut self, kind: &str) -> Result<TraceEvent, String> {
+        let TraceMode::Replay { cursor } = &mut self.mode else {
+            return Err("Trace is not in replay mode.".into());
+        };
+        match self.events.get(*cursor) {
+            Some(event) if event.kind() == kind => {
+                *cursor += 1;
+                Ok(event.clone())
+            }
+            Some(event) => Err(format!(
+                "Replay diverged at event {}: expected {kind}, but the trace recorded {}.",
+                *cursor,
+                event.kind()
+            )),
+            None => Err(format!(
+                "Replay diverged at event {}: expected {kind}, but the trace has ended.",
+                *cursor
+            )),
+        }
+    }
+
+    pub(crate) fn replay_prompt(&mut self) -> Result<String, String> {
+        match self.next_event("prompt")? {
+            TraceEvent::Prompt { response } => Ok(response),
+            _ => unreachable!(),
+        }
+    }
+
+    #[cfg(not(feature = "ai_test"))]
+    pub(crate) fn replay_completion(&mut self) -> Result<serde_json::Value, String> {
+        match self.next_event("completion")? {
+            TraceEvent::Completion { message } => Ok(message),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Take the recorded result of a tool that isn't executed in replay mode,
+    /// such as an API operation.
+    #[cfg(not(feature = "ai_test"))]
+    pub(crate) fn replay_tool_result(&mut self, name: &str) -> Result<serde_json::Value, String> {
+        match self.next_event("tool")? {
+            TraceEvent::Tool {
+                name: recorded_name,
+                result,
+            } if recorded_name == name => Ok(result),
+            TraceEvent::Tool {
+                name: recorded_name,
+                ..
+            } => Err(format!(
+                "Replay diverged: tool {name} was called, but the trace recorded {recorded_name}."
+            )),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Check the tool result of the replayed run against the recorded one.
+    #[cfg(not(feature = "ai_test"))]
+    pub(crate) fn replay_tool(
+        &mut self,
+        name: &str,
+        result: &serde_json::Value,
+    ) -> Result<(), String> {
+        match self.next_event("tool")? {
+            TraceEvent::Tool {
+                name: recorded_name,
+                result: recorded_result,
+            } => {
+                if recorded_name != name || recorded_result != *result {
+                    Err(format!(
+                        "Replay diverged: tool {name} returned {result}, \
+                        but the trace recorded {recorded_name} returned {recorded_result}."
+                    ))
+                } else {
+                    Ok(())
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "ai_test"))]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join("aiscript_test_record_and_replay.json");
+        let mut trace = Trace::record(path.clone());
+        trace.record_event(TraceEvent::Prompt {
+            response: "Charon".into(),
+        });
+        trace.record_event(TraceEvent::Tool {
+            name: "add".into(),
+            result: serde_json::json!(3),
+        });
+        trace.save().unwrap();
+
+        let mut trace = Trace::replay(path.clone()).unwrap();
+        assert_eq!(trace.replay_prompt().unwrap(), "Charon");
+        assert!(trace.replay_completion().is_err());
+        assert!(trace.replay_tool("add", &serde_json::json!(4)).is_err());
+        assert!(trace.replay_prompt().is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_prompt() {
+        let path = std::env::temp_dir().join("aiscript_test_replay_prompt.json");
+        let mut trace = Trace::record(path.clone());
+        trace.record_event(TraceEvent::Prompt {
+            response: "Charon".into(),
+        });
+        trace.save().unwrap();
+
+        let mut vm = crate::Vm::default();
+        vm.replay_trace(path.clone()).unwrap();
+        vm.compile(r#"return prompt "What is the moon of Pluto?";"#)
+            .unwrap();
+        assert_eq!(
+            vm.interpret().unwrap(),
+            crate::ReturnValue::String("Charon".into())
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(not(feature = "ai_test"))]
+    fn test_replay_malformed_completion() {
+        let path = std::env::temp_dir().join("aiscript_test_replay_malformed_completion.json");
+        let mut trace = Trace::record(path.clone());
+        trace.record_event(TraceEvent::Completion {
+            message: serde_json::json!({"role": 42}),
+        });
+        trace.save().unwrap();
+
+        let mut vm = crate::Vm::default();
+        vm.replay_trace(path.clone()).unwrap();
+        vm.compile(
+            r#"
+            agent Astronomer {
+                instructions: "Answer questions about the solar system.",
+            }
+            return Astronomer.run("What is the moon of Pluto?");
+            "#,
+        )
+        .unwrap();
+        let error = vm.interpret().unwrap_err().to_string();
+        assert!(error.contains("Invalid completion in the trace"), "{error}");
+        fs::remove_file(path).unwrap();
+    }
+}
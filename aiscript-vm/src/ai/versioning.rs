@@ -0,0 +1,174 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const SERVED_LOG: &str = "served.jsonl";
+
+/// The version of a prompt, the first 12 hex digits of the SHA-256 of its content.
+pub fn prompt_version(content: &str) -> String {
+    let digest = format!("{:x}", Sha256::digest(content.as_bytes()));
+    digest[..12].to_owned()
+}
+
+/// A version of the instructions of an agent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptVersion {
+    pub version: String,
+    pub created_at: DateTime<Utc>,
+    pub instructions: String,
+}
+
+/// An agent run served by a version of its instructions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServedPrompt {
+    pub time: DateTime<Utc>,
+    pub agent: String,
+    pub version: String,
+    // The route of the request the run served, none outside the web server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+/// The changelog of the agent instructions, configured in project.toml:
+///
+/// ```toml
+/// [ai]
+/// prompt_history = ".aiscript/prompts"
+/// ```
+///
+/// Every version of the instructions of an agent is appended to `<agent>.jsonl`,
+/// and every agent run is logged to `served.jsonl` with the version it was served by.
+#[derive(Debug, Clone)]
+pub struct PromptHistory {
+    dir: PathBuf,
+}
+
+impl PromptHistory {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        PromptHistory { dir: dir.into() }
+    }
+
+    fn agent_path(&self, agent: &str) -> PathBuf {
+        self.dir.join(format!("{agent}.jsonl"))
+    }
+
+    /// Record the instructions of an agent if its version isn't the latest one,
+    /// returns the version.
+    pub fn record(&self, agent: &str, instructions: &str) -> io::Result<String> {
+        let version = prompt_version(instructions);
+        let latest = self.versions(agent)?.pop();
+        if latest.is_none_or(|latest| latest.version != version) {
+            append_line(
+                &self.agent_path(agent),
+                &PromptVersion {
+                    version: version.clone(),
+                    created_at: Utc::now(),
+                    instructions: instructions.to_owned(),
+                },
+            )?;
+        }
+        Ok(version)
+    }
+
+    /// Record the instructions of an agent and log the run served by them.
+    pub fn serve(
+        &self,
+        agent: &str,
+        instructions: &str,
+        route: Option<&str>,
+    ) -> io::Result<String> {
+        let version = self.record(agent, instructions)?;
+        append_line(
+            &self.dir.join(SERVED_LOG),
+            &ServedPrompt {
+                time: Utc::now(),
+                agent: agent.to_owned(),
+                version: version.clone(),
+                route: route.map(str::to_owned),
+            },
+        )?;
+        Ok(version)
+    }
+
+    /// The recorded versions of an agent, from the oldest to the latest.
+    pub fn versions(&self, agent: &str) -> io::Result<Vec<PromptVersion>> {
+        read_lines(&self.agent_path(agent))
+    }
+
+    /// The logged runs of all agents.
+    pub fn served(&self) -> io::Result<Vec<ServedPrompt>> {
+        read_lines(&self.dir.join(SERVED_LOG))
+    }
+}
+
+fn append_line<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(value)?)
+}
+
+fn read_lines<T: for<'de> Deserialize<'de>>(path: &Path) -> io::Result<Vec<T>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    BufReader::new(file)
+        .lines()
+        .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+        .map(|line| {
+            serde_json::from_str(&line?)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_version() {
+        assert_eq!(
+            prompt_version("You are a helpful agent."),
+            prompt_version("You are a helpful agent.")
+        );
+        assert_ne!(prompt_version("v1"), prompt_version("v2"));
+        assert_eq!(prompt_version("v1").len(), 12);
+    }
+
+    #[test]
+    fn test_prompt_history() {
+        let dir = std::env::temp_dir().join("aiscript_test_prompt_history");
+        let _ = fs::remove_dir_all(&dir);
+        let history = PromptHistory::new(&dir);
+
+        let v1 = history.serve("Triage", "v1", None).unwrap();
+        history.serve("Triage", "v1", Some("/chat")).unwrap();
+        let v2 = history.serve("Triage", "v2", Some("/chat")).unwrap();
+        assert_ne!(v1, v2);
+
+        let versions = history.versions("Triage").unwrap();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|v| v.version.as_str())
+                .collect::<Vec<_>>(),
+            [v1.as_str(), v2.as_str()]
+        );
+        assert_eq!(versions[1].instructions, "v2");
+        let served = history.served().unwrap();
+        assert_eq!(served.len(), 3);
+        assert_eq!(served[2].route.as_deref(), Some("/chat"));
+        assert!(history.versions("Unknown").unwrap().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -7,7 +7,7 @@ use indexmap::IndexMap;
 
 use crate::object::{FunctionType, ListKind};
 use crate::{Value, string::InternedString};
-use crate::{lexer::Token, ty::PrimitiveType};
+use crate::{lexer::Token, ty::PrimitiveType, vm::JsonFormat};
 
 mod pretty;
 
@@ -68,17 +68,27 @@ pub struct EnumVariant<'gc> {
     pub value: Literal<'gc>,
 }
 
+/// The `@deprecated` and `@experimental` attributes of a function or a class.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Attributes {
+    // The note of `@deprecated`, e.g. "use foo2", empty if not given.
+    pub deprecated: Option<String>,
+    pub experimental: bool,
+}
+
 #[derive(Debug)]
 pub struct FunctionDecl<'gc> {
     pub name: Token<'gc>,
     pub mangled_name: String,
     pub doc: Option<Token<'gc>>,
     pub params: IndexMap<Token<'gc>, ParameterDecl<'gc>>,
-    pub return_type: Option<Token<'gc>>,
+    // The members of the return type, e.g. `-> User | nil`, empty if it isn't annotated.
+    pub return_types: Vec<Token<'gc>>,
     pub error_types: Vec<Token<'gc>>,
     pub body: Vec<Stmt<'gc>>,
     pub fn_type: FunctionType,
     pub visibility: Visibility,
+    pub attributes: Attributes,
     pub line: u32,
 }
 
@@ -101,10 +111,14 @@ pub struct ClassFieldDecl<'gc> {
 #[derive(Debug)]
 pub struct ClassDecl<'gc> {
     pub name: Token<'gc>,
+    pub doc: Option<Token<'gc>>,
     pub superclass: Option<Expr<'gc>>,
     // pub fields: Vec<ClassFieldDecl<'gc>>,
     pub methods: Vec<Stmt<'gc>>,
+    // The fields declared with `@json("<format>")`
+    pub json_formats: Vec<(Token<'gc>, JsonFormat)>,
     pub visibility: Visibility,
+    pub attributes: Attributes,
     pub line: u32,
 }
 
@@ -112,17 +126,62 @@ pub struct ClassDecl<'gc> {
 pub struct AgentDecl<'gc> {
     pub name: Token<'gc>,
     pub mangled_name: String,
+    pub doc: Option<Token<'gc>>,
     pub fields: HashMap<&'gc str, Expr<'gc>>,
     pub tools: Vec<Stmt<'gc>>,
     pub visibility: Visibility,
     pub line: u32,
 }
 
+#[derive(Debug)]
+pub struct WorkflowDecl<'gc> {
+    pub name: Token<'gc>,
+    pub mangled_name: String,
+    pub doc: Option<Token<'gc>>,
+    // The steps in their order, the first one starts the workflow.
+    pub steps: Vec<WorkflowStepDecl<'gc>>,
+    // The functions named by the `@compensate` of the steps.
+    pub compensations: Vec<Stmt<'gc>>,
+    pub visibility: Visibility,
+    pub line: u32,
+}
+
+#[derive(Debug)]
+pub struct WorkflowStepDecl<'gc> {
+    pub function: Stmt<'gc>,
+    // `@retry(3)` or the `retries` of the workflow.
+    pub retry: Retry,
+    // `@compensate("refund")`, run if a later step fails.
+    pub compensate: Option<Token<'gc>>,
+}
+
+/// The attempts of a workflow step after it failed, `delay` milliseconds
+/// apart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub struct Retry {
+    pub times: u32,
+    pub delay: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    #[default]
+    Positional,
+    // A parameter after the `*,` separator, it can only be passed as keyword argument.
+    KeywordOnly,
+    // `*args`, collects the extra positional arguments into an array.
+    Rest,
+    // `**kwargs`, collects the unknown keyword arguments into an object.
+    Kwargs,
+}
+
 pub struct ParameterDecl<'gc> {
     pub name: Token<'gc>,
     pub type_hint: Option<Token<'gc>>,
     pub default_value: Option<Expr<'gc>>,
     pub validators: Vec<Box<dyn Validator>>,
+    pub kind: ParameterKind,
 }
 
 impl std::fmt::Debug for ParameterDecl<'_> {
@@ -132,6 +191,7 @@ impl std::fmt::Debug for ParameterDecl<'_> {
             .field("type_hint", &self.type_hint)
             .field("default_value", &self.default_value)
             .field("validators", &self.validators.len())
+            .field("kind", &self.kind)
             .finish()
     }
 }
@@ -143,6 +203,7 @@ impl<'gc> ParameterDecl<'gc> {
             type_hint: None,
             default_value: None,
             validators: Vec::new(),
+            kind: ParameterKind::Positional,
         }
     }
 }
@@ -169,6 +230,9 @@ pub struct FnDef {
     pub chunk_id: ChunkId,
     pub doc: String,
     pub params: IndexMap<String, PrimitiveType>,
+    // The module path of a function imported from another file,
+    // it is looked up from the module exports instead of by chunk id.
+    pub module: Option<String>,
 }
 
 impl FnDef {
@@ -181,8 +245,14 @@ impl FnDef {
             chunk_id,
             doc: doc.map(|t| t.lexeme.to_owned()).unwrap_or_default(),
             params,
+            module: None,
         }
     }
+
+    pub fn with_module(mut self, module: &str) -> Self {
+        self.module = Some(module.to_owned());
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -234,6 +304,14 @@ impl MatchPattern<'_> {
     }
 }
 
+/// The positional arguments, the keyword arguments and the `**mapping`
+/// spread into the keyword arguments of a call.
+pub type Arguments<'gc> = (
+    Vec<Expr<'gc>>,
+    HashMap<String, Expr<'gc>>,
+    Option<Box<Expr<'gc>>>,
+);
+
 #[derive(Debug)]
 pub enum Expr<'gc> {
     EnvLookup {
@@ -311,6 +389,8 @@ pub enum Expr<'gc> {
     Lambda {
         params: Vec<Token<'gc>>,
         body: Box<Expr<'gc>>,
+        // `move |x| ...`, the captured variables are copied when the lambda is created.
+        capture_by_value: bool,
         line: u32,
     },
     Block {
@@ -322,6 +402,8 @@ pub enum Expr<'gc> {
         is_constructor: bool,
         arguments: Vec<Expr<'gc>>,
         keyword_args: HashMap<String, Expr<'gc>>,
+        // The `**mapping` spread into the keyword arguments
+        keyword_spread: Option<Box<Expr<'gc>>>,
         error_handler: Option<ErrorHandler<'gc>>,
         line: u32,
     },
@@ -330,6 +412,8 @@ pub enum Expr<'gc> {
         method: Token<'gc>,
         arguments: Vec<Expr<'gc>>,
         keyword_args: HashMap<String, Expr<'gc>>,
+        // The `**mapping` spread into the keyword arguments
+        keyword_spread: Option<Box<Expr<'gc>>>,
         error_handler: Option<ErrorHandler<'gc>>,
         line: u32,
     },
@@ -366,10 +450,13 @@ pub enum Expr<'gc> {
         method: Token<'gc>,
         arguments: Vec<Expr<'gc>>,
         keyword_args: HashMap<String, Expr<'gc>>,
+        // The `**mapping` spread into the keyword arguments
+        keyword_spread: Option<Box<Expr<'gc>>>,
         line: u32,
     },
     Prompt {
         expression: Box<Expr<'gc>>,
+        error_handler: Option<ErrorHandler<'gc>>,
         line: u32,
     },
 }
@@ -408,10 +495,81 @@ impl Expr<'_> {
     }
 }
 
+impl<'gc> Expr<'gc> {
+    /// Returns the callee token and path if the expression is a `file("path")` call.
+    pub fn as_file_path(&self) -> Option<(Token<'gc>, InternedString<'gc>)> {
+        match self {
+            Expr::Call {
+                callee, arguments, ..
+            } => match (&**callee, arguments.as_slice()) {
+                (
+                    Expr::Variable { name, .. },
+                    [
+                        Expr::Literal {
+                            value: Literal::String(path),
+                            ..
+                        },
+                    ],
+                ) if name.lexeme == "file" => Some((*name, *path)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the spec and allowed operation ids if the expression is a
+    /// `tools_from_openapi("spec", allow=["operationId"])` call.
+    pub fn as_openapi_tools(&self) -> Option<(InternedString<'gc>, Vec<InternedString<'gc>>)> {
+        let Expr::Call {
+            callee,
+            arguments,
+            keyword_args,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let (
+            Expr::Variable { name, .. },
+            [
+                Expr::Literal {
+                    value: Literal::String(spec),
+                    ..
+                },
+            ],
+        ) = (&**callee, arguments.as_slice())
+        else {
+            return None;
+        };
+        if name.lexeme != "tools_from_openapi" || keyword_args.keys().any(|key| key != "allow") {
+            return None;
+        }
+        let allow = match keyword_args.get("allow") {
+            Some(Expr::List { elements, .. }) => elements
+                .iter()
+                .map(|element| match element {
+                    Expr::Literal {
+                        value: Literal::String(id),
+                        ..
+                    } => Some(*id),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()?,
+            Some(_) => return None,
+            None => Vec::new(),
+        };
+        Some((*spec, allow))
+    }
+}
+
 #[derive(Debug)]
 pub enum Stmt<'gc> {
     Use {
         path: Token<'gc>,
+        // The name the module is bound to, e.g. `use std.math as m;`
+        alias: Option<Token<'gc>>,
+        // The imported names and their aliases, e.g. `use std.http.{get, post as send};`
+        items: Vec<(Token<'gc>, Option<Token<'gc>>)>,
         line: u32,
     },
     Enum(EnumDecl<'gc>),
@@ -458,6 +616,11 @@ pub enum Stmt<'gc> {
         value: Option<Expr<'gc>>,
         line: u32,
     },
+    // Suspend the generator and hand the value to its caller
+    Yield {
+        value: Option<Expr<'gc>>,
+        line: u32,
+    },
     // Block return just provides the block's value
     BlockReturn {
         value: Expr<'gc>,
@@ -465,6 +628,15 @@ pub enum Stmt<'gc> {
     },
     Class(ClassDecl<'gc>),
     Agent(AgentDecl<'gc>),
+    Workflow(WorkflowDecl<'gc>),
+    // Run the body with the value returned by `__enter__()` of the manager,
+    // `__exit__()` is called however the body is left.
+    With {
+        manager: Expr<'gc>,
+        name: Option<Token<'gc>>,
+        body: Box<Stmt<'gc>>,
+        line: u32,
+    },
 }
 
 impl Stmt<'_> {
@@ -483,9 +655,12 @@ impl Stmt<'_> {
             | Self::Function(FunctionDecl { line, .. })
             | Self::Raise { line, .. }
             | Self::Return { line, .. }
+            | Self::Yield { line, .. }
             | Self::BlockReturn { line, .. }
             | Self::Class(ClassDecl { line, .. })
-            | Self::Agent(AgentDecl { line, .. }) => *line,
+            | Self::Agent(AgentDecl { line, .. })
+            | Self::Workflow(WorkflowDecl { line, .. })
+            | Self::With { line, .. } => *line,
         }
     }
 }
@@ -524,12 +699,15 @@ impl std::hash::Hash for Literal<'_> {
 #[derive(Debug)]
 pub struct Program<'gc> {
     pub statements: Vec<Stmt<'gc>>,
+    /// Whether the file starts with the `#strict` pragma.
+    pub strict: bool,
 }
 
 impl Program<'_> {
     pub fn new() -> Self {
         Self {
             statements: Vec::new(),
+            strict: false,
         }
     }
 }
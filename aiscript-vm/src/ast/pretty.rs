@@ -99,8 +99,14 @@ impl Stmt<'_> {
                     )
                     .unwrap();
                 }
-                if let Some(ret_type) = &func.return_type {
-                    writeln!(f, "{}Return Type: {}", indent(level + 1), ret_type.lexeme).unwrap();
+                if !func.return_types.is_empty() {
+                    let return_types = func
+                        .return_types
+                        .iter()
+                        .map(|ty| ty.lexeme)
+                        .collect::<Vec<_>>()
+                        .join(" | ");
+                    writeln!(f, "{}Return Type: {}", indent(level + 1), return_types).unwrap();
                 }
                 if !func.error_types.is_empty() {
                     writeln!(f, "{}Error Types:", indent(level + 1)).unwrap();
@@ -119,6 +125,12 @@ impl Stmt<'_> {
                     val.fmt_with_indent(f, level + 1);
                 }
             }
+            Self::Yield { value, .. } => {
+                writeln!(f, "{ind}Yield").unwrap();
+                if let Some(val) = value {
+                    val.fmt_with_indent(f, level + 1);
+                }
+            }
             Self::BlockReturn { value, .. } => {
                 writeln!(f, "{ind}BlockReturn").unwrap();
                 value.fmt_with_indent(f, level + 1);
@@ -139,6 +151,20 @@ impl Stmt<'_> {
                 writeln!(f, "{}Body:", indent(level + 1)).unwrap();
                 body.fmt_with_indent(f, level + 2);
             }
+            Self::With {
+                manager,
+                name,
+                body,
+                ..
+            } => {
+                match name {
+                    Some(name) => writeln!(f, "{ind}With {}", name.lexeme).unwrap(),
+                    None => writeln!(f, "{ind}With").unwrap(),
+                }
+                manager.fmt_with_indent(f, level + 1);
+                writeln!(f, "{}Body:", indent(level + 1)).unwrap();
+                body.fmt_with_indent(f, level + 2);
+            }
             Self::Break { .. } => writeln!(f, "{ind}Break").unwrap(),
             Self::Continue { .. } => writeln!(f, "{ind}Continue").unwrap(),
             Self::Class(class) => {
@@ -172,6 +198,19 @@ impl Stmt<'_> {
                     tool.fmt_with_indent(f, level + 2);
                 }
             }
+            Self::Workflow(workflow) => {
+                writeln!(f, "{ind}Workflow {}", workflow.name.lexeme).unwrap();
+                writeln!(f, "{}Steps:", indent(level + 1)).unwrap();
+                for step in &workflow.steps {
+                    step.function.fmt_with_indent(f, level + 2);
+                }
+                if !workflow.compensations.is_empty() {
+                    writeln!(f, "{}Compensations:", indent(level + 1)).unwrap();
+                    for compensation in &workflow.compensations {
+                        compensation.fmt_with_indent(f, level + 2);
+                    }
+                }
+            }
             Self::Raise { error, .. } => {
                 writeln!(f, "{ind}Raise").unwrap();
                 error.fmt_with_indent(f, level + 1);
@@ -303,8 +342,17 @@ impl Expr<'_> {
                     }
                 }
             }
-            Self::Lambda { params, body, .. } => {
-                writeln!(f, "{ind}Lambda").unwrap();
+            Self::Lambda {
+                params,
+                body,
+                capture_by_value,
+                ..
+            } => {
+                if *capture_by_value {
+                    writeln!(f, "{ind}Lambda (move)").unwrap();
+                } else {
+                    writeln!(f, "{ind}Lambda").unwrap();
+                }
                 writeln!(f, "{}Parameters:", indent(level + 1)).unwrap();
                 for param in params {
                     writeln!(f, "{}{}", indent(level + 2), param.lexeme).unwrap();
@@ -104,6 +104,7 @@ fn insert<'gc>(
 
     // Insert the value at the specified position
     list_mut.data.insert(index, value);
+    list_mut.track_allocation();
 
     Ok(receiver)
 }
@@ -325,8 +326,8 @@ fn sort<'gc>(
     // This could be expanded to support custom comparators
     if reverse {
         list_mut.data.sort_by(|a, b| {
-            if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                y.partial_cmp(x).unwrap()
+            if let Some(ordering) = b.compare_number(a) {
+                ordering
             } else {
                 // For non-numeric values, just keep their order
                 std::cmp::Ordering::Equal
@@ -334,8 +335,8 @@ fn sort<'gc>(
         });
     } else {
         list_mut.data.sort_by(|a, b| {
-            if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                x.partial_cmp(y).unwrap()
+            if let Some(ordering) = a.compare_number(b) {
+                ordering
             } else {
                 // For non-numeric values, just keep their order
                 std::cmp::Ordering::Equal
@@ -13,12 +13,15 @@ pub(super) fn bool<'gc>(
 
     match &args[0] {
         Value::Number(n) => Ok(Value::Boolean(*n != 0.0)),
+        Value::Int(n) => Ok(Value::Boolean(*n != 0)),
         Value::String(s) => Ok(Value::Boolean(!s.is_empty())),
         Value::IoString(s) => Ok(Value::Boolean(!s.is_empty())),
         Value::Boolean(b) => Ok(Value::Boolean(*b)),
         Value::Nil => Ok(Value::Boolean(false)),
         Value::List(list) => Ok(Value::Boolean(!list.borrow().data.is_empty())),
         Value::Object(obj) => Ok(Value::Boolean(!obj.borrow().fields.is_empty())),
+        Value::Dict(dict) => Ok(Value::Boolean(!dict.borrow().is_empty())),
+        Value::Set(set) => Ok(Value::Boolean(!set.borrow().is_empty())),
         _ => Ok(Value::Boolean(true)),
     }
 }
@@ -35,6 +38,8 @@ pub(super) fn float<'gc>(
 
     match &args[0] {
         Value::Number(n) => Ok(Value::Number(*n)),
+        Value::Int(n) => Ok(Value::Number(*n as f64)),
+        Value::Decimal(d) => Ok(Value::Number(d.to_f64())),
         Value::String(s) /*| Value::IoString(s)*/ => {
             let s = s.to_string();
             match s.parse::<f64>() {
@@ -65,19 +70,27 @@ pub(super) fn int<'gc>(
     }
 
     match &args[0] {
-        Value::Number(n) => Ok(Value::Number(n.trunc())),
+        Value::Number(n) => float_to_int(*n),
+        Value::Int(n) => Ok(Value::Int(*n)),
+        Value::Decimal(d) => d.to_i64().map(Value::Int).ok_or_else(|| {
+            VmError::RuntimeError(format!("could not convert {} to int", d))
+        }),
         Value::String(s) /*| Value::IoString(s)*/ => {
             let s = s.to_string();
-            match s.parse::<f64>() {
-                Ok(n) => Ok(Value::Number(n.trunc())),
-                Err(_) => Err(VmError::RuntimeError(format!(
-                    "could not convert string to int: '{}'",
-                    s
-                ))),
+            // Parse as integer first, as parsing as float loses precision beyond 2^53
+            match s.trim().parse::<i64>() {
+                Ok(n) => Ok(Value::Int(n)),
+                Err(_) => match s.parse::<f64>() {
+                    Ok(n) => float_to_int(n),
+                    Err(_) => Err(VmError::RuntimeError(format!(
+                        "could not convert string to int: '{}'",
+                        s
+                    ))),
+                },
             }
         }
-        Value::Boolean(b) => Ok(Value::Number(if *b { 1.0 } else { 0.0 })),
-        Value::Nil => Ok(Value::Number(0.0)),
+        Value::Boolean(b) => Ok(Value::Int(*b as i64)),
+        Value::Nil => Ok(Value::Int(0)),
         _ => Err(VmError::RuntimeError(format!(
             "could not convert {} to int",
             args[0]
@@ -85,6 +98,18 @@ pub(super) fn int<'gc>(
     }
 }
 
+fn float_to_int<'gc>(n: f64) -> Result<Value<'gc>, VmError> {
+    let n = n.trunc();
+    if n.is_finite() && n >= i64::MIN as f64 && n < i64::MAX as f64 {
+        Ok(Value::Int(n as i64))
+    } else {
+        Err(VmError::RuntimeError(format!(
+            "could not convert {} to int",
+            n
+        )))
+    }
+}
+
 pub(super) fn ascii<'gc>(
     state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
@@ -125,6 +150,7 @@ pub(super) fn chr<'gc>(
 
     let num = match args[0] {
         Value::Number(n) => n as u32,
+        Value::Int(n) => n as u32,
         _ => {
             return Err(VmError::RuntimeError(
                 "chr() argument must be an integer.".into(),
@@ -187,7 +213,7 @@ pub(super) fn str<'gc>(
         ));
     }
 
-    let s = args[0].to_string();
+    let s = state.stringify(args[0])?;
     Ok(Value::IoString(Gc::new(state, s)))
 }
 
@@ -203,6 +229,7 @@ pub(super) fn bin<'gc>(
 
     let num = match args[0] {
         Value::Number(n) => n as i64,
+        Value::Int(n) => n,
         _ => {
             return Err(VmError::RuntimeError(
                 "bin() argument must be an integer.".into(),
@@ -233,6 +260,7 @@ pub(super) fn hex<'gc>(
 
     let num = match args[0] {
         Value::Number(n) => n as i64,
+        Value::Int(n) => n,
         _ => {
             return Err(VmError::RuntimeError(
                 "hex() argument must be an integer.".into(),
@@ -263,6 +291,7 @@ pub(super) fn oct<'gc>(
 
     let num = match args[0] {
         Value::Number(n) => n as i64,
+        Value::Int(n) => n,
         _ => {
             return Err(VmError::RuntimeError(
                 "oct() argument must be an integer.".into(),
@@ -0,0 +1,166 @@
+use aiscript_arena::{Gc, Mutation};
+use chrono::{Datelike, Timelike};
+use std::collections::HashMap;
+
+use crate::datetime::Zone;
+use crate::string::InternedString;
+use crate::{BuiltinMethod, Value, VmError, vm::Context};
+
+pub(crate) fn define_datetime_methods(ctx: Context) -> HashMap<InternedString, BuiltinMethod> {
+    [
+        // Conversion
+        ("format", BuiltinMethod(format)),
+        ("to_tz", BuiltinMethod(to_tz)),
+        ("timestamp", BuiltinMethod(timestamp)),
+        // Fields in the timezone
+        ("year", BuiltinMethod(year)),
+        ("month", BuiltinMethod(month)),
+        ("day", BuiltinMethod(day)),
+        ("hour", BuiltinMethod(hour)),
+        ("minute", BuiltinMethod(minute)),
+        ("second", BuiltinMethod(second)),
+        ("weekday", BuiltinMethod(weekday)),
+        ("tz", BuiltinMethod(tz)),
+        ("offset", BuiltinMethod(offset)),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect()
+}
+
+/// The timezone argument, a tz database name or an offset, None if omitted.
+pub(crate) fn zone_arg(value: Option<&Value>, fn_name: &str) -> Result<Option<Zone>, VmError> {
+    match value {
+        None | Some(Value::Nil) => Ok(None),
+        Some(value) => value
+            .as_string_value()
+            .map_err(|_| VmError::RuntimeError(format!("{fn_name}: timezone must be a string")))?
+            .as_str()
+            .parse()
+            .map(Some)
+            .map_err(|e| VmError::RuntimeError(format!("{fn_name}: {e}"))),
+    }
+}
+
+// ISO-8601 without a pattern, RFC 2822 with "rfc2822", or else the strftime pattern.
+fn format<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dt = receiver.as_datetime()?;
+    let formatted = match args.first() {
+        None | Some(Value::Nil) => dt.to_string(),
+        Some(pattern) => {
+            let pattern = pattern
+                .as_string_value()
+                .map_err(|_| VmError::RuntimeError("format: pattern must be a string".into()))?;
+            match pattern.as_str() {
+                p if p.eq_ignore_ascii_case("rfc2822") => dt.to_rfc2822(),
+                p => dt
+                    .format(p)
+                    .map_err(|e| VmError::RuntimeError(format!("format: {e}")))?,
+            }
+        }
+    };
+    Ok(Value::IoString(Gc::new(mc, formatted)))
+}
+
+// The same instant in another timezone.
+fn to_tz<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dt = receiver.as_datetime()?;
+    let zone = zone_arg(args.first(), "to_tz")?
+        .ok_or_else(|| VmError::RuntimeError("to_tz: expected a timezone".into()))?;
+    Ok(Value::DateTime(Gc::new(mc, dt.with_zone(zone))))
+}
+
+// The seconds since the Unix epoch
+fn timestamp<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Number(receiver.as_datetime()?.timestamp()))
+}
+
+fn year<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().year().into()))
+}
+
+fn month<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().month().into()))
+}
+
+fn day<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().day().into()))
+}
+
+fn hour<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().hour().into()))
+}
+
+fn minute<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().minute().into()))
+}
+
+fn second<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_datetime()?.local().second().into()))
+}
+
+// 1 for Monday to 7 for Sunday, as in ISO-8601
+fn weekday<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let weekday = receiver.as_datetime()?.local().weekday();
+    Ok(Value::Int(weekday.number_from_monday().into()))
+}
+
+// The name of the timezone, or its offset
+fn tz<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let zone = receiver.as_datetime()?.zone();
+    Ok(Value::IoString(Gc::new(mc, zone.to_string())))
+}
+
+// The seconds east of UTC at the instant, e.g. 3600 in Paris in winter
+fn offset<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let local = receiver.as_datetime()?.local();
+    Ok(Value::Int(local.offset().local_minus_utc().into()))
+}
@@ -0,0 +1,177 @@
+use aiscript_arena::{Gc, Mutation};
+use std::collections::HashMap;
+
+use crate::string::InternedString;
+use crate::{
+    BuiltinMethod, Value, VmError,
+    decimal::{MAX_PLACES, RoundingMode},
+    vm::Context,
+};
+
+pub(crate) fn define_decimal_methods(ctx: Context) -> HashMap<InternedString, BuiltinMethod> {
+    [
+        // Rounding
+        ("round", BuiltinMethod(round)),
+        ("rescale", BuiltinMethod(rescale)),
+        ("normalize", BuiltinMethod(normalize)),
+        ("abs", BuiltinMethod(abs)),
+        // Inspection
+        ("scale", BuiltinMethod(scale)),
+        ("sign", BuiltinMethod(sign)),
+        ("is_zero", BuiltinMethod(is_zero)),
+        // Conversion
+        ("format", BuiltinMethod(format)),
+        ("to_float", BuiltinMethod(to_float)),
+        ("to_int", BuiltinMethod(to_int)),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect()
+}
+
+/// The number of fractional digits at the index, 0 if omitted, at most
+/// [`MAX_PLACES`].
+pub(crate) fn places_arg(args: &[Value], index: usize, fn_name: &str) -> Result<u32, VmError> {
+    match args.get(index) {
+        None | Some(Value::Nil) => Ok(0),
+        Some(value) => value
+            .as_exact_int()
+            .and_then(|places| u32::try_from(places).ok())
+            .filter(|places| *places <= MAX_PLACES)
+            .ok_or_else(|| {
+                VmError::RuntimeError(format!(
+                    "{fn_name}: decimal places must be an integer from 0 to {MAX_PLACES}"
+                ))
+            }),
+    }
+}
+
+/// The rounding mode at the index, half to even if omitted.
+pub(crate) fn rounding_mode_arg(
+    args: &[Value],
+    index: usize,
+    fn_name: &str,
+) -> Result<RoundingMode, VmError> {
+    match args.get(index) {
+        None | Some(Value::Nil) => Ok(RoundingMode::default()),
+        Some(value) => value
+            .as_string_value()
+            .map_err(|_| {
+                VmError::RuntimeError(format!("{fn_name}: rounding mode must be a string"))
+            })?
+            .as_str()
+            .parse()
+            .map_err(|e| VmError::RuntimeError(format!("{fn_name}: {e}"))),
+    }
+}
+
+// Round to the number of fractional digits, 0 by default
+fn round<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    let places = places_arg(&args, 0, "round")?;
+    let mode = rounding_mode_arg(&args, 1, "round")?;
+    Ok(Value::Decimal(Gc::new(mc, d.round(places, mode))))
+}
+
+// Round or pad with zeros to exactly the number of fractional digits
+fn rescale<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    let places = places_arg(&args, 0, "rescale")?;
+    let mode = rounding_mode_arg(&args, 1, "rescale")?;
+    Ok(Value::Decimal(Gc::new(mc, d.rescale(places, mode))))
+}
+
+// Remove the trailing zeros of the fraction
+fn normalize<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    Ok(Value::Decimal(Gc::new(mc, d.normalize())))
+}
+
+fn abs<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    Ok(Value::Decimal(Gc::new(mc, d.abs())))
+}
+
+// The number of fractional digits
+fn scale<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_decimal()?.scale() as i64))
+}
+
+// -1, 0 or 1
+fn sign<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Int(receiver.as_decimal()?.signum()))
+}
+
+fn is_zero<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Boolean(receiver.as_decimal()?.is_zero()))
+}
+
+// Format with exactly the number of fractional digits, the integer
+// digits are grouped by thousands with the optional separator.
+fn format<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    let places = places_arg(&args, 0, "format")?;
+    let separator = match args.get(1) {
+        None | Some(Value::Nil) => None,
+        Some(value) => Some(
+            value
+                .as_string_value()
+                .map_err(|_| VmError::RuntimeError("format: separator must be a string".into()))?,
+        ),
+    };
+    let mode = rounding_mode_arg(&args, 2, "format")?;
+    let formatted = d.format(places, mode, separator.as_ref().map(|s| s.as_str()));
+    Ok(Value::IoString(Gc::new(mc, formatted)))
+}
+
+fn to_float<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Number(receiver.as_decimal()?.to_f64()))
+}
+
+// The integer part
+fn to_int<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let d = receiver.as_decimal()?;
+    d.to_i64()
+        .map(Value::Int)
+        .ok_or_else(|| VmError::RuntimeError(format!("to_int: {d} is out of range")))
+}
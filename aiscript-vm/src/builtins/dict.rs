@@ -0,0 +1,190 @@
+use aiscript_arena::{Gc, Mutation, RefLock};
+use std::collections::HashMap;
+
+use crate::string::InternedString;
+use crate::{
+    BuiltinMethod, Value, VmError,
+    dict::Dict,
+    object::List,
+    vm::{Context, State},
+};
+
+pub(crate) fn define_dict_methods(ctx: Context) -> HashMap<InternedString, BuiltinMethod> {
+    [
+        // Access
+        ("get", BuiltinMethod(get)),
+        ("set", BuiltinMethod(set)),
+        ("has", BuiltinMethod(has)),
+        ("remove", BuiltinMethod(remove)),
+        ("clear", BuiltinMethod(clear)),
+        // Views, in insertion order
+        ("keys", BuiltinMethod(keys)),
+        ("values", BuiltinMethod(values)),
+        ("items", BuiltinMethod(items)),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect()
+}
+
+/// Create a dict, empty or from an object, another dict or an array
+/// of `(key, value)` pairs.
+pub(super) fn dict<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() > 1 {
+        return Err(VmError::RuntimeError(
+            "dict() takes at most one argument.".into(),
+        ));
+    }
+
+    let mut dict = Dict::default();
+    match args.first() {
+        None | Some(Value::Nil) => {}
+        Some(Value::Object(obj)) => {
+            for (key, value) in &obj.borrow().fields {
+                dict.insert(Value::String(*key), *value)
+                    .map_err(VmError::RuntimeError)?;
+            }
+        }
+        Some(Value::Dict(other)) => {
+            for (key, value) in other.borrow().iter() {
+                dict.insert(key, value).map_err(VmError::RuntimeError)?;
+            }
+        }
+        Some(Value::List(list)) => {
+            for pair in &list.borrow().data {
+                let (key, value) = match pair {
+                    Value::List(pair) if pair.borrow().data.len() == 2 => {
+                        let pair = &pair.borrow().data;
+                        (pair[0], pair[1])
+                    }
+                    _ => {
+                        return Err(VmError::RuntimeError(
+                            "dict() array items must be (key, value) pairs.".into(),
+                        ));
+                    }
+                };
+                dict.insert(key, value).map_err(VmError::RuntimeError)?;
+            }
+        }
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "dict() argument must be an object, a dict or an array of pairs.".into(),
+            ));
+        }
+    }
+    Ok(Value::Dict(Gc::new(state, RefLock::new(dict))))
+}
+
+// The value of the key, or the default (nil if omitted) if it's missing
+fn get<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let key = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("get: expected 1 or 2 arguments".into()))?;
+    let value = dict.borrow().get(key).map_err(VmError::RuntimeError)?;
+    Ok(value.unwrap_or_else(|| args.get(1).copied().unwrap_or_default()))
+}
+
+// Insert or replace the value of the key
+fn set<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError("set: expected 2 arguments".into()));
+    }
+    dict.borrow_mut(mc)
+        .insert(args[0], args[1])
+        .map_err(VmError::RuntimeError)?;
+
+    // Return the dict for method chaining
+    Ok(receiver)
+}
+
+fn has<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let key = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("has: expected 1 argument".into()))?;
+    let found = dict
+        .borrow()
+        .contains_key(key)
+        .map_err(VmError::RuntimeError)?;
+    Ok(Value::Boolean(found))
+}
+
+// Remove the key and return its value, nil if it's missing
+fn remove<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let key = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("remove: expected 1 argument".into()))?;
+    let value = dict
+        .borrow_mut(mc)
+        .remove(key)
+        .map_err(VmError::RuntimeError)?;
+    Ok(value.unwrap_or_default())
+}
+
+fn clear<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    receiver.as_dict()?.borrow_mut(mc).clear();
+    Ok(receiver)
+}
+
+fn keys<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let keys = dict.borrow().iter().map(|(key, _)| key).collect();
+    Ok(Value::array(mc, keys))
+}
+
+fn values<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let values = dict.borrow().iter().map(|(_, value)| value).collect();
+    Ok(Value::array(mc, values))
+}
+
+// An array of (key, value) tuples
+fn items<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let dict = receiver.as_dict()?;
+    let items = dict
+        .borrow()
+        .iter()
+        .map(|(key, value)| {
+            Value::List(Gc::new(mc, RefLock::new(List::tuple(mc, vec![key, value]))))
+        })
+        .collect();
+    Ok(Value::array(mc, items))
+}
@@ -12,6 +12,11 @@ pub fn create_validation_error(ctx: Context) -> GcRefLock<'_, Class> {
     Gc::new(&ctx, RefLock::new(error_class))
 }
 
+pub fn create_json_error<'gc>(ctx: Context<'gc>) -> GcRefLock<'gc, Class<'gc>> {
+    let error_class = Class::new(ctx.intern(b"JsonError!"));
+    Gc::new(&ctx, RefLock::new(error_class))
+}
+
 // Helper to create error info object
 pub fn create_error_info<'gc>(
     ctx: Context<'gc>,
@@ -139,6 +139,16 @@ impl FormatSpec {
                     }
                 }
             },
+            Value::Int(n) => match self.format_type {
+                Some('x') => format!("{:x}", n),
+                Some('X') => format!("{:X}", n),
+                Some('o') => format!("{:o}", n),
+                Some('b') => format!("{:b}", n),
+                Some('f') | None if self.precision.is_some() => {
+                    format!("{:.*}", self.precision.unwrap(), *n as f64)
+                }
+                _ => format!("{}", n),
+            },
             Value::String(s) => {
                 let s = s.to_str().unwrap();
                 if let Some(precision) = self.precision {
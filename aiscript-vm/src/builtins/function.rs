@@ -10,12 +10,13 @@ pub(super) fn map<'gc>(
         ));
     }
 
-    // Get the iterable
+    // Get the iterable, a set is iterated in insertion order
     let vec = match &args[0] {
-        Value::List(list) => &list.borrow().data,
+        Value::List(list) => list.borrow().data.clone(),
+        Value::Set(set) => set.borrow().iter().collect(),
         _ => {
             return Err(VmError::RuntimeError(
-                "map() first argument must be an array.".into(),
+                "map() first argument must be an array or a set.".into(),
             ));
         }
     };
@@ -60,12 +61,13 @@ pub(super) fn filter<'gc>(
         ));
     }
 
-    // Get the iterable
+    // Get the iterable, a set is iterated in insertion order
     let vec = match &args[0] {
-        Value::List(list) => &list.borrow().data,
+        Value::List(list) => list.borrow().data.clone(),
+        Value::Set(set) => set.borrow().iter().collect(),
         _ => {
             return Err(VmError::RuntimeError(
-                "filter() first argument must be an array.".into(),
+                "filter() first argument must be an array or a set.".into(),
             ));
         }
     };
@@ -102,6 +104,36 @@ pub(super) fn filter<'gc>(
     Ok(Value::array(state, result))
 }
 
+/// `fallback(|| prompt "...", default)`, call the function and return its result,
+/// or the default value if the call fails, e.g. the AI provider or an upstream is down.
+/// The request is flagged as degraded when the default value is served.
+pub(super) fn fallback<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(
+            "fallback() takes exactly 2 arguments.".into(),
+        ));
+    }
+
+    let Value::Closure(closure) = args[0] else {
+        return Err(VmError::RuntimeError(
+            "fallback() first argument must be a function.".into(),
+        ));
+    };
+
+    match state.try_eval_closure(closure, &[]) {
+        Ok(value) => Ok(value),
+        // A compile error can't be recovered from
+        Err(VmError::CompileError) => Err(VmError::CompileError),
+        Err(_) => {
+            state.degraded = true;
+            Ok(args[1])
+        }
+    }
+}
+
 pub(super) fn zip<'gc>(
     state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
@@ -0,0 +1,348 @@
+use std::fmt::Write;
+
+use crate::{Value, VmError, module::ModuleKind, object::Function, vm::State};
+
+// The signatures and the docs of the natives, by their qualified name.
+const NATIVE_DOCS: &[(&str, &str, &str)] = &[
+    ("abs", "abs(x)", "Return the absolute value of a number."),
+    (
+        "all",
+        "all(array)",
+        "Return true if all the elements of the array are truthy.",
+    ),
+    (
+        "any",
+        "any(array)",
+        "Return true if any element of the array is truthy.",
+    ),
+    (
+        "ascii",
+        "ascii(value)",
+        "Return the printable representation of the value, non-ASCII characters escaped.",
+    ),
+    (
+        "assert_snapshot",
+        "assert_snapshot(value, name = nil)",
+        "Compare the value to its snapshot in tests/snapshots, written on the first run.",
+    ),
+    (
+        "bin",
+        "bin(x)",
+        "Convert an integer to a binary string prefixed with '0b'.",
+    ),
+    ("bool", "bool(value)", "Return whether the value is truthy."),
+    (
+        "callable",
+        "callable(value)",
+        "Return true if the value can be called.",
+    ),
+    (
+        "chr",
+        "chr(i)",
+        "Return the character of the Unicode code point.",
+    ),
+    (
+        "dict",
+        "dict(items = nil)",
+        "Create a dict, from an object or an array of [key, value] pairs if given.",
+    ),
+    (
+        "fallback",
+        "fallback(function, default)",
+        "Call the function and return its result, or the default value if the call fails.",
+    ),
+    (
+        "filter",
+        "filter(iterable, function)",
+        "Return the elements of the array or set the function returns true for.",
+    ),
+    (
+        "float",
+        "float(value)",
+        "Convert a number or a string to a float.",
+    ),
+    (
+        "format",
+        "format(template, *args)",
+        "Format the template, replacing its {} placeholders with the arguments.",
+    ),
+    (
+        "help",
+        "help(value)",
+        "Print the signature and the docstring of a function, class, agent or module.",
+    ),
+    (
+        "hex",
+        "hex(x)",
+        "Convert an integer to a hexadecimal string prefixed with '0x'.",
+    ),
+    (
+        "input",
+        "input(prompt = nil)",
+        "Read a line from the standard input, after printing the prompt if given.",
+    ),
+    (
+        "int",
+        "int(value)",
+        "Convert a number or a string to an integer.",
+    ),
+    (
+        "len",
+        "len(value)",
+        "Return the length of a string, bytes, array, object, dict or set.",
+    ),
+    (
+        "map",
+        "map(iterable, function)",
+        "Return an array of the results of calling the function on each element.",
+    ),
+    (
+        "max",
+        "max(*values)",
+        "Return the largest of the numbers, or of the elements of a single array.",
+    ),
+    (
+        "min",
+        "min(*values)",
+        "Return the smallest of the numbers, or of the elements of a single array.",
+    ),
+    (
+        "oct",
+        "oct(x)",
+        "Convert an integer to an octal string prefixed with '0o'.",
+    ),
+    (
+        "ord",
+        "ord(c)",
+        "Return the Unicode code point of the character.",
+    ),
+    (
+        "print",
+        "print(*values, sep = \" \", end = \"\\n\", flush = false)",
+        "Print the values separated by sep and followed by end.",
+    ),
+    (
+        "round",
+        "round(x)",
+        "Round a number to the nearest integer.",
+    ),
+    (
+        "set",
+        "set(items = nil)",
+        "Create a set, from the elements of an array if given.",
+    ),
+    ("str", "str(value)", "Convert the value to a string."),
+    (
+        "sum",
+        "sum(array)",
+        "Return the sum of the numbers of the array.",
+    ),
+    (
+        "zip",
+        "zip(*arrays)",
+        "Return an array of tuples of the elements at the same index of the arrays.",
+    ),
+    ("std.math.add", "add(x, y)", "Return x + y."),
+    ("std.math.sub", "sub(x, y)", "Return x - y."),
+    ("std.math.mul", "mul(x, y)", "Return x * y."),
+    ("std.math.div", "div(x, y)", "Return x / y."),
+    (
+        "std.math.floor_div",
+        "floor_div(x, y)",
+        "Return x / y rounded down, an integer if both are integers, like the `~/` operator.",
+    ),
+    ("std.math.sqrt", "sqrt(x)", "Return the square root of x."),
+    (
+        "std.math.pow",
+        "pow(x, y)",
+        "Return x raised to the power y.",
+    ),
+    (
+        "std.math.log",
+        "log(x, base = E)",
+        "Return the logarithm of x, the natural one by default.",
+    ),
+    ("std.math.sin", "sin(x)", "Return the sine of x radians."),
+    ("std.math.cos", "cos(x)", "Return the cosine of x radians."),
+    ("std.math.tan", "tan(x)", "Return the tangent of x radians."),
+    (
+        "std.math.asin",
+        "asin(x)",
+        "Return the arc sine of x, in radians.",
+    ),
+    (
+        "std.math.acos",
+        "acos(x)",
+        "Return the arc cosine of x, in radians.",
+    ),
+    (
+        "std.math.floor",
+        "floor(x)",
+        "Return the largest integer less than or equal to x.",
+    ),
+    (
+        "std.math.ceil",
+        "ceil(x)",
+        "Return the smallest integer greater than or equal to x.",
+    ),
+    (
+        "std.math.round",
+        "round(x)",
+        "Round x to the nearest integer.",
+    ),
+    ("std.math.abs", "abs(x)", "Return the absolute value of x."),
+    (
+        "std.math.min",
+        "min(x, y)",
+        "Return the smaller of x and y.",
+    ),
+    ("std.math.max", "max(x, y)", "Return the larger of x and y."),
+];
+
+/// Print the signature and the docstring of a function, class, agent or module.
+pub(super) fn help<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError(
+            "help() takes exactly one argument.".into(),
+        ));
+    }
+
+    let mut out = String::new();
+    match args[0] {
+        Value::Closure(closure) => write_function(&mut out, &closure.function, ""),
+        Value::BoundMethod(method) => write_function(&mut out, &method.method.function, ""),
+        Value::NativeFunction(function) => {
+            let name = state.native_name(function);
+            match name.as_deref().and_then(native_doc) {
+                Some((signature, doc)) => {
+                    let _ = writeln!(out, "fn {signature}");
+                    write_doc(&mut out, Some(doc), "    ");
+                }
+                None => {
+                    let name = name.as_deref().unwrap_or("<native fn>");
+                    let _ = writeln!(out, "fn {}(...)", name.rsplit('.').next().unwrap());
+                }
+            }
+        }
+        Value::Class(class) => {
+            let class = class.borrow();
+            let _ = writeln!(out, "class {}", class.name);
+            let doc = class.doc.map(|doc| doc.to_string());
+            write_doc(&mut out, doc.as_deref(), "    ");
+            let mut methods = class
+                .methods
+                .values()
+                .chain(class.static_methods.values())
+                .filter_map(|method| method.as_closure().ok())
+                .collect::<Vec<_>>();
+            methods.sort_by_key(|method| method.function.name.map(|name| name.to_string()));
+            for method in methods {
+                out.push('\n');
+                write_function(&mut out, &method.function, "    ");
+            }
+        }
+        Value::Agent(agent) => {
+            let _ = writeln!(out, "agent {}", agent.name);
+            let doc = agent.doc.map(|doc| doc.to_string());
+            write_doc(&mut out, doc.as_deref(), "    ");
+            let mut tools = agent.tools.iter().collect::<Vec<_>>();
+            tools.sort_by_key(|(name, _)| *name);
+            for (name, tool) in tools {
+                let params = tool.params.keys().cloned().collect::<Vec<_>>().join(", ");
+                let _ = writeln!(out, "\n    fn {name}({params})");
+                if !tool.doc.is_empty() {
+                    write_doc(&mut out, Some(tool.doc.as_str()), "        ");
+                }
+            }
+        }
+        Value::Workflow(workflow) => {
+            let _ = writeln!(out, "workflow {}", workflow.name);
+            let doc = workflow.doc.map(|doc| doc.to_string());
+            write_doc(&mut out, doc.as_deref(), "    ");
+            for step in &workflow.steps {
+                let _ = write!(out, "\n    fn {}(ctx)", step.name);
+                if step.retry.times > 0 {
+                    let _ = write!(out, " retry {}", step.retry.times);
+                }
+                if let Some((compensate, _)) = &step.compensate {
+                    let _ = write!(out, " compensate {compensate}");
+                }
+                out.push('\n');
+            }
+        }
+        Value::Module(name) => {
+            let _ = writeln!(out, "module {name}");
+            if let Some(module) = state.module_manager.modules.get(&name) {
+                let mut exports = match module {
+                    ModuleKind::Script { exports, .. } | ModuleKind::Native { exports, .. } => {
+                        exports
+                            .keys()
+                            .map(|name| name.to_string())
+                            .collect::<Vec<_>>()
+                    }
+                };
+                exports.sort();
+                for export in exports {
+                    let _ = writeln!(out, "    {export}");
+                }
+            }
+        }
+        _ => {
+            return Err(VmError::RuntimeError(
+                "help() argument must be a function, class, agent, workflow or module.".into(),
+            ));
+        }
+    }
+    print!("{out}");
+    Ok(Value::Nil)
+}
+
+/// The `__doc__` property of a function, class, agent or workflow, nil if it has no
+/// docstring, `None` if the value has no docstring at all.
+pub(crate) fn docstring<'gc>(state: &mut State<'gc>, value: Value<'gc>) -> Option<Value<'gc>> {
+    let doc = match value {
+        Value::Closure(closure) => closure.function.doc,
+        Value::BoundMethod(method) => method.method.function.doc,
+        Value::Class(class) => class.borrow().doc,
+        Value::Agent(agent) => agent.doc,
+        Value::Workflow(workflow) => workflow.doc,
+        Value::NativeFunction(function) => state
+            .native_name(function)
+            .as_deref()
+            .and_then(native_doc)
+            .map(|(_, doc)| state.intern_static(doc)),
+        _ => return None,
+    };
+    Some(doc.map_or(Value::Nil, Value::String))
+}
+
+fn native_doc(name: &str) -> Option<(&'static str, &'static str)> {
+    NATIVE_DOCS
+        .iter()
+        .find(|(native, ..)| *native == name)
+        .map(|(_, signature, doc)| (*signature, *doc))
+}
+
+fn write_function(out: &mut String, function: &Function, indent: &str) {
+    let signature = match (function.signature, function.name) {
+        (Some(signature), _) => signature.to_string(),
+        (None, Some(name)) => format!("{name}()"),
+        (None, None) => "<fn>()".to_owned(),
+    };
+    let _ = writeln!(out, "{indent}fn {signature}");
+    let doc = function.doc.map(|doc| doc.to_string());
+    write_doc(out, doc.as_deref(), &format!("{indent}    "));
+}
+
+fn write_doc(out: &mut String, doc: Option<&str>, indent: &str) {
+    for line in doc.unwrap_or_default().lines() {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = writeln!(out, "{indent}{line}");
+        }
+    }
+}
@@ -11,11 +11,17 @@ use std::{
 
 mod array;
 mod convert;
+pub(crate) mod datetime;
+pub(crate) mod decimal;
+mod dict;
 mod error;
 mod format;
 mod function;
+mod help;
 mod print;
 pub(crate) mod response;
+mod set;
+mod snapshot;
 pub(crate) mod sso;
 mod string;
 
@@ -23,13 +29,20 @@ use convert::*;
 pub use error::*;
 use format::format;
 use function::*;
+pub(crate) use help::docstring;
+use help::help;
 use print::print;
+use snapshot::assert_snapshot;
 
 #[derive(Collect)]
 #[collect(no_drop)]
 pub(crate) struct BuiltinMethods<'gc> {
     string: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
     array: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
+    decimal: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
+    datetime: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
+    dict: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
+    set: HashMap<InternedString<'gc>, BuiltinMethod<'gc>>,
 }
 
 impl Default for BuiltinMethods<'_> {
@@ -43,12 +56,20 @@ impl<'gc> BuiltinMethods<'gc> {
         BuiltinMethods {
             string: HashMap::default(),
             array: HashMap::default(),
+            decimal: HashMap::default(),
+            datetime: HashMap::default(),
+            dict: HashMap::default(),
+            set: HashMap::default(),
         }
     }
 
     pub fn init(&mut self, ctx: Context<'gc>) {
         self.string = string::define_string_methods(ctx);
         self.array = array::define_array_methods(ctx);
+        self.decimal = decimal::define_decimal_methods(ctx);
+        self.datetime = datetime::define_datetime_methods(ctx);
+        self.dict = dict::define_dict_methods(ctx);
+        self.set = set::define_set_methods(ctx);
     }
 
     pub fn invoke_string_method(
@@ -84,6 +105,74 @@ impl<'gc> BuiltinMethods<'gc> {
             )))
         }
     }
+
+    pub fn invoke_decimal_method(
+        &self,
+        mc: &'gc Mutation<'gc>,
+        name: InternedString<'gc>,
+        receiver: Value<'gc>,
+        args: Vec<Value<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        if let Some(f) = self.decimal.get(&name) {
+            f(mc, receiver, args)
+        } else {
+            Err(VmError::RuntimeError(format!(
+                "Unknown decimal method: {}",
+                name
+            )))
+        }
+    }
+
+    pub fn invoke_datetime_method(
+        &self,
+        mc: &'gc Mutation<'gc>,
+        name: InternedString<'gc>,
+        receiver: Value<'gc>,
+        args: Vec<Value<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        if let Some(f) = self.datetime.get(&name) {
+            f(mc, receiver, args)
+        } else {
+            Err(VmError::RuntimeError(format!(
+                "Unknown datetime method: {}",
+                name
+            )))
+        }
+    }
+
+    pub fn invoke_dict_method(
+        &self,
+        mc: &'gc Mutation<'gc>,
+        name: InternedString<'gc>,
+        receiver: Value<'gc>,
+        args: Vec<Value<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        if let Some(f) = self.dict.get(&name) {
+            f(mc, receiver, args)
+        } else {
+            Err(VmError::RuntimeError(format!(
+                "Unknown dict method: {}",
+                name
+            )))
+        }
+    }
+
+    pub fn invoke_set_method(
+        &self,
+        mc: &'gc Mutation<'gc>,
+        name: InternedString<'gc>,
+        receiver: Value<'gc>,
+        args: Vec<Value<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        if let Some(f) = self.set.get(&name) {
+            f(mc, receiver, args)
+        } else {
+            Err(VmError::RuntimeError(format!(
+                "Unknown set method: {}",
+                name
+            )))
+        }
+    }
 }
 
 pub(crate) fn define_builtin_functions(state: &mut State) {
@@ -92,13 +181,17 @@ pub(crate) fn define_builtin_functions(state: &mut State) {
         ("all", NativeFn(all)),
         ("any", NativeFn(any)),
         ("ascii", NativeFn(ascii)),
+        ("assert_snapshot", NativeFn(assert_snapshot)),
         ("bin", NativeFn(bin)),
         ("bool", NativeFn(bool)),
         ("callable", NativeFn(callable)),
         ("chr", NativeFn(chr)),
+        ("dict", NativeFn(dict::dict)),
+        ("fallback", NativeFn(fallback)),
         ("filter", NativeFn(filter)),
         ("float", NativeFn(float)),
         ("format", NativeFn(format)),
+        ("help", NativeFn(help)),
         ("hex", NativeFn(hex)),
         ("input", NativeFn(input)),
         ("int", NativeFn(int)),
@@ -110,6 +203,7 @@ pub(crate) fn define_builtin_functions(state: &mut State) {
         ("ord", NativeFn(ord)),
         ("print", NativeFn(print)),
         ("round", NativeFn(round)),
+        ("set", NativeFn(set::set)),
         ("str", NativeFn(str)),
         ("sum", NativeFn(sum)),
         ("zip", NativeFn(zip)),
@@ -127,6 +221,9 @@ fn abs<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
 
     match args[0] {
         Value::Number(n) => Ok(n.abs().into()),
+        Value::Int(n) => Ok(n
+            .checked_abs()
+            .map_or(Value::Number((n as f64).abs()), Value::Int)),
         _ => Err(VmError::RuntimeError(
             "abs() argument must be a number.".into(),
         )),
@@ -143,10 +240,13 @@ fn len<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
     match &args[0] {
         Value::String(s) => Ok(Value::Number(s.len() as f64)),
         Value::IoString(s) => Ok(Value::Number(s.len() as f64)),
+        Value::Bytes(bytes) => Ok(Value::Number(bytes.len() as f64)),
         Value::List(arr) => Ok(Value::Number(arr.borrow().data.len() as f64)),
         Value::Object(obj) => Ok(Value::Number(obj.borrow().fields.len() as f64)),
+        Value::Dict(dict) => Ok(Value::Number(dict.borrow().len() as f64)),
+        Value::Set(set) => Ok(Value::Number(set.borrow().len() as f64)),
         _ => Err(VmError::RuntimeError(
-            "len() argument must be a string, array or object.".into(),
+            "len() argument must be a string, bytes, array, object, dict or set.".into(),
         )),
     }
 }
@@ -203,12 +303,9 @@ fn min<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
                     return Err(VmError::RuntimeError("min() arg is an empty array".into()));
                 }
                 arr.iter()
-                    .min_by(|a, b| {
-                        if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                            x.partial_cmp(y).unwrap()
-                        } else {
-                            panic!("min() array elements must be numbers")
-                        }
+                    .min_by(|a, b| match a.compare_number(b) {
+                        Some(ordering) => ordering,
+                        None => panic!("min() array elements must be numbers"),
                     })
                     .copied()
                     .ok_or_else(|| VmError::RuntimeError("min() array must not be empty".into()))
@@ -220,12 +317,9 @@ fn min<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
     } else {
         // Multiple arguments case
         args.iter()
-            .min_by(|a, b| {
-                if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                    x.partial_cmp(y).unwrap()
-                } else {
-                    panic!("min() arguments must be numbers")
-                }
+            .min_by(|a, b| match a.compare_number(b) {
+                Some(ordering) => ordering,
+                None => panic!("min() arguments must be numbers"),
             })
             .copied()
             .ok_or_else(|| VmError::RuntimeError("min() arguments must be numbers".into()))
@@ -248,12 +342,9 @@ fn max<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
                     return Err(VmError::RuntimeError("max() arg is an empty array".into()));
                 }
                 arr.iter()
-                    .max_by(|a, b| {
-                        if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                            x.partial_cmp(y).unwrap()
-                        } else {
-                            panic!("max() array elements must be numbers")
-                        }
+                    .max_by(|a, b| match a.compare_number(b) {
+                        Some(ordering) => ordering,
+                        None => panic!("max() array elements must be numbers"),
                     })
                     .copied()
                     .ok_or_else(|| VmError::RuntimeError("max() array must not be empty".into()))
@@ -265,12 +356,9 @@ fn max<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
     } else {
         // Multiple arguments case
         args.iter()
-            .max_by(|a, b| {
-                if let (Value::Number(x), Value::Number(y)) = (a, b) {
-                    x.partial_cmp(y).unwrap()
-                } else {
-                    panic!("max() arguments must be numbers")
-                }
+            .max_by(|a, b| match a.compare_number(b) {
+                Some(ordering) => ordering,
+                None => panic!("max() arguments must be numbers"),
             })
             .copied()
             .ok_or_else(|| VmError::RuntimeError("max() arguments must be numbers".into()))
@@ -286,6 +374,7 @@ fn round<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'g
 
     match args[0] {
         Value::Number(n) => Ok(n.round().into()),
+        Value::Int(n) => Ok(Value::Int(n)),
         _ => Err(VmError::RuntimeError(
             "round() argument must be a number.".into(),
         )),
@@ -303,16 +392,26 @@ fn sum<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>
         Value::List(arr) => {
             let arr = &arr.borrow().data;
             let mut sum = 0.0;
+            // The sum stays an integer as long as the elements are integers
+            let mut int_sum = Some(0i64);
             for value in arr.iter() {
-                if let Value::Number(n) = value {
-                    sum += n;
-                } else {
-                    return Err(VmError::RuntimeError(
-                        "sum() array elements must be numbers.".into(),
-                    ));
+                match value {
+                    Value::Number(n) => {
+                        sum += n;
+                        int_sum = None;
+                    }
+                    Value::Int(n) => {
+                        sum += *n as f64;
+                        int_sum = int_sum.and_then(|int_sum| int_sum.checked_add(*n));
+                    }
+                    _ => {
+                        return Err(VmError::RuntimeError(
+                            "sum() array elements must be numbers.".into(),
+                        ));
+                    }
                 }
             }
-            Ok(sum.into())
+            Ok(int_sum.map_or(sum.into(), Value::Int))
         }
         _ => Err(VmError::RuntimeError(
             "sum() argument must be an array.".into(),
@@ -355,6 +454,10 @@ fn callable<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value
 
     Ok(Value::Boolean(matches!(
         args[0],
-        Value::Closure(_) | Value::NativeFunction(_) | Value::BoundMethod(_) | Value::Class(_)
+        Value::Closure(_)
+            | Value::NativeFunction(_)
+            | Value::HostFunction(_)
+            | Value::BoundMethod(_)
+            | Value::Class(_)
     )))
 }
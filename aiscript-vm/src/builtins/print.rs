@@ -1,5 +1,4 @@
 use crate::{Value, VmError, vm::State};
-use std::fmt::Write;
 
 /// Print objects to the text stream file, separated by sep and followed by end.
 ///
@@ -12,7 +11,7 @@ use std::fmt::Write;
 ///
 /// fn print(*objects, sep=" ", end="\n", file=nil, flush=false) {}
 pub(super) fn print<'gc>(
-    _state: &mut State<'gc>,
+    state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
     // Extract keyword arguments with defaults
@@ -66,7 +65,7 @@ pub(super) fn print<'gc>(
         if i > 0 {
             output.push_str(sep);
         }
-        write!(output, "{}", arg).unwrap();
+        output.push_str(&state.stringify(**arg)?);
     }
     output.push_str(end);
 
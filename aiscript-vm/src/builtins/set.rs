@@ -0,0 +1,174 @@
+use aiscript_arena::{Gc, Mutation, RefLock};
+use std::collections::HashMap;
+
+use crate::string::InternedString;
+use crate::{
+    BuiltinMethod, Value, VmError,
+    set::Set,
+    vm::{Context, State},
+};
+
+pub(crate) fn define_set_methods(ctx: Context) -> HashMap<InternedString, BuiltinMethod> {
+    [
+        // Basic operations
+        ("add", BuiltinMethod(add)),
+        ("remove", BuiltinMethod(remove)),
+        ("contains", BuiltinMethod(contains)),
+        // Set algebra
+        ("union", BuiltinMethod(union)),
+        ("intersection", BuiltinMethod(intersection)),
+        ("difference", BuiltinMethod(difference)),
+        ("is_subset", BuiltinMethod(is_subset)),
+        // Conversion
+        ("to_array", BuiltinMethod(to_array)),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect()
+}
+
+// The set of the values of an array, a tuple, a set or the keys of a dict.
+fn collect_set<'gc>(value: &Value<'gc>, fn_name: &str) -> Result<Set<'gc>, VmError> {
+    let set = match value {
+        Value::List(list) => Set::from_values(list.borrow().data.iter().copied()),
+        Value::Set(set) => Set::from_values(set.borrow().iter()),
+        Value::Dict(dict) => Set::from_values(dict.borrow().iter().map(|(key, _)| key)),
+        _ => {
+            return Err(VmError::RuntimeError(format!(
+                "{fn_name}: argument must be an array, a set or a dict"
+            )));
+        }
+    };
+    set.map_err(VmError::RuntimeError)
+}
+
+fn new_set<'gc>(mc: &Mutation<'gc>, set: Set<'gc>) -> Value<'gc> {
+    Value::Set(Gc::new(mc, RefLock::new(set)))
+}
+
+/// Create a set, empty or from the values of an array, a tuple, another
+/// set or the keys of a dict, duplicates are dropped.
+pub(super) fn set<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() > 1 {
+        return Err(VmError::RuntimeError(
+            "set() takes at most one argument.".into(),
+        ));
+    }
+
+    let set = match args.first() {
+        None | Some(Value::Nil) => Set::default(),
+        Some(value) => collect_set(value, "set()")?,
+    };
+    Ok(new_set(state, set))
+}
+
+// Add a value, returns whether it wasn't in the set yet
+fn add<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let value = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("add: expected 1 argument".into()))?;
+    let added = set
+        .borrow_mut(mc)
+        .insert(*value)
+        .map_err(VmError::RuntimeError)?;
+    Ok(Value::Boolean(added))
+}
+
+// Remove a value, returns whether it was in the set
+fn remove<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let value = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("remove: expected 1 argument".into()))?;
+    let removed = set
+        .borrow_mut(mc)
+        .remove(value)
+        .map_err(VmError::RuntimeError)?;
+    Ok(Value::Boolean(removed))
+}
+
+fn contains<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let value = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("contains: expected 1 argument".into()))?;
+    let found = set
+        .borrow()
+        .contains(value)
+        .map_err(VmError::RuntimeError)?;
+    Ok(Value::Boolean(found))
+}
+
+// The values of either set, the receiver's first
+fn union<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let other = collect_set(args.first().unwrap_or(&Value::Nil), "union")?;
+    let result = set.borrow().union(&other);
+    Ok(new_set(mc, result))
+}
+
+// The values in both sets
+fn intersection<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let other = collect_set(args.first().unwrap_or(&Value::Nil), "intersection")?;
+    let result = set.borrow().intersection(&other);
+    Ok(new_set(mc, result))
+}
+
+// The values of the receiver not in the other set
+fn difference<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let other = collect_set(args.first().unwrap_or(&Value::Nil), "difference")?;
+    let result = set.borrow().difference(&other);
+    Ok(new_set(mc, result))
+}
+
+// Whether all the values are in the other set, e.g. the required permissions
+fn is_subset<'gc>(
+    _mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let other = collect_set(args.first().unwrap_or(&Value::Nil), "is_subset")?;
+    Ok(Value::Boolean(set.borrow().is_subset(&other)))
+}
+
+// The values in insertion order
+fn to_array<'gc>(
+    mc: &'gc Mutation<'gc>,
+    receiver: Value<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let set = receiver.as_set()?;
+    let values = set.borrow().iter().collect();
+    Ok(Value::array(mc, values))
+}
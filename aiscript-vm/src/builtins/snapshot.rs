@@ -0,0 +1,157 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::{
+    Value, VmError,
+    vm::{Capability, State},
+};
+
+const SNAPSHOT_DIR: &str = "tests/snapshots";
+// Set to accept the changed snapshots instead of failing on them.
+const UPDATE_ENV: &str = "AISCRIPT_UPDATE_SNAPSHOTS";
+
+/// Compare the value to its snapshot stored in tests/snapshots, the
+/// snapshot is written on the first run. The unnamed snapshots of a script
+/// are numbered in their order. A string is stored as is, e.g. a
+/// rendered prompt, other values as JSON with sorted keys.
+///
+/// fn assert_snapshot(value, name = nil) {}
+pub(super) fn assert_snapshot<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "assert_snapshot() takes 1 or 2 arguments.".into(),
+        ));
+    }
+    state.require(Capability::Fs)?;
+    let snapshot = match args[0] {
+        Value::String(_) | Value::IoString(_) => args[0].to_string(),
+        value => render(state.jsonify(value)?),
+    };
+    let name = match args.get(1) {
+        None | Some(Value::Nil) => {
+            state.snapshot_count += 1;
+            state.snapshot_count.to_string()
+        }
+        Some(Value::String(_) | Value::IoString(_)) => args[1].to_string(),
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "assert_snapshot() name must be a string.".into(),
+            ));
+        }
+    };
+    let script = state
+        .script_path
+        .as_ref()
+        .and_then(|path| path.file_stem())
+        .map_or("snapshot".into(), |stem| stem.to_string_lossy());
+    let path = PathBuf::from(SNAPSHOT_DIR).join(format!("{script}__{name}.snap"));
+    let new_path = path.with_extension("snap.new");
+
+    let io_error = |err: std::io::Error| {
+        VmError::RuntimeError(format!(
+            "Failed to write the snapshot '{}': {err}",
+            path.display()
+        ))
+    };
+    match fs::read_to_string(&path) {
+        Ok(stored) if stored == snapshot => {
+            // A pending review is resolved
+            let _ = fs::remove_file(&new_path);
+            Ok(Value::Nil)
+        }
+        Ok(stored) if env::var_os(UPDATE_ENV).is_none() => {
+            fs::write(&new_path, &snapshot).map_err(io_error)?;
+            Err(VmError::RuntimeError(format!(
+                "Snapshot '{name}' doesn't match '{}'{}\nReview '{}', or rerun with {UPDATE_ENV}=1 to accept it.",
+                path.display(),
+                first_difference(&stored, &snapshot),
+                new_path.display(),
+            )))
+        }
+        _ => {
+            fs::create_dir_all(SNAPSHOT_DIR).map_err(io_error)?;
+            fs::write(&path, &snapshot).map_err(io_error)?;
+            let _ = fs::remove_file(&new_path);
+            eprintln!("Snapshot written to '{}'", path.display());
+            Ok(Value::Nil)
+        }
+    }
+}
+
+// The pretty JSON of the value with sorted keys and normalized numbers, so
+// the same value always has the same snapshot.
+fn render(value: serde_json::Value) -> String {
+    let mut snapshot = serde_json::to_string_pretty(&normalize(value)).unwrap_or_default();
+    snapshot.push('\n');
+    snapshot
+}
+
+// Sort the keys and write the integral floats as integers, numbers are
+// floats in scripts, e.g. `1` would be `1.0` otherwise.
+fn normalize(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Number(n) => match n.as_f64() {
+            Some(f) if n.is_f64() && f.fract() == 0.0 && f.abs() < 9e15 => (f as i64).into(),
+            _ => serde_json::Value::Number(n),
+        },
+        serde_json::Value::Array(items) => items.into_iter().map(normalize).collect(),
+        serde_json::Value::Object(fields) => {
+            let mut fields = fields.into_iter().collect::<Vec<_>>();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            fields
+                .into_iter()
+                .map(|(key, value)| (key, normalize(value)))
+                .collect()
+        }
+        value => value,
+    }
+}
+
+fn first_difference(stored: &str, snapshot: &str) -> String {
+    let mut stored_lines = stored.lines();
+    let mut lines = snapshot.lines();
+    for line in 1.. {
+        match (stored_lines.next(), lines.next()) {
+            (None, None) => break,
+            (a, b) if a == b => {}
+            (a, b) => {
+                return format!(
+                    ", first difference at line {line}:\n- {}\n+ {}",
+                    a.unwrap_or_default(),
+                    b.unwrap_or_default()
+                );
+            }
+        }
+    }
+    String::from(", the line endings differ.")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_render_normalized() {
+        let value = json!({"b": 1.0, "a": [2.5, -0.0, {"d": null, "c": 3}]});
+        assert_eq!(
+            render(value),
+            "{\n  \"a\": [\n    2.5,\n    0,\n    {\n      \"c\": 3,\n      \"d\": null\n    }\n  ],\n  \"b\": 1\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(
+            first_difference("a\nb\nc", "a\nx\nc"),
+            ", first difference at line 2:\n- b\n+ x"
+        );
+        assert_eq!(
+            first_difference("a", "a\nb"),
+            ", first difference at line 2:\n- \n+ b"
+        );
+    }
+}
@@ -10,20 +10,31 @@ use crate::{
     Value,
     ast::{ChunkId, Visibility},
     object::ListKind,
+    vm::JsonFormat,
 };
 
-#[derive(Copy, Clone, Collect, PartialEq)]
+#[derive(Copy, Clone, Debug, Collect, PartialEq)]
 #[collect(require_static)]
 pub enum OpCode {
     Constant(u8),
     Return,
+    Yield,
     Add,
     Subtract,
     Multiply,
     Divide,
+    // Division rounded towards negative infinity, `~/`
+    FloorDivide,
     Modulo,
     Power,
     Negate,
+    // The bitwise operators of integers
+    BitAnd,
+    BitOr,
+    BitXor,
+    ShiftLeft,
+    ShiftRight,
+    BitNot,
     Nil,
     Bool(bool),
     Not,
@@ -53,14 +64,18 @@ pub enum OpCode {
     JumpIfError(u16), // Jump to error handler if top of stack is error
     Jump(u16),
     Loop(u16),
+    // The calls with a `keyword_spread` have the mapping spread into their
+    // keyword arguments on top of them.
     Constructor {
         positional_count: u8,
         keyword_count: u8,
+        keyword_spread: bool,
         validate: bool,
     },
     Call {
         positional_count: u8,
         keyword_count: u8,
+        keyword_spread: bool,
     },
     Closure {
         chunk_id: ChunkId,
@@ -73,17 +88,33 @@ pub enum OpCode {
         name_constant: u8,
         evaluate: bool,
     },
-    Class(u8),
+    // Create a class with its docstring and `@deprecated` note, if any
+    Class {
+        name_constant: u8,
+        doc_constant: Option<u8>,
+        deprecated_constant: Option<u8>,
+        experimental: bool,
+    },
     SetProperty(u8),
     GetProperty(u8),
     Method {
         name_constant: u8,
         is_static: bool,
     },
+    Accessor {
+        name_constant: u8,
+        is_setter: bool,
+    },
+    // Declare the JSON format of a field of the class
+    JsonFormat {
+        name_constant: u8,
+        format: JsonFormat,
+    },
     Invoke {
         method_constant: u8,
         positional_count: u8,
         keyword_count: u8,
+        keyword_spread: bool,
     },
     Inherit,
     GetSuper(u8),
@@ -91,6 +122,7 @@ pub enum OpCode {
         method_constant: u8,
         positional_count: u8,
         keyword_count: u8,
+        keyword_spread: bool,
     },
     MakeObject(u8), //  number of key-value pairs in the object
     MakeList {
@@ -102,16 +134,27 @@ pub enum OpCode {
     GetIndex,
     In,
     EnvLookup,
-    // Import a module, constant index contains module name
-    ImportModule(u8),
+    // Import a module and bind it to the name, if any
+    ImportModule {
+        module_name_constant: u8,
+        name_constant: Option<u8>,
+    },
     // Get variable from module (module name index, var name index)
     GetModuleVar {
         module_name_constant: u8,
         var_name_constant: u8,
     },
     // AI
-    Prompt,
-    Agent(u8), // constant index
+    Prompt {
+        // Push a JsonError! instead of raising a runtime error if the JSON repair fails.
+        handle_error: bool,
+    },
+    Agent(u8),    // constant index
+    Workflow(u8), // constant index
+    // Replace the manager with the value of its `__enter__()`, see `with` statement.
+    EnterContext,
+    // Call `__exit__()` of the last entered manager.
+    ExitContext,
 }
 
 impl OpCode {
@@ -145,6 +188,19 @@ pub struct Chunk<'gc> {
     constans: Vec<Value<'gc>>,
     #[collect(require_static)]
     pub(crate) lines: Vec<u32>,
+    // The local variables declared by the user, for the debugger.
+    #[collect(require_static)]
+    pub(crate) locals: Vec<LocalVar>,
+}
+
+/// A local variable of a chunk, it lives in the slot from the instruction
+/// `start` until the instruction `end`.
+#[derive(Debug, Clone)]
+pub(crate) struct LocalVar {
+    pub name: String,
+    pub slot: u8,
+    pub start: usize,
+    pub end: usize,
 }
 
 impl Default for Chunk<'_> {
@@ -174,6 +230,7 @@ impl<'gc> Chunk<'gc> {
             code: Vec::new(),
             constans: Vec::new(),
             lines: Vec::new(),
+            locals: Vec::new(),
         }
     }
 
@@ -206,6 +263,14 @@ impl<'gc> Chunk<'gc> {
         self.constans.len() - 1
     }
 
+    pub fn constant_count(&self) -> usize {
+        self.constans.len()
+    }
+
+    pub(crate) fn constants(&self) -> &[Value<'gc>] {
+        &self.constans
+    }
+
     #[inline]
     pub fn read_constant(&self, byte: u8) -> Value<'gc> {
         // self.constans[byte as usize]
@@ -227,24 +292,65 @@ impl<'gc> Chunk<'gc> {
             println!("{:4} {:4} {:16} CIndex Constvalue", "IP", "Line", "OPCode",);
         });
 
-        print!("{:04} ", offset);
+        println!("{}", self.instruction_line(offset));
+        offset + 1
+    }
+
+    /// The listing of the chunk with its constants, the line of every
+    /// instruction and the targets of the jumps.
+    pub fn disassembly(&self, name: impl Display) -> String {
+        let mut out = format!("== {name} ==\n");
+        if !self.constans.is_empty() {
+            out.push_str("constants:\n");
+            for (index, constant) in self.constans.iter().enumerate() {
+                out.push_str(&format!("{index:6} {constant}\n"));
+            }
+        }
+        out.push_str(&format!(
+            "{:4} {:4} {:16} CIndex Constvalue\n",
+            "IP", "Line", "OPCode"
+        ));
+        for offset in 0..self.code.len() {
+            out.push_str(&self.instruction_line(offset));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The instruction at the offset, prefixed by the offset and its line,
+    /// the line is `|` if it's the line of the previous instruction.
+    pub fn instruction_line(&self, offset: usize) -> String {
         if offset > 0 && self.lines[offset] == self.lines[offset - 1] {
-            print!("   | ");
+            format!("{offset:04}    | {}", self.instruction(offset))
         } else {
-            print!("{:4} ", self.lines[offset]);
+            format!(
+                "{offset:04} {:4} {}",
+                self.lines[offset],
+                self.instruction(offset)
+            )
         }
+    }
 
-        if let Some(code) = self.code.get(offset) {
-            match *code {
+    fn instruction(&self, offset: usize) -> String {
+        match self.code.get(offset) {
+            Some(code) => match *code {
                 OpCode::Return => simple_instruction("RETURN"),
+                OpCode::Yield => simple_instruction("YIELD"),
                 OpCode::Constant(c) => self.constant_instruction("CONSTANT", c),
                 OpCode::Add => simple_instruction("ADD"),
                 OpCode::Subtract => simple_instruction("SUBTRACT"),
                 OpCode::Multiply => simple_instruction("MULTIPLY"),
                 OpCode::Divide => simple_instruction("DIVIDE"),
+                OpCode::FloorDivide => simple_instruction("FLOOR_DIVIDE"),
                 OpCode::Modulo => simple_instruction("MODULO"),
                 OpCode::Power => simple_instruction("POWER"),
                 OpCode::Negate => simple_instruction("NEGATE"),
+                OpCode::BitAnd => simple_instruction("BIT_AND"),
+                OpCode::BitOr => simple_instruction("BIT_OR"),
+                OpCode::BitXor => simple_instruction("BIT_XOR"),
+                OpCode::ShiftLeft => simple_instruction("SHIFT_LEFT"),
+                OpCode::ShiftRight => simple_instruction("SHIFT_RIGHT"),
+                OpCode::BitNot => simple_instruction("BIT_NOT"),
                 OpCode::Nil => simple_instruction("NIL"),
                 OpCode::Bool(b) => simple_instruction(if b { "TRUE" } else { "FALSE" }),
                 OpCode::Not => simple_instruction("NOT"),
@@ -257,7 +363,7 @@ impl<'gc> Chunk<'gc> {
                 OpCode::LessEqual => simple_instruction("LESS_EQUAL"),
                 OpCode::BuildString(c) => self.constant_instruction("BUILD_STRING", c),
                 OpCode::Dup => simple_instruction("DUP"),
-                OpCode::Pop(count) => println!("{:-16} {:4}", "OP_POP", count),
+                OpCode::Pop(count) => format!("{:-16} {:4}", "OP_POP", count),
                 OpCode::DefineGlobal { name_constant, .. } => {
                     self.constant_instruction("DEFINE_GLOBAL", name_constant)
                 }
@@ -276,28 +382,34 @@ impl<'gc> Chunk<'gc> {
                 OpCode::Constructor {
                     positional_count,
                     keyword_count,
+                    keyword_spread,
                     validate,
                 } => {
-                    println!(
-                        "{:-16} {:4} {:4} {validate}",
-                        "OP_CONSTRUCTOR", positional_count, keyword_count
-                    );
+                    format!(
+                        "{:-16} {:4} {:4}{} {validate}",
+                        "OP_CONSTRUCTOR",
+                        positional_count,
+                        keyword_count,
+                        if keyword_spread { " **" } else { "" }
+                    )
                 }
                 OpCode::Call {
                     positional_count,
                     keyword_count,
+                    keyword_spread,
                 } => {
-                    println!(
-                        "{:-16} {:4} {:4}",
-                        "OP_CALL", positional_count, keyword_count
-                    );
+                    format!(
+                        "{:-16} {:4} {:4}{}",
+                        "OP_CALL",
+                        positional_count,
+                        keyword_count,
+                        if keyword_spread { " **" } else { "" }
+                    )
                 }
                 OpCode::Closure { chunk_id } => {
                     // let mut offset = offset + 1;
                     // let constant = self.code[offset] as usize;
                     // offset += 1;
-                    println!("{:-16} {:4}", "OP_CLOSURE", chunk_id);
-
                     // let function = self.constans[c as usize].as_closure().unwrap().function;
                     // function.upvalues.iter().for_each(|upvalue| {
                     //     let Upvalue { index, is_local } = *upvalue;
@@ -309,6 +421,7 @@ impl<'gc> Chunk<'gc> {
                     //         index,
                     //     );
                     // });
+                    format!("{:-16} {:4}", "OP_CLOSURE", chunk_id)
                 }
                 OpCode::GetUpvalue(c) => self.byte_instruction("GET_UPVALUE", c),
                 OpCode::SetUpvalue(c) => self.byte_instruction("SET_UPVALUE", c),
@@ -317,16 +430,24 @@ impl<'gc> Chunk<'gc> {
                 OpCode::EnumVariant {
                     name_constant,
                     evaluate,
-                } => println!(
+                } => format!(
                     "{:-16} {:4} evaluate:'{}'",
                     "OP_ENUM_VARIANT", name_constant, evaluate
                 ),
-                OpCode::Class(c) => self.constant_instruction("CLASS", c),
+                OpCode::Class { name_constant, .. } => {
+                    self.constant_instruction("CLASS", name_constant)
+                }
                 OpCode::SetProperty(c) => self.constant_instruction("SET_PROPERTY", c),
                 OpCode::GetProperty(c) => self.constant_instruction("GET_PROPERTY", c),
                 OpCode::Method { name_constant, .. } => {
                     self.constant_instruction("METHOD", name_constant)
                 }
+                OpCode::Accessor { name_constant, .. } => {
+                    self.constant_instruction("ACCESSOR", name_constant)
+                }
+                OpCode::JsonFormat { name_constant, .. } => {
+                    self.constant_instruction("JSON_FORMAT", name_constant)
+                }
                 OpCode::Invoke {
                     method_constant,
                     positional_count,
@@ -344,13 +465,18 @@ impl<'gc> Chunk<'gc> {
                     size_constant,
                     kind,
                 } => {
-                    println!("{:-16} {:4} {:?}", "OP_MAKE_LIST", size_constant, kind);
+                    format!("{:-16} {:4} {:?}", "OP_MAKE_LIST", size_constant, kind)
                 }
                 OpCode::GetIndex => simple_instruction("GET_INDEX"),
                 OpCode::SetIndex => simple_instruction("SET_INDEX"),
                 OpCode::In => simple_instruction("IN"),
+                OpCode::EnterContext => simple_instruction("ENTER_CONTEXT"),
+                OpCode::ExitContext => simple_instruction("EXIT_CONTEXT"),
                 OpCode::EnvLookup => simple_instruction("ENV_LOOKUP"),
-                OpCode::ImportModule(c) => self.constant_instruction("IMPORT_MODULE", c),
+                OpCode::ImportModule {
+                    module_name_constant,
+                    ..
+                } => self.constant_instruction("IMPORT_MODULE", module_name_constant),
                 OpCode::GetModuleVar {
                     module_name_constant,
                     var_name_constant,
@@ -359,55 +485,61 @@ impl<'gc> Chunk<'gc> {
                     module_name_constant,
                     var_name_constant,
                 ),
-                OpCode::Prompt => simple_instruction("PROMPT"),
+                OpCode::Prompt { handle_error } => {
+                    format!("{:-16} {handle_error}", "OP_PROMPT")
+                }
                 OpCode::Agent(c) => {
-                    println!("{:-16} {:4} '{}'", "OP_AGENT", c, self.constans[c as usize]);
+                    format!("{:-16} {:4} '{}'", "OP_AGENT", c, self.constans[c as usize])
+                }
+                OpCode::Workflow(c) => {
+                    format!(
+                        "{:-16} {:4} '{}'",
+                        "OP_WORKFLOW", c, self.constans[c as usize]
+                    )
                 }
                 OpCode::JumpIfError(jump) => {
                     self.jump_instruction("JUMP_IF_ERROR", 1, offset, jump)
                 }
-            }
-        } else {
-            println!("Invalid opcode at offset: {offset}");
+            },
+            None => format!("Invalid opcode at offset: {offset}"),
         }
-
-        offset + 1
     }
 
-    fn constant_instruction(&self, name: &str, constant: u8) {
+    fn constant_instruction(&self, name: &str, constant: u8) -> String {
         let name = format!("OP_{name}");
-        println!(
+        format!(
             "{:-16} {:4} '{}'",
             name, constant, self.constans[constant as usize]
-        );
+        )
     }
 
-    fn byte_instruction(&self, name: &str, byte: u8) {
+    fn byte_instruction(&self, name: &str, byte: u8) -> String {
         let name = format!("OP_{name}");
-        println!("{:-16} {:4}", name, byte);
+        format!("{:-16} {:4}", name, byte)
     }
 
-    fn jump_instruction(&self, name: &str, sign: i8, offset: usize, jump: u16) {
+    fn jump_instruction(&self, name: &str, sign: i8, offset: usize, jump: u16) -> String {
         let name = format!("OP_{name}");
-        // let jump = u16::from_be_bytes([self.code[offset + 1], self.code[offset + 2]]);
+        // The jump is relative to the next instruction
+        let next = offset + 1;
         let jump = if sign < 0 {
-            offset.saturating_sub(jump as usize)
+            next.saturating_sub(jump as usize)
         } else {
-            offset.saturating_add(jump as usize)
+            next.saturating_add(jump as usize)
         };
 
-        println!("{:-16} {:4} -> {}", name, offset, jump);
+        format!("{:-16} {:4} -> {}", name, offset, jump)
     }
 
-    fn invoke_instruction(&self, name: &str, constant: u8, arity: u8) {
+    fn invoke_instruction(&self, name: &str, constant: u8, arity: u8) -> String {
         let name = format!("OP_{name}");
-        println!(
+        format!(
             "{:-16} ({} args) {} '{}'",
             name, arity, constant, self.constans[constant as usize]
-        );
+        )
     }
 }
 
-fn simple_instruction(name: &str) {
-    println!("OP_{name}");
+fn simple_instruction(name: &str) -> String {
+    format!("OP_{name}")
 }
@@ -1,24 +1,29 @@
 use std::{
     collections::{HashMap, HashSet},
-    mem,
+    fs, mem,
     sync::atomic::{AtomicU16, Ordering},
 };
 
 use crate::{
     OpCode, Value,
-    ai::Agent,
+    ai::{Agent, ToolRef},
     ast::{
-        AgentDecl, ChunkId, ClassDecl, EnumDecl, ErrorHandler, Expr, FStringPart, FnDef,
+        AgentDecl, Arguments, ChunkId, ClassDecl, EnumDecl, ErrorHandler, Expr, FStringPart, FnDef,
         FunctionDecl, Literal, MatchArm, MatchPattern, Mutability, ObjectProperty, ParameterDecl,
-        Program, Stmt, VariableDecl,
+        ParameterKind, Program, Stmt, VariableDecl, Visibility, WorkflowDecl, WorkflowStepDecl,
     },
+    chunk::LocalVar,
     lexer::{Token, TokenType},
-    object::{Enum, EnumVariant, Function, FunctionType, Parameter, Upvalue},
+    module,
+    object::{Attributes, Enum, EnumVariant, Function, FunctionType, Parameter, Upvalue},
+    parser::Parser,
+    string::InternedString,
     ty::PrimitiveType,
     vm::{Context, VmError},
+    workflow::{Workflow, WorkflowStep},
 };
 use aiscript_arena::{Gc, GcRefLock, RefLock};
-use aiscript_lexer::ErrorReporter;
+use aiscript_lexer::{ErrorReporter, Warning};
 use indexmap::IndexMap;
 
 const MAX_LOCALS: usize = u8::MAX as usize + 1;
@@ -31,6 +36,10 @@ struct Local<'gc> {
     depth: isize,
     is_captured: bool,
     mutability: Mutability,
+    is_used: bool,
+    // What the local is declared as in the unused warning, e.g. "variable",
+    // none for the locals not declared by the user.
+    unused_warning: Option<&'static str>,
 }
 
 impl Local<'_> {
@@ -45,6 +54,9 @@ struct LoopScope {
     increment: usize,
     // Break jump positions to patch
     breaks: Vec<usize>,
+    // The with depth of the loop, the contexts entered in the body
+    // are exited by break and continue.
+    with_depth: usize,
 }
 
 pub struct CodeGen<'gc> {
@@ -55,17 +67,26 @@ pub struct CodeGen<'gc> {
     // Keep track user defiend enums, help to allow
     // declare enum variant as default function arguments
     defined_enums: HashMap<&'gc str, GcRefLock<'gc, Enum<'gc>>>,
+    // The imported script modules and the names they are bound to.
+    imported_modules: Vec<(Option<&'gc str>, &'gc str)>,
+    // The public functions of the imported modules referenced as agent tools.
+    module_functions: HashMap<&'gc str, HashMap<String, FnDef>>,
+    // The `@deprecated` top-level functions and classes with their notes,
+    // their references are warned at compile time.
+    deprecated_globals: HashMap<&'gc str, String>,
     function: Function<'gc>,
     fn_type: FunctionType,
     locals: [Local<'gc>; MAX_LOCALS],
     local_count: usize,
     scope_depth: isize,
     loop_scopes: Vec<LoopScope>,
+    // The number of `with` bodies the code is in
+    with_depth: usize,
     // Track constant globals
     const_globals: HashSet<&'gc str>,
     enclosing: Option<Box<CodeGen<'gc>>>,
     current_line: u32,
-    error_reporter: ErrorReporter,
+    error_reporter: ErrorReporter<'gc>,
 }
 
 impl<'gc> CodeGen<'gc> {
@@ -75,6 +96,9 @@ impl<'gc> CodeGen<'gc> {
             chunks: HashMap::new(),
             named_id_map: HashMap::new(),
             defined_enums: HashMap::new(),
+            imported_modules: Vec::new(),
+            module_functions: HashMap::new(),
+            deprecated_globals: HashMap::new(),
             function: Function::new(ctx.intern(name.as_bytes()), 0),
             fn_type,
             locals: std::array::from_fn(|i| {
@@ -84,12 +108,14 @@ impl<'gc> CodeGen<'gc> {
                 // internal use. We give it an empty name so that the user can’t write an
                 // identifier that refers to it.
                 if i == 0 {
-                    let name = if fn_type.is_constructor() || fn_type.is_method() {
-                        // Slot zero will store the instance in class methods.
-                        Token::new(TokenType::Self_, "self", 0)
-                    } else {
-                        Token::default()
-                    };
+                    let name =
+                        if fn_type.is_constructor() || fn_type.is_method() || fn_type.is_accessor()
+                        {
+                            // Slot zero will store the instance in class methods.
+                            Token::new(TokenType::Self_, "self", 0)
+                        } else {
+                            Token::default()
+                        };
                     Local {
                         name,
                         ..Local::default()
@@ -103,6 +129,7 @@ impl<'gc> CodeGen<'gc> {
             local_count: 1,
             scope_depth: 0,
             loop_scopes: Vec::new(),
+            with_depth: 0,
             const_globals: HashSet::new(),
             enclosing: None,
             current_line: 0,
@@ -121,13 +148,28 @@ impl<'gc> CodeGen<'gc> {
     pub fn generate(
         program: Program<'gc>,
         ctx: Context<'gc>,
-    ) -> Result<HashMap<ChunkId, Function<'gc>>, VmError> {
+        first_chunk_id: ChunkId,
+    ) -> Result<(HashMap<ChunkId, Function<'gc>>, Vec<Warning>), VmError> {
         // Reset CHUNK_ID initial value to get the same id for repeat compile
-        CHUNK_ID.store(0, Ordering::Relaxed);
+        CHUNK_ID.store(first_chunk_id, Ordering::Relaxed);
         let mut generator = Self::new(ctx, FunctionType::Script, "script");
 
         for stmt in &program.statements {
             generator.declare_functions(stmt)?;
+            let (name, attributes) = match stmt {
+                Stmt::Function(FunctionDecl {
+                    name, attributes, ..
+                })
+                | Stmt::Class(ClassDecl {
+                    name, attributes, ..
+                }) => (name, attributes),
+                _ => continue,
+            };
+            if let Some(note) = &attributes.deprecated {
+                generator
+                    .deprecated_globals
+                    .insert(name.lexeme, note.clone());
+            }
         }
 
         for stmt in program.statements {
@@ -143,12 +185,25 @@ impl<'gc> CodeGen<'gc> {
             generator
                 .chunks
                 .insert(CHUNK_ID.fetch_add(1, Ordering::AcqRel), function);
-            Ok(generator.chunks)
+            let mut warnings = mem::take(&mut generator.error_reporter.warnings);
+            warnings.sort_by_key(|warning| warning.line);
+            Ok((generator.chunks, warnings))
         }
     }
 
     fn declare_functions(&mut self, stmt: &Stmt<'gc>) -> Result<(), VmError> {
         match stmt {
+            Stmt::Use {
+                path, alias, items, ..
+            } if !path.lexeme.starts_with("std.") => {
+                let name = match alias {
+                    Some(alias) => Some(alias.lexeme),
+                    // A selective import doesn't bind the module
+                    None if items.is_empty() => path.lexeme.rsplit('.').next(),
+                    None => None,
+                };
+                self.imported_modules.push((name, path.lexeme));
+            }
             Stmt::Block { statements, .. } => {
                 for stmt in statements {
                     self.declare_functions(stmt)?;
@@ -164,7 +219,7 @@ impl<'gc> CodeGen<'gc> {
                     self.declare_functions(else_branch)?;
                 }
             }
-            Stmt::Loop { body, .. } => {
+            Stmt::Loop { body, .. } | Stmt::With { body, .. } => {
                 self.declare_functions(body)?;
             }
             Stmt::Let(VariableDecl {
@@ -197,18 +252,9 @@ impl<'gc> CodeGen<'gc> {
             }) => {
                 if !self.named_id_map.contains_key(mangled_name) {
                     let chunk_id = CHUNK_ID.fetch_add(1, Ordering::AcqRel);
-                    let primitive_params = params
-                        .iter()
-                        .map(|(name, param)| {
-                            (
-                                name.lexeme.to_owned(),
-                                PrimitiveType::from(param.type_hint.unwrap_or_default()),
-                            )
-                        })
-                        .collect();
                     self.named_id_map.insert(
                         mangled_name.to_owned(),
-                        FnDef::new(chunk_id, doc, primitive_params),
+                        FnDef::new(chunk_id, doc, primitive_params(params)),
                     );
                 } else {
                     self.error_at(*name, "A function with same name already exists.");
@@ -233,6 +279,18 @@ impl<'gc> CodeGen<'gc> {
                     self.declare_functions(tool)?;
                 }
             }
+            Stmt::Workflow(WorkflowDecl {
+                steps,
+                compensations,
+                ..
+            }) => {
+                for step in steps {
+                    self.declare_functions(&step.function)?;
+                }
+                for compensation in compensations {
+                    self.declare_functions(compensation)?;
+                }
+            }
             _ => {}
         }
 
@@ -243,12 +301,46 @@ impl<'gc> CodeGen<'gc> {
         let stmt = stmt.into();
         self.current_line = stmt.line();
         match stmt {
-            Stmt::Use { path, .. } => {
+            Stmt::Use {
+                path, alias, items, ..
+            } => {
                 // Load the module name as a constant
-                let module_name = self.identifier_constant(path.lexeme);
-                self.emit(OpCode::ImportModule(module_name as u8));
+                let module_name = self.identifier_constant(path.lexeme) as u8;
+                if items.is_empty() {
+                    // Bind the module to the alias or the last component of its path
+                    let name = alias
+                        .map(|alias| alias.lexeme)
+                        .or_else(|| path.lexeme.rsplit('.').next())
+                        .unwrap();
+                    let name = self.identifier_constant(name) as u8;
+                    self.emit(OpCode::ImportModule {
+                        module_name_constant: module_name,
+                        name_constant: Some(name),
+                    });
+                } else {
+                    self.emit(OpCode::ImportModule {
+                        module_name_constant: module_name,
+                        name_constant: None,
+                    });
+                    for (item, alias) in items {
+                        let var_name = self.identifier_constant(item.lexeme) as u8;
+                        self.emit(OpCode::GetModuleVar {
+                            module_name_constant: module_name,
+                            var_name_constant: var_name,
+                        });
+                        let name = alias.unwrap_or(item);
+                        let name = self.identifier_constant(name.lexeme) as u8;
+                        self.emit(OpCode::DefineGlobal {
+                            name_constant: name,
+                            visibility: Visibility::Private,
+                        });
+                    }
+                }
             }
             Stmt::Break { .. } => {
+                self.emit_context_exits(
+                    self.with_depth - self.loop_scopes.last().unwrap().with_depth,
+                );
                 let exit_jump = self.emit_jump(OpCode::Jump(0));
                 // Get the last scope's index
                 let last_idx = self.loop_scopes.len() - 1;
@@ -264,7 +356,9 @@ impl<'gc> CodeGen<'gc> {
                 }
 
                 if let Some(loop_scope) = self.loop_scopes.last() {
-                    self.emit_loop(loop_scope.increment);
+                    let (increment, with_depth) = (loop_scope.increment, loop_scope.with_depth);
+                    self.emit_context_exits(self.with_depth - with_depth);
+                    self.emit_loop(increment);
                 }
             }
             Stmt::Expression { expression, .. } => {
@@ -279,7 +373,10 @@ impl<'gc> CodeGen<'gc> {
                 visibility,
                 ..
             }) => {
-                self.declare_variable(name, Mutability::Mutable);
+                let pos = self.declare_variable(name, Mutability::Mutable);
+                if self.scope_depth > 0 {
+                    self.locals[pos].unused_warning = Some("variable");
+                }
                 if let Some(initial_value) = initializer {
                     self.generate_expr(initial_value)?;
                 } else {
@@ -315,6 +412,7 @@ impl<'gc> CodeGen<'gc> {
                 }
             }
             Stmt::Block { statements, .. } => {
+                self.warn_unreachable(&statements);
                 self.begin_scope();
                 for stmt in statements {
                     self.generate_stmt(stmt)?;
@@ -357,6 +455,7 @@ impl<'gc> CodeGen<'gc> {
                 let mut loop_scope = LoopScope {
                     increment: loop_start, // Will be updated for increment
                     breaks: Vec::new(),
+                    with_depth: self.with_depth,
                 };
 
                 // Generate condition
@@ -404,11 +503,12 @@ impl<'gc> CodeGen<'gc> {
             Stmt::Function(FunctionDecl {
                 name,
                 mangled_name,
+                doc,
                 params,
-                return_type,
                 body,
                 fn_type,
                 visibility,
+                attributes,
                 ..
             }) => {
                 self.declare_variable(name, Mutability::default());
@@ -416,14 +516,18 @@ impl<'gc> CodeGen<'gc> {
                     self.mark_initialized();
                 }
 
-                self.generate_function(
-                    name.lexeme,
-                    &mangled_name,
-                    params,
-                    return_type,
-                    body,
-                    fn_type,
-                )?;
+                let chunk_id =
+                    self.generate_function(name.lexeme, &mangled_name, params, body, fn_type)?;
+                self.set_doc(chunk_id, doc);
+                let deprecated = attributes
+                    .deprecated
+                    .map(|note| self.ctx.intern(note.as_bytes()));
+                if let Some(function) = self.chunks.get_mut(&chunk_id) {
+                    function.attributes = Attributes {
+                        deprecated,
+                        experimental: attributes.experimental,
+                    };
+                }
 
                 if self.scope_depth == 0 {
                     let global = self.identifier_constant(name.lexeme);
@@ -435,16 +539,53 @@ impl<'gc> CodeGen<'gc> {
             }
             Stmt::Raise { error, .. } => {
                 self.generate_expr(error)?;
+                self.emit_context_exits(self.with_depth);
                 self.emit(OpCode::Return);
             }
             Stmt::Return { value, .. } => {
                 if let Some(expr) = value {
                     self.generate_expr(expr)?;
+                    self.emit_context_exits(self.with_depth);
                     self.emit(OpCode::Return);
                 } else {
+                    self.emit_context_exits(self.with_depth);
                     self.emit_return();
                 }
             }
+            Stmt::With {
+                manager,
+                name,
+                body,
+                ..
+            } => {
+                self.begin_scope();
+                self.generate_expr(manager)?;
+                self.emit(OpCode::EnterContext);
+                match name {
+                    Some(name) => {
+                        self.declare_variable(name, Mutability::Mutable);
+                        self.mark_initialized();
+                    }
+                    None => self.emit(OpCode::Pop(1)),
+                }
+                self.with_depth += 1;
+                self.generate_stmt(body)?;
+                self.with_depth -= 1;
+                self.emit(OpCode::ExitContext);
+                self.end_scope();
+            }
+            Stmt::Yield { value, .. } => {
+                if self.with_depth > 0 {
+                    // The entered contexts belong to the frame, which a generator leaves on `yield`
+                    self.error("Can't yield inside a 'with' body.");
+                }
+                match value {
+                    Some(expr) => self.generate_expr(expr)?,
+                    None => self.emit(OpCode::Nil),
+                }
+                self.emit(OpCode::Yield);
+                self.function.is_generator = true;
+            }
             Stmt::BlockReturn { value, .. } => {
                 self.generate_expr(value)?;
                 // Don't emit Return - block value stays on stack
@@ -470,6 +611,10 @@ impl<'gc> CodeGen<'gc> {
                                 )
                             })
                             .collect(),
+                        variant_names: variants
+                            .iter()
+                            .map(|v| self.ctx.intern(v.name.lexeme.as_bytes()))
+                            .collect(),
                         methods: HashMap::default(),
                         static_methods: HashMap::default(),
                     }),
@@ -501,6 +646,7 @@ impl<'gc> CodeGen<'gc> {
             Stmt::Agent(AgentDecl {
                 name,
                 mangled_name,
+                doc,
                 fields,
                 tools,
                 visibility,
@@ -509,26 +655,59 @@ impl<'gc> CodeGen<'gc> {
                 // Emit agent declaration
                 let agent_name = self.ctx.intern(name.lexeme.as_bytes());
                 let mut agent = Agent::new(&self.ctx, agent_name)
-                    .parse_instructions(&fields)
+                    .parse_instructions(&fields, |token, path| {
+                        match fs::read_to_string(path.to_str().unwrap()) {
+                            Ok(content) => Some(self.ctx.intern(content.trim().as_bytes())),
+                            Err(err) => {
+                                self.error_at(
+                                    token,
+                                    &format!("Failed to read instructions file '{path}': {err}."),
+                                );
+                                None
+                            }
+                        }
+                    })
                     .parse_model(&fields)
-                    .parse_tools(&fields, |name| {
-                        let mut scopes = mangled_name.split("$").collect::<Vec<_>>();
-                        loop {
-                            if scopes.is_empty() {
+                    .parse_tools(&fields, |tool| {
+                        let (module, name) = match tool {
+                            ToolRef::Function { module, name } => (module, name),
+                            ToolRef::Invalid(expr) => {
+                                self.error_reporter.error_with_line(
+                                    expr.line(),
+                                    "Tools in agent declaration should be function names.",
+                                );
+                                return None;
+                            }
+                        };
+                        let fn_def = match module {
+                            Some(module) => self.find_module_function(Some(module), name),
+                            None => match self.find_scoped_function(&mangled_name, name) {
+                                Some(fn_def) => Ok(Some(fn_def)),
+                                None => self.find_module_function(None, name),
+                            },
+                        };
+                        match fn_def {
+                            Ok(Some(fn_def)) => Some(fn_def),
+                            Ok(None) => {
                                 self.error_at(
                                     *name,
                                     &format!("Unable to find the function called {}", name.lexeme),
                                 );
-                                return None;
+                                None
+                            }
+                            // The module can't be read or has a syntax error
+                            Err(VmError::RuntimeError(message)) => {
+                                self.error_at(*name, &message);
+                                None
                             }
-                            scopes.pop();
-                            let n = format!("{}${}", scopes.join("$"), name.lexeme);
-                            if let Some(fn_def) = self.named_id_map.get(&n) {
-                                return Some(fn_def.clone());
+                            Err(err) => {
+                                self.error_at(*name, &err.to_string());
+                                None
                             }
                         }
                     });
 
+                agent.doc = self.docstring(doc);
                 let tool_count = tools.len();
                 for tool in tools {
                     if let Stmt::Function(FunctionDecl {
@@ -536,26 +715,16 @@ impl<'gc> CodeGen<'gc> {
                         mangled_name,
                         doc,
                         params,
-                        return_type,
                         body,
                         ..
                     }) = tool
                     {
-                        let primitive_params = params
-                            .iter()
-                            .map(|(name, param)| {
-                                (
-                                    name.lexeme.to_owned(),
-                                    PrimitiveType::from(param.type_hint.unwrap_or_default()),
-                                )
-                            })
-                            .collect();
+                        let primitive_params = primitive_params(&params);
                         let fn_type = FunctionType::Tool;
                         let chunk_id = self.generate_function(
                             name.lexeme,
                             &mangled_name,
                             params,
-                            return_type,
                             body,
                             fn_type,
                         )?;
@@ -585,6 +754,89 @@ impl<'gc> CodeGen<'gc> {
                 });
                 // self.emit(OpCode::Pop);
             }
+            Stmt::Workflow(WorkflowDecl {
+                name,
+                doc,
+                steps,
+                compensations,
+                visibility,
+                ..
+            }) => {
+                let function_count = steps.len() + compensations.len();
+                let mut compensation_chunks = HashMap::new();
+                for compensation in compensations {
+                    if let Stmt::Function(FunctionDecl {
+                        name,
+                        mangled_name,
+                        params,
+                        body,
+                        fn_type,
+                        ..
+                    }) = compensation
+                    {
+                        let chunk_id = self.generate_function(
+                            name.lexeme,
+                            &mangled_name,
+                            params,
+                            body,
+                            fn_type,
+                        )?;
+                        compensation_chunks.insert(name.lexeme, chunk_id);
+                    }
+                }
+                let mut workflow_steps = Vec::with_capacity(steps.len());
+                for WorkflowStepDecl {
+                    function,
+                    retry,
+                    compensate,
+                } in steps
+                {
+                    if let Stmt::Function(FunctionDecl {
+                        name,
+                        mangled_name,
+                        params,
+                        body,
+                        fn_type,
+                        ..
+                    }) = function
+                    {
+                        let chunk_id = self.generate_function(
+                            name.lexeme,
+                            &mangled_name,
+                            params,
+                            body,
+                            fn_type,
+                        )?;
+                        workflow_steps.push(WorkflowStep {
+                            name: name.lexeme.to_owned(),
+                            chunk_id,
+                            retry,
+                            compensate: compensate.map(|compensate| {
+                                (
+                                    compensate.lexeme.to_owned(),
+                                    compensation_chunks[compensate.lexeme],
+                                )
+                            }),
+                        });
+                    }
+                }
+                // Pop the step functions, they are called by the workflow
+                // instead of being defined as globals.
+                self.emit(OpCode::Pop(function_count as u8));
+                let workflow = Workflow {
+                    name: self.ctx.intern(name.lexeme.as_bytes()),
+                    doc: self.docstring(doc),
+                    steps: workflow_steps,
+                };
+                let workflow_constant =
+                    self.make_constant(Value::from(Gc::new(&self.ctx, workflow)));
+                self.emit(OpCode::Workflow(workflow_constant as u8));
+                let name_constant = self.identifier_constant(name.lexeme);
+                self.emit(OpCode::DefineGlobal {
+                    name_constant: name_constant as u8,
+                    visibility,
+                });
+            }
         }
         Ok(())
     }
@@ -663,7 +915,13 @@ impl<'gc> CodeGen<'gc> {
                     TokenType::Star => self.emit(OpCode::Multiply),
                     TokenType::StarStar => self.emit(OpCode::Power),
                     TokenType::Slash => self.emit(OpCode::Divide),
+                    TokenType::TildeSlash => self.emit(OpCode::FloorDivide),
                     TokenType::Percent => self.emit(OpCode::Modulo),
+                    TokenType::Ampersand => self.emit(OpCode::BitAnd),
+                    TokenType::Pipe => self.emit(OpCode::BitOr),
+                    TokenType::Caret => self.emit(OpCode::BitXor),
+                    TokenType::LessLess => self.emit(OpCode::ShiftLeft),
+                    TokenType::GreaterGreater => self.emit(OpCode::ShiftRight),
                     TokenType::NotEqual => self.emit(OpCode::NotEqual),
                     TokenType::EqualEqual => self.emit(OpCode::Equal),
                     TokenType::Greater => self.emit(OpCode::Greater),
@@ -723,6 +981,7 @@ impl<'gc> CodeGen<'gc> {
                 self.generate_expr(right)?;
                 match operator.kind {
                     TokenType::Minus => self.emit(OpCode::Negate),
+                    TokenType::Tilde => self.emit(OpCode::BitNot),
                     TokenType::Not => self.emit(OpCode::Not),
                     _ => {
                         self.error_at(
@@ -738,19 +997,24 @@ impl<'gc> CodeGen<'gc> {
                 self.named_variable(name, true)?;
             }
             Expr::Block { statements, .. } => self.generate_block_expr(statements)?,
-            Expr::Lambda { params, body, .. } => self.generate_lambda(params, body)?,
+            Expr::Lambda {
+                params,
+                body,
+                capture_by_value,
+                ..
+            } => self.generate_lambda(params, body, capture_by_value)?,
             Expr::Call {
                 callee,
                 is_constructor,
                 arguments,
                 keyword_args,
+                keyword_spread,
                 error_handler,
                 ..
             } => self.generate_call(
                 callee,
                 is_constructor,
-                arguments,
-                keyword_args,
+                (arguments, keyword_args, keyword_spread),
                 error_handler,
             )?,
             Expr::Invoke {
@@ -758,9 +1022,15 @@ impl<'gc> CodeGen<'gc> {
                 method,
                 arguments,
                 keyword_args,
+                keyword_spread,
                 error_handler,
                 ..
-            } => self.generate_invoke(object, method, arguments, keyword_args, error_handler)?,
+            } => self.generate_invoke(
+                object,
+                method,
+                (arguments, keyword_args, keyword_spread),
+                error_handler,
+            )?,
             Expr::Index {
                 object, key, value, ..
             } => {
@@ -865,6 +1135,7 @@ impl<'gc> CodeGen<'gc> {
                 method,
                 arguments,
                 keyword_args,
+                keyword_spread,
                 ..
             } => {
                 // Get this instance
@@ -876,7 +1147,7 @@ impl<'gc> CodeGen<'gc> {
                 for arg in arguments {
                     self.generate_expr(arg)?;
                 }
-                self.generate_keyword_args(keyword_args)?;
+                let keyword_spread = self.generate_keyword_args(keyword_args, keyword_spread)?;
 
                 // Get superclass and invoke method
                 if let Some((pos, _, _)) = self
@@ -893,6 +1164,7 @@ impl<'gc> CodeGen<'gc> {
                     method_constant: method_constant as u8,
                     positional_count,
                     keyword_count,
+                    keyword_spread,
                 });
             }
             Expr::And { left, right, .. } => {
@@ -912,9 +1184,18 @@ impl<'gc> CodeGen<'gc> {
                 self.generate_expr(right)?;
                 self.patch_jump(end_jump);
             }
-            Expr::Prompt { expression, .. } => {
+            Expr::Prompt {
+                expression,
+                error_handler,
+                ..
+            } => {
                 self.generate_expr(expression)?;
-                self.emit(OpCode::Prompt);
+                self.emit(OpCode::Prompt {
+                    handle_error: error_handler.is_some(),
+                });
+                if let Some(handler) = error_handler {
+                    self.generate_error_handler(handler)?;
+                }
             }
         }
         Ok(())
@@ -936,16 +1217,25 @@ impl<'gc> CodeGen<'gc> {
         }
     }
 
+    // Push the name and the value of each keyword argument, then the mapping
+    // spread into them if any, returns whether there is a spread.
     fn generate_keyword_args(
         &mut self,
         keyword_args: HashMap<String, Expr<'gc>>,
-    ) -> Result<(), VmError> {
+        keyword_spread: Option<Box<Expr<'gc>>>,
+    ) -> Result<bool, VmError> {
         for (name, value) in keyword_args {
             let name_constant = self.identifier_constant(&name);
             self.emit(OpCode::Constant(name_constant as u8));
             self.generate_expr(value)?;
         }
-        Ok(())
+        match keyword_spread {
+            Some(spread) => {
+                self.generate_expr(spread)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
     }
 
     fn generate_match(
@@ -1149,6 +1439,7 @@ impl<'gc> CodeGen<'gc> {
         &mut self,
         params: Vec<Token<'gc>>,
         body: Box<Expr<'gc>>,
+        capture_by_value: bool,
     ) -> Result<(), VmError> {
         // Create a new compiler for the lambda
         let name = format!("lambda_{}", CHUNK_ID.load(Ordering::Relaxed));
@@ -1157,6 +1448,8 @@ impl<'gc> CodeGen<'gc> {
         // Create the lambda compiler and swap with self
         let mut lambda_compiler = Self::new(self.ctx, FunctionType::Lambda, &name);
         lambda_compiler.named_id_map = self.named_id_map.clone();
+        lambda_compiler.imported_modules = self.imported_modules.clone();
+        lambda_compiler.deprecated_globals = self.deprecated_globals.clone();
 
         // Store current compiler as enclosing and set enclosing for lambda
         let current_compiler = mem::replace(self, *lambda_compiler);
@@ -1165,11 +1458,13 @@ impl<'gc> CodeGen<'gc> {
         // Set up function parameters
         self.function.arity = params.len() as u8;
         self.function.max_arity = params.len() as u8;
+        self.function.capture_by_value = capture_by_value;
 
         // Add parameters as locals
         self.begin_scope();
         for param in params {
-            self.declare_variable(param, Mutability::Mutable);
+            let pos = self.declare_variable(param, Mutability::Mutable);
+            self.locals[pos].unused_warning = Some("parameter");
             self.mark_initialized();
         }
 
@@ -1178,6 +1473,7 @@ impl<'gc> CodeGen<'gc> {
 
         // self.emit(OpCode::Return);
         // self.end_scope();
+        self.warn_unused_locals(0);
 
         // Check for errors
         if self.error_reporter.had_error {
@@ -1190,9 +1486,11 @@ impl<'gc> CodeGen<'gc> {
         let generated_chunks = mem::take(&mut self.chunks);
 
         // Get the enclosing compiler back
+        let warnings = mem::take(&mut self.error_reporter.warnings);
         if let Some(enclosing) = self.enclosing.take() {
             let _ = mem::replace(self, *enclosing);
         }
+        self.error_reporter.warnings.extend(warnings);
 
         // Store the generated function and extend chunks
         self.chunks.insert(chunk_id, generated_function);
@@ -1207,8 +1505,7 @@ impl<'gc> CodeGen<'gc> {
         &mut self,
         callee: Box<Expr<'gc>>,
         is_constructor: bool,
-        arguments: Vec<Expr<'gc>>,
-        keyword_args: HashMap<String, Expr<'gc>>,
+        (arguments, keyword_args, keyword_spread): Arguments<'gc>,
         error_handler: Option<ErrorHandler<'gc>>,
     ) -> Result<(), VmError> {
         let arg_count = arguments.len() as u8;
@@ -1217,12 +1514,13 @@ impl<'gc> CodeGen<'gc> {
         for arg in arguments {
             self.generate_expr(arg)?;
         }
-        self.generate_keyword_args(keyword_args)?;
+        let keyword_spread = self.generate_keyword_args(keyword_args, keyword_spread)?;
 
         if is_constructor {
             self.emit(OpCode::Constructor {
                 positional_count: arg_count,
                 keyword_count: kw_count,
+                keyword_spread,
                 validate: error_handler.is_some(),
             });
         } else {
@@ -1230,6 +1528,7 @@ impl<'gc> CodeGen<'gc> {
             self.emit(OpCode::Call {
                 positional_count: arg_count,
                 keyword_count: kw_count,
+                keyword_spread,
             });
         }
 
@@ -1243,8 +1542,7 @@ impl<'gc> CodeGen<'gc> {
         &mut self,
         object: Box<Expr<'gc>>,
         method: Token<'gc>,
-        arguments: Vec<Expr<'gc>>,
-        keyword_args: HashMap<String, Expr<'gc>>,
+        (arguments, keyword_args, keyword_spread): Arguments<'gc>,
         error_handler: Option<ErrorHandler<'gc>>,
     ) -> Result<(), VmError> {
         let arg_count = arguments.len() as u8;
@@ -1254,7 +1552,7 @@ impl<'gc> CodeGen<'gc> {
         for arg in arguments {
             self.generate_expr(arg)?;
         }
-        self.generate_keyword_args(keyword_args)?;
+        let keyword_spread = self.generate_keyword_args(keyword_args, keyword_spread)?;
 
         let method_const = self.identifier_constant(method.lexeme);
 
@@ -1262,6 +1560,7 @@ impl<'gc> CodeGen<'gc> {
             method_constant: method_const as u8,
             positional_count: arg_count,
             keyword_count: kw_count,
+            keyword_spread,
         });
 
         if let Some(handler) = error_handler {
@@ -1329,15 +1628,29 @@ impl<'gc> CodeGen<'gc> {
         &mut self,
         ClassDecl {
             name,
+            doc,
             superclass,
             methods,
+            json_formats,
             visibility,
+            attributes,
             ..
         }: ClassDecl<'gc>,
     ) -> Result<(), VmError> {
         // Emit class declaration
         let name_constant = self.identifier_constant(name.lexeme);
-        self.emit(OpCode::Class(name_constant as u8));
+        let doc_constant = self
+            .docstring(doc)
+            .map(|doc| self.make_constant(Value::String(doc)) as u8);
+        let deprecated_constant = attributes
+            .deprecated
+            .map(|note| self.make_constant(Value::String(self.ctx.intern(note.as_bytes()))) as u8);
+        self.emit(OpCode::Class {
+            name_constant: name_constant as u8,
+            doc_constant,
+            deprecated_constant,
+            experimental: attributes.experimental,
+        });
         self.emit(OpCode::DefineGlobal {
             name_constant: name_constant as u8,
             visibility,
@@ -1379,6 +1692,14 @@ impl<'gc> CodeGen<'gc> {
             }
         }
 
+        for (field, format) in json_formats {
+            let name_constant = self.identifier_constant(field.lexeme);
+            self.emit(OpCode::JsonFormat {
+                name_constant: name_constant as u8,
+                format,
+            });
+        }
+
         // Once we’ve reached the end of the methods, we no longer need
         // the class and tell the VM to pop it off the stack.
         self.emit(OpCode::Pop(1));
@@ -1395,35 +1716,63 @@ impl<'gc> CodeGen<'gc> {
         FunctionDecl {
             name,
             mangled_name,
+            doc,
             params,
-            return_type,
             body,
             fn_type,
             ..
         }: FunctionDecl<'gc>,
     ) -> Result<(), VmError> {
-        self.generate_function(
-            name.lexeme,
-            &mangled_name,
-            params,
-            return_type,
-            body,
-            fn_type,
-        )?;
+        let chunk_id = self.generate_function(name.lexeme, &mangled_name, params, body, fn_type)?;
+        self.set_doc(chunk_id, doc);
         let method_constant = self.identifier_constant(name.lexeme);
-        self.emit(OpCode::Method {
-            name_constant: method_constant as u8,
-            is_static: fn_type.is_static_method(),
-        });
+        if fn_type.is_accessor() {
+            self.emit(OpCode::Accessor {
+                name_constant: method_constant as u8,
+                is_setter: fn_type == FunctionType::Setter,
+            });
+        } else {
+            self.emit(OpCode::Method {
+                name_constant: method_constant as u8,
+                is_static: fn_type.is_static_method(),
+            });
+        }
         Ok(())
     }
 
+    fn set_doc(&mut self, chunk_id: ChunkId, doc: Option<Token<'gc>>) {
+        let doc = self.docstring(doc);
+        if let Some(function) = self.chunks.get_mut(&chunk_id) {
+            function.doc = doc;
+        }
+    }
+
+    // The docstring with the indentation of its lines removed.
+    fn docstring(&self, doc: Option<Token<'gc>>) -> Option<InternedString<'gc>> {
+        let doc = doc?.lexeme;
+        let indent = doc
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.len() - line.trim_start().len())
+            .min()
+            .unwrap_or(0);
+        let lines = doc
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match i {
+                0 => line,
+                _ => line.get(indent..).unwrap_or_default(),
+            })
+            .collect::<Vec<_>>();
+        Some(self.ctx.intern(lines.join("\n").as_bytes()))
+    }
+
     fn generate_function(
         &mut self,
         name: &'gc str,
         mangle_name: &str,
         mut params: IndexMap<Token<'gc>, ParameterDecl<'gc>>,
-        _return_type: Option<Token<'gc>>,
         body: Vec<Stmt<'gc>>,
         fn_type: FunctionType,
     ) -> Result<ChunkId, VmError> {
@@ -1433,23 +1782,37 @@ impl<'gc> CodeGen<'gc> {
         let mut enclosing = mem::replace(self, *compiler);
         self.named_id_map = mem::take(&mut enclosing.named_id_map);
         self.defined_enums = mem::take(&mut enclosing.defined_enums);
+        self.imported_modules = mem::take(&mut enclosing.imported_modules);
+        self.deprecated_globals = mem::take(&mut enclosing.deprecated_globals);
         self.enclosing = Some(Box::new(enclosing));
 
         self.begin_scope();
 
         // Store parameter count and default value count
-        let param_count = params.len();
+        let count = |kind| params.values().filter(|p| p.kind == kind).count();
+        let param_count = count(ParameterKind::Positional);
         let default_count = params
             .values()
-            .filter(|p| p.default_value.is_some())
+            .filter(|p| p.kind == ParameterKind::Positional && p.default_value.is_some())
             .count();
         self.function.arity = (param_count - default_count) as u8;
         self.function.max_arity = param_count as u8;
+        self.function.keyword_only = count(ParameterKind::KeywordOnly) as u8;
+        self.function.variadic = count(ParameterKind::Rest) > 0;
+        self.function.kwargs = count(ParameterKind::Kwargs) > 0;
+
+        let signature = format!("{name}({})", signature(&params));
+        self.function.signature = Some(self.ctx.intern(signature.as_bytes()));
 
         // Compile parameters and their default values
         for (index, param) in params.values_mut().enumerate() {
-            self.declare_variable(param.name, Mutability::Mutable);
+            let pos = self.declare_variable(param.name, Mutability::Mutable);
+            self.locals[pos].unused_warning = Some("parameter");
             self.mark_initialized();
+            if matches!(param.kind, ParameterKind::Rest | ParameterKind::Kwargs) {
+                // The rest parameters can't be passed as keyword argument
+                continue;
+            }
 
             let name = self.ctx.intern(param.name.lexeme.as_bytes());
             // Store default value if present
@@ -1502,6 +1865,7 @@ impl<'gc> CodeGen<'gc> {
                 self.function.params.insert(
                     name,
                     Parameter::new(index as u8, Value::Nil)
+                        .required()
                         .validators(mem::take(&mut param.validators)),
                 );
             }
@@ -1509,6 +1873,7 @@ impl<'gc> CodeGen<'gc> {
 
         // Compile function body
         self.generate_block_expr(body)?;
+        self.warn_unused_locals(0);
 
         // Restore the original compiler
         if self.error_reporter.had_error {
@@ -1527,6 +1892,12 @@ impl<'gc> CodeGen<'gc> {
             self.chunks.insert(chunk_id, function);
             enclosing.named_id_map = mem::take(&mut self.named_id_map);
             enclosing.defined_enums = mem::take(&mut self.defined_enums);
+            enclosing.imported_modules = mem::take(&mut self.imported_modules);
+            enclosing.deprecated_globals = mem::take(&mut self.deprecated_globals);
+            enclosing
+                .error_reporter
+                .warnings
+                .append(&mut self.error_reporter.warnings);
             let chunks = mem::take(&mut self.chunks);
             *self = *enclosing;
             self.chunks.extend(chunks);
@@ -1536,6 +1907,7 @@ impl<'gc> CodeGen<'gc> {
     }
 
     fn generate_block_expr(&mut self, mut statements: Vec<Stmt<'gc>>) -> Result<(), VmError> {
+        self.warn_unreachable(&statements);
         let last_stmt = statements.pop();
         for stmt in statements {
             self.generate_stmt(stmt)?;
@@ -1575,6 +1947,71 @@ impl<'gc> CodeGen<'gc> {
         }
     }
 
+    // Find the function in the current or enclosing scopes.
+    fn find_scoped_function(&self, mangled_name: &str, name: &Token<'gc>) -> Option<FnDef> {
+        let mut scopes = mangled_name.split("$").collect::<Vec<_>>();
+        while scopes.pop().is_some() {
+            let n = format!("{}${}", scopes.join("$"), name.lexeme);
+            if let Some(fn_def) = self.named_id_map.get(&n) {
+                return Some(fn_def.clone());
+            }
+        }
+        None
+    }
+
+    // Find a public function in the imported script modules, if the module
+    // is given, only the module imported with that name is searched.
+    fn find_module_function(
+        &mut self,
+        module: Option<&Token<'gc>>,
+        name: &Token<'gc>,
+    ) -> Result<Option<FnDef>, VmError> {
+        let paths = self
+            .imported_modules
+            .iter()
+            .filter(|(bound, _)| module.is_none_or(|m| *bound == Some(m.lexeme)))
+            .map(|(_, path)| *path)
+            .collect::<Vec<_>>();
+        for path in paths {
+            if let Some(fn_def) = self.module_functions(path)?.get(name.lexeme) {
+                return Ok(Some(fn_def.clone()));
+            }
+        }
+        Ok(None)
+    }
+
+    // The public functions of the imported script module, the module source
+    // is shared with the module loader and parsed once per compilation.
+    fn module_functions(&mut self, path: &'gc str) -> Result<&HashMap<String, FnDef>, VmError> {
+        if !self.module_functions.contains_key(path) {
+            let source =
+                module::read_source(&module::find_module_file(&module::search_paths(), path)?)?;
+            // The syntax errors are reported by the parser
+            let program = Parser::new(self.ctx, source).parse().map_err(|_| {
+                VmError::RuntimeError(format!("Failed to parse the module '{path}'."))
+            })?;
+            let functions = program
+                .statements
+                .into_iter()
+                .filter_map(|stmt| match stmt {
+                    Stmt::Function(FunctionDecl {
+                        name,
+                        doc,
+                        params,
+                        visibility: Visibility::Public,
+                        ..
+                    }) => Some((
+                        name.lexeme.to_owned(),
+                        FnDef::new(0, &doc, primitive_params(&params)).with_module(path),
+                    )),
+                    _ => None,
+                })
+                .collect();
+            self.module_functions.insert(path, functions);
+        }
+        Ok(&self.module_functions[path])
+    }
+
     // Bytecode emission methods
     fn emit(&mut self, op: OpCode) {
         self.function.write_byte(op, self.current_line);
@@ -1588,12 +2025,22 @@ impl<'gc> CodeGen<'gc> {
     fn emit_return(&mut self) {
         if self.fn_type == FunctionType::Constructor {
             self.emit(OpCode::GetLocal(0));
+        } else if self.fn_type == FunctionType::Setter {
+            // An assignment through a setter evaluates to the assigned value
+            self.emit(OpCode::GetLocal(1));
         } else {
             self.emit(OpCode::Nil);
         }
         self.emit(OpCode::Return);
     }
 
+    // Exit the innermost entered contexts, before the body is left by a jump or return.
+    fn emit_context_exits(&mut self, count: usize) {
+        for _ in 0..count {
+            self.emit(OpCode::ExitContext);
+        }
+    }
+
     fn emit_jump(&mut self, instruction: OpCode) -> usize {
         self.emit(instruction);
         self.function.code_size()
@@ -1641,6 +2088,9 @@ impl<'gc> CodeGen<'gc> {
                 if can_assign && self.const_globals.contains(name.lexeme) {
                     self.error_at(name, "Cannot assign to constant variable.");
                 }
+                if !can_assign {
+                    self.warn_deprecated(name);
+                }
                 let pos = self.identifier_constant(name.lexeme) as u8;
                 (OpCode::GetGlobal(pos), OpCode::SetGlobal(pos))
             };
@@ -1653,12 +2103,30 @@ impl<'gc> CodeGen<'gc> {
         Ok(())
     }
 
+    // Warn the reference to a `@deprecated` global, but not the recursive
+    // calls of the deprecated function itself.
+    fn warn_deprecated(&mut self, name: Token<'gc>) {
+        let Some(note) = self.deprecated_globals.get(name.lexeme) else {
+            return;
+        };
+        if self.function.name.is_some_and(|n| n == name.lexeme) {
+            return;
+        }
+        let message = if note.is_empty() {
+            format!("'{}' is deprecated.", name.lexeme)
+        } else {
+            format!("'{}' is deprecated: {}", name.lexeme, note)
+        };
+        self.error_reporter.warning_with_line(name.line, message);
+    }
+
     // Resolve a local variable by name, return its index and depth.
     fn resolve_local(&mut self, name: &str) -> Option<(u8, isize, Mutability)> {
-        (0..self.local_count)
+        let i = (0..self.local_count)
             .rev()
-            .find(|&i| self.locals[i].name.lexeme == name)
-            .map(|i| (i as u8, self.locals[i].depth, self.locals[i].mutability))
+            .find(|&i| self.locals[i].name.lexeme == name)?;
+        self.locals[i].is_used = true;
+        Some((i as u8, self.locals[i].depth, self.locals[i].mutability))
     }
 
     fn resolve_upvalue(
@@ -1720,6 +2188,7 @@ impl<'gc> CodeGen<'gc> {
 
     fn end_scope(&mut self) {
         self.scope_depth -= 1;
+        self.warn_unused_locals(self.scope_depth);
         let mut pop_count = 0;
 
         while self.local_count > 0 && self.locals[self.local_count - 1].depth > self.scope_depth {
@@ -1736,6 +2205,7 @@ impl<'gc> CodeGen<'gc> {
                 pop_count += 1;
             }
             self.local_count -= 1;
+            self.end_local(self.local_count);
         }
 
         if pop_count > 0 {
@@ -1743,6 +2213,19 @@ impl<'gc> CodeGen<'gc> {
         }
     }
 
+    // The local in the slot goes out of scope at the next instruction.
+    fn end_local(&mut self, slot: usize) {
+        let chunk = &mut self.function.chunk;
+        if let Some(local) = chunk
+            .locals
+            .iter_mut()
+            .rev()
+            .find(|local| local.slot as usize == slot && local.end == usize::MAX)
+        {
+            local.end = chunk.code.len();
+        }
+    }
+
     // Constants and identifiers
     fn make_constant(&mut self, value: Value<'gc>) -> usize {
         let constant = self.function.add_constant(value);
@@ -1794,6 +2277,8 @@ impl<'gc> CodeGen<'gc> {
             depth: UNINITIALIZED_LOCAL_DEPTH, // Mark as uninitialized
             is_captured: false,
             mutability,
+            is_used: false,
+            unused_warning: None,
         };
         let pos = self.local_count;
         self.local_count += 1;
@@ -1804,7 +2289,54 @@ impl<'gc> CodeGen<'gc> {
         if self.scope_depth == 0 {
             return;
         }
-        self.locals[self.local_count - 1].depth = self.scope_depth;
+        let slot = self.local_count - 1;
+        self.locals[slot].depth = self.scope_depth;
+        let name = self.locals[slot].name;
+        if name.kind == TokenType::Identifier {
+            let chunk = &mut self.function.chunk;
+            chunk.locals.push(LocalVar {
+                name: name.lexeme.to_owned(),
+                slot: slot as u8,
+                start: chunk.code.len(),
+                end: usize::MAX,
+            });
+        }
+    }
+
+    // Warn the unused locals deeper than the scope depth, prefix the name
+    // with '_' to silence the warning.
+    fn warn_unused_locals(&mut self, depth: isize) {
+        for local in self.locals[..self.local_count].iter().rev() {
+            if local.depth <= depth && local.is_initialized() {
+                break;
+            }
+            if let Some(kind) = local.unused_warning.filter(|_| {
+                !local.is_used && !local.is_captured && !local.name.lexeme.starts_with('_')
+            }) {
+                self.error_reporter.warning_with_line(
+                    local.name.line,
+                    format!("Unused {kind} '{}'.", local.name.lexeme),
+                );
+            }
+        }
+    }
+
+    // Warn the statements after a `return` or `raise`, which are never executed.
+    fn warn_unreachable(&mut self, statements: &[Stmt<'gc>]) {
+        let Some(index) = statements
+            .iter()
+            .position(|stmt| matches!(stmt, Stmt::Return { .. } | Stmt::Raise { .. }))
+        else {
+            return;
+        };
+        if let Some(stmt) = statements.get(index + 1) {
+            let keyword = match statements[index] {
+                Stmt::Return { .. } => "return",
+                _ => "raise",
+            };
+            self.error_reporter
+                .warning_with_line(stmt.line(), format!("Unreachable code after '{keyword}'."));
+        }
     }
 
     fn error(&mut self, message: &str) {
@@ -1830,3 +2362,143 @@ impl<'gc> CodeGen<'gc> {
         self.error_reporter.error_at(token, message);
     }
 }
+
+// The parameters as declared, e.g. `name: str, *, loud = false, **kwargs`.
+fn signature(params: &IndexMap<Token<'_>, ParameterDecl<'_>>) -> String {
+    let mut parts = Vec::new();
+    let mut separated = false;
+    for param in params.values() {
+        let name = param.name.lexeme;
+        match param.kind {
+            ParameterKind::Rest => {
+                separated = true;
+                parts.push(format!("*{name}"));
+                continue;
+            }
+            ParameterKind::Kwargs => {
+                parts.push(format!("**{name}"));
+                continue;
+            }
+            ParameterKind::KeywordOnly if !separated => {
+                separated = true;
+                parts.push("*".to_owned());
+            }
+            _ => {}
+        }
+        let mut part = name.to_owned();
+        if let Some(type_hint) = param.type_hint {
+            part.push_str(&format!(": {}", type_hint.lexeme));
+        }
+        match &param.default_value {
+            Some(Expr::Literal { value, .. }) => part.push_str(&format!(" = {value}")),
+            Some(Expr::EnumVariant {
+                enum_name, variant, ..
+            }) => part.push_str(&format!(" = {}::{}", enum_name.lexeme, variant.lexeme)),
+            Some(_) => part.push_str(" = ..."),
+            None => {}
+        }
+        parts.push(part);
+    }
+    parts.join(", ")
+}
+
+fn primitive_params(
+    params: &IndexMap<Token<'_>, ParameterDecl<'_>>,
+) -> IndexMap<String, PrimitiveType> {
+    params
+        .iter()
+        .filter(|(_, param)| !matches!(param.kind, ParameterKind::Rest | ParameterKind::Kwargs))
+        .map(|(name, param)| {
+            (
+                name.lexeme.to_owned(),
+                PrimitiveType::from(param.type_hint.unwrap_or_default()),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_arena::arena::rootless_mutate;
+
+    use super::*;
+    use crate::{parser::Parser, string::InternedStringSet};
+
+    #[test]
+    fn test_warnings() {
+        rootless_mutate(|mutation| {
+            let context = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let source = r#"
+                fn f(a, _b, c) {
+                    let unused = 1;
+                    let captured = 2;
+                    let add = |x| captured + c;
+                    return add(1);
+                    print("unreachable");
+                }
+                {
+                    let x = 1;
+                    let _y = 2;
+                }
+                let global = 1;
+            "#;
+            let program = Parser::new(context, source).parse().unwrap();
+            let (_, warnings) = CodeGen::generate(program, context, 0).unwrap();
+            assert_eq!(
+                warnings
+                    .iter()
+                    .map(|warning| warning.to_string())
+                    .collect::<Vec<_>>(),
+                [
+                    "[line 2] Warning: Unused parameter 'a'.",
+                    "[line 3] Warning: Unused variable 'unused'.",
+                    "[line 5] Warning: Unused parameter 'x'.",
+                    "[line 7] Warning: Unreachable code after 'return'.",
+                    "[line 10] Warning: Unused variable 'x'.",
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn test_deprecated_warnings() {
+        rootless_mutate(|mutation| {
+            let context = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let source = r#"
+                @deprecated("use greet2")
+                fn greet(n) {
+                    if n > 0 {
+                        greet(n - 1);
+                    }
+                }
+                @deprecated
+                class Old {}
+                fn caller() {
+                    greet(1);
+                    let f = || Old();
+                    return f;
+                }
+                greet(2);
+            "#;
+            let program = Parser::new(context, source).parse().unwrap();
+            let (_, warnings) = CodeGen::generate(program, context, 0).unwrap();
+            assert_eq!(
+                warnings
+                    .iter()
+                    .map(|warning| warning.to_string())
+                    .collect::<Vec<_>>(),
+                [
+                    "[line 11] Warning: 'greet' is deprecated: use greet2",
+                    "[line 12] Warning: 'Old' is deprecated.",
+                    "[line 15] Warning: 'greet' is deprecated: use greet2",
+                ]
+            );
+        });
+    }
+}
@@ -1,9 +1,17 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, mem};
 
 use aiscript_arena::Gc;
 use codegen::CodeGen;
 
-use crate::{VmError, ast::ChunkId, object::Function, parser::Parser, vm::Context};
+use crate::{
+    VmError,
+    ast::{ChunkId, Program, Stmt},
+    object::Function,
+    parser::Parser,
+    string::InternedString,
+    ty::{TypeChecker, TypeError},
+    vm::Context,
+};
 
 mod codegen;
 #[cfg(feature = "optimizer")]
@@ -13,21 +21,133 @@ pub fn compile<'gc>(
     ctx: Context<'gc>,
     source: &'gc str,
 ) -> Result<BTreeMap<ChunkId, Gc<'gc, Function<'gc>>>, VmError> {
+    compile_chunks(ctx, source, None, 0)
+}
+
+/// Run the static type check of the strict mode, all the type errors are reported,
+/// followed by the compile-time warnings, e.g. unused variables and unreachable code.
+/// The matches missing some variants of an enum are errors if `deny_non_exhaustive`.
+pub fn check<'gc>(
+    ctx: Context<'gc>,
+    source: &'gc str,
+    deny_non_exhaustive: bool,
+) -> Result<(), VmError> {
+    let mut parser = Parser::new(ctx, source);
+    let program = parser.parse()?;
+    let mut errors = TypeChecker::check(&program);
+    // The parser only warns about the non-exhaustive matches
+    let mut warnings = mem::take(&mut parser.warnings);
+    if deny_non_exhaustive {
+        errors.extend(warnings.drain(..).map(|warning| TypeError {
+            line: warning.line,
+            message: warning.message,
+        }));
+        errors.sort_by_key(|error| error.line);
+    }
+    for error in &errors {
+        eprintln!("{error}");
+    }
+    let (_, codegen_warnings) = CodeGen::generate(program, ctx, 0)?;
+    warnings.extend(codegen_warnings);
+    warnings.sort_by_key(|warning| warning.line);
+    for warning in &warnings {
+        eprintln!("{warning}");
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(VmError::CompileError)
+    }
+}
+
+/// Compile the source of a script module, the globals of its functions are resolved from the module.
+/// Chunk ids start from `first_chunk_id` so they don't overlap the chunks already loaded.
+pub fn compile_module<'gc>(
+    ctx: Context<'gc>,
+    source: &'gc str,
+    module: InternedString<'gc>,
+    first_chunk_id: ChunkId,
+) -> Result<BTreeMap<ChunkId, Gc<'gc, Function<'gc>>>, VmError> {
+    compile_chunks(ctx, source, Some(module), first_chunk_id)
+}
+
+/// Compile a snippet evaluated in the session of a VM, e.g. a line of the REPL,
+/// its trailing expression statement is returned as the value of the snippet.
+pub fn compile_snippet<'gc>(
+    ctx: Context<'gc>,
+    source: &'gc str,
+    first_chunk_id: ChunkId,
+) -> Result<BTreeMap<ChunkId, Gc<'gc, Function<'gc>>>, VmError> {
+    let mut program = parse(ctx, source)?;
+    if let Some(Stmt::Expression { .. }) = program.statements.last()
+        && let Some(Stmt::Expression { expression, line }) = program.statements.pop()
+    {
+        program.statements.push(Stmt::Return {
+            value: Some(expression),
+            line,
+        });
+    }
+    generate(ctx, program, None, first_chunk_id)
+}
+
+fn compile_chunks<'gc>(
+    ctx: Context<'gc>,
+    source: &'gc str,
+    module: Option<InternedString<'gc>>,
+    first_chunk_id: ChunkId,
+) -> Result<BTreeMap<ChunkId, Gc<'gc, Function<'gc>>>, VmError> {
+    let program = parse(ctx, source)?;
+    generate(ctx, program, module, first_chunk_id)
+}
+
+fn parse<'gc>(ctx: Context<'gc>, source: &'gc str) -> Result<Program<'gc>, VmError> {
     let mut parser = Parser::new(ctx, source);
     let program = parser.parse()?;
+    // The files with the `#strict` pragma are type checked before they run
+    if program.strict {
+        let errors = TypeChecker::check(&program);
+        for error in &errors {
+            eprintln!("{error}");
+        }
+        if !errors.is_empty() {
+            return Err(VmError::CompileError);
+        }
+    }
+    Ok(program)
+}
+
+fn generate<'gc>(
+    ctx: Context<'gc>,
+    program: Program<'gc>,
+    module: Option<InternedString<'gc>>,
+    first_chunk_id: ChunkId,
+) -> Result<BTreeMap<ChunkId, Gc<'gc, Function<'gc>>>, VmError> {
     #[cfg(feature = "debug")]
     println!("AST: {}", program);
     #[cfg(feature = "optimizer")]
-    let optimizer = optimizer::ChunkOptimizer::new();
+    let optimizer = optimizer::ChunkOptimizer::with_context(ctx);
 
-    CodeGen::generate(program, ctx).map(|chunks| {
+    CodeGen::generate(program, ctx, first_chunk_id).map(|(chunks, _)| {
         chunks
             .into_iter()
-            .map(|(id, function)| {
-                #[cfg(feature = "optimizer")]
-                let mut function = function;
+            .map(|(id, mut function)| {
+                function.module = module;
                 #[cfg(feature = "optimizer")]
-                optimizer.optimize(&mut function.chunk);
+                {
+                    #[cfg(feature = "debug")]
+                    let name = function
+                        .name
+                        .map_or_else(|| "script".to_string(), |name| name.to_string());
+                    #[cfg(feature = "debug")]
+                    function
+                        .chunk
+                        .disassemble(format!("{name} before optimization"));
+                    optimizer.optimize(&mut function.chunk);
+                    #[cfg(feature = "debug")]
+                    function
+                        .chunk
+                        .disassemble(format!("{name} after optimization"));
+                }
                 (id, Gc::new(&ctx, function))
             })
             .collect()
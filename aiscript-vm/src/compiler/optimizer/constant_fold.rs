@@ -0,0 +1,195 @@
+use super::{OptimizationStrategy, jump_targets, retain};
+use crate::{OpCode, Value, chunk::Chunk, vm::Context};
+
+// Evaluates the arithmetic, comparisons and string concatenations of
+// constant operands at compile time.
+pub(super) struct ConstantFolder<'gc> {
+    ctx: Context<'gc>,
+}
+
+impl<'gc> ConstantFolder<'gc> {
+    pub fn new(ctx: Context<'gc>) -> Self {
+        Self { ctx }
+    }
+
+    // The constant an instruction pushes, if any
+    fn load(chunk: &Chunk<'gc>, op: OpCode) -> Option<Value<'gc>> {
+        match op {
+            OpCode::Constant(index) => Some(chunk.read_constant(index)),
+            OpCode::Bool(b) => Some(Value::Boolean(b)),
+            OpCode::Nil => Some(Value::Nil),
+            _ => None,
+        }
+    }
+
+    fn binary(&self, a: Value<'gc>, b: Value<'gc>, op: OpCode) -> Option<Value<'gc>> {
+        if let (Value::String(a), Value::String(b), OpCode::Add) = (a, b, op) {
+            return Some(Value::String(self.ctx.intern(format!("{a}{b}").as_bytes())));
+        }
+        if let (OpCode::Equal | OpCode::NotEqual, Value::Number(_) | Value::String(_)) = (op, a)
+            && matches!(b, Value::Number(_) | Value::String(_))
+        {
+            return Some(Value::Boolean(a.equals(&b) == (op == OpCode::Equal)));
+        }
+        // Only floats, the integers and decimals never come from a literal
+        let (Value::Number(a), Value::Number(b)) = (a, b) else {
+            return None;
+        };
+        let value = match op {
+            OpCode::Add => Value::Number(a + b),
+            OpCode::Subtract => Value::Number(a - b),
+            OpCode::Multiply => Value::Number(a * b),
+            OpCode::Divide => Value::Number(a / b),
+            OpCode::Modulo => Value::Number(a % b),
+            OpCode::Power => Value::Number(a.powf(b)),
+            OpCode::Greater => Value::Boolean(a > b),
+            OpCode::GreaterEqual => Value::Boolean(a >= b),
+            OpCode::Less => Value::Boolean(a < b),
+            OpCode::LessEqual => Value::Boolean(a <= b),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    fn unary(a: Value<'gc>, op: OpCode) -> Option<Value<'gc>> {
+        match (op, a) {
+            (OpCode::Negate, Value::Number(n)) => Some(Value::Number(-n)),
+            (OpCode::Not, value) => Some(Value::Boolean(value.is_falsy())),
+            _ => None,
+        }
+    }
+
+    // The instruction loading the folded value, None when the constants are full
+    fn emit(chunk: &mut Chunk<'gc>, value: Value<'gc>) -> Option<OpCode> {
+        match value {
+            Value::Boolean(b) => Some(OpCode::Bool(b)),
+            Value::Nil => Some(OpCode::Nil),
+            value if chunk.constant_count() <= u8::MAX as usize => {
+                Some(OpCode::Constant(chunk.add_constant(value) as u8))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'gc> OptimizationStrategy<'gc> for ConstantFolder<'gc> {
+    fn optimize(&self, chunk: &mut Chunk<'gc>) -> bool {
+        let jump_targets = jump_targets(chunk);
+        let mut keep = vec![true; chunk.code.len()];
+
+        let mut i = 0;
+        while i < chunk.code.len() {
+            // The operands are evaluated right before the operator
+            let folded = if i + 2 < chunk.code.len()
+                && !jump_targets.contains(&(i + 1))
+                && !jump_targets.contains(&(i + 2))
+                && let Some(a) = Self::load(chunk, chunk.code[i])
+                && let Some(b) = Self::load(chunk, chunk.code[i + 1])
+                && let Some(value) = self.binary(a, b, chunk.code[i + 2])
+            {
+                Some((value, 2))
+            } else if i + 1 < chunk.code.len()
+                && !jump_targets.contains(&(i + 1))
+                && let Some(a) = Self::load(chunk, chunk.code[i])
+                && let Some(value) = Self::unary(a, chunk.code[i + 1])
+            {
+                Some((value, 1))
+            } else {
+                None
+            };
+
+            match folded.and_then(|(value, operands)| Some((Self::emit(chunk, value)?, operands))) {
+                Some((load, operands)) => {
+                    // The result replaces the operator, it may be folded again
+                    for kept in &mut keep[i..i + operands] {
+                        *kept = false;
+                    }
+                    i += operands;
+                    chunk.code[i] = load;
+                }
+                None => i += 1,
+            }
+        }
+
+        retain(chunk, &keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_arena::{Mutation, arena::rootless_mutate};
+
+    use super::*;
+    use crate::string::InternedStringSet;
+
+    fn context<'gc>(mutation: &'gc Mutation<'gc>) -> Context<'gc> {
+        Context {
+            mutation,
+            strings: InternedStringSet::new(mutation),
+        }
+    }
+
+    #[test]
+    fn test_fold_arithmetic() {
+        rootless_mutate(|mutation| {
+            let mut chunk = Chunk::new();
+            // 1 + 2 * 3
+            let one = chunk.add_constant(Value::Number(1.0)) as u8;
+            let two = chunk.add_constant(Value::Number(2.0)) as u8;
+            let three = chunk.add_constant(Value::Number(3.0)) as u8;
+            chunk.write_code(OpCode::Constant(one), 1);
+            chunk.write_code(OpCode::Constant(two), 1);
+            chunk.write_code(OpCode::Constant(three), 1);
+            chunk.write_code(OpCode::Multiply, 1);
+            chunk.write_code(OpCode::Add, 1);
+            chunk.write_code(OpCode::Negate, 1);
+            chunk.write_code(OpCode::Return, 1);
+
+            let folder = ConstantFolder::new(context(mutation));
+            assert!(folder.optimize(&mut chunk));
+            // A pass folds the innermost operation of the other operands
+            while folder.optimize(&mut chunk) {}
+            assert_eq!(chunk.code.len(), 2);
+            let OpCode::Constant(index) = chunk.code[0] else {
+                panic!("expected a constant, got {:?}", chunk.code[0]);
+            };
+            assert!(chunk.read_constant(index).equals(&Value::Number(-7.0)));
+        });
+    }
+
+    #[test]
+    fn test_fold_string_and_comparison() {
+        rootless_mutate(|mutation| {
+            let ctx = context(mutation);
+            let mut chunk = Chunk::new();
+            let a = chunk.add_constant(Value::String(ctx.intern(b"a"))) as u8;
+            let b = chunk.add_constant(Value::String(ctx.intern(b"b"))) as u8;
+            chunk.write_code(OpCode::Constant(a), 1);
+            chunk.write_code(OpCode::Constant(b), 1);
+            chunk.write_code(OpCode::Add, 1);
+            chunk.write_code(OpCode::Constant(a), 1);
+            chunk.write_code(OpCode::Equal, 1);
+            chunk.write_code(OpCode::Not, 1);
+            chunk.write_code(OpCode::Return, 1);
+
+            assert!(ConstantFolder::new(ctx).optimize(&mut chunk));
+            assert_eq!(chunk.code, vec![OpCode::Bool(true), OpCode::Return]);
+        });
+    }
+
+    #[test]
+    fn test_keep_jump_target() {
+        rootless_mutate(|mutation| {
+            let mut chunk = Chunk::new();
+            let one = chunk.add_constant(Value::Number(1.0)) as u8;
+            // The second operand is loaded by either branch
+            chunk.write_code(OpCode::Constant(one), 1);
+            chunk.write_code(OpCode::Jump(0), 1);
+            chunk.write_code(OpCode::Constant(one), 1);
+            chunk.write_code(OpCode::Add, 1);
+            chunk.write_code(OpCode::Return, 1);
+
+            assert!(!ConstantFolder::new(context(mutation)).optimize(&mut chunk));
+        });
+    }
+}
@@ -0,0 +1,140 @@
+use super::{OptimizationStrategy, jump_target, jump_targets, retain};
+use crate::{OpCode, chunk::Chunk};
+
+// Removes the jumps to the next instruction and the branches on a constant
+// condition, and threads the jumps landing on another jump.
+pub(super) struct JumpSimplifier;
+
+impl<'gc> OptimizationStrategy<'gc> for JumpSimplifier {
+    fn optimize(&self, chunk: &mut Chunk<'gc>) -> bool {
+        let jump_targets = jump_targets(chunk);
+        let mut keep = vec![true; chunk.code.len()];
+        let mut modified = false;
+
+        for i in 0..chunk.code.len() {
+            match chunk.code[i] {
+                OpCode::Jump(0) => keep[i] = false,
+                OpCode::JumpIfFalse(offset) | OpCode::JumpPopIfFalse(offset)
+                    if i > 0 && keep[i - 1] && !jump_targets.contains(&i) =>
+                {
+                    let Some(is_falsy) = Self::constant_condition(chunk, i - 1) else {
+                        continue;
+                    };
+                    let pops = matches!(chunk.code[i], OpCode::JumpPopIfFalse(_));
+                    match (is_falsy, pops) {
+                        // The branch is never taken
+                        (false, false) => keep[i] = false,
+                        (false, true) => {
+                            keep[i - 1] = false;
+                            keep[i] = false;
+                        }
+                        // The branch is always taken
+                        (true, false) => chunk.code[i] = OpCode::Jump(offset),
+                        (true, true) => {
+                            // Jump from the condition to the same target
+                            let Some(offset) = offset.checked_add(1) else {
+                                continue;
+                            };
+                            chunk.code[i - 1] = OpCode::Jump(offset);
+                            keep[i] = false;
+                        }
+                    }
+                }
+                op @ (OpCode::Jump(offset)
+                | OpCode::JumpIfFalse(offset)
+                | OpCode::JumpPopIfFalse(offset)
+                | OpCode::JumpIfError(offset)) => {
+                    let Some(target) = jump_target(i, op) else {
+                        continue;
+                    };
+                    // Go straight to the target of the jump landed on
+                    let Some(OpCode::Jump(next)) = chunk.code.get(target) else {
+                        continue;
+                    };
+                    let Some(offset) = offset.checked_add(*next + 1) else {
+                        continue;
+                    };
+                    chunk.code[i] = match op {
+                        OpCode::Jump(_) => OpCode::Jump(offset),
+                        OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(offset),
+                        OpCode::JumpPopIfFalse(_) => OpCode::JumpPopIfFalse(offset),
+                        _ => OpCode::JumpIfError(offset),
+                    };
+                    modified = true;
+                }
+                _ => {}
+            }
+        }
+
+        retain(chunk, &keep) || modified
+    }
+}
+
+impl JumpSimplifier {
+    // Whether the instruction loads a falsy constant, `None` if the
+    // condition isn't a constant.
+    fn constant_condition(chunk: &Chunk, pos: usize) -> Option<bool> {
+        match chunk.code[pos] {
+            OpCode::Nil => Some(true),
+            OpCode::Bool(b) => Some(!b),
+            OpCode::Constant(index) => Some(chunk.read_constant(index).is_falsy()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jump_to_next_instruction() {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::GetLocal(1), 1);
+        chunk.write_code(OpCode::JumpPopIfFalse(1), 1);
+        chunk.write_code(OpCode::Jump(0), 1); // `if` without `else`
+        chunk.write_code(OpCode::Nil, 2);
+        chunk.write_code(OpCode::Return, 2);
+
+        assert!(JumpSimplifier.optimize(&mut chunk));
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetLocal(1),
+                OpCode::JumpPopIfFalse(0),
+                OpCode::Nil,
+                OpCode::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constant_condition() {
+        let mut chunk = Chunk::new();
+        // if false { ... } else { return nil; }
+        chunk.write_code(OpCode::Bool(false), 1);
+        chunk.write_code(OpCode::JumpPopIfFalse(2), 1);
+        chunk.write_code(OpCode::Pop(1), 1);
+        chunk.write_code(OpCode::Pop(1), 1);
+        chunk.write_code(OpCode::Nil, 2);
+        chunk.write_code(OpCode::Return, 2);
+
+        assert!(JumpSimplifier.optimize(&mut chunk));
+        assert_eq!(chunk.code[0], OpCode::Jump(2));
+        assert_eq!(jump_target(0, chunk.code[0]), Some(3));
+        assert_eq!(chunk.code[3], OpCode::Nil);
+    }
+
+    #[test]
+    fn test_jump_threading() {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::JumpIfError(1), 1);
+        chunk.write_code(OpCode::Pop(1), 1);
+        chunk.write_code(OpCode::Jump(1), 1);
+        chunk.write_code(OpCode::Nil, 1);
+        chunk.write_code(OpCode::Return, 1);
+
+        assert!(JumpSimplifier.optimize(&mut chunk));
+        assert_eq!(jump_target(0, chunk.code[0]), Some(4));
+    }
+}
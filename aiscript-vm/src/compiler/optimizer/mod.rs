@@ -1,40 +1,57 @@
-use crate::chunk::Chunk;
+use std::collections::HashSet;
 
+use crate::{OpCode, chunk::Chunk, vm::Context};
+
+mod constant_fold;
 mod dead_code;
+mod jump;
+mod peephole;
 mod pop_combine;
 
 /// Defines a single optimization strategy
-pub(super) trait OptimizationStrategy {
+pub(super) trait OptimizationStrategy<'gc> {
     /// Apply the optimization strategy to the given chunk
     /// Returns true if any changes were made
-    fn optimize(&self, chunk: &mut Chunk) -> bool;
+    fn optimize(&self, chunk: &mut Chunk<'gc>) -> bool;
 }
 
 /// The main optimizer that applies multiple optimization strategies
-pub(super) struct ChunkOptimizer {
-    strategies: Vec<Box<dyn OptimizationStrategy>>,
+pub(super) struct ChunkOptimizer<'gc> {
+    strategies: Vec<Box<dyn OptimizationStrategy<'gc> + 'gc>>,
 }
 
-impl ChunkOptimizer {
+impl<'gc> ChunkOptimizer<'gc> {
     pub fn new() -> Self {
         let mut optimizer = ChunkOptimizer {
             strategies: Vec::new(),
         };
 
         // Add default optimization strategies
+        optimizer.add_strategy(Box::new(jump::JumpSimplifier));
+        optimizer.add_strategy(Box::new(peephole::LoadPopEliminator));
         optimizer.add_strategy(Box::new(pop_combine::PopCombiner));
         optimizer.add_strategy(Box::new(dead_code::DeadCodeEliminator));
 
         optimizer
     }
 
+    /// The default strategies preceded by the constant folding, the folded
+    /// strings are interned in the context.
+    pub fn with_context(ctx: Context<'gc>) -> Self {
+        let mut optimizer = Self::new();
+        optimizer
+            .strategies
+            .insert(0, Box::new(constant_fold::ConstantFolder::new(ctx)));
+        optimizer
+    }
+
     /// Add a new optimization strategy
-    pub fn add_strategy(&mut self, strategy: Box<dyn OptimizationStrategy>) {
+    pub fn add_strategy(&mut self, strategy: Box<dyn OptimizationStrategy<'gc> + 'gc>) {
         self.strategies.push(strategy);
     }
 
     /// Optimize the given chunk using all registered strategies
-    pub fn optimize(&self, chunk: &mut Chunk) {
+    pub fn optimize(&self, chunk: &mut Chunk<'gc>) {
         let mut modified = true;
         let mut iteration = 0;
         const MAX_ITERATIONS: usize = 10; // Prevent infinite loops
@@ -50,3 +67,93 @@ impl ChunkOptimizer {
         }
     }
 }
+
+// The position the instruction jumps to, the ip is past the
+// instruction when the offset is applied.
+fn jump_target(pos: usize, op: OpCode) -> Option<usize> {
+    match op {
+        OpCode::Jump(offset)
+        | OpCode::JumpIfFalse(offset)
+        | OpCode::JumpPopIfFalse(offset)
+        | OpCode::JumpIfError(offset) => Some(pos + 1 + offset as usize),
+        OpCode::Loop(offset) => (pos + 1).checked_sub(offset as usize),
+        _ => None,
+    }
+}
+
+// Get all positions that are targets of jumps
+fn jump_targets(chunk: &Chunk) -> HashSet<usize> {
+    chunk
+        .code
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, op)| jump_target(pos, *op))
+        .collect()
+}
+
+// Remove the instructions not kept and adjust the jumps to the new position
+// of their target, a removed target moves to the next kept instruction.
+fn retain(chunk: &mut Chunk, keep: &[bool]) -> bool {
+    if keep.iter().all(|kept| *kept) {
+        return false;
+    }
+
+    // The new position of every instruction, and of the end of the code
+    let mut positions = Vec::with_capacity(keep.len() + 1);
+    let mut kept = 0;
+    for keep in keep {
+        positions.push(kept);
+        kept += *keep as usize;
+    }
+    positions.push(kept);
+
+    for (pos, op) in chunk.code.iter_mut().enumerate() {
+        let Some(target) = jump_target(pos, *op).filter(|_| keep[pos]) else {
+            continue;
+        };
+        let (pos, target) = (positions[pos], positions[target]);
+        match op {
+            OpCode::Jump(offset)
+            | OpCode::JumpIfFalse(offset)
+            | OpCode::JumpPopIfFalse(offset)
+            | OpCode::JumpIfError(offset) => *offset = (target - pos - 1) as u16,
+            OpCode::Loop(offset) => *offset = (pos + 1 - target) as u16,
+            _ => {}
+        }
+    }
+
+    let mut keep_code = keep.iter();
+    chunk.code.retain(|_| *keep_code.next().unwrap());
+    let mut keep_lines = keep.iter();
+    chunk.lines.retain(|_| *keep_lines.next().unwrap());
+    for local in &mut chunk.locals {
+        local.start = positions[local.start];
+        if local.end != usize::MAX {
+            local.end = positions[local.end];
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retain_adjusts_jumps() {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::Nil, 1); // loop start
+        chunk.write_code(OpCode::JumpPopIfFalse(3), 1); // exit
+        chunk.write_code(OpCode::Nil, 1); // removed
+        chunk.write_code(OpCode::Pop(1), 1); // removed
+        chunk.write_code(OpCode::Loop(5), 1); // back to the start
+        chunk.write_code(OpCode::Return, 2);
+
+        assert!(retain(&mut chunk, &[true, true, false, false, true, true]));
+        assert_eq!(chunk.code[1], OpCode::JumpPopIfFalse(1));
+        assert_eq!(chunk.code[2], OpCode::Loop(3));
+        assert_eq!(jump_target(1, chunk.code[1]), Some(3));
+        assert_eq!(jump_target(2, chunk.code[2]), Some(0));
+        assert_eq!(chunk.lines, vec![1, 1, 1, 2]);
+    }
+}
@@ -0,0 +1,85 @@
+use super::{OptimizationStrategy, jump_targets, retain};
+use crate::{OpCode, chunk::Chunk};
+
+// Removes the values pushed only to be popped, e.g. the result of an
+// expression statement without side effects.
+pub(super) struct LoadPopEliminator;
+
+impl<'gc> OptimizationStrategy<'gc> for LoadPopEliminator {
+    fn optimize(&self, chunk: &mut Chunk<'gc>) -> bool {
+        let jump_targets = jump_targets(chunk);
+        let mut keep = vec![true; chunk.code.len()];
+
+        for i in 0..chunk.code.len() {
+            match chunk.code[i] {
+                OpCode::Pop(0) => keep[i] = false,
+                OpCode::Pop(count)
+                    if i > 0
+                        && keep[i - 1]
+                        && !jump_targets.contains(&i)
+                        // Loading a global can fail, don't remove the error
+                        && matches!(
+                            chunk.code[i - 1],
+                            OpCode::Constant(_)
+                                | OpCode::Nil
+                                | OpCode::Bool(_)
+                                | OpCode::GetLocal(_)
+                                | OpCode::GetUpvalue(_)
+                                | OpCode::Dup
+                        ) =>
+                {
+                    keep[i - 1] = false;
+                    if count == 1 {
+                        keep[i] = false;
+                    } else {
+                        chunk.code[i] = OpCode::Pop(count - 1);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        retain(chunk, &keep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_pop() {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::GetLocal(1), 1);
+        chunk.write_code(OpCode::Pop(1), 1);
+        chunk.write_code(OpCode::GetGlobal(0), 2); // may fail
+        chunk.write_code(OpCode::Pop(1), 2);
+        chunk.write_code(OpCode::Nil, 3);
+        chunk.write_code(OpCode::Nil, 3);
+        chunk.write_code(OpCode::Pop(3), 3);
+        chunk.write_code(OpCode::Return, 3);
+
+        assert!(LoadPopEliminator.optimize(&mut chunk));
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::GetGlobal(0),
+                OpCode::Pop(1),
+                OpCode::Nil,
+                OpCode::Pop(2),
+                OpCode::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pop_jump_target() {
+        let mut chunk = Chunk::new();
+        chunk.write_code(OpCode::JumpIfFalse(1), 1);
+        chunk.write_code(OpCode::Nil, 1);
+        chunk.write_code(OpCode::Pop(1), 1); // reached from the jump
+        chunk.write_code(OpCode::Return, 1);
+
+        assert!(!LoadPopEliminator.optimize(&mut chunk));
+    }
+}
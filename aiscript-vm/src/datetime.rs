@@ -0,0 +1,305 @@
+use std::{cmp::Ordering, fmt, fmt::Write, str::FromStr};
+
+use aiscript_arena::Collect;
+use chrono::{
+    FixedOffset, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+// The ISO-8601 forms parsed without a pattern, besides RFC 3339.
+const ISO_PATTERNS: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M",
+    "%Y-%m-%d %H:%M",
+];
+
+/// An instant with the timezone it is shown in, a zone of the tz database
+/// like `Europe/Paris` or a fixed offset like `+02:00`.
+///
+/// Datetimes compare by instant, whatever their timezone.
+#[derive(Clone, Copy, Debug)]
+pub struct DateTime {
+    instant: chrono::DateTime<Utc>,
+    zone: Zone,
+}
+
+unsafe impl Collect for DateTime {
+    fn needs_trace() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    fn trace(&self, _cc: &aiscript_arena::Collection) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Zone {
+    Named(Tz),
+    Fixed(FixedOffset),
+}
+
+impl Default for Zone {
+    fn default() -> Self {
+        Zone::Named(Tz::UTC)
+    }
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Zone::Named(Tz::UTC));
+        }
+        if s.starts_with(['+', '-']) {
+            return s
+                .parse()
+                .map(Zone::Fixed)
+                .map_err(|_| format!("Invalid offset '{s}', expect e.g. '+02:00'"));
+        }
+        s.parse().map(Zone::Named).map_err(|_| {
+            format!("Unknown timezone '{s}', expect a tz database name like 'Europe/Paris'")
+        })
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+            Zone::Fixed(offset) => write!(f, "{offset}"),
+        }
+    }
+}
+
+impl Zone {
+    // The instant of the wall clock time in the zone. The earliest one is
+    // taken when the clocks go back, a time skipped when they go forward
+    // doesn't exist.
+    fn resolve(self, local: NaiveDateTime) -> Result<chrono::DateTime<Utc>, String> {
+        let instant = match self {
+            Zone::Named(tz) => tz.from_local_datetime(&local).map(|dt| dt.to_utc()),
+            Zone::Fixed(offset) => offset.from_local_datetime(&local).map(|dt| dt.to_utc()),
+        };
+        match instant {
+            LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => Ok(instant),
+            LocalResult::None => Err(format!("{local} doesn't exist in {self}")),
+        }
+    }
+}
+
+impl DateTime {
+    pub fn now(zone: Zone) -> Self {
+        DateTime {
+            instant: Utc::now(),
+            zone,
+        }
+    }
+
+    pub fn from_timestamp(seconds: f64, zone: Zone) -> Option<Self> {
+        let instant = chrono::DateTime::from_timestamp_micros((seconds * 1e6).round() as i64)?;
+        Some(DateTime { instant, zone })
+    }
+
+    /// Parse the ISO-8601 text, or the RFC 2822 one with the "rfc2822"
+    /// format, or else the text of the strftime pattern. A time without an
+    /// offset is the wall clock time in the zone, a date alone is its
+    /// midnight. The datetime is shown in the zone if given, or else in the
+    /// offset of the text.
+    pub fn parse(text: &str, format: Option<&str>, zone: Option<Zone>) -> Result<Self, String> {
+        let with_offset = match format {
+            None => chrono::DateTime::parse_from_rfc3339(text).ok(),
+            Some(f) if f.eq_ignore_ascii_case("rfc2822") => Some(
+                chrono::DateTime::parse_from_rfc2822(text)
+                    .map_err(|e| format!("Invalid RFC 2822 datetime '{text}': {e}"))?,
+            ),
+            Some(pattern) => chrono::DateTime::parse_from_str(text, pattern).ok(),
+        };
+        if let Some(dt) = with_offset {
+            return Ok(DateTime {
+                instant: dt.to_utc(),
+                zone: zone.unwrap_or(Zone::Fixed(*dt.offset())),
+            });
+        }
+
+        let patterns = match format {
+            Some(pattern) => vec![pattern],
+            None => ISO_PATTERNS.to_vec(),
+        };
+        let date_pattern = format.unwrap_or("%Y-%m-%d");
+        let local = patterns
+            .iter()
+            .find_map(|pattern| NaiveDateTime::parse_from_str(text, pattern).ok())
+            .or_else(|| {
+                NaiveDate::parse_from_str(text, date_pattern)
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })
+            .ok_or_else(|| match format {
+                Some(pattern) => format!("'{text}' doesn't match the pattern '{pattern}'"),
+                None => format!("Invalid ISO-8601 datetime '{text}'"),
+            })?;
+        let zone = zone.unwrap_or_default();
+        Ok(DateTime {
+            instant: zone.resolve(local)?,
+            zone,
+        })
+    }
+
+    pub fn zone(&self) -> Zone {
+        self.zone
+    }
+
+    pub fn with_zone(&self, zone: Zone) -> Self {
+        DateTime {
+            instant: self.instant,
+            zone,
+        }
+    }
+
+    /// The seconds since the Unix epoch.
+    pub fn timestamp(&self) -> f64 {
+        self.instant.timestamp_micros() as f64 / 1e6
+    }
+
+    /// The wall clock time in the zone, with its offset at the instant.
+    pub fn local(&self) -> chrono::DateTime<FixedOffset> {
+        match self.zone {
+            Zone::Named(tz) => self.instant.with_timezone(&tz).fixed_offset(),
+            Zone::Fixed(offset) => self.instant.with_timezone(&offset),
+        }
+    }
+
+    /// The datetime in the strftime pattern, an invalid pattern is an error
+    /// rather than a panic.
+    pub fn format(&self, pattern: &str) -> Result<String, String> {
+        let mut formatted = String::new();
+        let result = match self.zone {
+            // %Z is the abbreviation of the zone, e.g. CET
+            Zone::Named(tz) => write!(
+                formatted,
+                "{}",
+                self.instant.with_timezone(&tz).format(pattern)
+            ),
+            Zone::Fixed(_) => write!(formatted, "{}", self.local().format(pattern)),
+        };
+        result.map_err(|_| format!("Invalid datetime pattern '{pattern}'"))?;
+        Ok(formatted)
+    }
+
+    pub fn to_rfc2822(self) -> String {
+        self.local().to_rfc2822()
+    }
+
+    /// The datetime the seconds later, earlier if negative.
+    pub fn checked_add_seconds(&self, seconds: f64) -> Option<Self> {
+        if !seconds.is_finite() {
+            return None;
+        }
+        let delta = TimeDelta::microseconds((seconds * 1e6).round() as i64);
+        Some(DateTime {
+            instant: self.instant.checked_add_signed(delta)?,
+            zone: self.zone,
+        })
+    }
+
+    /// The seconds from the other datetime to this one.
+    pub fn seconds_since(&self, other: &DateTime) -> f64 {
+        (self.instant - other.instant)
+            .num_microseconds()
+            .map_or_else(
+                || (self.instant - other.instant).num_seconds() as f64,
+                |micros| micros as f64 / 1e6,
+            )
+    }
+}
+
+impl PartialEq for DateTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.instant == other.instant
+    }
+}
+
+impl PartialOrd for DateTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.instant.cmp(&other.instant))
+    }
+}
+
+// ISO-8601, `Z` in UTC
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let formatted = match self.zone {
+            Zone::Named(Tz::UTC) => self.instant.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            _ => self.local().to_rfc3339_opts(SecondsFormat::AutoSi, false),
+        };
+        write!(f, "{formatted}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(name: &str) -> Zone {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_iso() {
+        let dt = DateTime::parse("2024-03-10T12:30:00+02:00", None, None).unwrap();
+        assert_eq!(dt.to_string(), "2024-03-10T12:30:00+02:00");
+        assert_eq!(dt.zone().to_string(), "+02:00");
+        let utc = DateTime::parse("2024-03-10T10:30:00Z", None, None).unwrap();
+        assert_eq!(dt, utc);
+        // Without an offset, the wall clock time in the zone
+        let paris = DateTime::parse("2024-07-01 09:00", None, Some(zone("Europe/Paris"))).unwrap();
+        assert_eq!(paris.to_string(), "2024-07-01T09:00:00+02:00");
+        let date = DateTime::parse("2024-01-15", None, None).unwrap();
+        assert_eq!(date.to_string(), "2024-01-15T00:00:00Z");
+        assert!(DateTime::parse("15/01/2024", None, None).is_err());
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let dt = DateTime::parse("Tue, 1 Jul 2003 10:52:37 +0200", Some("rfc2822"), None).unwrap();
+        assert_eq!(dt.to_rfc2822(), "Tue, 1 Jul 2003 10:52:37 +0200");
+        let dt = DateTime::parse("15/01/2024 08:05", Some("%d/%m/%Y %H:%M"), None).unwrap();
+        assert_eq!(dt.to_string(), "2024-01-15T08:05:00Z");
+        let dt = DateTime::parse("15/01/2024", Some("%d/%m/%Y"), None).unwrap();
+        assert_eq!(dt.to_string(), "2024-01-15T00:00:00Z");
+        assert!(DateTime::parse("2024", Some("%d/%m/%Y"), None).is_err());
+    }
+
+    #[test]
+    fn test_dst() {
+        let new_york = zone("America/New_York");
+        // The clocks go forward from 2:00 to 3:00
+        assert!(DateTime::parse("2024-03-10 02:30", None, Some(new_york)).is_err());
+        let before = DateTime::parse("2024-03-10 01:30", None, Some(new_york)).unwrap();
+        let after = before.checked_add_seconds(3600.0).unwrap();
+        assert_eq!(after.to_string(), "2024-03-10T03:30:00-04:00");
+        assert_eq!(after.seconds_since(&before), 3600.0);
+    }
+
+    #[test]
+    fn test_format() {
+        let dt = DateTime::parse("2024-01-15T08:05:00Z", None, None).unwrap();
+        let paris = dt.with_zone(zone("Europe/Paris"));
+        assert_eq!(paris.format("%H:%M %Z").unwrap(), "09:05 CET");
+        assert_eq!(dt.format("%A %-d %B").unwrap(), "Monday 15 January");
+        assert!(dt.format("%Q").is_err());
+        assert_eq!(dt.timestamp(), 1705305900.0);
+    }
+
+    #[test]
+    fn test_zone() {
+        assert_eq!(zone("utc"), Zone::Named(Tz::UTC));
+        assert_eq!(zone("-05:30").to_string(), "-05:30");
+        assert!("Mars/Olympus".parse::<Zone>().is_err());
+        assert!("+99:00".parse::<Zone>().is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use aiscript_arena::Collect;
+use num_bigint::{BigInt, Sign};
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+// The maximum digits of the quotient of `/`, its trailing zeros are trimmed.
+const DIVISION_SCALE: u32 = 28;
+// The largest exponent accepted when parsing, so `1e999999999` can't
+// allocate a huge mantissa.
+const MAX_EXPONENT: i64 = 4096;
+/// The most fractional digits a decimal can be rounded or divided to.
+pub const MAX_PLACES: u32 = 38;
+
+/// An exact decimal number, the mantissa scaled down by `10^scale`.
+///
+/// The scale is kept through addition, subtraction and multiplication,
+/// so `1.50 + 1` is `2.50`, the way amounts of money are written.
+#[derive(Clone, Debug)]
+pub struct Decimal {
+    mantissa: BigInt,
+    scale: u32,
+}
+
+unsafe impl Collect for Decimal {
+    fn needs_trace() -> bool
+    where
+        Self: Sized,
+    {
+        false
+    }
+
+    fn trace(&self, _cc: &aiscript_arena::Collection) {}
+}
+
+/// How the discarded digits are rounded, half to even (banker's rounding)
+/// by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RoundingMode {
+    #[default]
+    HalfEven,
+    HalfUp,
+    HalfDown,
+    // Away from zero
+    Up,
+    // Toward zero
+    Down,
+    Ceiling,
+    Floor,
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half_even" => Ok(RoundingMode::HalfEven),
+            "half_up" => Ok(RoundingMode::HalfUp),
+            "half_down" => Ok(RoundingMode::HalfDown),
+            "up" => Ok(RoundingMode::Up),
+            "down" => Ok(RoundingMode::Down),
+            "ceiling" => Ok(RoundingMode::Ceiling),
+            "floor" => Ok(RoundingMode::Floor),
+            _ => Err(format!("Invalid rounding mode: '{s}'")),
+        }
+    }
+}
+
+fn pow10(exp: u32) -> BigInt {
+    num_traits::pow(BigInt::from(10), exp as usize)
+}
+
+// The quotient rounded to an integer with the rounding mode.
+fn divide_round(num: &BigInt, den: &BigInt, mode: RoundingMode) -> BigInt {
+    let (quotient, remainder) = num.div_rem(den);
+    if remainder.is_zero() {
+        return quotient;
+    }
+    let negative = (num.sign() == Sign::Minus) != (den.sign() == Sign::Minus);
+    let half = (remainder.abs() * 2u32).cmp(&den.abs());
+    let away_from_zero = match mode {
+        RoundingMode::Up => true,
+        RoundingMode::Down => false,
+        RoundingMode::Ceiling => !negative,
+        RoundingMode::Floor => negative,
+        RoundingMode::HalfUp => half != Ordering::Less,
+        RoundingMode::HalfDown => half == Ordering::Greater,
+        RoundingMode::HalfEven => {
+            half == Ordering::Greater || (half == Ordering::Equal && quotient.is_odd())
+        }
+    };
+    match (away_from_zero, negative) {
+        (false, _) => quotient,
+        (true, false) => quotient + 1,
+        (true, true) => quotient - 1,
+    }
+}
+
+impl Decimal {
+    pub fn new(mantissa: BigInt, scale: u32) -> Self {
+        Decimal { mantissa, scale }
+    }
+
+    /// Parse a decimal string like `-12.50` or `1.5e3`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid decimal: '{s}'");
+        let trimmed = s.trim();
+        let (number, exponent) = match trimmed.split_once(['e', 'E']) {
+            Some((number, exponent)) => (
+                number,
+                exponent
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|exp| exp.abs() <= MAX_EXPONENT)
+                    .ok_or_else(invalid)?,
+            ),
+            None => (trimmed, 0),
+        };
+        let (negative, number) = match number.as_bytes().first() {
+            Some(b'-') => (true, &number[1..]),
+            Some(b'+') => (false, &number[1..]),
+            _ => (false, number),
+        };
+        let (integer, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if integer.is_empty() && fraction.is_empty()
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|b| b.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let digits = format!("{integer}{fraction}");
+        let mut mantissa = digits.parse::<BigInt>().map_err(|_| invalid())?;
+        if negative {
+            mantissa = -mantissa;
+        }
+        let scale = fraction.len() as i64 - exponent;
+        if scale < 0 {
+            Ok(Decimal::new(mantissa * pow10(-scale as u32), 0))
+        } else {
+            Ok(Decimal::new(mantissa, scale as u32))
+        }
+    }
+
+    /// The decimal of the shortest representation of the float,
+    /// so `0.1` is exactly `0.1`.
+    pub fn from_f64(value: f64) -> Result<Self, String> {
+        if !value.is_finite() {
+            return Err(format!("Cannot convert {value} to decimal"));
+        }
+        Decimal::parse(&value.to_string())
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mantissa.is_zero()
+    }
+
+    pub fn signum(&self) -> i64 {
+        match self.mantissa.sign() {
+            Sign::Minus => -1,
+            Sign::NoSign => 0,
+            Sign::Plus => 1,
+        }
+    }
+
+    // The mantissa at a larger scale.
+    fn mantissa_at(&self, scale: u32) -> BigInt {
+        &self.mantissa * pow10(scale - self.scale)
+    }
+
+    // Both mantissas at the larger scale of the two.
+    fn aligned(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (self.mantissa_at(scale), other.mantissa_at(scale), scale)
+    }
+
+    pub fn checked_add(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other);
+        Some(Decimal::new(a + b, scale))
+    }
+
+    pub fn checked_sub(&self, other: &Decimal) -> Option<Decimal> {
+        let (a, b, scale) = self.aligned(other);
+        Some(Decimal::new(a - b, scale))
+    }
+
+    pub fn checked_mul(&self, other: &Decimal) -> Option<Decimal> {
+        Some(Decimal::new(
+            &self.mantissa * &other.mantissa,
+            self.scale.checked_add(other.scale)?,
+        ))
+    }
+
+    /// The quotient of `/`, None if dividing by zero. It's exact when it fits
+    /// in 28 fractional digits, the trailing zeros beyond the scale of the
+    /// operands are trimmed.
+    pub fn checked_div(&self, other: &Decimal) -> Option<Decimal> {
+        let min_scale = self.scale.max(other.scale);
+        let quotient = self.div(other, DIVISION_SCALE.max(min_scale), RoundingMode::HalfEven)?;
+        Some(quotient.trim_zeros(min_scale))
+    }
+
+    /// The quotient rounded to the number of fractional digits,
+    /// None if dividing by zero or if the scale overflows.
+    pub fn div(&self, other: &Decimal, places: u32, mode: RoundingMode) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        // (a / 10^sa) / (b / 10^sb) = a * 10^sb / (b * 10^sa)
+        let num = &self.mantissa * pow10(other.scale.checked_add(places)?);
+        let den = &other.mantissa * pow10(self.scale);
+        Some(Decimal::new(divide_round(&num, &den, mode), places))
+    }
+
+    /// The remainder with the sign of the dividend, None if dividing by zero.
+    pub fn checked_rem(&self, other: &Decimal) -> Option<Decimal> {
+        if other.is_zero() {
+            return None;
+        }
+        let (a, b, scale) = self.aligned(other);
+        Some(Decimal::new(a % b, scale))
+    }
+
+    pub fn checked_pow(&self, exp: u32) -> Option<Decimal> {
+        Some(Decimal::new(
+            num_traits::pow(self.mantissa.clone(), exp as usize),
+            self.scale.checked_mul(exp)?,
+        ))
+    }
+
+    pub fn neg(&self) -> Decimal {
+        Decimal::new(-&self.mantissa, self.scale)
+    }
+
+    pub fn abs(&self) -> Decimal {
+        Decimal::new(self.mantissa.abs(), self.scale)
+    }
+
+    /// Round to the number of fractional digits, a decimal with
+    /// fewer digits is returned as is.
+    pub fn round(&self, places: u32, mode: RoundingMode) -> Decimal {
+        if places >= self.scale {
+            return self.clone();
+        }
+        let mantissa = divide_round(&self.mantissa, &pow10(self.scale - places), mode);
+        Decimal::new(mantissa, places)
+    }
+
+    /// Round or pad with zeros to exactly the number of fractional digits.
+    pub fn rescale(&self, places: u32, mode: RoundingMode) -> Decimal {
+        if places >= self.scale {
+            Decimal::new(self.mantissa_at(places), places)
+        } else {
+            self.round(places, mode)
+        }
+    }
+
+    // Remove the trailing zeros of the fraction, down to the minimum scale.
+    fn trim_zeros(mut self, min_scale: u32) -> Decimal {
+        let ten = BigInt::from(10);
+        while self.scale > min_scale {
+            let (quotient, remainder) = self.mantissa.div_rem(&ten);
+            if !remainder.is_zero() {
+                break;
+            }
+            self.mantissa = quotient;
+            self.scale -= 1;
+        }
+        self
+    }
+
+    /// The same value without the trailing zeros of the fraction.
+    pub fn normalize(&self) -> Decimal {
+        self.clone().trim_zeros(0)
+    }
+
+    /// The integer part, None if it doesn't fit in an i64.
+    pub fn to_i64(&self) -> Option<i64> {
+        (&self.mantissa / pow10(self.scale)).to_i64()
+    }
+
+    /// The integer part, None if it doesn't fit in a u64.
+    pub fn to_u64(&self) -> Option<u64> {
+        (&self.mantissa / pow10(self.scale)).to_u64()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The decimal with exactly the number of fractional digits, the integer
+    /// digits are grouped by thousands with the separator if any.
+    pub fn format(&self, places: u32, mode: RoundingMode, separator: Option<&str>) -> String {
+        let formatted = self.rescale(places, mode).to_string();
+        let Some(separator) = separator else {
+            return formatted;
+        };
+        let (sign, unsigned) = match formatted.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", formatted.as_str()),
+        };
+        let (integer, fraction) = match unsigned.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (unsigned, None),
+        };
+        let mut grouped = String::from(sign);
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i) % 3 == 0 {
+                grouped.push_str(separator);
+            }
+            grouped.push(digit);
+        }
+        if let Some(fraction) = fraction {
+            grouped.push('.');
+            grouped.push_str(fraction);
+        }
+        grouped
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Decimal::new(BigInt::from(value), 0)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = self.mantissa.abs().to_string();
+        let scale = self.scale as usize;
+        let digits = if digits.len() <= scale {
+            format!("{}{digits}", "0".repeat(scale + 1 - digits.len()))
+        } else {
+            digits
+        };
+        if self.mantissa.is_negative() {
+            write!(f, "-")?;
+        }
+        if scale == 0 {
+            write!(f, "{digits}")
+        } else {
+            let (integer, fraction) = digits.split_at(digits.len() - scale);
+            write!(f, "{integer}.{fraction}")
+        }
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = self.aligned(other);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Decimals are equal by value, `1.50 == 1.5`.
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!(d("12.50").to_string(), "12.50");
+        assert_eq!(d("-0.05").to_string(), "-0.05");
+        assert_eq!(d(".5").to_string(), "0.5");
+        assert_eq!(d("+3").to_string(), "3");
+        assert_eq!(d("1.5e3").to_string(), "1500");
+        assert_eq!(d("15e-3").to_string(), "0.015");
+        assert_eq!(d("123456789012345678901234567890.1").scale(), 1);
+        for invalid in ["", ".", "1.2.3", "abc", "1e", "--1", "1e99999999", "NaN"] {
+            assert!(Decimal::parse(invalid).is_err(), "{invalid}");
+        }
+        assert_eq!(Decimal::from_f64(0.1).unwrap().to_string(), "0.1");
+        assert!(Decimal::from_f64(f64::INFINITY).is_err());
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(d("0.1").checked_add(&d("0.2")).unwrap().to_string(), "0.3");
+        assert_eq!(d("1.50").checked_add(&d("1")).unwrap().to_string(), "2.50");
+        assert_eq!(d("1").checked_sub(&d("1.01")).unwrap().to_string(), "-0.01");
+        assert_eq!(
+            d("19.99").checked_mul(&d("3")).unwrap().to_string(),
+            "59.97"
+        );
+        assert_eq!(d("10").checked_div(&d("4")).unwrap().to_string(), "2.5");
+        assert_eq!(d("1.00").checked_div(&d("2")).unwrap().to_string(), "0.50");
+        assert_eq!(
+            d("1").checked_div(&d("3")).unwrap().to_string(),
+            "0.3333333333333333333333333333"
+        );
+        assert!(d("1").checked_div(&d("0.00")).is_none());
+        let huge_scale = Decimal::new(BigInt::from(1), u32::MAX);
+        assert!(d("1").div(&huge_scale, 2, RoundingMode::HalfEven).is_none());
+        assert_eq!(d("-7.5").checked_rem(&d("2")).unwrap().to_string(), "-1.5");
+        assert_eq!(d("1.1").checked_pow(2).unwrap().to_string(), "1.21");
+        assert_eq!(d("1.50"), d("1.5"));
+        assert!(d("-2") < d("1.99"));
+    }
+
+    #[test]
+    fn test_rounding() {
+        let round = |s: &str, mode| d(s).round(0, mode).to_string();
+        let cases = [
+            (RoundingMode::HalfEven, ["2", "2", "-2", "3"]),
+            (RoundingMode::HalfUp, ["3", "2", "-3", "3"]),
+            (RoundingMode::HalfDown, ["2", "2", "-2", "3"]),
+            (RoundingMode::Up, ["3", "3", "-3", "3"]),
+            (RoundingMode::Down, ["2", "2", "-2", "2"]),
+            (RoundingMode::Ceiling, ["3", "3", "-2", "3"]),
+            (RoundingMode::Floor, ["2", "2", "-3", "2"]),
+        ];
+        for (mode, expected) in cases {
+            let actual = ["2.5", "2.1", "-2.5", "2.51"].map(|s| round(s, mode));
+            assert_eq!(actual, expected, "{mode:?}");
+        }
+        assert_eq!(
+            d("1.005").round(2, RoundingMode::HalfUp).to_string(),
+            "1.01"
+        );
+        assert_eq!(d("1.5").round(2, RoundingMode::HalfUp).to_string(), "1.5");
+        assert_eq!(
+            d("2")
+                .div(&d("3"), 2, RoundingMode::Down)
+                .unwrap()
+                .to_string(),
+            "0.66"
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mode = RoundingMode::HalfEven;
+        assert_eq!(d("1234567.891").format(2, mode, Some(",")), "1,234,567.89");
+        assert_eq!(d("-1234").format(2, mode, Some(",")), "-1,234.00");
+        assert_eq!(d("123").format(0, mode, Some(",")), "123");
+        assert_eq!(d("0.5").format(3, mode, None), "0.500");
+        assert_eq!(d("12.3400").normalize().to_string(), "12.34");
+        assert_eq!(d("-12.99").to_i64(), Some(-12));
+    }
+}
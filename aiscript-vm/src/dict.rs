@@ -0,0 +1,160 @@
+use std::fmt::Display;
+
+use aiscript_arena::{Collect, Gc};
+use indexmap::IndexMap;
+
+use crate::{Value, object::ListKind};
+
+/// The hashable identity of a dict key, equal keys have equal identities
+/// the same way `Value::equals` compares them: `1`, `1.0` and a decimal `1`
+/// are the same key, as are an interned and a non-interned string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum DictKey {
+    Nil,
+    Boolean(bool),
+    Int(i64),
+    // The bits of a float with a fractional part
+    Float(u64),
+    // A normalized decimal with a fractional part
+    Decimal(String),
+    String(Box<[u8]>),
+    Bytes(Box<[u8]>),
+    Tuple(Vec<DictKey>),
+    // The address of the enum and the variant name
+    EnumVariant(usize, Box<[u8]>),
+}
+
+impl DictKey {
+    pub(crate) fn new(value: &Value) -> Result<Self, String> {
+        let key = match value {
+            Value::Nil => DictKey::Nil,
+            Value::Boolean(b) => DictKey::Boolean(*b),
+            Value::Int(n) => DictKey::Int(*n),
+            Value::Number(n) => match value.as_exact_int() {
+                Some(n) => DictKey::Int(n),
+                None => DictKey::Float(n.to_bits()),
+            },
+            Value::Decimal(d) => {
+                let d = d.normalize();
+                match d.to_i64() {
+                    Some(n) if d.scale() == 0 => DictKey::Int(n),
+                    _ => DictKey::Decimal(d.to_string()),
+                }
+            }
+            Value::String(s) => DictKey::String(s.as_bytes().into()),
+            Value::IoString(s) => DictKey::String(s.as_bytes().into()),
+            Value::Bytes(bytes) => DictKey::Bytes(bytes.as_slice().into()),
+            Value::List(list) if list.borrow().kind == ListKind::Tuple => DictKey::Tuple(
+                list.borrow()
+                    .data
+                    .iter()
+                    .map(DictKey::new)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::EnumVariant(variant) => DictKey::EnumVariant(
+                Gc::as_ptr(variant.enum_) as usize,
+                variant.name.as_bytes().into(),
+            ),
+            _ => return Err(format!("Unhashable dict key: {value}.")),
+        };
+        Ok(key)
+    }
+}
+
+/// A map of any hashable keys, numbers, strings, booleans, tuples and
+/// enum variants, iterated in insertion order.
+#[derive(Default)]
+pub struct Dict<'gc> {
+    // The original key is kept along the value to be handed back
+    entries: IndexMap<DictKey, (Value<'gc>, Value<'gc>)>,
+}
+
+unsafe impl Collect for Dict<'_> {
+    fn trace(&self, cc: &aiscript_arena::Collection) {
+        for (key, value) in self.entries.values() {
+            key.trace(cc);
+            value.trace(cc);
+        }
+    }
+}
+
+impl<'gc> Dict<'gc> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Value<'gc>) -> Result<Option<Value<'gc>>, String> {
+        Ok(self
+            .entries
+            .get(&DictKey::new(key)?)
+            .map(|(_, value)| *value))
+    }
+
+    pub fn contains_key(&self, key: &Value<'gc>) -> Result<bool, String> {
+        Ok(self.entries.contains_key(&DictKey::new(key)?))
+    }
+
+    /// Insert the value, an existing key keeps its position.
+    pub fn insert(&mut self, key: Value<'gc>, value: Value<'gc>) -> Result<(), String> {
+        let hashed = DictKey::new(&key)?;
+        match self.entries.get_mut(&hashed) {
+            Some(entry) => entry.1 = value,
+            None => {
+                self.entries.insert(hashed, (key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove the key and return its value, the order of the other keys
+    /// is preserved.
+    pub fn remove(&mut self, key: &Value<'gc>) -> Result<Option<Value<'gc>>, String> {
+        Ok(self
+            .entries
+            .shift_remove(&DictKey::new(key)?)
+            .map(|(_, value)| value))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Value<'gc>, Value<'gc>)> + '_ {
+        self.entries.values().copied()
+    }
+}
+
+impl Display for Dict<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{key}: {value}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numeric_keys_are_equal() {
+        assert_eq!(
+            DictKey::new(&Value::Int(1)),
+            DictKey::new(&Value::Number(1.0))
+        );
+        assert_ne!(
+            DictKey::new(&Value::Number(1.5)),
+            DictKey::new(&Value::Int(1))
+        );
+        assert!(DictKey::new(&Value::Nil).is_ok());
+    }
+}
@@ -3,31 +3,51 @@ mod ast;
 mod builtins;
 mod chunk;
 mod compiler;
+mod datetime;
+mod decimal;
+mod dict;
 mod module;
 mod object;
 mod parser;
+mod set;
 mod stdlib;
 mod string;
 mod ty;
 mod value;
 mod vm;
+mod workflow;
 
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::ops::Deref;
 
-pub use ai::AiConfig;
+pub use ai::{
+    AiConfig, BudgetScope, PROVIDERS, PromptHistory, PromptVersion, ServedPrompt, Trace,
+    TraceEvent, ping_provider,
+};
 use aiscript_arena::Collect;
 use aiscript_arena::Mutation;
 pub(crate) use aiscript_lexer as lexer;
 pub(crate) use chunk::{Chunk, OpCode};
+pub use module::set_module_paths;
 use serde::Serialize;
 use serde::ser::SerializeMap;
 use serde::ser::SerializeSeq;
-pub use value::Value;
+pub use stdlib::{DEFAULT_KV_PATH, DEFAULT_TEMPLATE_DIR, set_kv_path, set_template_dir};
+pub use string::share_names;
+pub use value::{Value, big_ints_to_strings};
 use vm::State;
 pub use vm::Vm;
 pub use vm::VmError;
+pub use vm::VmOptions;
+pub use vm::set_blocking_limit;
+pub use vm::set_experimental;
+pub use vm::{Arg, CompiledProgram};
+pub use vm::{BreakerState, BreakerStatus, CircuitBreakerConfig, circuit_breakers};
+pub use vm::{ChaosConfig, Fault};
+pub use vm::{CoverageTrace, DEFAULT_COVERAGE_TRACE, coverage_trace_path, save_coverage};
+pub use vm::{DebugAction, DebugFrame, DebugHandler, DebugVariable, Debugger, PauseReason, Paused};
+pub use vm::{RaisedError, StackFrame, TracedError};
 
 type NativeFnInner<'gc> = fn(&mut State<'gc>, Vec<Value<'gc>>) -> Result<Value<'gc>, VmError>;
 type BuiltinMethodInner<'gc> = fn(
@@ -85,17 +105,24 @@ unsafe impl Collect for BuiltinMethod<'_> {
 #[derive(Debug, PartialEq)]
 pub enum ReturnValue {
     Number(f64),
+    Int(i64),
     Boolean(bool),
     String(String),
     Array(Vec<serde_json::Value>),
-    Object(HashMap<String, serde_json::Value>),
-    Response(HashMap<String, serde_json::Value>),
+    // The keys are sorted, like the nested objects, so the JSON of a value
+    // is the same across runs.
+    Object(BTreeMap<String, serde_json::Value>),
+    Response(BTreeMap<String, serde_json::Value>),
     Agent(String), // agent name
+    // The function returned a generator, its values are pulled with `Vm::next_streamed()`.
+    Stream,
+    // The handler returned a list or an object, its JSON is written with `Vm::write_json()`.
+    Json,
     Nil,
 }
 
 impl ReturnValue {
-    pub fn as_object(&self) -> Option<&HashMap<String, serde_json::Value>> {
+    pub fn as_object(&self) -> Option<&BTreeMap<String, serde_json::Value>> {
         match self {
             Self::Object(obj) => Some(obj),
             _ => None,
@@ -110,6 +137,7 @@ impl Serialize for ReturnValue {
     {
         match self {
             ReturnValue::Number(n) => serializer.serialize_f64(*n),
+            ReturnValue::Int(n) => serializer.serialize_i64(*n),
             ReturnValue::Boolean(b) => serializer.serialize_bool(*b),
             ReturnValue::String(s) => serializer.serialize_str(s),
             ReturnValue::Array(vec) => {
@@ -127,7 +155,9 @@ impl Serialize for ReturnValue {
                 s.end()
             }
             ReturnValue::Agent(name) => serializer.serialize_str(name),
-            ReturnValue::Nil => serializer.serialize_none(),
+            ReturnValue::Stream | ReturnValue::Json | ReturnValue::Nil => {
+                serializer.serialize_none()
+            }
         }
     }
 }
@@ -137,6 +167,7 @@ impl Display for ReturnValue {
         match self {
             Self::String(s) => write!(f, "{s}"),
             Self::Number(n) => write!(f, "{n}"),
+            Self::Int(n) => write!(f, "{n}"),
             Self::Array(array) => {
                 write!(f, "[")?;
                 for (i, value) in array.iter().enumerate() {
@@ -152,7 +183,7 @@ impl Display for ReturnValue {
             Self::Object(obj) | Self::Response(obj) => {
                 write!(f, "{}", serde_json::to_string(obj).unwrap())
             }
-            Self::Nil => write!(f, ""),
+            Self::Stream | Self::Json | Self::Nil => write!(f, ""),
         }
     }
 }
@@ -161,9 +192,13 @@ impl<'gc> From<Value<'gc>> for ReturnValue {
     fn from(value: Value<'gc>) -> Self {
         match value {
             Value::Number(value) => ReturnValue::Number(value),
+            Value::Int(value) => ReturnValue::Int(value),
+            Value::Decimal(value) => ReturnValue::String(value.to_string()),
+            Value::DateTime(value) => ReturnValue::String(value.to_string()),
             Value::Boolean(value) => ReturnValue::Boolean(value),
             Value::String(value) => ReturnValue::String(value.to_string()),
             Value::IoString(value) => ReturnValue::String(value.to_string()),
+            Value::Bytes(_) => ReturnValue::String(value.to_string()),
             Value::List(value) => ReturnValue::Array(
                 value
                     .borrow()
@@ -200,6 +235,18 @@ impl<'gc> From<Value<'gc>> for ReturnValue {
                     .map(|(key, value)| (key.to_string(), value.to_serde_value()))
                     .collect(),
             ),
+            Value::Set(set) => ReturnValue::Array(
+                set.borrow()
+                    .iter()
+                    .map(|item| item.to_serde_value())
+                    .collect::<Vec<_>>(),
+            ),
+            Value::Dict(dict) => ReturnValue::Object(
+                dict.borrow()
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_serde_value()))
+                    .collect(),
+            ),
             Value::Agent(agent) => ReturnValue::Agent(agent.name.to_string()),
             _ => ReturnValue::Nil,
         }
@@ -219,4 +266,67 @@ mod tests {
     fn test_expression() {
         assert_eq!(eval("return 1 + 2 * 3;").unwrap(), ReturnValue::Number(7.0));
     }
+
+    #[test]
+    fn test_sorted_object_keys() {
+        let value = eval(
+            r#"
+            let fruits = {pear: 4, fig: 3, apple: 5, kiwi: 4, date: 4, lime: 4};
+            return {zeta: 1, alpha: {b: 2, a: 1}, mid: fruits};
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"alpha":{"a":1.0,"b":2.0},"mid":{"apple":5.0,"date":4.0,"fig":3.0,"kiwi":4.0,"lime":4.0,"pear":4.0},"zeta":1.0}"#
+        );
+    }
+
+    #[test]
+    fn test_json_integers() {
+        let source = r#"
+            use std.serde;
+            let value = serde.from_str("{\"id\": 1234567890123456789, \"max\": 18446744073709551615, \"n\": 7}");
+            return [value.id, value.id - 1, value.max, value.n];
+            "#;
+        let value = eval(source).unwrap();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"[1234567890123456789,1234567890123456788,18446744073709551615,7]"#
+        );
+
+        let mut json = serde_json::to_value(&value).unwrap();
+        crate::big_ints_to_strings(&mut json);
+        assert_eq!(
+            json.to_string(),
+            r#"["1234567890123456789","1234567890123456788","18446744073709551615",7]"#
+        );
+    }
+
+    #[test]
+    fn test_to_json_hook() {
+        let value = eval(
+            r#"
+            class Point {
+                fn new(x, y) {
+                    self.x = x;
+                    self.y = y;
+                }
+
+                fn to_json(self) {
+                    return [self.x, self.y];
+                }
+            }
+            return {origin: Point(0, 0), points: [Point(1, 2)]};
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            value,
+            ReturnValue::Object(BTreeMap::from([
+                ("origin".to_string(), serde_json::json!([0.0, 0.0])),
+                ("points".to_string(), serde_json::json!([[1.0, 2.0]])),
+            ]))
+        );
+    }
 }
@@ -2,11 +2,58 @@ use crate::{Value, VmError, string::InternedString};
 use ahash::AHasher;
 use aiscript_arena::Collect;
 use std::hash::BuildHasherDefault;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex, RwLock},
+    time::SystemTime,
+};
 
 pub enum ModuleSource {
     Cached,
-    New { source: String, path: PathBuf },
+    New { source: &'static str, path: PathBuf },
+}
+
+// Module sources are read once and shared by all VMs in the process,
+// a module is read again only if its file has been modified.
+static SOURCES: LazyLock<Mutex<HashMap<PathBuf, (SystemTime, &'static str)>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// The directories searched for script modules after the current one.
+static MODULE_PATHS: RwLock<Vec<PathBuf>> = RwLock::new(Vec::new());
+
+/// Search the directories for the script modules not found in the current
+/// one, e.g. the root of a workspace so its members share the modules of
+/// its `lib/` as `use lib.<name>`.
+pub fn set_module_paths(paths: Vec<PathBuf>) {
+    *MODULE_PATHS.write().unwrap() = paths;
+}
+
+/// The current directory followed by the paths of [`set_module_paths`].
+pub(crate) fn search_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from(".")];
+    paths.extend(MODULE_PATHS.read().unwrap().iter().cloned());
+    paths
+}
+
+pub(crate) fn read_source(path: &Path) -> Result<&'static str, VmError> {
+    let read_error =
+        |e: std::io::Error| VmError::RuntimeError(format!("Failed to read module: {}", e));
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(read_error)?;
+    let mut sources = SOURCES.lock().unwrap();
+    match sources.get(path) {
+        Some((time, source)) if *time == modified => Ok(source),
+        _ => {
+            let source = fs::read_to_string(path).map_err(read_error)?;
+            // Compiled chunks borrow the source for the lifetime of the VM
+            let source: &'static str = Box::leak(source.into_boxed_str());
+            sources.insert(path.to_owned(), (modified, source));
+            Ok(source)
+        }
+    }
 }
 
 #[derive(Collect)]
@@ -68,6 +115,8 @@ impl<'gc> ModuleKind<'gc> {
 pub struct ModuleManager<'gc> {
     pub modules: HashMap<InternedString<'gc>, ModuleKind<'gc>>,
     search_paths: Vec<PathBuf>,
+    // The chain of script modules being loaded, used to detect circular imports.
+    loading: Vec<InternedString<'gc>>,
 }
 
 impl Default for ModuleManager<'_> {
@@ -80,7 +129,8 @@ impl<'gc> ModuleManager<'gc> {
     pub fn new() -> Self {
         ModuleManager {
             modules: HashMap::new(),
-            search_paths: vec![PathBuf::from(".")], // Current directory by default
+            search_paths: search_paths(),
+            loading: Vec::new(),
         }
     }
 
@@ -111,8 +161,7 @@ impl<'gc> ModuleManager<'gc> {
 
         // For user script modules, find and load the source
         let module_path = self.find_module_file(&name)?;
-        let source = std::fs::read_to_string(&module_path)
-            .map_err(|e| VmError::RuntimeError(format!("Failed to read module: {}", e)))?;
+        let source = read_source(&module_path)?;
 
         Ok(ModuleSource::New {
             source,
@@ -121,20 +170,7 @@ impl<'gc> ModuleManager<'gc> {
     }
 
     fn find_module_file(&self, name: &InternedString) -> Result<PathBuf, VmError> {
-        let module_name = name.to_str().unwrap();
-        let file_name = format!("{}.ai", module_name);
-
-        for search_path in &self.search_paths {
-            let full_path = search_path.join(&file_name);
-            if full_path.exists() {
-                return Ok(full_path);
-            }
-        }
-
-        Err(VmError::RuntimeError(format!(
-            "Could not find module '{}'.",
-            module_name
-        )))
+        find_module_file(&self.search_paths, name.to_str().unwrap())
     }
 
     pub fn register_native_module(&mut self, name: InternedString<'gc>, module: ModuleKind<'gc>) {
@@ -155,6 +191,39 @@ impl<'gc> ModuleManager<'gc> {
         self.modules.insert(name, module);
     }
 
+    /// Mark the module as being loaded, fails if the module is
+    /// imported again while it's still loading.
+    pub fn begin_loading(&mut self, name: InternedString<'gc>) -> Result<(), VmError> {
+        if let Some(start) = self.loading.iter().position(|module| *module == name) {
+            let cycle = self.loading[start..]
+                .iter()
+                .chain([&name])
+                .map(|module| module.to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(VmError::RuntimeError(format!(
+                "Circular import of module '{}': {}.",
+                name, cycle
+            )));
+        }
+        self.loading.push(name);
+        Ok(())
+    }
+
+    pub fn end_loading(&mut self) {
+        self.loading.pop();
+    }
+
+    /// Whether the name is a private global of the script module, which isn't exported.
+    pub fn is_private(&self, module_name: InternedString<'gc>, name: InternedString<'gc>) -> bool {
+        match self.modules.get(&module_name) {
+            Some(ModuleKind::Script {
+                exports, globals, ..
+            }) => !exports.contains_key(&name) && globals.contains_key(&name),
+            _ => false,
+        }
+    }
+
     pub fn get_module(&self, name: InternedString<'gc>) -> Option<&ModuleKind<'gc>> {
         self.modules.get(&name)
     }
@@ -174,3 +243,29 @@ impl<'gc> ModuleManager<'gc> {
         })
     }
 }
+
+/// Find the source file of a script module in the search paths.
+/// The module `tools.search` resolves to `tools/search.ai` or `tools.search.ai`.
+pub(crate) fn find_module_file(
+    search_paths: &[PathBuf],
+    module_name: &str,
+) -> Result<PathBuf, VmError> {
+    let file_names = [
+        format!("{}.ai", module_name.replace('.', "/")),
+        format!("{}.ai", module_name),
+    ];
+
+    for search_path in search_paths {
+        for file_name in &file_names {
+            let full_path = search_path.join(file_name);
+            if full_path.exists() {
+                return Ok(full_path);
+            }
+        }
+    }
+
+    Err(VmError::RuntimeError(format!(
+        "Could not find module '{}'.",
+        module_name
+    )))
+}
@@ -2,18 +2,20 @@ use std::{
     collections::HashMap,
     fmt::{Display, Formatter},
     hash::BuildHasherDefault,
-    iter,
+    iter, mem,
     ops::{Deref, DerefMut},
+    sync::Arc,
 };
 
 use ahash::AHasher;
 use aiscript_arena::{
     Collect, Gc, Mutation,
     lock::{GcRefLock, RefLock},
+    metrics::Metrics,
 };
 use aiscript_directive::Validator;
 
-use crate::{Chunk, Value, string::InternedString};
+use crate::{Chunk, Value, string::InternedString, vm::JsonFormat};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Collect)]
 #[collect(require_static)]
@@ -27,28 +29,60 @@ pub enum ListKind {
 pub struct List<'gc> {
     pub kind: ListKind,
     pub data: Vec<Value<'gc>>,
+    #[collect(require_static)]
+    allocation: ExternalAllocation,
+}
+
+// The bytes of the items of a list, reported to the arena as external
+// allocation so the memory limit sees the lists growing.
+struct ExternalAllocation {
+    metrics: Metrics,
+    bytes: usize,
+}
+
+impl Drop for ExternalAllocation {
+    fn drop(&mut self) {
+        self.metrics.mark_external_deallocation(self.bytes);
+    }
 }
 
 impl<'gc> List<'gc> {
-    pub fn array(data: Vec<Value<'gc>>) -> Self {
-        Self {
-            kind: ListKind::Array,
-            data,
-        }
+    pub fn array(mc: &Mutation<'gc>, data: Vec<Value<'gc>>) -> Self {
+        Self::new(mc, ListKind::Array, data)
     }
 
-    pub fn tuple(data: Vec<Value<'gc>>) -> Self {
-        Self {
-            kind: ListKind::Tuple,
-            data,
-        }
+    pub fn tuple(mc: &Mutation<'gc>, data: Vec<Value<'gc>>) -> Self {
+        Self::new(mc, ListKind::Tuple, data)
     }
 
-    pub fn with_capacity(kind: ListKind, capacity: usize) -> Self {
-        Self {
+    pub fn new(mc: &Mutation<'gc>, kind: ListKind, data: Vec<Value<'gc>>) -> Self {
+        let mut list = Self {
             kind,
-            data: Vec::with_capacity(capacity),
+            data,
+            allocation: ExternalAllocation {
+                metrics: mc.metrics().clone(),
+                bytes: 0,
+            },
+        };
+        list.track_allocation();
+        list
+    }
+
+    /// Report the change of the capacity of the items since the last call,
+    /// called after the items are modified directly.
+    pub fn track_allocation(&mut self) {
+        let bytes = self.data.capacity() * mem::size_of::<Value>();
+        let allocation = &mut self.allocation;
+        if bytes > allocation.bytes {
+            allocation
+                .metrics
+                .mark_external_allocation(bytes - allocation.bytes);
+        } else {
+            allocation
+                .metrics
+                .mark_external_deallocation(allocation.bytes - bytes);
         }
+        allocation.bytes = bytes;
     }
 
     #[inline]
@@ -58,7 +92,11 @@ impl<'gc> List<'gc> {
 
     pub fn push(&mut self, value: Value<'gc>) {
         if self.kind == ListKind::Array {
+            let capacity = self.data.capacity();
             self.data.push(value);
+            if self.data.capacity() != capacity {
+                self.track_allocation();
+            }
         }
     }
 
@@ -107,11 +145,92 @@ pub struct Closure<'gc> {
 pub struct Function<'gc> {
     pub arity: u8,
     pub max_arity: u8,
+    // The number of keyword-only parameters, their slots follow the positional ones.
+    pub keyword_only: u8,
+    // Whether the function has a rest parameter, the extra positional
+    // arguments are collected into an array in the slot after the keyword-only ones.
+    pub variadic: bool,
+    // Whether the function has a `**kwargs` parameter, the unknown keyword
+    // arguments are collected into an object in the last slot.
+    pub kwargs: bool,
     // <name, parameter>
     pub params: HashMap<InternedString<'gc>, Parameter<'gc>>,
     pub chunk: Chunk<'gc>,
     pub name: Option<InternedString<'gc>>,
     pub upvalues: Vec<Upvalue>,
+    // Whether the upvalues are copied when the closure is created, see `move` lambdas.
+    pub capture_by_value: bool,
+    // The script module the function is defined in, its globals are resolved from the module.
+    pub module: Option<InternedString<'gc>>,
+    // Whether the function body has a `yield`, calling it returns a generator.
+    pub is_generator: bool,
+    // The docstring and the declared parameters, e.g. `greet(name: str, loud = false)`, see `help()`.
+    pub doc: Option<InternedString<'gc>>,
+    pub signature: Option<InternedString<'gc>>,
+    pub attributes: Attributes<'gc>,
+}
+
+/// The `@deprecated` note and the `@experimental` flag of a function or a class.
+#[derive(Debug, Clone, Copy, Default, Collect)]
+#[collect(no_drop)]
+pub struct Attributes<'gc> {
+    // The note of `@deprecated`, empty if not given.
+    pub deprecated: Option<InternedString<'gc>>,
+    pub experimental: bool,
+}
+
+impl Attributes<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.deprecated.is_none() && !self.experimental
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Collect)]
+#[collect(require_static)]
+pub enum GeneratorState {
+    Suspended,
+    Running,
+    Done,
+}
+
+/// The suspended call of a generator function, resumed by `next()` until
+/// the function returns.
+#[derive(Collect)]
+#[collect(no_drop)]
+pub struct Generator<'gc> {
+    pub closure: Gc<'gc, Closure<'gc>>,
+    pub ip: usize,
+    // The slots of the call frame, the closure and the arguments at first
+    pub stack: Vec<Value<'gc>>,
+    // The upvalues capturing the frame slots and their offset in the frame,
+    // they are closed while the generator is suspended.
+    pub upvalues: Vec<(usize, GcRefLock<'gc, UpvalueObj<'gc>>)>,
+    pub state: GeneratorState,
+}
+
+impl<'gc> Generator<'gc> {
+    pub fn new(closure: Gc<'gc, Closure<'gc>>, stack: Vec<Value<'gc>>) -> Self {
+        Self {
+            closure,
+            ip: 0,
+            stack,
+            upvalues: Vec::new(),
+            state: GeneratorState::Suspended,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.state == GeneratorState::Done
+    }
+}
+
+impl Display for Generator<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.closure.function.name {
+            Some(name) => write!(f, "<generator {}>", name),
+            None => write!(f, "<generator>"),
+        }
+    }
 }
 
 #[derive(Collect, Default)]
@@ -120,8 +239,11 @@ pub struct Parameter<'gc> {
     // parameter order index
     pub position: u8,
     pub default_value: Value<'gc>,
+    // Whether the parameter has no default value and must be passed.
+    pub required: bool,
+    // Shared by the VMs loading the same compiled program.
     #[collect(require_static)]
-    pub validators: Vec<Box<dyn Validator>>,
+    pub validators: Arc<[Box<dyn Validator>]>,
 }
 
 impl<'gc> Parameter<'gc> {
@@ -129,12 +251,18 @@ impl<'gc> Parameter<'gc> {
         Parameter {
             position,
             default_value,
-            validators: Vec::new(),
+            required: false,
+            validators: Arc::new([]),
         }
     }
 
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+
     pub fn validators(mut self, validators: Vec<Box<dyn Validator>>) -> Self {
-        self.validators = validators;
+        self.validators = validators.into();
         self
     }
 }
@@ -172,6 +300,10 @@ pub enum FunctionType {
     },
     // Class constructor
     Constructor,
+    // Class property getter, e.g. `get full_name() {}`
+    Getter,
+    // Class property setter, e.g. `set full_name(value) {}`
+    Setter,
     // Agent tool function
     Tool,
     // Root script function
@@ -199,14 +331,25 @@ impl FunctionType {
     pub fn is_constructor(&self) -> bool {
         matches!(self, Self::Constructor)
     }
+
+    pub fn is_accessor(&self) -> bool {
+        matches!(self, Self::Getter | Self::Setter)
+    }
 }
 
 #[derive(Collect)]
 #[collect(no_drop)]
 pub struct Class<'gc> {
     pub name: InternedString<'gc>,
+    pub doc: Option<InternedString<'gc>>,
     pub methods: HashMap<InternedString<'gc>, Value<'gc>, BuildHasherDefault<AHasher>>,
     pub static_methods: HashMap<InternedString<'gc>, Value<'gc>, BuildHasherDefault<AHasher>>,
+    // Property getters and setters, called when the property is read or assigned.
+    pub getters: HashMap<InternedString<'gc>, Value<'gc>, BuildHasherDefault<AHasher>>,
+    pub setters: HashMap<InternedString<'gc>, Value<'gc>, BuildHasherDefault<AHasher>>,
+    // The JSON formats of the fields declared with `@json("<format>")`.
+    pub json_formats: HashMap<InternedString<'gc>, JsonFormat, BuildHasherDefault<AHasher>>,
+    pub attributes: Attributes<'gc>,
 }
 
 #[derive(Collect)]
@@ -223,6 +366,18 @@ pub struct BoundMethod<'gc> {
     pub method: Gc<'gc, Closure<'gc>>,
 }
 
+/// The signature of the functions registered by the host application with
+/// `Vm::register_function`, the values cross the boundary as JSON.
+pub type HostFn = dyn Fn(Vec<serde_json::Value>) -> Result<serde_json::Value, String>;
+
+/// A function of the host application embedding the VM.
+#[derive(Collect)]
+#[collect(require_static)]
+pub struct HostFunction {
+    pub name: String,
+    pub function: Box<HostFn>,
+}
+
 #[derive(Collect, Default)]
 #[collect(no_drop)]
 pub struct Object<'gc> {
@@ -235,6 +390,8 @@ pub struct Enum<'gc> {
     pub name: InternedString<'gc>,
     // Variant name -> value mapping, default value is Value::Nil
     pub variants: HashMap<InternedString<'gc>, Value<'gc>>,
+    // The variant names in declaration order
+    pub variant_names: Vec<InternedString<'gc>>,
     // Method name -> function mapping
     pub methods: HashMap<InternedString<'gc>, Value<'gc>>,
     pub static_methods: HashMap<InternedString<'gc>, Value<'gc>>,
@@ -259,14 +416,27 @@ impl<'gc> Enum<'gc> {
     pub fn get_variant_value(&self, variant_name: InternedString<'gc>) -> Option<Value<'gc>> {
         self.variants.get(&variant_name).copied()
     }
+
+    /// The name of the first variant with the value.
+    pub fn find_variant(&self, value: &Value<'gc>) -> Option<InternedString<'gc>> {
+        self.variant_names
+            .iter()
+            .copied()
+            .find(|name| self.variants.get(name).is_some_and(|v| v.equals(value)))
+    }
 }
 
 impl<'gc> Class<'gc> {
     pub fn new(name: InternedString<'gc>) -> Self {
         Self {
             name,
+            doc: None,
             methods: HashMap::default(),
             static_methods: HashMap::default(),
+            getters: HashMap::default(),
+            setters: HashMap::default(),
+            json_formats: HashMap::default(),
+            attributes: Attributes::default(),
         }
     }
 
@@ -315,10 +485,19 @@ impl<'gc> Function<'gc> {
         Self {
             arity,
             max_arity: arity,
+            keyword_only: 0,
+            variadic: false,
+            kwargs: false,
             params: HashMap::new(),
             chunk: Chunk::new(),
             name: Some(name),
             upvalues: Vec::new(),
+            capture_by_value: false,
+            module: None,
+            is_generator: false,
+            doc: None,
+            signature: None,
+            attributes: Attributes::default(),
         }
     }
 
@@ -9,22 +9,25 @@ use indexmap::IndexMap;
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 
 use super::{
-    ast::{Expr, Literal, ParameterDecl, Program, Stmt},
+    ast::{Expr, Literal, ParameterDecl, ParameterKind, Program, Stmt},
     lexer::{Scanner, Token, TokenType},
 };
 use crate::{
     VmError,
     ast::{
-        AgentDecl, ClassDecl, ClassFieldDecl, EnumDecl, EnumVariant, ErrorHandler, FStringPart,
-        FunctionDecl, MatchArm, MatchPattern, ObjectProperty, VariableDecl, Visibility,
+        AgentDecl, Arguments, Attributes, ClassDecl, ClassFieldDecl, EnumDecl, EnumVariant,
+        ErrorHandler, FStringPart, FunctionDecl, MatchArm, MatchPattern, ObjectProperty, Retry,
+        VariableDecl, Visibility, WorkflowDecl, WorkflowStepDecl,
     },
     object::{FunctionType, ListKind},
     ty::{
         ClassField, EnumVariantChecker, FunctionErrorResolver, Type, TypeResolver, ValidationError,
     },
-    vm::Context,
+    vm::{Context, JsonFormat},
+};
+use aiscript_directive::{
+    Directive, DirectiveParams, DirectiveParser, FromDirective, expose::Expose, schedule::Schedule,
 };
-use aiscript_directive::DirectiveParser;
 
 mod stmt_test;
 
@@ -95,6 +98,14 @@ impl<'gc> Parser<'gc> {
         let mut program = Program::new();
         self.advance();
 
+        // The pragmas of the file come before its statements
+        while self.match_token(TokenType::Pragma) {
+            match self.previous.lexeme {
+                "#strict" => program.strict = true,
+                _ => self.error("Unknown pragma."),
+            }
+        }
+
         while !self.is_at_end() {
             if let Some(stmt) = self.declaration() {
                 match &stmt {
@@ -114,6 +125,9 @@ impl<'gc> Parser<'gc> {
             type_resolver.validate_all_types(|token, err| {
                 self.error_at(token, &err);
             });
+            type_resolver.validate_enum_matches(|line, message| {
+                self.warning_with_line(line, message);
+            });
         }
 
         if self.had_error {
@@ -127,14 +141,65 @@ impl<'gc> Parser<'gc> {
         if !self.check(TokenType::Identifier) && !self.check(TokenType::Error) {
             self.error_at_current("Invalid type annotation.");
         }
+        match self.parse_type_member()[..] {
+            // A plain alias is replaced by its target type
+            [ty] => ty,
+            // A union alias is kept as it is
+            _ => self.previous,
+        }
+    }
+
+    // Parse a member of a union type, a type alias expands to its member types.
+    fn parse_type_member(&mut self) -> Vec<Token<'gc>> {
+        if self.match_token(TokenType::Nil) {
+            return vec![self.previous];
+        }
         // Parse either builtin type or custom type (identifier)
         self.advance();
+        if let Some(members) = self.type_resolver.resolve_alias(self.previous.lexeme) {
+            return members.to_vec();
+        }
         // Record type usage for later validation
         self.type_resolver.add_type_usage(self.previous);
-        self.previous
+        vec![self.previous]
+    }
+
+    // A type alias, e.g. `type UserId = str;` or `type MaybeUser = User | nil;`,
+    // it only exists at compile time, so no statement is produced.
+    fn type_alias_declaration(&mut self, visibility: Visibility) -> Option<Stmt<'gc>> {
+        if visibility == Visibility::Public {
+            self.error("'pub' modifier cannot be used with type alias.");
+        }
+        self.consume(TokenType::Identifier, "Expect type alias name.");
+        let name = self.previous;
+        self.consume(TokenType::Equal, "Expect '=' after type alias name.");
+        if !self.check(TokenType::Identifier) && !self.check(TokenType::Nil) {
+            self.error_at_current("Expect type after '='.");
+            return None;
+        }
+        let mut members = self.parse_type_member();
+        while self.match_token(TokenType::Pipe) {
+            if !self.check(TokenType::Identifier) && !self.check(TokenType::Nil) {
+                self.error_at_current("Expect type after '|'.");
+                return None;
+            }
+            members.extend(self.parse_type_member());
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after type alias.");
+        if let Err(err) = self.type_resolver.register_alias(name, members) {
+            self.error_at(name, &err);
+        }
+        None
     }
 
     fn declaration(&mut self) -> Option<Stmt<'gc>> {
+        if self.match_token(TokenType::Pragma) {
+            self.error("A pragma must come before the statements of the file.");
+            return None;
+        }
+        if self.check(TokenType::At) {
+            return self.annotated_declaration();
+        }
         let visibility = if self.match_token(TokenType::Pub) {
             Visibility::Public
         } else {
@@ -152,7 +217,7 @@ impl<'gc> Parser<'gc> {
             self.enum_declaration(visibility)
         } else if self.match_token(TokenType::Class) {
             self.class_declaration(visibility)
-        } else if self.match_token(TokenType::AI) {
+        } else if !self.check_next(TokenType::Dot) && self.match_token(TokenType::AI) {
             self.consume(TokenType::Fn, "Expect 'fn' after 'ai'.");
             self.func_declaration(FunctionType::Function { is_ai: true }, visibility)
         } else if self.match_token(TokenType::Fn) {
@@ -163,6 +228,14 @@ impl<'gc> Parser<'gc> {
             self.const_declaration(visibility)
         } else if self.match_token(TokenType::Agent) {
             self.agent_declaration(visibility)
+        } else if self.check_identifier("type") && self.check_next(TokenType::Identifier) {
+            // `type` is only a keyword in front of an alias name
+            self.advance();
+            self.type_alias_declaration(visibility)
+        } else if self.check_identifier("workflow") && self.check_next(TokenType::Identifier) {
+            // And so is `workflow` in front of a workflow name
+            self.advance();
+            self.workflow_declaration(visibility)
         } else {
             self.statement()
         };
@@ -175,19 +248,118 @@ impl<'gc> Parser<'gc> {
         }
     }
 
+    // A function or agent run periodically by the server, e.g. `@schedule("0 9 * * 1")`,
+    // or an agent served as a chat endpoint, e.g. `@expose("/support")`. The
+    // directives are picked up by the runtime, the declaration compiles as usual.
+    //
+    // A function or class can also be `@deprecated("use foo2")`, its uses are
+    // warned about, or `@experimental`, its uses fail unless enabled in project.toml.
+    fn annotated_declaration(&mut self) -> Option<Stmt<'gc>> {
+        let mut names = Vec::new();
+        let mut attributes = Attributes::default();
+        for directive in DirectiveParser::new(&mut self.scanner).parse_directives() {
+            let result = match directive.name.as_str() {
+                "schedule" => Schedule::from_directive(directive).map(|_| "schedule"),
+                "expose" => Expose::from_directive(directive).map(|_| "expose"),
+                "deprecated" => deprecation_note(&directive).map(|note| {
+                    attributes.deprecated = Some(note);
+                    "deprecated"
+                }),
+                "experimental" => match &directive.params {
+                    DirectiveParams::KeyValue(params) if params.is_empty() => {
+                        attributes.experimental = true;
+                        Ok("experimental")
+                    }
+                    _ => Err("@experimental takes no arguments.".to_string()),
+                },
+                name => Err(format!(
+                    "Invalid directive '@{name}', only @schedule, @expose, @deprecated or @experimental is allowed on declarations."
+                )),
+            };
+            match result {
+                Ok(name) => names.push(name),
+                Err(err) => self.error(&err),
+            }
+        }
+        if let Some(name) = names
+            .iter()
+            .find(|name| matches!(**name, "schedule" | "expose"))
+            && self.scopes.len() > 1
+        {
+            self.error(&format!(
+                "@{name} is only allowed on top-level declarations."
+            ));
+        }
+        let declared = if self.check(TokenType::Pub) {
+            self.scanner
+                .peek_next()
+                .map_or(TokenType::Eof, |token| token.kind)
+        } else {
+            self.current.kind
+        };
+        if names.contains(&"expose") && declared != TokenType::Agent {
+            self.error_at_current("@expose can only be applied to agents.");
+        } else if names.contains(&"schedule")
+            && !matches!(declared, TokenType::AI | TokenType::Fn | TokenType::Agent)
+        {
+            self.error_at_current("@schedule can only be applied to functions and agents.");
+        } else if let Some(name) = names
+            .iter()
+            .find(|name| matches!(**name, "deprecated" | "experimental"))
+            && !matches!(declared, TokenType::AI | TokenType::Fn | TokenType::Class)
+        {
+            self.error_at_current(&format!(
+                "@{name} can only be applied to functions and classes."
+            ));
+        }
+        if self.panic_mode {
+            // Recover at the declaration rather than skipping it
+            self.synchronize();
+        }
+        let mut stmt = self.declaration();
+        match &mut stmt {
+            Some(Stmt::Function(decl)) => decl.attributes = attributes,
+            Some(Stmt::Class(decl)) => decl.attributes = attributes,
+            _ => {}
+        }
+        stmt
+    }
+
     fn use_declaration(&mut self) -> Option<Stmt<'gc>> {
         // Create a vector to store all parts of the module path
         let mut path_parts = Vec::new();
+        // The names of a selective import, e.g. `use std.http.{get, post as send};`
+        let mut items = Vec::new();
 
         self.consume(TokenType::Identifier, "Expect module name after 'use'.");
         path_parts.push(self.previous);
 
         // Handle dotted module paths (e.g., "std.math")
         while self.match_token(TokenType::Dot) {
-            self.consume(TokenType::Identifier, "Expect identifier after '.'.");
+            if self.match_token(TokenType::OpenBrace) {
+                loop {
+                    self.consume(TokenType::Identifier, "Expect name to import.");
+                    let name = self.previous;
+                    items.push((name, self.import_alias()));
+                    if !self.match_token(TokenType::Comma) || self.check(TokenType::CloseBrace) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::CloseBrace, "Expect '}' after imported names.");
+                break;
+            }
+            // `ai` is a keyword, but also the name of the std.ai module
+            if !self.match_token(TokenType::AI) {
+                self.consume(TokenType::Identifier, "Expect identifier after '.'.");
+            }
             path_parts.push(self.previous);
         }
 
+        let alias = if items.is_empty() {
+            self.import_alias()
+        } else {
+            None
+        };
         self.consume(TokenType::Semicolon, "Expect ';' after module path.");
 
         // Combine all parts into a single module path
@@ -204,10 +376,23 @@ impl<'gc> Parser<'gc> {
 
         Some(Stmt::Use {
             path,
+            alias,
+            items,
             line: path.line,
         })
     }
 
+    // Parse the optional `as name` of an import.
+    fn import_alias(&mut self) -> Option<Token<'gc>> {
+        if self.check_identifier("as") {
+            self.advance();
+            self.consume(TokenType::Identifier, "Expect name after 'as'.");
+            Some(self.previous)
+        } else {
+            None
+        }
+    }
+
     fn statement(&mut self) -> Option<Stmt<'gc>> {
         if self.match_token(TokenType::OpenBrace) {
             self.block_statement()
@@ -217,6 +402,8 @@ impl<'gc> Parser<'gc> {
             self.raise_statement()
         } else if self.match_token(TokenType::Return) {
             self.return_statement()
+        } else if self.match_token(TokenType::Yield) {
+            self.yield_statement()
         } else if self.match_token(TokenType::While) {
             self.while_statement()
         } else if self.match_token(TokenType::For) {
@@ -225,6 +412,9 @@ impl<'gc> Parser<'gc> {
             self.break_statement()
         } else if self.match_token(TokenType::Continue) {
             self.continue_statement()
+        } else if self.check_with() {
+            self.advance();
+            self.with_statement()
         } else {
             self.expression_statement()
         }
@@ -259,12 +449,28 @@ impl<'gc> Parser<'gc> {
         let name = self.previous;
         self.scopes.push(name.lexeme.to_owned());
         self.consume(TokenType::OpenBrace, "Expect '{' before agent body.");
+        let doc = self.docstring();
         let mut fields = HashMap::new();
         while !self.check(TokenType::CloseBrace) && !self.check(TokenType::Fn) && !self.is_at_end()
         {
-            let (key, value) = self.field_declaration()?;
+            let (key, mut value) = self.field_declaration()?;
             match key.lexeme {
-                "instructions" | "model" | "tool_choice" => {
+                "instructions" => {
+                    if !matches!(
+                        value,
+                        Expr::Literal {
+                            value: Literal::String { .. },
+                            ..
+                        }
+                    ) && value.as_file_path().is_none()
+                    {
+                        self.error(
+                            "Field 'instructions' in agent declaration should be a string or file(\"path\").",
+                        );
+                        continue;
+                    }
+                }
+                "model" | "tool_choice" => {
                     if !matches!(
                         value,
                         Expr::Literal {
@@ -280,7 +486,44 @@ impl<'gc> Parser<'gc> {
                     }
                 }
                 "tools" => {
-                    if !matches!(value, Expr::List { .. }) {
+                    // A single tool list `[web_search]` is parsed as variant evaluation
+                    value = match value {
+                        Expr::EvaluateVariant { expr, line }
+                            if matches!(*expr, Expr::Variable { .. } | Expr::Call { .. }) =>
+                        {
+                            Expr::List {
+                                elements: vec![*expr],
+                                kind: ListKind::Array,
+                                line,
+                            }
+                        }
+                        value => value,
+                    };
+                    if let Expr::List { elements, .. } = &value {
+                        // Tools are referenced by function name, either a function
+                        // in scope or one imported from a module: [search.web_search]
+                        let is_tool = |element: &Expr| match element {
+                            Expr::Variable { .. } => true,
+                            Expr::Get { object, .. } => matches!(**object, Expr::Variable { .. }),
+                            // Or generated from an OpenAPI spec:
+                            // [tools_from_openapi("openapi.json", allow=["getUser"])]
+                            Expr::Call { .. } => element.as_openapi_tools().is_some(),
+                            _ => false,
+                        };
+                        match elements.iter().find(|element| !is_tool(element)) {
+                            Some(Expr::Call { .. }) => {
+                                self.error(
+                                    "Expect tools_from_openapi(\"spec\", allow=[\"operationId\"]) in agent tools.",
+                                );
+                                continue;
+                            }
+                            Some(_) => {
+                                self.error("Tools in agent declaration should be function names.");
+                                continue;
+                            }
+                            None => {}
+                        }
+                    } else {
                         self.error("Field 'tools' in agent declaration should be an array.");
                         continue;
                     }
@@ -328,6 +571,7 @@ impl<'gc> Parser<'gc> {
         Some(Stmt::Agent(AgentDecl {
             name,
             mangled_name: format!("{}${}", self.scopes.join("$"), name.lexeme),
+            doc,
             fields,
             tools,
             visibility,
@@ -335,6 +579,167 @@ impl<'gc> Parser<'gc> {
         }))
     }
 
+    // A workflow is the steps of a pipeline run one after the other on a
+    // context object, e.g.:
+    //
+    // workflow Ingest {
+    //     retries: 1,
+    //
+    //     @retry(3)
+    //     @compensate("delete")
+    //     fn store(ctx) { ... }
+    //     fn notify(ctx) { ... }
+    //     fn delete(ctx) { ... }
+    // }
+    //
+    // The functions named by `@compensate` aren't steps, they undo their
+    // step if a later one fails.
+    fn workflow_declaration(&mut self, visibility: Visibility) -> Option<Stmt<'gc>> {
+        self.consume(TokenType::Identifier, "Expect workflow name.");
+        let name = self.previous;
+        // The steps are resumed from the store, so they can't capture locals.
+        if self.scopes.len() > 1 {
+            self.error_at(name, "A workflow is only allowed at the top level.");
+        }
+        self.scopes.push(name.lexeme.to_owned());
+        self.consume(TokenType::OpenBrace, "Expect '{' before workflow body.");
+        let doc = self.docstring();
+        let mut default_retry = Retry::default();
+        let mut fields = HashSet::new();
+        while self.check(TokenType::Identifier) && !self.is_at_end() {
+            let (key, value) = self.field_declaration()?;
+            match (key.lexeme, &value) {
+                (
+                    "retries",
+                    Expr::Literal {
+                        value: Literal::Number(n),
+                        ..
+                    },
+                ) if *n >= 0.0 && n.fract() == 0.0 => default_retry.times = *n as u32,
+                ("retries", _) => self.error_at(
+                    key,
+                    "Field 'retries' in workflow declaration should be a non-negative integer.",
+                ),
+                (invalid, _) => self.error_at(
+                    key,
+                    &format!("Invalid field '{invalid}' in workflow declaration."),
+                ),
+            }
+            if !fields.insert(key.lexeme) {
+                self.error_at(
+                    key,
+                    &format!("Duplicate field '{}' in workflow declaration.", key.lexeme),
+                );
+            }
+            if !self.check(TokenType::CloseBrace) {
+                self.consume(TokenType::Comma, "Expect ',' after field declaration.");
+            }
+        }
+
+        let mut steps = Vec::new();
+        while !self.check(TokenType::CloseBrace) && !self.is_at_end() {
+            let mut retry = None;
+            let mut compensate = None;
+            if self.check(TokenType::At) {
+                for directive in DirectiveParser::new(&mut self.scanner).parse_directives() {
+                    let result = match directive.name.as_str() {
+                        "retry" => retry_directive(&directive).map(|r| retry = Some(r)),
+                        "compensate" => match &directive.params {
+                            DirectiveParams::Array(values) => match values.as_slice() {
+                                [serde_json::Value::String(name)] => {
+                                    let name = Token::new(
+                                        TokenType::Identifier,
+                                        name.clone().leak(),
+                                        directive.line,
+                                    );
+                                    compensate = Some(name);
+                                    Ok(())
+                                }
+                                _ => Err("Expect @compensate(\"<function>\").".to_string()),
+                            },
+                            _ => Err("Expect @compensate(\"<function>\").".to_string()),
+                        },
+                        name => Err(format!(
+                            "Invalid directive '@{name}', only @retry or @compensate is allowed on workflow steps."
+                        )),
+                    };
+                    if let Err(err) = result {
+                        self.error(&err);
+                    }
+                }
+            }
+            self.consume(TokenType::Fn, "Expect 'fn' keyword.");
+            let function = self
+                .func_declaration(FunctionType::Function { is_ai: false }, Visibility::Private)?;
+            if let Stmt::Function(FunctionDecl { name, params, .. }) = &function {
+                if params.len() != 1 {
+                    self.error_at(
+                        *name,
+                        "A workflow step takes the context as its only parameter.",
+                    );
+                }
+                if name.lexeme == "done" {
+                    self.error_at(*name, "'done' is reserved for the end of the workflow.");
+                }
+            }
+            steps.push(WorkflowStepDecl {
+                function,
+                retry: retry.unwrap_or(default_retry),
+                compensate,
+            });
+        }
+        self.consume(TokenType::CloseBrace, "Expect '}' after workflow body.");
+        self.scopes.pop();
+
+        let step_name = |step: &WorkflowStepDecl<'gc>| match &step.function {
+            Stmt::Function(FunctionDecl { name, .. }) => name.lexeme,
+            _ => unreachable!("the steps are functions"),
+        };
+        let compensated: HashSet<_> = steps
+            .iter()
+            .filter_map(|step| step.compensate.map(|name| name.lexeme))
+            .collect();
+        for step in &steps {
+            if let Some(compensate) = step.compensate
+                && !steps
+                    .iter()
+                    .any(|other| step_name(other) == compensate.lexeme)
+            {
+                self.error_at(
+                    compensate,
+                    &format!(
+                        "No function '{}' in the workflow to compensate '{}'.",
+                        compensate.lexeme,
+                        step_name(step)
+                    ),
+                );
+            }
+        }
+        let (compensations, steps): (Vec<_>, Vec<_>) = steps
+            .into_iter()
+            .partition(|step| compensated.contains(step_name(step)));
+        if steps.is_empty() {
+            self.error_at(name, "A workflow needs at least one step.");
+        }
+        Some(Stmt::Workflow(WorkflowDecl {
+            name,
+            mangled_name: format!("{}${}", self.scopes.join("$"), name.lexeme),
+            doc,
+            steps,
+            compensations: compensations
+                .into_iter()
+                .map(|step| step.function)
+                .collect(),
+            visibility,
+            line: name.line,
+        }))
+    }
+
+    // The docstring at the start of a function, class or agent body.
+    fn docstring(&mut self) -> Option<Token<'gc>> {
+        self.match_token(TokenType::Doc).then_some(self.previous)
+    }
+
     fn field_declaration(&mut self) -> Option<(Token<'gc>, Expr<'gc>)> {
         self.consume(TokenType::Identifier, "Expect field name.");
         let key = self.previous;
@@ -423,6 +828,10 @@ impl<'gc> Parser<'gc> {
         }
 
         self.consume(TokenType::CloseBrace, "Expect '}' after enum body.");
+        self.type_resolver.register_enum(
+            name.lexeme,
+            variants.iter().map(|variant| variant.name.lexeme).collect(),
+        );
 
         self.scopes.pop();
         // pop that compiler off the stack and restore the enclosing class compiler.
@@ -494,16 +903,38 @@ impl<'gc> Parser<'gc> {
 
         self.type_resolver.register_class(name);
         self.consume(TokenType::OpenBrace, "Expect '{' before class body.");
+        let doc = self.docstring();
 
         let mut fields = Vec::new();
         let mut methods = Vec::new();
+        let mut json_formats = Vec::new();
         while !self.check(TokenType::CloseBrace) && !self.is_at_end() {
             let mut validators = Vec::new();
+            let mut json_format = None;
             if self.check(TokenType::At) {
-                validators = DirectiveParser::new(&mut self.scanner).parse_validators();
+                // @json declares the JSON format of the field, the others are validators
+                let directives = DirectiveParser::new(&mut self.scanner).parse_directives();
+                for directive in directives {
+                    let result = if directive.name == "json" {
+                        JsonFormat::from_directive(directive)
+                            .map(|format| json_format = Some(format))
+                    } else {
+                        FromDirective::from_directive(directive)
+                            .map(|validator| validators.push(validator))
+                    };
+                    if let Err(err) = result {
+                        self.error(&err);
+                    }
+                }
             }
-            if self.check(TokenType::Identifier) && !self.check_next(TokenType::OpenParen) {
+            if self.check(TokenType::Identifier)
+                && !self.check_next(TokenType::OpenParen)
+                && !self.check_accessor()
+            {
                 let mut field = self.parse_class_field()?;
+                if let Some(format) = json_format {
+                    json_formats.push((field.name, format));
+                }
                 self.type_resolver.add_class_field(
                     name.lexeme,
                     ClassField {
@@ -515,6 +946,9 @@ impl<'gc> Parser<'gc> {
                 field.validators = validators;
                 fields.push(field);
             } else {
+                if json_format.is_some() {
+                    self.error_at_current("@json is only allowed on class fields.");
+                }
                 methods.push(self.method_declaration()?);
             }
         }
@@ -623,6 +1057,7 @@ impl<'gc> Parser<'gc> {
                             type_hint: Some(field.type_hint),
                             default_value: field.default_value,
                             validators: field.validators,
+                            kind: ParameterKind::Positional,
                         },
                     );
                 }
@@ -631,11 +1066,12 @@ impl<'gc> Parser<'gc> {
                     mangled_name: format!("{}$new", self.scopes.join("$")),
                     params,
                     doc: None,
-                    return_type: None,
+                    return_types: Vec::new(),
                     error_types: Vec::new(),
                     body,
                     fn_type: FunctionType::Constructor,
                     visibility: Visibility::Public,
+                    attributes: Attributes::default(),
                     line: name.line,
                 })
             };
@@ -649,10 +1085,13 @@ impl<'gc> Parser<'gc> {
         self.class_compiler = self.class_compiler.take().and_then(|c| c.enclosing);
         Some(Stmt::Class(ClassDecl {
             name,
+            doc,
             superclass,
             // fields,
             methods,
+            json_formats,
             visibility,
+            attributes: Attributes::default(),
             line: name.line,
         }))
     }
@@ -697,7 +1136,9 @@ impl<'gc> Parser<'gc> {
         } else {
             Visibility::Private
         };
-        let method = if self.match_token(TokenType::AI) {
+        let method = if self.check_accessor() {
+            self.accessor_declaration(method_vis)?
+        } else if self.match_token(TokenType::AI) {
             self.consume(TokenType::Fn, "Expect 'fn' after 'ai'.");
             self.func_declaration(
                 FunctionType::Method {
@@ -721,6 +1162,34 @@ impl<'gc> Parser<'gc> {
         Some(method)
     }
 
+    // Whether the current token starts a property getter or setter, e.g. `get full_name()`.
+    fn check_accessor(&mut self) -> bool {
+        (self.check_identifier("get") || self.check_identifier("set"))
+            && self.check_next(TokenType::Identifier)
+    }
+
+    fn accessor_declaration(&mut self, visibility: Visibility) -> Option<Stmt<'gc>> {
+        let fn_type = if self.current.lexeme == "get" {
+            FunctionType::Getter
+        } else {
+            FunctionType::Setter
+        };
+        self.advance();
+        let accessor = self.func_declaration(fn_type, visibility)?;
+        if let Stmt::Function(FunctionDecl { name, params, .. }) = &accessor {
+            match fn_type {
+                FunctionType::Getter if !params.is_empty() => {
+                    self.error_at(*name, "A getter can't have parameters.");
+                }
+                FunctionType::Setter if params.len() != 1 => {
+                    self.error_at(*name, "A setter must have exactly one parameter.");
+                }
+                _ => {}
+            }
+        }
+        Some(accessor)
+    }
+
     fn func_declaration(
         &mut self,
         fn_type: FunctionType,
@@ -731,13 +1200,19 @@ impl<'gc> Parser<'gc> {
         self.fn_type = fn_type;
         let type_name = match fn_type {
             FunctionType::Method { .. } => "method",
+            FunctionType::Getter | FunctionType::Setter => "property",
             FunctionType::Tool => "tool function",
             _ => "function",
         };
 
         self.consume(TokenType::Identifier, &format!("Expect {type_name} name."));
         let name = self.previous;
-        self.scopes.push(name.lexeme.to_string());
+        // Getter and setter of the same property are different functions
+        self.scopes.push(match fn_type {
+            FunctionType::Getter => format!("get {}", name.lexeme),
+            FunctionType::Setter => format!("set {}", name.lexeme),
+            _ => name.lexeme.to_string(),
+        });
         if self.fn_type.is_method() && name.lexeme == "new" {
             self.fn_type = FunctionType::Constructor;
         }
@@ -753,6 +1228,9 @@ impl<'gc> Parser<'gc> {
         let mut params = IndexMap::new();
         let mut keyword_args_count = 0;
         let mut self_args_count = 0;
+        let mut has_rest_param = false;
+        let mut has_kwargs_param = false;
+        let mut keyword_only = false;
         loop {
             if self.check(TokenType::CloseParen) {
                 break;
@@ -773,6 +1251,9 @@ impl<'gc> Parser<'gc> {
                     FunctionType::Constructor => {
                         self.error("No need to declare 'self' parameter for class constructor.");
                     }
+                    FunctionType::Getter | FunctionType::Setter => {
+                        self.error("No need to declare 'self' parameter for property accessor.");
+                    }
                     _ => {
                         // unreachable
                     }
@@ -787,6 +1268,39 @@ impl<'gc> Parser<'gc> {
                 continue;
             }
 
+            if has_kwargs_param {
+                self.error_at_current("Keyword rest parameter must be the last parameter.");
+            } else if has_rest_param && !self.check(TokenType::StarStar) {
+                self.error_at_current("Rest parameter must be the last parameter.");
+            }
+            if self.check(TokenType::Star) && self.check_next(TokenType::Comma) {
+                // The bare `*` separator, the following parameters are keyword-only
+                self.advance();
+                if keyword_only {
+                    self.error("Only one '*' separator is allowed.");
+                }
+                keyword_only = true;
+                self.advance();
+                if self.check(TokenType::CloseParen) || self.check(TokenType::StarStar) {
+                    self.error_at_current("Expect keyword-only parameter after '*'.");
+                }
+                continue;
+            }
+            let kind = if self.match_token(TokenType::Star) {
+                if keyword_only {
+                    self.error("Rest parameter can't follow the '*' separator.");
+                }
+                has_rest_param = true;
+                ParameterKind::Rest
+            } else if self.match_token(TokenType::StarStar) {
+                has_kwargs_param = true;
+                ParameterKind::Kwargs
+            } else if keyword_only {
+                ParameterKind::KeywordOnly
+            } else {
+                ParameterKind::Positional
+            };
+
             if self.check(TokenType::Super) {
                 self.advance();
                 self.error("Can't use 'super' as function paramter.");
@@ -803,7 +1317,17 @@ impl<'gc> Parser<'gc> {
             };
 
             // Parse default value if present - must be a literal
-            let default_value = if self.match_token(TokenType::Equal) {
+            let default_value = if kind == ParameterKind::Rest || kind == ParameterKind::Kwargs {
+                if self.match_token(TokenType::Equal) {
+                    if kind == ParameterKind::Rest {
+                        self.error("Rest parameter can't have a default value.");
+                    } else {
+                        self.error("Keyword rest parameter can't have a default value.");
+                    }
+                    self.expression();
+                }
+                None
+            } else if self.match_token(TokenType::Equal) {
                 match self.expression() {
                     Some(expr) => {
                         keyword_args_count += 1;
@@ -815,7 +1339,7 @@ impl<'gc> Parser<'gc> {
                     }
                 }
             } else {
-                if keyword_args_count > 0 {
+                if keyword_args_count > 0 && kind == ParameterKind::Positional {
                     self.error("Positional parameter must come before parameter with a default.");
                 }
                 None
@@ -828,6 +1352,7 @@ impl<'gc> Parser<'gc> {
                     type_hint,
                     default_value,
                     validators: Vec::new(), // TODO: support validator for normal function?
+                    kind,
                 },
             );
 
@@ -847,7 +1372,7 @@ impl<'gc> Parser<'gc> {
             }
         }
 
-        if self.fn_type.is_method() {
+        if self.fn_type.is_method() || self.fn_type.is_accessor() {
             // Update class compiler's current method type
             if let Some(class_compiler) = self.class_compiler.as_mut() {
                 class_compiler.current_method_type = self.fn_type;
@@ -855,14 +1380,10 @@ impl<'gc> Parser<'gc> {
         }
 
         // Parse optional return type and error types
-        let (return_type, error_types) = self.parse_function_return();
+        let (return_types, error_types) = self.parse_function_return();
         self.consume(TokenType::OpenBrace, "Expect '{' before function body.");
 
-        let doc = if self.match_token(TokenType::Doc) {
-            Some(self.previous)
-        } else {
-            None
-        };
+        let doc = self.docstring();
 
         let body = self.block_expr();
 
@@ -882,11 +1403,12 @@ impl<'gc> Parser<'gc> {
             mangled_name,
             doc,
             params,
-            return_type,
+            return_types,
             error_types,
             body,
             fn_type: self.fn_type,
             visibility,
+            attributes: Attributes::default(),
             line: name.line,
         });
         // Restore previous function type
@@ -902,25 +1424,26 @@ impl<'gc> Parser<'gc> {
             .unwrap_or(false)
     }
 
-    fn parse_function_return(&mut self) -> (Option<Token<'gc>>, Vec<Token<'gc>>) {
-        let mut return_type = None;
+    fn parse_function_return(&mut self) -> (Vec<Token<'gc>>, Vec<Token<'gc>>) {
+        let mut return_types = Vec::new();
         let mut error_types = Vec::new();
 
         if self.match_token(TokenType::Arrow) {
-            if !self.check_either(TokenType::Error, TokenType::Identifier) {
+            if !self.check_either(TokenType::Error, TokenType::Identifier)
+                && !self.check(TokenType::Nil)
+            {
                 self.error_at_current("Expect type after '->'.");
-                return (None, Vec::new());
+                return (Vec::new(), Vec::new());
             }
-            let first_type = self.parse_type();
-
             // Check if first type is an error type
-            if first_type.is_error_type() {
+            if self.check(TokenType::Error) {
+                let first_type = self.parse_type();
                 if let Some(resolver) = self.error_resolver.as_mut() {
                     resolver.add_declared_error(first_type);
                 }
                 error_types.push(first_type);
             } else {
-                return_type = Some(first_type);
+                return_types = self.parse_type_member();
             }
 
             // Parse additional types (must be error types)
@@ -943,6 +1466,14 @@ impl<'gc> Parser<'gc> {
                     }
                 }
 
+                // A union return type, e.g. `-> User | nil`
+                if error_types.is_empty()
+                    && self.check_either(TokenType::Identifier, TokenType::Nil)
+                {
+                    let members = self.parse_type_member();
+                    return_types.extend(members);
+                    continue;
+                }
                 if self.match_token(TokenType::Error) {
                     let error_type = self.previous;
                     if let Some(resolver) = self.error_resolver.as_mut() {
@@ -956,7 +1487,7 @@ impl<'gc> Parser<'gc> {
             }
         }
 
-        (return_type, error_types)
+        (return_types, error_types)
     }
 
     fn lambda(&mut self, _can_assign: bool) -> Option<Expr<'gc>> {
@@ -1006,7 +1537,12 @@ impl<'gc> Parser<'gc> {
         };
 
         self.fn_type = previous_fn_type;
-        Some(Expr::Lambda { params, body, line })
+        Some(Expr::Lambda {
+            params,
+            body,
+            capture_by_value: false,
+            line,
+        })
     }
 
     fn pipe_arrow(&mut self, _can_assign: bool) -> Option<Expr<'gc>> {
@@ -1023,24 +1559,28 @@ impl<'gc> Parser<'gc> {
 
         let mut arguments = Vec::new();
         let mut keyword_args = HashMap::new();
+        let mut keyword_spread = None;
+        let mut piped = Some(*left);
 
         // Check if we have explicit parentheses
         if self.match_token(TokenType::OpenParen) {
-            // Parse arguments if any
+            // Parse arguments if any, the left side takes the place of the `_` placeholder
             if !self.check(TokenType::CloseParen) {
-                let (args, kw_args) = self.argument_list()?;
+                let (args, kw_args, spread) = self.arguments(Some(&mut piped))?;
                 arguments = args;
                 keyword_args = kw_args;
+                keyword_spread = spread;
             }
             self.consume(TokenType::CloseParen, "Expect ')' after arguments.");
         }
 
-        // Create call expression with left being first argument
+        // Create call expression with left being first argument if there's no placeholder
         Some(Expr::Call {
             callee,
             is_constructor: false,
-            arguments: std::iter::once(*left).chain(arguments).collect(),
+            arguments: piped.into_iter().chain(arguments).collect(),
             keyword_args,
+            keyword_spread,
             error_handler: self.parse_error_handling(),
             line: callee_name.line,
         })
@@ -1189,6 +1729,37 @@ impl<'gc> Parser<'gc> {
         })
     }
 
+    // `with` is only a keyword in front of the manager expression
+    fn check_with(&mut self) -> bool {
+        self.check_identifier("with")
+            && (self.check_next(TokenType::Identifier) || self.check_next(TokenType::Self_))
+    }
+
+    // with <expr> [as <name>] { <body> }
+    fn with_statement(&mut self) -> Option<Stmt<'gc>> {
+        let line = self.previous.line;
+        self.stop_at_brace = true;
+        let manager = self.expression()?;
+        self.stop_at_brace = false;
+
+        let name = if self.check_identifier("as") {
+            self.advance();
+            self.consume(TokenType::Identifier, "Expect variable name after 'as'.");
+            Some(self.previous)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::OpenBrace, "Expect '{' before with body.");
+        let body = Box::new(self.block_statement()?);
+        Some(Stmt::With {
+            manager,
+            name,
+            body,
+            line,
+        })
+    }
+
     fn block_statement(&mut self) -> Option<Stmt<'gc>> {
         let statements = self.block();
         Some(Stmt::Block {
@@ -1201,6 +1772,8 @@ impl<'gc> Parser<'gc> {
         let value = if !self.check(TokenType::Semicolon) {
             if self.fn_type == FunctionType::Constructor {
                 self.error("Can't return a value from an constructor.");
+            } else if self.fn_type == FunctionType::Setter {
+                self.error("Can't return a value from a setter.");
             }
             Some(self.expression()?)
         } else {
@@ -1214,6 +1787,25 @@ impl<'gc> Parser<'gc> {
         })
     }
 
+    fn yield_statement(&mut self) -> Option<Stmt<'gc>> {
+        match self.fn_type {
+            FunctionType::Function { .. } | FunctionType::Method { .. } => {}
+            FunctionType::Script => self.error("Can't yield from top-level code."),
+            _ => self.error("Only functions and methods can yield."),
+        }
+        let value = if !self.check(TokenType::Semicolon) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume(TokenType::Semicolon, "Expect ';' after yield value.");
+        Some(Stmt::Yield {
+            value,
+            line: self.previous.line,
+        })
+    }
+
     fn expression_statement(&mut self) -> Option<Stmt<'gc>> {
         let expr = self.expression()?;
         self.consume(TokenType::Semicolon, "Expect ';' after expression.");
@@ -1244,7 +1836,7 @@ impl<'gc> Parser<'gc> {
 
         while !self.check(TokenType::CloseBrace) && !self.is_at_end() {
             // Check if we're looking at a potential expression
-            if self.current.is_expr_start() {
+            if self.current.is_expr_start() && !self.check_with() {
                 // Parse as expression
                 if let Some(expr) = self.expression() {
                     if self.check(TokenType::CloseBrace) {
@@ -1591,6 +2183,7 @@ impl<'gc> Parser<'gc> {
                 is_constructor: true,
                 arguments: vec![],
                 keyword_args,
+                keyword_spread: None,
                 error_handler: self.parse_error_handling(),
                 line,
             })
@@ -1718,25 +2311,38 @@ impl<'gc> Parser<'gc> {
         })
     }
 
-    fn argument_list(&mut self) -> Option<(Vec<Expr<'gc>>, HashMap<String, Expr<'gc>>)> {
+    fn argument_list(&mut self) -> Option<Arguments<'gc>> {
+        self.arguments(None)
+    }
+
+    // Parse the arguments of a call, `piped` holds the left side of a pipe
+    // until it's taken by the `_` placeholder argument.
+    fn arguments(&mut self, mut piped: Option<&mut Option<Expr<'gc>>>) -> Option<Arguments<'gc>> {
         let mut arguments = Vec::new();
         let mut keyword_args = HashMap::new();
+        let mut keyword_spread = None;
 
         if !self.check(TokenType::CloseParen) {
             loop {
-                if self.check(TokenType::Identifier)
+                if self.match_token(TokenType::StarStar) {
+                    keyword_spread = Some(Box::new(self.expression()?));
+                    if !self.check(TokenType::CloseParen) {
+                        self.error_at_current("Keyword argument spread must be the last argument.");
+                    }
+                    break;
+                } else if self.check(TokenType::Identifier)
                     && matches!(self.peek_next(), Some(t) if t.kind == TokenType::Equal)
                 {
                     self.advance();
                     let name = self.previous;
                     self.advance(); // consume '='
-                    let value = self.expression()?;
+                    let value = self.argument(piped.as_deref_mut())?;
                     keyword_args.insert(name.lexeme.to_string(), value);
                 } else {
                     if !keyword_args.is_empty() {
                         self.error("Positional arguments must come before keyword arguments.");
                     }
-                    arguments.push(self.expression()?);
+                    arguments.push(self.argument(piped.as_deref_mut())?);
                 }
 
                 if arguments.len() + keyword_args.len() > 255 {
@@ -1750,7 +2356,20 @@ impl<'gc> Parser<'gc> {
             }
         }
 
-        Some((arguments, keyword_args))
+        Some((arguments, keyword_args, keyword_spread))
+    }
+
+    fn argument(&mut self, piped: Option<&mut Option<Expr<'gc>>>) -> Option<Expr<'gc>> {
+        match piped {
+            Some(piped) if self.match_token(TokenType::Underscore) => {
+                let value = piped.take();
+                if value.is_none() {
+                    self.error("Only one '_' placeholder is allowed in a pipe call.");
+                }
+                value
+            }
+            _ => self.expression(),
+        }
     }
 
     fn bracket(&mut self, _can_assign: bool) -> Option<Expr<'gc>> {
@@ -1803,7 +2422,7 @@ impl<'gc> Parser<'gc> {
     fn call(&mut self, _can_assign: bool) -> Option<Expr<'gc>> {
         let callee = Box::new(self.previous_expr.take()?);
 
-        let (arguments, keyword_args) = self.argument_list()?;
+        let (arguments, keyword_args, keyword_spread) = self.argument_list()?;
         self.consume(TokenType::CloseParen, "Expect ')' after arguments.");
 
         let is_constructor =
@@ -1813,6 +2432,7 @@ impl<'gc> Parser<'gc> {
             is_constructor,
             arguments,
             keyword_args,
+            keyword_spread,
             error_handler: self.parse_error_handling(),
             line: self.previous.line,
         })
@@ -1853,7 +2473,7 @@ impl<'gc> Parser<'gc> {
                 line: self.previous.line,
             })
         } else if self.match_token(TokenType::OpenParen) {
-            let (arguments, keyword_args) = self.argument_list()?;
+            let (arguments, keyword_args, keyword_spread) = self.argument_list()?;
             self.consume(TokenType::CloseParen, "Expect ')' after arguments.");
 
             Some(Expr::Invoke {
@@ -1861,6 +2481,7 @@ impl<'gc> Parser<'gc> {
                 method: name,
                 arguments,
                 keyword_args,
+                keyword_spread,
                 error_handler: self.parse_error_handling(),
                 line: self.previous.line,
             })
@@ -1912,10 +2533,20 @@ impl<'gc> Parser<'gc> {
         })
     }
 
+    // Whether the `|` opens an error handler, `|err| { ... }`, rather
+    // than a bitwise or.
+    fn check_error_handler(&mut self) -> bool {
+        self.check(TokenType::Pipe)
+            && self.check_next(TokenType::Identifier)
+            && self.peek_nth(1).map(|token| token.kind) == Some(TokenType::Pipe)
+            && self.peek_nth(2).map(|token| token.kind) == Some(TokenType::OpenBrace)
+    }
+
     // Parse error handling after a call/invoke
     fn parse_error_handling(&mut self) -> Option<ErrorHandler<'gc>> {
         let mut handler = None;
-        if self.match_token(TokenType::Pipe) {
+        if self.check_error_handler() {
+            self.advance();
             self.consume(
                 TokenType::Identifier,
                 "Expect error variable name after '|'.",
@@ -2030,10 +2661,46 @@ impl<'gc> Parser<'gc> {
         self.in_match_arm = false;
 
         self.consume(TokenType::CloseBrace, "Expect '}' after match arms.");
+        self.check_enum_match(&arms, line);
 
         Some(Expr::Match { expr, arms, line })
     }
 
+    // Record a match on the variants of a single enum without a catch-all
+    // arm, whether all the variants are covered is known at the end.
+    fn check_enum_match(&mut self, arms: &[MatchArm<'gc>], line: u32) {
+        let mut enum_name: Option<Token<'gc>> = None;
+        let mut covered = HashSet::new();
+        for arm in arms {
+            for pattern in &arm.patterns {
+                match pattern {
+                    // A catch-all arm, unless it's guarded
+                    MatchPattern::Wildcard | MatchPattern::Variable { .. } => {
+                        if arm.guard.is_none() {
+                            return;
+                        }
+                    }
+                    MatchPattern::EnumVariant {
+                        enum_name: name,
+                        variant,
+                    } => {
+                        if enum_name.is_some_and(|enum_name| enum_name.lexeme != name.lexeme) {
+                            return;
+                        }
+                        enum_name = Some(*name);
+                        if arm.guard.is_none() {
+                            covered.insert(variant.lexeme);
+                        }
+                    }
+                    _ => return,
+                }
+            }
+        }
+        if let Some(enum_name) = enum_name {
+            self.type_resolver.add_enum_match(enum_name, covered, line);
+        }
+    }
+
     fn match_arm(&mut self) -> Option<MatchArm<'gc>> {
         let mut patterns = Vec::new();
         patterns.push(self.match_pattern()?);
@@ -2191,6 +2858,21 @@ impl<'gc> Parser<'gc> {
     fn variable(&mut self, can_assign: bool) -> Option<Expr<'gc>> {
         let name = self.previous;
 
+        // `move |x| ...`, a lambda capturing the variables by value
+        if name.lexeme == "move" && self.match_token(TokenType::Pipe) {
+            return match self.lambda(can_assign)? {
+                Expr::Lambda {
+                    params, body, line, ..
+                } => Some(Expr::Lambda {
+                    params,
+                    body,
+                    capture_by_value: true,
+                    line,
+                }),
+                _ => unreachable!(),
+            };
+        }
+
         if can_assign {
             if let Some(op_kind) = self.match_compound_assignment() {
                 return self.parse_compound_assignment(name, op_kind);
@@ -2236,13 +2918,14 @@ impl<'gc> Parser<'gc> {
         let method = self.previous;
 
         if self.match_token(TokenType::OpenParen) {
-            let (arguments, keyword_args) = self.argument_list()?;
+            let (arguments, keyword_args, keyword_spread) = self.argument_list()?;
             self.consume(TokenType::CloseParen, "Expect ')' after arguments.");
 
             Some(Expr::SuperInvoke {
                 method,
                 arguments,
                 keyword_args,
+                keyword_spread,
                 line: keyword.line,
             })
         } else {
@@ -2278,6 +2961,7 @@ impl<'gc> Parser<'gc> {
         let expr = Box::new(self.expression()?);
         Some(Expr::Prompt {
             expression: expr,
+            error_handler: self.parse_error_handling(),
             line: self.previous.line,
         })
     }
@@ -2306,6 +2990,9 @@ impl<'gc> Parser<'gc> {
                 // If we're in a match arm and see a 'if', stop here to
                 // avoid conflict with match arm's if guard
                 break;
+            } else if self.check_error_handler() {
+                // Leave the error handler to the call or prompt it follows
+                break;
             }
 
             self.advance();
@@ -2348,10 +3035,14 @@ enum Precedence {
     And,        // and
     Equality,   // == !=
     Comparison, // < > <= >=
+    BitOr,      // |
+    BitXor,     // ^
+    BitAnd,     // &
+    Shift,      // << >>
     Term,       // + -
-    Factor,     // * /
+    Factor,     // * / ~/ %
     Power,      // **
-    Unary,      // ! -
+    Unary,      // ! - ~
     Call,       // . ()
     Primary,
 }
@@ -2385,6 +3076,49 @@ impl<'gc> ParseRule<'gc> {
     }
 }
 
+// The note of `@deprecated("use foo2")`, empty for a bare `@deprecated`.
+// `@retry(3)`, or `@retry(times=3, delay=500)` to wait 500 milliseconds
+// before each attempt.
+fn retry_directive(directive: &Directive) -> Result<Retry, String> {
+    let count = |value: &serde_json::Value| value.as_u64().filter(|n| *n <= u32::MAX as u64);
+    let error = || "Expect @retry(<times>) or @retry(times=<times>, delay=<ms>).".to_string();
+    match &directive.params {
+        DirectiveParams::Array(values) => match values.as_slice() {
+            [times] => Ok(Retry {
+                times: count(times).ok_or_else(error)? as u32,
+                delay: 0,
+            }),
+            _ => Err(error()),
+        },
+        DirectiveParams::KeyValue(params) => {
+            if params.keys().any(|key| key != "times" && key != "delay") {
+                return Err(error());
+            }
+            let times = params.get("times").and_then(count).ok_or_else(error)?;
+            let delay = match params.get("delay") {
+                Some(delay) => delay.as_u64().ok_or_else(error)?,
+                None => 0,
+            };
+            Ok(Retry {
+                times: times as u32,
+                delay,
+            })
+        }
+        DirectiveParams::Directives(_) => Err(error()),
+    }
+}
+
+fn deprecation_note(directive: &Directive) -> Result<String, String> {
+    match &directive.params {
+        DirectiveParams::KeyValue(params) if params.is_empty() => Ok(String::new()),
+        DirectiveParams::Array(values) => match values.as_slice() {
+            [serde_json::Value::String(note)] => Ok(note.clone()),
+            _ => Err("Expect @deprecated(\"<note>\").".into()),
+        },
+        _ => Err("Expect @deprecated(\"<note>\").".into()),
+    }
+}
+
 fn get_rule<'gc>(kind: TokenType) -> ParseRule<'gc> {
     match kind {
         TokenType::Dollar => ParseRule::new(Some(Parser::env_lookup), None, Precedence::Unary),
@@ -2400,14 +3134,26 @@ fn get_rule<'gc>(kind: TokenType) -> ParseRule<'gc> {
             ParseRule::new(Some(Parser::bracket), Some(Parser::index), Precedence::Call)
         }
         TokenType::ColonColon => ParseRule::new(None, Some(Parser::enum_variant), Precedence::Call),
-        TokenType::Pipe => ParseRule::new(Some(Parser::lambda), None, Precedence::None),
+        TokenType::Pipe => ParseRule::new(
+            Some(Parser::lambda),
+            Some(Parser::binary),
+            Precedence::BitOr,
+        ),
+        TokenType::Ampersand => ParseRule::new(None, Some(Parser::binary), Precedence::BitAnd),
+        TokenType::Caret => ParseRule::new(None, Some(Parser::binary), Precedence::BitXor),
+        TokenType::Tilde => ParseRule::new(Some(Parser::unary), None, Precedence::None),
+        TokenType::LessLess | TokenType::GreaterGreater => {
+            ParseRule::new(None, Some(Parser::binary), Precedence::Shift)
+        }
         TokenType::PipeArrow => ParseRule::new(None, Some(Parser::pipe_arrow), Precedence::Pipe),
         TokenType::Dot => ParseRule::new(None, Some(Parser::dot), Precedence::Call),
         TokenType::Minus => {
             ParseRule::new(Some(Parser::unary), Some(Parser::binary), Precedence::Term)
         }
         TokenType::Plus => ParseRule::new(None, Some(Parser::binary), Precedence::Term),
-        TokenType::Slash => ParseRule::new(None, Some(Parser::binary), Precedence::Factor),
+        TokenType::Slash | TokenType::TildeSlash => {
+            ParseRule::new(None, Some(Parser::binary), Precedence::Factor)
+        }
         TokenType::Star => ParseRule::new(None, Some(Parser::binary), Precedence::Factor),
         TokenType::StarStar => ParseRule::new(
             None,
@@ -2438,6 +3184,8 @@ fn get_rule<'gc>(kind: TokenType) -> ParseRule<'gc> {
         TokenType::Or => ParseRule::new(None, Some(Parser::or), Precedence::Or),
         TokenType::Super => ParseRule::new(Some(Parser::super_), None, Precedence::None),
         TokenType::Self_ => ParseRule::new(Some(Parser::self_), None, Precedence::None),
+        // The std.ai module, e.g. `ai.ping("openai")`
+        TokenType::AI => ParseRule::new(Some(Parser::variable), None, Precedence::None),
         TokenType::True | TokenType::False | TokenType::Nil => {
             ParseRule::new(Some(Parser::literal), None, Precedence::None)
         }
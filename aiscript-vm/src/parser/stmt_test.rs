@@ -3,7 +3,7 @@ mod tests {
     use aiscript_arena::arena::rootless_mutate;
 
     use crate::{
-        ast::{AgentDecl, FunctionDecl, Stmt},
+        ast::{AgentDecl, FunctionDecl, Retry, Stmt, WorkflowDecl},
         parser::Parser,
         string::InternedStringSet,
         vm::Context,
@@ -39,7 +39,7 @@ mod tests {
                 mangled_name,
                 doc,
                 params,
-                return_type,
+                return_types,
                 body,
                 line,
                 ..
@@ -51,7 +51,7 @@ mod tests {
             assert_eq!(mangled_name, "script$test1");
             assert_eq!(doc.unwrap().lexeme, "test1 doc");
             assert_eq!(params.len(), 0);
-            assert!(return_type.is_none());
+            assert!(return_types.is_empty());
             assert_eq!(body.len(), 1);
             assert_eq!(line, &2);
 
@@ -60,7 +60,7 @@ mod tests {
                 mangled_name,
                 doc,
                 params,
-                return_type,
+                return_types,
                 ..
             }) = &result.statements[1]
             else {
@@ -72,14 +72,14 @@ mod tests {
             assert_eq!(params.len(), 2);
             assert_eq!(params[0].type_hint.unwrap().lexeme, "int");
             assert_eq!(params[1].type_hint.unwrap().lexeme, "int");
-            assert_eq!(return_type.unwrap().lexeme, "int");
+            assert_eq!(return_types[0].lexeme, "int");
 
             let Stmt::Function(FunctionDecl {
                 name,
                 mangled_name,
                 doc,
                 params,
-                return_type,
+                return_types,
                 ..
             }) = &result.statements[2]
             else {
@@ -91,7 +91,7 @@ mod tests {
             assert_eq!(params.len(), 2);
             assert_eq!(params[0].type_hint, None);
             assert_eq!(params[1].type_hint, None);
-            assert_eq!(return_type.unwrap().lexeme, "int");
+            assert_eq!(return_types[0].lexeme, "int");
         });
     }
 
@@ -186,4 +186,122 @@ mod tests {
             assert!(result.is_err());
         });
     }
+
+    #[test]
+    fn test_parse_workflow() {
+        rootless_mutate(|mutation| {
+            let context = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let source = r#"
+                workflow Ingest {
+                    retries: 1,
+
+                    fn fetch(ctx) {}
+
+                    @retry(times=3, delay=500)
+                    @compensate("unindex")
+                    fn index(ctx) {}
+
+                    fn unindex(ctx) {}
+                }
+            "#;
+            let mut parser = Parser::new(context, source);
+            let result = parser.parse().unwrap();
+            let Stmt::Workflow(WorkflowDecl {
+                name,
+                steps,
+                compensations,
+                line,
+                ..
+            }) = &result.statements[0]
+            else {
+                panic!("Expected workflow statement");
+            };
+            assert_eq!(name.lexeme, "Ingest");
+            assert_eq!(*line, 2);
+            let steps = steps
+                .iter()
+                .map(|step| {
+                    let Stmt::Function(FunctionDecl { name, .. }) = &step.function else {
+                        panic!("Expected function statement");
+                    };
+                    (name.lexeme, step.retry, step.compensate.map(|c| c.lexeme))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                steps,
+                vec![
+                    ("fetch", Retry { times: 1, delay: 0 }, None),
+                    (
+                        "index",
+                        Retry {
+                            times: 3,
+                            delay: 500
+                        },
+                        Some("unindex")
+                    ),
+                ]
+            );
+            assert_eq!(compensations.len(), 1);
+
+            for source in [
+                // No steps
+                "workflow Ingest { retries: 1 }",
+                "workflow Ingest { retries: -1, fn fetch(ctx) {} }",
+                "workflow Ingest { fn fetch(ctx, other) {} }",
+                "workflow Ingest { fn done(ctx) {} }",
+                r#"workflow Ingest { @compensate("undo") fn fetch(ctx) {} }"#,
+                "workflow Ingest { @cache fn fetch(ctx) {} }",
+                "fn f() { workflow Ingest { fn fetch(ctx) {} } }",
+            ] {
+                let mut parser = Parser::new(context, source);
+                assert!(parser.parse().is_err(), "{source}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_non_exhaustive_match() {
+        rootless_mutate(|mutation| {
+            let context = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let source = r#"
+                fn name(color) {
+                    return match color {
+                        Color::Red => "red",
+                        Color::Green if true => "green",
+                    };
+                }
+                let all = match Color::Red {
+                    Color::Red | Color::Green => 1,
+                    Color::Blue => 2,
+                };
+                let wildcard = match Color::Red {
+                    Color::Red => 1,
+                    _ => 2,
+                };
+                let binding = match Color::Red {
+                    Color::Red => 1,
+                    other => 2,
+                };
+                enum Color { Red, Green, Blue }
+            "#;
+            let mut parser = Parser::new(context, source);
+            parser.parse().unwrap();
+            assert_eq!(
+                parser
+                    .warnings
+                    .iter()
+                    .map(|warning| warning.to_string())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "[line 3] Warning: Non-exhaustive match on enum 'Color', missing Color::Green, Color::Blue."
+                ]
+            );
+        });
+    }
 }
@@ -0,0 +1,136 @@
+use std::fmt::Display;
+
+use aiscript_arena::Collect;
+use indexmap::IndexMap;
+
+use crate::{Value, dict::DictKey};
+
+/// A set of hashable values, keyed like the dict keys and iterated in
+/// insertion order.
+#[derive(Default)]
+pub struct Set<'gc> {
+    // The original value is kept to be handed back
+    items: IndexMap<DictKey, Value<'gc>>,
+}
+
+unsafe impl Collect for Set<'_> {
+    fn trace(&self, cc: &aiscript_arena::Collection) {
+        for value in self.items.values() {
+            value.trace(cc);
+        }
+    }
+}
+
+impl<'gc> Set<'gc> {
+    pub fn from_values(values: impl IntoIterator<Item = Value<'gc>>) -> Result<Self, String> {
+        let mut set = Set::default();
+        for value in values {
+            set.insert(value)?;
+        }
+        Ok(set)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, value: &Value<'gc>) -> Result<bool, String> {
+        Ok(self.items.contains_key(&DictKey::new(value)?))
+    }
+
+    /// Insert the value, returns false if it was already in the set.
+    pub fn insert(&mut self, value: Value<'gc>) -> Result<bool, String> {
+        let key = DictKey::new(&value)?;
+        if self.items.contains_key(&key) {
+            return Ok(false);
+        }
+        self.items.insert(key, value);
+        Ok(true)
+    }
+
+    /// Remove the value, returns false if it wasn't in the set.
+    pub fn remove(&mut self, value: &Value<'gc>) -> Result<bool, String> {
+        Ok(self.items.shift_remove(&DictKey::new(value)?).is_some())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Value<'gc>> + '_ {
+        self.items.values().copied()
+    }
+
+    pub fn union(&self, other: &Set<'gc>) -> Set<'gc> {
+        let mut items = self.items.clone();
+        for (key, value) in &other.items {
+            items.entry(key.clone()).or_insert(*value);
+        }
+        Set { items }
+    }
+
+    pub fn intersection(&self, other: &Set<'gc>) -> Set<'gc> {
+        self.filter(|key| other.items.contains_key(key))
+    }
+
+    pub fn difference(&self, other: &Set<'gc>) -> Set<'gc> {
+        self.filter(|key| !other.items.contains_key(key))
+    }
+
+    pub fn is_subset(&self, other: &Set<'gc>) -> bool {
+        self.items.keys().all(|key| other.items.contains_key(key))
+    }
+
+    /// Sets are equal if they have the same values, in any order.
+    pub fn equals(&self, other: &Set<'gc>) -> bool {
+        self.len() == other.len() && self.is_subset(other)
+    }
+
+    fn filter(&self, f: impl Fn(&DictKey) -> bool) -> Set<'gc> {
+        let items = self
+            .items
+            .iter()
+            .filter(|(key, _)| f(key))
+            .map(|(key, value)| (key.clone(), *value))
+            .collect();
+        Set { items }
+    }
+}
+
+impl Display for Set<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // `{}` is an empty object
+        if self.is_empty() {
+            return write!(f, "set()");
+        }
+        write!(f, "{{")?;
+        for (i, value) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{value}")?;
+        }
+        write!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(values: &[i64]) -> Set<'static> {
+        Set::from_values(values.iter().map(|n| Value::Int(*n))).unwrap()
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let a = set(&[1, 2, 3]);
+        let b = set(&[3, 4]);
+        assert!(a.union(&b).equals(&set(&[1, 2, 3, 4])));
+        assert!(a.intersection(&b).equals(&set(&[3])));
+        assert!(a.difference(&b).equals(&set(&[1, 2])));
+        assert!(set(&[2, 1]).is_subset(&a));
+        assert!(!a.is_subset(&b));
+        assert_eq!(set(&[1, 1, 2]).len(), 2);
+    }
+}
@@ -0,0 +1,54 @@
+use tokio::runtime::Handle;
+
+use crate::{
+    NativeFn, Value, VmError,
+    ai::{PROVIDERS, ping_provider},
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State, block_on, with_deadline},
+};
+
+pub fn create_ai_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.ai");
+
+    let exports = [("ping", Value::NativeFunction(NativeFn(ai_ping)))]
+        .into_iter()
+        .map(|(name, f)| (ctx.intern_static(name), f))
+        .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+/// Assert the provider of the `[ai]` section is reachable and accepts its
+/// API key, e.g. at the top of a script, so a misconfigured provider fails
+/// fast rather than on the first prompt.
+///
+/// fn ping(provider) {}
+fn ai_ping<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("ping() takes 1 argument.".into()));
+    }
+    let provider = string_arg!(&args, 0, "ping")?.to_string();
+    if !PROVIDERS.contains(&provider.as_str()) {
+        return Err(VmError::RuntimeError(format!(
+            "ping() unknown provider '{provider}', expect one of {}.",
+            PROVIDERS.join(", ")
+        )));
+    }
+    let config = state.ai_config.provider(&provider).cloned().ok_or_else(|| {
+        VmError::RuntimeError(format!(
+            "ping() provider '{provider}' isn't configured, add [ai.{provider}] to project.toml."
+        ))
+    })?;
+    let ping = with_deadline(state.deadline, ping_provider(&provider, &config));
+    let result = match Handle::try_current() {
+        Ok(handle) => block_on(&handle, ping)?,
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(ping)?,
+    };
+    result.map_err(|e| VmError::RuntimeError(format!("ping() {e}.")))?;
+    Ok(Value::Nil)
+}
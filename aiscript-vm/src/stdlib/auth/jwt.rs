@@ -94,6 +94,7 @@ fn build_claims<'gc>(claims_obj: &GcRefLock<'gc, Object<'gc>>) -> Result<Claims,
                     Value::Number(n) => {
                         serde_json::Value::Number(serde_json::Number::from_f64(*n).unwrap())
                     }
+                    Value::Int(n) => serde_json::Value::Number((*n).into()),
                     Value::Boolean(b) => serde_json::Value::Bool(*b),
                     Value::Nil => serde_json::Value::Null,
                     _ => {
@@ -156,7 +157,10 @@ fn claims_to_object(ctx: Context, claims: Claims) -> Value {
     for (key, value) in claims.extra {
         let value = match value {
             serde_json::Value::String(s) => Value::String(ctx.intern(s.as_bytes())),
-            serde_json::Value::Number(n) => Value::Number(n.as_f64().unwrap_or(0.0)),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(n) => Value::Int(n),
+                None => Value::Number(n.as_f64().unwrap_or(0.0)),
+            },
             serde_json::Value::Bool(b) => Value::Boolean(b),
             serde_json::Value::Null => Value::Nil,
             _ => continue, // Skip unsupported types
@@ -319,6 +323,7 @@ fn create_access_token<'gc>(
                     Value::Number(n) => {
                         serde_json::Value::Number(serde_json::Number::from_f64(*n).unwrap())
                     }
+                    Value::Int(n) => serde_json::Value::Number((*n).into()),
                     Value::Boolean(b) => serde_json::Value::Bool(*b),
                     Value::Nil => serde_json::Value::Null,
                     _ => {
@@ -1,3 +1,5 @@
 mod jwt;
+mod password;
 
 pub use jwt::create_jwt_module;
+pub use password::create_password_module;
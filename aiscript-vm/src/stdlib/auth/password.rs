@@ -0,0 +1,73 @@
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier, password_hash::SaltString};
+use rand_core::OsRng;
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State, run_blocking},
+};
+
+pub fn create_password_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern(b"std.auth.password");
+    let exports = [
+        ("hash", Value::NativeFunction(NativeFn(password_hash))),
+        ("verify", Value::NativeFunction(NativeFn(password_verify))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+/// Hash the password with argon2id and a random salt, with the parameters
+/// recommended by OWASP (19 MiB of memory, 2 iterations). The result is a
+/// PHC string, e.g. `$argon2id$v=19$m=19456,t=2,p=1$...`, to be stored as is.
+///
+/// fn hash(password) {}
+fn password_hash<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("hash() takes 1 argument.".into()));
+    }
+    let password = string_arg!(&args, 0, "hash")?.as_bytes().to_vec();
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = run_blocking(state, move || {
+        Argon2::default()
+            .hash_password(&password, &salt)
+            .map(|hash| hash.to_string())
+    })?
+    .map_err(|e| VmError::RuntimeError(format!("hash() failed: {e}")))?;
+    Ok(Value::String(state.intern(hash.as_bytes())))
+}
+
+/// Whether the password matches the hash, an argon2 hash of `hash()` or a
+/// bcrypt one (`$2b$...`) of an existing user table.
+///
+/// fn verify(password, hash) {}
+fn password_verify<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(
+            "verify() takes 2 arguments: the password and the hash.".into(),
+        ));
+    }
+    let password = string_arg!(&args, 0, "verify")?.as_bytes().to_vec();
+    let hash = string_arg!(&args, 1, "verify")?.to_string();
+    let invalid = || VmError::RuntimeError("verify() invalid password hash.".into());
+    let matches = if hash.starts_with("$2") {
+        run_blocking(state, move || bcrypt::verify(&password, &hash))?.map_err(|_| invalid())?
+    } else {
+        PasswordHash::new(&hash).map_err(|_| invalid())?;
+        run_blocking(state, move || {
+            let hash = PasswordHash::new(&hash).expect("parsed");
+            Argon2::default().verify_password(&password, &hash).is_ok()
+        })?
+    };
+    Ok(Value::Boolean(matches))
+}
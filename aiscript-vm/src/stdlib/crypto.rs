@@ -0,0 +1,370 @@
+use aiscript_arena::Gc;
+use rand_core::OsRng;
+use ring::{
+    aead::{AES_128_GCM, AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+    signature::{ED25519, Ed25519KeyPair, KeyPair, UnparsedPublicKey},
+};
+use rsa::{
+    RsaPrivateKey, RsaPublicKey,
+    pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey},
+    pkcs1v15::{Signature, SigningKey, VerifyingKey},
+    pkcs8::{
+        DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding, der::pem,
+    },
+    sha2::Sha256,
+    signature::{SignatureEncoding, Signer, Verifier},
+};
+use serde_json::json;
+
+use crate::{
+    NativeFn, Value, VmError, bytes_arg,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State, run_blocking},
+};
+
+use super::serde::extract_keyword_args;
+
+// The DER of an Ed25519 SubjectPublicKeyInfo up to the 32 bytes of the key.
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+const RSA_BITS: [usize; 3] = [2048, 3072, 4096];
+const MAX_RANDOM_BYTES: usize = 65536;
+
+pub fn create_crypto_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.crypto");
+
+    let exports = [
+        (
+            "random_bytes",
+            Value::NativeFunction(NativeFn(crypto_random_bytes)),
+        ),
+        ("encrypt", Value::NativeFunction(NativeFn(crypto_encrypt))),
+        ("decrypt", Value::NativeFunction(NativeFn(crypto_decrypt))),
+        (
+            "generate_keypair",
+            Value::NativeFunction(NativeFn(crypto_generate_keypair)),
+        ),
+        (
+            "public_key",
+            Value::NativeFunction(NativeFn(crypto_public_key)),
+        ),
+        ("sign", Value::NativeFunction(NativeFn(crypto_sign))),
+        ("verify", Value::NativeFunction(NativeFn(crypto_verify))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// The keys, ciphertexts and signatures are bytes, which can be encoded with
+// std.encoding to be stored or sent. The data is a string or bytes.
+
+/// `n` cryptographically secure random bytes, e.g. an AES key.
+///
+/// fn random_bytes(n) {}
+fn crypto_random_bytes<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let n = match args.as_slice() {
+        [value] => value.as_exact_int().and_then(|n| usize::try_from(n).ok()),
+        _ => None,
+    }
+    .filter(|n| *n <= MAX_RANDOM_BYTES)
+    .ok_or_else(|| {
+        VmError::RuntimeError(format!(
+            "random_bytes() takes a number of bytes from 0 to {MAX_RANDOM_BYTES}."
+        ))
+    })?;
+    let mut bytes = vec![0; n];
+    fill_random(&mut bytes)?;
+    Ok(Value::Bytes(Gc::new(state, bytes)))
+}
+
+/// Encrypt the data with AES-GCM, AES-128 with a 16 bytes key or AES-256
+/// with a 32 bytes key. The result is the random nonce followed by the
+/// ciphertext and its tag. The additional data `aad` is authenticated but
+/// not encrypted, the same one must be given to `decrypt()`.
+///
+/// fn encrypt(key, data, aad = nil) {}
+fn crypto_encrypt<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (key, data, aad) = aead_args(&args, "encrypt")?;
+    let mut nonce = [0; NONCE_LEN];
+    fill_random(&mut nonce)?;
+    let mut sealed = data;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(&aad),
+        &mut sealed,
+    )
+    .map_err(|_| VmError::RuntimeError("encrypt() failed.".into()))?;
+    let mut output = nonce.to_vec();
+    output.extend(sealed);
+    Ok(Value::Bytes(Gc::new(state, output)))
+}
+
+/// Decrypt the result of `encrypt()`, fails if the key or the additional
+/// data differ, or if the ciphertext was tampered with. The data is
+/// returned as bytes, `encoding.utf8_decode()` turns it back into text.
+///
+/// fn decrypt(key, ciphertext, aad = nil) {}
+fn crypto_decrypt<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (key, ciphertext, aad) = aead_args(&args, "decrypt")?;
+    let failed = || {
+        VmError::RuntimeError(
+            "decrypt() failed: wrong key or additional data, or tampered ciphertext.".into(),
+        )
+    };
+    if ciphertext.len() < NONCE_LEN {
+        return Err(failed());
+    }
+    let (nonce, sealed) = ciphertext.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| failed())?;
+    let mut sealed = sealed.to_vec();
+    let data = key
+        .open_in_place(nonce, Aad::from(&aad), &mut sealed)
+        .map_err(|_| failed())?;
+    let data = data.to_vec();
+    Ok(Value::Bytes(Gc::new(state, data)))
+}
+
+/// Generate a key pair, "ed25519" or "rsa" with `bits` of 2048, 3072 or
+/// 4096. Returns an object of the `private_key` (PKCS#8) and `public_key`
+/// (SPKI) in PEM.
+///
+/// fn generate_keypair(algorithm, bits = 2048) {}
+fn crypto_generate_keypair<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["bits"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "generate_keypair() takes 1 or 2 arguments: the algorithm and the bits.".into(),
+        ));
+    }
+    let algorithm = positional[0].as_string()?.to_string();
+    let (private_key, public_key) = match algorithm.as_str() {
+        "ed25519" => {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(|_| {
+                VmError::RuntimeError("generate_keypair() failed to generate the key.".into())
+            })?;
+            let private_key = pem_encode("PRIVATE KEY", pkcs8.as_ref())?;
+            let public_key = PrivateKey::from_pem(private_key.as_bytes())?.public_key_pem()?;
+            (private_key, public_key)
+        }
+        "rsa" => {
+            let bits = match positional.get(1).or(keyword.get("bits")) {
+                None | Some(Value::Nil) => 2048,
+                Some(value) => match value
+                    .as_exact_int()
+                    .and_then(|bits| usize::try_from(bits).ok())
+                {
+                    Some(bits) if RSA_BITS.contains(&bits) => bits,
+                    _ => {
+                        return Err(VmError::RuntimeError(
+                            "generate_keypair() bits must be 2048, 3072 or 4096.".into(),
+                        ));
+                    }
+                },
+            };
+            let key = run_blocking(state, move || RsaPrivateKey::new(&mut OsRng, bits))?
+                .map_err(|e| VmError::RuntimeError(format!("generate_keypair() failed: {e}")))?;
+            let private_key = key
+                .to_pkcs8_pem(LineEnding::LF)
+                .map_err(|e| VmError::RuntimeError(format!("Failed to export the key: {e}")))?
+                .to_string();
+            (
+                private_key,
+                PrivateKey::Rsa(Box::new(key)).public_key_pem()?,
+            )
+        }
+        _ => {
+            return Err(VmError::RuntimeError(format!(
+                "generate_keypair() algorithm must be \"ed25519\" or \"rsa\", got \"{algorithm}\"."
+            )));
+        }
+    };
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &json!({ "private_key": private_key, "public_key": public_key }),
+    ))
+}
+
+/// The public key (SPKI) in PEM of the private key in PEM.
+///
+/// fn public_key(private_key) {}
+fn crypto_public_key<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError(
+            "public_key() takes 1 argument.".into(),
+        ));
+    }
+    let private_key = string_arg!(&args, 0, "public_key")?;
+    let public_key = PrivateKey::from_pem(private_key.as_bytes())?.public_key_pem()?;
+    Ok(Value::String(state.intern(public_key.as_bytes())))
+}
+
+/// Sign the data with the private key in PEM, Ed25519 or RSA with PKCS#1
+/// v1.5 and SHA-256 (as RS256). PKCS#8 and PKCS#1 RSA keys are accepted.
+///
+/// fn sign(private_key, data) {}
+fn crypto_sign<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(
+            "sign() takes 2 arguments: the private key and the data.".into(),
+        ));
+    }
+    let private_key = PrivateKey::from_pem(string_arg!(&args, 0, "sign")?.as_bytes())?;
+    let data = bytes_arg!(&args, 1, "sign")?;
+    let signature = match private_key {
+        PrivateKey::Ed25519(key) => key.sign(data).as_ref().to_vec(),
+        PrivateKey::Rsa(key) => SigningKey::<Sha256>::new(*key).sign(data).to_vec(),
+    };
+    Ok(Value::Bytes(Gc::new(state, signature)))
+}
+
+/// Whether the signature of the data is valid for the public key in PEM,
+/// SPKI or PKCS#1 for RSA keys.
+///
+/// fn verify(public_key, data, signature) {}
+fn crypto_verify<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 3 {
+        return Err(VmError::RuntimeError(
+            "verify() takes 3 arguments: the public key, the data and the signature.".into(),
+        ));
+    }
+    let public_key = PublicKey::from_pem(string_arg!(&args, 0, "verify")?.as_bytes())?;
+    let data = bytes_arg!(&args, 1, "verify")?;
+    let signature = bytes_arg!(&args, 2, "verify")?;
+    let valid = match public_key {
+        PublicKey::Ed25519(key) => UnparsedPublicKey::new(&ED25519, key)
+            .verify(data, signature)
+            .is_ok(),
+        PublicKey::Rsa(key) => Signature::try_from(signature).is_ok_and(|signature| {
+            VerifyingKey::<Sha256>::new(*key)
+                .verify(data, &signature)
+                .is_ok()
+        }),
+    };
+    Ok(Value::Boolean(valid))
+}
+
+enum PrivateKey {
+    Ed25519(Ed25519KeyPair),
+    Rsa(Box<RsaPrivateKey>),
+}
+
+impl PrivateKey {
+    fn from_pem(pem: &[u8]) -> Result<Self, VmError> {
+        let invalid = || VmError::RuntimeError("Invalid private key.".into());
+        let (label, der) = pem::decode_vec(pem).map_err(|_| invalid())?;
+        match label {
+            "PRIVATE KEY" => Ed25519KeyPair::from_pkcs8_maybe_unchecked(&der)
+                .map(PrivateKey::Ed25519)
+                .or_else(|_| RsaPrivateKey::from_pkcs8_der(&der).map(|key| Self::Rsa(key.into())))
+                .map_err(|_| invalid()),
+            "RSA PRIVATE KEY" => RsaPrivateKey::from_pkcs1_der(&der)
+                .map(|key| Self::Rsa(key.into()))
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn public_key_pem(&self) -> Result<String, VmError> {
+        match self {
+            PrivateKey::Ed25519(key) => {
+                let mut der = ED25519_SPKI_PREFIX.to_vec();
+                der.extend_from_slice(key.public_key().as_ref());
+                pem_encode("PUBLIC KEY", &der)
+            }
+            PrivateKey::Rsa(key) => RsaPublicKey::from(key.as_ref())
+                .to_public_key_pem(LineEnding::LF)
+                .map_err(|e| VmError::RuntimeError(format!("Failed to export the key: {e}"))),
+        }
+    }
+}
+
+enum PublicKey {
+    Ed25519(Vec<u8>),
+    Rsa(Box<RsaPublicKey>),
+}
+
+impl PublicKey {
+    fn from_pem(pem: &[u8]) -> Result<Self, VmError> {
+        let invalid = || VmError::RuntimeError("Invalid public key.".into());
+        let (label, der) = pem::decode_vec(pem).map_err(|_| invalid())?;
+        match label {
+            "PUBLIC KEY" => match der.strip_prefix(&ED25519_SPKI_PREFIX) {
+                Some(key) if key.len() == 32 => Ok(PublicKey::Ed25519(key.to_vec())),
+                _ => RsaPublicKey::from_public_key_der(&der)
+                    .map(|key| PublicKey::Rsa(key.into()))
+                    .map_err(|_| invalid()),
+            },
+            "RSA PUBLIC KEY" => RsaPublicKey::from_pkcs1_der(&der)
+                .map(|key| PublicKey::Rsa(key.into()))
+                .map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+// The key, data and additional data of `encrypt()` and `decrypt()`.
+fn aead_args<'gc>(
+    args: &[Value<'gc>],
+    fn_name: &str,
+) -> Result<(LessSafeKey, Vec<u8>, Vec<u8>), VmError> {
+    let (positional, keyword) = extract_keyword_args(args, &["aad"])?;
+    if positional.len() < 2 || positional.len() > 3 {
+        return Err(VmError::RuntimeError(format!(
+            "{fn_name}() takes 2 or 3 arguments: the key, the data and the additional data."
+        )));
+    }
+    let key = bytes_arg!(&positional, 0, fn_name)?;
+    let algorithm = match key.len() {
+        16 => &AES_128_GCM,
+        32 => &AES_256_GCM,
+        len => {
+            return Err(VmError::RuntimeError(format!(
+                "{fn_name}() key must be 16 or 32 bytes, got {len}."
+            )));
+        }
+    };
+    let key = LessSafeKey::new(UnboundKey::new(algorithm, key).expect("checked length"));
+    let data = bytes_arg!(&positional, 1, fn_name)?.to_vec();
+    let aad = match positional.get(2).or(keyword.get("aad")) {
+        None | Some(Value::Nil) => Vec::new(),
+        Some(value) => value.as_byte_slice().map(<[u8]>::to_vec).ok_or_else(|| {
+            VmError::RuntimeError(format!("{fn_name}() aad must be a string or bytes."))
+        })?,
+    };
+    Ok((key, data, aad))
+}
+
+fn fill_random(bytes: &mut [u8]) -> Result<(), VmError> {
+    SystemRandom::new()
+        .fill(bytes)
+        .map_err(|_| VmError::RuntimeError("Failed to generate random bytes.".into()))
+}
+
+fn pem_encode(label: &str, der: &[u8]) -> Result<String, VmError> {
+    pem::encode_string(label, LineEnding::LF, der)
+        .map_err(|e| VmError::RuntimeError(format!("Failed to export the key: {e}")))
+}
@@ -0,0 +1,120 @@
+use aiscript_arena::Gc;
+
+use crate::{
+    NativeFn, Value, VmError,
+    builtins::datetime::zone_arg,
+    datetime::DateTime,
+    module::ModuleKind,
+    vm::{Context, State},
+};
+
+use super::serde::extract_keyword_args;
+
+pub fn create_datetime_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.datetime");
+
+    let exports = [
+        ("now", Value::NativeFunction(NativeFn(datetime_now))),
+        ("parse", Value::NativeFunction(NativeFn(datetime_parse))),
+        (
+            "from_timestamp",
+            Value::NativeFunction(NativeFn(datetime_from_timestamp)),
+        ),
+        (
+            "is_datetime",
+            Value::NativeFunction(NativeFn(datetime_is_datetime)),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// Datetimes are values of their own: `+` and `-` add and subtract seconds,
+// e.g. `time.hours(2)`, the difference of two datetimes is in seconds, and
+// they compare by instant whatever their timezone.
+
+/// The current datetime in the timezone, a tz database name like
+/// "Europe/Paris" or an offset like "+02:00", UTC by default.
+///
+/// fn now(tz = "UTC") {}
+fn datetime_now<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["tz"])?;
+    if positional.len() > 1 {
+        return Err(VmError::RuntimeError(
+            "now() takes at most 1 argument.".into(),
+        ));
+    }
+    let zone = zone_arg(positional.first().or(keyword.get("tz")), "now")?;
+    let dt = DateTime::now(zone.unwrap_or_default());
+    Ok(Value::DateTime(Gc::new(state, dt)))
+}
+
+/// Parse an ISO-8601 datetime, an RFC 2822 one with the "rfc2822" format,
+/// or else the text of the strftime pattern, e.g. "%d/%m/%Y %H:%M". A time
+/// without an offset is the wall clock time in the timezone, UTC by
+/// default, and a date alone its midnight.
+///
+/// fn parse(text, format = nil, tz = nil) {}
+fn datetime_parse<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["format", "tz"])?;
+    if positional.is_empty() || positional.len() > 3 {
+        return Err(VmError::RuntimeError(
+            "parse() takes 1 to 3 arguments: the text, the format and the timezone.".into(),
+        ));
+    }
+    let text = positional[0]
+        .as_string_value()
+        .map_err(|_| VmError::RuntimeError("parse() text must be a string.".into()))?;
+    let format = match positional.get(1).or(keyword.get("format")) {
+        None | Some(Value::Nil) => None,
+        Some(format) => Some(
+            format
+                .as_string_value()
+                .map_err(|_| VmError::RuntimeError("parse() format must be a string.".into()))?,
+        ),
+    };
+    let zone = zone_arg(positional.get(2).or(keyword.get("tz")), "parse")?;
+    let dt = DateTime::parse(text.as_str(), format.as_ref().map(|f| f.as_str()), zone)
+        .map_err(|e| VmError::RuntimeError(format!("parse() {e}.")))?;
+    Ok(Value::DateTime(Gc::new(state, dt)))
+}
+
+/// The datetime of the seconds since the Unix epoch, e.g. `time.now()`,
+/// in the timezone, UTC by default.
+///
+/// fn from_timestamp(seconds, tz = "UTC") {}
+fn datetime_from_timestamp<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["tz"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "from_timestamp() takes 1 or 2 arguments: the seconds and the timezone.".into(),
+        ));
+    }
+    let seconds = positional[0]
+        .as_number()
+        .map_err(|_| VmError::RuntimeError("from_timestamp() seconds must be a number.".into()))?;
+    let zone = zone_arg(positional.get(1).or(keyword.get("tz")), "from_timestamp")?;
+    let dt = DateTime::from_timestamp(seconds, zone.unwrap_or_default())
+        .ok_or_else(|| VmError::RuntimeError("from_timestamp() out of range.".into()))?;
+    Ok(Value::DateTime(Gc::new(state, dt)))
+}
+
+/// fn is_datetime(value) {}
+fn datetime_is_datetime<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Boolean(matches!(
+        args.first(),
+        Some(Value::DateTime(_))
+    )))
+}
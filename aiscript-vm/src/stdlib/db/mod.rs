@@ -5,3 +5,41 @@ mod sqlite;
 pub use pg::create_pg_module;
 pub use redis::create_redis_module;
 pub use sqlite::create_sqlite_module;
+
+// The `application_name` of the database session while it runs the queries
+// of a request, so they can be correlated with the request in the database
+// logs and `pg_stat_activity`. The SQL text is left alone, an annotation
+// there would defeat the prepared statement cache.
+fn application_name(request_id: &str) -> String {
+    format!("request_id={request_id}")
+}
+
+// Whether the error means the database is down rather than the query failed,
+// only the former counts towards the circuit breaker.
+fn is_sqlx_unavailable(err: &sqlx::Error) -> bool {
+    matches!(
+        err,
+        sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed
+            | sqlx::Error::WorkerCrashed
+    )
+}
+
+fn is_redis_unavailable(err: &::redis::RedisError) -> bool {
+    err.is_io_error()
+        || err.is_timeout()
+        || err.is_connection_refusal()
+        || err.is_connection_dropped()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::application_name;
+
+    #[test]
+    fn test_application_name() {
+        assert_eq!(application_name("abc"), "request_id=abc");
+    }
+}
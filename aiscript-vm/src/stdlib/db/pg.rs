@@ -1,15 +1,16 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, time::Instant};
 
 use aiscript_arena::{Gc, GcRefLock, RefLock};
-use sqlx::{Column, Postgres, Row, TypeInfo, ValueRef};
+use sqlx::{Column, Postgres, Row, TypeInfo, ValueRef, pool::PoolConnection};
 
 use tokio::runtime::Handle;
 
+use super::{application_name, is_sqlx_unavailable};
 use crate::{
     NativeFn, Value, VmError,
     module::ModuleKind,
     object::{Class, Instance, Object},
-    vm::{Context, State},
+    vm::{Context, State, breaker, with_deadline},
 };
 
 thread_local! {
@@ -48,14 +49,14 @@ fn column_to_value<'gc>(
 
     let value = match type_info.name() {
         // Integer types
-        "INT2" | "SMALLINT" => row.try_get::<i16, _>(i).map(|v| Value::Number(v as f64)),
-        "INT4" | "INTEGER" => row.try_get::<i32, _>(i).map(|v| Value::Number(v as f64)),
-        "INT8" | "BIGINT" => row.try_get::<i64, _>(i).map(|v| Value::Number(v as f64)),
+        "INT2" | "SMALLINT" => row.try_get::<i16, _>(i).map(|v| Value::Int(v as i64)),
+        "INT4" | "INTEGER" => row.try_get::<i32, _>(i).map(|v| Value::Int(v as i64)),
+        "INT8" | "BIGINT" => row.try_get::<i64, _>(i).map(Value::Int),
 
         // Serial types (same as integer types)
-        "SERIAL2" | "SMALLSERIAL" => row.try_get::<i16, _>(i).map(|v| Value::Number(v as f64)),
-        "SERIAL4" | "SERIAL" => row.try_get::<i32, _>(i).map(|v| Value::Number(v as f64)),
-        "SERIAL8" | "BIGSERIAL" => row.try_get::<i64, _>(i).map(|v| Value::Number(v as f64)),
+        "SERIAL2" | "SMALLSERIAL" => row.try_get::<i16, _>(i).map(|v| Value::Int(v as i64)),
+        "SERIAL4" | "SERIAL" => row.try_get::<i32, _>(i).map(|v| Value::Int(v as i64)),
+        "SERIAL8" | "BIGSERIAL" => row.try_get::<i64, _>(i).map(Value::Int),
 
         // Floating-point types
         "FLOAT4" | "REAL" => row.try_get::<f32, _>(i).map(|v| Value::Number(v as f64)),
@@ -103,23 +104,14 @@ fn column_to_value<'gc>(
             match &t[1..] {
                 // Integer arrays
                 "INT2" | "SMALLINT" => row.try_get::<Vec<i16>, _>(i).map(|v| {
-                    Value::array(
-                        &ctx,
-                        v.into_iter().map(|n| Value::Number(n as f64)).collect(),
-                    )
+                    Value::array(&ctx, v.into_iter().map(|n| Value::Int(n as i64)).collect())
                 }),
                 "INT4" | "INTEGER" => row.try_get::<Vec<i32>, _>(i).map(|v| {
-                    Value::array(
-                        &ctx,
-                        v.into_iter().map(|n| Value::Number(n as f64)).collect(),
-                    )
-                }),
-                "INT8" | "BIGINT" => row.try_get::<Vec<i64>, _>(i).map(|v| {
-                    Value::array(
-                        &ctx,
-                        v.into_iter().map(|n| Value::Number(n as f64)).collect(),
-                    )
+                    Value::array(&ctx, v.into_iter().map(|n| Value::Int(n as i64)).collect())
                 }),
+                "INT8" | "BIGINT" => row
+                    .try_get::<Vec<i64>, _>(i)
+                    .map(|v| Value::array(&ctx, v.into_iter().map(Value::Int).collect())),
 
                 // Float arrays
                 "FLOAT4" | "REAL" => row.try_get::<Vec<f32>, _>(i).map(|v| {
@@ -195,99 +187,110 @@ fn execute_query<'a, E>(
     executor: E,
     query: &str,
     bindings: Vec<Value<'_>>,
+    deadline: Option<Instant>,
 ) -> Result<Vec<sqlx::postgres::PgRow>, VmError>
 where
     E: sqlx::Executor<'a, Database = sqlx::Postgres>,
 {
-    Handle::current()
-        .block_on(async {
-            let mut query_builder = sqlx::query(query);
-
-            // Bind parameters
-            for value in bindings {
-                match value {
-                    Value::Number(n) => {
-                        query_builder = query_builder.bind(n);
-                    }
-                    Value::String(s) => {
-                        let s_str = s.to_str().unwrap();
-                        // Try to parse special types from string
-                        if let Ok(uuid) = sqlx::types::Uuid::parse_str(s_str) {
-                            query_builder = query_builder.bind(uuid);
-                        } else if let Ok(date) =
-                            sqlx::types::chrono::NaiveDate::parse_from_str(s_str, "%Y-%m-%d")
-                        {
-                            query_builder = query_builder.bind(date);
-                        } else if let Ok(datetime) =
-                            sqlx::types::chrono::NaiveDateTime::parse_from_str(
-                                s_str,
-                                "%Y-%m-%dT%H:%M:%S",
-                            )
-                        {
-                            query_builder = query_builder.bind(datetime);
-                        } else {
-                            query_builder = query_builder.bind(s_str);
-                        }
-                    }
-                    Value::Boolean(b) => {
-                        query_builder = query_builder.bind(b);
-                    }
-                    Value::Nil => {
-                        query_builder = query_builder.bind(Option::<String>::None);
+    let permit = breaker::acquire("db:postgres")?;
+    let result = Handle::current().block_on(with_deadline(deadline, async {
+        let mut query_builder = sqlx::query(query);
+
+        // Bind parameters
+        for value in bindings {
+            match value {
+                Value::Number(n) => {
+                    query_builder = query_builder.bind(n);
+                }
+                Value::Int(n) => {
+                    query_builder = query_builder.bind(n);
+                }
+                Value::String(s) => {
+                    let s_str = s.to_str().unwrap();
+                    // Try to parse special types from string
+                    if let Ok(uuid) = sqlx::types::Uuid::parse_str(s_str) {
+                        query_builder = query_builder.bind(uuid);
+                    } else if let Ok(date) =
+                        sqlx::types::chrono::NaiveDate::parse_from_str(s_str, "%Y-%m-%d")
+                    {
+                        query_builder = query_builder.bind(date);
+                    } else if let Ok(datetime) = sqlx::types::chrono::NaiveDateTime::parse_from_str(
+                        s_str,
+                        "%Y-%m-%dT%H:%M:%S",
+                    ) {
+                        query_builder = query_builder.bind(datetime);
+                    } else {
+                        query_builder = query_builder.bind(s_str);
                     }
-                    Value::List(arr) => {
-                        let arr = &arr.borrow().data;
-                        if let Some(first) = arr.first() {
-                            match first {
-                                Value::Number(_) => {
-                                    let nums: Vec<f64> = arr
-                                        .iter()
-                                        .filter_map(|v| match v {
-                                            Value::Number(n) => Some(*n),
-                                            _ => None,
-                                        })
-                                        .collect();
-                                    query_builder = query_builder.bind(nums);
-                                }
-                                Value::String(_) => {
-                                    let strings: Vec<String> = arr
-                                        .iter()
-                                        .filter_map(|v| match v {
-                                            Value::String(s) => {
-                                                Some(s.to_str().unwrap().to_string())
-                                            }
-                                            _ => None,
-                                        })
-                                        .collect();
-                                    query_builder = query_builder.bind(strings);
-                                }
-                                Value::Boolean(_) => {
-                                    let bools: Vec<bool> = arr
-                                        .iter()
-                                        .filter_map(|v| match v {
-                                            Value::Boolean(b) => Some(*b),
-                                            _ => None,
-                                        })
-                                        .collect();
-                                    query_builder = query_builder.bind(bools);
-                                }
-                                _ => {
-                                    return Err(sqlx::Error::Protocol(
-                                        "Unsupported array element type".into(),
-                                    ));
-                                }
+                }
+                Value::Boolean(b) => {
+                    query_builder = query_builder.bind(b);
+                }
+                Value::Nil => {
+                    query_builder = query_builder.bind(Option::<String>::None);
+                }
+                Value::List(arr) => {
+                    let arr = &arr.borrow().data;
+                    if let Some(first) = arr.first() {
+                        match first {
+                            Value::Int(_) => {
+                                let nums: Vec<i64> = arr
+                                    .iter()
+                                    .filter_map(|v| match v {
+                                        Value::Int(n) => Some(*n),
+                                        _ => None,
+                                    })
+                                    .collect();
+                                query_builder = query_builder.bind(nums);
+                            }
+                            Value::Number(_) => {
+                                let nums: Vec<f64> = arr
+                                    .iter()
+                                    .filter_map(|v| match v {
+                                        Value::Number(n) => Some(*n),
+                                        _ => None,
+                                    })
+                                    .collect();
+                                query_builder = query_builder.bind(nums);
+                            }
+                            Value::String(_) => {
+                                let strings: Vec<String> = arr
+                                    .iter()
+                                    .filter_map(|v| match v {
+                                        Value::String(s) => Some(s.to_str().unwrap().to_string()),
+                                        _ => None,
+                                    })
+                                    .collect();
+                                query_builder = query_builder.bind(strings);
+                            }
+                            Value::Boolean(_) => {
+                                let bools: Vec<bool> = arr
+                                    .iter()
+                                    .filter_map(|v| match v {
+                                        Value::Boolean(b) => Some(*b),
+                                        _ => None,
+                                    })
+                                    .collect();
+                                query_builder = query_builder.bind(bools);
+                            }
+                            _ => {
+                                return Err(sqlx::Error::Protocol(
+                                    "Unsupported array element type".into(),
+                                ));
                             }
-                        } else {
-                            query_builder = query_builder.bind::<Vec<String>>(vec![]);
                         }
+                    } else {
+                        query_builder = query_builder.bind::<Vec<String>>(vec![]);
                     }
-                    _ => return Err(sqlx::Error::Protocol("Unsupported parameter type".into())),
                 }
+                _ => return Err(sqlx::Error::Protocol("Unsupported parameter type".into())),
             }
+        }
 
-            query_builder.fetch_all(executor).await
-        })
-        .map_err(|e| VmError::RuntimeError(format!("Database query error: {}", e)))
+        query_builder.fetch_all(executor).await
+    }))?;
+    permit.report(!result.as_ref().is_err_and(is_sqlx_unavailable));
+    result.map_err(|e| VmError::RuntimeError(format!("Database query error: {}", e)))
 }
 
 fn execute_typed_query<'gc, 'a, E>(
@@ -296,12 +299,13 @@ fn execute_typed_query<'gc, 'a, E>(
     class: GcRefLock<'gc, Class<'gc>>,
     query: &str,
     bindings: Vec<Value<'gc>>,
+    deadline: Option<Instant>,
 ) -> Result<Value<'gc>, VmError>
 where
     E: sqlx::Executor<'a, Database = sqlx::Postgres>,
 {
     // Execute the query
-    let rows = execute_query(executor, query, bindings)?;
+    let rows = execute_query(executor, query, bindings, deadline)?;
 
     // TODO: Validate first row's columns against class fields?
     // if let Some(first_row) = rows.first() {
@@ -327,6 +331,10 @@ where
     Ok(Value::array(&ctx, results))
 }
 
+// Set the `application_name` of the session, or only of the transaction if
+// the second parameter is true.
+const SET_APPLICATION_NAME: &str = "SELECT set_config('application_name', $1, $2)";
+
 // Native function implementations
 fn pg_query<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
     if args.is_empty() {
@@ -338,12 +346,17 @@ fn pg_query<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<
     let sql = args[0].as_string()?;
     let ctx = state.get_context();
     let conn = state.pg_connection.as_ref().unwrap();
+    let bindings = args.into_iter().skip(1).collect();
     // Execute query in runtime
-    let rows = execute_query(
-        conn,
-        sql.to_str().unwrap(),
-        args.into_iter().skip(1).collect(),
-    )?;
+    let rows = match state.request_id.as_deref() {
+        Some(request_id) => execute_query(
+            &mut *tagged_connection(conn, request_id, state.deadline)?,
+            sql.to_str().unwrap(),
+            bindings,
+            state.deadline,
+        ),
+        None => execute_query(conn, sql.to_str().unwrap(), bindings, state.deadline),
+    }?;
 
     // Convert rows to array of objects
     let mut results = Vec::new();
@@ -374,14 +387,47 @@ fn pg_query_as<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Val
     let sql = args[1].as_string()?;
     let ctx = state.get_context();
     let conn = state.pg_connection.as_ref().unwrap();
+    let bindings = args.into_iter().skip(2).collect();
+
+    match state.request_id.as_deref() {
+        Some(request_id) => execute_typed_query(
+            ctx,
+            &mut *tagged_connection(conn, request_id, state.deadline)?,
+            class,
+            sql.to_str().unwrap(),
+            bindings,
+            state.deadline,
+        ),
+        None => execute_typed_query(
+            ctx,
+            conn,
+            class,
+            sql.to_str().unwrap(),
+            bindings,
+            state.deadline,
+        ),
+    }
+}
 
-    execute_typed_query(
-        ctx,
-        conn,
-        class,
-        sql.to_str().unwrap(),
-        args.into_iter().skip(2).collect(),
-    )
+// Acquire a connection of the pool whose session is named after the request,
+// see `application_name()`.
+fn tagged_connection(
+    pool: &sqlx::PgPool,
+    request_id: &str,
+    deadline: Option<Instant>,
+) -> Result<PoolConnection<Postgres>, VmError> {
+    let permit = breaker::acquire("db:postgres")?;
+    let result = Handle::current().block_on(with_deadline(deadline, async {
+        let mut conn = pool.acquire().await?;
+        sqlx::query(SET_APPLICATION_NAME)
+            .bind(application_name(request_id))
+            .bind(false)
+            .execute(&mut *conn)
+            .await?;
+        Ok::<_, sqlx::Error>(conn)
+    }))?;
+    permit.report(!result.as_ref().is_err_and(is_sqlx_unavailable));
+    result.map_err(|e| VmError::RuntimeError(format!("Database query error: {}", e)))
 }
 
 mod transaction {
@@ -409,8 +455,13 @@ mod transaction {
             &ctx,
             RefLock::new(Class {
                 name: ctx.intern(b"Transaction"),
+                doc: None,
                 methods,
                 static_methods: HashMap::default(),
+                getters: HashMap::default(),
+                setters: HashMap::default(),
+                json_formats: HashMap::default(),
+                attributes: Default::default(),
             }),
         )
     }
@@ -427,8 +478,20 @@ mod transaction {
 
         let ctx = state.get_context();
         let conn = state.pg_connection.as_ref().unwrap();
+        let request_id = state.request_id.as_deref().map(application_name);
         let tx = Handle::current()
-            .block_on(async move { conn.begin().await })
+            .block_on(async move {
+                let mut tx = conn.begin().await?;
+                // The name of the request is reset when the transaction ends
+                if let Some(name) = request_id {
+                    sqlx::query(SET_APPLICATION_NAME)
+                        .bind(name)
+                        .bind(true)
+                        .execute(&mut *tx)
+                        .await?;
+                }
+                Ok::<_, sqlx::Error>(tx)
+            })
             .map_err(|e| VmError::RuntimeError(format!("Failed to begin transaction: {}", e)))?;
 
         // Store transaction in thread local
@@ -450,6 +513,7 @@ mod transaction {
 
         let query = args[0].as_string()?;
         let ctx = state.get_context();
+        let deadline = state.deadline;
 
         // Execute query with the active transaction
         let result = ACTIVE_TRANSACTION.with(|cell| {
@@ -458,6 +522,7 @@ mod transaction {
                     &mut **tx,
                     query.to_str().unwrap(),
                     args.into_iter().skip(1).collect(),
+                    deadline,
                 );
                 Some(rows)
             } else {
@@ -498,6 +563,7 @@ mod transaction {
 
         let query = args[1].as_string()?;
         let ctx = state.get_context();
+        let deadline = state.deadline;
 
         // Execute query using the active transaction
         let result = ACTIVE_TRANSACTION.with(|cell| {
@@ -509,6 +575,7 @@ mod transaction {
                     class,
                     query.to_str().unwrap(),
                     bindings,
+                    deadline,
                 ))
             } else {
                 None
@@ -4,11 +4,12 @@ use aiscript_arena::{Gc, RefLock};
 use redis::{RedisResult, Value as RedisValue};
 use tokio::runtime::Handle;
 
+use super::is_redis_unavailable;
 use crate::{
     NativeFn, Value, VmError,
     module::ModuleKind,
     object::Object,
-    vm::{Context, State},
+    vm::{Context, State, breaker, with_deadline},
 };
 
 thread_local! {
@@ -42,7 +43,7 @@ fn redis_to_value(ctx: Context, value: RedisValue) -> Value {
             if i == 0 || i == 1 {
                 Value::Boolean(i == 1)
             } else {
-                Value::Number(i as f64)
+                Value::Int(i)
             }
         }
         RedisValue::Double(d) => Value::Number(d),
@@ -89,6 +90,7 @@ fn redis_to_value(ctx: Context, value: RedisValue) -> Value {
 // Helper to convert AIScript value to Redis value
 fn value_to_redis(value: &Value) -> RedisValue {
     match value {
+        Value::Int(n) => RedisValue::Int(*n),
         Value::Number(n) => {
             if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n <= i64::MAX as f64 {
                 RedisValue::Int(*n as i64)
@@ -150,16 +152,20 @@ fn redis_cmd<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value
     }
 
     let ctx = state.get_context();
+    let deadline = state.deadline;
     let conn = state.redis_connection.as_mut().unwrap();
 
     // Execute the command
-    let result: RedisResult<RedisValue> = Handle::current().block_on(async {
-        let mut cmd = redis::cmd(&command);
-        for arg in redis_args {
-            cmd.arg(arg);
-        }
-        cmd.query_async(conn).await
-    });
+    let permit = breaker::acquire("db:redis")?;
+    let result: RedisResult<RedisValue> =
+        Handle::current().block_on(with_deadline(deadline, async {
+            let mut cmd = redis::cmd(&command);
+            for arg in redis_args {
+                cmd.arg(arg);
+            }
+            cmd.query_async(conn).await
+        }))?;
+    permit.report(!result.as_ref().is_err_and(is_redis_unavailable));
 
     match result {
         Ok(value) => Ok(redis_to_value(ctx, value)),
@@ -189,8 +195,13 @@ mod pipeline {
             &ctx,
             RefLock::new(Class {
                 name: ctx.intern(b"Pipeline"),
+                doc: None,
                 methods,
                 static_methods: HashMap::default(),
+                getters: HashMap::default(),
+                setters: HashMap::default(),
+                json_formats: HashMap::default(),
+                attributes: Default::default(),
             }),
         )
     }
@@ -250,14 +261,24 @@ mod pipeline {
 
     fn exec<'gc>(state: &mut State<'gc>, _args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
         let ctx = state.get_context();
+        let deadline = state.deadline;
         let conn = state.redis_connection.as_mut().unwrap();
 
-        let result: std::option::Option<RedisResult<Vec<RedisValue>>> =
+        let permit = breaker::acquire("db:redis")?;
+        let result: std::option::Option<Result<RedisResult<Vec<RedisValue>>, VmError>> =
             ACTIVE_PIPELINE.with(|cell| {
                 cell.borrow_mut().take().map(|mut pipeline| {
-                    Handle::current().block_on(async { pipeline.atomic().query_async(conn).await })
+                    Handle::current().block_on(with_deadline(deadline, async {
+                        pipeline.atomic().query_async(conn).await
+                    }))
                 })
             });
+        let result = result.transpose()?;
+        permit.report(
+            !result
+                .as_ref()
+                .is_some_and(|result| result.as_ref().is_err_and(is_redis_unavailable)),
+        );
 
         match result {
             Some(Ok(values)) => {
@@ -1,14 +1,15 @@
-use std::{cell::RefCell, collections::HashMap};
+use std::{cell::RefCell, collections::HashMap, time::Instant};
 
 use aiscript_arena::{Gc, GcRefLock, RefLock};
 use sqlx::{Column, Row, Sqlite, TypeInfo, ValueRef};
 use tokio::runtime::Handle;
 
+use super::is_sqlx_unavailable;
 use crate::{
     NativeFn, Value, VmError,
     module::ModuleKind,
     object::{Class, Instance, Object},
-    vm::{Context, State},
+    vm::{Context, State, breaker, with_deadline},
 };
 
 thread_local! {
@@ -46,7 +47,7 @@ fn column_to_value<'gc>(
 
     let value = match type_info.name() {
         // Integer types
-        "INTEGER" => row.try_get::<i64, _>(i).map(|v| Value::Number(v as f64)),
+        "INTEGER" => row.try_get::<i64, _>(i).map(Value::Int),
 
         // Floating-point types
         "REAL" => row.try_get::<f64, _>(i).map(Value::Number),
@@ -104,45 +105,50 @@ fn execute_query<'a, E>(
     executor: E,
     query: &str,
     bindings: Vec<Value<'_>>,
+    deadline: Option<Instant>,
 ) -> Result<Vec<sqlx::sqlite::SqliteRow>, VmError>
 where
     E: sqlx::Executor<'a, Database = sqlx::Sqlite>,
 {
-    Handle::current()
-        .block_on(async {
-            let mut query_builder = sqlx::query(query);
-
-            // Bind parameters
-            for value in bindings {
-                match value {
-                    Value::Number(n) => {
-                        query_builder = query_builder.bind(n);
-                    }
-                    Value::String(s) => {
-                        query_builder = query_builder.bind(s.to_str().unwrap());
-                    }
-                    Value::Boolean(b) => {
-                        query_builder = query_builder.bind(b);
-                    }
-                    Value::Nil => {
-                        query_builder = query_builder.bind(Option::<String>::None);
-                    }
-                    Value::List(list) => {
-                        let vec = &list.borrow().data;
-                        // SQLite doesn't have native array types, so convert to string representation
-                        let json = serde_json::to_string(
-                            &vec.iter().map(Value::to_serde_value).collect::<Vec<_>>(),
-                        )
-                        .map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
-                        query_builder = query_builder.bind(json);
-                    }
-                    _ => return Err(sqlx::Error::Protocol("Unsupported parameter type".into())),
+    let permit = breaker::acquire("db:sqlite")?;
+    let result = Handle::current().block_on(with_deadline(deadline, async {
+        let mut query_builder = sqlx::query(query);
+
+        // Bind parameters
+        for value in bindings {
+            match value {
+                Value::Number(n) => {
+                    query_builder = query_builder.bind(n);
                 }
+                Value::Int(n) => {
+                    query_builder = query_builder.bind(n);
+                }
+                Value::String(s) => {
+                    query_builder = query_builder.bind(s.to_str().unwrap());
+                }
+                Value::Boolean(b) => {
+                    query_builder = query_builder.bind(b);
+                }
+                Value::Nil => {
+                    query_builder = query_builder.bind(Option::<String>::None);
+                }
+                Value::List(list) => {
+                    let vec = &list.borrow().data;
+                    // SQLite doesn't have native array types, so convert to string representation
+                    let json = serde_json::to_string(
+                        &vec.iter().map(Value::to_serde_value).collect::<Vec<_>>(),
+                    )
+                    .map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+                    query_builder = query_builder.bind(json);
+                }
+                _ => return Err(sqlx::Error::Protocol("Unsupported parameter type".into())),
             }
+        }
 
-            query_builder.fetch_all(executor).await
-        })
-        .map_err(|e| VmError::RuntimeError(format!("Database query error: {}", e)))
+        query_builder.fetch_all(executor).await
+    }))?;
+    permit.report(!result.as_ref().is_err_and(is_sqlx_unavailable));
+    result.map_err(|e| VmError::RuntimeError(format!("Database query error: {}", e)))
 }
 
 fn execute_typed_query<'gc, 'a, E>(
@@ -151,12 +157,13 @@ fn execute_typed_query<'gc, 'a, E>(
     class: GcRefLock<'gc, Class<'gc>>,
     query: &str,
     bindings: Vec<Value<'gc>>,
+    deadline: Option<Instant>,
 ) -> Result<Value<'gc>, VmError>
 where
     E: sqlx::Executor<'a, Database = sqlx::Sqlite>,
 {
     // Execute the query
-    let rows = execute_query(executor, query, bindings)?;
+    let rows = execute_query(executor, query, bindings, deadline)?;
 
     // Convert rows to class instances
     let mut results = Vec::new();
@@ -192,6 +199,7 @@ fn sqlite_query<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Va
         conn,
         sql.to_str().unwrap(),
         args.into_iter().skip(1).collect(),
+        state.deadline,
     )?;
 
     let mut results = Vec::new();
@@ -231,6 +239,7 @@ fn sqlite_query_as<'gc>(
         class,
         sql.to_str().unwrap(),
         args.into_iter().skip(2).collect(),
+        state.deadline,
     )
 }
 
@@ -259,8 +268,13 @@ mod transaction {
             &ctx,
             RefLock::new(Class {
                 name: ctx.intern(b"Transaction"),
+                doc: None,
                 methods,
                 static_methods: HashMap::default(),
+                getters: HashMap::default(),
+                setters: HashMap::default(),
+                json_formats: HashMap::default(),
+                attributes: Default::default(),
             }),
         )
     }
@@ -297,6 +311,7 @@ mod transaction {
 
         let query = args[0].as_string()?;
         let ctx = state.get_context();
+        let deadline = state.deadline;
 
         let result = ACTIVE_TRANSACTION.with(|cell| {
             if let Some(tx) = (*cell.borrow_mut()).as_mut() {
@@ -304,6 +319,7 @@ mod transaction {
                     &mut **tx,
                     query.to_str().unwrap(),
                     args.into_iter().skip(1).collect(),
+                    deadline,
                 );
                 Some(rows)
             } else {
@@ -342,6 +358,7 @@ mod transaction {
 
         let query = args[1].as_string()?;
         let ctx = state.get_context();
+        let deadline = state.deadline;
 
         let result = ACTIVE_TRANSACTION.with(|cell| {
             if let Some(tx) = (*cell.borrow_mut()).as_mut() {
@@ -352,6 +369,7 @@ mod transaction {
                     class,
                     query.to_str().unwrap(),
                     bindings,
+                    deadline,
                 ))
             } else {
                 None
@@ -0,0 +1,110 @@
+use aiscript_arena::Gc;
+
+use crate::{
+    NativeFn, Value, VmError,
+    builtins::decimal::{places_arg, rounding_mode_arg},
+    decimal::Decimal,
+    module::ModuleKind,
+    vm::{Context, State},
+};
+
+pub fn create_decimal_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.decimal");
+
+    let exports = [
+        // Rounding modes
+        ("HALF_EVEN", Value::String(ctx.intern_static("half_even"))),
+        ("HALF_UP", Value::String(ctx.intern_static("half_up"))),
+        ("HALF_DOWN", Value::String(ctx.intern_static("half_down"))),
+        ("UP", Value::String(ctx.intern_static("up"))),
+        ("DOWN", Value::String(ctx.intern_static("down"))),
+        ("CEILING", Value::String(ctx.intern_static("ceiling"))),
+        ("FLOOR", Value::String(ctx.intern_static("floor"))),
+        // Functions
+        ("Decimal", Value::NativeFunction(NativeFn(decimal_new))),
+        (
+            "is_decimal",
+            Value::NativeFunction(NativeFn(decimal_is_decimal)),
+        ),
+        ("div", Value::NativeFunction(NativeFn(decimal_div))),
+        ("sum", Value::NativeFunction(NativeFn(decimal_sum))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// The decimal of a string, an integer, a float or a decimal.
+fn to_decimal(value: &Value, fn_name: &str) -> Result<Decimal, VmError> {
+    let result = match value {
+        Value::Decimal(d) => Ok((**d).clone()),
+        Value::Int(n) => Ok(Decimal::from(*n)),
+        Value::Number(n) => Decimal::from_f64(*n),
+        Value::String(_) | Value::IoString(_) => Decimal::parse(value.as_string_value()?.as_str()),
+        _ => Err(format!("cannot convert {value} to decimal")),
+    };
+    result.map_err(|e| VmError::RuntimeError(format!("{fn_name}: {e}")))
+}
+
+/// Create a decimal from a string like "19.99", an integer or a float.
+/// A float is converted from its shortest representation, prefer strings
+/// for amounts.
+fn decimal_new<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let value = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("Decimal: expected 1 argument, got 0".into()))?;
+    let d = to_decimal(value, "Decimal")?;
+    Ok(Value::Decimal(Gc::new(state, d)))
+}
+
+fn decimal_is_decimal<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Boolean(matches!(
+        args.first(),
+        Some(Value::Decimal(_))
+    )))
+}
+
+/// Divide with an explicit number of fractional digits and rounding mode,
+/// e.g. `div(total, 3, 2, decimal.DOWN)` to split a bill.
+fn decimal_div<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() < 3 {
+        return Err(VmError::RuntimeError(format!(
+            "div: expected at least 3 arguments, got {}",
+            args.len()
+        )));
+    }
+    let a = to_decimal(&args[0], "div")?;
+    let b = to_decimal(&args[1], "div")?;
+    let places = places_arg(&args, 2, "div")?;
+    let mode = rounding_mode_arg(&args, 3, "div")?;
+    let quotient = a.div(&b, places, mode).ok_or_else(|| {
+        VmError::RuntimeError(match b.is_zero() {
+            true => "div: division by zero".into(),
+            false => "div: the scale of the quotient is too large".into(),
+        })
+    })?;
+    Ok(Value::Decimal(Gc::new(state, quotient)))
+}
+
+/// The exact sum of an array of decimals and integers.
+fn decimal_sum<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let list = args
+        .first()
+        .ok_or_else(|| VmError::RuntimeError("sum: expected 1 argument, got 0".into()))?
+        .as_array()?;
+    let mut total = Decimal::from(0);
+    for value in &list.borrow().data {
+        let value = value
+            .as_decimal_operand()
+            .map_err(|e| VmError::RuntimeError(format!("sum: {e}")))?;
+        total = total
+            .checked_add(&value)
+            .expect("decimal addition can't fail");
+    }
+    Ok(Value::Decimal(Gc::new(state, total)))
+}
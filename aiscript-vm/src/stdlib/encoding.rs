@@ -0,0 +1,185 @@
+use data_encoding::{BASE32, BASE64, BASE64URL_NOPAD, Encoding, HEXLOWER, HEXLOWER_PERMISSIVE};
+
+use aiscript_arena::Gc;
+
+use crate::{
+    NativeFn, Value, VmError, bytes_arg,
+    module::ModuleKind,
+    vm::{Context, State},
+};
+
+use super::serde::extract_keyword_args;
+
+pub fn create_encoding_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.encoding");
+
+    let exports = [
+        (
+            "base64_encode",
+            Value::NativeFunction(NativeFn(base64_encode)),
+        ),
+        (
+            "base64_decode",
+            Value::NativeFunction(NativeFn(base64_decode)),
+        ),
+        (
+            "base32_encode",
+            Value::NativeFunction(NativeFn(base32_encode)),
+        ),
+        (
+            "base32_decode",
+            Value::NativeFunction(NativeFn(base32_decode)),
+        ),
+        ("hex_encode", Value::NativeFunction(NativeFn(hex_encode))),
+        ("hex_decode", Value::NativeFunction(NativeFn(hex_decode))),
+        ("utf8_encode", Value::NativeFunction(NativeFn(utf8_encode))),
+        ("utf8_decode", Value::NativeFunction(NativeFn(utf8_decode))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// The data to encode is a string or bytes, the decoded data is bytes, which
+// aren't necessarily valid UTF-8, `utf8_decode()` turns them back into text.
+
+/// The base64 of the string or bytes, the URL-safe alphabet without
+/// padding if `url_safe`, e.g. for JWT segments.
+///
+/// fn base64_encode(data, url_safe = false) {}
+fn base64_encode<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let encoding = base64_encoding(&args, "base64_encode")?;
+    encode(state, &args, &encoding, "base64_encode")
+}
+
+/// The bytes of the base64 text, the URL-safe alphabet if `url_safe`, in
+/// which case the padding is optional.
+///
+/// fn base64_decode(text, url_safe = false) {}
+fn base64_decode<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let encoding = base64_encoding(&args, "base64_decode")?;
+    decode(state, &args, &encoding, "base64_decode")
+}
+
+/// The padded base32 (RFC 4648) of the string or bytes.
+///
+/// fn base32_encode(data) {}
+fn base32_encode<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "base32_encode")?;
+    encode(state, &args, &BASE32, "base32_encode")
+}
+
+/// The bytes of the padded base32 (RFC 4648) text.
+///
+/// fn base32_decode(text) {}
+fn base32_decode<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "base32_decode")?;
+    decode(state, &args, &BASE32, "base32_decode")
+}
+
+/// The lowercase hex of the string or bytes.
+///
+/// fn hex_encode(data) {}
+fn hex_encode<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "hex_encode")?;
+    encode(state, &args, &HEXLOWER, "hex_encode")
+}
+
+/// The bytes of the hex text, in lowercase or uppercase.
+///
+/// fn hex_decode(text) {}
+fn hex_decode<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "hex_decode")?;
+    decode(state, &args, &HEXLOWER_PERMISSIVE, "hex_decode")
+}
+
+/// The UTF-8 bytes of the string.
+///
+/// fn utf8_encode(text) {}
+fn utf8_encode<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "utf8_encode")?;
+    let data = bytes_arg!(args, 0, "utf8_encode")?.to_vec();
+    Ok(Value::Bytes(Gc::new(state, data)))
+}
+
+/// The text of the UTF-8 bytes, it fails on invalid UTF-8.
+///
+/// fn utf8_decode(data) {}
+fn utf8_decode<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    check_arity(&args, "utf8_decode")?;
+    let data = bytes_arg!(args, 0, "utf8_decode")?;
+    let text = std::str::from_utf8(data)
+        .map_err(|e| VmError::RuntimeError(format!("utf8_decode() invalid UTF-8: {e}.")))?;
+    Ok(Value::IoString(Gc::new(state, text.to_owned())))
+}
+
+fn check_arity(args: &[Value], fn_name: &str) -> Result<(), VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError(format!(
+            "{fn_name}() takes 1 argument."
+        )));
+    }
+    Ok(())
+}
+
+fn base64_encoding(args: &[Value], fn_name: &str) -> Result<Encoding, VmError> {
+    let (positional, keyword) = extract_keyword_args(args, &["url_safe"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(format!(
+            "{fn_name}() takes 1 or 2 arguments."
+        )));
+    }
+    match positional.get(1).or(keyword.get("url_safe")) {
+        None | Some(Value::Nil) | Some(Value::Boolean(false)) => Ok(BASE64),
+        Some(Value::Boolean(true)) => Ok(BASE64URL_NOPAD),
+        Some(_) => Err(VmError::RuntimeError(format!(
+            "{fn_name}() url_safe must be a boolean."
+        ))),
+    }
+}
+
+fn encode<'gc>(
+    state: &mut State<'gc>,
+    args: &[Value<'gc>],
+    encoding: &Encoding,
+    fn_name: &str,
+) -> Result<Value<'gc>, VmError> {
+    let data = bytes_arg!(args, 0, fn_name)?;
+    let text = encoding.encode(data);
+    Ok(Value::String(state.intern(text.as_bytes())))
+}
+
+fn decode<'gc>(
+    state: &mut State<'gc>,
+    args: &[Value<'gc>],
+    encoding: &Encoding,
+    fn_name: &str,
+) -> Result<Value<'gc>, VmError> {
+    let text = bytes_arg!(args, 0, fn_name)?;
+    // The padding is optional in URL-safe base64.
+    let text = match encoding == &BASE64URL_NOPAD {
+        true => text
+            .strip_suffix(b"==")
+            .or(text.strip_suffix(b"="))
+            .unwrap_or(text),
+        false => text,
+    };
+    let data = encoding
+        .decode(text)
+        .map_err(|e| VmError::RuntimeError(format!("{fn_name}() invalid input: {e}.")))?;
+    Ok(Value::Bytes(Gc::new(state, data)))
+}
@@ -0,0 +1,606 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+};
+
+use serde_json::{Map, Value as Json, json};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State},
+};
+
+use super::serde::{extract_keyword_args, to_json_value};
+
+pub fn create_graph_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.graph");
+
+    let exports = [
+        (
+            "from_edges",
+            Value::NativeFunction(NativeFn(graph_from_edges)),
+        ),
+        ("nodes", Value::NativeFunction(NativeFn(graph_nodes))),
+        ("reverse", Value::NativeFunction(NativeFn(graph_reverse))),
+        ("bfs", Value::NativeFunction(NativeFn(graph_bfs))),
+        ("dfs", Value::NativeFunction(NativeFn(graph_dfs))),
+        (
+            "shortest_path",
+            Value::NativeFunction(NativeFn(graph_shortest_path)),
+        ),
+        (
+            "topological_sort",
+            Value::NativeFunction(NativeFn(graph_topological_sort)),
+        ),
+        (
+            "has_cycle",
+            Value::NativeFunction(NativeFn(graph_has_cycle)),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// A graph is a plain object of its adjacency lists, the neighbors of each
+// node are a list, e.g. `{a: ["b", "c"], b: ["c"]}`, or an object of the
+// weights of the edges, e.g. `{a: {b: 2, c: 5}}`. The nodes are strings,
+// a node only reached by an edge needs no key. The edges are directed,
+// `from_edges(edges, directed=false)` adds them both ways.
+#[derive(Debug, Default)]
+struct Graph {
+    // The nodes in the order of the keys, then of their first edge.
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    edges: Vec<Vec<(usize, f64)>>,
+    weighted: bool,
+}
+
+impl Graph {
+    fn node(&mut self, name: &str) -> usize {
+        if let Some(&node) = self.index.get(name) {
+            return node;
+        }
+        self.names.push(name.to_owned());
+        self.edges.push(Vec::new());
+        self.index.insert(name.to_owned(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn add_edge(&mut self, from: &str, to: &str, weight: f64) {
+        let (from, to) = (self.node(from), self.node(to));
+        match self.edges[from].iter_mut().find(|(node, _)| *node == to) {
+            Some(edge) => edge.1 = weight,
+            None => self.edges[from].push((to, weight)),
+        }
+    }
+
+    fn from_json(adjacency: &Json) -> Result<Self, String> {
+        let Json::Object(adjacency) = adjacency else {
+            return Err("graph must be an object of the neighbors of the nodes".into());
+        };
+        let mut graph = Graph::default();
+        for name in adjacency.keys() {
+            graph.node(name);
+        }
+        for (from, neighbors) in adjacency {
+            match neighbors {
+                Json::Null => {}
+                Json::Array(neighbors) => {
+                    for to in neighbors {
+                        let Json::String(to) = to else {
+                            return Err(format!("graph node '{from}' has a neighbor not a string"));
+                        };
+                        graph.add_edge(from, to, 1.0);
+                    }
+                }
+                Json::Object(weights) => {
+                    graph.weighted = true;
+                    for (to, weight) in weights {
+                        let weight = weight.as_f64().filter(|w| *w >= 0.0).ok_or_else(|| {
+                            format!(
+                                "graph edge '{from}' -> '{to}' weight must be a non-negative number"
+                            )
+                        })?;
+                        graph.add_edge(from, to, weight);
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "graph node '{from}' neighbors must be a list or an object of weights"
+                    ));
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    fn to_json(&self) -> Json {
+        let adjacency = self
+            .edges
+            .iter()
+            .enumerate()
+            .map(|(from, edges)| {
+                let neighbors = if self.weighted {
+                    Json::Object(
+                        edges
+                            .iter()
+                            .map(|(to, weight)| (self.names[*to].clone(), number(*weight)))
+                            .collect(),
+                    )
+                } else {
+                    edges.iter().map(|(to, _)| json!(self.names[*to])).collect()
+                };
+                (self.names[from].clone(), neighbors)
+            })
+            .collect::<Map<_, _>>();
+        Json::Object(adjacency)
+    }
+
+    fn reverse(&self) -> Self {
+        let mut reversed = Graph {
+            weighted: self.weighted,
+            ..Default::default()
+        };
+        for name in &self.names {
+            reversed.node(name);
+        }
+        for (from, edges) in self.edges.iter().enumerate() {
+            for (to, weight) in edges {
+                reversed.add_edge(&self.names[*to], &self.names[from], *weight);
+            }
+        }
+        reversed
+    }
+
+    // The nodes reachable from the start in the order of their visit, the
+    // neighbors in their order.
+    fn traverse(&self, start: usize, depth_first: bool) -> Vec<usize> {
+        let mut visited = vec![false; self.names.len()];
+        let mut order = Vec::new();
+        if depth_first {
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                if visited[node] {
+                    continue;
+                }
+                visited[node] = true;
+                order.push(node);
+                stack.extend(self.edges[node].iter().rev().map(|(to, _)| *to));
+            }
+        } else {
+            visited[start] = true;
+            order.push(start);
+            let mut next = 0;
+            while let Some(&node) = order.get(next) {
+                next += 1;
+                for (to, _) in &self.edges[node] {
+                    if !visited[*to] {
+                        visited[*to] = true;
+                        order.push(*to);
+                    }
+                }
+            }
+        }
+        order
+    }
+
+    // The path of the lowest total weight and its cost, by Dijkstra, each
+    // edge weighs 1 in a graph of lists.
+    fn shortest_path(&self, from: usize, to: usize) -> Option<(Vec<usize>, f64)> {
+        let mut cost = vec![f64::INFINITY; self.names.len()];
+        let mut previous = vec![None; self.names.len()];
+        let mut queue = BinaryHeap::new();
+        cost[from] = 0.0;
+        queue.push(Reverse(Cost(0.0, from)));
+        while let Some(Reverse(Cost(total, node))) = queue.pop() {
+            if node == to {
+                break;
+            }
+            if total > cost[node] {
+                continue;
+            }
+            for (next, weight) in &self.edges[node] {
+                let total = total + weight;
+                if total < cost[*next] {
+                    cost[*next] = total;
+                    previous[*next] = Some(node);
+                    queue.push(Reverse(Cost(total, *next)));
+                }
+            }
+        }
+        if cost[to].is_infinite() {
+            return None;
+        }
+        let mut path = vec![to];
+        while let Some(node) = previous[*path.last().unwrap()] {
+            path.push(node);
+        }
+        path.reverse();
+        Some((path, cost[to]))
+    }
+
+    // The nodes of a cycle, the first one repeated at the end, None if the
+    // graph is acyclic.
+    fn find_cycle(&self) -> Option<Vec<usize>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Mark {
+            New,
+            Visiting,
+            Done,
+        }
+        let mut marks = vec![Mark::New; self.names.len()];
+        for root in 0..self.names.len() {
+            if marks[root] != Mark::New {
+                continue;
+            }
+            // The path from the root, with the next edge of each node
+            let mut path = vec![(root, 0)];
+            marks[root] = Mark::Visiting;
+            while let Some((node, edge)) = path.last_mut() {
+                let node = *node;
+                let Some(&(next, _)) = self.edges[node].get(*edge) else {
+                    marks[node] = Mark::Done;
+                    path.pop();
+                    continue;
+                };
+                *edge += 1;
+                match marks[next] {
+                    Mark::New => {
+                        marks[next] = Mark::Visiting;
+                        path.push((next, 0));
+                    }
+                    Mark::Visiting => {
+                        let start = path.iter().position(|(node, _)| *node == next).unwrap();
+                        let mut cycle: Vec<_> =
+                            path[start..].iter().map(|(node, _)| *node).collect();
+                        cycle.push(next);
+                        return Some(cycle);
+                    }
+                    Mark::Done => {}
+                }
+            }
+        }
+        None
+    }
+
+    // The nodes ordered so every edge goes forward, by Kahn's algorithm
+    // taking the first ready node in the order of the graph. A cycle if
+    // there's none.
+    fn topological_order(&self) -> Result<Vec<usize>, Vec<usize>> {
+        let mut incoming = vec![0; self.names.len()];
+        for edges in &self.edges {
+            for (to, _) in edges {
+                incoming[*to] += 1;
+            }
+        }
+        let mut ready: BinaryHeap<_> = (0..self.names.len())
+            .filter(|node| incoming[*node] == 0)
+            .map(Reverse)
+            .collect();
+        let mut order = Vec::with_capacity(self.names.len());
+        while let Some(Reverse(node)) = ready.pop() {
+            order.push(node);
+            for (to, _) in &self.edges[node] {
+                incoming[*to] -= 1;
+                if incoming[*to] == 0 {
+                    ready.push(Reverse(*to));
+                }
+            }
+        }
+        if order.len() < self.names.len() {
+            return Err(self.find_cycle().expect("the remaining nodes have a cycle"));
+        }
+        Ok(order)
+    }
+
+    fn names(&self, nodes: impl IntoIterator<Item = usize>) -> Json {
+        nodes
+            .into_iter()
+            .map(|node| json!(self.names[node]))
+            .collect()
+    }
+
+    fn cycle_text(&self, cycle: &[usize]) -> String {
+        let names: Vec<_> = cycle
+            .iter()
+            .map(|node| self.names[*node].as_str())
+            .collect();
+        names.join(" -> ")
+    }
+}
+
+// A cost of the queue of Dijkstra, the ties go to the first node.
+#[derive(PartialEq)]
+struct Cost(f64, usize);
+
+impl Eq for Cost {}
+
+impl PartialOrd for Cost {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Cost {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+// The JSON number of the weight, an integer if it's whole.
+fn number(n: f64) -> Json {
+    if n.fract() == 0.0 && n.abs() < 2f64.powi(53) {
+        json!(n as i64)
+    } else {
+        json!(n)
+    }
+}
+
+fn graph_arg<'gc>(
+    state: &mut State<'gc>,
+    graph: &Value<'gc>,
+    fn_name: &str,
+) -> Result<Graph, VmError> {
+    let graph = to_json_value(state, graph)?;
+    Graph::from_json(&graph).map_err(|e| VmError::RuntimeError(format!("{fn_name}() {e}.")))
+}
+
+fn node_arg(graph: &Graph, args: &[Value], index: usize, fn_name: &str) -> Result<usize, VmError> {
+    let name = string_arg!(args, index, fn_name)?;
+    let name = name.to_str().unwrap();
+    graph.index.get(name).copied().ok_or_else(|| {
+        VmError::RuntimeError(format!("{fn_name}() node '{name}' isn't in the graph."))
+    })
+}
+
+/// The graph of the edges, lists of the two nodes and the weight if any,
+/// e.g. `[["a", "b"], ["b", "c", 2]]`. The neighbors are objects of the
+/// weights if an edge has one, the others weigh 1. The edges go both ways
+/// unless `directed`.
+///
+/// fn from_edges(edges, directed = true) {}
+fn graph_from_edges<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["directed"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "from_edges() takes 1 or 2 arguments: the edges and directed.".into(),
+        ));
+    }
+    let directed = match positional.get(1).or(keyword.get("directed")) {
+        None | Some(Value::Nil) => true,
+        Some(Value::Boolean(directed)) => *directed,
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "from_edges() directed must be a boolean.".into(),
+            ));
+        }
+    };
+    let Json::Array(edges) = to_json_value(state, &positional[0])? else {
+        return Err(VmError::RuntimeError(
+            "from_edges() edges must be a list.".into(),
+        ));
+    };
+    let mut graph = Graph::default();
+    for edge in &edges {
+        let (from, to, weight) = match edge.as_array().map(Vec::as_slice) {
+            Some([Json::String(from), Json::String(to)]) => (from, to, 1.0),
+            Some([Json::String(from), Json::String(to), weight]) => {
+                graph.weighted = true;
+                let weight = weight.as_f64().filter(|w| *w >= 0.0).ok_or_else(|| {
+                    VmError::RuntimeError(format!(
+                        "from_edges() edge '{from}' -> '{to}' weight must be a non-negative number."
+                    ))
+                })?;
+                (from, to, weight)
+            }
+            _ => {
+                return Err(VmError::RuntimeError(format!(
+                    "from_edges() edge {edge} must be a list of two nodes and an optional weight."
+                )));
+            }
+        };
+        graph.add_edge(from, to, weight);
+        if !directed {
+            graph.add_edge(to, from, weight);
+        }
+    }
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &graph.to_json(),
+    ))
+}
+
+/// The nodes of the graph, the keys and the nodes only reached by an edge.
+///
+/// fn nodes(graph) {}
+fn graph_nodes<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("nodes() takes 1 argument.".into()));
+    }
+    let graph = graph_arg(state, &args[0], "nodes")?;
+    let nodes = graph.names(0..graph.names.len());
+    Ok(Value::from_serde_value(state.get_context(), &nodes))
+}
+
+/// The graph with the direction of every edge reversed, e.g. the
+/// dependents of each node of a graph of dependencies.
+///
+/// fn reverse(graph) {}
+fn graph_reverse<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("reverse() takes 1 argument.".into()));
+    }
+    let graph = graph_arg(state, &args[0], "reverse")?;
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &graph.reverse().to_json(),
+    ))
+}
+
+fn traverse<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+    depth_first: bool,
+    fn_name: &str,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(format!(
+            "{fn_name}() takes 2 arguments: the graph and the start node."
+        )));
+    }
+    let graph = graph_arg(state, &args[0], fn_name)?;
+    let start = node_arg(&graph, &args, 1, fn_name)?;
+    let order = graph.names(graph.traverse(start, depth_first));
+    Ok(Value::from_serde_value(state.get_context(), &order))
+}
+
+/// The nodes reachable from the start, breadth first.
+///
+/// fn bfs(graph, start) {}
+fn graph_bfs<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    traverse(state, args, false, "bfs")
+}
+
+/// The nodes reachable from the start, depth first.
+///
+/// fn dfs(graph, start) {}
+fn graph_dfs<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    traverse(state, args, true, "dfs")
+}
+
+/// The path of the lowest cost between the nodes as `{path, cost}`, the
+/// cost is the sum of the weights, or the number of edges in a graph of
+/// lists. Nil if the end can't be reached.
+///
+/// fn shortest_path(graph, from, to) {}
+fn graph_shortest_path<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 3 {
+        return Err(VmError::RuntimeError(
+            "shortest_path() takes 3 arguments: the graph, the start and the end.".into(),
+        ));
+    }
+    let graph = graph_arg(state, &args[0], "shortest_path")?;
+    let from = node_arg(&graph, &args, 1, "shortest_path")?;
+    let to = node_arg(&graph, &args, 2, "shortest_path")?;
+    let Some((path, cost)) = graph.shortest_path(from, to) else {
+        return Ok(Value::Nil);
+    };
+    let result = json!({"path": graph.names(path), "cost": number(cost)});
+    Ok(Value::from_serde_value(state.get_context(), &result))
+}
+
+/// The nodes ordered so that each one comes before the nodes of its
+/// edges, e.g. the steps of a workflow before the steps that need them.
+/// An error names a cycle of the graph if it has one.
+///
+/// fn topological_sort(graph) {}
+fn graph_topological_sort<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError(
+            "topological_sort() takes 1 argument.".into(),
+        ));
+    }
+    let graph = graph_arg(state, &args[0], "topological_sort")?;
+    let order = graph.topological_order().map_err(|cycle| {
+        VmError::RuntimeError(format!(
+            "topological_sort() the graph has a cycle: {}.",
+            graph.cycle_text(&cycle)
+        ))
+    })?;
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &graph.names(order),
+    ))
+}
+
+/// Whether the graph has a cycle.
+///
+/// fn has_cycle(graph) {}
+fn graph_has_cycle<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError(
+            "has_cycle() takes 1 argument.".into(),
+        ));
+    }
+    let graph = graph_arg(state, &args[0], "has_cycle")?;
+    Ok(Value::Boolean(graph.find_cycle().is_some()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(adjacency: Json) -> Graph {
+        Graph::from_json(&adjacency).unwrap()
+    }
+
+    fn names(graph: &Graph, nodes: Vec<usize>) -> Vec<&str> {
+        nodes
+            .into_iter()
+            .map(|node| graph.names[node].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_traverse() {
+        let g = graph(json!({"a": ["b", "c"], "b": ["d"], "c": ["d"], "d": ["a"]}));
+        assert_eq!(names(&g, g.traverse(0, false)), ["a", "b", "c", "d"]);
+        assert_eq!(names(&g, g.traverse(0, true)), ["a", "b", "d", "c"]);
+        assert_eq!(names(&g, g.traverse(3, false)), ["d", "a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_shortest_path() {
+        let g = graph(json!({"a": {"b": 1, "c": 5}, "b": {"c": 1.5}, "d": {}}));
+        let node = |name: &str| g.index[name];
+        let (path, cost) = g.shortest_path(node("a"), node("c")).unwrap();
+        assert_eq!((names(&g, path), cost), (vec!["a", "b", "c"], 2.5));
+        assert_eq!(g.shortest_path(node("a"), node("d")), None);
+        assert_eq!(
+            g.shortest_path(node("a"), node("a")),
+            Some((vec![node("a")], 0.0))
+        );
+
+        let err = Graph::from_json(&json!({"a": {"b": -1}})).unwrap_err();
+        assert_eq!(
+            err,
+            "graph edge 'a' -> 'b' weight must be a non-negative number"
+        );
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let g = graph(json!({"deploy": [], "build": ["test", "deploy"], "test": ["deploy"]}));
+        let order = g.topological_order().unwrap();
+        assert_eq!(names(&g, order), ["build", "test", "deploy"]);
+
+        let g = graph(json!({"a": ["b"], "b": ["c"], "c": ["b"]}));
+        let cycle = g.topological_order().unwrap_err();
+        assert_eq!(g.cycle_text(&cycle), "b -> c -> b");
+        assert!(
+            graph(json!({"a": ["b"], "c": ["b"]}))
+                .find_cycle()
+                .is_none()
+        );
+    }
+}
@@ -10,7 +10,7 @@ use crate::{
     module::ModuleKind,
     object::Object,
     string::InternedString,
-    vm::{Context, VmError},
+    vm::{Context, VmError, breaker, with_deadline},
 };
 
 pub fn create_http_module(ctx: Context) -> ModuleKind {
@@ -110,12 +110,12 @@ async fn response_to_object(
     Ok(Value::Object(Gc::new(&ctx, RefLock::new(resp_obj))))
 }
 
-async fn make_request(
+pub(crate) async fn make_request(
     method: reqwest::Method,
     url: &str,
     headers: HeaderMap,
     body: Option<String>,
-) -> Result<reqwest::Response, reqwest::Error> {
+) -> Result<reqwest::Response, VmError> {
     let client = reqwest::Client::new();
     let mut request = client.request(method, url);
     request = request.headers(headers);
@@ -124,7 +124,19 @@ async fn make_request(
         request = request.body(body);
     }
 
-    request.send().await
+    // The hosts are guarded by their own circuit breaker
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_else(|| url.to_owned());
+    let permit = breaker::acquire(format_args!("http:{host}"))?;
+    let result = request.send().await;
+    permit.report(
+        result
+            .as_ref()
+            .is_ok_and(|response| !response.status().is_server_error()),
+    );
+    result.map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))
 }
 
 fn http_get<'gc>(
@@ -148,13 +160,11 @@ fn http_get<'gc>(
     };
 
     // Execute request and process response in runtime
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::GET, &url, headers_map, None)
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::GET, &url, headers_map, None).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -182,13 +192,11 @@ fn http_post<'gc>(
         HeaderMap::new()
     };
 
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::POST, &url, headers_map, Some(body))
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::POST, &url, headers_map, Some(body)).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -217,13 +225,11 @@ fn http_put<'gc>(
 
     let headers_map = parse_headers(headers);
 
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::PUT, &url, headers_map, Some(body))
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::PUT, &url, headers_map, Some(body)).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -250,13 +256,11 @@ fn http_delete<'gc>(
         HeaderMap::new()
     };
 
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::DELETE, &url, headers_map, None)
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::DELETE, &url, headers_map, None).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -285,13 +289,11 @@ fn http_patch<'gc>(
 
     let headers_map = parse_headers(headers);
 
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::PATCH, &url, headers_map, Some(body))
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::PATCH, &url, headers_map, Some(body)).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -319,13 +321,11 @@ fn http_head<'gc>(
 
     let headers_map = parse_headers(headers);
 
-    let result = Handle::current().block_on(async {
-        let response = make_request(reqwest::Method::HEAD, &url, headers_map, None)
-            .await
-            .map_err(|e| VmError::RuntimeError(format!("HTTP request failed: {}", e)))?;
+    let result = Handle::current().block_on(with_deadline(ctx.deadline, async {
+        let response = make_request(reqwest::Method::HEAD, &url, headers_map, None).await?;
 
         response_to_object(ctx.get_context(), response).await
-    })?;
+    }))??;
 
     Ok(result)
 }
@@ -1,6 +1,6 @@
 use std::{
     fs::{self, File, OpenOptions},
-    io::{self, BufRead, BufReader, Write},
+    io::{self, BufRead, BufReader, Read, Write},
     path::Path,
 };
 
@@ -11,7 +11,7 @@ use crate::{
     module::ModuleKind,
     string_arg,
     value::Value,
-    vm::{Context, State, VmError},
+    vm::{Context, State, VmError, run_blocking},
 };
 
 pub fn create_io_module(ctx: Context) -> ModuleKind {
@@ -29,6 +29,9 @@ pub fn create_io_module(ctx: Context) -> ModuleKind {
         ),
         // Standard IO
         ("input", Value::NativeFunction(NativeFn(io_input))),
+        ("stdin", Value::Module(ctx.intern_static("std.io.stdin"))),
+        ("stdout", Value::Module(ctx.intern_static("std.io.stdout"))),
+        ("stderr", Value::Module(ctx.intern_static("std.io.stderr"))),
         // File/directory operations
         ("exists", Value::NativeFunction(NativeFn(io_exists))),
         ("is_file", Value::NativeFunction(NativeFn(io_is_file))),
@@ -47,10 +50,110 @@ pub fn create_io_module(ctx: Context) -> ModuleKind {
     ModuleKind::Native { name, exports }
 }
 
+/// The standard input, e.g. `io.stdin.read_all()` to read what's piped
+/// into the script.
+pub fn create_stdin_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.io.stdin");
+    let exports = [
+        ("read_all", Value::NativeFunction(NativeFn(stdin_read_all))),
+        (
+            "read_line",
+            Value::NativeFunction(NativeFn(stdin_read_line)),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+    ModuleKind::Native { name, exports }
+}
+
+pub fn create_stdout_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.io.stdout");
+    let exports = [("write", Value::NativeFunction(NativeFn(stdout_write)))]
+        .into_iter()
+        .map(|(name, f)| (ctx.intern_static(name), f))
+        .collect();
+    ModuleKind::Native { name, exports }
+}
+
+pub fn create_stderr_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.io.stderr");
+    let exports = [("write", Value::NativeFunction(NativeFn(stderr_write)))]
+        .into_iter()
+        .map(|(name, f)| (ctx.intern_static(name), f))
+        .collect();
+    ModuleKind::Native { name, exports }
+}
+
+// Read the standard input until its end
+fn stdin_read_all<'gc>(
+    state: &mut State<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let mut input = String::new();
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|e| VmError::RuntimeError(format!("Failed to read stdin: {}", e)))?;
+    Ok(Value::IoString(Gc::new(state, input)))
+}
+
+// Read a line of the standard input without its newline, nil at the end of the input
+fn stdin_read_line<'gc>(
+    state: &mut State<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let mut line = String::new();
+    let read = io::stdin()
+        .read_line(&mut line)
+        .map_err(|e| VmError::RuntimeError(format!("Failed to read stdin: {}", e)))?;
+    if read == 0 {
+        return Ok(Value::Nil);
+    }
+    let line = line.strip_suffix('\n').unwrap_or(&line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    Ok(Value::IoString(Gc::new(state, line.to_owned())))
+}
+
+// Write the string as is, without a newline, and flush it
+fn write_stream<'gc>(
+    mut stream: impl Write,
+    name: &str,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let text = match args.first().map(Value::as_string_value) {
+        Some(Ok(text)) => text,
+        _ => {
+            return Err(VmError::RuntimeError(format!(
+                "{name}.write() expects a string argument."
+            )));
+        }
+    };
+    stream
+        .write_all(text.as_str().as_bytes())
+        .and_then(|_| stream.flush())
+        .map_err(|e| VmError::RuntimeError(format!("Failed to write to {name}: {}", e)))?;
+    Ok(Value::Nil)
+}
+
+fn stdout_write<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    write_stream(io::stdout().lock(), "stdout", args)
+}
+
+fn stderr_write<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    write_stream(io::stderr().lock(), "stderr", args)
+}
+
 // File reading functions
 fn io_read_file<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
     let path = string_arg!(args, 0, "read_file")?.to_string();
-    match fs::read_to_string(&path) {
+    let read_path = path.clone();
+    match run_blocking(state, move || fs::read_to_string(read_path))? {
         Ok(content) => Ok(Value::IoString(Gc::new(state, content))),
         Err(e) => Err(VmError::RuntimeError(format!(
             "Failed to read file '{}': {}",
@@ -88,13 +191,14 @@ fn io_read_lines<'gc>(
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
     let path = string_arg!(args, 0, "read_lines")?.to_string();
-    let file = File::open(&path)
-        .map_err(|e| VmError::RuntimeError(format!("Failed to open file '{}': {}", path, e)))?;
-
-    let lines: Vec<String> = BufReader::new(file)
-        .lines()
-        .collect::<Result<_, _>>()
-        .map_err(|e| VmError::RuntimeError(format!("Failed to read lines: {}", e)))?;
+    let lines: Vec<String> = run_blocking(state, move || {
+        let file = File::open(&path)
+            .map_err(|e| VmError::RuntimeError(format!("Failed to open file '{}': {}", path, e)))?;
+        BufReader::new(file)
+            .lines()
+            .collect::<Result<_, _>>()
+            .map_err(|e| VmError::RuntimeError(format!("Failed to read lines: {}", e)))
+    })??;
 
     let content = lines.join("\n");
     Ok(Value::IoString(Gc::new(state, content)))
@@ -102,34 +206,39 @@ fn io_read_lines<'gc>(
 
 // File writing functions
 fn io_write_file<'gc>(
-    _state: &mut State<'gc>,
+    state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
     let path = string_arg!(args, 0, "write_file")?.to_string();
     let content = string_arg!(args, 1, "write_file")?.to_string();
 
-    fs::write(&path, content)
-        .map_err(|e| VmError::RuntimeError(format!("Failed to write to file '{}': {}", path, e)))?;
+    run_blocking(state, move || {
+        fs::write(&path, content).map_err(|e| {
+            VmError::RuntimeError(format!("Failed to write to file '{}': {}", path, e))
+        })
+    })??;
 
     Ok(Value::Boolean(true))
 }
 
 fn io_append_file<'gc>(
-    _state: &mut State<'gc>,
+    state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
     let path = string_arg!(args, 0, "append_file")?.to_string();
     let content = string_arg!(args, 1, "append_file")?.to_string();
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&path)
-        .map_err(|e| VmError::RuntimeError(format!("Failed to open file '{}': {}", path, e)))?;
+    run_blocking(state, move || {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| VmError::RuntimeError(format!("Failed to open file '{}': {}", path, e)))?;
 
-    file.write_all(content.as_bytes()).map_err(|e| {
-        VmError::RuntimeError(format!("Failed to append to file '{}': {}", path, e))
-    })?;
+        file.write_all(content.as_bytes()).map_err(|e| {
+            VmError::RuntimeError(format!("Failed to append to file '{}': {}", path, e))
+        })
+    })??;
 
     Ok(Value::Boolean(true))
 }
@@ -0,0 +1,255 @@
+use std::{
+    pin::pin,
+    sync::{Arc, LazyLock, Mutex},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+use serde_json::{Value as Json, json};
+use tokio::{runtime::Handle, sync::Notify};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State, block_on, with_deadline},
+};
+
+use super::serde::to_json_value;
+
+// The oldest jobs are forgotten past it.
+const MAX_JOBS: usize = 10_000;
+
+/// The background jobs of the process, shared by the VMs of the handlers
+/// so a request can wait for a job finished by another one.
+static JOBS: LazyLock<Mutex<IndexMap<String, Job>>> = LazyLock::new(Default::default);
+
+struct Job {
+    // The result once finished.
+    result: Option<Json>,
+    // Wakes the requests waiting for the job when it finishes.
+    finished: Arc<Notify>,
+}
+
+pub fn create_jobs_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.jobs");
+
+    let exports = [
+        ("create", Value::NativeFunction(NativeFn(jobs_create))),
+        ("finish", Value::NativeFunction(NativeFn(jobs_finish))),
+        ("status", Value::NativeFunction(NativeFn(jobs_status))),
+        ("wait_for", Value::NativeFunction(NativeFn(jobs_wait_for))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+/// Register a pending job, returns its id.
+///
+/// fn create() {}
+fn jobs_create<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if !args.is_empty() {
+        return Err(VmError::RuntimeError("create() takes no arguments.".into()));
+    }
+    let id = format!("{:016x}", rand::random::<u64>());
+    let mut jobs = JOBS.lock().unwrap();
+    if jobs.len() == MAX_JOBS {
+        jobs.shift_remove_index(0);
+    }
+    jobs.insert(
+        id.clone(),
+        Job {
+            result: None,
+            finished: Arc::new(Notify::new()),
+        },
+    );
+    Ok(Value::String(state.intern(id.as_bytes())))
+}
+
+/// Finish the job with its result, the requests waiting for it resume.
+///
+/// fn finish(job_id, result = nil) {}
+fn jobs_finish<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "finish() takes 1 or 2 arguments: the job id and its result.".into(),
+        ));
+    }
+    let id = string_arg!(&args, 0, "finish")?.to_string();
+    let result = match args.get(1) {
+        Some(value) => to_json_value(state, value)?,
+        None => Json::Null,
+    };
+    let mut jobs = JOBS.lock().unwrap();
+    let job = jobs.get_mut(&id).ok_or_else(|| unknown_job(&id))?;
+    if job.result.is_some() {
+        return Err(VmError::RuntimeError(format!(
+            "Job '{id}' is already finished."
+        )));
+    }
+    job.result = Some(result);
+    job.finished.notify_waiters();
+    Ok(Value::Nil)
+}
+
+/// The `status` of the job, "pending" or "finished", and its `result`,
+/// nil if the job is unknown.
+///
+/// fn status(job_id) {}
+fn jobs_status<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("status() takes 1 argument.".into()));
+    }
+    let id = string_arg!(&args, 0, "status")?.to_string();
+    match JOBS.lock().unwrap().get(&id) {
+        Some(job) => Ok(Value::from_serde_value(
+            state.get_context(),
+            &status_json(&id, job.result.as_ref()),
+        )),
+        None => Ok(Value::Nil),
+    }
+}
+
+/// Wait until the job finishes or the seconds of the timeout elapse, then
+/// return its status like `status()`. The request is parked meanwhile, a
+/// status endpoint can long-poll rather than the clients polling it.
+///
+/// fn wait_for(job_id, timeout) {}
+fn jobs_wait_for<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(
+            "wait_for() takes 2 arguments: the job id and the timeout in seconds.".into(),
+        ));
+    }
+    let id = string_arg!(&args, 0, "wait_for")?.to_string();
+    let timeout = match args[1] {
+        Value::Number(seconds) if seconds >= 0.0 && seconds.is_finite() => seconds,
+        Value::Int(seconds) if seconds >= 0 => seconds as f64,
+        _ => {
+            return Err(VmError::RuntimeError(
+                "wait_for() timeout must be a non-negative number of seconds.".into(),
+            ));
+        }
+    };
+    let until = Duration::try_from_secs_f64(timeout)
+        .ok()
+        .and_then(|timeout| Instant::now().checked_add(timeout))
+        .ok_or_else(|| VmError::RuntimeError("wait_for() timeout is too large.".into()))?;
+    let wait = with_deadline(state.deadline, wait_until(&id, until));
+    let result = match Handle::try_current() {
+        Ok(handle) => block_on(&handle, wait)?,
+        Err(_) => tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(wait)?,
+    };
+    let result = result.ok_or_else(|| unknown_job(&id))?;
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &status_json(&id, result.as_ref()),
+    ))
+}
+
+// The result of the job once finished, or None once `until` is reached, None
+// if the job is unknown.
+async fn wait_until(id: &str, until: Instant) -> Option<Option<Json>> {
+    loop {
+        let finished = JOBS.lock().unwrap().get(id)?.finished.clone();
+        // Registered before the job is checked so a finish in between isn't missed
+        let mut notified = pin!(finished.notified());
+        notified.as_mut().enable();
+        let result = JOBS.lock().unwrap().get(id)?.result.clone();
+        if result.is_some() || Instant::now() >= until {
+            return Some(result);
+        }
+        if tokio::time::timeout_at(until.into(), notified)
+            .await
+            .is_err()
+        {
+            return Some(JOBS.lock().unwrap().get(id)?.result.clone());
+        }
+    }
+}
+
+fn status_json(id: &str, result: Option<&Json>) -> Json {
+    json!({
+        "id": id,
+        "status": if result.is_some() { "finished" } else { "pending" },
+        "result": result,
+    })
+}
+
+fn unknown_job(id: &str) -> VmError {
+    VmError::RuntimeError(format!("Unknown job '{id}'."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert(id: &str) {
+        JOBS.lock().unwrap().insert(
+            id.to_owned(),
+            Job {
+                result: None,
+                finished: Arc::new(Notify::new()),
+            },
+        );
+    }
+
+    #[test]
+    fn test_wait_until() {
+        insert("test_wait_until");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let started = Instant::now();
+        let waiter = runtime.spawn(wait_until(
+            "test_wait_until",
+            started + Duration::from_secs(5),
+        ));
+        std::thread::sleep(Duration::from_millis(20));
+        {
+            let mut jobs = JOBS.lock().unwrap();
+            let job = jobs.get_mut("test_wait_until").unwrap();
+            job.result = Some(json!(42));
+            job.finished.notify_waiters();
+        }
+        assert_eq!(runtime.block_on(waiter).unwrap(), Some(Some(json!(42))));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_wait_until_timeout() {
+        insert("test_wait_until_timeout");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let until = Instant::now() + Duration::from_millis(20);
+        assert_eq!(
+            runtime.block_on(wait_until("test_wait_until_timeout", until)),
+            Some(None)
+        );
+        assert_eq!(runtime.block_on(wait_until("unknown", until)), None);
+    }
+
+    #[test]
+    fn test_wait_for_in_runtime() {
+        insert("test_wait_for_in_runtime");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut vm = crate::Vm::default();
+            vm.compile(
+                "use std.jobs;\nreturn jobs.wait_for(\"test_wait_for_in_runtime\", 0.01).status;",
+            )
+            .unwrap();
+            assert_eq!(
+                vm.interpret().unwrap(),
+                crate::ReturnValue::String("pending".into())
+            );
+        });
+    }
+}
@@ -0,0 +1,445 @@
+use serde::Serialize;
+use serde_json::{Map, Value as Json, ser::PrettyFormatter};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State},
+};
+
+use super::serde::{extract_keyword_args, from_json_value, to_json_value};
+
+pub fn create_json_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.json");
+
+    let exports = [
+        ("parse", Value::NativeFunction(NativeFn(json_parse))),
+        ("stringify", Value::NativeFunction(NativeFn(json_stringify))),
+        ("get", Value::NativeFunction(NativeFn(json_get))),
+        ("merge", Value::NativeFunction(NativeFn(json_merge))),
+        ("diff", Value::NativeFunction(NativeFn(json_diff))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+/// Parse the JSON string, into an instance of the class if given.
+///
+/// fn parse(text, class = nil) {}
+fn json_parse<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.is_empty() || args.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "parse() takes 1 or 2 arguments.".into(),
+        ));
+    }
+    let text = string_arg!(&args, 0, "parse")?;
+    let parsed = serde_json::from_str(text.to_str().unwrap())
+        .map_err(|e| VmError::RuntimeError(format!("Failed to parse JSON: {e}")))?;
+    from_json_value(state, &parsed, args.get(1), "parse")
+}
+
+/// The JSON of the value, indented by the number of spaces if given.
+///
+/// fn stringify(value, indent = nil) {}
+fn json_stringify<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["indent"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "stringify() takes 1 or 2 arguments.".into(),
+        ));
+    }
+    let indent = match positional.get(1).or(keyword.get("indent")) {
+        None | Some(Value::Nil) => None,
+        Some(value) => match value.as_exact_int() {
+            Some(n @ 0..=16) => Some(n as usize),
+            _ => {
+                return Err(VmError::RuntimeError(
+                    "stringify() indent must be a number of spaces from 0 to 16.".into(),
+                ));
+            }
+        },
+    };
+
+    let json = to_json_value(state, &positional[0])?;
+    let text = match indent {
+        None => serde_json::to_string(&json).unwrap_or_default(),
+        Some(indent) => {
+            let indent = " ".repeat(indent);
+            let mut buf = Vec::new();
+            let mut serializer = serde_json::Serializer::with_formatter(
+                &mut buf,
+                PrettyFormatter::with_indent(indent.as_bytes()),
+            );
+            json.serialize(&mut serializer)
+                .map_err(|e| VmError::RuntimeError(format!("Failed to serialize to JSON: {e}")))?;
+            String::from_utf8(buf).unwrap_or_default()
+        }
+    };
+    Ok(Value::String(state.intern(text.as_bytes())))
+}
+
+/// The value at the JSONPath, e.g. `$.items[0].id`, nil if there is none.
+/// A path with a wildcard `*`, a slice `[1:3]` or a recursive `..name`
+/// returns the array of all the values it matches.
+///
+/// fn get(value, path) {}
+fn json_get<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(
+            "get() takes 2 arguments: the value and the path.".into(),
+        ));
+    }
+    let path = string_arg!(&args, 1, "get")?;
+    let segments = parse_path(path.to_str().unwrap()).map_err(|message| {
+        VmError::RuntimeError(format!("Invalid JSONPath '{path}': {message}"))
+    })?;
+    let json = to_json_value(state, &args[0])?;
+    let matches = select(&json, &segments);
+    let ctx = state.get_context();
+    if segments.iter().all(Segment::is_definite) {
+        return Ok(matches
+            .first()
+            .map_or(Value::Nil, |value| Value::from_serde_value(ctx, value)));
+    }
+    let values = matches
+        .into_iter()
+        .map(|value| Value::from_serde_value(ctx, value))
+        .collect();
+    Ok(Value::array(&ctx, values))
+}
+
+/// Merge the objects deeply, the fields of the second one win. Arrays and
+/// other values are replaced, not merged.
+///
+/// fn merge(base, changes) {}
+fn json_merge<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError("merge() takes 2 arguments.".into()));
+    }
+    let mut base = to_json_value(state, &args[0])?;
+    merge(&mut base, to_json_value(state, &args[1])?);
+    Ok(Value::from_serde_value(state.get_context(), &base))
+}
+
+/// The changes from the first value to the second one, as an array of
+/// `{op, path, old, new}` objects, `op` being "add", "remove" or "replace"
+/// and `path` the JSONPath of the changed value.
+///
+/// fn diff(old, new) {}
+fn json_diff<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError("diff() takes 2 arguments.".into()));
+    }
+    let old = to_json_value(state, &args[0])?;
+    let new = to_json_value(state, &args[1])?;
+    let mut changes = Vec::new();
+    diff(&old, &new, &mut String::from("$"), &mut changes);
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &Json::Array(changes),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(i64),
+    Slice(Option<i64>, Option<i64>),
+    Wildcard,
+    // `..name` or `..*`, the values at any depth
+    Descendants(Option<String>),
+}
+
+impl Segment {
+    // Whether the segment selects at most one value
+    fn is_definite(&self) -> bool {
+        matches!(self, Segment::Field(_) | Segment::Index(_))
+    }
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let mut rest = path
+        .trim()
+        .strip_prefix('$')
+        .ok_or("it must start with '$'")?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let (name, after) = split_name(after);
+            segments.push(Segment::Descendants(match name {
+                "*" => None,
+                "" => return Err("expected a name after '..'".into()),
+                name => Some(name.to_owned()),
+            }));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let (name, after) = split_name(after);
+            segments.push(match name {
+                "*" => Segment::Wildcard,
+                "" => return Err("expected a name after '.'".into()),
+                name => Segment::Field(name.to_owned()),
+            });
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let end = if after.starts_with(['\'', '"']) {
+                let quote = &after[..1];
+                after[1..].find(quote).map(|end| end + 2)
+            } else {
+                after.find(']')
+            }
+            .filter(|end| after[*end..].starts_with(']'))
+            .ok_or("unclosed '['")?;
+            segments.push(parse_bracket(after[..end].trim())?);
+            rest = &after[end + 1..];
+        } else {
+            return Err(format!("unexpected '{rest}'"));
+        }
+    }
+    Ok(segments)
+}
+
+// The name of a dotted segment and the rest of the path.
+fn split_name(path: &str) -> (&str, &str) {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    path.split_at(end)
+}
+
+fn parse_bracket(selector: &str) -> Result<Segment, String> {
+    let index = |s: &str| -> Result<Option<i64>, String> {
+        match s.trim() {
+            "" => Ok(None),
+            s => s
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("invalid index '{s}'")),
+        }
+    };
+    if selector == "*" {
+        Ok(Segment::Wildcard)
+    } else if selector.len() >= 2
+        && (selector.starts_with('\'') && selector.ends_with('\'')
+            || selector.starts_with('"') && selector.ends_with('"'))
+    {
+        Ok(Segment::Field(selector[1..selector.len() - 1].to_owned()))
+    } else if let Some((start, end)) = selector.split_once(':') {
+        Ok(Segment::Slice(index(start)?, index(end)?))
+    } else {
+        index(selector)?
+            .map(Segment::Index)
+            .ok_or_else(|| "empty '[]'".to_owned())
+    }
+}
+
+fn select<'a>(root: &'a Json, segments: &[Segment]) -> Vec<&'a Json> {
+    let mut current = vec![root];
+    for segment in segments {
+        let mut next = Vec::new();
+        for value in current {
+            match segment {
+                Segment::Field(name) => next.extend(value.get(name)),
+                Segment::Index(index) => {
+                    if let Json::Array(items) = value {
+                        let index = if *index < 0 {
+                            items.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        next.extend(usize::try_from(index).ok().and_then(|i| items.get(i)));
+                    }
+                }
+                Segment::Slice(start, end) => {
+                    if let Json::Array(items) = value {
+                        let len = items.len() as i64;
+                        let bound = |i: i64| (if i < 0 { len + i } else { i }).clamp(0, len);
+                        let start = start.map_or(0, bound);
+                        let end = end.map_or(len, bound);
+                        if start < end {
+                            next.extend(&items[start as usize..end as usize]);
+                        }
+                    }
+                }
+                Segment::Wildcard => match value {
+                    Json::Array(items) => next.extend(items),
+                    Json::Object(fields) => next.extend(fields.values()),
+                    _ => {}
+                },
+                Segment::Descendants(name) => descendants(value, name.as_deref(), &mut next),
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+// The values below the value, in document order, only the fields with the
+// name if given.
+fn descendants<'a>(value: &'a Json, name: Option<&str>, out: &mut Vec<&'a Json>) {
+    match value {
+        Json::Array(items) => {
+            for item in items {
+                if name.is_none() {
+                    out.push(item);
+                }
+                descendants(item, name, out);
+            }
+        }
+        Json::Object(fields) => {
+            for (key, field) in fields {
+                if name.is_none_or(|name| name == key) {
+                    out.push(field);
+                }
+                descendants(field, name, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn merge(base: &mut Json, changes: Json) {
+    match (base, changes) {
+        (Json::Object(base), Json::Object(changes)) => {
+            for (key, value) in changes {
+                match base.get_mut(&key) {
+                    Some(field) => merge(field, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, changes) => *base = changes,
+    }
+}
+
+fn diff(old: &Json, new: &Json, path: &mut String, changes: &mut Vec<Json>) {
+    let change = |op: &str, path: &str, old: Option<&Json>, new: Option<&Json>| {
+        let mut change = Map::new();
+        change.insert("op".into(), op.into());
+        change.insert("path".into(), path.into());
+        if let Some(old) = old {
+            change.insert("old".into(), old.clone());
+        }
+        if let Some(new) = new {
+            change.insert("new".into(), new.clone());
+        }
+        Json::Object(change)
+    };
+    let len = path.len();
+    match (old, new) {
+        (Json::Object(old), Json::Object(new)) => {
+            for (key, old_field) in old {
+                push_field(path, key);
+                match new.get(key) {
+                    Some(new_field) => diff(old_field, new_field, path, changes),
+                    None => changes.push(change("remove", path, Some(old_field), None)),
+                }
+                path.truncate(len);
+            }
+            for (key, new_field) in new.iter().filter(|(key, _)| !old.contains_key(*key)) {
+                push_field(path, key);
+                changes.push(change("add", path, None, Some(new_field)));
+                path.truncate(len);
+            }
+        }
+        (Json::Array(old), Json::Array(new)) => {
+            for i in 0..old.len().max(new.len()) {
+                path.push_str(&format!("[{i}]"));
+                match (old.get(i), new.get(i)) {
+                    (Some(old), Some(new)) => diff(old, new, path, changes),
+                    (Some(old), None) => changes.push(change("remove", path, Some(old), None)),
+                    (None, new) => changes.push(change("add", path, None, new)),
+                }
+                path.truncate(len);
+            }
+        }
+        (old, new) if old != new => changes.push(change("replace", path, Some(old), Some(new))),
+        _ => {}
+    }
+}
+
+// Append the field to the path, quoted unless it's an identifier.
+fn push_field(path: &mut String, key: &str) {
+    let identifier = !key.is_empty()
+        && !key.starts_with(|c: char| c.is_ascii_digit())
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if identifier {
+        path.push('.');
+        path.push_str(key);
+    } else {
+        path.push_str(&format!("['{}']", key.replace('\'', "\\'")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn get(value: &Json, path: &str) -> Vec<Json> {
+        select(value, &parse_path(path).unwrap())
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_json_path() {
+        let value = json!({
+            "items": [{"id": 1, "tags": ["a"]}, {"id": 2}, {"id": 3, "meta": {"id": 4}}],
+            "odd key": true,
+        });
+        assert_eq!(get(&value, "$.items[0].id"), [json!(1)]);
+        assert_eq!(get(&value, "$['items'][-1].meta.id"), [json!(4)]);
+        assert_eq!(get(&value, "$[\"odd key\"]"), [json!(true)]);
+        assert_eq!(get(&value, "$.items[*].id"), [json!(1), json!(2), json!(3)]);
+        assert_eq!(get(&value, "$.items[1:].id"), [json!(2), json!(3)]);
+        assert_eq!(
+            get(&value, "$..id"),
+            [json!(1), json!(2), json!(3), json!(4)]
+        );
+        assert!(get(&value, "$.items[7].id").is_empty());
+        assert_eq!(get(&value, "$"), std::slice::from_ref(&value));
+
+        assert!(parse_path("items").is_err());
+        assert!(parse_path("$.items[0").is_err());
+        assert!(parse_path("$.items[x]").is_err());
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut base = json!({"a": {"b": 1, "c": [1, 2]}, "d": 1});
+        merge(&mut base, json!({"a": {"c": [3], "e": null}, "f": 2}));
+        assert_eq!(
+            base,
+            json!({"a": {"b": 1, "c": [3], "e": null}, "d": 1, "f": 2})
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut changes = Vec::new();
+        diff(
+            &json!({"a": 1, "b": [1, 2], "c": {"my key": 1}}),
+            &json!({"a": 2, "b": [1], "c": {}, "d": true}),
+            &mut String::from("$"),
+            &mut changes,
+        );
+        assert_eq!(
+            changes,
+            [
+                json!({"op": "replace", "path": "$.a", "old": 1, "new": 2}),
+                json!({"op": "remove", "path": "$.b[1]", "old": 2}),
+                json!({"op": "remove", "path": "$.c['my key']", "old": 1}),
+                json!({"op": "add", "path": "$.d", "new": true}),
+            ]
+        );
+    }
+}
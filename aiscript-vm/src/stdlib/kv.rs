@@ -0,0 +1,358 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use redb::{Database, DatabaseError, TableDefinition};
+use serde_json::Value as Json;
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State, run_blocking},
+};
+
+use super::serde::{extract_keyword_args, to_json_value};
+
+/// The file of the store unless set by `[kv]` in project.toml.
+pub const DEFAULT_KV_PATH: &str = ".aiscript/kv.redb";
+
+// The values are JSON, after the expiry in milliseconds since the Unix
+// epoch as 8 big-endian bytes, 0 if the key never expires.
+const ENTRIES: TableDefinition<&str, &[u8]> = TableDefinition::new("kv");
+
+static KV_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+// How long to wait for a database file held by another process, e.g. the
+// executions of the workflows being saved by a worker resuming them.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// The store is opened once for the process, a file can't be opened twice.
+static STORE: Mutex<Option<(PathBuf, Arc<Database>)>> = Mutex::new(None);
+
+/// The file of the `std.kv` store, [`DEFAULT_KV_PATH`] by default.
+pub fn set_kv_path(path: PathBuf) {
+    *KV_PATH.lock().unwrap() = Some(path);
+}
+
+pub fn create_kv_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.kv");
+
+    let exports = [
+        ("get", Value::NativeFunction(NativeFn(kv_get))),
+        ("set", Value::NativeFunction(NativeFn(kv_set))),
+        ("delete", Value::NativeFunction(NativeFn(kv_delete))),
+        ("scan", Value::NativeFunction(NativeFn(kv_scan))),
+        ("ttl", Value::NativeFunction(NativeFn(kv_ttl))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// The configured file of the store, or the default one.
+pub(crate) fn kv_path() -> PathBuf {
+    KV_PATH
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_KV_PATH))
+}
+
+// The store of the configured file, opened on first use. The expired
+// entries are removed when it's opened. It waits for the file held by
+// another process, so it's only called on a blocking thread.
+fn store() -> Result<Arc<Database>, KvError> {
+    let path = kv_path();
+    let mut store = STORE.lock().unwrap();
+    if let Some((opened, db)) = store.as_ref()
+        && *opened == path
+    {
+        return Ok(db.clone());
+    }
+    let db = Arc::new(
+        open(&path)
+            .map_err(|e| format!("Failed to open the kv store '{}': {e}", path.display()))?,
+    );
+    *store = Some((path, db.clone()));
+    Ok(db)
+}
+
+fn open(path: &Path) -> Result<Database, KvError> {
+    let db = open_database(path)?;
+    let txn = db.begin_write()?;
+    {
+        let now = now_millis();
+        let mut table = txn.open_table(ENTRIES)?;
+        table.retain(|_, entry| !is_expired(entry, now))?;
+    }
+    txn.commit()?;
+    Ok(db)
+}
+
+/// Open or create the database file, waiting for another process holding it.
+pub(crate) fn open_database(path: &Path) -> Result<Database, KvError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    let deadline = Instant::now() + LOCK_TIMEOUT;
+    loop {
+        match Database::create(path) {
+            Err(DatabaseError::DatabaseAlreadyOpen) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(20));
+            }
+            db => return Ok(db?),
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as u64)
+}
+
+fn encode(value: &Json, expires_at: u64) -> Vec<u8> {
+    let mut entry = expires_at.to_be_bytes().to_vec();
+    entry.extend(serde_json::to_vec(value).expect("JSON values serialize"));
+    entry
+}
+
+fn expiry(entry: &[u8]) -> u64 {
+    entry
+        .get(..8)
+        .map_or(0, |bytes| u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn is_expired(entry: &[u8], now: u64) -> bool {
+    let expires_at = expiry(entry);
+    expires_at != 0 && expires_at <= now
+}
+
+// The value of the entry, None once expired.
+fn decode(entry: &[u8], now: u64) -> Option<Json> {
+    if is_expired(entry, now) {
+        return None;
+    }
+    serde_json::from_slice(entry.get(8..)?).ok()
+}
+
+pub(crate) type KvError = Box<dyn std::error::Error + Send + Sync>;
+
+fn kv_error(e: impl std::fmt::Display) -> VmError {
+    VmError::RuntimeError(format!("kv: {e}"))
+}
+
+// Open the store if needed and run the operation on it on a blocking thread.
+fn with_store<'gc, T: Send + 'static>(
+    state: &mut State<'gc>,
+    op: impl FnOnce(&Database) -> Result<T, KvError> + Send + 'static,
+) -> Result<T, VmError> {
+    run_blocking(state, move || op(&*store()?))?.map_err(kv_error)
+}
+
+/// The value of the key, or the default if it's missing or expired.
+///
+/// fn get(key, default = nil) {}
+fn kv_get<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["default"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "get() takes 1 or 2 arguments: the key and the default.".into(),
+        ));
+    }
+    let key = string_arg!(&positional, 0, "get")?.to_string();
+    let default = positional
+        .get(1)
+        .or(keyword.get("default"))
+        .copied()
+        .unwrap_or(Value::Nil);
+    let value = with_store(state, move |db| {
+        let txn = db.begin_read()?;
+        let table = match txn.open_table(ENTRIES) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let now = now_millis();
+        Ok(table
+            .get(key.as_str())?
+            .and_then(|entry| decode(entry.value(), now)))
+    })?;
+    Ok(match value {
+        Some(value) => Value::from_serde_value(state.get_context(), &value),
+        None => default,
+    })
+}
+
+/// Store the value of the key, it expires after the seconds of the ttl if
+/// given. The value is stored as JSON.
+///
+/// fn set(key, value, ttl = nil) {}
+fn kv_set<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["ttl"])?;
+    if positional.len() < 2 || positional.len() > 3 {
+        return Err(VmError::RuntimeError(
+            "set() takes 2 or 3 arguments: the key, the value and the ttl in seconds.".into(),
+        ));
+    }
+    let key = string_arg!(&positional, 0, "set")?.to_string();
+    let value = to_json_value(state, &positional[1])?;
+    let expires_at = match positional.get(2).or(keyword.get("ttl")) {
+        None | Some(Value::Nil) => 0,
+        Some(ttl) => match ttl.as_number() {
+            Ok(seconds) if seconds > 0.0 && seconds.is_finite() => {
+                // The milliseconds saturate, a huge ttl never expires
+                now_millis().saturating_add((seconds * 1000.0) as u64)
+            }
+            _ => {
+                return Err(VmError::RuntimeError(
+                    "set() ttl must be a positive number of seconds.".into(),
+                ));
+            }
+        },
+    };
+    with_store(state, move |db| {
+        let txn = db.begin_write()?;
+        txn.open_table(ENTRIES)?
+            .insert(key.as_str(), encode(&value, expires_at).as_slice())?;
+        txn.commit()?;
+        Ok(())
+    })?;
+    Ok(Value::Nil)
+}
+
+/// Remove the key, returns whether it was stored.
+///
+/// fn delete(key) {}
+fn kv_delete<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("delete() takes 1 argument.".into()));
+    }
+    let key = string_arg!(&args, 0, "delete")?.to_string();
+    let removed = with_store(state, move |db| {
+        let txn = db.begin_write()?;
+        let removed = txn
+            .open_table(ENTRIES)?
+            .remove(key.as_str())?
+            .is_some_and(|entry| !is_expired(entry.value(), now_millis()));
+        txn.commit()?;
+        Ok(removed)
+    })?;
+    Ok(Value::Boolean(removed))
+}
+
+/// The entries of the keys starting with the prefix in the order of the
+/// keys, as `{key, value}` objects, at most `limit` of them if given.
+///
+/// fn scan(prefix = "", limit = nil) {}
+fn kv_scan<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["prefix", "limit"])?;
+    if positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "scan() takes at most 2 arguments: the prefix and the limit.".into(),
+        ));
+    }
+    let prefix = match positional.first().or(keyword.get("prefix")) {
+        None | Some(Value::Nil) => String::new(),
+        Some(prefix) => prefix
+            .as_string_value()
+            .map_err(|_| VmError::RuntimeError("scan() prefix must be a string.".into()))?
+            .as_str()
+            .to_owned(),
+    };
+    let limit = match positional.get(1).or(keyword.get("limit")) {
+        None | Some(Value::Nil) => usize::MAX,
+        Some(limit) => match limit.as_number() {
+            Ok(limit) if limit >= 0.0 && limit.fract() == 0.0 => limit as usize,
+            _ => {
+                return Err(VmError::RuntimeError(
+                    "scan() limit must be a non-negative integer.".into(),
+                ));
+            }
+        },
+    };
+    let entries = with_store(state, move |db| {
+        let txn = db.begin_read()?;
+        let table = match txn.open_table(ENTRIES) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let now = now_millis();
+        let mut entries = Vec::new();
+        for entry in table.range(prefix.as_str()..)? {
+            if entries.len() == limit {
+                break;
+            }
+            let (key, entry) = entry?;
+            if !key.value().starts_with(prefix.as_str()) {
+                break;
+            }
+            if let Some(value) = decode(entry.value(), now) {
+                entries.push(serde_json::json!({"key": key.value(), "value": value}));
+            }
+        }
+        Ok(entries)
+    })?;
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &Json::Array(entries),
+    ))
+}
+
+/// The seconds left before the key expires, nil if it's missing or never
+/// expires.
+///
+/// fn ttl(key) {}
+fn kv_ttl<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("ttl() takes 1 argument.".into()));
+    }
+    let key = string_arg!(&args, 0, "ttl")?.to_string();
+    let expires_at = with_store(state, move |db| {
+        let txn = db.begin_read()?;
+        let table = match txn.open_table(ENTRIES) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(table
+            .get(key.as_str())?
+            .map_or(0, |entry| expiry(entry.value())))
+    })?;
+    let now = now_millis();
+    if expires_at <= now {
+        return Ok(Value::Nil);
+    }
+    Ok(Value::Number((expires_at - now) as f64 / 1000.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let entry = encode(&serde_json::json!({"a": 1}), 0);
+        assert_eq!(expiry(&entry), 0);
+        assert_eq!(
+            decode(&entry, now_millis()),
+            Some(serde_json::json!({"a": 1}))
+        );
+
+        let entry = encode(&Json::from("x"), u64::MAX);
+        assert_eq!(decode(&entry, now_millis()), Some(Json::from("x")));
+
+        let entry = encode(&Json::from("x"), 1_000);
+        assert!(is_expired(&entry, 1_000));
+        assert_eq!(decode(&entry, 1_000), None);
+        assert_eq!(decode(&entry, 999), Some(Json::from("x")));
+    }
+}
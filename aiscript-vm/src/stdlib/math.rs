@@ -20,6 +20,8 @@ pub fn create_math_module(ctx: Context) -> ModuleKind {
         ("sub", Value::NativeFunction(NativeFn(math_sub))),
         ("mul", Value::NativeFunction(NativeFn(math_mul))),
         ("div", Value::NativeFunction(NativeFn(math_div))),
+        ("floor_div", Value::NativeFunction(NativeFn(math_floor_div))),
+        // The misspelled name of `floor_div`, kept for the existing scripts
         ("floo_div", Value::NativeFunction(NativeFn(math_floor_div))),
         // Advanced functions
         ("sqrt", Value::NativeFunction(NativeFn(math_sqrt))),
@@ -74,10 +76,26 @@ fn math_div<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value
     Ok(Value::Number(x / y))
 }
 
+// Floor division, like the `~/` operator. The quotient of two integers is an
+// exact integer.
 fn math_floor_div<'gc>(
     _state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
+    if let [Value::Int(x), Value::Int(y), ..] = args[..] {
+        if y == 0 {
+            return Err(VmError::RuntimeError("floor_div: division by zero".into()));
+        }
+        let quotient = x
+            .checked_div(y)
+            .ok_or_else(|| VmError::RuntimeError("floor_div: integer overflow".into()))?;
+        // Round towards negative infinity rather than zero
+        return Ok(Value::Int(if x % y != 0 && (x < 0) != (y < 0) {
+            quotient - 1
+        } else {
+            quotient
+        }));
+    }
     let x = float_arg!(&args, 0, "floor_div")?;
     let y = float_arg!(&args, 1, "floor_div")?;
     if y == 0.0 {
@@ -1,24 +1,56 @@
+mod ai;
 mod auth;
+mod crypto;
+mod datetime;
 mod db;
+mod decimal;
+mod encoding;
 mod env;
-mod http;
+mod graph;
+pub(crate) mod http;
 mod io;
+mod jobs;
+mod json;
+mod kv;
 mod math;
+mod os;
+mod process;
 mod random;
+mod search;
 mod serde;
+mod template;
 mod time;
+mod xml;
 
-pub use auth::create_jwt_module;
+pub use ai::create_ai_module;
+pub use auth::{create_jwt_module, create_password_module};
+pub use crypto::create_crypto_module;
+pub use datetime::create_datetime_module;
 pub use db::create_pg_module;
 pub use db::create_redis_module;
 pub use db::create_sqlite_module;
+pub use decimal::create_decimal_module;
+pub use encoding::create_encoding_module;
 pub use env::create_env_module;
+pub use graph::create_graph_module;
 pub use http::create_http_module;
-pub use io::create_io_module;
+pub use io::{create_io_module, create_stderr_module, create_stdin_module, create_stdout_module};
+pub use jobs::create_jobs_module;
+pub use json::create_json_module;
+pub use kv::{DEFAULT_KV_PATH, create_kv_module, set_kv_path};
+pub(crate) use kv::{KvError, kv_path, open_database};
 pub use math::create_math_module;
+pub use os::create_os_module;
+pub(crate) use os::set_args;
+pub use process::create_process_module;
 pub use random::create_random_module;
+pub use search::{SearchConfig, create_search_module};
 pub use serde::create_serde_module;
+pub(crate) use serde::{extract_keyword_args, to_json_value};
+pub use template::{DEFAULT_TEMPLATE_DIR, create_template_module, set_template_dir};
 pub use time::create_time_module;
+pub(crate) use time::sleep_for;
+pub use xml::create_xml_module;
 
 /// Macro to get and validate a float argument from a slice of Values
 ///
@@ -48,6 +80,29 @@ macro_rules! float_arg {
     };
 }
 
+/// Macro to get the bytes of a string or bytes argument from a slice of Values
+#[macro_export]
+#[doc(hidden)]
+macro_rules! bytes_arg {
+    ($args:expr, $index:expr, $fn_name:expr) => {
+        match $args.get($index) {
+            Some(value) => value.as_byte_slice().ok_or_else(|| {
+                VmError::RuntimeError(format!(
+                    "{}: argument {} must be a string or bytes",
+                    $fn_name,
+                    $index + 1
+                ))
+            }),
+            None => Err(VmError::RuntimeError(format!(
+                "{}: expected {} arguments, got {}",
+                $fn_name,
+                $index + 1,
+                $args.len()
+            ))),
+        }
+    };
+}
+
 /// Macro to get and validate a string argument from a slice of Values
 #[macro_export]
 #[doc(hidden)]
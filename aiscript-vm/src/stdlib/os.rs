@@ -0,0 +1,138 @@
+use std::{env, process, thread};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    vm::{Context, State},
+};
+
+pub fn create_os_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.os");
+
+    // The arguments are set by `Vm::set_args`
+    let exports = [
+        ("args", Value::array(&ctx, Vec::new())),
+        ("platform", Value::NativeFunction(NativeFn(os_platform))),
+        ("arch", Value::NativeFunction(NativeFn(os_arch))),
+        ("cpu_count", Value::NativeFunction(NativeFn(os_cpu_count))),
+        ("hostname", Value::NativeFunction(NativeFn(os_hostname))),
+        ("pid", Value::NativeFunction(NativeFn(os_pid))),
+        ("get_env", Value::NativeFunction(NativeFn(os_get_env))),
+        ("set_env", Value::NativeFunction(NativeFn(os_set_env))),
+        ("unset_env", Value::NativeFunction(NativeFn(os_unset_env))),
+    ]
+    .into_iter()
+    .map(|(name, value)| (ctx.intern_static(name), value))
+    .collect();
+    ModuleKind::Native { name, exports }
+}
+
+/// Set `std.os.args` to the arguments given to the script.
+pub(crate) fn set_args(state: &mut State, args: Vec<String>) {
+    let ctx = state.get_context();
+    let args = args
+        .into_iter()
+        .map(|arg| Value::String(ctx.intern(arg.as_bytes())))
+        .collect();
+    let args = Value::array(&ctx, args);
+    if let Some(module) = state
+        .module_manager
+        .modules
+        .get_mut(&ctx.intern_static("std.os"))
+    {
+        module.add_export(ctx.intern_static("args"), args);
+    }
+}
+
+fn no_arguments(name: &str, args: &[Value]) -> Result<(), VmError> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(VmError::RuntimeError(format!(
+            "{name}() takes no arguments."
+        )))
+    }
+}
+
+// The operating system, e.g. "linux", "macos" or "windows"
+fn os_platform<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    no_arguments("platform", &args)?;
+    Ok(Value::String(state.intern_static(env::consts::OS)))
+}
+
+// The CPU architecture, e.g. "x86_64" or "aarch64"
+fn os_arch<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    no_arguments("arch", &args)?;
+    Ok(Value::String(state.intern_static(env::consts::ARCH)))
+}
+
+// The number of CPUs available to the process
+fn os_cpu_count<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    no_arguments("cpu_count", &args)?;
+    let count = thread::available_parallelism().map_or(1, |count| count.get());
+    Ok(Value::Number(count as f64))
+}
+
+fn os_hostname<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    no_arguments("hostname", &args)?;
+    let hostname = whoami::fallible::hostname()
+        .map_err(|err| VmError::RuntimeError(format!("Failed to get the hostname: {err}")))?;
+    Ok(Value::String(state.intern(hostname.as_bytes())))
+}
+
+// The id of the current process
+fn os_pid<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    no_arguments("pid", &args)?;
+    Ok(Value::Number(process::id() as f64))
+}
+
+// The value of the environment variable, nil if it isn't set
+fn os_get_env<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let name = env_name("get_env", &args, 1)?;
+    match env::var(name) {
+        Ok(value) => Ok(Value::String(state.intern(value.as_bytes()))),
+        Err(_) => Ok(Value::Nil),
+    }
+}
+
+fn os_set_env<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let name = env_name("set_env", &args, 2)?;
+    let value = args[1].as_string()?.to_string();
+    if value.contains('\0') {
+        return Err(VmError::RuntimeError(
+            "set_env() value can't contain a NUL character.".into(),
+        ));
+    }
+    // Like the `set_env()` of std.env, not synchronized with other threads
+    unsafe { env::set_var(name, value) };
+    Ok(Value::Nil)
+}
+
+fn os_unset_env<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let name = env_name("unset_env", &args, 1)?;
+    unsafe { env::remove_var(name) };
+    Ok(Value::Nil)
+}
+
+// The variable name argument, which `std::env` would panic on if invalid.
+fn env_name(function: &str, args: &[Value], arity: usize) -> Result<String, VmError> {
+    if args.len() != arity {
+        return Err(VmError::RuntimeError(format!(
+            "{function}() takes {arity} argument{}.",
+            if arity == 1 { "" } else { "s" }
+        )));
+    }
+    let name = args[0].as_string()?.to_string();
+    if name.is_empty() || name.contains(['=', '\0']) {
+        return Err(VmError::RuntimeError(format!(
+            "{function}() got an invalid variable name '{name}'."
+        )));
+    }
+    Ok(name)
+}
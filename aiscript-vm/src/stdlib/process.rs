@@ -0,0 +1,211 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use aiscript_arena::{Gc, RefLock};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    object::Object,
+    vm::{Context, State, run_blocking},
+};
+
+pub fn create_process_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.process");
+
+    let exports = [("run", Value::NativeFunction(NativeFn(process_run)))]
+        .into_iter()
+        .map(|(name, f)| (ctx.intern_static(name), f))
+        .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// How often a running process is checked for its exit.
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Run the command with the arguments and wait for it to exit, returns its
+/// exit `code` (nil if killed by a signal), `stdout` and `stderr`. The
+/// command isn't run by a shell. The options are:
+/// - `stdin`: the string written to its standard input
+/// - `env`: an object of the environment variables added to the inherited ones
+/// - `cwd`: the working directory
+/// - `timeout`: the seconds after which the process is killed
+///
+/// fn run(cmd, args = [], options = {}) {}
+fn process_run<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.is_empty() || args.len() > 3 {
+        return Err(VmError::RuntimeError(
+            "run() takes 1 to 3 arguments: the command, its arguments and the options.".into(),
+        ));
+    }
+    let program = args[0].as_string()?.to_string();
+    let mut command = Command::new(&program);
+    match args.get(1) {
+        None | Some(Value::Nil) => {}
+        Some(Value::List(list)) => {
+            for arg in &list.borrow().data {
+                command.arg(arg.as_string()?.to_string());
+            }
+        }
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "run() arguments must be an array of strings.".into(),
+            ));
+        }
+    }
+
+    let mut stdin = None;
+    let mut timeout = None;
+    match args.get(2) {
+        None | Some(Value::Nil) => {}
+        Some(Value::Object(options)) => {
+            for (key, value) in &options.borrow().fields {
+                match (key.to_str().unwrap_or_default(), value) {
+                    ("stdin", Value::Nil) | ("env", Value::Nil) | ("cwd", Value::Nil) => {}
+                    ("timeout", Value::Nil) => {}
+                    ("stdin", value) => stdin = Some(value.as_string()?.to_string()),
+                    ("cwd", value) => {
+                        command.current_dir(value.as_string()?.to_string());
+                    }
+                    ("env", Value::Object(vars)) => {
+                        for (name, value) in &vars.borrow().fields {
+                            command.env(name.to_string(), value.as_string()?.to_string());
+                        }
+                    }
+                    ("timeout", value) => {
+                        let seconds = value.as_number()?;
+                        if !(seconds > 0.0 && seconds.is_finite()) {
+                            return Err(VmError::RuntimeError(
+                                "run() timeout must be a positive number of seconds.".into(),
+                            ));
+                        }
+                        timeout = Some(Duration::try_from_secs_f64(seconds).map_err(|_| {
+                            VmError::RuntimeError("run() timeout is too large.".into())
+                        })?);
+                    }
+                    (key, _) => {
+                        return Err(VmError::RuntimeError(format!(
+                            "run() got an invalid option '{key}', expected stdin, env, cwd or timeout."
+                        )));
+                    }
+                }
+            }
+        }
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "run() options must be an object.".into(),
+            ));
+        }
+    }
+
+    let child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| VmError::RuntimeError(format!("Failed to run '{program}': {err}")))?;
+
+    // The process is killed by the timeout or the deadline of the request
+    let deadline = state.deadline;
+    let output = run_blocking(state, move || {
+        wait(child, &program, stdin, timeout, deadline)
+    })??;
+
+    let ctx = state.get_context();
+    let mut result = Object::default();
+    result.fields.insert(
+        ctx.intern_static("code"),
+        output
+            .code
+            .map_or(Value::Nil, |code| Value::Number(code as f64)),
+    );
+    result.fields.insert(
+        ctx.intern_static("stdout"),
+        Value::String(ctx.intern(String::from_utf8_lossy(&output.stdout).as_bytes())),
+    );
+    result.fields.insert(
+        ctx.intern_static("stderr"),
+        Value::String(ctx.intern(String::from_utf8_lossy(&output.stderr).as_bytes())),
+    );
+    Ok(Value::Object(Gc::new(&ctx, RefLock::new(result))))
+}
+
+struct Output {
+    code: Option<i32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+// Write the input of the process and wait for it to exit, its exit code and
+// its output.
+fn wait(
+    mut child: Child,
+    program: &str,
+    stdin: Option<String>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Result<Output, VmError> {
+    // The pipes are written and read by their own threads, a process
+    // filling one of them while waiting on another doesn't block.
+    let writer = child.stdin.take().zip(stdin).map(|(mut pipe, input)| {
+        thread::spawn(move || {
+            // The process may exit without reading all of it
+            let _ = pipe.write_all(input.as_bytes());
+        })
+    });
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+            output
+        })
+    };
+    let stdout = read(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|err| {
+            VmError::RuntimeError(format!("Failed to wait for '{program}': {err}"))
+        })? {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(VmError::DeadlineExceeded);
+        }
+        if let Some(timeout) = timeout
+            && started.elapsed() >= timeout
+        {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(VmError::RuntimeError(format!(
+                "'{program}' timed out after {}s.",
+                timeout.as_secs_f64()
+            )));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    Ok(Output {
+        code: status.code(),
+        stdout,
+        stderr,
+    })
+}
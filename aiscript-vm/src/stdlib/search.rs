@@ -0,0 +1,260 @@
+use std::env;
+
+use aiscript_common::EnvString;
+use serde::Deserialize;
+use serde_json::{Value as Json, json};
+use tokio::runtime::Handle;
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State},
+};
+
+const DEFAULT_PROVIDER: &str = "brave";
+const DEFAULT_COUNT: usize = 5;
+
+/// API keys of the web search providers, configured in project.toml:
+///
+/// ```toml
+/// [ai.search]
+/// provider = "tavily"
+/// brave = { api_key = "$BRAVE_API_KEY" }
+/// serpapi = { api_key = "$SERPAPI_API_KEY" }
+/// tavily = { api_key = "$TAVILY_API_KEY" }
+/// ```
+///
+/// The key of a provider missing in the config is read from its environment variable.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchConfig {
+    // The provider used when `web()` isn't given one.
+    pub provider: Option<String>,
+    pub brave: Option<SearchProviderConfig>,
+    pub serpapi: Option<SearchProviderConfig>,
+    pub tavily: Option<SearchProviderConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchProviderConfig {
+    pub api_key: EnvString,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Provider {
+    Brave,
+    SerpApi,
+    Tavily,
+}
+
+impl Provider {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "brave" => Some(Provider::Brave),
+            "serpapi" => Some(Provider::SerpApi),
+            "tavily" => Some(Provider::Tavily),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Provider::Brave => "brave",
+            Provider::SerpApi => "serpapi",
+            Provider::Tavily => "tavily",
+        }
+    }
+
+    fn api_key(self, config: Option<&SearchConfig>) -> Option<String> {
+        let (configured, env_var) = match self {
+            Provider::Brave => (config.and_then(|c| c.brave.as_ref()), "BRAVE_API_KEY"),
+            Provider::SerpApi => (config.and_then(|c| c.serpapi.as_ref()), "SERPAPI_API_KEY"),
+            Provider::Tavily => (config.and_then(|c| c.tavily.as_ref()), "TAVILY_API_KEY"),
+        };
+        configured
+            .map(|provider| provider.api_key.to_string())
+            .or_else(|| env::var(env_var).ok())
+            // An unresolved `$VAR` means the environment variable isn't set
+            .filter(|key| !key.is_empty() && !key.starts_with('$'))
+    }
+
+    async fn search(self, api_key: &str, query: &str, count: usize) -> Result<Json, String> {
+        let client = reqwest::Client::new();
+        let request = match self {
+            Provider::Brave => client
+                .get("https://api.search.brave.com/res/v1/web/search")
+                .header("X-Subscription-Token", api_key)
+                .query(&[("q", query), ("count", &count.to_string())]),
+            Provider::SerpApi => client.get("https://serpapi.com/search.json").query(&[
+                ("engine", "google"),
+                ("q", query),
+                ("num", &count.to_string()),
+                ("api_key", api_key),
+            ]),
+            Provider::Tavily => client
+                .post("https://api.tavily.com/search")
+                .bearer_auth(api_key)
+                .json(&json!({ "query": query, "max_results": count })),
+        };
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("{} returned {status}", self.name()));
+        }
+        response.json().await.map_err(|e| e.to_string())
+    }
+
+    // Normalize the provider response into a list of `{title, url, snippet}`.
+    fn parse_results(self, response: &Json, count: usize) -> Vec<Json> {
+        let (results, url, snippet) = match self {
+            Provider::Brave => (&response["web"]["results"], "url", "description"),
+            Provider::SerpApi => (&response["organic_results"], "link", "snippet"),
+            Provider::Tavily => (&response["results"], "url", "content"),
+        };
+        results
+            .as_array()
+            .map(|results| {
+                results
+                    .iter()
+                    .take(count)
+                    .map(|result| {
+                        let field = |name: &str| result[name].as_str().unwrap_or_default();
+                        json!({
+                            "title": field("title"),
+                            "url": field(url),
+                            "snippet": field(snippet),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+pub fn create_search_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern(b"std.search");
+
+    let exports = [("web", Value::NativeFunction(NativeFn(search_web)))]
+        .into_iter()
+        .map(|(name, f)| (ctx.intern_static(name), f))
+        .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// web(query, provider="brave", count=5)
+fn search_web<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let query = string_arg!(&args, 0, "web")?.to_string();
+    let config = state.ai_config.search.as_ref();
+    let mut provider = config
+        .and_then(|config| config.provider.clone())
+        .unwrap_or_else(|| DEFAULT_PROVIDER.to_owned());
+    let mut count = DEFAULT_COUNT;
+
+    // The provider can be passed positionally, other options are keyword arguments.
+    let mut options = &args[1..];
+    if options.len() % 2 == 1 {
+        provider = options[0].as_string()?.to_string();
+        options = &options[1..];
+    }
+    for option in options.chunks(2) {
+        match (option[0].as_string()?.to_str().unwrap(), option[1]) {
+            ("provider", value) => provider = value.as_string()?.to_string(),
+            ("count", value) => match value.as_exact_int() {
+                Some(n) if n >= 1 => count = n as usize,
+                _ => {
+                    return Err(VmError::RuntimeError(
+                        "web: count must be a positive number".into(),
+                    ));
+                }
+            },
+            (name, _) => {
+                return Err(VmError::RuntimeError(format!(
+                    "web: unknown argument '{name}'"
+                )));
+            }
+        }
+    }
+
+    let provider = Provider::from_name(&provider).ok_or_else(|| {
+        VmError::RuntimeError(format!(
+            "web: unknown search provider '{provider}', expect one of brave, serpapi or tavily"
+        ))
+    })?;
+    let api_key = provider.api_key(config).ok_or_else(|| {
+        VmError::RuntimeError(format!(
+            "web: no API key configured for search provider '{}'",
+            provider.name()
+        ))
+    })?;
+
+    let response = Handle::current()
+        .block_on(provider.search(&api_key, &query, count))
+        .map_err(|e| VmError::RuntimeError(format!("web: search request failed: {e}")))?;
+    let results = provider.parse_results(&response, count);
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &Json::Array(results),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_results() {
+        let brave = json!({
+            "web": { "results": [
+                { "title": "Rust", "url": "https://rust-lang.org", "description": "A language" },
+                { "title": "Crates", "url": "https://crates.io", "description": "Registry" },
+            ]}
+        });
+        assert_eq!(
+            Provider::Brave.parse_results(&brave, 1),
+            vec![
+                json!({ "title": "Rust", "url": "https://rust-lang.org", "snippet": "A language" })
+            ]
+        );
+
+        let serpapi = json!({
+            "organic_results": [{ "title": "Rust", "link": "https://rust-lang.org", "snippet": "A language" }]
+        });
+        assert_eq!(
+            Provider::SerpApi.parse_results(&serpapi, 5),
+            vec![
+                json!({ "title": "Rust", "url": "https://rust-lang.org", "snippet": "A language" })
+            ]
+        );
+
+        let tavily = json!({
+            "results": [{ "title": "Rust", "url": "https://rust-lang.org", "content": "A language" }]
+        });
+        assert_eq!(
+            Provider::Tavily.parse_results(&tavily, 5),
+            vec![
+                json!({ "title": "Rust", "url": "https://rust-lang.org", "snippet": "A language" })
+            ]
+        );
+
+        assert!(Provider::Brave.parse_results(&json!({}), 5).is_empty());
+    }
+
+    #[test]
+    fn test_api_key() {
+        let config = SearchConfig {
+            tavily: Some(SearchProviderConfig {
+                api_key: EnvString("key".into()),
+            }),
+            serpapi: Some(SearchProviderConfig {
+                api_key: EnvString("$AISCRIPT_TEST_UNSET_SEARCH_KEY".into()),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            Provider::Tavily.api_key(Some(&config)),
+            Some("key".to_owned())
+        );
+        assert_eq!(Provider::SerpApi.api_key(Some(&config)), None);
+    }
+}
@@ -5,7 +5,7 @@ use crate::{
     NativeFn, Value, VmError,
     module::ModuleKind,
     string_arg,
-    vm::{Context, State},
+    vm::{Capability, Context, State},
 };
 
 pub fn create_serde_module(ctx: Context) -> ModuleKind {
@@ -31,9 +31,9 @@ fn serde_from_str<'gc>(
     state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
-    if args.is_empty() || args.len() > 1 {
+    if args.is_empty() || args.len() > 2 {
         return Err(VmError::RuntimeError(
-            "from_str() takes exactly 1 argument".into(),
+            "from_str() takes 1 or 2 arguments".into(),
         ));
     }
 
@@ -43,12 +43,11 @@ fn serde_from_str<'gc>(
     let parsed = serde_json::from_str(json_str.to_str().unwrap())
         .map_err(|e| VmError::RuntimeError(format!("Failed to parse JSON: {}", e)))?;
 
-    // Convert serde_json::Value to AIScript Value
-    Ok(Value::from_serde_value(state.get_context(), &parsed))
+    from_json_value(state, &parsed, args.get(1), "from_str")
 }
 
 fn serde_to_str<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
-    let (positional, keyword) = extract_keyword_args(&args)?;
+    let (positional, keyword) = extract_keyword_args(&args, &["pretty"])?;
 
     if positional.len() != 1 {
         return Err(VmError::RuntimeError(
@@ -70,7 +69,7 @@ fn serde_to_str<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Va
     };
 
     // Convert AIScript Value to JSON value
-    let json_value = to_json_value(&positional[0])?;
+    let json_value = to_json_value(state, &positional[0])?;
 
     // Convert to string with appropriate formatting
     let result = if pretty {
@@ -87,11 +86,12 @@ fn serde_from_file<'gc>(
     state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
-    if args.len() != 1 {
+    if args.is_empty() || args.len() > 2 {
         return Err(VmError::RuntimeError(
-            "from_file() takes exactly 1 argument".into(),
+            "from_file() takes 1 or 2 arguments".into(),
         ));
     }
+    state.require(Capability::Fs)?;
 
     let path = string_arg!(&args, 0, "from_file")?;
 
@@ -103,22 +103,38 @@ fn serde_from_file<'gc>(
     let parsed = serde_json::from_str(&content)
         .map_err(|e| VmError::RuntimeError(format!("Failed to parse JSON from file: {}", e)))?;
 
-    // Convert to AIScript Value
-    Ok(Value::from_serde_value(state.get_context(), &parsed))
+    from_json_value(state, &parsed, args.get(1), "from_file")
+}
+
+// Convert the parsed JSON to a value, an instance of the class if given.
+pub(super) fn from_json_value<'gc>(
+    state: &mut State<'gc>,
+    parsed: &serde_json::Value,
+    class: Option<&Value<'gc>>,
+    function: &str,
+) -> Result<Value<'gc>, VmError> {
+    match class {
+        None => Ok(Value::from_serde_value(state.get_context(), parsed)),
+        Some(Value::Class(class)) => state.instance_from_json(*class, parsed),
+        Some(_) => Err(VmError::RuntimeError(format!(
+            "{function}() second argument must be a class."
+        ))),
+    }
 }
 
 fn serde_to_file<'gc>(
-    _state: &mut State<'gc>,
+    state: &mut State<'gc>,
     args: Vec<Value<'gc>>,
 ) -> Result<Value<'gc>, VmError> {
     // First extract keyword args
-    let (positional, keyword) = extract_keyword_args(&args)?;
+    let (positional, keyword) = extract_keyword_args(&args, &["pretty"])?;
 
     if positional.len() != 2 {
         return Err(VmError::RuntimeError(
             "to_file() requires path and value arguments".into(),
         ));
     }
+    state.require(Capability::Fs)?;
 
     let path = string_arg!(&positional, 0, "to_file")?;
 
@@ -136,7 +152,7 @@ fn serde_to_file<'gc>(
     };
 
     // Convert AIScript Value to JSON value
-    let json_value = to_json_value(&positional[1])?;
+    let json_value = to_json_value(state, &positional[1])?;
 
     // Serialize to string with appropriate formatting
     let json_str = if pretty {
@@ -153,22 +169,42 @@ fn serde_to_file<'gc>(
     Ok(Value::Boolean(true))
 }
 
-// Helper function to convert AIScript Value to serde_json::Value
-fn to_json_value(value: &Value) -> Result<serde_json::Value, VmError> {
+// Helper function to convert AIScript Value to serde_json::Value,
+// the instances are converted by their `to_json()` method if any.
+pub(crate) fn to_json_value<'gc>(
+    state: &mut State<'gc>,
+    value: &Value<'gc>,
+) -> Result<serde_json::Value, VmError> {
     match value {
+        Value::Instance(_) | Value::EnumVariant(_) => state.jsonify(*value),
         Value::Object(obj) => {
             let mut map = serde_json::Map::new();
             for (k, v) in &obj.borrow().fields {
-                map.insert(k.to_string(), to_json_value(v)?);
+                map.insert(k.to_string(), to_json_value(state, v)?);
             }
             Ok(serde_json::Value::Object(map))
         }
+        Value::Dict(dict) => {
+            let mut map = serde_json::Map::new();
+            for (k, v) in dict.borrow().iter() {
+                map.insert(k.to_string(), to_json_value(state, &v)?);
+            }
+            Ok(serde_json::Value::Object(map))
+        }
+        Value::Set(set) => {
+            let values: Result<Vec<_>, _> = set
+                .borrow()
+                .iter()
+                .map(|v| to_json_value(state, &v))
+                .collect();
+            Ok(serde_json::Value::Array(values?))
+        }
         Value::List(list) => {
             let values: Result<Vec<_>, _> = list
                 .borrow()
                 .data
                 .iter()
-                .map(|v| to_json_value(v))
+                .map(|v| to_json_value(state, v))
                 .collect();
             Ok(serde_json::Value::Array(values?))
         }
@@ -176,6 +212,9 @@ fn to_json_value(value: &Value) -> Result<serde_json::Value, VmError> {
             serde_json::Number::from_f64(*n)
                 .ok_or_else(|| VmError::RuntimeError("Invalid number value for JSON".into()))?,
         )),
+        Value::Int(_) | Value::Decimal(_) | Value::DateTime(_) | Value::Bytes(_) => {
+            Ok(value.to_serde_value())
+        }
         Value::String(s) => Ok(serde_json::Value::String(s.to_string())),
         Value::IoString(s) => Ok(serde_json::Value::String(s.to_string())),
         Value::Boolean(b) => Ok(serde_json::Value::Bool(*b)),
@@ -187,8 +226,9 @@ fn to_json_value(value: &Value) -> Result<serde_json::Value, VmError> {
 }
 
 // Helper function to extract keyword arguments from args vector
-fn extract_keyword_args<'gc>(
+pub(crate) fn extract_keyword_args<'gc>(
     args: &[Value<'gc>],
+    names: &[&str],
 ) -> Result<
     (
         Vec<Value<'gc>>,
@@ -203,9 +243,12 @@ fn extract_keyword_args<'gc>(
     while i < args.len() {
         match (&args[i], args.get(i + 1)) {
             (Value::String(key), Some(value)) if i < args.len() - 1 => {
-                // Check if this is a key-value pair for a named argument
-                if key.to_str().unwrap() == "pretty" {
-                    keyword.insert("pretty".to_string(), *value);
+                // Check if this is a key-value pair for a named argument,
+                // a binary string is a positional argument
+                if let Ok(key) = key.to_str()
+                    && names.contains(&key)
+                {
+                    keyword.insert(key.to_string(), *value);
                     i += 2;
                     continue;
                 }
@@ -0,0 +1,195 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use minijinja::{AutoEscape, Environment, Error, ErrorKind, default_auto_escape_callback};
+use serde_json::Value as Json;
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Capability, Context, State, run_blocking},
+};
+
+use super::serde::{extract_keyword_args, to_json_value};
+
+/// The directory of the templates unless set by `[template]` in project.toml.
+pub const DEFAULT_TEMPLATE_DIR: &str = "templates";
+
+// The name of the template given as a string to `render()`.
+const INLINE_TEMPLATE: &str = "<template>";
+
+static TEMPLATE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// The directory of the files of `render_file()` and of the includes,
+/// [`DEFAULT_TEMPLATE_DIR`] by default.
+pub fn set_template_dir(dir: PathBuf) {
+    *TEMPLATE_DIR.lock().unwrap() = Some(dir);
+}
+
+fn template_dir() -> PathBuf {
+    TEMPLATE_DIR
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_TEMPLATE_DIR))
+}
+
+pub fn create_template_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.template");
+
+    let exports = [
+        ("render", Value::NativeFunction(NativeFn(template_render))),
+        (
+            "render_file",
+            Value::NativeFunction(NativeFn(template_render_file)),
+        ),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+// The environment of a render, the templates are loaded from the template
+// directory unless the file system is disabled by the sandbox. The block
+// tags take their whole line, so the loops and conditionals of a prompt
+// don't leave blank lines.
+fn environment(allow_fs: bool, autoescape: bool) -> Environment<'static> {
+    let mut env = Environment::new();
+    env.set_trim_blocks(true);
+    env.set_lstrip_blocks(true);
+    env.set_auto_escape_callback(move |name| match name {
+        INLINE_TEMPLATE if autoescape => AutoEscape::Html,
+        INLINE_TEMPLATE => AutoEscape::None,
+        name => default_auto_escape_callback(name),
+    });
+    if allow_fs {
+        env.set_loader(minijinja::path_loader(template_dir()));
+    } else {
+        env.set_loader(|_| {
+            Err(Error::new(
+                ErrorKind::InvalidOperation,
+                format!("{} is disabled by the sandbox", Capability::Fs),
+            ))
+        });
+    }
+    env
+}
+
+// The variables of the template, an object or nil.
+fn data_arg<'gc>(
+    state: &mut State<'gc>,
+    data: Option<&Value<'gc>>,
+    fn_name: &str,
+) -> Result<Json, VmError> {
+    match data {
+        None | Some(Value::Nil) => Ok(Json::Object(Default::default())),
+        Some(data) => match to_json_value(state, data)? {
+            data @ Json::Object(_) => Ok(data),
+            _ => Err(VmError::RuntimeError(format!(
+                "{fn_name}() data must be an object."
+            ))),
+        },
+    }
+}
+
+fn render_error(e: Error) -> VmError {
+    let mut message = format!("Failed to render the template: {e}");
+    if let Some(source) = std::error::Error::source(&e) {
+        message.push_str(&format!(": {source}"));
+    }
+    VmError::RuntimeError(message)
+}
+
+/// Render the template with the variables of the data. The template is
+/// Jinja: `{{ name }}`, `{% for x in xs %}`, `{% if x %}`, the filters
+/// like `{{ name | upper }}` and `{% include "file" %}` of the files of the
+/// template directory. The values are HTML escaped if `autoescape` is true,
+/// `| safe` marks a value as already escaped.
+///
+/// fn render(template, data = {}, autoescape = false) {}
+fn template_render<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["data", "autoescape"])?;
+    if positional.is_empty() || positional.len() > 3 {
+        return Err(VmError::RuntimeError(
+            "render() takes 1 to 3 arguments: the template, the data and autoescape.".into(),
+        ));
+    }
+    let source = string_arg!(&positional, 0, "render")?.to_string();
+    let data = data_arg(state, positional.get(1).or(keyword.get("data")), "render")?;
+    let autoescape = match positional.get(2).or(keyword.get("autoescape")) {
+        None | Some(Value::Nil) => false,
+        Some(Value::Boolean(autoescape)) => *autoescape,
+        Some(_) => {
+            return Err(VmError::RuntimeError(
+                "render() autoescape must be a boolean.".into(),
+            ));
+        }
+    };
+    let env = environment(state.allows(Capability::Fs), autoescape);
+    let output = run_blocking(state, move || {
+        env.render_named_str(INLINE_TEMPLATE, &source, data)
+    })?
+    .map_err(render_error)?;
+    Ok(Value::String(state.intern(output.as_bytes())))
+}
+
+/// Render the file of the template directory, e.g. `emails/welcome.html`,
+/// with the variables of the data. The values are HTML escaped in the
+/// `.html`, `.htm` and `.xml` files.
+///
+/// fn render_file(name, data = {}) {}
+fn template_render_file<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["data"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "render_file() takes 1 or 2 arguments: the name and the data.".into(),
+        ));
+    }
+    state.require(Capability::Fs)?;
+    let name = string_arg!(&positional, 0, "render_file")?.to_string();
+    let data = data_arg(
+        state,
+        positional.get(1).or(keyword.get("data")),
+        "render_file",
+    )?;
+    let env = environment(true, false);
+    let output = run_blocking(state, move || env.get_template(&name)?.render(data))?
+        .map_err(render_error)?;
+    Ok(Value::String(state.intern(output.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_environment() {
+        let data = serde_json::json!({"name": "<b>Ann</b>", "items": [1, 2]});
+        let render =
+            |env: Environment, source| env.render_named_str(INLINE_TEMPLATE, source, &data);
+
+        let source = "Hi {{ name }}\n{% for item in items %}\n- {{ item }}\n{% endfor %}\n";
+        assert_eq!(
+            render(environment(false, false), source).unwrap(),
+            "Hi <b>Ann</b>\n- 1\n- 2\n"
+        );
+        assert_eq!(
+            render(environment(false, true), "{{ name }} {{ name | safe }}").unwrap(),
+            "&lt;b&gt;Ann&lt;&#x2f;b&gt; <b>Ann</b>"
+        );
+        let err = render(environment(false, false), "{% include \"a.txt\" %}").unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("File system access is disabled by the sandbox"),
+            "{err}"
+        );
+    }
+}
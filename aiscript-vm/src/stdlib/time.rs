@@ -1,17 +1,22 @@
 use aiscript_arena::Gc;
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 use std::{
+    sync::LazyLock,
     thread,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use tokio::runtime::Handle;
 
 use crate::{
     NativeFn, Value, VmError, float_arg,
     module::ModuleKind,
     string_arg,
-    vm::{Context, State},
+    vm::{Context, State, block_on, with_deadline},
 };
 
+// The origin of the monotonic clock, the first time it's read.
+static MONOTONIC_ORIGIN: LazyLock<Instant> = LazyLock::new(Instant::now);
+
 pub fn create_time_module(ctx: Context) -> ModuleKind {
     let name = ctx.intern_static("std.time");
 
@@ -25,6 +30,10 @@ pub fn create_time_module(ctx: Context) -> ModuleKind {
             Value::NativeFunction(NativeFn(time_unix_timestamp)),
         ),
         ("sleep", Value::NativeFunction(NativeFn(time_sleep))),
+        ("after", Value::NativeFunction(NativeFn(time_after))),
+        // Monotonic clock, for measuring durations
+        ("monotonic", Value::NativeFunction(NativeFn(time_monotonic))),
+        ("elapsed", Value::NativeFunction(NativeFn(time_elapsed))),
         // Time conversion functions
         ("to_utc", Value::NativeFunction(NativeFn(time_to_utc))),
         ("to_local", Value::NativeFunction(NativeFn(time_to_local))),
@@ -72,20 +81,88 @@ fn time_unix_timestamp<'gc>(
     Ok(Value::Number(dt.timestamp() as f64))
 }
 
-/// Sleeps for the specified number of seconds
-fn time_sleep<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
-    let seconds = float_arg!(&args, 0, "sleep")?;
+/// Sleeps for the specified number of milliseconds, e.g. between the
+/// attempts of a polling loop. The thread of the VM waits on a timer of the
+/// runtime rather than spinning, and the sleep is cut short with an error
+/// once the deadline of the request has passed.
+///
+/// fn sleep(ms) {}
+fn time_sleep<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let duration = millis_arg(&args, "sleep")?;
+    sleep_for(state, duration)?;
+    Ok(Value::Nil)
+}
 
-    if seconds < 0.0 {
+/// Calls the function with no arguments once the milliseconds have passed,
+/// returns its result, e.g. a retry with backoff.
+///
+/// fn after(ms, callback) {}
+fn time_after<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 2 {
         return Err(VmError::RuntimeError(
-            "sleep: duration cannot be negative".into(),
+            "after: expected 2 arguments, the milliseconds and the function".into(),
         ));
     }
+    let duration = millis_arg(&args, "after")?;
+    let Value::Closure(callback) = args[1] else {
+        return Err(VmError::RuntimeError(
+            "after: argument 2 must be a function".into(),
+        ));
+    };
+    sleep_for(state, duration)?;
+    state.try_eval_closure(callback, &[])
+}
 
-    let duration = Duration::from_secs_f64(seconds);
-    thread::sleep(duration);
+/// Returns the milliseconds of a monotonic clock, which never goes back
+/// unlike `now()`, only the difference of two readings is meaningful.
+///
+/// fn monotonic() {}
+fn time_monotonic<'gc>(
+    _state: &mut State<'gc>,
+    _args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    Ok(Value::Number(
+        MONOTONIC_ORIGIN.elapsed().as_secs_f64() * 1000.0,
+    ))
+}
 
-    Ok(Value::Nil)
+/// Returns the milliseconds elapsed since a reading of `monotonic()`.
+///
+/// fn elapsed(start) {}
+fn time_elapsed<'gc>(
+    _state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let start = float_arg!(&args, 0, "elapsed")?;
+    let now = MONOTONIC_ORIGIN.elapsed().as_secs_f64() * 1000.0;
+    Ok(Value::Number(now - start))
+}
+
+// The non-negative milliseconds of the first argument as a duration.
+fn millis_arg(args: &[Value], fn_name: &str) -> Result<Duration, VmError> {
+    let ms = float_arg!(args, 0, fn_name)?;
+    if ms < 0.0 || !ms.is_finite() {
+        return Err(VmError::RuntimeError(format!(
+            "{fn_name}: duration must be a non-negative number of milliseconds"
+        )));
+    }
+    Duration::try_from_secs_f64(ms / 1000.0)
+        .map_err(|_| VmError::RuntimeError(format!("{fn_name}: duration is too large")))
+}
+
+// Wait on a timer of the runtime within the deadline of the request, the
+// thread sleeps when there is no runtime, e.g. in the tests.
+pub(crate) fn sleep_for(state: &State, duration: Duration) -> Result<(), VmError> {
+    match Handle::try_current() {
+        Ok(handle) => block_on(
+            &handle,
+            with_deadline(state.deadline, tokio::time::sleep(duration)),
+        ),
+        Err(_) => {
+            thread::sleep(duration);
+            Ok(())
+        }
+    }
 }
 
 /// Converts a timestamp to UTC datetime string
@@ -175,3 +252,32 @@ fn time_days<'gc>(_state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Valu
     let days = float_arg!(&args, 0, "days")?;
     Ok(Value::Number(days * 86400.0))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{Vm, VmError};
+
+    #[test]
+    fn test_sleep_deadline() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _runtime = runtime.enter();
+        let mut vm = Vm::default();
+        vm.set_deadline(Instant::now() + Duration::from_millis(20));
+        vm.compile("use std.time;\ntime.sleep(5000);").unwrap();
+        let started = Instant::now();
+        assert!(matches!(vm.interpret(), Err(VmError::DeadlineExceeded)));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_sleep_in_runtime() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut vm = Vm::default();
+            vm.compile("use std.time;\ntime.sleep(10);").unwrap();
+            assert!(vm.interpret().is_ok());
+        });
+    }
+}
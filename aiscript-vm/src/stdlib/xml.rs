@@ -0,0 +1,703 @@
+use serde_json::{Map, Value as Json, json};
+
+use crate::{
+    NativeFn, Value, VmError,
+    module::ModuleKind,
+    string_arg,
+    vm::{Context, State},
+};
+
+use super::serde::{extract_keyword_args, to_json_value};
+
+pub fn create_xml_module(ctx: Context) -> ModuleKind {
+    let name = ctx.intern_static("std.xml");
+
+    let exports = [
+        ("parse", Value::NativeFunction(NativeFn(xml_parse))),
+        ("stringify", Value::NativeFunction(NativeFn(xml_stringify))),
+        ("element", Value::NativeFunction(NativeFn(xml_element))),
+        ("find", Value::NativeFunction(NativeFn(xml_find))),
+        ("find_all", Value::NativeFunction(NativeFn(xml_find_all))),
+        ("text", Value::NativeFunction(NativeFn(xml_text))),
+    ]
+    .into_iter()
+    .map(|(name, f)| (ctx.intern_static(name), f))
+    .collect();
+
+    ModuleKind::Native { name, exports }
+}
+
+/// Parse the XML document into its root element, an object of its `name`
+/// (with its namespace prefix if any), `attrs` and `children`, the child
+/// elements and text strings. Comments, processing instructions and the
+/// whitespace between elements are dropped, DTDs are rejected.
+///
+/// fn parse(text) {}
+fn xml_parse<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("parse() takes 1 argument.".into()));
+    }
+    let text = string_arg!(&args, 0, "parse")?;
+    let document = roxmltree::Document::parse(text.to_str().unwrap())
+        .map_err(|e| VmError::RuntimeError(format!("Failed to parse XML: {e}")))?;
+    let root = element_json(document.root_element());
+    Ok(Value::from_serde_value(state.get_context(), &root))
+}
+
+/// The XML of the element, indented by the number of spaces if given.
+///
+/// fn stringify(element, indent = nil) {}
+fn xml_stringify<'gc>(
+    state: &mut State<'gc>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["indent"])?;
+    if positional.is_empty() || positional.len() > 2 {
+        return Err(VmError::RuntimeError(
+            "stringify() takes 1 or 2 arguments.".into(),
+        ));
+    }
+    let indent = match positional.get(1).or(keyword.get("indent")) {
+        None | Some(Value::Nil) => None,
+        Some(value) => match value.as_exact_int() {
+            Some(n @ 0..=16) => Some(n as usize),
+            _ => {
+                return Err(VmError::RuntimeError(
+                    "stringify() indent must be a number of spaces from 0 to 16.".into(),
+                ));
+            }
+        },
+    };
+    let element = to_json_value(state, &positional[0])?;
+    let mut out = String::new();
+    write_element(&element, indent, 0, &mut out)
+        .map_err(|message| VmError::RuntimeError(format!("stringify() {message}")))?;
+    Ok(Value::String(state.intern(out.as_bytes())))
+}
+
+/// An element to build a document with, the children are elements or
+/// strings.
+///
+/// fn element(name, attrs = {}, children = []) {}
+fn xml_element<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["attrs", "children"])?;
+    if positional.is_empty() || positional.len() > 3 {
+        return Err(VmError::RuntimeError(
+            "element() takes 1 to 3 arguments: the name, the attributes and the children.".into(),
+        ));
+    }
+    let name = positional[0].as_string()?.to_string();
+    let attrs = match positional.get(1).or(keyword.get("attrs")) {
+        None | Some(Value::Nil) => Json::Object(Map::new()),
+        Some(value) => to_json_value(state, value)?,
+    };
+    let children = match positional.get(2).or(keyword.get("children")) {
+        None | Some(Value::Nil) => Json::Array(Vec::new()),
+        Some(value) => to_json_value(state, value)?,
+    };
+    let element = json!({"name": name, "attrs": attrs, "children": children});
+    // Fail early rather than when the document is written
+    write_element(&element, None, 0, &mut String::new())
+        .map_err(|message| VmError::RuntimeError(format!("element() {message}")))?;
+    Ok(Value::from_serde_value(state.get_context(), &element))
+}
+
+/// The first match of the path in the element, nil if there is none.
+///
+/// fn find(element, path) {}
+fn xml_find<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let matches = query(state, &args, "find")?;
+    Ok(matches.first().map_or(Value::Nil, |value| {
+        Value::from_serde_value(state.get_context(), value)
+    }))
+}
+
+/// All the matches of the path in the element. The path is a subset of
+/// XPath: `/rss/channel/item`, `//item`, `item/title`, `*`, `.`, `..` is not
+/// supported. A step may have predicates: `[2]` (from 1), `[last()]`,
+/// `[@attr]`, `[@attr='value']`, `[child]` and `[child='text']`. A path may
+/// end with `@attr` or `text()` to select the values instead of the elements.
+///
+/// fn find_all(element, path) {}
+fn xml_find_all<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    let matches = query(state, &args, "find_all")?;
+    let ctx = state.get_context();
+    let values = matches
+        .iter()
+        .map(|value| Value::from_serde_value(ctx, value))
+        .collect();
+    Ok(Value::array(&ctx, values))
+}
+
+/// The text of the element and its descendants.
+///
+/// fn text(element) {}
+fn xml_text<'gc>(state: &mut State<'gc>, args: Vec<Value<'gc>>) -> Result<Value<'gc>, VmError> {
+    if args.len() != 1 {
+        return Err(VmError::RuntimeError("text() takes 1 argument.".into()));
+    }
+    let element = to_json_value(state, &args[0])?;
+    let mut text = String::new();
+    collect_text(&element, &mut text);
+    Ok(Value::String(state.intern(text.as_bytes())))
+}
+
+fn query<'gc>(
+    state: &mut State<'gc>,
+    args: &[Value<'gc>],
+    function: &str,
+) -> Result<Vec<Json>, VmError> {
+    if args.len() != 2 {
+        return Err(VmError::RuntimeError(format!(
+            "{function}() takes 2 arguments: the element and the path."
+        )));
+    }
+    let path = string_arg!(args, 1, function)?;
+    let path = parse_path(path.to_str().unwrap()).map_err(|message| {
+        VmError::RuntimeError(format!("Invalid XML path '{path}': {message}"))
+    })?;
+    let element = to_json_value(state, &args[0])?;
+    Ok(select(&element, &path).into_iter().cloned().collect())
+}
+
+fn element_json(node: roxmltree::Node) -> Json {
+    let mut attrs = Map::new();
+    // The namespaces declared by the element, not inherited from its parent
+    let inherited = node
+        .parent_element()
+        .map(|parent| parent.namespaces().collect::<Vec<_>>())
+        .unwrap_or_default();
+    for namespace in node.namespaces() {
+        if !inherited.contains(&namespace) {
+            let name = match namespace.name() {
+                Some(prefix) => format!("xmlns:{prefix}"),
+                None => "xmlns".to_owned(),
+            };
+            attrs.insert(name, namespace.uri().into());
+        }
+    }
+    for attr in node.attributes() {
+        let name = qualified_name(node, attr.namespace(), attr.name());
+        attrs.insert(name, attr.value().into());
+    }
+    let children: Vec<_> = node
+        .children()
+        .filter_map(|child| {
+            if child.is_element() {
+                Some(element_json(child))
+            } else {
+                child
+                    .text()
+                    .filter(|text| child.is_text() && !text.trim().is_empty())
+                    .map(Json::from)
+            }
+        })
+        .collect();
+    let name = qualified_name(node, node.tag_name().namespace(), node.tag_name().name());
+    json!({"name": name, "attrs": attrs, "children": children})
+}
+
+// The name with the prefix of its namespace, e.g. `atom:link`.
+fn qualified_name(node: roxmltree::Node, namespace: Option<&str>, name: &str) -> String {
+    match namespace.and_then(|uri| node.lookup_prefix(uri)) {
+        Some(prefix) if !prefix.is_empty() => format!("{prefix}:{name}"),
+        _ => name.to_owned(),
+    }
+}
+
+fn write_element(
+    element: &Json,
+    indent: Option<usize>,
+    depth: usize,
+    out: &mut String,
+) -> Result<(), String> {
+    let name = element
+        .get("name")
+        .and_then(Json::as_str)
+        .filter(|name| is_name(name))
+        .ok_or_else(|| format!("expected an element with a valid name, got {element}"))?;
+    out.push('<');
+    out.push_str(name);
+    match element.get("attrs") {
+        None | Some(Json::Null) => {}
+        Some(Json::Object(attrs)) => {
+            for (key, value) in attrs {
+                if !is_name(key) {
+                    return Err(format!("got an invalid attribute name '{key}'"));
+                }
+                out.push_str(&format!(" {key}=\""));
+                escape(&scalar(value)?, true, out);
+                out.push('"');
+            }
+        }
+        Some(attrs) => return Err(format!("expected the attributes as an object, got {attrs}")),
+    }
+    let children = match element.get("children") {
+        None | Some(Json::Null) => &Vec::new(),
+        Some(Json::Array(children)) => children,
+        Some(children) => return Err(format!("expected the children as an array, got {children}")),
+    };
+    if children.is_empty() {
+        out.push_str("/>");
+        return Ok(());
+    }
+    out.push('>');
+    // Mixed content is kept on one line, its whitespace is significant
+    let indent = indent.filter(|_| children.iter().all(Json::is_object));
+    for child in children {
+        if let Some(indent) = indent {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent * (depth + 1)));
+        }
+        match child {
+            Json::Object(_) => write_element(child, indent, depth + 1, out)?,
+            child => escape(&scalar(child)?, false, out),
+        }
+    }
+    if let Some(indent) = indent {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+    out.push_str(&format!("</{name}>"));
+    Ok(())
+}
+
+fn scalar(value: &Json) -> Result<String, String> {
+    match value {
+        Json::String(s) => Ok(s.clone()),
+        Json::Number(n) => Ok(n.to_string()),
+        Json::Bool(b) => Ok(b.to_string()),
+        value => Err(format!("expected a string, got {value}")),
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'))
+}
+
+fn escape(text: &str, in_attr: bool, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if in_attr => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn collect_text(node: &Json, out: &mut String) {
+    match node {
+        Json::String(text) => out.push_str(text),
+        Json::Object(element) => {
+            if let Some(Json::Array(children)) = element.get("children") {
+                for child in children {
+                    collect_text(child, out);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Axis {
+    Child,
+    // `//`, the descendants and the node itself
+    Descendant,
+}
+
+#[derive(Debug, PartialEq)]
+enum Test {
+    // An element by name, any element for `*`
+    Element(Option<String>),
+    // `.`, the node itself
+    Current,
+    Attribute(String),
+    Text,
+}
+
+#[derive(Debug, PartialEq)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Attribute(String, Option<String>),
+    Child(String, Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    axis: Axis,
+    test: Test,
+    predicates: Vec<Predicate>,
+}
+
+struct Path {
+    // A path starting with `/` matches the element as the document root.
+    absolute: bool,
+    steps: Vec<Step>,
+}
+
+fn parse_path(path: &str) -> Result<Path, String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return Err("it's empty".into());
+    }
+    let absolute = path.starts_with('/');
+    let mut rest = path;
+    let mut steps = Vec::new();
+    let mut first = true;
+    while !rest.is_empty() {
+        let axis = if let Some(after) = rest.strip_prefix("//") {
+            rest = after;
+            Axis::Descendant
+        } else if let Some(after) = rest.strip_prefix('/') {
+            rest = after;
+            Axis::Child
+        } else if first {
+            Axis::Child
+        } else {
+            return Err(format!("expected '/' before '{rest}'"));
+        };
+        first = false;
+        let end = step_end(rest)?;
+        let (step, after) = rest.split_at(end);
+        rest = after;
+        if let Some(last) = steps.last()
+            && matches!(
+                last,
+                Step {
+                    test: Test::Attribute(_) | Test::Text,
+                    ..
+                }
+            )
+        {
+            return Err("@attr and text() must be the last step".into());
+        }
+        steps.push(parse_step(step, axis)?);
+    }
+    Ok(Path { absolute, steps })
+}
+
+// The end of the step at the start of the path, its predicates included.
+fn step_end(path: &str) -> Result<usize, String> {
+    let mut quote = None;
+    let mut depth = 0;
+    for (i, c) in path.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, '/') if depth == 0 => return Ok(i),
+            _ => {}
+        }
+    }
+    if quote.is_some() || depth != 0 {
+        return Err("unclosed '[' or quote".into());
+    }
+    Ok(path.len())
+}
+
+fn parse_step(step: &str, axis: Axis) -> Result<Step, String> {
+    let (test, mut rest) = step.split_at(step.find('[').unwrap_or(step.len()));
+    let test = match test.trim() {
+        "" => return Err("expected a name".into()),
+        "*" => Test::Element(None),
+        "." => Test::Current,
+        "text()" => Test::Text,
+        name => match name.strip_prefix('@') {
+            Some(attr) if is_name(attr) => Test::Attribute(attr.to_owned()),
+            None if is_name(name) => Test::Element(Some(name.to_owned())),
+            _ => return Err(format!("invalid step '{name}'")),
+        },
+    };
+    let mut predicates = Vec::new();
+    while let Some(after) = rest.strip_prefix('[') {
+        let end = find_close(after).ok_or("unclosed '['")?;
+        predicates.push(parse_predicate(after[..end].trim())?);
+        rest = after[end + 1..].trim_start();
+    }
+    if !rest.trim().is_empty() {
+        return Err(format!("unexpected '{rest}'"));
+    }
+    if !predicates.is_empty() && !matches!(test, Test::Element(_)) {
+        return Err("predicates are only allowed on elements".into());
+    }
+    Ok(Step {
+        axis,
+        test,
+        predicates,
+    })
+}
+
+// The index of the ']' closing the predicate, skipping the quoted strings.
+fn find_close(predicate: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in predicate.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ']') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_predicate(predicate: &str) -> Result<Predicate, String> {
+    if predicate == "last()" {
+        return Ok(Predicate::Last);
+    }
+    if let Ok(position) = predicate.parse::<usize>() {
+        return match position {
+            0 => Err("positions start from 1".into()),
+            position => Ok(Predicate::Position(position)),
+        };
+    }
+    let (name, value) = match predicate.split_once('=') {
+        Some((name, value)) => {
+            let value = value.trim();
+            let unquoted = value
+                .strip_prefix('\'')
+                .and_then(|v| v.strip_suffix('\''))
+                .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+                .ok_or_else(|| format!("expected a quoted value, got '{value}'"))?;
+            (name.trim(), Some(unquoted.to_owned()))
+        }
+        None => (predicate, None),
+    };
+    match name.strip_prefix('@') {
+        Some(attr) if is_name(attr) => Ok(Predicate::Attribute(attr.to_owned(), value)),
+        None if is_name(name) => Ok(Predicate::Child(name.to_owned(), value)),
+        _ => Err(format!("invalid predicate '{predicate}'")),
+    }
+}
+
+fn children(element: &Json) -> impl Iterator<Item = &Json> {
+    element
+        .get("children")
+        .and_then(Json::as_array)
+        .into_iter()
+        .flatten()
+}
+
+fn element_name(element: &Json) -> Option<&str> {
+    element.get("name").and_then(Json::as_str)
+}
+
+fn select<'a>(root: &'a Json, path: &Path) -> Vec<&'a Json> {
+    // The document holding the element, so that `/name` matches the element
+    let document = [root];
+    let mut current: Vec<&Json> = vec![root];
+    let mut steps = path.steps.iter();
+    if path.absolute
+        && let Some(step) = steps.next()
+    {
+        current = match step.axis {
+            Axis::Child => filter(document.to_vec(), step),
+            Axis::Descendant => {
+                let mut nodes = Vec::new();
+                descendants(root, &mut nodes);
+                filter(nodes, step)
+            }
+        };
+    }
+    for step in steps {
+        let nodes = current
+            .into_iter()
+            .flat_map(|node| match step.axis {
+                // The attributes are the ones of the node itself
+                Axis::Child if matches!(step.test, Test::Current | Test::Attribute(_)) => {
+                    vec![node]
+                }
+                Axis::Child => children(node).collect(),
+                Axis::Descendant => {
+                    let mut nodes = Vec::new();
+                    descendants(node, &mut nodes);
+                    nodes
+                }
+            })
+            .collect();
+        current = filter(nodes, step);
+    }
+    current
+}
+
+// The node and the elements and texts below it, in document order.
+fn descendants<'a>(node: &'a Json, out: &mut Vec<&'a Json>) {
+    out.push(node);
+    for child in children(node) {
+        descendants(child, out);
+    }
+}
+
+// The nodes passing the test and the predicates of the step, or the
+// values of the attributes or texts it selects.
+fn filter<'a>(nodes: Vec<&'a Json>, step: &Step) -> Vec<&'a Json> {
+    match &step.test {
+        Test::Current => nodes,
+        Test::Text => nodes.into_iter().filter(|node| node.is_string()).collect(),
+        Test::Attribute(attr) => nodes
+            .into_iter()
+            .filter_map(|node| node.get("attrs").and_then(|attrs| attrs.get(attr)))
+            .collect(),
+        Test::Element(name) => {
+            let mut matches: Vec<_> = nodes
+                .into_iter()
+                .filter(|node| {
+                    node.is_object()
+                        && name
+                            .as_deref()
+                            .is_none_or(|name| element_name(node) == Some(name))
+                })
+                .collect();
+            for predicate in &step.predicates {
+                matches = match predicate {
+                    Predicate::Position(position) => {
+                        matches.get(position - 1).copied().into_iter().collect()
+                    }
+                    Predicate::Last => matches.last().copied().into_iter().collect(),
+                    Predicate::Attribute(attr, value) => matches
+                        .into_iter()
+                        .filter(|node| {
+                            let actual = node.get("attrs").and_then(|attrs| attrs.get(attr));
+                            match value {
+                                None => actual.is_some(),
+                                Some(value) => actual.and_then(Json::as_str) == Some(value),
+                            }
+                        })
+                        .collect(),
+                    Predicate::Child(child, value) => matches
+                        .into_iter()
+                        .filter(|node| {
+                            children(node).any(|node| {
+                                element_name(node) == Some(child)
+                                    && value.as_ref().is_none_or(|value| {
+                                        let mut text = String::new();
+                                        collect_text(node, &mut text);
+                                        &text == value
+                                    })
+                            })
+                        })
+                        .collect(),
+                };
+            }
+            matches
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = r#"<?xml version="1.0"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>News</title>
+    <atom:link href="https://example.com/feed" rel="self"/>
+    <item id="1"><title>First</title><category>rust</category></item>
+    <item id="2"><title>Second &amp; last</title><![CDATA[<b>raw</b>]]></item>
+  </channel>
+</rss>"#;
+
+    fn find(root: &Json, path: &str) -> Vec<Json> {
+        select(root, &parse_path(path).unwrap())
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let document = roxmltree::Document::parse(FEED).unwrap();
+        let root = element_json(document.root_element());
+        assert_eq!(root["name"], "rss");
+        assert_eq!(
+            root["attrs"],
+            json!({"version": "2.0", "xmlns:atom": "http://www.w3.org/2005/Atom"})
+        );
+        let channel = &root["children"][0];
+        assert_eq!(
+            channel["children"][0],
+            json!({"name": "title", "attrs": {}, "children": ["News"]})
+        );
+        assert_eq!(channel["children"][1]["name"], "atom:link");
+        assert_eq!(channel["children"][3]["children"][1], "<b>raw</b>");
+
+        assert!(roxmltree::Document::parse("<!DOCTYPE x [<!ENTITY a 'b'>]><x>&a;</x>").is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let document = roxmltree::Document::parse(FEED).unwrap();
+        let root = element_json(document.root_element());
+        assert_eq!(find(&root, "/rss/channel/item").len(), 2);
+        assert_eq!(find(&root, "/channel").len(), 0);
+        assert_eq!(find(&root, "channel/item/@id"), [json!("1"), json!("2")]);
+        assert_eq!(
+            find(&root, "//item[2]/title/text()"),
+            [json!("Second & last")]
+        );
+        assert_eq!(find(&root, "//item[last()]/@id"), [json!("2")]);
+        assert_eq!(
+            find(&root, "//item[@id='1']/category/text()"),
+            [json!("rust")]
+        );
+        assert_eq!(find(&root, "//item[category]/@id"), [json!("1")]);
+        assert_eq!(find(&root, "//item[title='First']/@id"), [json!("1")]);
+        assert_eq!(
+            find(&root, "//atom:link/@href"),
+            [json!("https://example.com/feed")]
+        );
+        assert_eq!(find(&root, "channel/*").len(), 4);
+        assert_eq!(find(&root, ".")[0]["name"], "rss");
+
+        assert!(parse_path("").is_err());
+        assert!(parse_path("item[").is_err());
+        assert!(parse_path("item[0]").is_err());
+        assert!(parse_path("@id/title").is_err());
+        assert!(parse_path("item[@id=1]").is_err());
+    }
+
+    #[test]
+    fn test_stringify() {
+        let element = json!({
+            "name": "feed",
+            "attrs": {"lang": "en \"us\""},
+            "children": [
+                {"name": "title", "attrs": {}, "children": ["A < B"]},
+                {"name": "empty", "attrs": {}, "children": []},
+            ],
+        });
+        let mut out = String::new();
+        write_element(&element, None, 0, &mut out).unwrap();
+        assert_eq!(
+            out,
+            r#"<feed lang="en &quot;us&quot;"><title>A &lt; B</title><empty/></feed>"#
+        );
+        let mut out = String::new();
+        write_element(&element, Some(2), 0, &mut out).unwrap();
+        assert_eq!(
+            out,
+            "<feed lang=\"en &quot;us&quot;\">\n  <title>A &lt; B</title>\n  <empty/>\n</feed>"
+        );
+        assert!(write_element(&json!({"name": "1x"}), None, 0, &mut String::new()).is_err());
+        assert!(
+            write_element(
+                &json!({"name": "x", "children": [[1]]}),
+                None,
+                0,
+                &mut String::new()
+            )
+            .is_err()
+        );
+    }
+}
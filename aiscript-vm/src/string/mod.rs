@@ -1,8 +1,11 @@
 mod interned;
+mod shared;
 mod utils;
 
 use aiscript_arena::Gc;
 pub use interned::{InternedString, InternedStringSet};
+pub use shared::share_names;
+pub(crate) use shared::shared_name;
 
 use crate::{Value, vm::Context};
 
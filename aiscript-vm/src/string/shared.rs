@@ -0,0 +1,40 @@
+use std::{collections::BTreeSet, sync::RwLock};
+
+// Leaked once, a reload only adds the new names.
+static NAMES: RwLock<BTreeSet<&'static str>> = RwLock::new(BTreeSet::new());
+
+/// Share the names across the VMs of the process, e.g. the field names of
+/// the requests. A VM interns a shared name without copying it, instead of
+/// allocating it again for every request.
+pub fn share_names<'a>(names: impl IntoIterator<Item = &'a str>) {
+    let mut shared = NAMES.write().unwrap();
+    for name in names {
+        if !shared.contains(name) {
+            shared.insert(name.to_owned().leak());
+        }
+    }
+}
+
+/// The shared name equal to the string, if any.
+pub(crate) fn shared_name(s: &[u8]) -> Option<&'static str> {
+    let name = std::str::from_utf8(s).ok()?;
+    NAMES.read().unwrap().get(name).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_share_names() {
+        share_names(["shared_test_name"]);
+        let name = shared_name(b"shared_test_name").unwrap();
+        share_names(["shared_test_name"]);
+        // Not leaked again
+        assert!(std::ptr::eq(
+            name,
+            shared_name(b"shared_test_name").unwrap()
+        ));
+        assert!(shared_name(b"unshared_test_name").is_none());
+    }
+}
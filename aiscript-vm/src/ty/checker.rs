@@ -0,0 +1,1217 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    ast::{
+        AgentDecl, ClassDecl, EnumDecl, ErrorHandler, Expr, FStringPart, FunctionDecl, Literal,
+        MatchPattern, ObjectProperty, ParameterKind, Program, Stmt, VariableDecl, Visibility,
+        WorkflowDecl,
+    },
+    lexer::{Token, TokenType},
+    object::FunctionType,
+};
+
+/// The type of an expression inferred by the [`TypeChecker`],
+/// `Unknown` is the dynamic type that is compatible with any type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Ty<'gc> {
+    Unknown,
+    Number,
+    Str,
+    Bool,
+    Nil,
+    Array,
+    Object,
+    Function,
+    Class(&'gc str),
+    Instance(&'gc str),
+}
+
+impl Display for Ty<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Ty::Unknown => write!(f, "unknown"),
+            Ty::Number => write!(f, "number"),
+            Ty::Str => write!(f, "str"),
+            Ty::Bool => write!(f, "bool"),
+            Ty::Nil => write!(f, "nil"),
+            Ty::Array => write!(f, "array"),
+            Ty::Object => write!(f, "object"),
+            Ty::Function => write!(f, "function"),
+            Ty::Class(name) => write!(f, "class {name}"),
+            Ty::Instance(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+/// A type error found by the [`TypeChecker`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TypeError {
+    pub line: u32,
+    pub message: String,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClassInfo<'a, 'gc> {
+    superclass: Option<&'gc str>,
+    // The fields assigned through `self.field = ...` in the methods,
+    // typed by the constructor parameter of the same name.
+    fields: HashMap<&'gc str, Option<Token<'gc>>>,
+    methods: HashMap<&'gc str, &'a FunctionDecl<'gc>>,
+}
+
+#[derive(Clone, Copy)]
+enum Binding<'a, 'gc> {
+    Value(Ty<'gc>),
+    Function(&'a FunctionDecl<'gc>),
+}
+
+/// The optional static type checking pass of the strict mode, it infers the types
+/// of expressions from literals, annotations and declarations, then reports mismatched
+/// argument types, wrong return types and unknown fields before the script runs.
+/// Everything it can't infer is left to the runtime.
+pub(crate) struct TypeChecker<'a, 'gc> {
+    classes: HashMap<&'gc str, ClassInfo<'a, 'gc>>,
+    scopes: Vec<HashMap<&'gc str, Binding<'a, 'gc>>>,
+    // The declared return type of the current function, none if it isn't checked.
+    return_types: Option<(&'a FunctionDecl<'gc>, Vec<Ty<'gc>>)>,
+    self_ty: Ty<'gc>,
+    // Whether the file has the `#strict` pragma, which also rejects the
+    // functions ending without returning a value, the public functions
+    // missing type annotations and the shadowed variables.
+    strict: bool,
+    errors: Vec<TypeError>,
+}
+
+impl<'a, 'gc> TypeChecker<'a, 'gc> {
+    pub fn check(program: &'a Program<'gc>) -> Vec<TypeError> {
+        let mut checker = TypeChecker {
+            classes: HashMap::new(),
+            scopes: Vec::new(),
+            return_types: None,
+            self_ty: Ty::Unknown,
+            strict: program.strict,
+            errors: Vec::new(),
+        };
+        checker.collect_classes(&program.statements);
+        checker.check_block(&program.statements);
+        checker.errors.sort_by_key(|error| error.line);
+        checker.errors
+    }
+
+    fn error(&mut self, line: u32, message: String) {
+        self.errors.push(TypeError { line, message });
+    }
+
+    fn collect_classes(&mut self, statements: &'a [Stmt<'gc>]) {
+        for stmt in statements {
+            let Stmt::Class(ClassDecl {
+                name,
+                superclass,
+                methods,
+                ..
+            }) = stmt
+            else {
+                continue;
+            };
+            let mut info = ClassInfo {
+                superclass: match superclass.as_ref() {
+                    Some(Expr::Variable { name, .. }) => Some(name.lexeme),
+                    _ => None,
+                },
+                ..Default::default()
+            };
+            for method in methods {
+                let Stmt::Function(decl) = method else {
+                    continue;
+                };
+                info.methods.insert(decl.name.lexeme, decl);
+                collect_fields(&decl.body, &mut info.fields);
+                if decl.fn_type == FunctionType::Constructor {
+                    for param in decl.params.values() {
+                        if let Some(ty) = info.fields.get_mut(param.name.lexeme) {
+                            *ty = param.type_hint;
+                        }
+                    }
+                }
+            }
+            self.classes.insert(name.lexeme, info);
+        }
+    }
+
+    // The class and its superclasses, none if a class of the chain isn't declared
+    // in the script, as its fields and methods can't be known.
+    fn class_chain(&self, name: &'gc str) -> Option<Vec<&ClassInfo<'a, 'gc>>> {
+        let mut chain = Vec::new();
+        let mut current = Some(name);
+        while let Some(name) = current {
+            let info = self.classes.get(name)?;
+            if chain.len() > self.classes.len() {
+                return None;
+            }
+            chain.push(info);
+            current = info.superclass;
+        }
+        Some(chain)
+    }
+
+    fn find_method(&self, class: &'gc str, name: &str) -> Option<Option<&'a FunctionDecl<'gc>>> {
+        let chain = self.class_chain(class)?;
+        Some(
+            chain
+                .iter()
+                .find_map(|info| info.methods.get(name).copied()),
+        )
+    }
+
+    fn find_field(&self, class: &'gc str, name: &str) -> Option<Option<Option<Token<'gc>>>> {
+        let chain = self.class_chain(class)?;
+        Some(chain.iter().find_map(|info| info.fields.get(name).copied()))
+    }
+
+    fn is_subclass(&self, class: &'gc str, ancestor: &'gc str) -> bool {
+        let mut current = Some(class);
+        let mut depth = 0;
+        while let Some(name) = current {
+            if name == ancestor {
+                return true;
+            }
+            depth += 1;
+            if depth > self.classes.len() {
+                return false;
+            }
+            current = self.classes.get(name).and_then(|info| info.superclass);
+        }
+        false
+    }
+
+    fn annotation(&self, token: Token<'gc>) -> Ty<'gc> {
+        match (token.kind, token.lexeme) {
+            (TokenType::Nil, _) => Ty::Nil,
+            (_, "int" | "float") => Ty::Number,
+            (_, "str") => Ty::Str,
+            (_, "bool") => Ty::Bool,
+            (_, name) if self.classes.contains_key(name) => Ty::Instance(name),
+            // Enums, error types and union aliases are left to the runtime
+            _ => Ty::Unknown,
+        }
+    }
+
+    // Whether a value of the actual type can be used where the expected type is.
+    // Nil is accepted everywhere, as it is by the runtime.
+    fn accepts(&self, expected: Ty<'gc>, actual: Ty<'gc>) -> bool {
+        match (expected, actual) {
+            (Ty::Unknown, _) | (_, Ty::Unknown) | (_, Ty::Nil) => true,
+            (Ty::Instance(expected), Ty::Instance(actual)) => self.is_subclass(actual, expected),
+            _ => expected == actual,
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    fn define(&mut self, name: &'gc str, binding: Binding<'a, 'gc>) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name, binding);
+        }
+    }
+
+    // Reject a variable shadowing one of an enclosing scope in strict files.
+    fn check_shadowing(&mut self, name: Token<'gc>) {
+        let Some((_, enclosing)) = self.scopes.split_last() else {
+            return;
+        };
+        if self.strict
+            && enclosing
+                .iter()
+                .any(|scope| scope.contains_key(name.lexeme))
+        {
+            let message = format!(
+                "Variable '{}' shadows a variable of an enclosing scope.",
+                name.lexeme
+            );
+            self.error(name.line, message);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<Binding<'a, 'gc>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).copied())
+    }
+
+    fn check_block(&mut self, statements: &'a [Stmt<'gc>]) {
+        self.begin_scope();
+        // Functions can be called before their declarations
+        for stmt in statements {
+            if let Stmt::Function(decl) = stmt {
+                self.define(decl.name.lexeme, Binding::Function(decl));
+            }
+        }
+        for stmt in statements {
+            self.check_stmt(stmt);
+        }
+        self.end_scope();
+    }
+
+    fn check_stmt(&mut self, stmt: &'a Stmt<'gc>) {
+        match stmt {
+            Stmt::Use { alias, items, .. } => {
+                for (name, item_alias) in items {
+                    self.define(
+                        item_alias.unwrap_or(*name).lexeme,
+                        Binding::Value(Ty::Unknown),
+                    );
+                }
+                if let Some(alias) = alias {
+                    self.define(alias.lexeme, Binding::Value(Ty::Unknown));
+                }
+            }
+            Stmt::Enum(EnumDecl { methods, .. }) => {
+                for method in methods {
+                    if let Stmt::Function(decl) = method {
+                        self.check_function(decl, Ty::Unknown);
+                    }
+                }
+            }
+            Stmt::Expression { expression, .. } => {
+                self.synth(expression);
+            }
+            Stmt::Let(VariableDecl {
+                name, initializer, ..
+            }) => {
+                let ty = match initializer {
+                    // A variable initialized to nil is assigned later
+                    Some(initializer) => match self.synth(initializer) {
+                        Ty::Nil => Ty::Unknown,
+                        ty => ty,
+                    },
+                    None => Ty::Unknown,
+                };
+                self.check_shadowing(*name);
+                self.define(name.lexeme, Binding::Value(ty));
+            }
+            Stmt::Const {
+                name, initializer, ..
+            } => {
+                let ty = self.synth(initializer);
+                self.check_shadowing(*name);
+                self.define(name.lexeme, Binding::Value(ty));
+            }
+            Stmt::Block { statements, .. } => self.check_block(statements),
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.synth(condition);
+                self.check_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.check_stmt(else_branch);
+                }
+            }
+            Stmt::Loop {
+                initializer,
+                condition,
+                increment,
+                body,
+                ..
+            } => {
+                self.begin_scope();
+                if let Some(initializer) = initializer {
+                    self.check_stmt(initializer);
+                }
+                self.synth(condition);
+                self.check_stmt(body);
+                if let Some(increment) = increment {
+                    self.synth(increment);
+                }
+                self.end_scope();
+            }
+            Stmt::With {
+                manager,
+                name,
+                body,
+                ..
+            } => {
+                self.synth(manager);
+                self.begin_scope();
+                if let Some(name) = name {
+                    self.define(name.lexeme, Binding::Value(Ty::Unknown));
+                }
+                self.check_stmt(body);
+                self.end_scope();
+            }
+            Stmt::Function(decl) => {
+                self.define(decl.name.lexeme, Binding::Function(decl));
+                self.check_function(decl, Ty::Unknown);
+            }
+            Stmt::Raise { error, .. } => {
+                self.synth(error);
+            }
+            Stmt::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.synth(value);
+                }
+            }
+            Stmt::Return { value, line } => {
+                let ty = value.as_ref().map_or(Ty::Nil, |value| self.synth(value));
+                let Some((decl, expected)) = &self.return_types else {
+                    return;
+                };
+                if !expected.iter().any(|expected| self.accepts(*expected, ty)) {
+                    let declared = decl
+                        .return_types
+                        .iter()
+                        .map(|ty| ty.lexeme)
+                        .collect::<Vec<_>>()
+                        .join(" | ");
+                    let message = format!(
+                        "Function '{}' should return {declared}, found {ty}.",
+                        decl.name.lexeme
+                    );
+                    self.error(*line, message);
+                }
+            }
+            Stmt::BlockReturn { value, .. } => {
+                self.synth(value);
+            }
+            Stmt::Class(ClassDecl { name, methods, .. }) => {
+                for method in methods {
+                    if let Stmt::Function(decl) = method {
+                        self.check_function(decl, Ty::Instance(name.lexeme));
+                    }
+                }
+            }
+            Stmt::Agent(AgentDecl { fields, tools, .. }) => {
+                for field in fields.values() {
+                    self.synth(field);
+                }
+                for tool in tools {
+                    if let Stmt::Function(decl) = tool {
+                        self.check_function(decl, Ty::Unknown);
+                    }
+                }
+            }
+            Stmt::Workflow(WorkflowDecl {
+                steps,
+                compensations,
+                ..
+            }) => {
+                let functions = steps.iter().map(|step| &step.function).chain(compensations);
+                for function in functions {
+                    if let Stmt::Function(decl) = function {
+                        self.check_function(decl, Ty::Unknown);
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_function(&mut self, decl: &'a FunctionDecl<'gc>, self_ty: Ty<'gc>) {
+        if self.strict {
+            self.check_strict_function(decl);
+        }
+        self.begin_scope();
+        for param in decl.params.values() {
+            let ty = match param.kind {
+                ParameterKind::Rest => Ty::Array,
+                ParameterKind::Kwargs => Ty::Object,
+                _ => param
+                    .type_hint
+                    .map_or(Ty::Unknown, |type_hint| self.annotation(type_hint)),
+            };
+            self.define(param.name.lexeme, Binding::Value(ty));
+        }
+        let expected = decl
+            .return_types
+            .iter()
+            .map(|ty| self.annotation(*ty))
+            .collect::<Vec<_>>();
+        let return_types = if expected.is_empty() || expected.contains(&Ty::Unknown) {
+            None
+        } else {
+            Some((decl, expected))
+        };
+        let enclosing_return_types = std::mem::replace(&mut self.return_types, return_types);
+        let enclosing_self_ty = std::mem::replace(&mut self.self_ty, self_ty);
+        self.check_block(&decl.body);
+        self.return_types = enclosing_return_types;
+        self.self_ty = enclosing_self_ty;
+        self.end_scope();
+    }
+
+    fn check_strict_function(&mut self, decl: &FunctionDecl<'gc>) {
+        let name = decl.name.lexeme;
+        let returns_value = returns_value(&decl.body)
+            || decl.return_types.iter().any(|ty| ty.kind != TokenType::Nil);
+        if decl.visibility == Visibility::Public
+            && matches!(decl.fn_type, FunctionType::Function { .. })
+        {
+            for param in decl.params.values() {
+                if param.type_hint.is_none()
+                    && matches!(
+                        param.kind,
+                        ParameterKind::Positional | ParameterKind::KeywordOnly
+                    )
+                {
+                    let message = format!(
+                        "Public function '{name}' must annotate the type of parameter '{}'.",
+                        param.name.lexeme
+                    );
+                    self.error(param.name.line, message);
+                }
+            }
+            if returns_value && decl.return_types.is_empty() {
+                let message = format!("Public function '{name}' must annotate its return type.");
+                self.error(decl.name.line, message);
+            }
+        }
+        if returns_value && decl.fn_type != FunctionType::Constructor && !always_returns(&decl.body)
+        {
+            let message = format!(
+                "Function '{name}' can end without returning a value, add an explicit return."
+            );
+            self.error(decl.name.line, message);
+        }
+    }
+
+    // Check the expression against the expected type, the branches
+    // of an inline if are checked separately.
+    fn check_expr(&mut self, expr: &'a Expr<'gc>, expected: Ty<'gc>) -> Option<Ty<'gc>> {
+        match expr {
+            Expr::Grouping { expression, .. } => self.check_expr(expression, expected),
+            Expr::InlineIf {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.synth(condition);
+                self.check_expr(then_branch, expected)
+                    .or_else(|| self.check_expr(else_branch, expected))
+            }
+            _ => {
+                let ty = self.synth(expr);
+                (!self.accepts(expected, ty)).then_some(ty)
+            }
+        }
+    }
+
+    fn check_call(
+        &mut self,
+        decl: &'a FunctionDecl<'gc>,
+        callee: &str,
+        arguments: &'a [Expr<'gc>],
+        keyword_args: &'a HashMap<String, Expr<'gc>>,
+    ) {
+        let positional = decl
+            .params
+            .values()
+            .filter(|param| param.kind == ParameterKind::Positional)
+            .collect::<Vec<_>>();
+        let mut checks = arguments
+            .iter()
+            .enumerate()
+            .map(|(i, argument)| (positional.get(i).copied(), argument))
+            .collect::<Vec<_>>();
+        for (name, argument) in keyword_args {
+            let param = decl.params.values().find(|param| {
+                param.name.lexeme == name
+                    && matches!(
+                        param.kind,
+                        ParameterKind::Positional | ParameterKind::KeywordOnly
+                    )
+            });
+            checks.push((param, argument));
+        }
+        for (param, argument) in checks {
+            let Some((param, type_hint)) =
+                param.and_then(|param| param.type_hint.map(|type_hint| (param, type_hint)))
+            else {
+                self.synth(argument);
+                continue;
+            };
+            let expected = self.annotation(type_hint);
+            if let Some(ty) = self.check_expr(argument, expected) {
+                let message = format!(
+                    "Argument '{}' of '{callee}' expects {}, found {ty}.",
+                    param.name.lexeme, type_hint.lexeme
+                );
+                self.error(argument.line(), message);
+            }
+        }
+    }
+
+    fn check_error_handler(&mut self, error_handler: &'a Option<ErrorHandler<'gc>>) {
+        if let Some(ErrorHandler {
+            error_var,
+            handler_body,
+            ..
+        }) = error_handler
+        {
+            self.begin_scope();
+            self.define(error_var.lexeme, Binding::Value(Ty::Unknown));
+            self.check_block(handler_body);
+            self.end_scope();
+        }
+    }
+
+    fn return_type(&self, decl: &FunctionDecl<'gc>) -> Ty<'gc> {
+        match decl.return_types.as_slice() {
+            [ty] => self.annotation(*ty),
+            _ => Ty::Unknown,
+        }
+    }
+
+    fn synth(&mut self, expr: &'a Expr<'gc>) -> Ty<'gc> {
+        match expr {
+            Expr::Literal { value, .. } => match value {
+                Literal::Number(_) => Ty::Number,
+                Literal::String(_) => Ty::Str,
+                Literal::Boolean(_) => Ty::Bool,
+                Literal::Nil => Ty::Nil,
+            },
+            Expr::FString { parts, .. } => {
+                for part in parts {
+                    if let FStringPart::Expression(expr) = part {
+                        self.synth(expr);
+                    }
+                }
+                Ty::Str
+            }
+            Expr::List { elements, .. } => {
+                for element in elements {
+                    self.synth(element);
+                }
+                Ty::Array
+            }
+            Expr::Object { properties, .. } => {
+                for property in properties {
+                    match property {
+                        ObjectProperty::Literal { value, .. } => {
+                            self.synth(value);
+                        }
+                        ObjectProperty::Computed { key_expr, value } => {
+                            self.synth(key_expr);
+                            self.synth(value);
+                        }
+                    }
+                }
+                Ty::Object
+            }
+            Expr::EnvLookup { expr, .. } | Expr::EvaluateVariant { expr, .. } => {
+                self.synth(expr);
+                Ty::Unknown
+            }
+            Expr::EnumVariant { .. } => Ty::Unknown,
+            Expr::Binary {
+                left,
+                operator,
+                right,
+                line,
+            } => {
+                let left = self.synth(left);
+                let right = self.synth(right);
+                match operator.kind {
+                    TokenType::Plus if left == right && matches!(left, Ty::Number | Ty::Str) => {
+                        left
+                    }
+                    TokenType::Plus
+                        if matches!(
+                            (left, right),
+                            (Ty::Str, Ty::Number | Ty::Bool | Ty::Nil)
+                                | (Ty::Number | Ty::Bool | Ty::Nil, Ty::Str)
+                        ) =>
+                    {
+                        let message =
+                            format!("Cannot add {left} and {right}, convert it with str() first.");
+                        self.error(*line, message);
+                        Ty::Str
+                    }
+                    TokenType::Star
+                        if matches!(
+                            (left, right),
+                            (Ty::Str, Ty::Number) | (Ty::Number, Ty::Str)
+                        ) =>
+                    {
+                        Ty::Str
+                    }
+                    TokenType::Minus
+                    | TokenType::Star
+                    | TokenType::Slash
+                    | TokenType::TildeSlash
+                    | TokenType::Percent
+                    | TokenType::StarStar
+                        if left == Ty::Number && right == Ty::Number =>
+                    {
+                        Ty::Number
+                    }
+                    TokenType::Ampersand
+                    | TokenType::Pipe
+                    | TokenType::Caret
+                    | TokenType::LessLess
+                    | TokenType::GreaterGreater
+                        if left == Ty::Number && right == Ty::Number =>
+                    {
+                        Ty::Number
+                    }
+                    TokenType::EqualEqual
+                    | TokenType::NotEqual
+                    | TokenType::Greater
+                    | TokenType::GreaterEqual
+                    | TokenType::Less
+                    | TokenType::LessEqual
+                    | TokenType::In => Ty::Bool,
+                    _ => Ty::Unknown,
+                }
+            }
+            Expr::Grouping { expression, .. } => self.synth(expression),
+            Expr::Unary {
+                operator, right, ..
+            } => {
+                let right = self.synth(right);
+                match operator.kind {
+                    TokenType::Not | TokenType::Bang => Ty::Bool,
+                    TokenType::Minus | TokenType::Tilde if right == Ty::Number => Ty::Number,
+                    _ => Ty::Unknown,
+                }
+            }
+            Expr::Variable { name, .. } => match self.lookup(name.lexeme) {
+                Some(Binding::Value(ty)) => ty,
+                Some(Binding::Function(_)) => Ty::Function,
+                None if self.classes.contains_key(name.lexeme) => Ty::Class(name.lexeme),
+                None => Ty::Unknown,
+            },
+            Expr::Index {
+                object, key, value, ..
+            } => {
+                self.synth(object);
+                self.synth(key);
+                if let Some(value) = value {
+                    self.synth(value);
+                }
+                Ty::Unknown
+            }
+            Expr::Assign { name, value, .. } => {
+                self.synth(value);
+                // The type of a reassigned variable isn't tracked any more
+                if let Some(scope) = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|scope| scope.contains_key(name.lexeme))
+                {
+                    scope.insert(name.lexeme, Binding::Value(Ty::Unknown));
+                }
+                Ty::Unknown
+            }
+            Expr::And { left, right, .. } | Expr::Or { left, right, .. } => {
+                let left = self.synth(left);
+                let right = self.synth(right);
+                if left == Ty::Bool && right == Ty::Bool {
+                    Ty::Bool
+                } else {
+                    Ty::Unknown
+                }
+            }
+            Expr::Lambda { params, body, .. } => {
+                self.begin_scope();
+                for param in params {
+                    self.define(param.lexeme, Binding::Value(Ty::Unknown));
+                }
+                let enclosing_return_types = self.return_types.take();
+                self.synth(body);
+                self.return_types = enclosing_return_types;
+                self.end_scope();
+                Ty::Function
+            }
+            Expr::Block { statements, .. } => {
+                self.check_block(statements);
+                Ty::Unknown
+            }
+            Expr::Call {
+                callee,
+                arguments,
+                keyword_args,
+                keyword_spread,
+                error_handler,
+                ..
+            } => {
+                if let Some(spread) = keyword_spread {
+                    self.synth(spread);
+                }
+                let ty = match &**callee {
+                    Expr::Variable { name, .. } => match self.lookup(name.lexeme) {
+                        Some(Binding::Function(decl)) => {
+                            self.check_call(decl, name.lexeme, arguments, keyword_args);
+                            self.return_type(decl)
+                        }
+                        None if self.classes.contains_key(name.lexeme) => {
+                            match self.find_method(name.lexeme, "new") {
+                                Some(Some(decl)) => {
+                                    self.check_call(decl, name.lexeme, arguments, keyword_args);
+                                }
+                                _ => self.synth_arguments(arguments, keyword_args),
+                            }
+                            Ty::Instance(name.lexeme)
+                        }
+                        _ => {
+                            self.synth_arguments(arguments, keyword_args);
+                            Ty::Unknown
+                        }
+                    },
+                    callee => {
+                        self.synth(callee);
+                        self.synth_arguments(arguments, keyword_args);
+                        Ty::Unknown
+                    }
+                };
+                if error_handler.is_some() {
+                    self.check_error_handler(error_handler);
+                    Ty::Unknown
+                } else {
+                    ty
+                }
+            }
+            Expr::Invoke {
+                object,
+                method,
+                arguments,
+                keyword_args,
+                keyword_spread,
+                error_handler,
+                ..
+            } => {
+                let object = self.synth(object);
+                if let Some(spread) = keyword_spread {
+                    self.synth(spread);
+                }
+                let mut ty = Ty::Unknown;
+                match object {
+                    Ty::Instance(class) => match self.find_method(class, method.lexeme) {
+                        Some(Some(decl)) => {
+                            self.check_call(decl, method.lexeme, arguments, keyword_args);
+                            ty = self.return_type(decl);
+                        }
+                        // A field holding a function can be invoked too
+                        Some(None)
+                            if matches!(self.find_field(class, method.lexeme), Some(None)) =>
+                        {
+                            self.error(
+                                method.line,
+                                format!("Undefined method '{}' of class '{class}'.", method.lexeme),
+                            );
+                            self.synth_arguments(arguments, keyword_args);
+                        }
+                        _ => self.synth_arguments(arguments, keyword_args),
+                    },
+                    _ => self.synth_arguments(arguments, keyword_args),
+                }
+                if error_handler.is_some() {
+                    self.check_error_handler(error_handler);
+                    Ty::Unknown
+                } else {
+                    ty
+                }
+            }
+            Expr::Match { expr, arms, .. } => {
+                self.synth(expr);
+                for arm in arms {
+                    self.begin_scope();
+                    for pattern in &arm.patterns {
+                        match pattern {
+                            MatchPattern::Variable { name } => {
+                                self.define(name.lexeme, Binding::Value(Ty::Unknown));
+                            }
+                            MatchPattern::Range { start, end, .. } => {
+                                if let Some(start) = start {
+                                    self.synth(start);
+                                }
+                                if let Some(end) = end {
+                                    self.synth(end);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.synth(guard);
+                    }
+                    self.synth(&arm.body);
+                    self.end_scope();
+                }
+                Ty::Unknown
+            }
+            Expr::InlineIf {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.synth(condition);
+                let then_ty = self.synth(then_branch);
+                let else_ty = self.synth(else_branch);
+                if then_ty == else_ty {
+                    then_ty
+                } else {
+                    Ty::Unknown
+                }
+            }
+            Expr::Get { object, name, .. } => {
+                let object = self.synth(object);
+                let Ty::Instance(class) = object else {
+                    return Ty::Unknown;
+                };
+                match self.find_field(class, name.lexeme) {
+                    Some(Some(type_hint)) => {
+                        type_hint.map_or(Ty::Unknown, |type_hint| self.annotation(type_hint))
+                    }
+                    Some(None) if matches!(self.find_method(class, name.lexeme), Some(None)) => {
+                        self.error(
+                            name.line,
+                            format!("Undefined field '{}' of class '{class}'.", name.lexeme),
+                        );
+                        Ty::Unknown
+                    }
+                    _ => Ty::Unknown,
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+                ..
+            } => {
+                let object = self.synth(object);
+                let Ty::Instance(class) = object else {
+                    self.synth(value);
+                    return Ty::Unknown;
+                };
+                match self.find_field(class, name.lexeme) {
+                    Some(Some(Some(type_hint))) => {
+                        let expected = self.annotation(type_hint);
+                        if let Some(ty) = self.check_expr(value, expected) {
+                            let message = format!(
+                                "Field '{}' of class '{class}' expects {}, found {ty}.",
+                                name.lexeme, type_hint.lexeme
+                            );
+                            self.error(value.line(), message);
+                        }
+                    }
+                    Some(None) if matches!(self.find_method(class, name.lexeme), Some(None)) => {
+                        self.error(
+                            name.line,
+                            format!("Undefined field '{}' of class '{class}'.", name.lexeme),
+                        );
+                        self.synth(value);
+                    }
+                    _ => {
+                        self.synth(value);
+                    }
+                }
+                Ty::Unknown
+            }
+            Expr::Self_ { .. } => self.self_ty,
+            Expr::Super { .. } => Ty::Unknown,
+            Expr::SuperInvoke {
+                arguments,
+                keyword_args,
+                keyword_spread,
+                ..
+            } => {
+                self.synth_arguments(arguments, keyword_args);
+                if let Some(spread) = keyword_spread {
+                    self.synth(spread);
+                }
+                Ty::Unknown
+            }
+            Expr::Prompt {
+                expression,
+                error_handler,
+                ..
+            } => {
+                self.synth(expression);
+                if error_handler.is_some() {
+                    self.check_error_handler(error_handler);
+                    Ty::Unknown
+                } else {
+                    Ty::Str
+                }
+            }
+        }
+    }
+
+    fn synth_arguments(
+        &mut self,
+        arguments: &'a [Expr<'gc>],
+        keyword_args: &'a HashMap<String, Expr<'gc>>,
+    ) {
+        for argument in arguments.iter().chain(keyword_args.values()) {
+            self.synth(argument);
+        }
+    }
+}
+
+// Whether a return of the statements, outside of their nested functions, has a value.
+fn returns_value(statements: &[Stmt]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Stmt::Return { value, .. } => value.is_some(),
+        // The tail expression of the body
+        Stmt::BlockReturn { .. } => true,
+        Stmt::Block { statements, .. } => returns_value(statements),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            returns_value(std::slice::from_ref(then_branch))
+                || else_branch
+                    .as_ref()
+                    .is_some_and(|else_branch| returns_value(std::slice::from_ref(else_branch)))
+        }
+        Stmt::Loop { body, .. } | Stmt::With { body, .. } => {
+            returns_value(std::slice::from_ref(body))
+        }
+        _ => false,
+    })
+}
+
+// Whether the statements return or raise on every path.
+fn always_returns(statements: &[Stmt]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Stmt::Return { .. } | Stmt::BlockReturn { .. } | Stmt::Raise { .. } => true,
+        Stmt::Block { statements, .. } => always_returns(statements),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => {
+            always_returns(std::slice::from_ref(then_branch))
+                && always_returns(std::slice::from_ref(else_branch))
+        }
+        Stmt::With { body, .. } => always_returns(std::slice::from_ref(body)),
+        _ => false,
+    })
+}
+
+// Collect the fields assigned through `self.field = ...` in a method body.
+fn collect_fields<'gc>(
+    statements: &[Stmt<'gc>],
+    fields: &mut HashMap<&'gc str, Option<Token<'gc>>>,
+) {
+    for stmt in statements {
+        match stmt {
+            Stmt::Expression {
+                expression: Expr::Set { object, name, .. },
+                ..
+            } if matches!(**object, Expr::Self_ { .. }) => {
+                fields.entry(name.lexeme).or_insert(None);
+            }
+            Stmt::Block { statements, .. } => collect_fields(statements, fields),
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                collect_fields(std::slice::from_ref(then_branch), fields);
+                if let Some(else_branch) = else_branch {
+                    collect_fields(std::slice::from_ref(else_branch), fields);
+                }
+            }
+            Stmt::Loop { body, .. } => collect_fields(std::slice::from_ref(body), fields),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_arena::arena::rootless_mutate;
+
+    use super::*;
+    use crate::{parser::Parser, string::InternedStringSet, vm::Context};
+
+    fn check(source: &'static str) -> Vec<String> {
+        rootless_mutate(|mutation| {
+            let context = Context {
+                mutation,
+                strings: InternedStringSet::new(mutation),
+            };
+            let mut parser = Parser::new(context, source);
+            let program = parser.parse().unwrap();
+            TypeChecker::check(&program)
+                .into_iter()
+                .map(|error| error.to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_argument_types() {
+        let errors = check(
+            r#"
+            fn greet(name: str, times: int = 1, *, loud: bool = false) {
+                return name;
+            }
+            greet("Alice");
+            greet(42);
+            greet("Bob", "twice");
+            greet("Carol", loud="yes");
+            let n = 1;
+            greet(n + 1);
+            greet(true if n > 0 else "Dave");
+            let dynamic = nil;
+            greet(dynamic);
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "[line 6] Error: Argument 'name' of 'greet' expects str, found number.",
+                "[line 7] Error: Argument 'times' of 'greet' expects int, found str.",
+                "[line 8] Error: Argument 'loud' of 'greet' expects bool, found str.",
+                "[line 10] Error: Argument 'name' of 'greet' expects str, found number.",
+                "[line 11] Error: Argument 'name' of 'greet' expects str, found bool.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_return_types() {
+        let errors = check(
+            r#"
+            class User {
+                name: str,
+            }
+            class Admin(User) {}
+            fn name() -> str {
+                if true {
+                    return 1;
+                }
+                return "name";
+            }
+            fn find(id: str) -> User | nil {
+                if id == "admin" {
+                    return Admin(name="root");
+                }
+                if id == "" {
+                    return nil;
+                }
+                return id;
+            }
+            fn count() -> int {
+                return name();
+            }
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "[line 8] Error: Function 'name' should return str, found number.",
+                "[line 19] Error: Function 'find' should return User | nil, found str.",
+                "[line 22] Error: Function 'count' should return int, found str.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let errors = check(
+            r#"
+            class Point {
+                x: int,
+                y: int,
+                fn norm(self) -> int {
+                    return self.x + self.z;
+                }
+            }
+            class Counter {
+                fn new() {
+                    self.count = 0;
+                }
+            }
+            let p = Point(x=1, y=2);
+            print(p.x, p.y, p.norm());
+            print(p.name);
+            p.x = "one";
+            p.scale(2);
+            let c = Counter();
+            print(c.count);
+            c.total = 1;
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "[line 6] Error: Undefined field 'z' of class 'Point'.",
+                "[line 16] Error: Undefined field 'name' of class 'Point'.",
+                "[line 17] Error: Field 'x' of class 'Point' expects int, found str.",
+                "[line 18] Error: Undefined method 'scale' of class 'Point'.",
+                "[line 21] Error: Undefined field 'total' of class 'Counter'.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_operators() {
+        let errors = check(
+            r#"
+            fn greet(name: str) {
+                return name;
+            }
+            greet("ab" * 3);
+            greet(3 * "ab");
+            greet("n=" + 3);
+            let flag = true;
+            print(flag + "!");
+            "#,
+        );
+        assert_eq!(
+            errors,
+            [
+                "[line 7] Error: Cannot add str and number, convert it with str() first.",
+                "[line 9] Error: Cannot add bool and str, convert it with str() first.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strict_pragma() {
+        let source = r#"
+            fn first(items) {
+                for let i = 0; i < len(items); i += 1 {
+                    let items = i;
+                    return items;
+                }
+            }
+            pub fn last(items, n: int) {
+                return items[n];
+            }
+            "#;
+        assert!(check(source).is_empty());
+        let errors = check(Box::leak(format!("#strict{source}").into_boxed_str()));
+        assert_eq!(
+            errors,
+            [
+                "[line 2] Error: Function 'first' can end without returning a value, add an explicit return.",
+                "[line 4] Error: Variable 'items' shadows a variable of an enclosing scope.",
+                "[line 8] Error: Public function 'last' must annotate the type of parameter 'items'.",
+                "[line 8] Error: Public function 'last' must annotate its return type.",
+            ]
+        );
+    }
+}
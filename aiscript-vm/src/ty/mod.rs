@@ -1,10 +1,12 @@
 use aiscript_arena::Collect;
 
+mod checker;
 mod r#enum;
 mod error;
 mod resolver;
 
 use crate::lexer::Token;
+pub(crate) use checker::{TypeChecker, TypeError};
 pub(crate) use r#enum::EnumVariantChecker;
 pub(crate) use error::FunctionErrorResolver;
 pub(crate) use resolver::{ClassField, TypeResolver, ValidationError};
@@ -2,7 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use crate::{
     ast::{Expr, Literal, ObjectProperty},
-    lexer::Token,
+    lexer::{Token, TokenType},
 };
 
 use super::Type;
@@ -41,6 +41,21 @@ pub(crate) struct TypeResolver<'gc> {
     pending_validations: Vec<Token<'gc>>,
     // Store class information
     class_info: HashMap<&'gc str, ClassInfo<'gc>>,
+    // Type aliases and their member types, e.g. `type MaybeUser = User | nil;`
+    aliases: HashMap<&'gc str, Vec<Token<'gc>>>,
+    // The variants of the enums in declaration order
+    enums: HashMap<&'gc str, Vec<&'gc str>>,
+    // The matches on enum variants without a catch-all arm, checked once
+    // all the enums are declared.
+    enum_matches: Vec<EnumMatch<'gc>>,
+}
+
+#[derive(Debug)]
+struct EnumMatch<'gc> {
+    enum_name: Token<'gc>,
+    // The variants of the arms without a guard
+    covered: HashSet<&'gc str>,
+    line: u32,
 }
 
 impl Default for TypeResolver<'_> {
@@ -55,6 +70,9 @@ impl<'gc> TypeResolver<'gc> {
             defined_types: HashMap::new(),
             pending_validations: Vec::new(),
             class_info: HashMap::new(),
+            aliases: HashMap::new(),
+            enums: HashMap::new(),
+            enum_matches: Vec::new(),
         };
 
         // Register built-in types
@@ -104,6 +122,66 @@ impl<'gc> TypeResolver<'gc> {
         self.defined_types.insert(name, typ);
     }
 
+    /// Register a type alias, the member types have been recorded for validation.
+    pub fn register_alias(
+        &mut self,
+        name: Token<'gc>,
+        members: Vec<Token<'gc>>,
+    ) -> Result<(), String> {
+        if self.defined_types.contains_key(name.lexeme) || self.aliases.contains_key(name.lexeme) {
+            return Err(format!("Type '{}' is already defined.", name.lexeme));
+        }
+        self.defined_types.insert(name.lexeme, Type::Custom(name));
+        self.aliases.insert(name.lexeme, members);
+        Ok(())
+    }
+
+    /// The member types of a type alias, a single member for a plain alias.
+    pub fn resolve_alias(&self, name: &str) -> Option<&[Token<'gc>]> {
+        self.aliases.get(name).map(Vec::as_slice)
+    }
+
+    pub fn register_enum(&mut self, name: &'gc str, variants: Vec<&'gc str>) {
+        self.enums.insert(name, variants);
+    }
+
+    /// Record a match on the variants of an enum without a catch-all arm.
+    pub fn add_enum_match(&mut self, enum_name: Token<'gc>, covered: HashSet<&'gc str>, line: u32) {
+        self.enum_matches.push(EnumMatch {
+            enum_name,
+            covered,
+            line,
+        });
+    }
+
+    /// Report the matches missing some variants of their enum, the enums
+    /// that aren't declared in the script are skipped, e.g. the imported ones.
+    pub fn validate_enum_matches<F>(&self, mut f: F)
+    where
+        F: FnMut(u32, String),
+    {
+        for enum_match in &self.enum_matches {
+            let name = enum_match.enum_name.lexeme;
+            let Some(variants) = self.enums.get(name) else {
+                continue;
+            };
+            let missing: Vec<_> = variants
+                .iter()
+                .filter(|variant| !enum_match.covered.contains(*variant))
+                .map(|variant| format!("{name}::{variant}"))
+                .collect();
+            if !missing.is_empty() {
+                f(
+                    enum_match.line,
+                    format!(
+                        "Non-exhaustive match on enum '{name}', missing {}.",
+                        missing.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
     /// Resolve a type reference, returning None if the type is not defined
     fn resolve_type(&self, typ: Type<'gc>) -> Option<Type<'gc>> {
         match typ {
@@ -124,6 +202,30 @@ impl<'gc> TypeResolver<'gc> {
     }
 
     fn check_type(&self, expr: &Expr<'gc>, expected_type: Type<'gc>) -> Result<(), String> {
+        // A union alias accepts the value of any of its members
+        let members = match expected_type {
+            Type::Custom(token) => self.resolve_alias(token.lexeme),
+            _ => None,
+        };
+        if let Some(members) = members {
+            let is_nil = matches!(
+                expr,
+                Expr::Literal {
+                    value: Literal::Nil,
+                    ..
+                }
+            );
+            return if is_nil
+                || members
+                    .iter()
+                    .filter(|member| member.kind != TokenType::Nil)
+                    .any(|member| self.check_type(expr, Type::from_token(*member)).is_ok())
+            {
+                Ok(())
+            } else {
+                Err(format!("Type mismatch: expected {:?}", expected_type))
+            };
+        }
         match expr {
             // For literals, we can check the type
             Expr::Literal { value, .. } => {
@@ -5,25 +5,73 @@ use aiscript_arena::{Collect, Gc, Mutation, RefLock, lock::GcRefLock};
 use crate::{
     NativeFn,
     ai::Agent,
-    object::{BoundMethod, Class, Closure, Enum, EnumVariant, Instance, List, ListKind, Object},
+    datetime::DateTime,
+    decimal::Decimal,
+    dict::Dict,
+    object::{
+        BoundMethod, Class, Closure, Enum, EnumVariant, Generator, HostFunction, Instance, List,
+        ListKind, Object,
+    },
+    set::Set,
     string::{InternedString, StringValue},
     vm::{Context, VmError},
+    workflow::Workflow,
 };
 
+// The largest integer a JavaScript number holds exactly, 2^53 - 1.
+const MAX_SAFE_INT: i64 = (1 << 53) - 1;
+
+/// Replace the integers of the JSON beyond ±(2^53 - 1) with strings, a
+/// JavaScript client would read them as floats and round them, e.g. the
+/// snowflake ids.
+pub fn big_ints_to_strings(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Number(n) => {
+            let big = match (n.as_i64(), n.as_u64()) {
+                (Some(n), _) => !(-MAX_SAFE_INT..=MAX_SAFE_INT).contains(&n),
+                (None, Some(_)) => true,
+                (None, None) => false,
+            };
+            if big {
+                *json = n.to_string().into();
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(big_ints_to_strings),
+        serde_json::Value::Object(fields) => fields.values_mut().for_each(big_ints_to_strings),
+        _ => {}
+    }
+}
+
 #[derive(Copy, Clone, Default, Collect)]
 #[collect(no_drop)]
 pub enum Value<'gc> {
     Number(f64),
+    // Integers from `int()`, databases and JSON, kept exact beyond 2^53.
+    Int(i64),
+    // Exact decimals from `std.decimal`, for money.
+    Decimal(Gc<'gc, Decimal>),
+    // Timezone-aware datetimes from `std.datetime`.
+    DateTime(Gc<'gc, DateTime>),
     Boolean(bool),
     // For identifiers, module names, etc.
     String(InternedString<'gc>),
     // For file contents, user input, etc. Not interned.
     IoString(Gc<'gc, String>),
+    // Binary data from `std.encoding` and `std.crypto`, not necessarily UTF-8.
+    Bytes(Gc<'gc, Vec<u8>>),
     Closure(Gc<'gc, Closure<'gc>>),
+    // The suspended call of a function with `yield`.
+    Generator(GcRefLock<'gc, Generator<'gc>>),
     NativeFunction(NativeFn<'gc>),
+    // A function registered by the host application, see `Vm::register_function`.
+    HostFunction(Gc<'gc, HostFunction>),
     // Array(GcRefLock<'gc, Vec<Value<'gc>>>),
     List(GcRefLock<'gc, List<'gc>>),
     Object(GcRefLock<'gc, Object<'gc>>),
+    // Insertion ordered map of non-string keys from `dict()`.
+    Dict(GcRefLock<'gc, Dict<'gc>>),
+    // Insertion ordered set of hashable values from `set()`.
+    Set(GcRefLock<'gc, Set<'gc>>),
     Enum(GcRefLock<'gc, Enum<'gc>>),
     EnumVariant(Gc<'gc, EnumVariant<'gc>>),
     Class(GcRefLock<'gc, Class<'gc>>),
@@ -31,6 +79,7 @@ pub enum Value<'gc> {
     BoundMethod(Gc<'gc, BoundMethod<'gc>>),
     Module(InternedString<'gc>),
     Agent(Gc<'gc, Agent<'gc>>),
+    Workflow(Gc<'gc, Workflow<'gc>>),
     #[default]
     Nil,
 }
@@ -45,9 +94,13 @@ impl Display for Value<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Number(v) => write!(f, "{}", v),
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::DateTime(dt) => write!(f, "{}", dt),
             Value::Boolean(b) => write!(f, "{}", b),
             Value::String(s) => write!(f, "{}", s),
             Value::IoString(s) => write!(f, "{}", s),
+            Value::Bytes(bytes) => write!(f, "b\"{}\"", bytes.escape_ascii()),
             Value::Closure(closure) => {
                 if let Some(name) = closure.function.name {
                     write!(f, "<fn {}>", name)
@@ -56,6 +109,7 @@ impl Display for Value<'_> {
                 }
             }
             Value::NativeFunction(_) => write!(f, "<native fn>"),
+            Value::HostFunction(function) => write!(f, "<native fn {}>", function.name),
             Value::List(list) => {
                 let list = list.borrow();
                 match list.kind {
@@ -97,6 +151,9 @@ impl Display for Value<'_> {
                 }
                 write!(f, "}}")
             }
+            Value::Dict(dict) => write!(f, "{}", dict.borrow()),
+            Value::Set(set) => write!(f, "{}", set.borrow()),
+            Value::Generator(generator) => write!(f, "{}", generator.borrow()),
             Value::Enum(enum_) => write!(f, "enum {}", enum_.borrow().name),
             Value::EnumVariant(variant) => {
                 write!(f, "{}::{}", variant.enum_.borrow().name, variant.name)?;
@@ -120,6 +177,7 @@ impl Display for Value<'_> {
             }
             Value::BoundMethod(bm) => write!(f, "{}", bm.method.function),
             Value::Agent(agent) => write!(f, "agent {}", agent.name),
+            Value::Workflow(workflow) => write!(f, "workflow {}", workflow.name),
             Value::Module(module) => write!(f, "module {}", module),
             Value::Nil => write!(f, "nil"),
         }
@@ -129,20 +187,35 @@ impl Display for Value<'_> {
 impl<'gc> Value<'gc> {
     #[inline]
     pub fn array(mc: &Mutation<'gc>, data: Vec<Value<'gc>>) -> Self {
-        Value::List(Gc::new(mc, RefLock::new(List::array(data))))
+        Value::List(Gc::new(mc, RefLock::new(List::array(mc, data))))
     }
 
     #[inline]
     pub fn equals(&self, other: &Value<'gc>) -> bool {
         match (self, other) {
+            (Value::Decimal(_), _) | (_, Value::Decimal(_)) => {
+                matches!(self.decimal_operands(other), Some(Ok((a, b))) if a == b)
+            }
+            (Value::DateTime(a), Value::DateTime(b)) => **a == **b,
             (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Int(a), Value::Number(b)) | (Value::Number(b), Value::Int(a)) => {
+                match Value::Number(*b).as_exact_int() {
+                    Some(b) => *a == b,
+                    None => *a as f64 == *b,
+                }
+            }
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::String(a), Value::String(b)) => a.equals(b),
             (Value::IoString(a), Value::IoString(b)) => *a == *b,
             (Value::String(a), Value::IoString(b)) => a.as_bytes() == b.as_bytes(),
             (Value::IoString(a), Value::String(b)) => a.as_bytes() == b.as_bytes(),
+            (Value::Bytes(a), Value::Bytes(b)) => **a == **b,
             (Value::List(a), Value::List(b)) => a.borrow().equals(&b.borrow()),
             (Value::Object(a), Value::Object(b)) => Gc::ptr_eq(*a, *b),
+            (Value::Dict(a), Value::Dict(b)) => Gc::ptr_eq(*a, *b),
+            (Value::Set(a), Value::Set(b)) => Gc::ptr_eq(*a, *b) || a.borrow().equals(&b.borrow()),
+            (Value::Generator(a), Value::Generator(b)) => Gc::ptr_eq(*a, *b),
             (Value::Enum(a), Value::Enum(b)) => Gc::ptr_eq(*a, *b),
             (Value::EnumVariant(a), Value::EnumVariant(b)) => {
                 // We only need to compare the enum type name and variant name, not the underlying value.
@@ -154,6 +227,7 @@ impl<'gc> Value<'gc> {
             (Value::Instance(a), Value::Instance(b)) => Gc::ptr_eq(*a, *b),
             (Value::BoundMethod(a), Value::BoundMethod(b)) => Gc::ptr_eq(*a, *b),
             (Value::Agent(a), Value::Agent(b)) => Gc::ptr_eq(*a, *b),
+            (Value::Workflow(a), Value::Workflow(b)) => Gc::ptr_eq(*a, *b),
             (Value::Nil, Value::Nil) => true,
             // _ => core::mem::discriminant(self) == core::mem::discriminant(other),
             _ => false,
@@ -163,6 +237,7 @@ impl<'gc> Value<'gc> {
     pub fn as_number(self) -> Result<f64, VmError> {
         match self {
             Value::Number(value) => Ok(value),
+            Value::Int(value) => Ok(value as f64),
             a => Err(VmError::RuntimeError(format!(
                 "cannot convert to number: {}",
                 a
@@ -170,10 +245,83 @@ impl<'gc> Value<'gc> {
         }
     }
 
+    // The integer value, a float is accepted if it has no fractional part.
+    pub fn as_int(self) -> Result<i64, VmError> {
+        self.as_exact_int()
+            .ok_or_else(|| VmError::RuntimeError(format!("cannot convert to integer: {}", self)))
+    }
+
+    // The integer the value represents exactly, if any. Number literals are floats,
+    // so `id + 1` stays an integer when `id` is one.
+    pub(crate) fn as_exact_int(&self) -> Option<i64> {
+        match *self {
+            Value::Int(value) => Some(value),
+            Value::Number(value)
+                if value.fract() == 0.0 && value >= i64::MIN as f64 && value < i64::MAX as f64 =>
+            {
+                Some(value as i64)
+            }
+            _ => None,
+        }
+    }
+
+    // Both values as integers if either is an integer and the other represents one,
+    // integers operate with floats as floats otherwise.
+    pub(crate) fn int_operands(&self, other: &Value<'gc>) -> Option<(i64, i64)> {
+        if !self.is_int() && !other.is_int() {
+            return None;
+        }
+        Some((self.as_exact_int()?, other.as_exact_int()?))
+    }
+
+    // The value as a decimal operand, integers and floats without a fractional
+    // part convert exactly. Other floats are rejected instead of leaking
+    // their binary rounding errors into the decimals.
+    pub(crate) fn as_decimal_operand(&self) -> Result<Decimal, &'static str> {
+        match self {
+            Value::Decimal(d) => Ok((**d).clone()),
+            Value::Int(_) | Value::Number(_) => self
+                .as_exact_int()
+                .map(Decimal::from)
+                .ok_or("Cannot mix decimals and floats, convert the float with decimal.Decimal()."),
+            _ => Err("Operands must be numbers."),
+        }
+    }
+
+    // Both values as decimals if either is a decimal, or why they can't be.
+    pub(crate) fn decimal_operands(
+        &self,
+        other: &Value<'gc>,
+    ) -> Option<Result<(Decimal, Decimal), &'static str>> {
+        if !matches!(self, Value::Decimal(_)) && !matches!(other, Value::Decimal(_)) {
+            return None;
+        }
+        Some(
+            self.as_decimal_operand()
+                .and_then(|a| Ok((a, other.as_decimal_operand()?))),
+        )
+    }
+
+    // Compare two numbers, integers and decimals exactly, None if either isn't a number.
+    pub fn compare_number(&self, other: &Value<'gc>) -> Option<std::cmp::Ordering> {
+        if let Some(operands) = self.decimal_operands(other) {
+            return operands.ok().map(|(a, b)| a.cmp(&b));
+        }
+        if let Some((a, b)) = self.int_operands(other) {
+            return Some(a.cmp(&b));
+        }
+        if self.is_number() && other.is_number() {
+            self.as_number().ok()?.partial_cmp(&other.as_number().ok()?)
+        } else {
+            None
+        }
+    }
+
     pub fn as_boolean(&self) -> bool {
         match self {
             Value::Boolean(value) => *value,
             Value::Number(value) => *value != 0.0,
+            Value::Int(value) => *value != 0,
             Value::String(s) => !s.is_empty(),
             _ => false,
         }
@@ -197,6 +345,16 @@ impl<'gc> Value<'gc> {
         }
     }
 
+    // The bytes of a string or of binary data
+    pub fn as_byte_slice(&self) -> Option<&[u8]> {
+        match self {
+            Value::String(s) => Some(s.as_bytes()),
+            Value::IoString(s) => Some(s.as_bytes()),
+            Value::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
     // Helper to create a new string value
     pub fn new_string(ctx: Context<'gc>, s: &str, should_intern: bool) -> Value<'gc> {
         if should_intern {
@@ -224,6 +382,42 @@ impl<'gc> Value<'gc> {
         }
     }
 
+    pub fn as_dict(self) -> Result<GcRefLock<'gc, Dict<'gc>>, VmError> {
+        match self {
+            Value::Dict(dict) => Ok(dict),
+            v => Err(VmError::RuntimeError(format!(
+                "cannot convert to dict, the value is {v}"
+            ))),
+        }
+    }
+
+    pub fn as_set(self) -> Result<GcRefLock<'gc, Set<'gc>>, VmError> {
+        match self {
+            Value::Set(set) => Ok(set),
+            v => Err(VmError::RuntimeError(format!(
+                "cannot convert to set, the value is {v}"
+            ))),
+        }
+    }
+
+    pub fn as_decimal(self) -> Result<Gc<'gc, Decimal>, VmError> {
+        match self {
+            Value::Decimal(d) => Ok(d),
+            v => Err(VmError::RuntimeError(format!(
+                "cannot convert to decimal, the value is {v}"
+            ))),
+        }
+    }
+
+    pub fn as_datetime(self) -> Result<Gc<'gc, DateTime>, VmError> {
+        match self {
+            Value::DateTime(dt) => Ok(dt),
+            v => Err(VmError::RuntimeError(format!(
+                "cannot convert to datetime, the value is {v}"
+            ))),
+        }
+    }
+
     pub fn as_agent(self) -> Result<Gc<'gc, Agent<'gc>>, VmError> {
         match self {
             Value::Agent(agent) => Ok(agent),
@@ -279,7 +473,11 @@ impl<'gc> Value<'gc> {
     }
 
     pub fn is_number(&self) -> bool {
-        matches!(self, Value::Number(_))
+        matches!(self, Value::Number(_) | Value::Int(_))
+    }
+
+    pub fn is_int(&self) -> bool {
+        matches!(self, Value::Int(_))
     }
 
     pub fn is_boolean(&self) -> bool {
@@ -297,7 +495,12 @@ impl<'gc> Value<'gc> {
     pub fn from_serde_value(ctx: Context<'gc>, value: &serde_json::Value) -> Value<'gc> {
         match value {
             serde_json::Value::Bool(b) => Value::Boolean(*b),
-            serde_json::Value::Number(number) => Value::Number(number.as_f64().unwrap()),
+            serde_json::Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+                (Some(n), _) => Value::Int(n),
+                // Beyond the integers, kept exact as a decimal
+                (None, Some(n)) => Value::Decimal(Gc::new(&ctx, Decimal::new(n.into(), 0))),
+                (None, None) => Value::Number(number.as_f64().unwrap()),
+            },
             serde_json::Value::String(str) => {
                 let s = ctx.intern(str.as_bytes());
                 Value::from(s)
@@ -307,7 +510,7 @@ impl<'gc> Value<'gc> {
                     .into_iter()
                     .map(|(key, value)| {
                         (
-                            ctx.intern(key.as_bytes()),
+                            ctx.intern_name(key.as_bytes()),
                             Value::from_serde_value(ctx, value),
                         )
                     })
@@ -328,9 +531,19 @@ impl<'gc> Value<'gc> {
     pub fn to_serde_value(&self) -> serde_json::Value {
         match self {
             Value::Number(n) => (*n).into(),
+            Value::Int(n) => (*n).into(),
+            // A string keeps all the digits, a JSON number would be read as a
+            // float. The integers beyond i64 of a JSON are numbers again.
+            Value::Decimal(d) => match d.to_u64() {
+                Some(n) if d.scale() == 0 && n > i64::MAX as u64 => n.into(),
+                _ => d.to_string().into(),
+            },
+            Value::DateTime(dt) => dt.to_string().into(),
             Value::Boolean(b) => (*b).into(),
             Value::String(str) => str.to_string().into(),
             Value::IoString(str) => str.to_string().into(),
+            // JSON has no binary data, the bytes are sent as base64
+            Value::Bytes(bytes) => data_encoding::BASE64.encode(bytes).into(),
             Value::List(list) => serde_json::Value::Array(
                 list.borrow()
                     .data
@@ -345,6 +558,16 @@ impl<'gc> Value<'gc> {
                     .map(|(k, v)| (k.to_string(), v.to_serde_value()))
                     .collect(),
             ),
+            Value::Set(set) => {
+                serde_json::Value::Array(set.borrow().iter().map(|v| v.to_serde_value()).collect())
+            }
+            // JSON keys are strings
+            Value::Dict(dict) => serde_json::Value::Object(
+                dict.borrow()
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_serde_value()))
+                    .collect(),
+            ),
             Value::Instance(instance) => instance
                 .borrow()
                 .fields
@@ -374,6 +597,12 @@ impl From<u64> for Value<'_> {
     }
 }
 
+impl From<i64> for Value<'_> {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
 impl From<f64> for Value<'_> {
     fn from(value: f64) -> Self {
         Value::Number(value)
@@ -427,3 +656,9 @@ impl<'gc> From<Gc<'gc, Agent<'gc>>> for Value<'gc> {
         Value::Agent(value)
     }
 }
+
+impl<'gc> From<Gc<'gc, Workflow<'gc>>> for Value<'gc> {
+    fn from(value: Gc<'gc, Workflow<'gc>>) -> Self {
+        Value::Workflow(value)
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use aiscript_lexer::Warning;
+
+static EXPERIMENTAL: AtomicBool = AtomicBool::new(false);
+
+// The call sites of the deprecated functions already warned, by the name
+// of the callee, the calling function and the line of the call.
+static WARNED: LazyLock<Mutex<HashSet<(String, String, u32)>>> = LazyLock::new(Mutex::default);
+
+/// Allow the calls of the `@experimental` functions and classes, enabled by
+/// `experimental = true` in the `[language]` section of project.toml. Off by
+/// default, calling them is a runtime error.
+pub fn set_experimental(enabled: bool) {
+    EXPERIMENTAL.store(enabled, Ordering::Relaxed);
+}
+
+pub(crate) fn experimental_enabled() -> bool {
+    EXPERIMENTAL.load(Ordering::Relaxed)
+}
+
+/// Print the warning of a call of a deprecated function of another module,
+/// which the compiler of the calling script can't see, once per call site
+/// for the process.
+pub(crate) fn warn_deprecated(name: &str, note: &str, caller: &str, line: u32) {
+    let site = (name.to_owned(), caller.to_owned(), line);
+    if !WARNED.lock().unwrap().insert(site) {
+        return;
+    }
+    let message = if note.is_empty() {
+        format!("'{name}' is deprecated.")
+    } else {
+        format!("'{name}' is deprecated: {note}")
+    };
+    eprintln!("{}", Warning { line, message });
+}
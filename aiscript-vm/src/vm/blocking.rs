@@ -0,0 +1,116 @@
+use std::{
+    future::Future,
+    sync::{Arc, OnceLock},
+    thread,
+    time::Instant,
+};
+
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::Semaphore,
+    task,
+};
+
+use super::{State, VmError, with_deadline};
+
+// The fuel of an instruction is charged for every microsecond of a blocking
+// call, so a script can't dodge its fuel limit with heavy natives.
+const FUEL_PER_MICRO: u64 = 1;
+
+static SLOTS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+/// Limit the blocking calls of the natives (file IO, processes, password
+/// hashing) running at once across the VMs, twice the number of CPUs by
+/// default. Only the first limit set is used.
+pub fn set_blocking_limit(limit: usize) {
+    let _ = SLOTS.set(Arc::new(Semaphore::new(limit.max(1))));
+}
+
+fn slots() -> Arc<Semaphore> {
+    SLOTS
+        .get_or_init(|| {
+            let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+            Arc::new(Semaphore::new(cpus * 2))
+        })
+        .clone()
+}
+
+/// Block the thread of the VM on a future. On a worker of the multi-threaded
+/// runtime, e.g. the async main thread running the REPL, the worker hands its
+/// tasks over first, as [`Handle::block_on`] panics there.
+pub(crate) fn block_on<F: Future>(handle: &Handle, future: F) -> F::Output {
+    match handle.runtime_flavor() {
+        RuntimeFlavor::MultiThread => task::block_in_place(|| handle.block_on(future)),
+        _ => handle.block_on(future),
+    }
+}
+
+/// Run a blocking call of a native on the blocking threads of the runtime
+/// once a slot is free, instead of on the thread of the VM, within the
+/// deadline of the request. Its duration is charged to the fuel of the
+/// script. Without a runtime, e.g. in the tests, it runs in place.
+pub(crate) fn run_blocking<T, F>(state: &mut State, call: F) -> Result<T, VmError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let started = Instant::now();
+    let result = match Handle::try_current() {
+        Ok(handle) => block_on(
+            &handle,
+            with_deadline(state.deadline, async {
+                let permit = slots().acquire_owned().await.expect("never closed");
+                // The slot is held until the call returns, even past the deadline
+                task::spawn_blocking(move || {
+                    let _permit = permit;
+                    call()
+                })
+                .await
+            }),
+        )?
+        .map_err(|err| VmError::RuntimeError(format!("The blocking call failed: {err}")))?,
+        Err(_) => call(),
+    };
+    let micros = started.elapsed().as_micros().try_into().unwrap_or(u64::MAX);
+    state
+        .limits
+        .consume(micros.saturating_mul(FUEL_PER_MICRO))?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Vm;
+
+    #[test]
+    fn test_blocking_fuel() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _runtime = runtime.enter();
+        let mut vm = Vm::default();
+        vm.set_fuel_limit(Some(1_000_000));
+        vm.compile("use std.process;\nreturn process.run(\"sleep\", [\"0.01\"]).code;")
+            .unwrap();
+        assert_eq!(vm.interpret().unwrap(), crate::ReturnValue::Number(0.0));
+
+        let mut vm = Vm::default();
+        vm.set_fuel_limit(Some(1_000));
+        vm.compile("use std.process;\nprocess.run(\"sleep\", [\"0.01\"]);")
+            .unwrap();
+        assert!(matches!(
+            vm.interpret(),
+            Err(crate::VmError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_blocking_in_runtime() {
+        // Like the REPL, the VM runs on the thread blocked on the runtime
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let mut vm = Vm::default();
+            vm.compile("use std.process;\nreturn process.run(\"true\", []).code;")
+                .unwrap();
+            assert_eq!(vm.interpret().unwrap(), crate::ReturnValue::Number(0.0));
+        });
+    }
+}
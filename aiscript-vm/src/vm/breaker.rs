@@ -0,0 +1,273 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{LazyLock, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use super::{VmError, chaos};
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_reset_timeout() -> u64 {
+    30
+}
+
+fn default_half_open_probes() -> u32 {
+    1
+}
+
+/// The circuit breaker of the external dependencies (AI providers, std.http hosts
+/// and database pools), configured in project.toml:
+///
+/// ```toml
+/// [circuit_breaker]
+/// failure_threshold = 5  # consecutive failures to open the circuit
+/// reset_timeout = 30     # seconds the circuit stays open before probing
+/// half_open_probes = 1   # calls let through to probe a half-open circuit
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct CircuitBreakerConfig {
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    #[serde(default = "default_reset_timeout")]
+    pub reset_timeout: u64,
+    #[serde(default = "default_half_open_probes")]
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            reset_timeout: default_reset_timeout(),
+            half_open_probes: default_half_open_probes(),
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// Enable the circuit breaker for all VMs in the process.
+    pub fn install(self) {
+        *CONFIG.write().unwrap() = Some(self);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// A snapshot of the breaker of a dependency, see [`circuit_breakers`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BreakerStatus {
+    pub dependency: String,
+    pub state: BreakerState,
+    // Consecutive failures since the last success.
+    pub failures: u32,
+    // Calls rejected while the circuit was open.
+    pub rejected: u64,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    failures: u32,
+    rejected: u64,
+    opened_at: Instant,
+    // Probes in flight while half-open.
+    probes: u32,
+}
+
+impl Breaker {
+    fn new(now: Instant) -> Self {
+        Self {
+            state: BreakerState::Closed,
+            failures: 0,
+            rejected: 0,
+            opened_at: now,
+            probes: 0,
+        }
+    }
+
+    // How long the call has to wait for the next probe, None if it can be sent now.
+    fn acquire(&mut self, config: &CircuitBreakerConfig, now: Instant) -> Option<Duration> {
+        let reset_timeout = Duration::from_secs(config.reset_timeout);
+        if self.state == BreakerState::Open && now.duration_since(self.opened_at) >= reset_timeout {
+            self.state = BreakerState::HalfOpen;
+            self.probes = 0;
+        }
+        match self.state {
+            BreakerState::Closed => None,
+            BreakerState::HalfOpen if self.probes < config.half_open_probes => {
+                self.probes += 1;
+                None
+            }
+            _ => {
+                self.rejected += 1;
+                Some(reset_timeout.saturating_sub(now.duration_since(self.opened_at)))
+            }
+        }
+    }
+
+    fn report(&mut self, config: &CircuitBreakerConfig, healthy: bool, now: Instant) {
+        if healthy {
+            self.state = BreakerState::Closed;
+            self.failures = 0;
+            return;
+        }
+        self.failures = self.failures.saturating_add(1);
+        // A failed probe opens the circuit again right away
+        if self.state == BreakerState::HalfOpen || self.failures >= config.failure_threshold {
+            self.state = BreakerState::Open;
+            self.opened_at = now;
+        }
+    }
+}
+
+static CONFIG: RwLock<Option<CircuitBreakerConfig>> = RwLock::new(None);
+
+// Breakers are shared by all VMs in the process, keyed by dependency.
+static BREAKERS: LazyLock<Mutex<BTreeMap<String, Breaker>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+/// The permission to call a dependency, the outcome of the call is reported
+/// to the breaker. A permit dropped without a report, e.g. the call was
+/// abandoned on the request deadline, counts as a failure.
+#[must_use]
+pub(crate) struct Permit {
+    dependency: Option<String>,
+}
+
+impl Permit {
+    pub(crate) fn report(mut self, healthy: bool) {
+        if let Some(dependency) = self.dependency.take() {
+            record(dependency, healthy);
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(dependency) = self.dependency.take() {
+            record(dependency, false);
+        }
+    }
+}
+
+fn record(dependency: String, healthy: bool) {
+    let Some(config) = CONFIG.read().unwrap().clone() else {
+        return;
+    };
+    let now = Instant::now();
+    BREAKERS
+        .lock()
+        .unwrap()
+        .entry(dependency)
+        .or_insert_with(|| Breaker::new(now))
+        .report(&config, healthy, now);
+}
+
+/// Ask the breaker of the dependency for a call, the call fails fast with
+/// [`VmError::CircuitOpen`] while the dependency is considered down.
+pub(crate) fn acquire(dependency: impl Display) -> Result<Permit, VmError> {
+    let dependency = dependency.to_string();
+    let permit = acquire_permit(dependency.clone())?;
+    // An injected fault fails the call, the dropped permit reports it
+    chaos::inject(&dependency)?;
+    Ok(permit)
+}
+
+fn acquire_permit(dependency: String) -> Result<Permit, VmError> {
+    let Some(config) = CONFIG.read().unwrap().clone() else {
+        return Ok(Permit { dependency: None });
+    };
+    let now = Instant::now();
+    let wait = BREAKERS
+        .lock()
+        .unwrap()
+        .entry(dependency.clone())
+        .or_insert_with(|| Breaker::new(now))
+        .acquire(&config, now);
+    match wait {
+        None => Ok(Permit {
+            dependency: Some(dependency),
+        }),
+        Some(wait) => Err(VmError::CircuitOpen {
+            dependency,
+            retry_after: wait.as_secs().max(1),
+        }),
+    }
+}
+
+/// The state of the breaker of every dependency called so far.
+pub fn circuit_breakers() -> Vec<BreakerStatus> {
+    BREAKERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(dependency, breaker)| BreakerStatus {
+            dependency: dependency.clone(),
+            state: breaker.state,
+            failures: breaker.failures,
+            rejected: breaker.rejected,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker() {
+        let config = CircuitBreakerConfig {
+            failure_threshold: 2,
+            reset_timeout: 10,
+            half_open_probes: 1,
+        };
+        let now = Instant::now();
+        let mut breaker = Breaker::new(now);
+
+        assert_eq!(breaker.acquire(&config, now), None);
+        breaker.report(&config, false, now);
+        assert_eq!(breaker.state, BreakerState::Closed);
+        breaker.report(&config, false, now);
+        assert_eq!(breaker.state, BreakerState::Open);
+        assert_eq!(
+            breaker.acquire(&config, now + Duration::from_secs(4)),
+            Some(Duration::from_secs(6))
+        );
+        assert_eq!(breaker.rejected, 1);
+
+        // Only one probe is let through when half-open
+        let later = now + Duration::from_secs(10);
+        assert_eq!(breaker.acquire(&config, later), None);
+        assert_eq!(breaker.state, BreakerState::HalfOpen);
+        assert!(breaker.acquire(&config, later).is_some());
+        breaker.report(&config, false, later);
+        assert_eq!(breaker.state, BreakerState::Open);
+
+        let later = later + Duration::from_secs(10);
+        assert_eq!(breaker.acquire(&config, later), None);
+        breaker.report(&config, true, later);
+        assert_eq!(breaker.state, BreakerState::Closed);
+        assert_eq!(breaker.failures, 0);
+    }
+}
@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, sync::RwLock, thread, time::Duration};
+
+use serde::Deserialize;
+
+use super::VmError;
+
+/// The faults injected into the calls of the external dependencies (AI
+/// providers, std.http hosts and database pools), to check the fallbacks,
+/// retries and circuit breakers handle them. Configured in project.toml by
+/// the dependency names of the circuit breaker, a trailing `*` matches a
+/// prefix, and only injected by `aiscript serve --chaos`:
+///
+/// ```toml
+/// [chaos."ai:openai"]
+/// latency_ms = 2000  # delay added to every call
+/// error_rate = 0.3   # share of the calls failing
+///
+/// [chaos."db:*"]
+/// error_rate = 1.0
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct ChaosConfig {
+    pub faults: BTreeMap<String, Fault>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fault {
+    #[serde(default)]
+    pub latency_ms: u64,
+    #[serde(default)]
+    pub error_rate: f64,
+}
+
+impl ChaosConfig {
+    /// Inject the faults into the calls of all VMs in the process, an empty
+    /// config stops injecting them.
+    pub fn install(self) {
+        *CONFIG.write().unwrap() = self;
+    }
+
+    // The fault of the dependency, an exact name wins over the longest prefix.
+    fn fault(&self, dependency: &str) -> Option<&Fault> {
+        if let Some(fault) = self.faults.get(dependency) {
+            return Some(fault);
+        }
+        self.faults
+            .iter()
+            .filter_map(|(pattern, fault)| {
+                let prefix = pattern.strip_suffix('*')?;
+                dependency
+                    .starts_with(prefix)
+                    .then_some((prefix.len(), fault))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map(|(_, fault)| fault)
+    }
+}
+
+static CONFIG: RwLock<ChaosConfig> = RwLock::new(ChaosConfig {
+    faults: BTreeMap::new(),
+});
+
+/// Delay the call of the dependency and fail it at the configured rate,
+/// like a slow or failing dependency would.
+pub(crate) fn inject(dependency: &str) -> Result<(), VmError> {
+    let Some(fault) = CONFIG.read().unwrap().fault(dependency).cloned() else {
+        return Ok(());
+    };
+    if fault.latency_ms > 0 {
+        thread::sleep(Duration::from_millis(fault.latency_ms));
+    }
+    if fault.error_rate > 0.0 && rand::random::<f64>() < fault.error_rate {
+        return Err(VmError::RuntimeError(format!(
+            "Injected fault: the call to '{dependency}' failed."
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fault_matching() {
+        let config: ChaosConfig = serde_json::from_value(serde_json::json!({
+            "ai:openai": {"latency_ms": 100},
+            "ai:*": {"error_rate": 0.5},
+            "*": {"error_rate": 0.1},
+        }))
+        .unwrap();
+        assert_eq!(config.fault("ai:openai").unwrap().latency_ms, 100);
+        assert_eq!(config.fault("ai:anthropic").unwrap().error_rate, 0.5);
+        assert_eq!(config.fault("db:postgres").unwrap().error_rate, 0.1);
+        assert!(ChaosConfig::default().fault("db:postgres").is_none());
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The trace file the coverage is merged into, unless `AISCRIPT_COVERAGE` is set.
+pub const DEFAULT_COVERAGE_TRACE: &str = ".aiscript/coverage.json";
+
+/// The execution counts of the lines of the scripts, by file.
+///
+/// The VMs built with the `coverage` feature register the executable lines
+/// of the chunks they compile with a count of 0, and count the times a frame
+/// enters each line, so the lines never run are in the trace as well. The
+/// route handlers are counted at their lines in the route file.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoverageTrace {
+    pub files: BTreeMap<PathBuf, BTreeMap<u32, u64>>,
+}
+
+impl CoverageTrace {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let trace = fs::read_to_string(path)?;
+        serde_json::from_str(&trace).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    /// Add the counts of the other trace to this one.
+    pub fn merge(&mut self, other: CoverageTrace) {
+        for (file, lines) in other.files {
+            let counts = self.files.entry(file).or_default();
+            for (line, count) in lines {
+                *counts.entry(line).or_default() += count;
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+// The counts recorded by all the VMs of the process since the last save.
+static COUNTS: LazyLock<Mutex<CoverageTrace>> = LazyLock::new(Mutex::default);
+
+/// The trace file of `AISCRIPT_COVERAGE`, or else [`DEFAULT_COVERAGE_TRACE`].
+pub fn coverage_trace_path() -> PathBuf {
+    env::var_os("AISCRIPT_COVERAGE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_COVERAGE_TRACE))
+}
+
+/// Merge the counts recorded by the process into the trace file, they are
+/// reset so the next save doesn't add them twice. Nothing is written when
+/// nothing was recorded, e.g. without the `coverage` feature.
+pub fn save_coverage() -> io::Result<()> {
+    let recorded = std::mem::take(&mut *COUNTS.lock().unwrap());
+    if recorded.is_empty() {
+        return Ok(());
+    }
+    let path = coverage_trace_path();
+    let mut trace = match CoverageTrace::load(&path) {
+        Ok(trace) => trace,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => CoverageTrace::default(),
+        Err(err) => return Err(err),
+    };
+    trace.merge(recorded);
+    trace.save(&path)
+}
+
+/// Register the executable lines of a compiled chunk, counted 0 until run.
+#[cfg(feature = "coverage")]
+pub(crate) fn register(file: &Path, lines: impl IntoIterator<Item = u32>) {
+    let mut trace = COUNTS.lock().unwrap();
+    if !trace.files.contains_key(file) {
+        trace.files.insert(file.to_owned(), BTreeMap::new());
+    }
+    let counts = trace.files.get_mut(file).unwrap();
+    for line in lines {
+        counts.entry(line).or_default();
+    }
+}
+
+#[cfg(feature = "coverage")]
+pub(crate) fn hit(file: &Path, line: u32) {
+    let mut counts = COUNTS.lock().unwrap();
+    match counts.files.get_mut(file) {
+        Some(counts) => *counts.entry(line).or_default() += 1,
+        None => {
+            counts
+                .files
+                .insert(file.to_owned(), BTreeMap::from([(line, 1)]));
+        }
+    }
+}
+
+/// The lines entered by the frames of a VM.
+#[cfg(feature = "coverage")]
+#[derive(Debug, Default)]
+pub(crate) struct LineTracker {
+    // The line last run at each depth of the call stack
+    lines: Vec<u32>,
+    // The file of each function run and the offset of its lines in the
+    // file, None for the functions outside of a file
+    pub files: std::collections::HashMap<usize, Option<(PathBuf, u32)>>,
+    // The file of the script and the offset of its lines in the file when
+    // compiled from a part of it, see `CompiledProgram::with_origin`
+    pub origin: Option<(PathBuf, u32)>,
+}
+
+#[cfg(feature = "coverage")]
+impl LineTracker {
+    /// Whether the line run at the depth of the call stack is a new one
+    /// for the depth, the lines of the deeper frames are forgotten.
+    pub fn enter_line(&mut self, depth: usize, line: u32) -> bool {
+        self.lines.resize(depth, 0);
+        let last = &mut self.lines[depth - 1];
+        let entered = *last != line;
+        *last = line;
+        entered
+    }
+
+    /// The file of the script outside of the modules and the offset of its
+    /// lines, the origin of the program or else the file run.
+    pub fn script_file<'a>(&'a self, script_path: &'a Option<PathBuf>) -> Option<(&'a Path, u32)> {
+        match &self.origin {
+            Some((file, offset)) => Some((file, *offset)),
+            None => script_path.as_deref().map(|file| (file, 0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut trace = CoverageTrace {
+            files: BTreeMap::from([("a.ai".into(), BTreeMap::from([(1, 2), (2, 0)]))]),
+        };
+        trace.merge(CoverageTrace {
+            files: BTreeMap::from([
+                ("a.ai".into(), BTreeMap::from([(2, 1), (3, 0)])),
+                ("b.ai".into(), BTreeMap::from([(1, 1)])),
+            ]),
+        });
+        assert_eq!(
+            trace.files[Path::new("a.ai")],
+            BTreeMap::from([(1, 2), (2, 1), (3, 0)])
+        );
+        assert_eq!(trace.files[Path::new("b.ai")], BTreeMap::from([(1, 1)]));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let path = env::temp_dir().join("aiscript_test_coverage/trace.json");
+        let trace = CoverageTrace {
+            files: BTreeMap::from([("routes/users.ai".into(), BTreeMap::from([(3, 4), (7, 0)]))]),
+        };
+        trace.save(&path).unwrap();
+        assert_eq!(CoverageTrace::load(&path).unwrap(), trace);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,44 @@
+use std::{future::Future, time::Instant};
+
+use super::VmError;
+
+/// Run an upstream call (AI provider, HTTP request or database query) within the
+/// deadline of the request, it fails with [`VmError::DeadlineExceeded`] once the
+/// deadline has passed instead of holding the resources the request no longer waits for.
+pub(crate) async fn with_deadline<F: Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> Result<F::Output, VmError> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), future)
+            .await
+            .map_err(|_| VmError::DeadlineExceeded),
+        None => Ok(future.await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_with_deadline() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert_eq!(
+            runtime
+                .block_on(with_deadline(Some(deadline), async { 1 }))
+                .unwrap(),
+            1
+        );
+        assert!(matches!(
+            runtime.block_on(with_deadline(Some(deadline), async {
+                tokio::time::sleep(Duration::from_secs(1)).await
+            })),
+            Err(VmError::DeadlineExceeded)
+        ));
+        assert!(runtime.block_on(with_deadline(None, async {})).is_ok());
+    }
+}
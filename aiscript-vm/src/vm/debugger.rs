@@ -0,0 +1,155 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// How the script resumes after a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugAction {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next line, stepping into the called functions.
+    StepIn,
+    /// Pause at the next line of the current function or its callers.
+    Next,
+    /// Pause once the current function has returned.
+    StepOut,
+    /// Abort the script.
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// A variable of a paused script, its value is displayed as printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugVariable {
+    pub name: String,
+    pub value: String,
+}
+
+/// A frame of the call stack of a paused script.
+#[derive(Debug, Clone)]
+pub struct DebugFrame {
+    pub function: String,
+    /// The file of the function, unknown if the script isn't run from a file.
+    pub file: Option<PathBuf>,
+    pub line: u32,
+    pub locals: Vec<DebugVariable>,
+}
+
+/// The state of a paused script, the innermost frame first.
+#[derive(Debug, Clone)]
+pub struct Paused {
+    pub reason: PauseReason,
+    pub frames: Vec<DebugFrame>,
+    pub globals: Vec<DebugVariable>,
+}
+
+/// Decides how a paused script resumes, e.g. by asking an editor attached
+/// with the debug adapter protocol.
+pub trait DebugHandler {
+    fn paused(&mut self, paused: &Paused) -> DebugAction;
+}
+
+/// The breakpoints and the stepping state of a debugged script.
+pub struct Debugger {
+    handler: Box<dyn DebugHandler>,
+    breakpoints: HashMap<PathBuf, HashSet<u32>>,
+    mode: Mode,
+    // Whether the next pause is the one before the first line
+    on_entry: bool,
+    // The line last run at each depth of the call stack
+    lines: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    StepIn,
+    // Pause at a new line at the depth or shallower
+    Next(usize),
+    // Pause at a new line shallower than the depth
+    StepOut(usize),
+}
+
+impl Debugger {
+    pub fn new(handler: impl DebugHandler + 'static) -> Self {
+        Self {
+            handler: Box::new(handler),
+            breakpoints: HashMap::new(),
+            mode: Mode::Continue,
+            on_entry: false,
+            lines: Vec::new(),
+        }
+    }
+
+    /// Pause before the first line of the script.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.mode = Mode::StepIn;
+        self.on_entry = true;
+        self
+    }
+
+    /// Replace the breakpoints of the file.
+    pub fn set_breakpoints(&mut self, file: &Path, lines: impl IntoIterator<Item = u32>) {
+        self.breakpoints
+            .insert(canonical(file), lines.into_iter().collect());
+    }
+
+    /// Whether the line run at the depth of the call stack is a new one
+    /// for the depth, the lines of the deeper frames are forgotten.
+    pub(crate) fn enter_line(&mut self, depth: usize, line: u32) -> bool {
+        self.lines.resize(depth, 0);
+        let last = &mut self.lines[depth - 1];
+        let entered = *last != line;
+        *last = line;
+        entered
+    }
+
+    /// Why the script pauses at the newly entered line, if it does.
+    pub(crate) fn should_pause(
+        &self,
+        depth: usize,
+        file: Option<&Path>,
+        line: u32,
+    ) -> Option<PauseReason> {
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::StepIn => true,
+            Mode::Next(at) => depth <= at,
+            Mode::StepOut(at) => depth < at,
+        };
+        if stepped {
+            return Some(if self.on_entry {
+                PauseReason::Entry
+            } else {
+                PauseReason::Step
+            });
+        }
+        file.and_then(|file| self.breakpoints.get(file))
+            .filter(|lines| lines.contains(&line))
+            .map(|_| PauseReason::Breakpoint)
+    }
+
+    pub(crate) fn pause(&mut self, paused: &Paused, depth: usize) -> DebugAction {
+        self.on_entry = false;
+        let action = self.handler.paused(paused);
+        self.mode = match action {
+            DebugAction::Continue | DebugAction::Stop => Mode::Continue,
+            DebugAction::StepIn => Mode::StepIn,
+            DebugAction::Next => Mode::Next(depth),
+            DebugAction::StepOut => Mode::StepOut(depth),
+        };
+        action
+    }
+}
+
+// The breakpoints and the files of the functions are compared canonicalized.
+pub(crate) fn canonical(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_owned())
+}
@@ -1,21 +1,42 @@
 use std::collections::HashMap;
 
 use aiscript_arena::{Gc, RefLock};
+use serde::de::DeserializeOwned;
 
 use crate::{
-    NativeFn, ReturnValue, Value,
+    NativeFn, Value,
     builtins::response,
     object::{Instance, Object},
 };
 
-use super::Vm;
+use super::{Vm, VmError};
 
 impl Vm {
-    pub fn get_global(&mut self, name: &'static str) -> Option<ReturnValue> {
+    /// Define a global variable of the scripts from a JSON value, objects
+    /// and arrays are converted to objects and arrays of the VM.
+    pub fn set_global(&mut self, name: &str, value: serde_json::Value) {
         self.arena.mutate_root(|_mc, state| {
-            let name = state.intern_static(name);
-            state.globals.get(&name).copied().map(ReturnValue::from)
-        })
+            let ctx = state.get_context();
+            let name = state.intern(name.as_bytes());
+            state
+                .globals
+                .insert(name, Value::from_serde_value(ctx, &value));
+        });
+    }
+
+    /// The value of a global variable converted to `T` through its JSON
+    /// representation, `None` if the global isn't defined.
+    pub fn get_global<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>, VmError> {
+        let value = self.arena.mutate_root(|_mc, state| {
+            let name = state.intern(name.as_bytes());
+            state.globals.get(&name).map(Value::to_serde_value)
+        });
+        value
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|err| {
+                VmError::RuntimeError(format!("Invalid value of global '{name}': {err}"))
+            })
     }
 
     pub fn register_extra_native_functions(&mut self) {
@@ -107,7 +128,7 @@ mod tests {
         assert_eq!(result, ReturnValue::String("abc".into()));
         vm.compile("return test2;").unwrap();
         let result = vm.interpret().unwrap();
-        assert_eq!(result, ReturnValue::Number(123.0));
+        assert_eq!(result, ReturnValue::Int(123));
         vm.compile("return test3;").unwrap();
         let result = vm.interpret().unwrap();
         assert_eq!(result, ReturnValue::Boolean(true));
@@ -131,4 +152,36 @@ mod tests {
         assert_eq!(request.get("test").unwrap(), true);
         assert!(request.get("abc").is_none());
     }
+
+    #[test]
+    fn test_set_and_get_global() {
+        #[derive(Debug, PartialEq, serde::Deserialize)]
+        struct Summary {
+            user: String,
+            total: i64,
+            tags: Vec<String>,
+        }
+
+        let mut vm = Vm::default();
+        vm.set_global(
+            "ctx",
+            serde_json::json!({"user": "ada", "items": [{"price": 3}, {"price": 4}]}),
+        );
+        vm.compile(
+            "let total = 0;\nfor let i = 0; i < len(ctx.items); i += 1 {\n  total += ctx.items[i].price;\n}\nlet summary = {user: ctx.user, total: total, tags: [\"new\"]};",
+        )
+        .unwrap();
+        vm.interpret().unwrap();
+        assert_eq!(
+            vm.get_global::<Summary>("summary").unwrap(),
+            Some(Summary {
+                user: "ada".into(),
+                total: 7,
+                tags: vec!["new".into()],
+            })
+        );
+        assert_eq!(vm.get_global::<i64>("total").unwrap(), Some(7));
+        assert_eq!(vm.get_global::<i64>("missing").unwrap(), None);
+        assert!(vm.get_global::<i64>("summary").is_err());
+    }
 }
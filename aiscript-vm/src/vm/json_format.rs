@@ -0,0 +1,146 @@
+use aiscript_arena::Collect;
+use aiscript_directive::{Directive, DirectiveParams, FromDirective};
+use chrono::{DateTime, NaiveDate, SecondsFormat};
+
+use crate::Value;
+
+/// The JSON format of a class field declared by `@json("<format>")`, its
+/// value is converted when an instance is serialized to JSON and when it's
+/// created from JSON. The datetimes are Unix timestamps in seconds, like
+/// the ones of std.time.
+#[derive(Copy, Clone, Debug, Collect, PartialEq, Eq)]
+#[collect(require_static)]
+pub enum JsonFormat {
+    // A RFC 3339 string in UTC, e.g. "2024-05-01T08:30:00Z"
+    Rfc3339,
+    // A date string in UTC, e.g. "2024-05-01"
+    Date,
+    // An integer of milliseconds since the Unix epoch
+    TimestampMs,
+}
+
+impl FromDirective for JsonFormat {
+    fn from_directive(directive: Directive) -> Result<Self, String> {
+        let format = match &directive.params {
+            DirectiveParams::Array(values) => match values.as_slice() {
+                [serde_json::Value::String(format)] => format,
+                _ => return Err("Expect @json(\"<format>\").".into()),
+            },
+            DirectiveParams::KeyValue(_) => match directive.get_arg_value("format") {
+                Some(serde_json::Value::String(format)) => format,
+                _ => return Err("Expect @json(\"<format>\").".into()),
+            },
+            DirectiveParams::Directives(_) => return Err("Expect @json(\"<format>\").".into()),
+        };
+        match format.as_str() {
+            "rfc3339" => Ok(JsonFormat::Rfc3339),
+            "date" => Ok(JsonFormat::Date),
+            "timestamp_ms" => Ok(JsonFormat::TimestampMs),
+            _ => Err(format!(
+                "Unknown JSON format '{format}', expect rfc3339, date or timestamp_ms."
+            )),
+        }
+    }
+}
+
+impl JsonFormat {
+    fn expected(self) -> &'static str {
+        match self {
+            JsonFormat::Rfc3339 => "a RFC 3339 datetime",
+            JsonFormat::Date => "a YYYY-MM-DD date",
+            JsonFormat::TimestampMs => "a timestamp in milliseconds",
+        }
+    }
+
+    /// The JSON of the field value, nil is kept as null.
+    pub(crate) fn write(self, value: Value) -> Result<serde_json::Value, String> {
+        let seconds = match value {
+            Value::Nil => return Ok(serde_json::Value::Null),
+            Value::Number(seconds) => seconds,
+            Value::Int(seconds) => seconds as f64,
+            value => return Err(format!("expect a timestamp, got {value}")),
+        };
+        if self == JsonFormat::TimestampMs {
+            return Ok(serde_json::Value::from((seconds * 1000.0).round() as i64));
+        }
+        let nanos = ((seconds - seconds.floor()) * 1e9)
+            .round()
+            .min(999_999_999.0) as u32;
+        let datetime = Some(seconds)
+            .filter(|seconds| seconds.is_finite())
+            .and_then(|seconds| DateTime::from_timestamp(seconds.floor() as i64, nanos))
+            .ok_or_else(|| format!("invalid timestamp {seconds}"))?;
+        Ok(serde_json::Value::String(match self {
+            JsonFormat::Date => datetime.format("%Y-%m-%d").to_string(),
+            _ => datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        }))
+    }
+
+    /// The field value of the JSON value, null is kept as nil.
+    pub(crate) fn read<'gc>(self, value: Value<'gc>) -> Result<Value<'gc>, String> {
+        let seconds = match (self, value) {
+            (_, Value::Nil) => return Ok(Value::Nil),
+            (JsonFormat::TimestampMs, Value::Int(ms)) => ms as f64 / 1000.0,
+            (JsonFormat::TimestampMs, Value::Number(ms)) => ms / 1000.0,
+            (JsonFormat::Rfc3339, Value::String(s)) => {
+                let datetime = DateTime::parse_from_rfc3339(&s.to_string())
+                    .map_err(|_| format!("expect {}, got \"{s}\"", self.expected()))?;
+                datetime.timestamp() as f64 + datetime.timestamp_subsec_nanos() as f64 / 1e9
+            }
+            (JsonFormat::Date, Value::String(s)) => {
+                NaiveDate::parse_from_str(&s.to_string(), "%Y-%m-%d")
+                    .map_err(|_| format!("expect {}, got \"{s}\"", self.expected()))?
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+                    .and_utc()
+                    .timestamp() as f64
+            }
+            (_, value) => return Err(format!("expect {}, got {value}", self.expected())),
+        };
+        Ok(Value::Number(seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_format() {
+        let value = Value::Number(1714552200.5);
+        assert_eq!(
+            JsonFormat::Rfc3339.write(value).unwrap(),
+            "2024-05-01T08:30:00.500Z"
+        );
+        assert_eq!(JsonFormat::Date.write(value).unwrap(), "2024-05-01");
+        assert_eq!(
+            JsonFormat::TimestampMs.write(value).unwrap(),
+            1714552200500i64
+        );
+        assert_eq!(
+            JsonFormat::Rfc3339.write(Value::Nil).unwrap(),
+            serde_json::Value::Null
+        );
+        assert!(JsonFormat::Rfc3339.write(Value::Boolean(true)).is_err());
+
+        assert!(matches!(
+            JsonFormat::TimestampMs.read(Value::Int(1714552200500)),
+            Ok(Value::Number(n)) if n == 1714552200.5
+        ));
+        assert!(JsonFormat::Date.read(Value::Number(1.0)).is_err());
+
+        let directive = |params| Directive {
+            name: "json".into(),
+            params,
+            line: 1,
+        };
+        assert_eq!(
+            JsonFormat::from_directive(directive(DirectiveParams::Array(vec!["date".into()]))),
+            Ok(JsonFormat::Date)
+        );
+        assert!(
+            JsonFormat::from_directive(directive(DirectiveParams::Array(vec!["iso".into()])))
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{borrow::Cow, fmt};
+
+use aiscript_arena::{Gc, RefLock};
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+
+use super::{Context, VmError};
+use crate::{Value, decimal::Decimal, object::Object};
+
+// Parses JSON into VM values like `Value::from_serde_value()` converts it,
+// without a `serde_json::Value` in between. The keys are interned with
+// `Context::intern_name()`, a name shared across the VMs isn't copied.
+#[derive(Clone, Copy)]
+pub(super) struct ValueSeed<'gc>(pub Context<'gc>);
+
+impl<'gc> ValueSeed<'gc> {
+    // The value of the JSON text.
+    pub(super) fn parse(self, json: &[u8]) -> Result<Value<'gc>, VmError> {
+        let mut deserializer = serde_json::Deserializer::from_slice(json);
+        let value = self
+            .deserialize(&mut deserializer)
+            .and_then(|value| deserializer.end().map(|_| value))
+            .map_err(|err| VmError::RuntimeError(format!("Invalid JSON: {err}")))?;
+        Ok(value)
+    }
+}
+
+impl<'de, 'gc> DeserializeSeed<'de> for ValueSeed<'gc> {
+    type Value = Value<'gc>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'gc> Visitor<'de> for ValueSeed<'gc> {
+    type Value = Value<'gc>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Self::Value, E> {
+        Ok(Value::Boolean(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Self::Value, E> {
+        Ok(Value::Int(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Self::Value, E> {
+        Ok(match i64::try_from(n) {
+            Ok(n) => Value::Int(n),
+            // Beyond the integers, kept exact as a decimal
+            Err(_) => Value::Decimal(Gc::new(&self.0, Decimal::new(n.into(), 0))),
+        })
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Self::Value, E> {
+        Ok(Value::Number(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E> {
+        Ok(Value::from(self.0.intern(s.as_bytes())))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(Value::Nil)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(item) = seq.next_element_seed(self)? {
+            data.push(item);
+        }
+        Ok(Value::array(&self.0, data))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut object = Object::default();
+        // Borrowed from the JSON unless escaped
+        while let Some(key) = map.next_key::<Cow<'de, str>>()? {
+            let value = map.next_value_seed(self)?;
+            object
+                .fields
+                .insert(self.0.intern_name(key.as_bytes()), value);
+        }
+        Ok(Value::Object(Gc::new(&self.0, RefLock::new(object))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Arg, ReturnValue, Vm};
+
+    #[test]
+    fn test_json_fields() {
+        let mut vm = Vm::default();
+        vm.compile("fn handler(body) { return [body.user, body.ids, body.big, body.text]; }")
+            .unwrap();
+        vm.interpret().unwrap();
+        let handler = vm.function_id("handler").unwrap();
+        let fields = [
+            ("user", &br#"{"name": "a\"b", "age": 7}"#[..]),
+            ("ids", b"[1, -2, 2.5, null, true]"),
+            ("big", b"18446744073709551615"),
+            ("text", br#""x""#),
+        ];
+        let value = vm
+            .eval_handler(handler, &[Arg::JsonFields(fields.to_vec())])
+            .unwrap();
+        assert_eq!(value, ReturnValue::Json);
+        let mut json = Vec::new();
+        vm.write_json(&mut json, false).unwrap();
+        assert_eq!(
+            String::from_utf8(json).unwrap(),
+            r#"[{"age":7,"name":"a\"b"},[1,-2,2.5,null,true],18446744073709551615,"x"]"#
+        );
+
+        let handler = vm.function_id("handler").unwrap();
+        assert!(
+            vm.eval_handler(handler, &[Arg::JsonFields(vec![("user", b"{")])])
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    cell::{Cell, RefCell},
+    io,
+};
+
+use aiscript_arena::Gc;
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+use super::{State, VmError, state::MAX_JSON_DEPTH};
+use crate::{Value, big_ints_to_strings};
+
+// The VM running the `to_json()` methods of the written values.
+struct Writer<'a, 'gc> {
+    state: RefCell<&'a mut State<'gc>>,
+    big_int_strings: bool,
+    // The error of a `to_json()` or a field format, serde only keeps its message.
+    error: Cell<Option<VmError>>,
+}
+
+// A value written like `State::jsonify()` converts it, nested `depth` levels deep.
+struct Json<'w, 'a, 'gc> {
+    writer: &'w Writer<'a, 'gc>,
+    value: Value<'gc>,
+    depth: usize,
+}
+
+impl<'w, 'a, 'gc> Json<'w, 'a, 'gc> {
+    fn nested(&self, value: Value<'gc>) -> Json<'w, 'a, 'gc> {
+        Json {
+            writer: self.writer,
+            value,
+            depth: self.depth + 1,
+        }
+    }
+
+    fn fail<E: serde::ser::Error>(&self, err: VmError) -> E {
+        let serde_err = E::custom(&err);
+        self.writer.error.set(Some(err));
+        serde_err
+    }
+
+    fn leaf<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut json = self.value.to_serde_value();
+        if self.writer.big_int_strings {
+            big_ints_to_strings(&mut json);
+        }
+        json.serialize(serializer)
+    }
+}
+
+impl Serialize for Json<'_, '_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.depth > MAX_JSON_DEPTH {
+            return Err(self.fail(VmError::RuntimeError(format!(
+                "Can't serialize to JSON a value nested deeper than {MAX_JSON_DEPTH} levels, is it cyclic?"
+            ))));
+        }
+        match self.value {
+            Value::String(s) => serializer.collect_str(&s),
+            Value::IoString(s) => serializer.serialize_str(&s),
+            Value::Instance(instance) => {
+                let hook = self.writer.state.borrow_mut().json_hook(instance);
+                if let Some(hook) = hook {
+                    let json = self
+                        .writer
+                        .state
+                        .borrow_mut()
+                        .try_eval_method(self.value, hook, &[])
+                        .map_err(|err| self.fail(err))?;
+                    if let Value::Instance(other) = json
+                        && Gc::ptr_eq(other.borrow().class, instance.borrow().class)
+                    {
+                        return Err(self.fail(VmError::RuntimeError(format!(
+                            "to_json() of class '{}' can't return an instance of its class.",
+                            instance.borrow().class.borrow().name
+                        ))));
+                    }
+                    return self.nested(json).serialize(serializer);
+                }
+                let (class_name, mut fields) = {
+                    let instance = instance.borrow();
+                    let class = instance.class.borrow();
+                    let fields = instance
+                        .fields
+                        .iter()
+                        .map(|(key, value)| (*key, *value, class.json_formats.get(key).copied()))
+                        .collect::<Vec<_>>();
+                    (class.name, fields)
+                };
+                // Sorted like the keys of a `serde_json::Map`
+                fields.sort_unstable_by_key(|(key, ..)| key.as_bytes());
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value, format) in fields {
+                    map.serialize_key(&key.to_string())?;
+                    match format {
+                        Some(format) => {
+                            let mut json = format.write(value).map_err(|err| {
+                                self.fail(VmError::RuntimeError(format!(
+                                    "Can't serialize field '{key}' of class '{class_name}': {err}."
+                                )))
+                            })?;
+                            if self.writer.big_int_strings {
+                                big_ints_to_strings(&mut json);
+                            }
+                            map.serialize_value(&json)?;
+                        }
+                        None => map.serialize_value(&self.nested(value))?,
+                    }
+                }
+                map.end()
+            }
+            Value::List(list) => {
+                let items = list.borrow().data.clone();
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&self.nested(item))?;
+                }
+                seq.end()
+            }
+            Value::Set(set) => {
+                let items = set.borrow().iter().collect::<Vec<_>>();
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&self.nested(item))?;
+                }
+                seq.end()
+            }
+            Value::Object(obj) => {
+                let mut fields = obj
+                    .borrow()
+                    .fields
+                    .iter()
+                    .map(|(key, value)| (*key, *value))
+                    .collect::<Vec<_>>();
+                fields.sort_unstable_by_key(|(key, _)| key.as_bytes());
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value) in fields {
+                    map.serialize_entry(&key.to_string(), &self.nested(value))?;
+                }
+                map.end()
+            }
+            Value::Dict(dict) => {
+                let mut fields = dict
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect::<Vec<_>>();
+                // The last of the keys with the same string wins, like in a `serde_json::Map`
+                fields.reverse();
+                fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+                fields.dedup_by(|(a, _), (b, _)| a == b);
+                let mut map = serializer.serialize_map(Some(fields.len()))?;
+                for (key, value) in fields {
+                    map.serialize_entry(&key, &self.nested(value))?;
+                }
+                map.end()
+            }
+            Value::EnumVariant(variant) => self.nested(variant.value).serialize(serializer),
+            _ => self.leaf(serializer),
+        }
+    }
+}
+
+impl<'gc> State<'gc> {
+    // Write the JSON of the value like `jsonify()` converts it, straight
+    // from the VM values instead of building a `serde_json::Value` first.
+    pub(super) fn write_json(
+        &mut self,
+        value: Value<'gc>,
+        writer: impl io::Write,
+        big_int_strings: bool,
+    ) -> Result<(), VmError> {
+        let json_writer = Writer {
+            state: RefCell::new(self),
+            big_int_strings,
+            error: Cell::new(None),
+        };
+        let json = Json {
+            writer: &json_writer,
+            value,
+            depth: 0,
+        };
+        serde_json::to_writer(writer, &json).map_err(|err| {
+            json_writer
+                .error
+                .take()
+                .unwrap_or_else(|| VmError::RuntimeError(format!("Can't write the JSON: {err}")))
+        })
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::{Duration, Instant};
+
+use aiscript_arena::metrics::Metrics;
+
+use super::VmError;
+
+// How many instructions run between two checks of the clock and the heap.
+const CHECK_INTERVAL: u32 = 1024;
+
+/// The execution limits of a script, it fails with
+/// [`VmError::LimitExceeded`] once it has run more instructions than its
+/// fuel, for longer than its timeout, e.g. an infinite loop in a route,
+/// or has allocated more than its max heap.
+#[derive(Debug, Default)]
+pub(crate) struct Limits {
+    fuel: Option<u64>,
+    remaining: u64,
+    timeout: Option<(Duration, Instant)>,
+    max_heap: Option<usize>,
+    ticks: u32,
+}
+
+impl Limits {
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+        self.remaining = fuel.unwrap_or_default();
+    }
+
+    /// The timeout starts when it's set.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout.map(|timeout| (timeout, Instant::now() + timeout));
+    }
+
+    /// The max bytes of the arena, including the strings and the items
+    /// of the lists.
+    pub fn set_max_heap(&mut self, max_heap: Option<usize>) {
+        self.max_heap = max_heap;
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.fuel.is_none() && self.timeout.is_none() && self.max_heap.is_none()
+    }
+
+    /// Charge the fuel of a native call, e.g. the time spent in a
+    /// blocking call.
+    pub fn consume(&mut self, fuel: u64) -> Result<(), VmError> {
+        if let Some(limit) = self.fuel {
+            if self.remaining < fuel {
+                self.remaining = 0;
+                return Err(VmError::LimitExceeded(format!(
+                    "Execution ran out of fuel after {limit} instructions."
+                )));
+            }
+            self.remaining -= fuel;
+        }
+        Ok(())
+    }
+
+    /// Check the max heap before allocating `bytes` at once, e.g. a
+    /// repeated string, which would exceed it before the next check.
+    pub fn reserve(&self, metrics: &Metrics, bytes: usize) -> Result<(), VmError> {
+        if let Some(max_heap) = self.max_heap
+            && metrics.total_allocation().saturating_add(bytes) > max_heap
+        {
+            return Err(VmError::LimitExceeded(format!(
+                "Memory limit of {max_heap} bytes exceeded."
+            )));
+        }
+        Ok(())
+    }
+
+    /// Account an instruction to the limits.
+    pub fn tick(&mut self, metrics: &Metrics) -> Result<(), VmError> {
+        if let Some(fuel) = self.fuel {
+            if self.remaining == 0 {
+                return Err(VmError::LimitExceeded(format!(
+                    "Execution ran out of fuel after {fuel} instructions."
+                )));
+            }
+            self.remaining -= 1;
+        }
+        self.ticks += 1;
+        if self.ticks < CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.ticks = 0;
+        if let Some((timeout, deadline)) = self.timeout
+            && Instant::now() >= deadline
+        {
+            return Err(VmError::LimitExceeded(format!(
+                "Execution timed out after {}ms.",
+                timeout.as_millis()
+            )));
+        }
+        if let Some(max_heap) = self.max_heap
+            && metrics.total_allocation() > max_heap
+        {
+            return Err(VmError::LimitExceeded(format!(
+                "Memory limit of {max_heap} bytes exceeded."
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aiscript_arena::{Arena, Rootable};
+
+    use super::*;
+
+    fn metrics() -> Metrics {
+        Arena::<Rootable![()]>::new(|_| ()).metrics().clone()
+    }
+
+    #[test]
+    fn test_fuel_limit() {
+        let metrics = metrics();
+        let mut limits = Limits::default();
+        assert!(limits.is_unlimited());
+        limits.set_fuel(Some(3));
+        for _ in 0..3 {
+            assert!(limits.tick(&metrics).is_ok());
+        }
+        assert!(matches!(
+            limits.tick(&metrics),
+            Err(VmError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let metrics = metrics();
+        let mut limits = Limits::default();
+        assert!(limits.reserve(&metrics, usize::MAX).is_ok());
+        limits.set_max_heap(Some(1024));
+        assert!(limits.reserve(&metrics, 16).is_ok());
+        assert!(matches!(
+            limits.reserve(&metrics, usize::MAX),
+            Err(VmError::LimitExceeded(_))
+        ));
+    }
+
+    #[test]
+    fn test_timeout() {
+        let metrics = metrics();
+        let mut limits = Limits::default();
+        limits.set_timeout(Some(Duration::ZERO));
+        let result = (0..CHECK_INTERVAL).try_for_each(|_| limits.tick(&metrics));
+        assert!(matches!(result, Err(VmError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_max_heap() {
+        let metrics = metrics();
+        let mut limits = Limits::default();
+        limits.set_max_heap(Some(1024));
+        metrics.mark_external_allocation(1024);
+        assert!(
+            (0..CHECK_INTERVAL)
+                .try_for_each(|_| limits.tick(&metrics))
+                .is_ok()
+        );
+        metrics.mark_external_allocation(1);
+        let result = (0..CHECK_INTERVAL).try_for_each(|_| limits.tick(&metrics));
+        assert!(matches!(result, Err(VmError::LimitExceeded(_))));
+    }
+}
@@ -1,35 +1,167 @@
-use std::{fmt::Display, fs, ops, path::PathBuf};
+use std::{
+    fmt::Display,
+    fs, io, ops,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use aiscript_arena::{Arena, Mutation, Rootable, arena::CollectionPhase};
+use aiscript_arena::{Arena, Gc, Mutation, RefLock, Rootable, arena::CollectionPhase};
+use serde::Serialize;
 use sqlx::{PgPool, SqlitePool};
 pub use state::State;
 
 use crate::{
     ReturnValue, Value,
-    ai::AiConfig,
+    ai::{AiConfig, BudgetScope, Trace},
     ast::ChunkId,
-    builtins, stdlib,
-    string::{InternedString, InternedStringSet},
+    builtins,
+    object::Object,
+    stdlib,
+    string::{InternedString, InternedStringSet, shared_name},
 };
 use fuel::Fuel;
+use json_reader::ValueSeed;
 
+mod attributes;
+mod blocking;
+pub(crate) mod breaker;
+mod chaos;
+mod coverage;
+mod deadline;
+mod debugger;
 mod extra;
 mod fuel;
+mod json_format;
+mod json_reader;
+mod json_writer;
+mod limits;
+mod profiler;
+mod program;
+mod sandbox;
 mod state;
 
+pub use attributes::set_experimental;
+pub use blocking::set_blocking_limit;
+pub(crate) use blocking::{block_on, run_blocking};
+pub use breaker::{BreakerState, BreakerStatus, CircuitBreakerConfig, circuit_breakers};
+pub use chaos::{ChaosConfig, Fault};
+pub use coverage::{CoverageTrace, DEFAULT_COVERAGE_TRACE, coverage_trace_path, save_coverage};
+pub(crate) use deadline::with_deadline;
+pub use debugger::{
+    DebugAction, DebugFrame, DebugHandler, DebugVariable, Debugger, PauseReason, Paused,
+};
+pub(crate) use json_format::JsonFormat;
+pub use program::CompiledProgram;
+pub(crate) use sandbox::Capability;
+pub use sandbox::VmOptions;
+
+/// An argument of `Vm::eval_handler()`.
+pub enum Arg<'a> {
+    Json(&'a serde_json::Value),
+    /// An object of the JSON text of each field, e.g. the declared fields of
+    /// a request body, parsed straight into VM values.
+    JsonFields(Vec<(&'a str, &'a [u8])>),
+}
+
 #[derive(Debug)]
 pub enum VmError {
     CompileError,
     RuntimeError(std::string::String),
+    // A runtime error raised while running the script, with its call stack.
+    Traced(Box<TracedError>),
+    // The AI provider is saturated, the call was shed instead of queued.
+    RateLimited {
+        retry_after: u64,
+    },
+    // The AI call is rejected because a cost budget is used up.
+    BudgetExceeded(std::string::String),
+    // An upstream call is abandoned because the deadline of the request has passed.
+    DeadlineExceeded,
+    // The dependency is considered down, the call fails fast until the circuit closes.
+    CircuitOpen {
+        dependency: std::string::String,
+        retry_after: u64,
+    },
+    // The script is stopped because it exceeded its fuel or timeout.
+    LimitExceeded(std::string::String),
+    // The handler returned a value of a declared error type, e.g. `raise NotFound!{}`,
+    // see `Vm::eval_handler`.
+    Raised(Box<RaisedError>),
 }
 
 impl std::error::Error for VmError {}
 
+/// A frame of the call stack of a runtime error.
+#[derive(Debug, Clone, Serialize)]
+pub struct StackFrame {
+    /// The name of the function, `script` for the top level code.
+    pub function: std::string::String,
+    /// The chunk of the function, unknown for the functions not compiled
+    /// by this VM, e.g. the ones of a precompiled program.
+    pub chunk_id: Option<ChunkId>,
+    pub line: u32,
+}
+
+/// A runtime error with the call stack at the point of failure, the
+/// innermost frame first.
+#[derive(Debug, Clone, Serialize)]
+pub struct TracedError {
+    pub message: std::string::String,
+    pub backtrace: Vec<StackFrame>,
+}
+
+impl Display for TracedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)?;
+        for frame in &self.backtrace {
+            write!(f, "\n[line {}] in {}", frame.line, frame.function)?;
+        }
+        Ok(())
+    }
+}
+
+/// A value of an error type returned by the evaluated function, e.g. the
+/// `raise NotFound! { message: "..." }` of a route handler.
+#[derive(Debug, Clone, Serialize)]
+pub struct RaisedError {
+    /// The name of the error type, e.g. `NotFound!`.
+    pub name: std::string::String,
+    /// The fields of an error class, the `variant` of an error enum.
+    pub fields: serde_json::Map<std::string::String, serde_json::Value>,
+}
+
+impl Display for RaisedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.fields.is_empty() {
+            write!(f, " {}", serde_json::Value::Object(self.fields.clone()))?;
+        }
+        Ok(())
+    }
+}
+
 impl Display for VmError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::CompileError => write!(f, "CompileError"),
             Self::RuntimeError(s) => write!(f, "RuntimeError: {s}"),
+            Self::Traced(error) => write!(f, "RuntimeError: {error}"),
+            Self::RateLimited { retry_after } => {
+                write!(f, "RateLimited: retry after {retry_after} seconds")
+            }
+            Self::BudgetExceeded(s) => write!(f, "BudgetExceeded: {s}"),
+            Self::DeadlineExceeded => {
+                write!(f, "DeadlineExceeded: the request deadline has passed")
+            }
+            Self::CircuitOpen {
+                dependency,
+                retry_after,
+            } => write!(
+                f,
+                "CircuitOpen: {dependency} is unavailable, retry after {retry_after} seconds"
+            ),
+            Self::LimitExceeded(s) => write!(f, "LimitExceeded: {s}"),
+            Self::Raised(error) => write!(f, "Raised: {error}"),
         }
     }
 }
@@ -65,16 +197,192 @@ impl Vm {
         vm
     }
 
+    /// Record provider responses and tool results of the run into the trace file.
+    pub fn record_trace(&mut self, path: PathBuf) {
+        self.arena.mutate_root(|_mc, state| {
+            state.ai_trace = Some(Trace::record(path));
+        });
+    }
+
+    /// Replay the provider responses and tool results recorded in the trace file.
+    pub fn replay_trace(&mut self, path: PathBuf) -> io::Result<()> {
+        let trace = Trace::replay(path)?;
+        self.arena.mutate_root(|_mc, state| {
+            state.ai_trace = Some(trace);
+        });
+        Ok(())
+    }
+
+    /// Record provider responses and tool results of the run in memory, see [`Vm::take_trace`].
+    pub fn record_trace_in_memory(&mut self) {
+        self.arena.mutate_root(|_mc, state| {
+            state.ai_trace = Some(Trace::record_in_memory());
+        });
+    }
+
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.arena.mutate_root(|_mc, state| state.ai_trace.take())
+    }
+
+    /// Set the route and principal the AI calls are charged to.
+    pub fn set_budget_scope(&mut self, scope: BudgetScope) {
+        self.arena.mutate_root(|_mc, state| {
+            state.ai_budget_scope = scope;
+        });
+    }
+
+    /// Run the prompts and agents on the model rather than on theirs, e.g. a
+    /// cheaper one while the server is under load.
+    pub fn set_model_override(&mut self, model: String) {
+        self.arena.mutate_root(|_mc, state| {
+            state.model_override = Some(model);
+        });
+    }
+
+    /// Set the deadline of the request, AI provider calls, HTTP requests and
+    /// database queries fail with [`VmError::DeadlineExceeded`] once it has passed.
+    pub fn set_deadline(&mut self, deadline: Instant) {
+        self.arena.mutate_root(|_mc, state| {
+            state.deadline = Some(deadline);
+        });
+    }
+
+    /// Attach the debugger, the script pauses at its breakpoints and steps.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.arena.mutate_root(|_mc, state| {
+            state.debugger = Some(debugger);
+        });
+    }
+
+    /// Set the capabilities of the script, the standard library modules and
+    /// the AI calls needing a disabled capability fail with a runtime error.
+    pub fn set_options(&mut self, options: VmOptions) {
+        self.arena.mutate_root(|_mc, state| {
+            state.options = options;
+        });
+    }
+
+    /// Limit the number of instructions the script runs, it fails with
+    /// [`VmError::LimitExceeded`] once the fuel is used up, unlimited if `None`.
+    pub fn set_fuel_limit(&mut self, fuel: Option<u64>) {
+        self.arena.mutate_root(|_mc, state| {
+            state.limits.set_fuel(fuel);
+        });
+    }
+
+    /// Limit the wall-clock time the script runs from now, it fails with
+    /// [`VmError::LimitExceeded`] once the timeout has passed, unlimited if `None`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.arena.mutate_root(|_mc, state| {
+            state.limits.set_timeout(timeout);
+        });
+    }
+
+    /// Limit the bytes the script allocates, including the strings and the
+    /// items of the lists, it fails with [`VmError::LimitExceeded`] once the
+    /// heap of the VM exceeds the limit, unlimited if `None`.
+    pub fn set_memory_limit(&mut self, max_heap: Option<usize>) {
+        self.arena.mutate_root(|_mc, state| {
+            state.limits.set_max_heap(max_heap);
+        });
+    }
+
+    /// Set the id of the request, it's sent to the AI providers in the
+    /// `X-Request-Id` header and prefixed to the database queries as a comment.
+    pub fn set_request_id(&mut self, request_id: String) {
+        self.arena.mutate_root(|_mc, state| {
+            state.request_id = Some(request_id);
+        });
+    }
+
+    /// Enable the strict mode, the script is type checked statically
+    /// before it's compiled, see [`Vm::check`].
+    pub fn set_strict(&mut self, strict: bool) {
+        self.arena.mutate_root(|_mc, state| {
+            state.strict = strict;
+        });
+    }
+
+    pub fn save_trace(&mut self) -> io::Result<()> {
+        self.arena.mutate_root(|_mc, state| match &state.ai_trace {
+            Some(trace) => trace.save(),
+            None => Ok(()),
+        })
+    }
+
     pub fn run_file(&mut self, path: PathBuf) {
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                if let Err(err) = self.run_source(&path, source) {
+                    match err {
+                        VmError::CompileError => std::process::exit(65),
+                        VmError::RuntimeError(message) => eprintln!("{message}"),
+                        VmError::Traced(error) => eprintln!("{error}"),
+                        err => eprintln!("{err}"),
+                    }
+                    std::process::exit(70);
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to read file '{}': {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Compile and run the file like [`Vm::run_file`], the errors are
+    /// returned rather than exiting the process.
+    pub fn try_run_file(&mut self, path: &Path) -> Result<(), VmError> {
+        let source = fs::read_to_string(path).map_err(|err| {
+            VmError::RuntimeError(format!("Failed to read file '{}': {}", path.display(), err))
+        })?;
+        self.run_source(path, source)
+    }
+
+    fn run_source(&mut self, path: &Path, source: String) -> Result<(), VmError> {
+        self.arena.mutate_root(|_mc, state| {
+            state.script_path = Some(debugger::canonical(path));
+        });
+        let source: &'static str = Box::leak(source.into_boxed_str());
+        self.compile(source)?;
+        let result = self.interpret();
+        if let Err(err) = self.save_trace() {
+            eprintln!("Failed to save trace: {err}");
+        }
+        if let Err(err) = self.save_profile() {
+            eprintln!("Failed to save profile: {err}");
+        }
+        if let Err(err) = save_coverage() {
+            eprintln!("Failed to save coverage: {err}");
+        }
+        result.map(|_| ())
+    }
+
+    /// Type check the file without running it, exits with 65 on type errors.
+    pub fn check_file(&mut self, path: PathBuf) {
         match fs::read_to_string(&path) {
             Ok(source) => {
                 let source: &'static str = Box::leak(source.into_boxed_str());
-                if let Err(VmError::CompileError) = self.compile(source) {
+                if let Err(VmError::CompileError) = self.check(source) {
                     std::process::exit(65);
                 }
-                if let Err(VmError::RuntimeError(err)) = self.interpret() {
-                    eprintln!("{err}");
-                    std::process::exit(70);
+            }
+            Err(err) => {
+                eprintln!("Failed to read file '{}': {}", path.display(), err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Print the compiled chunks of the file without running it, exits with 65
+    /// on compile errors.
+    pub fn disassemble_file(&mut self, path: PathBuf) {
+        match fs::read_to_string(&path) {
+            Ok(source) => {
+                let source: &'static str = Box::leak(source.into_boxed_str());
+                match self.disassemble(source) {
+                    Ok(listing) => print!("{listing}"),
+                    Err(_) => std::process::exit(65),
                 }
             }
             Err(err) => {
@@ -84,21 +392,102 @@ impl Vm {
         }
     }
 
+    /// The listing of the compiled chunks of the source, with their constants,
+    /// lines and jump targets, the script chunk last.
+    pub fn disassemble(&mut self, source: &'static str) -> Result<String, VmError> {
+        self.arena.mutate_root(|_mc, state| {
+            let chunks = crate::compiler::compile(state.get_context(), source)?;
+            Ok(chunks
+                .iter()
+                .map(|(id, function)| {
+                    let name = function
+                        .name
+                        .map_or_else(|| "script".to_string(), |name| name.to_string());
+                    function.disassembly(format!("{name} (chunk {id})"))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"))
+        })
+    }
+
+    /// Profile the script, the time spent by each call stack is written to
+    /// the file in the folded stacks format once the script has run.
+    pub fn profile(&mut self, path: PathBuf) {
+        self.arena.mutate_root(|_mc, state| {
+            state.profiler = Some(profiler::Profiler::new(path));
+        });
+    }
+
+    pub fn save_profile(&mut self) -> io::Result<()> {
+        self.arena
+            .mutate_root(|_mc, state| match state.profiler.as_mut() {
+                Some(profiler) => profiler.save(),
+                None => Ok(()),
+            })
+    }
+
+    /// Set the arguments given to the script, available as `std.os.args`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.arena
+            .mutate_root(|_mc, state| stdlib::set_args(state, args));
+    }
+
+    /// Register a function of the host application callable by the scripts,
+    /// the arguments and the result are converted from and to JSON values,
+    /// an error is raised as a runtime error of the script.
+    ///
+    /// A qualified name like `host.lookup` exports the function from the
+    /// `host` module, imported with `use host;`, a plain name defines it as
+    /// a builtin function.
+    ///
+    /// ```
+    /// # use aiscript_vm::Vm;
+    /// let mut vm = Vm::default();
+    /// vm.register_function("host.lookup", |args| match args[..] {
+    ///     [serde_json::Value::String(ref key)] => Ok(serde_json::json!({"key": key})),
+    ///     _ => Err("lookup() expects a string key.".into()),
+    /// });
+    /// ```
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(Vec<serde_json::Value>) -> Result<serde_json::Value, String> + 'static,
+    ) {
+        self.arena.mutate_root(|_mc, state| {
+            state.define_host_function(name, Box::new(function));
+        });
+    }
+
+    /// Log every executed instruction with its function and line to stderr.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.arena.mutate_root(|_mc, state| {
+            state.trace = trace;
+        });
+    }
+
     fn init_stdlib(&mut self) {
         self.arena.mutate_root(|_mc, state| {
             let ctx = state.get_context();
 
             state.builtin_methods.init(ctx);
-            state.globals.insert(
+            state.natives.insert(
                 ctx.intern(b"ValidationError!"),
                 Value::Class(builtins::create_validation_error(ctx)),
             );
+            state.natives.insert(
+                ctx.intern(b"JsonError!"),
+                Value::Class(builtins::create_json_error(ctx)),
+            );
 
             // Initialize standard library modules
             state.module_manager.register_native_module(
                 ctx.intern(b"std.auth.jwt"),
                 stdlib::create_jwt_module(ctx),
             );
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.auth.password"),
+                stdlib::create_password_module(ctx),
+            );
             state
                 .module_manager
                 .register_native_module(ctx.intern(b"std.env"), stdlib::create_env_module(ctx));
@@ -111,16 +500,77 @@ impl Vm {
             state
                 .module_manager
                 .register_native_module(ctx.intern(b"std.io"), stdlib::create_io_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.io.stdin"),
+                stdlib::create_stdin_module(ctx),
+            );
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.io.stdout"),
+                stdlib::create_stdout_module(ctx),
+            );
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.io.stderr"),
+                stdlib::create_stderr_module(ctx),
+            );
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.os"), stdlib::create_os_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.process"),
+                stdlib::create_process_module(ctx),
+            );
             state
                 .module_manager
                 .register_native_module(ctx.intern(b"std.time"), stdlib::create_time_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.datetime"),
+                stdlib::create_datetime_module(ctx),
+            );
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.decimal"),
+                stdlib::create_decimal_module(ctx),
+            );
             state.module_manager.register_native_module(
                 ctx.intern(b"std.random"),
                 stdlib::create_random_module(ctx),
             );
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.ai"), stdlib::create_ai_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.search"),
+                stdlib::create_search_module(ctx),
+            );
             state
                 .module_manager
                 .register_native_module(ctx.intern(b"std.serde"), stdlib::create_serde_module(ctx));
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.json"), stdlib::create_json_module(ctx));
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.xml"), stdlib::create_xml_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.encoding"),
+                stdlib::create_encoding_module(ctx),
+            );
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.crypto"),
+                stdlib::create_crypto_module(ctx),
+            );
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.jobs"), stdlib::create_jobs_module(ctx));
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.kv"), stdlib::create_kv_module(ctx));
+            state
+                .module_manager
+                .register_native_module(ctx.intern(b"std.graph"), stdlib::create_graph_module(ctx));
+            state.module_manager.register_native_module(
+                ctx.intern(b"std.template"),
+                stdlib::create_template_module(ctx),
+            );
             state
                 .module_manager
                 .register_native_module(ctx.intern(b"std.db.pg"), stdlib::create_pg_module(ctx));
@@ -135,36 +585,226 @@ impl Vm {
         });
     }
 
+    /// Type check the script without running it, reports mismatched argument types,
+    /// wrong return types and unknown fields.
+    pub fn check(&mut self, source: &'static str) -> Result<(), VmError> {
+        self.arena
+            .mutate_root(|_mc, state| crate::compiler::check(state.get_context(), source, false))
+    }
+
     pub fn compile(&mut self, source: &'static str) -> Result<(), VmError> {
         self.arena.mutate_root(|_mc, state| {
             let context = state.get_context();
+            if state.strict {
+                crate::compiler::check(context, source, true)?;
+            }
             state.chunks = crate::compiler::compile(context, source)?;
-            builtins::define_builtin_functions(state);
-            // The script function's chunk id is always the highest chunk id.
-            let script_chunk_id = state.chunks.keys().max().copied().unwrap();
-            let function = state.get_chunk(script_chunk_id)?;
-            state.call_function(function, &[])
+            run_script(state)
         })
     }
 
+    /// Evaluate a snippet in the session of the VM, e.g. an input of the REPL,
+    /// the globals, functions and classes of the previous snippets stay
+    /// defined. Returns the value of the trailing expression of the snippet,
+    /// whose semicolon is optional, nil if it doesn't end with an expression.
+    pub fn eval_incremental(&mut self, source: &str) -> Result<ReturnValue, VmError> {
+        let mut source = source.trim_end().to_owned();
+        if !source.ends_with([';', '}']) {
+            source.push(';');
+        }
+        // The compiled functions borrow the source
+        let source: &'static str = Box::leak(source.into_boxed_str());
+        self.arena.mutate_root(|_mc, state| {
+            state.reset_stack();
+            let context = state.get_context();
+            // The chunks of the previous snippets are kept for their closures
+            let first_chunk_id = state.chunks.keys().max().map_or(0, |id| id + 1);
+            let chunks = crate::compiler::compile_snippet(context, source, first_chunk_id)?;
+            let script = *chunks.values().last().unwrap();
+            state.chunks.extend(chunks);
+            builtins::define_builtin_functions(state);
+            state.call_function(script, &[])
+        })?;
+        self.interpret()
+    }
+
     pub fn eval_function(
         &mut self,
         chunk_id: ChunkId,
         params: &[serde_json::Value],
+    ) -> Result<ReturnValue, VmError> {
+        let args = params.iter().map(Arg::Json).collect::<Vec<_>>();
+        self.eval(chunk_id, &args, false)
+    }
+
+    /// Like `eval_function()`, but a value of an error type returned by the
+    /// function, e.g. the `raise NotFound! {...}` of a route handler, is the
+    /// `VmError::Raised` error instead of the return value.
+    pub fn eval_handler(
+        &mut self,
+        chunk_id: ChunkId,
+        args: &[Arg],
+    ) -> Result<ReturnValue, VmError> {
+        self.eval(chunk_id, args, true)
+    }
+
+    fn eval(
+        &mut self,
+        chunk_id: ChunkId,
+        args: &[Arg],
+        raise: bool,
     ) -> Result<ReturnValue, VmError> {
         self.arena.mutate_root(|_mc, state| {
             let ctx = state.get_context();
-            let return_value = state.eval_function_with_id(
-                chunk_id,
-                &params
-                    .iter()
-                    .map(|v| Value::from_serde_value(ctx, v))
-                    .collect::<Vec<_>>(),
-            )?;
-            Ok(ReturnValue::from(return_value))
+            let args = args
+                .iter()
+                .map(|arg| match arg {
+                    Arg::Json(value) => Ok(Value::from_serde_value(ctx, value)),
+                    Arg::JsonFields(fields) => {
+                        let mut object = Object::default();
+                        for (name, json) in fields {
+                            object.fields.insert(
+                                ctx.intern_name(name.as_bytes()),
+                                ValueSeed(ctx).parse(json)?,
+                            );
+                        }
+                        Ok(Value::Object(Gc::new(&ctx, RefLock::new(object))))
+                    }
+                })
+                .collect::<Result<Vec<_>, VmError>>()?;
+            let return_value = state.eval_function_with_id(chunk_id, &args)?;
+            if let Value::Generator(generator) = return_value {
+                state.stream = Some(generator);
+                return Ok(ReturnValue::Stream);
+            }
+            if !raise {
+                return state.return_value(return_value);
+            }
+            if return_value.is_error() {
+                return Err(VmError::Raised(Box::new(state.raised_error(return_value)?)));
+            }
+            match return_value {
+                // The body of a response is written by `write_json()`, its
+                // status code and headers are returned
+                Value::Instance(instance)
+                    if instance.borrow().class.borrow().name.as_bytes() == b"Response" =>
+                {
+                    let body = state.intern_static("body");
+                    let fields = instance
+                        .borrow()
+                        .fields
+                        .iter()
+                        .filter(|(key, _)| **key != body)
+                        .map(|(key, value)| (key.to_string(), *value))
+                        .collect::<Vec<_>>();
+                    state.json = Some(
+                        instance
+                            .borrow()
+                            .fields
+                            .get(&body)
+                            .copied()
+                            .unwrap_or_default(),
+                    );
+                    Ok(ReturnValue::Response(
+                        fields
+                            .into_iter()
+                            .map(|(key, value)| Ok((key, state.jsonify(value)?)))
+                            .collect::<Result<_, VmError>>()?,
+                    ))
+                }
+                Value::List(_)
+                | Value::Object(_)
+                | Value::Set(_)
+                | Value::Dict(_)
+                | Value::Instance(_) => {
+                    state.json = Some(return_value);
+                    Ok(ReturnValue::Json)
+                }
+                value => state.return_value(value),
+            }
         })
     }
 
+    /// Write the JSON of the list or object returned by `eval_handler()`,
+    /// the body of a `ReturnValue::Response` or the value of a
+    /// `ReturnValue::Json`, as the writer takes it. The integers beyond
+    /// 2^53 are strings if `big_int_strings`.
+    pub fn write_json(
+        &mut self,
+        writer: impl io::Write,
+        big_int_strings: bool,
+    ) -> Result<(), VmError> {
+        self.arena.mutate_root(|_mc, state| {
+            let value = state.json.take().unwrap_or_default();
+            state.write_json(value, writer, big_int_strings)
+        })
+    }
+
+    /// The chunk of the first function named `name`, e.g. the handler of an
+    /// endpoint compiled after the classes of its error types.
+    pub fn function_id(&mut self, name: &str) -> Option<ChunkId> {
+        self.arena.mutate_root(|_mc, state| {
+            state.chunks.iter().find_map(|(id, function)| {
+                function
+                    .name
+                    .is_some_and(|n| n.as_bytes() == name.as_bytes())
+                    .then_some(*id)
+            })
+        })
+    }
+
+    /// Resume the generator returned by `eval_function()` for its next value,
+    /// `None` once the generator has returned.
+    pub fn next_streamed(&mut self) -> Result<Option<ReturnValue>, VmError> {
+        self.arena.mutate_root(|_mc, state| {
+            let Some(generator) = state.stream else {
+                return Ok(None);
+            };
+            let value = state.resume_generator(generator)?;
+            if !generator.borrow().is_done() {
+                return state.return_value(value).map(Some);
+            }
+            state.stream = None;
+            if value.is_error() {
+                return Err(VmError::RuntimeError(format!(
+                    "The generator raised an error: {value}"
+                )));
+            }
+            Ok(None)
+        })
+    }
+
+    /// Evaluate the fallback function of the endpoint, a function declared
+    /// in the handler by name, it's called without arguments.
+    pub fn eval_fallback(&mut self, name: &str) -> Result<ReturnValue, VmError> {
+        self.arena.mutate_root(|_mc, state| {
+            let function = state
+                .chunks
+                .values()
+                .find(|function| {
+                    function
+                        .name
+                        .is_some_and(|n| n.as_bytes() == name.as_bytes())
+                })
+                .copied()
+                .ok_or_else(|| {
+                    VmError::RuntimeError(format!("Fallback function '{name}' is not defined."))
+                })?;
+            if !function.upvalues.is_empty() {
+                return Err(VmError::RuntimeError(format!(
+                    "Fallback function '{name}' can't capture the variables of the handler."
+                )));
+            }
+            let return_value = state.eval_function(function, &[])?;
+            state.return_value(return_value)
+        })
+    }
+
+    /// Whether a fallback value was served in place of a failed call.
+    pub fn is_degraded(&mut self) -> bool {
+        self.arena.mutate_root(|_mc, state| state.degraded)
+    }
+
     pub fn interpret(&mut self) -> Result<ReturnValue, VmError> {
         loop {
             const FUEL_PER_GC: i32 = 1024 * 10;
@@ -197,6 +837,21 @@ impl Vm {
     }
 }
 
+// Call the script function of the compiled chunks of the state.
+fn run_script(state: &mut State<'_>) -> Result<(), VmError> {
+    #[cfg(feature = "coverage")]
+    if let Some((file, offset)) = state.coverage.script_file(&state.script_path) {
+        for function in state.chunks.values() {
+            coverage::register(file, function.chunk.lines.iter().map(|line| line + offset));
+        }
+    }
+    builtins::define_builtin_functions(state);
+    // The script function's chunk id is always the highest chunk id.
+    let script_chunk_id = state.chunks.keys().max().copied().unwrap();
+    let function = state.get_chunk(script_chunk_id)?;
+    state.call_function(function, &[])
+}
+
 #[derive(Copy, Clone)]
 pub struct Context<'gc> {
     pub mutation: &'gc Mutation<'gc>,
@@ -212,6 +867,15 @@ impl<'gc> Context<'gc> {
     pub fn intern_static(self, s: &'static str) -> InternedString<'gc> {
         self.strings.intern_static(&self, s.as_bytes())
     }
+
+    /// Intern a field name, a name shared by [`share_names`](crate::share_names)
+    /// isn't copied.
+    pub fn intern_name(self, s: &[u8]) -> InternedString<'gc> {
+        match shared_name(s) {
+            Some(name) => self.intern_static(name),
+            None => self.intern(s),
+        }
+    }
 }
 
 impl<'gc> ops::Deref for Context<'gc> {
@@ -221,3 +885,274 @@ impl<'gc> ops::Deref for Context<'gc> {
         self.mutation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_runtime_error_backtrace() {
+        let mut vm = Vm::default();
+        vm.compile("fn inner() {\n  return 1 + nil;\n}\nfn outer() {\n  inner();\n}\nouter();")
+            .unwrap();
+        let Err(VmError::Traced(error)) = vm.interpret() else {
+            panic!("expect a traced runtime error");
+        };
+        assert_eq!(
+            error.message,
+            "Operands must be two numbers or two strings."
+        );
+        let frames: Vec<_> = error
+            .backtrace
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.line))
+            .collect();
+        assert_eq!(frames, [("inner", 2), ("outer", 5), ("script", 7)]);
+        assert!(error.backtrace.iter().all(|frame| frame.chunk_id.is_some()));
+    }
+
+    #[test]
+    fn test_fuel_limit() {
+        let mut vm = Vm::default();
+        vm.set_fuel_limit(Some(10_000));
+        vm.compile("let i = 0;\nwhile true {\n  i += 1;\n}")
+            .unwrap();
+        assert!(matches!(vm.interpret(), Err(VmError::LimitExceeded(_))));
+
+        let mut vm = Vm::default();
+        vm.set_fuel_limit(Some(10_000));
+        vm.compile("let a = 1 + 2;").unwrap();
+        assert!(vm.interpret().is_ok());
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut vm = Vm::default();
+        vm.set_memory_limit(Some(16 * 1024 * 1024));
+        vm.compile("let items = [];\nwhile true {\n  items.append(1);\n}")
+            .unwrap();
+        let Err(VmError::LimitExceeded(message)) = vm.interpret() else {
+            panic!("expect the script to exceed the memory limit");
+        };
+        assert_eq!(message, "Memory limit of 16777216 bytes exceeded.");
+
+        let mut vm = Vm::default();
+        vm.set_memory_limit(Some(16 * 1024 * 1024));
+        vm.compile("let s = \"x\" * 100000000;").unwrap();
+        assert!(matches!(vm.interpret(), Err(VmError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_sandbox() {
+        let run = |options: VmOptions, source: &'static str| {
+            let mut vm = Vm::default();
+            vm.set_options(options);
+            vm.compile(source).unwrap();
+            vm.interpret().map_err(|err| err.to_string())
+        };
+
+        let err = run(VmOptions::sandboxed(), "use std.io;").unwrap_err();
+        assert!(err.contains("File system access is disabled by the sandbox."));
+        let err = run(VmOptions::sandboxed(), "use std.http;").unwrap_err();
+        assert!(err.contains("Network access is disabled by the sandbox."));
+        let err = run(VmOptions::sandboxed(), "use std.ai;").unwrap_err();
+        assert!(err.contains("Network access is disabled by the sandbox."));
+        let err = run(VmOptions::sandboxed(), "let home = $HOME;").unwrap_err();
+        assert!(err.contains("Environment access is disabled by the sandbox."));
+        let err = run(VmOptions::sandboxed(), "use std.process;").unwrap_err();
+        assert!(err.contains("Process access is disabled by the sandbox."));
+        // Pure modules are always allowed
+        assert!(run(VmOptions::sandboxed(), "use std.math;").is_ok());
+        // The templates render in the sandbox, but not the template files
+        let source = "use std.template;\ntemplate.render(\"{{ a }}\", {a: 1});";
+        assert!(run(VmOptions::sandboxed(), source).is_ok());
+        let source = "use std.template;\ntemplate.render_file(\"page.html\");";
+        let err = run(VmOptions::sandboxed(), source).unwrap_err();
+        assert!(err.contains("File system access is disabled by the sandbox."));
+        // Same for the JSON files of std.serde
+        let source = "use std.serde;\nserde.to_str({a: 1});";
+        assert!(run(VmOptions::sandboxed(), source).is_ok());
+        let source = "use std.serde;\nserde.from_file(\"/etc/hostname\");";
+        let err = run(VmOptions::sandboxed(), source).unwrap_err();
+        assert!(err.contains("File system access is disabled by the sandbox."));
+        let source = "use std.serde;\nserde.to_file(\"out.json\", {a: 1});";
+        let err = run(VmOptions::sandboxed(), source).unwrap_err();
+        assert!(err.contains("File system access is disabled by the sandbox."));
+
+        let options = VmOptions {
+            allow_fs: true,
+            ..VmOptions::sandboxed()
+        };
+        assert!(run(options, "use std.io;").is_ok());
+        assert!(run(VmOptions::default(), "use std.env;").is_ok());
+    }
+
+    #[test]
+    fn test_args() {
+        let mut vm = Vm::default();
+        vm.set_args(vec!["doc.txt".to_owned(), "--short".to_owned()]);
+        vm.compile("use std.os;\nreturn os.args;").unwrap();
+        let ReturnValue::Array(args) = vm.interpret().unwrap() else {
+            panic!("expect the arguments");
+        };
+        assert_eq!(args, vec!["doc.txt", "--short"]);
+    }
+
+    #[test]
+    fn test_eval_incremental() {
+        let mut vm = Vm::default();
+        assert!(matches!(
+            vm.eval_incremental("let x = 1;"),
+            Ok(ReturnValue::Nil)
+        ));
+        vm.eval_incremental("fn add(n) {\n  return x + n;\n}")
+            .unwrap();
+        let value = vm.eval_incremental("add(2)").unwrap();
+        assert_eq!(value.to_string(), "3");
+        // The session survives a runtime error
+        assert!(vm.eval_incremental("add(nil)").is_err());
+        vm.eval_incremental("x = 10").unwrap();
+        let value = vm.eval_incremental("add(2);").unwrap();
+        assert_eq!(value.to_string(), "12");
+    }
+
+    #[test]
+    fn test_register_function() {
+        let mut vm = Vm::default();
+        vm.register_function("host.lookup", |args| match args[..] {
+            [serde_json::Value::String(ref key)] => Ok(serde_json::json!({"key": key, "hits": 2})),
+            _ => Err("lookup() expects a string key.".into()),
+        });
+        vm.register_function("double", |args| {
+            let n = args.first().and_then(|n| n.as_i64()).unwrap_or_default();
+            Ok((n * 2).into())
+        });
+        vm.compile("use host;\nlet entry = host.lookup(\"a\");\nreturn entry.key + str(double(entry.hits));")
+            .unwrap();
+        assert_eq!(vm.interpret().unwrap(), ReturnValue::String("a4".into()));
+
+        vm.compile("host.lookup(1);").unwrap();
+        let Err(VmError::Traced(error)) = vm.interpret() else {
+            panic!("expect the host error");
+        };
+        assert_eq!(error.message, "lookup() expects a string key.");
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let listing = Vm::default()
+            .disassemble("let a = 1;\nif a > 0 {\n    print(a);\n}")
+            .unwrap();
+        assert!(listing.starts_with("== script (chunk 0) =="));
+        assert!(listing.contains("constants:"));
+        assert!(listing.contains("OP_JUMP"));
+        assert!(listing.contains(" -> "));
+    }
+
+    #[test]
+    fn test_profile() {
+        let path = std::env::temp_dir().join("aiscript_test_profile.ai");
+        let out = std::env::temp_dir().join("aiscript_test_profile.folded");
+        fs::write(
+            &path,
+            "fn add(a, b) {\n  return a + b;\n}\nlet s = 0;\nfor let i = 0; i < 10; i = i + 1 {\n  s = add(s, len(str(i)));\n}\n",
+        )
+        .unwrap();
+        let mut vm = Vm::default();
+        vm.profile(out.clone());
+        vm.run_file(path);
+        let report = fs::read_to_string(out).unwrap();
+        let stacks: Vec<_> = report
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap())
+            .collect();
+        for expected in ["script", "script;add", "script;len", "script;str"] {
+            assert!(stacks.iter().any(|(stack, _)| *stack == expected));
+        }
+        assert!(stacks.iter().all(|(_, time)| time.parse::<u128>().is_ok()));
+    }
+
+    #[test]
+    fn test_debugger() {
+        use std::{cell::RefCell, rc::Rc};
+
+        struct Recorder {
+            actions: Vec<DebugAction>,
+            pauses: Rc<RefCell<Vec<Paused>>>,
+        }
+
+        impl DebugHandler for Recorder {
+            fn paused(&mut self, paused: &Paused) -> DebugAction {
+                self.pauses.borrow_mut().push(paused.clone());
+                self.actions.remove(0)
+            }
+        }
+
+        let path = std::env::temp_dir().join("aiscript_test_debugger.ai");
+        fs::write(
+            &path,
+            "fn add(a, b) {\n  let sum = a + b;\n  return sum;\n}\nlet x = 1;\nlet y = add(x, 2);\nprint(y);\n",
+        )
+        .unwrap();
+        let run = |debugger: Debugger| {
+            let mut vm = Vm::default();
+            vm.set_debugger(debugger);
+            vm.run_file(path.clone());
+        };
+
+        // Pause at the breakpoint, then step out to the caller
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let mut debugger = Debugger::new(Recorder {
+            actions: vec![DebugAction::StepOut, DebugAction::Continue],
+            pauses: pauses.clone(),
+        });
+        debugger.set_breakpoints(&path, [3]);
+        run(debugger);
+        let pauses = pauses.borrow();
+        assert_eq!(pauses.len(), 2);
+        assert_eq!(pauses[0].reason, PauseReason::Breakpoint);
+        let frames: Vec<_> = pauses[0]
+            .frames
+            .iter()
+            .map(|frame| (frame.function.as_str(), frame.line))
+            .collect();
+        assert_eq!(frames, [("add", 3), ("script", 6)]);
+        let locals: Vec<_> = pauses[0].frames[0]
+            .locals
+            .iter()
+            .map(|local| (local.name.as_str(), local.value.as_str()))
+            .collect();
+        assert_eq!(locals, [("a", "1"), ("b", "2"), ("sum", "3")]);
+        assert!(pauses[0].globals.iter().any(|global| global.name == "x"));
+        assert_eq!(pauses[1].reason, PauseReason::Step);
+        assert_eq!(pauses[1].frames[0].line, 7);
+
+        // Step over the lines of the script from its entry
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        run(Debugger::new(Recorder {
+            actions: vec![
+                DebugAction::Next,
+                DebugAction::Next,
+                DebugAction::Next,
+                DebugAction::Continue,
+            ],
+            pauses: pauses.clone(),
+        })
+        .stop_on_entry());
+        let pauses = pauses.borrow();
+        assert_eq!(pauses[0].reason, PauseReason::Entry);
+        let lines: Vec<_> = pauses.iter().map(|paused| paused.frames[0].line).collect();
+        assert_eq!(lines, [1, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut vm = Vm::default();
+        vm.set_timeout(Some(Duration::from_millis(50)));
+        vm.compile("while true {}").unwrap();
+        let Err(VmError::LimitExceeded(message)) = vm.interpret() else {
+            panic!("expect the script to time out");
+        };
+        assert_eq!(message, "Execution timed out after 50ms.");
+    }
+}
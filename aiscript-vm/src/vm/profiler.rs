@@ -0,0 +1,105 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// An instrumentation profiler charging the time between two instructions to
+/// the call stack running them, the natives and the AI calls are charged to
+/// a frame of their own on top of their caller.
+///
+/// The report is in the folded stacks format, one `script;outer;inner <us>`
+/// line per stack, which flamegraph tools like `inferno-flamegraph` read.
+#[derive(Debug)]
+pub(crate) struct Profiler {
+    path: PathBuf,
+    // The time spent by each folded stack
+    samples: HashMap<String, Duration>,
+    // The folded stack running and its depth
+    stack: String,
+    depth: usize,
+    last: Instant,
+}
+
+impl Profiler {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            samples: HashMap::new(),
+            stack: String::new(),
+            depth: 0,
+            last: Instant::now(),
+        }
+    }
+
+    /// Charge the time since the last tick to the running stack, the stack
+    /// is rebuilt from the names of its frames when its depth has changed.
+    pub fn tick<'a>(&mut self, depth: usize, frames: impl FnOnce() -> Vec<&'a str>) {
+        self.charge_running();
+        if self.depth != depth {
+            self.depth = depth;
+            self.stack = frames().join(";");
+        }
+    }
+
+    /// Charge the time since the last tick to the running stack, nothing
+    /// is charged before the script starts.
+    pub fn charge_running(&mut self) {
+        let now = Instant::now();
+        if !self.stack.is_empty() {
+            let elapsed = now - self.last;
+            match self.samples.get_mut(&self.stack) {
+                Some(time) => *time += elapsed,
+                None => {
+                    self.samples.insert(self.stack.clone(), elapsed);
+                }
+            }
+        }
+        self.last = now;
+    }
+
+    /// The running stack, to charge a native call to once it returns.
+    pub fn stack(&self) -> String {
+        self.stack.clone()
+    }
+
+    /// Charge the time since the last tick to the frame on top of the stack.
+    pub fn charge(&mut self, stack: &str, frame: &str) {
+        let now = Instant::now();
+        *self.samples.entry(format!("{stack};{frame}")).or_default() += now - self.last;
+        self.last = now;
+    }
+
+    /// Write the folded stacks and print the functions taking the most
+    /// time by themselves.
+    pub fn save(&mut self) -> io::Result<()> {
+        self.charge_running();
+        let mut stacks = self.samples.iter().collect::<Vec<_>>();
+        stacks.sort();
+        let report = stacks
+            .iter()
+            .map(|(stack, time)| format!("{stack} {}\n", time.as_micros()))
+            .collect::<String>();
+        fs::write(&self.path, report)?;
+
+        let mut functions = HashMap::<&str, Duration>::new();
+        for (stack, time) in &self.samples {
+            let function = stack.rsplit(';').next().unwrap_or_default();
+            *functions.entry(function).or_default() += *time;
+        }
+        let total = functions.values().sum::<Duration>();
+        let mut functions = functions.into_iter().collect::<Vec<_>>();
+        functions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        eprintln!("Profile written to '{}'", self.path.display());
+        eprintln!("{:>12} {:>7}  function", "self (ms)", "%");
+        for (function, time) in functions.into_iter().take(10) {
+            eprintln!(
+                "{:>12.3} {:>6.1}%  {function}",
+                time.as_secs_f64() * 1000.0,
+                time.as_secs_f64() * 100.0 / total.as_secs_f64().max(f64::EPSILON)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,548 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use aiscript_arena::{
+    Gc,
+    lock::{GcRefLock, RefLock},
+};
+use aiscript_directive::Validator;
+
+use super::{Context, Vm, VmError, run_script};
+use crate::{
+    Value,
+    ai::{Agent, OpenApiTools, ToolChoice},
+    ast::{ChunkId, FnDef},
+    chunk::{Chunk, LocalVar, OpCode},
+    object::{Attributes, Enum, EnumVariant, Function, Parameter, Upvalue},
+    workflow::{Workflow, WorkflowStep},
+};
+
+/// A script compiled once and run by many VMs, e.g. the handler of an
+/// endpoint run by a new VM for each request.
+///
+/// The compile errors are reported once when the program is created. The
+/// compiled functions are kept outside of any VM heap and shared by the VMs
+/// loading the program, [`Vm::load()`] only copies them into the heap of the
+/// VM, the source isn't parsed again.
+#[derive(Debug, Clone)]
+pub struct CompiledProgram {
+    inner: Arc<Program>,
+    // The file and the line the source starts at, see `with_origin()`.
+    origin: Option<(&'static Path, u32)>,
+}
+
+#[derive(Debug)]
+struct Program {
+    source: &'static str,
+    chunks: BTreeMap<ChunkId, FunctionProto>,
+    // The enums declared by the program, a constant refers to its enum
+    // by index so the variants of a VM share the same enum.
+    enums: Vec<EnumProto>,
+    // The files read at compile time, e.g. the instructions of the agents.
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug)]
+struct FunctionProto {
+    arity: u8,
+    max_arity: u8,
+    keyword_only: u8,
+    variadic: bool,
+    kwargs: bool,
+    params: Vec<(Box<[u8]>, ParameterProto)>,
+    code: Vec<OpCode>,
+    constants: Vec<Constant>,
+    lines: Vec<u32>,
+    locals: Vec<LocalVar>,
+    name: Option<Box<[u8]>>,
+    upvalues: Vec<(usize, bool)>,
+    capture_by_value: bool,
+    module: Option<Box<[u8]>>,
+    is_generator: bool,
+    doc: Option<Box<[u8]>>,
+    signature: Option<Box<[u8]>>,
+    deprecated: Option<Box<[u8]>>,
+    experimental: bool,
+}
+
+struct ParameterProto {
+    position: u8,
+    default_value: Constant,
+    required: bool,
+    validators: Arc<[Box<dyn Validator>]>,
+}
+
+impl std::fmt::Debug for ParameterProto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ParameterProto")
+            .field("position", &self.position)
+            .field("default_value", &self.default_value)
+            .field("required", &self.required)
+            .field("validators", &self.validators.len())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct EnumProto {
+    name: Box<[u8]>,
+    variants: Vec<(Box<[u8]>, Constant)>,
+}
+
+#[derive(Debug)]
+struct AgentProto {
+    name: Box<[u8]>,
+    doc: Option<Box<[u8]>>,
+    instructions: Box<[u8]>,
+    instructions_file: Option<Box<[u8]>>,
+    model: Box<[u8]>,
+    tools: std::collections::HashMap<String, FnDef>,
+    openapi: Vec<OpenApiTools>,
+    tool_choice: ToolChoice,
+}
+
+#[derive(Debug)]
+struct WorkflowProto {
+    name: Box<[u8]>,
+    doc: Option<Box<[u8]>>,
+    steps: Vec<WorkflowStep>,
+}
+
+// The constants emitted by the compiler.
+#[derive(Debug)]
+enum Constant {
+    Number(f64),
+    Boolean(bool),
+    String(Box<[u8]>),
+    Nil,
+    Enum(usize),
+    EnumVariant {
+        enum_: usize,
+        name: Box<[u8]>,
+        value: Box<Constant>,
+    },
+    Agent(Box<AgentProto>),
+    Workflow(Box<WorkflowProto>),
+}
+
+impl CompiledProgram {
+    pub fn new(source: impl Into<String>) -> Result<Self, VmError> {
+        // The compiled functions borrow the source for the VM lifetime,
+        // the program keeps it for the lifetime of the process.
+        let source: &'static str = Box::leak(source.into().into_boxed_str());
+        let mut vm = Vm::default();
+        let program = vm.arena.mutate_root(|_mc, state| {
+            let chunks = crate::compiler::compile(state.get_context(), source)?;
+            let mut enums = Vec::new();
+            let chunks: BTreeMap<_, _> = chunks
+                .into_iter()
+                .map(|(id, function)| (id, FunctionProto::from_function(&function, &mut enums)))
+                .collect();
+            let files = chunks
+                .values()
+                .flat_map(|function| &function.constants)
+                .filter_map(|constant| match constant {
+                    Constant::Agent(agent) => agent.instructions_file.as_deref(),
+                    _ => None,
+                })
+                .map(|path| PathBuf::from(String::from_utf8_lossy(path).into_owned()))
+                .collect();
+            Ok::<_, VmError>(Program {
+                source,
+                chunks,
+                files,
+                enums: enums
+                    .into_iter()
+                    .map(|enum_| EnumProto::from_enum(enum_))
+                    .collect(),
+            })
+        })?;
+        Ok(Self {
+            inner: Arc::new(program),
+            origin: None,
+        })
+    }
+
+    /// Set the file and the line the source is taken from, e.g. the handler
+    /// of an endpoint in its route file, the first line of the source is
+    /// the line of the file in the coverage trace.
+    pub fn with_origin(mut self, file: impl Into<PathBuf>, line: u32) -> Self {
+        let file: &'static Path = Box::leak(file.into().into_boxed_path());
+        self.origin = Some((file, line.max(1)));
+        self
+    }
+
+    pub fn source(&self) -> &'static str {
+        self.inner.source
+    }
+
+    pub fn origin(&self) -> Option<(&'static Path, u32)> {
+        self.origin
+    }
+
+    /// The files read when compiling the program, e.g. the instructions of
+    /// an agent declared with `file("path")`, relative to the working directory.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.inner.files
+    }
+}
+
+impl Vm {
+    /// Load the compiled functions of the program into the VM, ready to run
+    /// like after `compile()`.
+    pub fn load(&mut self, program: &CompiledProgram) -> Result<(), VmError> {
+        #[cfg(feature = "coverage")]
+        self.arena.mutate_root(|_mc, state| {
+            state.coverage.origin = program
+                .origin
+                .map(|(file, line)| (super::debugger::canonical(file), line - 1));
+        });
+        self.arena.mutate_root(|_mc, state| {
+            let ctx = state.get_context();
+            let program = &program.inner;
+            // The strict mode reports the type errors and the warnings of the source
+            if state.strict {
+                crate::compiler::check(ctx, program.source, true)?;
+            }
+            let enums = program
+                .enums
+                .iter()
+                .map(|enum_| enum_.instantiate(ctx))
+                .collect::<Vec<_>>();
+            state.chunks = program
+                .chunks
+                .iter()
+                .map(|(id, function)| (*id, function.instantiate(ctx, &enums)))
+                .collect();
+            run_script(state)
+        })
+    }
+}
+
+fn to_bytes(s: impl AsRef<[u8]>) -> Box<[u8]> {
+    s.as_ref().into()
+}
+
+impl FunctionProto {
+    fn from_function<'gc>(
+        function: &Function<'gc>,
+        enums: &mut Vec<GcRefLock<'gc, Enum<'gc>>>,
+    ) -> Self {
+        Self {
+            arity: function.arity,
+            max_arity: function.max_arity,
+            keyword_only: function.keyword_only,
+            variadic: function.variadic,
+            kwargs: function.kwargs,
+            params: function
+                .params
+                .iter()
+                .map(|(name, param)| {
+                    let param = ParameterProto {
+                        position: param.position,
+                        default_value: Constant::from_value(param.default_value, enums),
+                        required: param.required,
+                        validators: param.validators.clone(),
+                    };
+                    (to_bytes(name.as_bytes()), param)
+                })
+                .collect(),
+            code: function.chunk.code.clone(),
+            constants: function
+                .chunk
+                .constants()
+                .iter()
+                .map(|value| Constant::from_value(*value, enums))
+                .collect(),
+            lines: function.chunk.lines.clone(),
+            locals: function.chunk.locals.clone(),
+            name: function.name.map(|name| to_bytes(name.as_bytes())),
+            upvalues: function
+                .upvalues
+                .iter()
+                .map(|upvalue| (upvalue.index, upvalue.is_local))
+                .collect(),
+            capture_by_value: function.capture_by_value,
+            module: function.module.map(|module| to_bytes(module.as_bytes())),
+            is_generator: function.is_generator,
+            doc: function.doc.map(|doc| to_bytes(doc.as_bytes())),
+            signature: function.signature.map(|s| to_bytes(s.as_bytes())),
+            deprecated: function
+                .attributes
+                .deprecated
+                .map(|s| to_bytes(s.as_bytes())),
+            experimental: function.attributes.experimental,
+        }
+    }
+
+    fn instantiate<'gc>(
+        &self,
+        ctx: Context<'gc>,
+        enums: &[GcRefLock<'gc, Enum<'gc>>],
+    ) -> Gc<'gc, Function<'gc>> {
+        let mut chunk = Chunk::new();
+        chunk.code = self.code.clone();
+        chunk.lines = self.lines.clone();
+        chunk.locals = self.locals.clone();
+        for constant in &self.constants {
+            chunk.add_constant(constant.instantiate(ctx, enums));
+        }
+        let function = Function {
+            arity: self.arity,
+            max_arity: self.max_arity,
+            keyword_only: self.keyword_only,
+            variadic: self.variadic,
+            kwargs: self.kwargs,
+            params: self
+                .params
+                .iter()
+                .map(|(name, param)| {
+                    let param = Parameter {
+                        position: param.position,
+                        default_value: param.default_value.instantiate(ctx, enums),
+                        required: param.required,
+                        validators: param.validators.clone(),
+                    };
+                    (ctx.intern(name), param)
+                })
+                .collect(),
+            chunk,
+            name: self.name.as_deref().map(|name| ctx.intern(name)),
+            upvalues: self
+                .upvalues
+                .iter()
+                .map(|&(index, is_local)| Upvalue { index, is_local })
+                .collect(),
+            capture_by_value: self.capture_by_value,
+            module: self.module.as_deref().map(|module| ctx.intern(module)),
+            is_generator: self.is_generator,
+            doc: self.doc.as_deref().map(|doc| ctx.intern(doc)),
+            signature: self.signature.as_deref().map(|s| ctx.intern(s)),
+            attributes: Attributes {
+                deprecated: self.deprecated.as_deref().map(|s| ctx.intern(s)),
+                experimental: self.experimental,
+            },
+        };
+        Gc::new(&ctx, function)
+    }
+}
+
+impl EnumProto {
+    fn from_enum(enum_: GcRefLock<'_, Enum<'_>>) -> Self {
+        let enum_ = enum_.borrow();
+        Self {
+            name: to_bytes(enum_.name.as_bytes()),
+            variants: enum_
+                .variant_names
+                .iter()
+                .map(|name| {
+                    // The variant values are literals, they never refer to an enum.
+                    let value = Constant::from_value(enum_.variants[name], &mut Vec::new());
+                    (to_bytes(name.as_bytes()), value)
+                })
+                .collect(),
+        }
+    }
+
+    fn instantiate<'gc>(&self, ctx: Context<'gc>) -> GcRefLock<'gc, Enum<'gc>> {
+        let variants = self
+            .variants
+            .iter()
+            .map(|(name, value)| (ctx.intern(name), value.instantiate(ctx, &[])))
+            .collect::<Vec<_>>();
+        Gc::new(
+            &ctx,
+            RefLock::new(Enum {
+                name: ctx.intern(&self.name),
+                variant_names: variants.iter().map(|(name, _)| *name).collect(),
+                variants: variants.into_iter().collect(),
+                methods: Default::default(),
+                static_methods: Default::default(),
+            }),
+        )
+    }
+}
+
+impl Constant {
+    fn from_value<'gc>(value: Value<'gc>, enums: &mut Vec<GcRefLock<'gc, Enum<'gc>>>) -> Self {
+        let mut enum_index = |enum_: GcRefLock<'gc, Enum<'gc>>| {
+            enums
+                .iter()
+                .position(|e| Gc::ptr_eq(*e, enum_))
+                .unwrap_or_else(|| {
+                    enums.push(enum_);
+                    enums.len() - 1
+                })
+        };
+        match value {
+            Value::Number(n) => Constant::Number(n),
+            Value::Boolean(b) => Constant::Boolean(b),
+            Value::String(s) => Constant::String(to_bytes(s.as_bytes())),
+            Value::Nil => Constant::Nil,
+            Value::Enum(enum_) => Constant::Enum(enum_index(enum_)),
+            Value::EnumVariant(variant) => Constant::EnumVariant {
+                enum_: enum_index(variant.enum_),
+                name: to_bytes(variant.name.as_bytes()),
+                value: Box::new(Constant::from_value(variant.value, enums)),
+            },
+            Value::Agent(agent) => Constant::Agent(Box::new(AgentProto {
+                name: to_bytes(agent.name.as_bytes()),
+                doc: agent.doc.map(|doc| to_bytes(doc.as_bytes())),
+                instructions: to_bytes(agent.instructions.as_bytes()),
+                instructions_file: agent
+                    .instructions_file
+                    .map(|path| to_bytes(path.as_bytes())),
+                model: to_bytes(agent.model.as_bytes()),
+                tools: agent.tools.clone(),
+                openapi: agent.openapi.clone(),
+                tool_choice: agent.tool_choice,
+            })),
+            Value::Workflow(workflow) => Constant::Workflow(Box::new(WorkflowProto {
+                name: to_bytes(workflow.name.as_bytes()),
+                doc: workflow.doc.map(|doc| to_bytes(doc.as_bytes())),
+                steps: workflow.steps.clone(),
+            })),
+            value => unreachable!("the compiler doesn't emit {value} constants"),
+        }
+    }
+
+    fn instantiate<'gc>(
+        &self,
+        ctx: Context<'gc>,
+        enums: &[GcRefLock<'gc, Enum<'gc>>],
+    ) -> Value<'gc> {
+        match self {
+            Constant::Number(n) => Value::Number(*n),
+            Constant::Boolean(b) => Value::Boolean(*b),
+            Constant::String(s) => Value::String(ctx.intern(s)),
+            Constant::Nil => Value::Nil,
+            Constant::Enum(index) => Value::Enum(enums[*index]),
+            Constant::EnumVariant { enum_, name, value } => Value::EnumVariant(Gc::new(
+                &ctx,
+                EnumVariant {
+                    enum_: enums[*enum_],
+                    name: ctx.intern(name),
+                    value: value.instantiate(ctx, enums),
+                },
+            )),
+            Constant::Agent(agent) => {
+                let mut instance = Agent::new(&ctx, ctx.intern(&agent.name));
+                instance.doc = agent.doc.as_deref().map(|doc| ctx.intern(doc));
+                instance.instructions = ctx.intern(&agent.instructions);
+                instance.instructions_file = agent
+                    .instructions_file
+                    .as_deref()
+                    .map(|path| ctx.intern(path));
+                instance.model = ctx.intern(&agent.model);
+                instance.tools = agent.tools.clone();
+                instance.openapi = agent.openapi.clone();
+                instance.tool_choice = agent.tool_choice;
+                Value::Agent(Gc::new(&ctx, instance))
+            }
+            Constant::Workflow(workflow) => Value::Workflow(Gc::new(
+                &ctx,
+                Workflow {
+                    name: ctx.intern(&workflow.name),
+                    doc: workflow.doc.as_deref().map(|doc| ctx.intern(doc)),
+                    steps: workflow.steps.clone(),
+                },
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReturnValue;
+
+    #[test]
+    fn test_compiled_program() {
+        let program = CompiledProgram::new("fn double(x) { return x * 2; }").unwrap();
+        for x in [1, 2] {
+            let mut vm = Vm::default();
+            vm.load(&program).unwrap();
+            let value = vm.eval_function(0, &[serde_json::json!(x)]).unwrap();
+            assert!(matches!(value, ReturnValue::Int(v) if v == x * 2));
+        }
+        assert!(CompiledProgram::new("fn broken( {").is_err());
+    }
+
+    #[test]
+    fn test_shared_chunks() {
+        let program = CompiledProgram::new(
+            r#"enum Color { Red = 1, Green = 2 }
+            fn is_green(c = Color::Green) { return c == Color::Green; }"#,
+        )
+        .unwrap();
+        let copy = program.clone();
+        assert!(Arc::ptr_eq(&program.inner, &copy.inner));
+        for _ in 0..2 {
+            let mut vm = Vm::default();
+            vm.load(&copy).unwrap();
+            vm.interpret().unwrap();
+            let is_green = vm.function_id("is_green").unwrap();
+            // The default variant and the variant of the body share the enum
+            assert!(matches!(
+                vm.eval_function(is_green, &[]),
+                Ok(ReturnValue::Boolean(true))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_raised_error() {
+        let program = CompiledProgram::new(
+            r#"class NotFound! { message: str = "", } fn find(id) -> NotFound! {
+                if id > 1 { raise NotFound! { message: "no user " + str(id) }; }
+                return id;
+            }"#,
+        )
+        .unwrap();
+        let mut vm = Vm::default();
+        vm.load(&program).unwrap();
+        vm.interpret().unwrap();
+        let find = vm.function_id("find").unwrap();
+        assert!(matches!(
+            vm.eval_function(find, &[serde_json::json!(1)]),
+            Ok(ReturnValue::Int(1))
+        ));
+        // Only a handler raises it, a function returns the error value
+        let mut vm = Vm::default();
+        vm.load(&program).unwrap();
+        vm.interpret().unwrap();
+        assert!(vm.eval_function(find, &[serde_json::json!(2)]).is_ok());
+        let mut vm = Vm::default();
+        vm.load(&program).unwrap();
+        vm.interpret().unwrap();
+        match vm.eval_handler(find, &[crate::Arg::Json(&serde_json::json!(2))]) {
+            Err(VmError::Raised(error)) => {
+                assert_eq!(error.name, "NotFound!");
+                assert_eq!(error.fields["message"], "no user 2");
+            }
+            other => panic!("expect a raised error, got {other:?}"),
+        }
+    }
+    #[test]
+    fn test_program_files() {
+        let path = std::env::temp_dir().join("aiscript_program_files.md");
+        std::fs::write(&path, "Triage the issues.").unwrap();
+        let program = CompiledProgram::new(format!(
+            r#"agent Triage {{ instructions: file("{}") }}
+            agent Chat {{ instructions: "Chat." }}"#,
+            path.display()
+        ))
+        .unwrap();
+        assert_eq!(program.files(), std::slice::from_ref(&path));
+        let _ = std::fs::remove_file(&path);
+        assert!(
+            CompiledProgram::new("fn f() {}")
+                .unwrap()
+                .files()
+                .is_empty()
+        );
+    }
+}
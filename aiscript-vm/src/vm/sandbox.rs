@@ -0,0 +1,81 @@
+use std::fmt::Display;
+
+/// The capabilities of a VM, consulted by the standard library before any
+/// IO, so an untrusted script, e.g. a user-supplied agent tool, can run
+/// with its IO disabled. Everything is allowed by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmOptions {
+    /// HTTP requests, databases, web search and the AI providers.
+    pub allow_net: bool,
+    /// Reading and writing files, and the standard input.
+    pub allow_fs: bool,
+    /// Reading and writing the environment variables and the arguments.
+    pub allow_env: bool,
+    /// Spawning processes.
+    pub allow_process: bool,
+}
+
+impl Default for VmOptions {
+    fn default() -> Self {
+        Self {
+            allow_net: true,
+            allow_fs: true,
+            allow_env: true,
+            allow_process: true,
+        }
+    }
+}
+
+impl VmOptions {
+    /// The options with every capability disabled.
+    pub fn sandboxed() -> Self {
+        Self {
+            allow_net: false,
+            allow_fs: false,
+            allow_env: false,
+            allow_process: false,
+        }
+    }
+
+    pub(crate) fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Net => self.allow_net,
+            Capability::Fs => self.allow_fs,
+            Capability::Env => self.allow_env,
+            Capability::Process => self.allow_process,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Capability {
+    Net,
+    Fs,
+    Env,
+    Process,
+}
+
+impl Capability {
+    /// The capability a standard library module requires, if any.
+    pub fn of_module(module: &str) -> Option<Self> {
+        match module {
+            "std.http" | "std.search" | "std.ai" => Some(Self::Net),
+            module if module.starts_with("std.db.") => Some(Self::Net),
+            "std.io" | "std.io.stdin" | "std.kv" => Some(Self::Fs),
+            "std.env" | "std.os" => Some(Self::Env),
+            "std.process" => Some(Self::Process),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Net => write!(f, "Network access"),
+            Self::Fs => write!(f, "File system access"),
+            Self::Env => write!(f, "Environment access"),
+            Self::Process => write!(f, "Process access"),
+        }
+    }
+}
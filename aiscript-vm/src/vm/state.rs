@@ -4,6 +4,8 @@ use std::{
     collections::{BTreeMap, HashMap},
     hash::BuildHasherDefault,
     mem, ops,
+    path::PathBuf,
+    time::Instant,
 };
 
 use ahash::AHasher;
@@ -15,18 +17,28 @@ use sqlx::{PgPool, SqlitePool};
 
 use crate::{
     NativeFn, OpCode, ReturnValue, Value,
-    ai::{self, AiConfig, PromptConfig},
+    ai::{self, AiConfig, Budget, BudgetScope, ModelConfig, PromptConfig, Trace, TraceEvent},
     ast::{ChunkId, Visibility},
     builtins::BuiltinMethods,
+    decimal::RoundingMode,
     module::{ModuleKind, ModuleManager, ModuleSource},
     object::{
-        BoundMethod, Class, Closure, EnumVariant, Function, Instance, List, ListKind, Object,
-        Upvalue, UpvalueObj,
+        Attributes, BoundMethod, Class, Closure, Enum, EnumVariant, Function, Generator,
+        GeneratorState, HostFn, HostFunction, Instance, List, ListKind, Object, Upvalue,
+        UpvalueObj,
     },
     string::{InternedString, InternedStringSet},
+    workflow,
 };
 
-use super::{Context, VmError, fuel::Fuel};
+use super::{
+    Context, RaisedError, StackFrame, TracedError, VmError, VmOptions, attributes,
+    debugger::{self, DebugAction, DebugFrame, DebugVariable, Debugger, PauseReason, Paused},
+    fuel::Fuel,
+    limits::Limits,
+    profiler::Profiler,
+    sandbox::Capability,
+};
 
 type Table<'gc> = HashMap<InternedString<'gc>, Value<'gc>, BuildHasherDefault<AHasher>>;
 
@@ -36,6 +48,12 @@ const FRAME_MAX_SIZE: usize = 64;
 const STACK_MAX_SIZE: usize = 4096; // Temporary reduce the stack size due to tokio thread stack size limit
 #[cfg(test)]
 const STACK_MAX_SIZE: usize = 128;
+// The max bytes of a string repeated with `*`, beyond which the allocation
+// would fail and abort the process.
+const MAX_REPEATED_STRING_LEN: usize = 1 << 30;
+// The max nesting of a value serialized to JSON, like the recursion limit
+// of serde_json.
+pub(super) const MAX_JSON_DEPTH: usize = 128;
 
 static NUMBER_OPERATOR_ERROR: &str = "Operands must be numbers.";
 
@@ -53,6 +71,67 @@ macro_rules! binary_op {
     }};
 }
 
+// Decimal arithmetic if either operand is a decimal, see `Value::decimal_operands`.
+// The checked operations only fail when dividing by zero.
+macro_rules! decimal_op {
+    ($self:expr, $checked:ident) => {
+        if let Some(operands) = $self.peek(1).decimal_operands($self.peek(0)) {
+            let (a, b) = operands.map_err(|e| $self.runtime_error(e.into()))?;
+            let value = a
+                .$checked(&b)
+                .ok_or_else(|| $self.runtime_error("Division by zero.".into()))?;
+            $self.stack_top -= 2;
+            let value = Value::Decimal(Gc::new($self.mc, value));
+            $self.push_stack(value);
+            return Ok(None);
+        }
+    };
+}
+
+// Integer operands stay integers, unless the result overflows
+// or the other operand has a fractional part.
+macro_rules! arithmetic_op {
+    ($self:expr, $checked:ident, $op:tt) => {{
+        decimal_op!($self, $checked);
+        match $self.peek(1).int_operands($self.peek(0)) {
+            Some((a, b)) => {
+                let value = match a.$checked(b) {
+                    Some(value) => Value::Int(value),
+                    None => Value::Number(a as f64 $op b as f64),
+                };
+                $self.stack_top -= 2;
+                $self.push_stack(value);
+            }
+            None => binary_op!($self, $op),
+        }
+    }};
+}
+
+// Integers are compared exactly, see `Value::int_operands`, and datetimes by instant.
+macro_rules! comparison_op {
+    ($self:expr, $op:tt) => {{
+        if let (Value::DateTime(a), Value::DateTime(b)) = ($self.peek(1), $self.peek(0)) {
+            let value = **a $op **b;
+            $self.stack_top -= 2;
+            $self.push_stack(value.into());
+            return Ok(None);
+        }
+        if let Some(operands) = $self.peek(1).decimal_operands($self.peek(0)) {
+            let (a, b) = operands.map_err(|e| $self.runtime_error(e.into()))?;
+            $self.stack_top -= 2;
+            $self.push_stack((a $op b).into());
+            return Ok(None);
+        }
+        match $self.peek(1).int_operands($self.peek(0)) {
+            Some((a, b)) => {
+                $self.stack_top -= 2;
+                $self.push_stack((a $op b).into());
+            }
+            None => binary_op!($self, $op),
+        }
+    }};
+}
+
 enum CheckArgsResult<'gc> {
     Args(Vec<Value<'gc>>),
     ValidationError(Value<'gc>),
@@ -68,6 +147,8 @@ struct CallFrame<'gc> {
     // slot_start field points into the VM’s value stack
     // at the first slot that this function can use
     slot_start: usize,
+    // The generator running in this frame, its slots are saved on `yield`.
+    generator: Option<GcRefLock<'gc, Generator<'gc>>>,
 }
 
 impl<'gc> CallFrame<'gc> {
@@ -103,6 +184,7 @@ pub struct State<'gc> {
     stack_top: usize,
     pub(super) strings: InternedStringSet<'gc>,
     pub(super) globals: Table<'gc>,
+    pub(super) natives: Table<'gc>,
     open_upvalues: Option<GcRefLock<'gc, UpvalueObj<'gc>>>,
     pub module_manager: ModuleManager<'gc>,
     pub(super) builtin_methods: BuiltinMethods<'gc>,
@@ -111,6 +193,42 @@ pub struct State<'gc> {
     pub sqlite_connection: Option<SqlitePool>,
     pub redis_connection: Option<redis::aio::MultiplexedConnection>,
     pub ai_config: AiConfig,
+    pub ai_trace: Option<Trace>,
+    pub ai_budget_scope: BudgetScope,
+    // The model used by the prompts and agents in place of theirs, see `Vm::set_model_override`.
+    pub model_override: Option<String>,
+    // The deadline of the request, upstream calls are abandoned once it has passed.
+    pub deadline: Option<Instant>,
+    // The id of the request, sent along with AI provider calls and database queries.
+    pub request_id: Option<String>,
+    // Run the static type check before compiling, see `Vm::set_strict`.
+    pub strict: bool,
+    // Whether a fallback value was served in place of a failed call, see `fallback()`.
+    pub degraded: bool,
+    // The generator returned by `Vm::eval_function`, see `Vm::next_streamed`.
+    pub(super) stream: Option<GcRefLock<'gc, Generator<'gc>>>,
+    // The list or object returned by `Vm::eval_handler`, see `Vm::write_json`.
+    pub(super) json: Option<Value<'gc>>,
+    // The managers of the entered `with` bodies and the frame count they
+    // are entered at, exited when the frames are unwound by an error.
+    contexts: Vec<(usize, Value<'gc>)>,
+    // The fuel and timeout of the script, see `Vm::set_fuel_limit`.
+    pub(super) limits: Limits,
+    // The capabilities of the script, see `Vm::set_options`.
+    pub(super) options: VmOptions,
+    // The debugger attached by `Vm::set_debugger`.
+    pub(super) debugger: Option<Debugger>,
+    // The file run by `Vm::run_file`, the file of the functions outside of modules.
+    pub(crate) script_path: Option<PathBuf>,
+    // The unnamed snapshots asserted so far, the name of the next one.
+    pub(crate) snapshot_count: usize,
+    // Whether every executed instruction is logged to stderr, see `Vm::set_trace`.
+    pub(super) trace: bool,
+    // The profiler set by `Vm::profile`.
+    pub(super) profiler: Option<Profiler>,
+    // The lines run by the frames, counted into the coverage trace.
+    #[cfg(feature = "coverage")]
+    pub(super) coverage: super::coverage::LineTracker,
 }
 
 unsafe impl Collect for State<'_> {
@@ -128,10 +246,14 @@ unsafe impl Collect for State<'_> {
         self.stack_top.trace(cc);
         self.strings.trace(cc);
         self.globals.trace(cc);
+        self.natives.trace(cc);
         self.open_upvalues.trace(cc);
         self.module_manager.trace(cc);
         self.builtin_methods.trace(cc);
         self.current_module.trace(cc);
+        self.stream.trace(cc);
+        self.json.trace(cc);
+        self.contexts.trace(cc);
     }
 }
 
@@ -146,6 +268,7 @@ impl<'gc> State<'gc> {
             stack_top: 0,
             strings: InternedStringSet::new(mc),
             globals: HashMap::default(),
+            natives: HashMap::default(),
             open_upvalues: None,
             module_manager: ModuleManager::new(),
             builtin_methods: BuiltinMethods::new(),
@@ -154,6 +277,25 @@ impl<'gc> State<'gc> {
             sqlite_connection: None,
             redis_connection: None,
             ai_config: AiConfig::default(),
+            ai_trace: None,
+            ai_budget_scope: BudgetScope::default(),
+            model_override: None,
+            deadline: None,
+            request_id: None,
+            strict: false,
+            degraded: false,
+            stream: None,
+            json: None,
+            contexts: Vec::new(),
+            limits: Limits::default(),
+            options: VmOptions::default(),
+            debugger: None,
+            script_path: None,
+            snapshot_count: 0,
+            trace: false,
+            profiler: None,
+            #[cfg(feature = "coverage")]
+            coverage: Default::default(),
         }
     }
 
@@ -164,64 +306,107 @@ impl<'gc> State<'gc> {
         }
     }
 
-    pub fn import_module(&mut self, path: InternedString<'gc>) -> Result<(), VmError> {
-        // Get the simple name (last component) from the path
-        let simple_name = path.to_str().unwrap().split('.').next_back().unwrap();
-        let simple_name = self.intern(simple_name.as_bytes());
-
-        // Check if simple name is already used
-        if self.globals.contains_key(&simple_name) {
+    pub fn import_module(
+        &mut self,
+        path: InternedString<'gc>,
+        name: Option<InternedString<'gc>>,
+    ) -> Result<(), VmError> {
+        // Check if the name is already used
+        if let Some(name) = name.filter(|name| self.globals.contains_key(name)) {
             return Err(VmError::RuntimeError(format!(
                 "Name '{}' is already in use",
-                simple_name
+                name
             )));
         }
 
-        // Get module source
-        let module_source = self.module_manager.get_or_load_module(path)?;
-
-        match module_source {
-            ModuleSource::Cached => {
-                // For any module (std or script), just bind it to its simple name
-                self.globals.insert(simple_name, Value::Module(path));
-                Ok(())
-            }
-            ModuleSource::New {
-                source,
-                path: module_path,
-            } => {
-                let prev_module = self.current_module.replace(path);
-                let prev_globals = mem::take(&mut self.globals);
-
-                let module = ModuleKind::Script {
-                    name: path,
-                    exports: HashMap::default(),
-                    globals: HashMap::default(),
-                    path: module_path,
-                };
+        if let Some(capability) = Capability::of_module(path.to_str().unwrap_or_default()) {
+            self.require(capability)?;
+        }
+        if let Err(VmError::RuntimeError(message)) = self.module_manager.begin_loading(path) {
+            return Err(self.runtime_error(message.into()));
+        }
+        let result = self.load_module(path);
+        self.module_manager.end_loading();
+        result?;
 
-                self.module_manager.register_script_module(path, module);
+        // Bind the module (std or script) to the name
+        if let Some(name) = name {
+            self.globals.insert(name, Value::Module(path));
+        }
+        Ok(())
+    }
 
-                let source: &'static str = Box::leak(source.into_boxed_str());
-                let chunks = crate::compiler::compile(self.get_context(), source)?;
+    fn load_module(&mut self, path: InternedString<'gc>) -> Result<(), VmError> {
+        let module_source = match self.module_manager.get_or_load_module(path) {
+            Ok(module_source) => module_source,
+            Err(VmError::RuntimeError(message)) => return Err(self.runtime_error(message.into())),
+            Err(err) => return Err(err),
+        };
+        let ModuleSource::New {
+            source,
+            path: module_path,
+        } = module_source
+        else {
+            return Ok(());
+        };
 
-                let imported_script_chunk_id = chunks.keys().last().copied().unwrap();
-                self.chunks.extend(chunks);
-                let function = self.get_chunk(imported_script_chunk_id)?;
-                self.eval_function(function, &[])?;
+        let prev_module = self.current_module.replace(path);
+        let prev_globals = mem::take(&mut self.globals);
 
+        #[cfg(feature = "coverage")]
+        let module_file = debugger::canonical(&module_path);
+        let module = ModuleKind::Script {
+            name: path,
+            exports: HashMap::default(),
+            globals: HashMap::default(),
+            path: module_path,
+        };
+        self.module_manager.register_script_module(path, module);
+
+        let first_chunk_id = self.chunks.keys().last().map_or(0, |id| id + 1);
+        let result =
+            crate::compiler::compile_module(self.get_context(), source, path, first_chunk_id)
+                .and_then(|chunks| {
+                    #[cfg(feature = "coverage")]
+                    for function in chunks.values() {
+                        super::coverage::register(&module_file, function.chunk.lines.clone());
+                    }
+                    let imported_script_chunk_id = chunks.keys().last().copied().unwrap();
+                    self.chunks.extend(chunks);
+                    let function = self.get_chunk(imported_script_chunk_id)?;
+                    self.eval_function(function, &[])
+                });
+
+        let module_globals = mem::replace(&mut self.globals, prev_globals);
+        self.current_module = prev_module;
+        match result {
+            Ok(_) => {
                 if let Some(ModuleKind::Script { globals, .. }) =
                     self.module_manager.modules.get_mut(&path)
                 {
-                    *globals = mem::replace(&mut self.globals, prev_globals);
+                    *globals = module_globals;
                 }
-
-                // Add the module to globals with its simple name
-                self.globals.insert(simple_name, Value::Module(path));
-
-                self.current_module = prev_module;
                 Ok(())
             }
+            Err(err) => {
+                // Don't cache a module failed to load
+                self.module_manager.modules.remove(&path);
+                Err(err)
+            }
+        }
+    }
+
+    // The script module of the running function.
+    fn frame_module(&self) -> Option<InternedString<'gc>> {
+        self.frames
+            .get(self.frame_count.wrapping_sub(1))
+            .and_then(|frame| frame.closure.function.module)
+    }
+
+    fn module_globals(&mut self, module: InternedString<'gc>) -> Option<&mut Table<'gc>> {
+        match self.module_manager.modules.get_mut(&module) {
+            Some(ModuleKind::Script { globals, .. }) => Some(globals),
+            _ => None,
         }
     }
 
@@ -231,12 +416,23 @@ impl<'gc> State<'gc> {
             return Some(Value::Module(module.name()));
         }
 
+        // Then check the globals of the module the running function is defined in
+        let module_global =
+            self.frame_module()
+                .and_then(|module| match self.module_manager.modules.get(&module) {
+                    Some(ModuleKind::Script { globals, .. }) => globals.get(&name).copied(),
+                    _ => None,
+                });
+        if module_global.is_some() {
+            return module_global;
+        }
+
         // Then check current globals scope
         if let Some(value) = self.globals.get(&name).copied() {
             return Some(value);
         }
 
-        // Finally check current module's globals if we're in a module
+        // Then check current module's globals if we're in a module
         if let Some(current_module) = self.current_module {
             if let Some(ModuleKind::Script { globals, .. }) =
                 self.module_manager.modules.get(&current_module)
@@ -247,7 +443,8 @@ impl<'gc> State<'gc> {
             }
         }
 
-        None
+        // Finally check the native functions, which are visible in all modules
+        self.natives.get(&name).copied()
     }
 
     pub fn gc_ref<T: Collect>(&mut self, value: T) -> GcRefLock<'gc, T> {
@@ -268,6 +465,15 @@ impl<'gc> State<'gc> {
         })
     }
 
+    // Drop the frames and the stack left by a script which failed.
+    pub(super) fn reset_stack(&mut self) {
+        self.frames.clear();
+        self.frame_count = 0;
+        self.stack_top = 0;
+        self.open_upvalues = None;
+        self.contexts.clear();
+    }
+
     // Call function with params
     pub fn call_function(
         &mut self,
@@ -284,30 +490,371 @@ impl<'gc> State<'gc> {
 }
 
 impl<'gc> State<'gc> {
+    pub(crate) fn allows(&self, capability: Capability) -> bool {
+        self.options.allows(capability)
+    }
+
+    // Fail unless the capability is allowed by the options of the VM.
+    pub(crate) fn require(&mut self, capability: Capability) -> Result<(), VmError> {
+        if self.allows(capability) {
+            return Ok(());
+        }
+        Err(self.runtime_error(format!("{capability} is disabled by the sandbox.").into()))
+    }
+
+    // Whether the run replays a trace, the AI calls don't reach the provider.
+    fn is_replaying(&self) -> bool {
+        self.ai_trace
+            .as_ref()
+            .is_some_and(|trace| trace.is_replay())
+    }
+
+    // Send the prompt to the provider, the response is replayed
+    // from the trace instead if the run is replaying a trace.
+    fn prompt(&mut self, mut config: PromptConfig) -> Result<String, VmError> {
+        if !self.is_replaying() {
+            self.require(Capability::Net)?;
+        }
+        config.budget = self.ai_budget();
+        config.deadline = self.deadline;
+        config.request_id = self.request_id.clone();
+        self.profiled(|_| "prompt".to_owned(), |state| state.send_prompt(config))
+    }
+
+    fn send_prompt(&mut self, config: PromptConfig) -> Result<String, VmError> {
+        match self.ai_trace.as_mut() {
+            Some(trace) if trace.is_replay() => {
+                let result = trace.replay_prompt();
+                result.map_err(|err| self.runtime_error(err.into()))
+            }
+            Some(trace) => {
+                let response = ai::prompt_with_config(config)?;
+                trace.record_event(TraceEvent::Prompt {
+                    response: response.clone(),
+                });
+                Ok(response)
+            }
+            None => ai::prompt_with_config(config),
+        }
+    }
+
+    // Run the host work, e.g. a native or an AI call, charging its time to
+    // a frame of its own on top of the running stack when profiling.
+    fn profiled<T>(
+        &mut self,
+        frame: impl FnOnce(&Self) -> String,
+        work: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let Some(profiler) = self.profiler.as_mut() else {
+            return work(self);
+        };
+        profiler.charge_running();
+        let stack = profiler.stack();
+        let result = work(self);
+        let frame = frame(self);
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.charge(&stack, &frame);
+        }
+        result
+    }
+
+    pub(crate) fn ai_budget(&self) -> Option<Budget> {
+        self.ai_config.budget.clone().map(|config| Budget {
+            config,
+            scope: self.ai_budget_scope.clone(),
+        })
+    }
+
+    // The config of the model, or of the override model if any.
+    pub(crate) fn model_config(&self, model: Option<String>) -> Result<ModelConfig, VmError> {
+        self.ai_config
+            .get_model_config(self.model_override.clone().or(model))
+            .map_err(VmError::RuntimeError)
+    }
+
     fn runtime_error(&mut self, message: Cow<'static, str>) -> VmError {
-        let mut error_message = String::from(message);
-        for i in (0..self.frame_count).rev() {
-            let frame = &self.frames[i];
+        let mut backtrace = Vec::new();
+        for frame in self.frames[..self.frame_count].iter().rev() {
             // Break loop if reach the un-initialized callframe.
             // Call Vm::eval_function directly will reach this case,
             // since it never init the root script.
             if frame.ip == 0 {
                 break;
             }
-            let function = &frame.closure.function;
-            error_message.push_str(&format!(
-                "\n[line {}] in ",
-                function.chunk.line(frame.ip - 1)
-            ));
-            let name = if let Some(name) = function.name {
-                name.to_str().unwrap()
-            } else {
-                "script"
+            let function = frame.closure.function;
+            let chunk_id = self
+                .chunks
+                .iter()
+                .find(|(_, chunk)| Gc::ptr_eq(**chunk, function))
+                .map(|(id, _)| *id);
+            backtrace.push(StackFrame {
+                function: function
+                    .name
+                    .map_or_else(|| "script".to_owned(), |name| name.to_string()),
+                chunk_id,
+                line: function.chunk.line(frame.ip - 1),
+            });
+        }
+        VmError::Traced(Box::new(TracedError {
+            message: message.into(),
+            backtrace,
+        }))
+    }
+
+    // Pause at a breakpoint or a step when a new line is entered.
+    fn debug_hook(&mut self) -> Result<(), VmError> {
+        let depth = self.frame_count;
+        let frame = &self.frames[depth - 1];
+        let function = frame.closure.function;
+        let line = function.chunk.line(frame.ip);
+        let Some(debugger) = self.debugger.as_mut() else {
+            return Ok(());
+        };
+        if !debugger.enter_line(depth, line) {
+            return Ok(());
+        }
+        let file = self.function_file(function);
+        let Some(reason) = self
+            .debugger
+            .as_ref()
+            .and_then(|debugger| debugger.should_pause(depth, file.as_deref(), line))
+        else {
+            return Ok(());
+        };
+
+        let paused = self.paused(reason);
+        let mut debugger = self.debugger.take().unwrap();
+        let action = debugger.pause(&paused, depth);
+        self.debugger = Some(debugger);
+        if action == DebugAction::Stop {
+            return Err(VmError::RuntimeError("Stopped by the debugger.".into()));
+        }
+        Ok(())
+    }
+
+    // Count the line entered by the frame on top of the stack.
+    #[cfg(feature = "coverage")]
+    fn coverage_hook(&mut self) {
+        let depth = self.frame_count;
+        let function = self.frames[depth - 1].closure.function;
+        let line = function.chunk.line(self.frames[depth - 1].ip);
+        if !self.coverage.enter_line(depth, line) {
+            return;
+        }
+        let key = Gc::as_ptr(function) as usize;
+        if !self.coverage.files.contains_key(&key) {
+            let file = match function.module {
+                None => self
+                    .coverage
+                    .script_file(&self.script_path)
+                    .map(|(file, offset)| (file.to_owned(), offset)),
+                Some(_) => self.function_file(function).map(|file| (file, 0)),
+            };
+            self.coverage.files.insert(key, file);
+        }
+        if let Some(Some((file, offset))) = self.coverage.files.get(&key) {
+            super::coverage::hit(file, line + offset);
+        }
+    }
+
+    // The file the function is defined in.
+    fn function_file(&self, function: Gc<'gc, Function<'gc>>) -> Option<PathBuf> {
+        match function.module {
+            None => self.script_path.clone(),
+            Some(module) => match self.module_manager.get_module(module) {
+                Some(ModuleKind::Script { path, .. }) => Some(debugger::canonical(path)),
+                _ => None,
+            },
+        }
+    }
+
+    // The call stack and the globals of the paused script.
+    fn paused(&self, reason: PauseReason) -> Paused {
+        let display = |value: Value<'gc>| match value {
+            Value::String(_) | Value::IoString(_) => format!("{:?}", value.to_string()),
+            value => value.to_string(),
+        };
+        let mut frames = Vec::new();
+        for (i, frame) in self.frames[..self.frame_count].iter().rev().enumerate() {
+            // The callers are at their call instruction
+            let Some(ip) = frame.ip.checked_sub((i > 0) as usize) else {
+                break;
             };
-            error_message.push_str(name);
-            error_message.push('\n');
+            let function = frame.closure.function;
+            let mut locals: Vec<DebugVariable> = Vec::new();
+            for local in function.chunk.locals.iter().rev() {
+                let slot = frame.slot_start + local.slot as usize;
+                if local.start <= ip
+                    && ip < local.end
+                    && slot < self.stack_top
+                    && !locals.iter().any(|variable| variable.name == local.name)
+                {
+                    locals.push(DebugVariable {
+                        name: local.name.clone(),
+                        value: display(self.stack[slot]),
+                    });
+                }
+            }
+            locals.reverse();
+            frames.push(DebugFrame {
+                function: function
+                    .name
+                    .map_or_else(|| "script".to_owned(), |name| name.to_string()),
+                file: self.function_file(function),
+                line: function.chunk.line(ip),
+                locals,
+            });
+        }
+        let mut globals: Vec<_> = self
+            .globals
+            .iter()
+            .map(|(name, value)| DebugVariable {
+                name: name.to_string(),
+                value: display(*value),
+            })
+            .collect();
+        globals.sort_by(|a, b| a.name.cmp(&b.name));
+        Paused {
+            reason,
+            frames,
+            globals,
+        }
+    }
+
+    // Pop the operands of a bitwise operator, the integers and the floats
+    // without a fractional part, e.g. the number literals.
+    fn bitwise_operands(&mut self) -> Result<(i64, i64), VmError> {
+        match (self.peek(1).as_exact_int(), self.peek(0).as_exact_int()) {
+            (Some(a), Some(b)) => {
+                self.stack_top -= 2;
+                Ok((a, b))
+            }
+            _ => Err(self.runtime_error("Operands must be two integers.".into())),
+        }
+    }
+
+    fn shift(&mut self, amount: i64, shift: impl Fn(u32) -> Option<i64>) -> Result<i64, VmError> {
+        u32::try_from(amount)
+            .ok()
+            .and_then(shift)
+            .ok_or_else(|| self.runtime_error("Shift amount must be between 0 and 63.".into()))
+    }
+
+    // An integer divided by zero is an error, only the floats are IEEE 754
+    // infinities or NaN.
+    fn check_int_divisor(&mut self) -> Result<(), VmError> {
+        if let Some((_, 0)) = self.peek(1).int_operands(self.peek(0)) {
+            return Err(self.runtime_error("Division by zero.".into()));
         }
-        VmError::RuntimeError(error_message)
+        Ok(())
+    }
+
+    // Pop the operands of `~/` and push their quotient rounded towards
+    // negative infinity. Integers stay integers, and a zero divisor is an
+    // error rather than an infinity.
+    fn floor_divide(&mut self) -> Result<(), VmError> {
+        let value = if let Some(operands) = self.peek(1).decimal_operands(self.peek(0)) {
+            let (a, b) = operands.map_err(|e| self.runtime_error(e.into()))?;
+            let value = a
+                .div(&b, 0, RoundingMode::Floor)
+                .ok_or_else(|| self.runtime_error("Division by zero.".into()))?;
+            Value::Decimal(Gc::new(self.mc, value))
+        } else if let Some((a, b)) = self.peek(1).int_operands(self.peek(0)) {
+            if b == 0 {
+                return Err(self.runtime_error("Division by zero.".into()));
+            }
+            match a.checked_div(b) {
+                Some(q) if a % b != 0 && (a < 0) != (b < 0) => Value::Int(q - 1),
+                Some(q) => Value::Int(q),
+                // i64::MIN ~/ -1 overflows
+                None => Value::Number((a as f64 / b as f64).floor()),
+            }
+        } else {
+            let b = self
+                .peek(0)
+                .as_number()
+                .map_err(|_| self.runtime_error(NUMBER_OPERATOR_ERROR.into()))?;
+            let a = self
+                .peek(1)
+                .as_number()
+                .map_err(|_| self.runtime_error(NUMBER_OPERATOR_ERROR.into()))?;
+            if b == 0.0 {
+                return Err(self.runtime_error("Division by zero.".into()));
+            }
+            Value::Number((a / b).floor())
+        };
+        self.stack_top -= 2;
+        self.push_stack(value);
+        Ok(())
+    }
+
+    // A datetime plus or minus seconds, e.g. `time.hours(2)`, is a datetime,
+    // and a datetime minus another one the seconds between them. Returns
+    // false if no operand is a datetime.
+    fn datetime_arithmetic(&mut self, subtract: bool) -> Result<bool, VmError> {
+        let value = match (*self.peek(1), *self.peek(0)) {
+            (Value::DateTime(a), Value::DateTime(b)) if subtract => {
+                Value::Number(a.seconds_since(&b))
+            }
+            (Value::DateTime(dt), seconds @ (Value::Number(_) | Value::Int(_)))
+            | (seconds @ (Value::Number(_) | Value::Int(_)), Value::DateTime(dt))
+                if !subtract || matches!(self.peek(1), Value::DateTime(_)) =>
+            {
+                let seconds = seconds.as_number()?;
+                let seconds = if subtract { -seconds } else { seconds };
+                let value = dt
+                    .checked_add_seconds(seconds)
+                    .ok_or_else(|| self.runtime_error("Datetime out of range.".into()))?;
+                Value::DateTime(Gc::new(self.mc, value))
+            }
+            (Value::DateTime(_), _) | (_, Value::DateTime(_)) => {
+                return Err(self.runtime_error(
+                    "A datetime can only be added or subtracted seconds, or subtracted a datetime."
+                        .into(),
+                ));
+            }
+            _ => return Ok(false),
+        };
+        self.stack_top -= 2;
+        self.push_stack(value);
+        Ok(true)
+    }
+
+    // Repeat the string operand of `*` by the number operand, in either order,
+    // returns false if the operands aren't a string and a number.
+    fn repeat_string(&mut self) -> Result<bool, VmError> {
+        let (string, count) = match (*self.peek(1), *self.peek(0)) {
+            (
+                string @ (Value::String(_) | Value::IoString(_)),
+                count @ (Value::Number(_) | Value::Int(_) | Value::Decimal(_)),
+            )
+            | (
+                count @ (Value::Number(_) | Value::Int(_) | Value::Decimal(_)),
+                string @ (Value::String(_) | Value::IoString(_)),
+            ) => (string.as_string_value()?, count),
+            _ => return Ok(false),
+        };
+        let count = count
+            .as_exact_int()
+            .and_then(|count| usize::try_from(count).ok())
+            .ok_or_else(|| {
+                self.runtime_error(
+                    "A string can only be repeated a non-negative integer number of times.".into(),
+                )
+            })?;
+        let string = string.as_str();
+        let Some(len) = string
+            .len()
+            .checked_mul(count)
+            .filter(|len| *len <= MAX_REPEATED_STRING_LEN)
+        else {
+            return Err(self.runtime_error("Repeated string is too long.".into()));
+        };
+        self.limits.reserve(self.mc.metrics(), len)?;
+        let value = self.intern(string.repeat(count).as_bytes());
+        self.stack_top -= 2;
+        self.push_stack(value.into());
+        Ok(true)
     }
 
     fn current_frame(&mut self) -> &mut CallFrame<'gc> {
@@ -325,6 +872,37 @@ impl<'gc> State<'gc> {
         // Debug stack info
         #[cfg(feature = "debug")]
         self.print_stack();
+        if !self.limits.is_unlimited() {
+            self.limits.tick(self.mc.metrics())?;
+        }
+        if self.debugger.is_some() {
+            self.debug_hook()?;
+        }
+        #[cfg(feature = "coverage")]
+        self.coverage_hook();
+        if self.trace {
+            let frame = &self.frames[self.frame_count - 1];
+            let function = &frame.closure.function;
+            let name = function
+                .name
+                .map_or("script".into(), |name| name.to_string());
+            eprintln!("[{name}] {}", function.instruction_line(frame.ip));
+        }
+        if let Some(profiler) = self.profiler.as_mut() {
+            let frames = &self.frames[..self.frame_count];
+            profiler.tick(self.frame_count, || {
+                frames
+                    .iter()
+                    .map(|frame| {
+                        frame
+                            .closure
+                            .function
+                            .name
+                            .map_or("script", |name| name.to_str().unwrap_or("?"))
+                    })
+                    .collect()
+            });
+        }
         let frame = self.current_frame();
         // Disassemble instruction for debug
         #[cfg(feature = "debug")]
@@ -335,37 +913,91 @@ impl<'gc> State<'gc> {
                 self.push_stack(constant);
             }
             OpCode::Add => match (self.peek(0), self.peek(1)) {
-                (Value::Number(_), Value::Number(_)) => {
-                    binary_op!(self, +);
+                (
+                    Value::Number(_) | Value::Int(_) | Value::Decimal(_),
+                    Value::Number(_) | Value::Int(_) | Value::Decimal(_),
+                ) => {
+                    arithmetic_op!(self, checked_add, +);
                 }
                 (Value::String(_), Value::String(_))
                 | (Value::IoString(_), Value::IoString(_))
                 | (Value::String(_), Value::IoString(_))
                 | (Value::IoString(_), Value::String(_)) => {
-                    let b = self.pop_stack().as_string()?;
-                    let a = self.pop_stack().as_string()?;
-                    let s = self.intern(format!("{a}{b}").as_bytes());
+                    let b = self.pop_stack().as_string_value()?;
+                    let a = self.pop_stack().as_string_value()?;
+                    let s = self.intern(format!("{}{}", a.as_str(), b.as_str()).as_bytes());
                     self.push_stack(s.into());
                 }
+                // A string is never implicitly converted, e.g. `"n=" + 3`
+                // is an error rather than "n=3".
+                (Value::String(_) | Value::IoString(_), _)
+                | (_, Value::String(_) | Value::IoString(_)) => {
+                    if !self.overload_binary_op("__add__")? {
+                        return Err(self.runtime_error(
+                            "Cannot add a string and a non-string value, convert it with str() first."
+                                .into(),
+                        ));
+                    }
+                }
                 _ => {
-                    return Err(
-                        self.runtime_error("Operands must be two numbers or two strings.".into())
-                    );
+                    if !self.datetime_arithmetic(false)? && !self.overload_binary_op("__add__")? {
+                        return Err(self
+                            .runtime_error("Operands must be two numbers or two strings.".into()));
+                    }
                 }
             },
             OpCode::Subtract => {
-                binary_op!(self, -);
+                if !self.datetime_arithmetic(true)? && !self.overload_binary_op("__sub__")? {
+                    arithmetic_op!(self, checked_sub, -);
+                }
             }
             OpCode::Multiply => {
-                binary_op!(self, *);
+                // A string times a non-negative integer repeats the string
+                if !self.repeat_string()? && !self.overload_binary_op("__mul__")? {
+                    arithmetic_op!(self, checked_mul, *);
+                }
             }
             OpCode::Divide => {
-                binary_op!(self, /);
+                if !self.overload_binary_op("__div__")? {
+                    decimal_op!(self, checked_div);
+                    self.check_int_divisor()?;
+                    binary_op!(self, /);
+                }
+            }
+            OpCode::FloorDivide => {
+                if !self.overload_binary_op("__floordiv__")? {
+                    self.floor_divide()?;
+                }
             }
             OpCode::Modulo => {
-                binary_op!(self, %);
+                if !self.overload_binary_op("__mod__")? {
+                    self.check_int_divisor()?;
+                    arithmetic_op!(self, checked_rem, %);
+                }
             }
             OpCode::Power => {
+                if let Value::Decimal(d) = self.peek(1) {
+                    let value = self
+                        .peek(0)
+                        .as_exact_int()
+                        .and_then(|exp| u32::try_from(exp).ok())
+                        .and_then(|exp| d.checked_pow(exp))
+                        .ok_or_else(|| {
+                            self.runtime_error(
+                                "Exponent of a decimal must be a non-negative integer.".into(),
+                            )
+                        })?;
+                    self.stack_top -= 2;
+                    self.push_stack(Value::Decimal(Gc::new(self.mc, value)));
+                    return Ok(None);
+                }
+                if let Some((a, b)) = self.peek(1).int_operands(self.peek(0))
+                    && let Some(value) = u32::try_from(b).ok().and_then(|b| a.checked_pow(b))
+                {
+                    self.stack_top -= 2;
+                    self.push_stack(Value::Int(value));
+                    return Ok(None);
+                }
                 let b = self
                     .pop_stack()
                     .as_number()
@@ -379,13 +1011,56 @@ impl<'gc> State<'gc> {
                 self.push_stack(a.powf(b).into());
             }
             OpCode::Negate => {
+                if let Value::Decimal(d) = self.peek(0) {
+                    self.stack[self.stack_top - 1] = Value::Decimal(Gc::new(self.mc, d.neg()));
+                    return Ok(None);
+                }
+                if let Value::Int(v) = self.peek(0)
+                    && let Some(v) = v.checked_neg()
+                {
+                    self.stack[self.stack_top - 1] = Value::Int(v);
+                    return Ok(None);
+                }
                 let v = self
                     .pop_stack()
                     .as_number()
                     .map_err(|_| self.runtime_error("Operand must be a number.".into()))?;
                 self.push_stack((-v).into());
             }
+            OpCode::BitAnd => {
+                let (a, b) = self.bitwise_operands()?;
+                self.push_stack(Value::Int(a & b));
+            }
+            OpCode::BitOr => {
+                let (a, b) = self.bitwise_operands()?;
+                self.push_stack(Value::Int(a | b));
+            }
+            OpCode::BitXor => {
+                let (a, b) = self.bitwise_operands()?;
+                self.push_stack(Value::Int(a ^ b));
+            }
+            OpCode::ShiftLeft => {
+                let (a, b) = self.bitwise_operands()?;
+                let value = self.shift(b, |b| a.checked_shl(b))?;
+                self.push_stack(Value::Int(value));
+            }
+            OpCode::ShiftRight => {
+                let (a, b) = self.bitwise_operands()?;
+                let value = self.shift(b, |b| a.checked_shr(b))?;
+                self.push_stack(Value::Int(value));
+            }
+            OpCode::BitNot => {
+                let v = self.peek(0).as_exact_int().ok_or_else(|| {
+                    self.runtime_error("Operand of '~' must be an integer.".into())
+                })?;
+                self.stack[self.stack_top - 1] = Value::Int(!v);
+            }
             OpCode::Return => {
+                if let Some(generator) = frame.generator {
+                    // The generator is only resumed by `resume_generator()`
+                    let value = self.finish_generator(generator);
+                    return Ok(Some(value));
+                }
                 let frame_slot_start = frame.slot_start;
                 let return_value = self.pop_stack();
                 self.close_upvalues(frame_slot_start);
@@ -399,6 +1074,11 @@ impl<'gc> State<'gc> {
                 self.stack_top = frame_slot_start;
                 self.push_stack(return_value);
             }
+            OpCode::Yield => {
+                let value = self.pop_stack();
+                self.suspend_generator();
+                return Ok(Some(value));
+            }
             OpCode::Nil => self.push_stack(Value::Nil),
             OpCode::Bool(b) => self.push_stack(Value::Boolean(b)),
             OpCode::Not => {
@@ -408,29 +1088,41 @@ impl<'gc> State<'gc> {
             OpCode::Equal => {
                 let b = self.pop_stack();
                 let a = self.pop_stack();
-                self.push_stack(a.equals(&b).into());
+                let equal = self.values_equal(a, b)?;
+                self.push_stack(equal.into());
             }
             OpCode::EqualInplace => {
-                let b = self.peek(0);
-                let a = self.peek(1);
-                self.stack[self.stack_top - 1] = a.equals(b).into();
+                let b = *self.peek(0);
+                let a = *self.peek(1);
+                self.stack[self.stack_top - 1] = self.values_equal(a, b)?.into();
             }
             OpCode::NotEqual => {
                 let b = self.pop_stack();
                 let a = self.pop_stack();
-                self.push_stack((!a.equals(&b)).into());
+                let equal = self.values_equal(a, b)?;
+                self.push_stack((!equal).into());
             }
+            // The comparisons of instances are derived from `__lt__`,
+            // a > b is b < a, and a >= b is not a < b.
             OpCode::Greater => {
-                binary_op!(self, >);
+                if !self.overload_comparison(true, false)? {
+                    comparison_op!(self, >);
+                }
             }
             OpCode::GreaterEqual => {
-                binary_op!(self, >=);
+                if !self.overload_comparison(false, true)? {
+                    comparison_op!(self, >=);
+                }
             }
             OpCode::Less => {
-                binary_op!(self, <);
+                if !self.overload_comparison(false, false)? {
+                    comparison_op!(self, <);
+                }
             }
             OpCode::LessEqual => {
-                binary_op!(self, <=);
+                if !self.overload_comparison(true, true)? {
+                    comparison_op!(self, <=);
+                }
             }
             OpCode::BuildString(count) => {
                 let count = count as usize;
@@ -461,6 +1153,16 @@ impl<'gc> State<'gc> {
                                 total_len += s.len();
                                 s
                             }
+                            Value::Int(n) => {
+                                let s = format!("{}", n);
+                                total_len += s.len();
+                                s
+                            }
+                            Value::Decimal(d) => {
+                                let s = d.to_string();
+                                total_len += s.len();
+                                s
+                            }
                             Value::Boolean(b) => {
                                 let s = format!("{}", b);
                                 total_len += s.len();
@@ -504,19 +1206,18 @@ impl<'gc> State<'gc> {
                                 result
                             }
                             Value::Instance(instance) => {
-                                let name = instance.borrow().class.borrow().name.to_string();
-                                let s = format!("<instance of {}>", name);
+                                let s = match self.eval_str_method(value)? {
+                                    Some(s) => s,
+                                    None => {
+                                        let name = instance.borrow().class.borrow().name;
+                                        format!("<instance of {}>", name)
+                                    }
+                                };
                                 total_len += s.len();
                                 s
                             }
-                            Value::EnumVariant(variant) => {
-                                let enum_name = variant.enum_.borrow().name.to_string();
-                                let variant_name = variant.name.to_string();
-                                let s = if variant.value.is_nil() {
-                                    format!("{}::{}", enum_name, variant_name)
-                                } else {
-                                    format!("{}::{}({})", enum_name, variant_name, variant.value)
-                                };
+                            Value::EnumVariant(_) => {
+                                let s = self.stringify(value)?;
                                 total_len += s.len();
                                 s
                             }
@@ -573,8 +1274,15 @@ impl<'gc> State<'gc> {
             }
             OpCode::SetGlobal(byte) => {
                 let varible_name = frame.read_constant(byte).as_string()?;
-                #[allow(clippy::map_entry)]
-                if self.globals.contains_key(&varible_name) {
+                let value = *self.peek(0);
+                let module_global = self
+                    .frame_module()
+                    .and_then(|module| self.module_globals(module))
+                    .and_then(|globals| globals.get_mut(&varible_name));
+                if let Some(global) = module_global {
+                    // Assign to the global of the module the function is defined in
+                    *global = value;
+                } else if self.globals.contains_key(&varible_name) {
                     self.globals.insert(varible_name, *self.peek(0));
                 } else {
                     return Err(self
@@ -620,9 +1328,13 @@ impl<'gc> State<'gc> {
             }
             OpCode::Constructor {
                 positional_count,
-                keyword_count,
+                mut keyword_count,
+                keyword_spread,
                 validate,
             } => {
+                if keyword_spread {
+                    keyword_count = self.spread_keywords(keyword_count)?;
+                }
                 // *2 because each keyword arg has name and value
                 // Get the actual function from the correct stack position
                 // Need to peek past all args (both positional and keyword) to get to the function
@@ -632,8 +1344,12 @@ impl<'gc> State<'gc> {
             }
             OpCode::Call {
                 positional_count,
-                keyword_count,
+                mut keyword_count,
+                keyword_spread,
             } => {
+                if keyword_spread {
+                    keyword_count = self.spread_keywords(keyword_count)?;
+                }
                 // *2 because each keyword arg has name and value
                 // Get the actual function from the correct stack position
                 // Need to peek past all args (both positional and keyword) to get to the function
@@ -653,7 +1369,23 @@ impl<'gc> State<'gc> {
                     .for_each(|(i, upvalue)| {
                         let frame = self.current_frame();
                         let Upvalue { is_local, index } = *upvalue;
-                        if is_local {
+                        if closure.function.capture_by_value {
+                            // Copy the current value into a closed upvalue
+                            let value = if is_local {
+                                self.stack[frame.slot_start + index]
+                            } else {
+                                let upvalue = frame.closure.upvalues[index].borrow();
+                                upvalue.closed.unwrap_or(self.stack[upvalue.location])
+                            };
+                            closure.upvalues[i] = Gc::new(
+                                self.mc,
+                                RefLock::new(UpvalueObj {
+                                    location: 0,
+                                    closed: Some(value),
+                                    next: None,
+                                }),
+                            );
+                        } else if is_local {
                             let slot = frame.slot_start + index;
                             let upvalue = self.capture_upvalue(slot);
                             // println!("function {} capture local: {slot}, {:?}", fn_name, upvalue);
@@ -703,12 +1435,20 @@ impl<'gc> State<'gc> {
                     unreachable!();
                 }
             }
-            OpCode::Class(byte) => {
-                let name = frame.read_constant(byte).as_string().unwrap();
-                self.push_stack(Value::from(Gc::new(
-                    self.mc,
-                    RefLock::new(Class::new(name)),
-                )));
+            OpCode::Class {
+                name_constant,
+                doc_constant,
+                deprecated_constant,
+                experimental,
+            } => {
+                let mut class = Class::new(frame.read_constant(name_constant).as_string().unwrap());
+                class.doc = doc_constant.map(|doc| frame.read_constant(doc).as_string().unwrap());
+                class.attributes = Attributes {
+                    deprecated: deprecated_constant
+                        .map(|note| frame.read_constant(note).as_string().unwrap()),
+                    experimental,
+                };
+                self.push_stack(Value::from(Gc::new(self.mc, RefLock::new(class))));
             }
             OpCode::EnumVariant {
                 name_constant,
@@ -765,6 +1505,19 @@ impl<'gc> State<'gc> {
                             );
                         }
                     }
+                    Value::EnumVariant(variant) => {
+                        let value = match name.as_bytes() {
+                            b"name" => Value::String(variant.name),
+                            b"value" => variant.value,
+                            _ => {
+                                return Err(self.runtime_error(
+                                    format!("Undefined property '{}'", name).into(),
+                                ));
+                            }
+                        };
+                        self.pop_stack(); // Pop variant
+                        self.push_stack(value);
+                    }
                     Value::Object(obj) => {
                         // Pop the target object first
                         self.pop_stack();
@@ -773,17 +1526,27 @@ impl<'gc> State<'gc> {
                         self.push_stack(value);
                     }
                     Value::Instance(instance) => {
+                        let class = instance.borrow().class;
+                        let getter = class.borrow().getters.get(&name).copied();
                         if let Some(property) = instance.borrow().fields.get(&name) {
                             self.pop_stack(); // Instance
                             self.push_stack(*property);
+                        } else if let Some(getter) = getter {
+                            // The instance is the receiver in slot zero of the getter
+                            self.call(getter.as_closure()?, 0, 0)?;
                         } else {
-                            self.bind_method(instance.borrow().class, name)?;
+                            self.bind_method(class, name)?;
                         }
                     }
                     Value::Module(module_name) => {
                         if let Some(value) = self.module_manager.get_export(module_name, name) {
                             self.pop_stack(); // Pop module
                             self.push_stack(value);
+                        } else if self.module_manager.is_private(module_name, name) {
+                            return Err(self.runtime_error(
+                                format!("'{}' is private in module '{}'.", name, module_name)
+                                    .into(),
+                            ));
                         } else {
                             return Err(self.runtime_error(
                                 format!(
@@ -794,6 +1557,15 @@ impl<'gc> State<'gc> {
                             ));
                         }
                     }
+                    value if name.as_bytes() == b"__doc__" => {
+                        let Some(doc) = crate::builtins::docstring(self, value) else {
+                            return Err(
+                                self.runtime_error("Only instances have properties.".into())
+                            );
+                        };
+                        self.pop_stack();
+                        self.push_stack(doc);
+                    }
                     _ => {
                         // Only instances and modules have properties.
                         return Err(self.runtime_error("Only instances have properties.".into()));
@@ -806,11 +1578,23 @@ impl<'gc> State<'gc> {
                     Value::Instance(instantce) => {
                         let frame = self.current_frame();
                         let name = frame.read_constant(byte).as_string().unwrap();
-                        instantce.borrow_mut(self.mc).fields.insert(name, value);
+                        let setter = instantce
+                            .borrow()
+                            .class
+                            .borrow()
+                            .setters
+                            .get(&name)
+                            .copied();
+                        if let Some(setter) = setter {
+                            // Call the setter with the instance as receiver and the value as argument
+                            self.call(setter.as_closure()?, 1, 0)?;
+                        } else {
+                            instantce.borrow_mut(self.mc).fields.insert(name, value);
 
-                        let value = self.pop_stack(); // Value
-                        self.pop_stack(); // Instance
-                        self.push_stack(value);
+                            let value = self.pop_stack(); // Value
+                            self.pop_stack(); // Instance
+                            self.push_stack(value);
+                        }
                     }
                     Value::Object(obj) => {
                         let frame = self.current_frame();
@@ -831,21 +1615,48 @@ impl<'gc> State<'gc> {
                 let name = frame.read_constant(name_constant).as_string().unwrap();
                 self.define_method(name, is_static)?;
             }
+            OpCode::Accessor {
+                name_constant,
+                is_setter,
+            } => {
+                let name = frame.read_constant(name_constant).as_string().unwrap();
+                let accessor = self.pop_stack();
+                let mut class = self.peek(0).as_class()?.borrow_mut(self.mc);
+                if is_setter {
+                    class.setters.insert(name, accessor);
+                } else {
+                    class.getters.insert(name, accessor);
+                }
+            }
+            OpCode::JsonFormat {
+                name_constant,
+                format,
+            } => {
+                let name = frame.read_constant(name_constant).as_string().unwrap();
+                let mut class = self.peek(0).as_class()?.borrow_mut(self.mc);
+                class.json_formats.insert(name, format);
+            }
             OpCode::Invoke {
                 method_constant,
                 positional_count,
-                keyword_count,
+                mut keyword_count,
+                keyword_spread,
             } => {
                 let method_name = frame.read_constant(method_constant).as_string().unwrap();
+                if keyword_spread {
+                    keyword_count = self.spread_keywords(keyword_count)?;
+                }
                 self.invoke(method_name, positional_count, keyword_count)?;
             }
             OpCode::Inherit => {
                 if let Value::Class(superclass) = self.peek(1) {
                     let subclass = self.peek(0).as_class()?;
-                    subclass
-                        .borrow_mut(self.mc)
-                        .methods
-                        .extend(&superclass.borrow().methods);
+                    let mut subclass = subclass.borrow_mut(self.mc);
+                    let superclass = superclass.borrow();
+                    subclass.methods.extend(&superclass.methods);
+                    subclass.getters.extend(&superclass.getters);
+                    subclass.setters.extend(&superclass.setters);
+                    subclass.json_formats.extend(&superclass.json_formats);
                     self.pop_stack(); // Subclass
                 } else {
                     return Err(self.runtime_error("Superclass must be a class.".into()));
@@ -859,10 +1670,14 @@ impl<'gc> State<'gc> {
             OpCode::SuperInvoke {
                 method_constant,
                 positional_count,
-                keyword_count,
+                mut keyword_count,
+                keyword_spread,
             } => {
                 let method_name = frame.read_constant(method_constant).as_string().unwrap();
                 let superclass = self.pop_stack().as_class()?;
+                if keyword_spread {
+                    keyword_count = self.spread_keywords(keyword_count)?;
+                }
                 self.invoke_from_class(superclass, method_name, positional_count, keyword_count)?;
             }
             OpCode::MakeObject(count) => {
@@ -889,13 +1704,23 @@ impl<'gc> State<'gc> {
                 kind,
             } => {
                 let count = size_constant as usize;
-                let mut list = List::with_capacity(kind, count);
                 let elements = self.pop_stack_n(count);
-                list.data = elements;
+                let list = List::new(self.mc, kind, elements);
 
                 let list = Value::List(Gc::new(self.mc, RefLock::new(list)));
                 self.push_stack(list);
             }
+            OpCode::EnterContext => {
+                let manager = self.pop_stack();
+                let (enter, _) = self.context_methods(manager)?;
+                let value = self.try_eval_method(manager, enter, &[])?;
+                self.contexts.push((self.frame_count, manager));
+                self.push_stack(value);
+            }
+            OpCode::ExitContext => {
+                let (_, manager) = self.contexts.pop().expect("an entered context");
+                self.exit_context(manager, None)?;
+            }
             OpCode::GetIndex => {
                 // Stack: [object] [key]
                 let key = self.pop_stack();
@@ -912,6 +1737,14 @@ impl<'gc> State<'gc> {
                         let value = obj.borrow().fields.get(&key).copied().unwrap_or_default();
                         self.push_stack(value);
                     }
+                    Value::Dict(dict) => {
+                        let value = dict
+                            .borrow()
+                            .get(&key)
+                            .map_err(|e| self.runtime_error(e.into()))?
+                            .unwrap_or_default();
+                        self.push_stack(value);
+                    }
                     Value::List(list) => {
                         let index = key.as_number().map_err(|_| {
                             self.runtime_error("Array index must be a number.".into())
@@ -920,11 +1753,14 @@ impl<'gc> State<'gc> {
                         let value = vec.get(index as usize).copied().unwrap_or(Value::Nil);
                         self.push_stack(value);
                     }
-                    Value::Instance(_) => {
-                        return Err(self.runtime_error(
-                            "Use dot notation for accessing instance properties.".into(),
-                        ));
-                    }
+                    Value::Instance(_) => match self.eval_operator("__index__", target, key)? {
+                        Some(value) => self.push_stack(value),
+                        None => {
+                            return Err(self.runtime_error(
+                                "Use dot notation for accessing instance properties.".into(),
+                            ));
+                        }
+                    },
                     _ => {
                         return Err(
                             self.runtime_error("Only object and array support indexing.".into())
@@ -946,6 +1782,12 @@ impl<'gc> State<'gc> {
                         // Push value back for assignment expressions
                         self.push_stack(value);
                     }
+                    Value::Dict(dict) => {
+                        dict.borrow_mut(self.mc)
+                            .insert(index, value)
+                            .map_err(|e| self.runtime_error(e.into()))?;
+                        self.push_stack(value);
+                    }
                     Value::List(list) => {
                         // TODO: don't support tuple set index
                         let index = index.as_number().unwrap();
@@ -988,6 +1830,14 @@ impl<'gc> State<'gc> {
                         })?;
                         obj.borrow().fields.contains_key(&key)
                     }
+                    Value::Dict(dict) => dict
+                        .borrow()
+                        .contains_key(&value)
+                        .map_err(|e| self.runtime_error(e.into()))?,
+                    Value::Set(set) => set
+                        .borrow()
+                        .contains(&value)
+                        .map_err(|e| self.runtime_error(e.into()))?,
                     _ => {
                         return Err(self.runtime_error(
                             "Right operand of 'in' operator must be array or object.".into(),
@@ -998,6 +1848,7 @@ impl<'gc> State<'gc> {
                 self.push_stack(Value::Boolean(result));
             }
             OpCode::EnvLookup => {
+                self.require(Capability::Env)?;
                 let name = self.pop_stack().as_string()?;
                 let value = match std::env::var(name.to_str().unwrap()) {
                     Ok(value) => Value::String(self.intern(value.as_bytes())),
@@ -1007,21 +1858,19 @@ impl<'gc> State<'gc> {
                 };
                 self.push_stack(value);
             }
-            OpCode::Prompt => {
+            OpCode::Prompt { handle_error } => {
                 let value = self.pop_stack();
+                let mut repair_json = false;
 
                 let result = match value {
                     // Simple string case
                     Value::String(s) => {
                         let config = PromptConfig {
                             input: s.to_str().unwrap().to_string(),
-                            model_config: self
-                                .ai_config
-                                .get_model_config(None)
-                                .map_err(VmError::RuntimeError)?,
+                            model_config: self.model_config(None)?,
                             ..Default::default()
                         };
-                        ai::prompt_with_config(config)
+                        self.prompt(config)?
                     }
                     // Object config case
                     Value::Object(obj) => {
@@ -1031,10 +1880,8 @@ impl<'gc> State<'gc> {
                         if let Some(Value::String(model)) =
                             obj_ref.fields.get(&self.intern(b"model"))
                         {
-                            config.model_config = self
-                                .ai_config
-                                .get_model_config(Some(model.to_str().unwrap().to_string()))
-                                .map_err(VmError::RuntimeError)?
+                            config.model_config =
+                                self.model_config(Some(model.to_str().unwrap().to_string()))?
                         }
 
                         // Extract input (required)
@@ -1049,17 +1896,13 @@ impl<'gc> State<'gc> {
                         }
 
                         // Extract max_tokens (optional)
-                        if let Some(Value::Number(tokens)) =
-                            obj_ref.fields.get(&self.intern(b"max_tokens"))
-                        {
-                            config.max_tokens = Some(*tokens as i64);
+                        if let Some(tokens) = obj_ref.fields.get(&self.intern(b"max_tokens")) {
+                            config.max_tokens = tokens.as_number().ok().map(|tokens| tokens as i64);
                         }
 
                         // Extract temperature (optional)
-                        if let Some(Value::Number(temp)) =
-                            obj_ref.fields.get(&self.intern(b"temperature"))
-                        {
-                            config.temperature = Some(*temp);
+                        if let Some(temp) = obj_ref.fields.get(&self.intern(b"temperature")) {
+                            config.temperature = temp.as_number().ok();
                         }
 
                         // Extract system_prompt (optional)
@@ -1069,7 +1912,14 @@ impl<'gc> State<'gc> {
                             config.system_prompt = Some(sys_prompt.to_str().unwrap().to_string());
                         }
 
-                        ai::prompt_with_config(config)
+                        // Extract repair_json (optional)
+                        if let Some(Value::Boolean(repair)) =
+                            obj_ref.fields.get(&self.intern(b"repair_json"))
+                        {
+                            repair_json = *repair;
+                        }
+
+                        self.prompt(config)?
                     }
                     _ => {
                         return Err(self.runtime_error(
@@ -1078,18 +1928,64 @@ impl<'gc> State<'gc> {
                     }
                 };
 
-                let result = self.intern(result.as_bytes());
-                self.push_stack(Value::from(result));
-            }
-            OpCode::Agent(name) => {
-                let agent = frame.read_constant(name);
-                self.push_stack(agent);
-            }
-            OpCode::ImportModule(module_name_idx) => {
-                let module_name = frame.read_constant(module_name_idx).as_string()?;
-                self.import_module(module_name)?;
-            }
-            OpCode::GetModuleVar {
+                if !repair_json {
+                    let result = self.intern(result.as_bytes());
+                    self.push_stack(Value::from(result));
+                } else {
+                    match ai::repair_json(&result) {
+                        Ok(json) => {
+                            let value = Value::from_serde_value(self.get_context(), &json);
+                            self.push_stack(value);
+                        }
+                        Err(err) if handle_error => {
+                            // The JsonError! class registered with the stdlib, so the
+                            // error is the one scripts refer to
+                            let name = self.intern(b"JsonError!");
+                            let Some(Value::Class(error_class)) = self.natives.get(&name).copied()
+                            else {
+                                unreachable!("JsonError! is registered with the stdlib");
+                            };
+                            let mut instance = Instance::new(error_class);
+                            instance.fields.insert(
+                                self.intern(b"message"),
+                                Value::from(self.intern(err.as_bytes())),
+                            );
+                            instance.fields.insert(
+                                self.intern(b"response"),
+                                Value::from(self.intern(result.as_bytes())),
+                            );
+                            self.push_stack(Value::Instance(Gc::new(
+                                self.mc,
+                                RefLock::new(instance),
+                            )));
+                        }
+                        Err(err) => {
+                            return Err(self.runtime_error(
+                                format!("Failed to repair the JSON response: {err}").into(),
+                            ));
+                        }
+                    }
+                }
+            }
+            OpCode::Agent(name) => {
+                let agent = frame.read_constant(name);
+                self.push_stack(agent);
+            }
+            OpCode::Workflow(name) => {
+                let workflow = frame.read_constant(name);
+                self.push_stack(workflow);
+            }
+            OpCode::ImportModule {
+                module_name_constant,
+                name_constant,
+            } => {
+                let module_name = frame.read_constant(module_name_constant).as_string()?;
+                let name = name_constant
+                    .map(|name| frame.read_constant(name).as_string())
+                    .transpose()?;
+                self.import_module(module_name, name)?;
+            }
+            OpCode::GetModuleVar {
                 module_name_constant,
                 var_name_constant,
             } => {
@@ -1098,6 +1994,10 @@ impl<'gc> State<'gc> {
 
                 if let Some(value) = self.module_manager.get_export(module_name, var_name) {
                     self.push_stack(value);
+                } else if self.module_manager.is_private(module_name, var_name) {
+                    return Err(self.runtime_error(
+                        format!("'{}' is private in module '{}'.", var_name, module_name).into(),
+                    ));
                 } else {
                     return Err(self.runtime_error(
                         format!(
@@ -1151,24 +2051,97 @@ impl<'gc> State<'gc> {
         // Remember the current frame count in order to exit the loop at the correct frame.
         let frame_count = self.frame_count;
         self.call_function(function, params)?;
+        if self.frame_count == frame_count {
+            // A generator function returns the generator without running
+            return Ok(self.pop_stack());
+        }
 
         loop {
-            if let Some(result) = self.dispatch_next(frame_count)? {
-                // Popup the call function pushed to the stack top
-                self.pop_stack();
-                return Ok(result);
+            match self.dispatch_next(frame_count) {
+                Ok(Some(result)) => {
+                    // Popup the call function pushed to the stack top
+                    self.pop_stack();
+                    return Ok(result);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    self.unwind_contexts(frame_count, &err);
+                    return Err(err);
+                }
             }
         }
     }
 
+    // Like `try_eval_closure`, for a function declared at the top level,
+    // e.g. a workflow step.
+    pub(crate) fn try_eval_function_with_id(
+        &mut self,
+        chunk_id: ChunkId,
+        params: &[Value<'gc>],
+    ) -> Result<Value<'gc>, VmError> {
+        let function = self.get_chunk(chunk_id)?;
+        let closure = Gc::new(self.mc, Closure::new(self.mc, function));
+        self.try_eval_closure(closure, params)
+    }
+
+    // Call the closure and run it to completion. Unlike `eval_function`, the frames
+    // and the stack are restored even if the call fails, so the caller can recover from it.
+    pub(crate) fn try_eval_closure(
+        &mut self,
+        closure: Gc<'gc, Closure<'gc>>,
+        params: &[Value<'gc>],
+    ) -> Result<Value<'gc>, VmError> {
+        self.try_eval_method(Value::from(closure), closure, params)
+    }
+
+    // Like `try_eval_closure`, with the receiver in slot zero of the call.
+    pub(crate) fn try_eval_method(
+        &mut self,
+        receiver: Value<'gc>,
+        closure: Gc<'gc, Closure<'gc>>,
+        params: &[Value<'gc>],
+    ) -> Result<Value<'gc>, VmError> {
+        let frame_count = self.frame_count;
+        let stack_top = self.stack_top;
+        let mut eval = || {
+            self.push_stack(receiver);
+            for param in params {
+                self.push_stack(*param);
+            }
+            self.call(closure, params.len() as u8, 0)?;
+            if self.frame_count == frame_count {
+                return Ok(self.pop_stack());
+            }
+            loop {
+                if let Some(result) = self.dispatch_next(frame_count)? {
+                    return Ok(result);
+                }
+            }
+        };
+        let result = eval();
+        if let Err(err) = &result {
+            self.unwind_contexts(frame_count, err);
+            self.close_upvalues(stack_top);
+            self.frames.truncate(frame_count);
+            self.frame_count = frame_count;
+        }
+        self.stack_top = stack_top;
+        result
+    }
+
     // Runs the VM for a period of time controlled by the `fuel` parameter.
     //
     // Returns `Ok(false)` if the method has exhausted its fuel, but there is more work to
     // do, and returns `Ok(true)` if no more progress can be made.
     pub(super) fn step(&mut self, fuel: &mut Fuel) -> Result<Option<ReturnValue>, VmError> {
         loop {
-            if let Some(result) = self.dispatch_next(0)? {
-                return Ok(Some(ReturnValue::from(result)));
+            match self.dispatch_next(0) {
+                Ok(Some(result)) => return Ok(Some(self.return_value(result)?)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.unwind_contexts(0, &err);
+                    return Err(err);
+                }
             }
             const FUEL_PER_STEP: i32 = 1;
             fuel.consume(FUEL_PER_STEP);
@@ -1179,6 +2152,539 @@ impl<'gc> State<'gc> {
         }
     }
 
+    /// Run the generator until its next `yield` and return the yielded value.
+    /// Once the function has returned, it's nil, or the error it raised.
+    pub(crate) fn resume_generator(
+        &mut self,
+        generator: GcRefLock<'gc, Generator<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        match generator.borrow().state {
+            GeneratorState::Done => return Ok(Value::Nil),
+            GeneratorState::Running => {
+                return Err(self.runtime_error("Generator is already running.".into()));
+            }
+            GeneratorState::Suspended => {}
+        }
+        if self.frame_count == FRAME_MAX_SIZE
+            || self.stack_top + generator.borrow().stack.len() > STACK_MAX_SIZE
+        {
+            return Err(self.runtime_error("Stack overflow.".into()));
+        }
+
+        let mut suspended = generator.borrow_mut(self.mc);
+        suspended.state = GeneratorState::Running;
+        let slots = mem::take(&mut suspended.stack);
+        let upvalues = mem::take(&mut suspended.upvalues);
+        let (closure, ip) = (suspended.closure, suspended.ip);
+        drop(suspended);
+
+        let frame_count = self.frame_count;
+        let slot_start = self.stack_top;
+        for value in slots {
+            self.push_stack(value);
+        }
+        // Reopen the upvalues at the new location of the frame, the open
+        // upvalues are sorted from the top of the stack.
+        for (offset, upvalue) in upvalues.into_iter().rev() {
+            let mut open = upvalue.borrow_mut(self.mc);
+            open.location = slot_start + offset;
+            if let Some(value) = open.closed.take() {
+                self.stack[open.location] = value;
+            }
+            open.next = self.open_upvalues;
+            drop(open);
+            self.open_upvalues = Some(upvalue);
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip,
+            slot_start,
+            generator: Some(generator),
+        });
+        self.frame_count += 1;
+
+        loop {
+            if let Some(value) = self.dispatch_next(frame_count)? {
+                return Ok(value);
+            }
+        }
+    }
+
+    // The generator methods run the VM, unlike the builtin methods of the
+    // other values.
+    fn invoke_generator_method(
+        &mut self,
+        generator: GcRefLock<'gc, Generator<'gc>>,
+        name: InternedString<'gc>,
+    ) -> Result<Value<'gc>, VmError> {
+        match name.as_bytes() {
+            b"next" => self.resume_generator(generator),
+            b"is_done" => Ok(Value::Boolean(generator.borrow().is_done())),
+            // Drain the remaining values, or the error raised on the way
+            b"to_array" => {
+                let mut values = Vec::new();
+                loop {
+                    let value = self.resume_generator(generator)?;
+                    if generator.borrow().is_done() {
+                        if value.is_error() {
+                            return Ok(value);
+                        }
+                        return Ok(Value::array(self.mc, values));
+                    }
+                    values.push(value);
+                }
+            }
+            _ => Err(self.runtime_error(format!("Unknown generator method: {}", name).into())),
+        }
+    }
+
+    // The builtin static methods of enums, a user defined static method
+    // with the same name takes precedence.
+    fn invoke_enum_method(
+        &mut self,
+        enum_: GcRefLock<'gc, Enum<'gc>>,
+        name: InternedString<'gc>,
+        args: Vec<Value<'gc>>,
+    ) -> Result<Value<'gc>, VmError> {
+        let variant = |name| {
+            let value = enum_.borrow().variants[&name];
+            Value::EnumVariant(Gc::new(self.mc, EnumVariant { enum_, name, value }))
+        };
+        match name.as_bytes() {
+            // All the variants in declaration order
+            b"variants" => {
+                let variants = enum_
+                    .borrow()
+                    .variant_names
+                    .iter()
+                    .map(|name| variant(*name))
+                    .collect();
+                Ok(Value::array(self.mc, variants))
+            }
+            // The variant with the value, or nil if there is none
+            b"from_value" => {
+                let [value] = args[..] else {
+                    return Err(self.runtime_error(
+                        format!(
+                            "{}.from_value() takes 1 argument, got {}.",
+                            enum_.borrow().name,
+                            args.len()
+                        )
+                        .into(),
+                    ));
+                };
+                let found = enum_.borrow().find_variant(&value);
+                Ok(found.map(variant).unwrap_or_default())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Convert the value to string, a variant of an enum implementing
+    // `to_str()` or an instance implementing `__str__()` is converted by calling it.
+    pub(crate) fn stringify(&mut self, value: Value<'gc>) -> Result<String, VmError> {
+        match self.eval_str_method(value)? {
+            Some(s) => Ok(s),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    // The result of the user defined string conversion of the value, if any.
+    fn eval_str_method(&mut self, value: Value<'gc>) -> Result<Option<String>, VmError> {
+        let method = match value {
+            Value::EnumVariant(variant) => {
+                let to_str = self.intern_static("to_str");
+                variant.enum_.borrow().methods.get(&to_str).copied()
+            }
+            Value::Instance(instance) => {
+                let str_ = self.intern_static("__str__");
+                instance.borrow().class.borrow().methods.get(&str_).copied()
+            }
+            _ => None,
+        };
+        match method {
+            Some(method) => Ok(Some(
+                self.try_eval_method(value, method.as_closure()?, &[])?
+                    .to_string(),
+            )),
+            None => Ok(None),
+        }
+    }
+
+    // The value returned to the host, the instances implementing `to_json()`
+    // are converted by calling it, the nested ones too.
+    pub(crate) fn return_value(&mut self, value: Value<'gc>) -> Result<ReturnValue, VmError> {
+        if let Value::Instance(instance) = value
+            && self.json_hook(instance).is_none()
+        {
+            let is_response = instance.borrow().class.borrow().name.as_bytes() == b"Response";
+            let fields = self.json_fields(instance, 0)?.into_iter().collect();
+            return Ok(if is_response {
+                ReturnValue::Response(fields)
+            } else {
+                ReturnValue::Object(fields)
+            });
+        }
+        if !matches!(
+            value,
+            Value::List(_) | Value::Object(_) | Value::Set(_) | Value::Dict(_) | Value::Instance(_)
+        ) {
+            return Ok(ReturnValue::from(value));
+        }
+        Ok(match self.jsonify(value)? {
+            serde_json::Value::Null => ReturnValue::Nil,
+            serde_json::Value::Bool(b) => ReturnValue::Boolean(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(n) => ReturnValue::Int(n),
+                None => ReturnValue::Number(n.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(s) => ReturnValue::String(s),
+            serde_json::Value::Array(items) => ReturnValue::Array(items),
+            serde_json::Value::Object(fields) => ReturnValue::Object(fields.into_iter().collect()),
+        })
+    }
+
+    // The name and the fields of an error instance or error enum variant.
+    pub(crate) fn raised_error(&mut self, value: Value<'gc>) -> Result<RaisedError, VmError> {
+        Ok(match value {
+            Value::EnumVariant(variant) => RaisedError {
+                name: variant.enum_.borrow().name.to_string(),
+                fields: serde_json::Map::from_iter([(
+                    "variant".to_owned(),
+                    serde_json::Value::String(variant.name.to_string()),
+                )]),
+            },
+            Value::Instance(instance) => RaisedError {
+                name: instance.borrow().class.borrow().name.to_string(),
+                fields: self.json_fields(instance, 0)?,
+            },
+            value => RaisedError {
+                name: value.to_string(),
+                fields: serde_json::Map::new(),
+            },
+        })
+    }
+
+    // Convert the value to JSON like `to_serde_value()`, an instance
+    // implementing `to_json()` is converted to the value it returns.
+    pub(crate) fn jsonify(&mut self, value: Value<'gc>) -> Result<serde_json::Value, VmError> {
+        self.jsonify_nested(value, 0)
+    }
+
+    // A value nested too deep is a cycle, e.g. an object containing itself
+    // or a `to_json()` returning its instance in another value.
+    fn jsonify_nested(
+        &mut self,
+        value: Value<'gc>,
+        depth: usize,
+    ) -> Result<serde_json::Value, VmError> {
+        if depth > MAX_JSON_DEPTH {
+            return Err(VmError::RuntimeError(format!(
+                "Can't serialize to JSON a value nested deeper than {MAX_JSON_DEPTH} levels, is it cyclic?"
+            )));
+        }
+        let depth = depth + 1;
+        Ok(match value {
+            Value::Instance(instance) => match self.json_hook(instance) {
+                Some(hook) => {
+                    let json = self.try_eval_method(value, hook, &[])?;
+                    if let Value::Instance(other) = json
+                        && Gc::ptr_eq(other.borrow().class, instance.borrow().class)
+                    {
+                        return Err(VmError::RuntimeError(format!(
+                            "to_json() of class '{}' can't return an instance of its class.",
+                            instance.borrow().class.borrow().name
+                        )));
+                    }
+                    self.jsonify_nested(json, depth)?
+                }
+                None => serde_json::Value::Object(self.json_fields(instance, depth)?),
+            },
+            Value::List(list) => {
+                let items = list.borrow().data.clone();
+                serde_json::Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| self.jsonify_nested(item, depth))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Value::Set(set) => {
+                let items = set.borrow().iter().collect::<Vec<_>>();
+                serde_json::Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| self.jsonify_nested(item, depth))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            Value::Object(obj) => {
+                let fields = obj
+                    .borrow()
+                    .fields
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), *value))
+                    .collect::<Vec<_>>();
+                serde_json::Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(key, value)| Ok((key, self.jsonify_nested(value, depth)?)))
+                        .collect::<Result<_, VmError>>()?,
+                )
+            }
+            Value::Dict(dict) => {
+                let fields = dict
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect::<Vec<_>>();
+                serde_json::Value::Object(
+                    fields
+                        .into_iter()
+                        .map(|(key, value)| Ok((key, self.jsonify_nested(value, depth)?)))
+                        .collect::<Result<_, VmError>>()?,
+                )
+            }
+            Value::EnumVariant(variant) => self.jsonify_nested(variant.value, depth)?,
+            value => value.to_serde_value(),
+        })
+    }
+
+    // Create an instance of the class from JSON, by its `from_json()` static
+    // method if any, otherwise the fields of a JSON object are set as is, or
+    // parsed in their `@json` format if declared.
+    pub(crate) fn instance_from_json(
+        &mut self,
+        class: GcRefLock<'gc, Class<'gc>>,
+        json: &serde_json::Value,
+    ) -> Result<Value<'gc>, VmError> {
+        let value = Value::from_serde_value(self.get_context(), json);
+        let name = self.intern_static("from_json");
+        let hook = class.borrow().static_methods.get(&name).copied();
+        if let Some(hook) = hook {
+            return self.try_eval_method(Value::Class(class), hook.as_closure()?, &[value]);
+        }
+        let Value::Object(obj) = value else {
+            return Err(VmError::RuntimeError(format!(
+                "Can't create an instance of class '{}' from {json}, expect a JSON object or a from_json() static method.",
+                class.borrow().name
+            )));
+        };
+        let mut instance = Instance::new(class);
+        for (key, value) in &obj.borrow().fields {
+            let value = match class.borrow().json_formats.get(key) {
+                Some(format) => format.read(*value).map_err(|err| {
+                    VmError::RuntimeError(format!(
+                        "Can't set field '{key}' of class '{}' from JSON: {err}.",
+                        class.borrow().name
+                    ))
+                })?,
+                None => *value,
+            };
+            instance.fields.insert(*key, value);
+        }
+        Ok(Value::from(Gc::new(self.mc, RefLock::new(instance))))
+    }
+
+    // The `to_json()` method of the class of the instance, if any.
+    pub(super) fn json_hook(
+        &mut self,
+        instance: GcRefLock<'gc, Instance<'gc>>,
+    ) -> Option<Gc<'gc, Closure<'gc>>> {
+        let name = self.intern_static("to_json");
+        let method = instance.borrow().class.borrow().methods.get(&name).copied();
+        method.and_then(|method| method.as_closure().ok())
+    }
+
+    // The JSON of the fields, in their `@json` format if declared.
+    fn json_fields(
+        &mut self,
+        instance: GcRefLock<'gc, Instance<'gc>>,
+        depth: usize,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, VmError> {
+        let (class_name, fields) = {
+            let instance = instance.borrow();
+            let class = instance.class.borrow();
+            let fields = instance
+                .fields
+                .iter()
+                .map(|(key, value)| (*key, *value, class.json_formats.get(key).copied()))
+                .collect::<Vec<_>>();
+            (class.name, fields)
+        };
+        fields
+            .into_iter()
+            .map(|(key, value, format)| {
+                let json = match format {
+                    Some(format) => format.write(value).map_err(|err| {
+                        VmError::RuntimeError(format!(
+                            "Can't serialize field '{key}' of class '{class_name}': {err}."
+                        ))
+                    })?,
+                    None => self.jsonify_nested(value, depth)?,
+                };
+                Ok((key.to_string(), json))
+            })
+            .collect()
+    }
+
+    // The result of the operator method of an instance called with the other
+    // operand, e.g. `__add__` for `a + b`. None if the receiver isn't an
+    // instance implementing it.
+    fn eval_operator(
+        &mut self,
+        name: &'static str,
+        receiver: Value<'gc>,
+        arg: Value<'gc>,
+    ) -> Result<Option<Value<'gc>>, VmError> {
+        let Value::Instance(instance) = receiver else {
+            return Ok(None);
+        };
+        let name = self.intern_static(name);
+        let method = instance.borrow().class.borrow().methods.get(&name).copied();
+        match method {
+            Some(method) => self
+                .try_eval_method(receiver, method.as_closure()?, &[arg])
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Replace the two operands on the stack with the result of the operator
+    // method of the left one, returns false if it doesn't implement it.
+    fn overload_binary_op(&mut self, name: &'static str) -> Result<bool, VmError> {
+        let (a, b) = (*self.peek(1), *self.peek(0));
+        let Some(value) = self.eval_operator(name, a, b)? else {
+            return Ok(false);
+        };
+        self.stack_top -= 2;
+        self.push_stack(value);
+        Ok(true)
+    }
+
+    // Like `overload_binary_op` with `__lt__`, the operands are swapped and
+    // the result negated as requested.
+    fn overload_comparison(&mut self, swap: bool, negate: bool) -> Result<bool, VmError> {
+        let (mut a, mut b) = (*self.peek(1), *self.peek(0));
+        if swap {
+            (a, b) = (b, a);
+        }
+        let Some(value) = self.eval_operator("__lt__", a, b)? else {
+            return Ok(false);
+        };
+        self.stack_top -= 2;
+        self.push_stack((value.is_falsy() == negate).into());
+        Ok(true)
+    }
+
+    // Whether the values are equal, by `__eq__` if the left one is an instance implementing it.
+    fn values_equal(&mut self, a: Value<'gc>, b: Value<'gc>) -> Result<bool, VmError> {
+        match self.eval_operator("__eq__", a, b)? {
+            Some(value) => Ok(!value.is_falsy()),
+            None => Ok(a.equals(&b)),
+        }
+    }
+
+    // The enter and exit methods of the manager of a `with` body.
+    fn context_methods(
+        &mut self,
+        manager: Value<'gc>,
+    ) -> Result<(Gc<'gc, Closure<'gc>>, Gc<'gc, Closure<'gc>>), VmError> {
+        if let Value::Instance(instance) = manager {
+            let class = instance.borrow().class;
+            for (enter, exit) in [("__enter__", "__exit__"), ("acquire", "release")] {
+                let (enter, exit) = (self.intern_static(enter), self.intern_static(exit));
+                let methods = &class.borrow().methods;
+                if let (Some(enter), Some(exit)) = (methods.get(&enter), methods.get(&exit)) {
+                    return Ok((enter.as_closure()?, exit.as_closure()?));
+                }
+            }
+        }
+        Err(self.runtime_error(
+            "The manager of 'with' must implement __enter__() and __exit__(), or acquire() and release()."
+                .into(),
+        ))
+    }
+
+    // Exit the context of the manager, the error the body failed with is
+    // passed to the exit method if it takes a parameter, e.g. `error = nil`.
+    fn exit_context(
+        &mut self,
+        manager: Value<'gc>,
+        error: Option<Value<'gc>>,
+    ) -> Result<(), VmError> {
+        let (_, exit) = self.context_methods(manager)?;
+        let args = match error {
+            Some(error) if exit.function.max_arity > 0 => vec![error],
+            _ => Vec::new(),
+        };
+        self.try_eval_method(manager, exit, &args)?;
+        Ok(())
+    }
+
+    // Exit the contexts entered above the frame count, the frames are unwound by the error.
+    fn unwind_contexts(&mut self, frame_count: usize, err: &VmError) {
+        if self
+            .contexts
+            .last()
+            .is_none_or(|(depth, _)| *depth <= frame_count)
+        {
+            return;
+        }
+        let message = match err {
+            VmError::Traced(error) => error.message.clone(),
+            err => err.to_string(),
+        };
+        let error = Value::String(self.intern(message.as_bytes()));
+        while let Some(&(depth, manager)) = self.contexts.last()
+            && depth > frame_count
+        {
+            self.contexts.pop();
+            // An error of the exit method is dropped, the original one is reported
+            let _ = self.exit_context(manager, Some(error));
+        }
+    }
+
+    // Save the frame of the generator on `yield` and pop it.
+    fn suspend_generator(&mut self) {
+        let frame = self.frames.pop().expect("the generator frame");
+        self.frame_count -= 1;
+        let mut upvalues = Vec::new();
+        while let Some(upvalue) = self
+            .open_upvalues
+            .filter(|upvalue| upvalue.borrow().location >= frame.slot_start)
+        {
+            let mut open = upvalue.borrow_mut(self.mc);
+            open.closed = Some(self.stack[open.location]);
+            self.open_upvalues = open.next.take();
+            upvalues.push((open.location - frame.slot_start, upvalue));
+        }
+
+        let generator = frame.generator.expect("yield from a generator");
+        let mut suspended = generator.borrow_mut(self.mc);
+        suspended.stack = self.stack[frame.slot_start..self.stack_top].to_vec();
+        suspended.upvalues = upvalues;
+        suspended.ip = frame.ip;
+        suspended.state = GeneratorState::Suspended;
+        self.stack_top = frame.slot_start;
+    }
+
+    // Pop the frame of the generator on return, it can't be resumed anymore.
+    fn finish_generator(&mut self, generator: GcRefLock<'gc, Generator<'gc>>) -> Value<'gc> {
+        let return_value = self.pop_stack();
+        let frame = self.frames.pop().expect("the generator frame");
+        self.frame_count -= 1;
+        self.close_upvalues(frame.slot_start);
+        self.stack_top = frame.slot_start;
+        generator.borrow_mut(self.mc).state = GeneratorState::Done;
+        // A raised error is handed to the caller, the returned value is dropped
+        if return_value.is_error() {
+            return_value
+        } else {
+            Value::Nil
+        }
+    }
+
     fn capture_upvalue(&mut self, slot: usize) -> GcRefLock<'gc, UpvalueObj<'gc>> {
         let mut prev_upvalue = None;
         let mut open_upvalue = self.open_upvalues;
@@ -1263,7 +2769,56 @@ impl<'gc> State<'gc> {
 
     pub(crate) fn define_native_function(&mut self, name: &'static str, function: NativeFn<'gc>) {
         let s = self.intern_static(name);
-        self.globals.insert(s, Value::NativeFunction(function));
+        self.natives.insert(s, Value::NativeFunction(function));
+    }
+
+    // Define a function of the host application, a qualified name like
+    // `host.lookup` exports it from the `host` module, a plain name defines
+    // it as a builtin.
+    pub(crate) fn define_host_function(&mut self, name: &str, function: Box<HostFn>) {
+        let ctx = self.get_context();
+        let value = Value::HostFunction(Gc::new(
+            self.mc,
+            HostFunction {
+                name: name.to_owned(),
+                function,
+            },
+        ));
+        match name.rsplit_once('.') {
+            Some((module, export)) => {
+                let module = ctx.intern(module.as_bytes());
+                self.module_manager
+                    .modules
+                    .entry(module)
+                    .or_insert_with(|| ModuleKind::Native {
+                        name: module,
+                        exports: Default::default(),
+                    })
+                    .add_export(ctx.intern(export.as_bytes()), value);
+            }
+            None => {
+                self.natives.insert(ctx.intern(name.as_bytes()), value);
+            }
+        }
+    }
+
+    // The name of a builtin or a loaded native module function,
+    // e.g. `len` or `std.math.sqrt`.
+    pub(crate) fn native_name(&self, function: NativeFn<'gc>) -> Option<String> {
+        let same = |value: &Value<'gc>| matches!(value, Value::NativeFunction(native) if std::ptr::fn_addr_eq(**native, *function));
+        if let Some((name, _)) = self.natives.iter().find(|(_, value)| same(value)) {
+            return Some(name.to_string());
+        }
+        self.module_manager
+            .modules
+            .values()
+            .find_map(|module| match module {
+                ModuleKind::Native { name, exports } => exports
+                    .iter()
+                    .find(|(_, value)| same(value))
+                    .map(|(export, _)| format!("{name}.{export}")),
+                ModuleKind::Script { .. } => None,
+            })
     }
 
     fn bind_method(
@@ -1292,6 +2847,17 @@ impl<'gc> State<'gc> {
     ) -> Result<(), VmError> {
         let args_slot_count = (args_count + keyword_args_count * 2) as usize;
         if let Value::Class(class) = callee {
+            let attributes = class.borrow().attributes;
+            if !attributes.is_empty() {
+                // The module of the class is the one of its constructor
+                let module = class
+                    .borrow()
+                    .methods
+                    .get(&self.intern(b"new"))
+                    .and_then(|new| new.as_closure().ok())
+                    .and_then(|new| new.function.module);
+                self.check_attributes(class.borrow().name, attributes, module)?;
+            }
             let instance = Instance::new(class);
             self.stack[self.stack_top - args_slot_count - 1] =
                 Value::from(Gc::new(self.mc, RefLock::new(instance)));
@@ -1364,18 +2930,93 @@ impl<'gc> State<'gc> {
                 // Calculate total arguments slots (positional + keyword pairs)
                 let total_args = args_count + keyword_args_count * 2;
                 let args = self.pop_stack_n(total_args as usize);
-                let result = function(self, args).map_err(|err| match err {
-                    VmError::CompileError => err,
+                let result = self.profiled(
+                    |state| {
+                        state
+                            .native_name(function)
+                            .unwrap_or_else(|| "<native>".to_owned())
+                    },
+                    |state| function(state, args),
+                );
+                let result = result.map_err(|err| match err {
                     VmError::RuntimeError(message) => self.runtime_error(message.into()),
+                    err => err,
                 })?;
                 self.stack_top -= 1; // Remove the function
                 self.push_stack(result);
                 Ok(())
             }
+            Value::HostFunction(function) => {
+                if keyword_args_count > 0 {
+                    return Err(self.runtime_error(
+                        format!("{}() doesn't take keyword arguments.", function.name).into(),
+                    ));
+                }
+                let args = self
+                    .pop_stack_n(args_count as usize)
+                    .iter()
+                    .map(Value::to_serde_value)
+                    .collect();
+                let result =
+                    self.profiled(|_| function.name.clone(), |_| (function.function)(args));
+                let result = result.map_err(|message| self.runtime_error(message.into()))?;
+                self.stack_top -= 1; // Remove the function
+                let result = Value::from_serde_value(self.get_context(), &result);
+                self.push_stack(result);
+                Ok(())
+            }
             _ => Err(self.runtime_error("Can only call functions and classes.".into())),
         }
     }
 
+    // Replace the object or dict on top of the stack with its entries as
+    // keyword arguments following the given ones, returns their new count.
+    fn spread_keywords(&mut self, keyword_count: u8) -> Result<u8, VmError> {
+        let entries = match self.pop_stack() {
+            Value::Object(object) => object
+                .borrow()
+                .fields
+                .iter()
+                .map(|(name, value)| (*name, *value))
+                .collect::<Vec<_>>(),
+            Value::Dict(dict) => {
+                let entries = dict
+                    .borrow()
+                    .iter()
+                    .map(|(key, value)| key.as_string().map(|name| (name, value)))
+                    .collect::<Result<Vec<_>, _>>();
+                entries.map_err(|_| {
+                    self.runtime_error("Keyword argument names must be strings.".into())
+                })?
+            }
+            value => {
+                return Err(self.runtime_error(
+                    format!(
+                        "Can only spread an object or a dict into keyword arguments, got {value}."
+                    )
+                    .into(),
+                ));
+            }
+        };
+        let count = keyword_count as usize + entries.len();
+        if count > u8::MAX as usize {
+            return Err(self.runtime_error("Can't have more than 255 keyword arguments.".into()));
+        }
+        let given = (0..keyword_count as usize)
+            .map(|i| self.stack[self.stack_top - (keyword_count as usize - i) * 2])
+            .collect::<Vec<_>>();
+        for (name, value) in entries {
+            if given.contains(&Value::String(name)) {
+                return Err(self.runtime_error(
+                    format!("Got multiple values for keyword argument '{name}'.").into(),
+                ));
+            }
+            self.push_stack(Value::String(name));
+            self.push_stack(value);
+        }
+        Ok(count as u8)
+    }
+
     fn invoke_from_class(
         &mut self,
         class: GcRefLock<'gc, Class<'gc>>,
@@ -1441,6 +3082,89 @@ impl<'gc> State<'gc> {
                 self.push_stack(result);
                 Ok(())
             }
+            Value::Decimal(_) => {
+                let mut args = Vec::new();
+
+                // Collect arguments
+                for _ in 0..args_count {
+                    args.push(self.pop_stack());
+                }
+                args.reverse(); // Restore argument order
+
+                // Pop the receiver and keyword args
+                self.stack_top -= keyword_args_count as usize * 2 + 1;
+
+                // Dispatch to decimal method
+                let result = self
+                    .builtin_methods
+                    .invoke_decimal_method(self.mc, name, receiver, args)?;
+                self.push_stack(result);
+                Ok(())
+            }
+            Value::DateTime(_) => {
+                let mut args = Vec::new();
+
+                // Collect arguments
+                for _ in 0..args_count {
+                    args.push(self.pop_stack());
+                }
+                args.reverse(); // Restore argument order
+
+                // Pop the receiver and keyword args
+                self.stack_top -= keyword_args_count as usize * 2 + 1;
+
+                // Dispatch to datetime method
+                let result = self
+                    .builtin_methods
+                    .invoke_datetime_method(self.mc, name, receiver, args)?;
+                self.push_stack(result);
+                Ok(())
+            }
+            Value::Dict(_) => {
+                let mut args = Vec::new();
+
+                // Collect arguments
+                for _ in 0..args_count {
+                    args.push(self.pop_stack());
+                }
+                args.reverse(); // Restore argument order
+
+                // Pop the receiver and keyword args
+                self.stack_top -= keyword_args_count as usize * 2 + 1;
+
+                // Dispatch to dict method
+                let result = self
+                    .builtin_methods
+                    .invoke_dict_method(self.mc, name, receiver, args)?;
+                self.push_stack(result);
+                Ok(())
+            }
+            Value::Set(_) => {
+                let mut args = Vec::new();
+
+                // Collect arguments
+                for _ in 0..args_count {
+                    args.push(self.pop_stack());
+                }
+                args.reverse(); // Restore argument order
+
+                // Pop the receiver and keyword args
+                self.stack_top -= keyword_args_count as usize * 2 + 1;
+
+                // Dispatch to set method
+                let result = self
+                    .builtin_methods
+                    .invoke_set_method(self.mc, name, receiver, args)?;
+                self.push_stack(result);
+                Ok(())
+            }
+            Value::Generator(generator) => {
+                // Pop the arguments and the receiver
+                self.stack_top -= args_slot_count + 1;
+                let result = self.invoke_generator_method(generator, name)?;
+                self.push_stack(result);
+                Ok(())
+            }
             Value::Class(class) => {
                 if let Some(value) = class.borrow().static_methods.get(&name) {
                     self.call_value(*value, args_count, keyword_args_count)
@@ -1485,6 +3209,17 @@ impl<'gc> State<'gc> {
             Value::Enum(enum_) => {
                 if let Some(value) = enum_.borrow().static_methods.get(&name) {
                     self.call_value(*value, args_count, keyword_args_count)
+                } else if matches!(name.as_bytes(), b"variants" | b"from_value") {
+                    let mut args = Vec::new();
+                    for _ in 0..args_count {
+                        args.push(self.pop_stack());
+                    }
+                    args.reverse();
+                    // Pop the receiver and keyword args
+                    self.stack_top -= keyword_args_count as usize * 2 + 1;
+                    let result = self.invoke_enum_method(enum_, name, args)?;
+                    self.push_stack(result);
+                    Ok(())
                 } else {
                     Err(self.runtime_error(
                         format!(
@@ -1525,6 +3260,10 @@ impl<'gc> State<'gc> {
                     self.stack[self.stack_top - args_slot_count - 1] = value;
                     // Now call the function
                     self.call_value(value, args_count, keyword_args_count)
+                } else if self.module_manager.is_private(module_name, name) {
+                    Err(self.runtime_error(
+                        format!("'{}' is private in module '{}'.", name, module_name).into(),
+                    ))
                 } else {
                     Err(self.runtime_error(
                         format!("Undefined function '{}' in module '{}'", name, module_name).into(),
@@ -1535,14 +3274,24 @@ impl<'gc> State<'gc> {
                 if let Some(method) = agent.methods.get(&name) {
                     let args = self.check_args(method, args_count, keyword_args_count)?;
 
-                    // Pop the arguments from the stack.
+                    // Pop the agent and the arguments from the stack.
                     // The stack before call run_agent:
                     // [ <fn script> ][ agent Triage ][ debug ][ true ][ input ][ some message ]
                     // 0033    | OP_INVOKE        (0 args) 17 'run'
                     // The stack after called run_agent:
-                    // [ <fn script> ][ agent Triage ]
-                    self.stack_top -= (args_count + keyword_args_count * 2) as usize;
-                    let result = ai::run_agent(self, agent, args);
+                    // [ <fn script> ]
+                    if !self.is_replaying() {
+                        self.require(Capability::Net)?;
+                    }
+                    self.stack_top -= (args_count + keyword_args_count * 2) as usize + 1;
+                    let result = self.profiled(
+                        |_| format!("agent {}", agent.name),
+                        |state| ai::run_agent(state, agent, args),
+                    );
+                    let result = result.map_err(|err| match err {
+                        VmError::RuntimeError(message) => self.runtime_error(message.into()),
+                        err => err,
+                    })?;
                     self.push_stack(result);
                     Ok(())
                 } else {
@@ -1550,6 +3299,21 @@ impl<'gc> State<'gc> {
                         .runtime_error(format!("Agent have no method called '{}'.", name).into()))
                 }
             }
+            Value::Workflow(workflow) => {
+                let args = self.pop_stack_n(args_slot_count);
+                // Pop the workflow
+                self.stack_top -= 1;
+                let result = self.profiled(
+                    |_| format!("workflow {}.{name}", workflow.name),
+                    |state| workflow::invoke(state, workflow, &name.to_string(), args),
+                );
+                let result = result.map_err(|err| match err {
+                    VmError::RuntimeError(message) => self.runtime_error(message.into()),
+                    err => err,
+                })?;
+                self.push_stack(result);
+                Ok(())
+            }
             _ => Err(self.runtime_error("Only instances or modules have methods.".into())),
         }
     }
@@ -1563,7 +3327,12 @@ impl<'gc> State<'gc> {
         let total_args = args_count + keyword_args_count; // Count keyword args too
 
         // For functions without keyword args or default values
-        if function.arity == function.max_arity && total_args != function.arity {
+        if !function.variadic
+            && !function.kwargs
+            && function.keyword_only == 0
+            && function.arity == function.max_arity
+            && total_args != function.arity
+        {
             return Err(self.runtime_error(
                 format!(
                     "Expected {} arguments but got {}.",
@@ -1572,23 +3341,40 @@ impl<'gc> State<'gc> {
                 .into(),
             ));
         }
+        if !function.variadic && args_count > function.max_arity {
+            return Err(self.runtime_error(
+                format!(
+                    "Expected at most {} arguments but got {}.",
+                    function.max_arity, args_count,
+                )
+                .into(),
+            ));
+        }
 
         if self.frame_count == FRAME_MAX_SIZE {
             return Err(self.runtime_error("Stack overflow.".into()));
         }
 
         let max_arity = function.max_arity as usize;
-        let mut final_args = vec![Value::Nil; max_arity];
+        let rest_slot = max_arity + function.keyword_only as usize;
+        let kwargs_slot = rest_slot + function.variadic as usize;
+        let mut final_args = vec![Value::Nil; kwargs_slot + function.kwargs as usize];
 
         // Copy positional arguments
-        let total_args = args_count as usize;
         let keyword_slots = keyword_args_count as usize * 2;
-
+        let arg_start = self.stack_top - args_count as usize - keyword_slots;
+        let total_args = (args_count as usize).min(max_arity);
         if total_args > 0 {
-            let arg_start = self.stack_top - total_args - keyword_slots;
             final_args[..total_args]
                 .copy_from_slice(&self.stack[arg_start..(total_args + arg_start)]);
         }
+        if function.variadic {
+            // Collect the extra positional arguments into the rest parameter
+            let rest =
+                self.stack[(arg_start + total_args)..(arg_start + args_count as usize)].to_vec();
+            final_args[rest_slot] = Value::array(self.mc, rest);
+        }
+        let mut kwargs = Table::default();
 
         // Process keyword arguments
         if keyword_args_count > 0 {
@@ -1609,6 +3395,8 @@ impl<'gc> State<'gc> {
                         ));
                     }
                     final_args[pos] = value;
+                } else if function.kwargs {
+                    kwargs.insert(name, value);
                 } else {
                     return Err(
                         self.runtime_error(format!("Unknown keyword argument '{}'.", name).into())
@@ -1616,6 +3404,9 @@ impl<'gc> State<'gc> {
                 }
             }
         }
+        if function.kwargs {
+            final_args[kwargs_slot] = Value::Object(self.gc_ref(Object { fields: kwargs }));
+        }
         Ok(final_args)
     }
 
@@ -1630,7 +3421,7 @@ impl<'gc> State<'gc> {
         for (name, param) in &function.params {
             let pos = param.position as usize;
             if final_args[pos].equals(&Value::Nil) {
-                if pos < function.arity as usize && param.default_value.is_nil() {
+                if param.required {
                     return Err(
                         self.runtime_error(format!("Missing required argument '{}'.", name).into())
                     );
@@ -1655,7 +3446,7 @@ impl<'gc> State<'gc> {
         for (name, param) in &function.params {
             let pos = param.position as usize;
             if final_args[pos].equals(&Value::Nil) {
-                if pos < function.arity as usize && param.default_value.is_nil() {
+                if param.required {
                     validation_errors.push(crate::builtins::create_error_info(
                         ctx,
                         *name,
@@ -1667,7 +3458,7 @@ impl<'gc> State<'gc> {
                 }
                 final_args[pos] = param.default_value;
             } else {
-                for validator in &param.validators {
+                for validator in param.validators.iter() {
                     if let Err(err) = validator.validate(&final_args[pos].to_serde_value()) {
                         validation_errors.push(crate::builtins::create_error_info(
                             ctx,
@@ -1719,9 +3510,27 @@ impl<'gc> State<'gc> {
         keyword_args_count: u8,
         args: Vec<Value<'gc>>,
     ) -> Result<(), VmError> {
+        let function = closure.function;
+        if !function.attributes.is_empty()
+            && let Some(name) = function.name
+        {
+            self.check_attributes(name, function.attributes, function.module)?;
+        }
         self.stack_top -= args_count as usize + keyword_args_count as usize * 2;
         let slot_start = self.stack_top - 1; // -1 for the function itself
 
+        if closure.function.is_generator {
+            // The body doesn't run until the first `next()`, the slots of
+            // the frame are kept by the generator in the meantime.
+            let mut slots = Vec::with_capacity(args.len() + 1);
+            slots.push(self.stack[slot_start]);
+            slots.extend(args);
+            let generator = Generator::new(closure, slots);
+            self.stack_top = slot_start;
+            self.push_stack(Value::Generator(Gc::new(self.mc, RefLock::new(generator))));
+            return Ok(());
+        }
+
         for arg in args {
             self.push_stack(arg);
         }
@@ -1731,6 +3540,7 @@ impl<'gc> State<'gc> {
             closure,
             ip: 0,
             slot_start,
+            generator: None,
         };
 
         #[cfg(feature = "debug")]
@@ -1741,6 +3551,41 @@ impl<'gc> State<'gc> {
         Ok(())
     }
 
+    // An `@experimental` callee is an error unless enabled in project.toml.
+    // A `@deprecated` one is warned once per call site when called from
+    // another module, the calls within its module are warned at compile time.
+    #[cold]
+    fn check_attributes(
+        &mut self,
+        name: InternedString<'gc>,
+        attributes: Attributes<'gc>,
+        module: Option<InternedString<'gc>>,
+    ) -> Result<(), VmError> {
+        if attributes.experimental && !attributes::experimental_enabled() {
+            return Err(self.runtime_error(
+                format!(
+                    "'{name}' is experimental, enable it with `experimental = true` in the [language] section of project.toml."
+                )
+                .into(),
+            ));
+        }
+        if let Some(note) = attributes.deprecated
+            && self.frame_count > 0
+        {
+            let frame = &self.frames[self.frame_count - 1];
+            let caller = frame.closure.function;
+            if caller.module == module {
+                return Ok(());
+            }
+            let line = caller.chunk.line(frame.ip.saturating_sub(1));
+            let caller = caller
+                .name
+                .map_or_else(|| "script".to_owned(), |name| name.to_string());
+            attributes::warn_deprecated(&name.to_string(), &note.to_string(), &caller, line);
+        }
+        Ok(())
+    }
+
     #[inline(always)]
     pub fn push_stack(&mut self, value: Value<'gc>) {
         debug_assert!(self.stack_top < STACK_MAX_SIZE, "Stack overflow");
@@ -0,0 +1,473 @@
+//! The executions of the workflows declared by `workflow Name { ... }`.
+//!
+//! An execution runs the steps on its context object and is saved to
+//! `workflows.redb`, next to the store of `std.kv`, after each of them, so an
+//! execution interrupted by a crash resumes from the step it was running. Each attempt of a step starts
+//! from the saved context, the changes of a failed attempt are discarded:
+//!
+//! ```text
+//! let execution = Ingest.run({url: url});
+//! Ingest.status(execution.id);
+//! for id in Ingest.pending() { Ingest.resume(id); }
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aiscript_arena::{Collect, Gc};
+use redb::{Database, TableDefinition};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::{
+    Value, VmError,
+    ast::{ChunkId, Retry},
+    stdlib::{KvError, extract_keyword_args, kv_path, open_database, sleep_for, to_json_value},
+    string::InternedString,
+    vm::{Capability, State, run_blocking},
+};
+
+// The file of the executions, in the directory of the `std.kv` store.
+const EXECUTIONS_FILE: &str = "workflows.redb";
+
+// The executions by `<workflow>/<id>`, as JSON.
+const EXECUTIONS: TableDefinition<&str, &str> = TableDefinition::new("workflows");
+
+// The step name returned to end the workflow before its last step.
+const DONE: &str = "done";
+
+// The steps an execution runs at most, so a step returning its own name
+// can't grow the history without bound.
+const MAX_STEPS: usize = 100;
+
+#[derive(Collect)]
+#[collect(no_drop)]
+pub struct Workflow<'gc> {
+    pub name: InternedString<'gc>,
+    pub doc: Option<InternedString<'gc>>,
+    pub steps: Vec<WorkflowStep>,
+}
+
+#[derive(Debug, Clone, Collect)]
+#[collect(require_static)]
+pub struct WorkflowStep {
+    pub name: String,
+    pub chunk_id: ChunkId,
+    pub retry: Retry,
+    // The name and the chunk of the function undoing the step.
+    pub compensate: Option<(String, ChunkId)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Execution {
+    id: String,
+    workflow: String,
+    status: Status,
+    // The step to run next, None once the execution is finished.
+    step: Option<String>,
+    context: Json,
+    history: Vec<Entry>,
+    error: Option<String>,
+    // The seconds since the Unix epoch.
+    started_at: u64,
+    updated_at: u64,
+}
+
+// A step run, or a compensation run after a step failed.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    step: String,
+    // The step undone by the compensation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compensates: Option<String>,
+    status: Status,
+    attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Call the method of the workflow:
+///
+/// - `run(input = {})` runs a new execution with the input as its context.
+/// - `resume(id)` runs the remaining steps of an interrupted execution.
+/// - `status(id)` is the execution, nil if there's none of the id.
+/// - `pending()` is the ids of the executions still running, e.g. the ones
+///   interrupted by a crash of the process.
+///
+/// `run()` and `resume()` return the execution, its `status` is "completed"
+/// or "failed", its `history` the steps and compensations run.
+pub(crate) fn invoke<'gc>(
+    state: &mut State<'gc>,
+    workflow: Gc<'gc, Workflow<'gc>>,
+    method: &str,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let run = match method {
+        "run" => run,
+        "resume" => resume,
+        "status" => status,
+        "pending" => pending,
+        _ => {
+            return Err(VmError::RuntimeError(format!(
+                "Workflow has no method called '{method}'."
+            )));
+        }
+    };
+    // The executions are saved in the store
+    state.require(Capability::Fs)?;
+    run(state, workflow, args)
+}
+
+fn run<'gc>(
+    state: &mut State<'gc>,
+    workflow: Gc<'gc, Workflow<'gc>>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let (positional, keyword) = extract_keyword_args(&args, &["input"])?;
+    if positional.len() > 1 {
+        return Err(VmError::RuntimeError(
+            "run() takes at most 1 argument: the input.".into(),
+        ));
+    }
+    let context = match positional.first().or(keyword.get("input")) {
+        None | Some(Value::Nil) => Json::Object(Default::default()),
+        Some(input) => match to_json_value(state, input)? {
+            input @ Json::Object(_) => input,
+            _ => {
+                return Err(VmError::RuntimeError(
+                    "run() input must be an object.".into(),
+                ));
+            }
+        },
+    };
+    let now = now_secs();
+    let mut execution = Execution {
+        id: format!("{:016x}", rand::random::<u64>()),
+        workflow: workflow.name.to_string(),
+        status: Status::Running,
+        step: workflow.steps.first().map(|step| step.name.clone()),
+        context,
+        history: Vec::new(),
+        error: None,
+        started_at: now,
+        updated_at: now,
+    };
+    save(state, &execution)?;
+    execute(state, &workflow, &mut execution)?;
+    Ok(execution_value(state, &execution))
+}
+
+fn resume<'gc>(
+    state: &mut State<'gc>,
+    workflow: Gc<'gc, Workflow<'gc>>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let id = id_arg(&args, "resume")?;
+    let Some(mut execution) = load(state, &workflow, &id)? else {
+        return Err(VmError::RuntimeError(format!(
+            "resume() no execution '{id}' of workflow '{}'.",
+            workflow.name
+        )));
+    };
+    if execution.status != Status::Running {
+        return Err(VmError::RuntimeError(format!(
+            "resume() the execution '{id}' has already {}.",
+            match execution.status {
+                Status::Completed => "completed",
+                _ => "failed",
+            }
+        )));
+    }
+    execute(state, &workflow, &mut execution)?;
+    Ok(execution_value(state, &execution))
+}
+
+fn status<'gc>(
+    state: &mut State<'gc>,
+    workflow: Gc<'gc, Workflow<'gc>>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    let id = id_arg(&args, "status")?;
+    Ok(match load(state, &workflow, &id)? {
+        Some(execution) => execution_value(state, &execution),
+        None => Value::Nil,
+    })
+}
+
+fn pending<'gc>(
+    state: &mut State<'gc>,
+    workflow: Gc<'gc, Workflow<'gc>>,
+    args: Vec<Value<'gc>>,
+) -> Result<Value<'gc>, VmError> {
+    if !args.is_empty() {
+        return Err(VmError::RuntimeError(
+            "pending() takes no arguments.".into(),
+        ));
+    }
+    let prefix = format!("{}/", workflow.name);
+    let ids = with_executions(state, move |db| {
+        let txn = db.begin_read()?;
+        let table = match txn.open_table(EXECUTIONS) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut ids = Vec::new();
+        for entry in table.range(prefix.as_str()..)? {
+            let (key, execution) = entry?;
+            let Some(id) = key.value().strip_prefix(prefix.as_str()) else {
+                break;
+            };
+            let execution: Execution = serde_json::from_str(execution.value())?;
+            if execution.status == Status::Running {
+                ids.push(Json::from(id));
+            }
+        }
+        Ok(ids)
+    })?;
+    Ok(Value::from_serde_value(
+        state.get_context(),
+        &Json::Array(ids),
+    ))
+}
+
+// Run the steps from the current one until the execution finishes.
+fn execute<'gc>(
+    state: &mut State<'gc>,
+    workflow: &Workflow<'gc>,
+    execution: &mut Execution,
+) -> Result<(), VmError> {
+    while let Some(name) = execution.step.clone() {
+        if execution.history.len() >= MAX_STEPS {
+            compensate(state, workflow, execution)?;
+            execution.step = None;
+            execution.status = Status::Failed;
+            execution.error = Some(format!(
+                "The execution ran {MAX_STEPS} steps without ending, stopped before '{name}'."
+            ));
+            execution.updated_at = now_secs();
+            save(state, execution)?;
+            break;
+        }
+        let Some(index) = workflow.steps.iter().position(|step| step.name == name) else {
+            // The workflow changed since the execution was saved
+            execution.step = None;
+            execution.status = Status::Failed;
+            execution.error = Some(format!("No step '{name}' in the workflow."));
+            save(state, execution)?;
+            break;
+        };
+        let step = &workflow.steps[index];
+        let mut attempts = 0;
+        let outcome = loop {
+            attempts += 1;
+            let context = Value::from_serde_value(state.get_context(), &execution.context);
+            match call(state, step.chunk_id, context) {
+                Ok(result) => {
+                    let context = to_json_value(state, &context)?;
+                    break next_step(workflow, index, result).map(|next| (context, next));
+                }
+                // The execution stays running, to be resumed
+                Err(err @ (VmError::LimitExceeded(_) | VmError::DeadlineExceeded)) => {
+                    return Err(err);
+                }
+                Err(_) if attempts <= step.retry.times => {
+                    sleep_for(state, Duration::from_millis(step.retry.delay))?;
+                }
+                Err(err) => break Err(error_message(err)),
+            }
+        };
+        match outcome {
+            Ok((context, next)) => {
+                execution.context = context;
+                execution.history.push(Entry {
+                    step: name,
+                    compensates: None,
+                    status: Status::Completed,
+                    attempts,
+                    error: None,
+                });
+                if next.is_none() {
+                    execution.status = Status::Completed;
+                }
+                execution.step = next;
+            }
+            Err(error) => {
+                execution.history.push(Entry {
+                    step: name.clone(),
+                    compensates: None,
+                    status: Status::Failed,
+                    attempts,
+                    error: Some(error.clone()),
+                });
+                compensate(state, workflow, execution)?;
+                execution.step = None;
+                execution.status = Status::Failed;
+                execution.error = Some(format!("Step '{name}' failed: {error}"));
+            }
+        }
+        execution.updated_at = now_secs();
+        save(state, execution)?;
+    }
+    Ok(())
+}
+
+// The step after the step of the index, by the name it returned, or the
+// next one in the order of the workflow if it returned nil.
+fn next_step(workflow: &Workflow, index: usize, result: Value) -> Result<Option<String>, String> {
+    match result {
+        Value::Nil => Ok(workflow.steps.get(index + 1).map(|step| step.name.clone())),
+        Value::String(next) if next == DONE => Ok(None),
+        Value::String(next) => match workflow
+            .steps
+            .iter()
+            .find(|step| next == step.name.as_str())
+        {
+            Some(step) => Ok(Some(step.name.clone())),
+            None => Err(format!(
+                "returned '{next}', which isn't a step of the workflow."
+            )),
+        },
+        _ => Err(format!(
+            "must return the name of the next step, \"{DONE}\" or nil, not {result}."
+        )),
+    }
+}
+
+// Undo the completed steps, the last one first. A compensation is run once,
+// the others run even if it fails.
+fn compensate<'gc>(
+    state: &mut State<'gc>,
+    workflow: &Workflow<'gc>,
+    execution: &mut Execution,
+) -> Result<(), VmError> {
+    let completed: Vec<_> = execution
+        .history
+        .iter()
+        .rev()
+        .filter(|entry| entry.status == Status::Completed && entry.compensates.is_none())
+        .filter_map(|entry| {
+            let step = workflow.steps.iter().find(|step| step.name == entry.step)?;
+            step.compensate
+                .clone()
+                .map(|undo| (step.name.clone(), undo))
+        })
+        .collect();
+    for (step, (name, chunk_id)) in completed {
+        let context = Value::from_serde_value(state.get_context(), &execution.context);
+        let error = match call(state, chunk_id, context) {
+            Ok(_) => {
+                execution.context = to_json_value(state, &context)?;
+                None
+            }
+            Err(err @ (VmError::LimitExceeded(_) | VmError::DeadlineExceeded)) => return Err(err),
+            Err(err) => Some(error_message(err)),
+        };
+        execution.history.push(Entry {
+            step: name,
+            compensates: Some(step),
+            status: if error.is_some() {
+                Status::Failed
+            } else {
+                Status::Completed
+            },
+            attempts: 1,
+            error,
+        });
+    }
+    Ok(())
+}
+
+// Call the step or the compensation with the context, an error raised by
+// the function fails it like a runtime error.
+fn call<'gc>(
+    state: &mut State<'gc>,
+    chunk_id: ChunkId,
+    context: Value<'gc>,
+) -> Result<Value<'gc>, VmError> {
+    let result = state.try_eval_function_with_id(chunk_id, &[context])?;
+    if result.is_error() {
+        return Err(VmError::Raised(Box::new(state.raised_error(result)?)));
+    }
+    Ok(result)
+}
+
+fn error_message(err: VmError) -> String {
+    match err {
+        VmError::RuntimeError(message) => message,
+        VmError::Traced(error) => error.message,
+        VmError::Raised(error) => error.to_string(),
+        err => err.to_string(),
+    }
+}
+
+fn id_arg(args: &[Value], fn_name: &str) -> Result<String, VmError> {
+    match args {
+        [id] => Ok(id
+            .as_string_value()
+            .map_err(|_| VmError::RuntimeError(format!("{fn_name}() id must be a string.")))?
+            .as_str()
+            .to_owned()),
+        _ => Err(VmError::RuntimeError(format!(
+            "{fn_name}() takes 1 argument: the execution id."
+        ))),
+    }
+}
+
+fn execution_value<'gc>(state: &mut State<'gc>, execution: &Execution) -> Value<'gc> {
+    let execution = serde_json::to_value(execution).expect("executions serialize");
+    Value::from_serde_value(state.get_context(), &execution)
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+// Open the file of the executions for the operation only, unlike the store of
+// `std.kv` it isn't held by the process, so a worker resuming the executions
+// can open it while another process runs them.
+fn with_executions<'gc, T: Send + 'static>(
+    state: &mut State<'gc>,
+    op: impl FnOnce(&Database) -> Result<T, KvError> + Send + 'static,
+) -> Result<T, VmError> {
+    let path = kv_path().with_file_name(EXECUTIONS_FILE);
+    run_blocking(state, move || op(&open_database(&path)?))?
+        .map_err(|e| VmError::RuntimeError(format!("workflow: {e}")))
+}
+
+fn save(state: &mut State, execution: &Execution) -> Result<(), VmError> {
+    let key = format!("{}/{}", execution.workflow, execution.id);
+    let execution = serde_json::to_string(execution).expect("executions serialize");
+    with_executions(state, move |db| {
+        let txn = db.begin_write()?;
+        txn.open_table(EXECUTIONS)?
+            .insert(key.as_str(), execution.as_str())?;
+        txn.commit()?;
+        Ok(())
+    })
+}
+
+fn load(state: &mut State, workflow: &Workflow, id: &str) -> Result<Option<Execution>, VmError> {
+    let key = format!("{}/{}", workflow.name, id);
+    with_executions(state, move |db| {
+        let txn = db.begin_read()?;
+        let table = match txn.open_table(EXECUTIONS) {
+            Ok(table) => table,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        match table.get(key.as_str())? {
+            Some(execution) => Ok(Some(serde_json::from_str(execution.value())?)),
+            None => Ok(None),
+        }
+    })
+}
@@ -0,0 +1,220 @@
+use std::{
+    env,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aiscript_vm::CoverageTrace;
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CoverageFormat {
+    /// The lcov tracefile, read by genhtml and the coverage services.
+    Lcov,
+    /// A standalone HTML page with the source of each file.
+    Html,
+}
+
+impl CoverageFormat {
+    fn default_output(self) -> &'static str {
+        match self {
+            CoverageFormat::Lcov => "lcov.info",
+            CoverageFormat::Html => "coverage.html",
+        }
+    }
+}
+
+/// The report of a coverage trace recorded by a build with the `coverage`
+/// feature, the lines not run are the untested branches of the handlers.
+pub struct CoverageCommand {
+    trace: CoverageTrace,
+}
+
+impl CoverageCommand {
+    pub fn new(path: &Path) -> Result<Self, String> {
+        let trace = CoverageTrace::load(path).map_err(|e| {
+            format!(
+                "Failed to read the coverage trace '{}': {}, run the tests with a build of the `coverage` feature first",
+                path.display(),
+                e
+            )
+        })?;
+        Ok(Self { trace })
+    }
+
+    /// Write the report in the format, returns the summary of the files.
+    pub fn report(
+        &self,
+        format: CoverageFormat,
+        output: Option<PathBuf>,
+    ) -> Result<String, String> {
+        let output = output.unwrap_or_else(|| PathBuf::from(format.default_output()));
+        let report = match format {
+            CoverageFormat::Lcov => self.lcov(),
+            CoverageFormat::Html => self.html(),
+        };
+        fs::write(&output, report)
+            .map_err(|e| format!("Failed to write '{}': {}", output.display(), e))?;
+        Ok(format!("{}\nWrote {}", self.summary(), output.display()))
+    }
+
+    /// The lines run and the executable lines of each file, with the total.
+    pub fn summary(&self) -> String {
+        let mut summary = String::new();
+        let (mut total_hit, mut total) = (0, 0);
+        for (file, lines) in &self.trace.files {
+            let (hit, found) = counts(lines);
+            total_hit += hit;
+            total += found;
+            writeln!(
+                summary,
+                "{:>6} {hit:>5}/{found:<5} {}",
+                percent(hit, found),
+                display_path(file)
+            )
+            .unwrap();
+        }
+        write!(
+            summary,
+            "{:>6} {total_hit:>5}/{total:<5} total",
+            percent(total_hit, total)
+        )
+        .unwrap();
+        summary
+    }
+
+    // See the tracefile format in the geninfo(1) manual.
+    fn lcov(&self) -> String {
+        let mut lcov = String::from("TN:\n");
+        for (file, lines) in &self.trace.files {
+            writeln!(lcov, "SF:{}", file.display()).unwrap();
+            for (line, count) in lines {
+                writeln!(lcov, "DA:{line},{count}").unwrap();
+            }
+            let (hit, found) = counts(lines);
+            writeln!(lcov, "LF:{found}\nLH:{hit}\nend_of_record").unwrap();
+        }
+        lcov
+    }
+
+    fn html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>AIScript coverage</title>\n<style>\
+            body { font-family: sans-serif; } pre { line-height: 1.3; } \
+            .hit { background: #dfd; } .miss { background: #fdd; } .count { color: #888; }\
+            </style>\n</head>\n<body>\n",
+        );
+        writeln!(
+            html,
+            "<h1>Coverage</h1>\n<pre>{}</pre>",
+            escape(&self.summary())
+        )
+        .unwrap();
+        for (file, lines) in &self.trace.files {
+            let (hit, found) = counts(lines);
+            writeln!(
+                html,
+                "<h2>{} {}</h2>\n<pre>",
+                escape(&display_path(file)),
+                percent(hit, found)
+            )
+            .unwrap();
+            let Ok(source) = fs::read_to_string(file) else {
+                html.push_str("The source file can't be read.</pre>\n");
+                continue;
+            };
+            for (i, text) in source.lines().enumerate() {
+                let line = i as u32 + 1;
+                let (class, count) = match lines.get(&line) {
+                    Some(0) => ("miss", "0".to_string()),
+                    Some(count) => ("hit", count.to_string()),
+                    None => ("", String::new()),
+                };
+                writeln!(
+                    html,
+                    "<span class=\"{class}\">{line:>5} <span class=\"count\">{count:>6}</span>  {}</span>",
+                    escape(text)
+                )
+                .unwrap();
+            }
+            html.push_str("</pre>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+// The lines run and the executable lines.
+fn counts(lines: &std::collections::BTreeMap<u32, u64>) -> (usize, usize) {
+    let hit = lines.values().filter(|count| **count > 0).count();
+    (hit, lines.len())
+}
+
+fn percent(hit: usize, found: usize) -> String {
+    if found == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", hit as f64 * 100.0 / found as f64)
+}
+
+// The path relative to the current directory if it's inside.
+fn display_path(file: &Path) -> String {
+    env::current_dir()
+        .ok()
+        .and_then(|dir| dir.canonicalize().ok())
+        .and_then(|dir| file.strip_prefix(dir).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| file.to_path_buf())
+        .display()
+        .to_string()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_coverage_report() {
+        let temp_dir = tempdir().unwrap();
+        let route = temp_dir.path().join("users.ai");
+        fs::write(
+            &route,
+            "get /users {\n    if x < 1 {\n        return 1;\n    }\n}\n",
+        )
+        .unwrap();
+        let trace = temp_dir.path().join("coverage.json");
+        CoverageTrace {
+            files: BTreeMap::from([(route.clone(), BTreeMap::from([(2, 3), (3, 0)]))]),
+        }
+        .save(&trace)
+        .unwrap();
+
+        let command = CoverageCommand::new(&trace).unwrap();
+        assert_eq!(
+            command.lcov(),
+            format!(
+                "TN:\nSF:{}\nDA:2,3\nDA:3,0\nLF:2\nLH:1\nend_of_record\n",
+                route.display()
+            )
+        );
+        assert!(command.summary().ends_with(" 50.0%     1/2     total"));
+        let html = command.html();
+        assert!(html.contains("<span class=\"hit\">    2 <span class=\"count\">     3</span>      if x &lt; 1 {</span>"));
+        assert!(html.contains("<span class=\"miss\">    3 "));
+
+        let output = temp_dir.path().join("lcov.info");
+        command
+            .report(CoverageFormat::Lcov, Some(output.clone()))
+            .unwrap();
+        assert_eq!(fs::read_to_string(output).unwrap(), command.lcov());
+        assert!(CoverageCommand::new(&temp_dir.path().join("missing.json")).is_err());
+    }
+}
@@ -0,0 +1,486 @@
+//! A debug adapter speaking the Debug Adapter Protocol over TCP, so editors
+//! can attach to a script, set breakpoints, step through it and inspect
+//! its variables.
+//!
+//! The adapter listens on a port rather than stdio since the output of the
+//! script is printed to stdout.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+};
+
+use aiscript_vm::{DebugAction, DebugHandler, Debugger, PauseReason, Paused, Vm, VmError};
+use serde_json::{Value, json};
+use tokio::runtime::Handle;
+
+// The only thread of a script
+const THREAD_ID: u64 = 1;
+// The variables reference of the globals, the locals of the frame at
+// index `i` use `i + 2`
+const GLOBALS_REFERENCE: u64 = 1;
+// The longest message read, the session is closed on a longer one
+const MAX_MESSAGE_LENGTH: usize = 1 << 20;
+
+enum Event {
+    Request(Value),
+    Paused(Paused),
+    Ended(Result<(), String>),
+    Closed,
+}
+
+/// Accept the debug sessions of the editors on the port one at a time,
+/// every session runs its script with a new VM.
+pub fn serve(port: u16, new_vm: impl Fn() -> Vm + Send + Sync + 'static) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Debug adapter listening on 127.0.0.1:{port}");
+    let new_vm: Arc<dyn Fn() -> Vm + Send + Sync> = Arc::new(new_vm);
+    for stream in listener.incoming() {
+        if let Err(err) = Session::new(stream?, new_vm.clone())?.run() {
+            eprintln!("Debug session failed: {err}");
+        }
+    }
+    Ok(())
+}
+
+struct ChannelHandler {
+    events: Sender<Event>,
+    actions: Receiver<DebugAction>,
+}
+
+impl DebugHandler for ChannelHandler {
+    fn paused(&mut self, paused: &Paused) -> DebugAction {
+        if self.events.send(Event::Paused(paused.clone())).is_err() {
+            return DebugAction::Stop;
+        }
+        // The script is stopped once the editor is gone
+        self.actions.recv().unwrap_or(DebugAction::Stop)
+    }
+}
+
+struct Session {
+    writer: TcpStream,
+    events: Sender<Event>,
+    receiver: Receiver<Event>,
+    new_vm: Arc<dyn Fn() -> Vm + Send + Sync>,
+    seq: u64,
+    program: Option<PathBuf>,
+    stop_on_entry: bool,
+    breakpoints: Vec<(PathBuf, Vec<u32>)>,
+    // Set once the script runs
+    actions: Option<Sender<DebugAction>>,
+    paused: Option<Paused>,
+}
+
+impl Session {
+    fn new(stream: TcpStream, new_vm: Arc<dyn Fn() -> Vm + Send + Sync>) -> io::Result<Self> {
+        let (events, receiver) = mpsc::channel();
+        let reader = BufReader::new(stream.try_clone()?);
+        let requests = events.clone();
+        thread::spawn(move || read_messages(reader, requests));
+        Ok(Self {
+            writer: stream,
+            events,
+            receiver,
+            new_vm,
+            seq: 0,
+            program: None,
+            stop_on_entry: false,
+            breakpoints: Vec::new(),
+            actions: None,
+            paused: None,
+        })
+    }
+
+    fn run(mut self) -> io::Result<()> {
+        while let Ok(event) = self.receiver.recv() {
+            match event {
+                Event::Request(request) => {
+                    if !self.handle(&request)? {
+                        break;
+                    }
+                }
+                Event::Paused(paused) => {
+                    let reason = match paused.reason {
+                        PauseReason::Entry => "entry",
+                        PauseReason::Breakpoint => "breakpoint",
+                        PauseReason::Step => "step",
+                    };
+                    self.paused = Some(paused);
+                    self.event(
+                        "stopped",
+                        json!({"reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true}),
+                    )?;
+                }
+                Event::Ended(result) => {
+                    let exit_code = match result {
+                        Ok(()) => 0,
+                        Err(message) => {
+                            self.event(
+                                "output",
+                                json!({"category": "stderr", "output": format!("{message}\n")}),
+                            )?;
+                            70
+                        }
+                    };
+                    self.event("exited", json!({"exitCode": exit_code}))?;
+                    self.event("terminated", json!({}))?;
+                }
+                Event::Closed => break,
+            }
+        }
+        // Stop the script if it's still running
+        self.actions.take();
+        Ok(())
+    }
+
+    // Handle the request, returns whether the session goes on.
+    fn handle(&mut self, request: &Value) -> io::Result<bool> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let body = match command {
+            "initialize" => {
+                self.respond(
+                    request,
+                    Ok(json!({"supportsConfigurationDoneRequest": true})),
+                )?;
+                self.event("initialized", json!({}))?;
+                return Ok(true);
+            }
+            "launch" => {
+                self.program = arguments["program"].as_str().map(PathBuf::from);
+                self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                match self.program {
+                    Some(_) => Ok(json!({})),
+                    None => Err("Missing the program to launch.".to_string()),
+                }
+            }
+            "setBreakpoints" => {
+                let path = PathBuf::from(arguments["source"]["path"].as_str().unwrap_or_default());
+                let lines: Vec<u32> = arguments["breakpoints"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                    .map(|line| line as u32)
+                    .collect();
+                // The breakpoints are handed to the VM when the script starts
+                let verified = self.actions.is_none();
+                let breakpoints = lines
+                    .iter()
+                    .map(|line| json!({"verified": verified, "line": line}))
+                    .collect::<Vec<_>>();
+                if verified {
+                    self.breakpoints.push((path, lines));
+                }
+                Ok(json!({"breakpoints": breakpoints}))
+            }
+            "configurationDone" => {
+                self.start();
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "main"}]})),
+            "stackTrace" => {
+                let frames = self
+                    .paused
+                    .iter()
+                    .flat_map(|paused| paused.frames.iter().enumerate())
+                    .map(|(index, frame)| {
+                        json!({
+                            "id": index,
+                            "name": frame.function,
+                            "line": frame.line,
+                            "column": 1,
+                            "source": frame.file.as_ref().map(|file| json!({"path": file})),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!({"totalFrames": frames.len(), "stackFrames": frames}))
+            }
+            "scopes" => {
+                let frame = arguments["frameId"].as_u64().unwrap_or_default();
+                Ok(json!({"scopes": [
+                    {"name": "Locals", "variablesReference": frame + 2, "expensive": false},
+                    {"name": "Globals", "variablesReference": GLOBALS_REFERENCE, "expensive": false},
+                ]}))
+            }
+            "variables" => {
+                let reference = arguments["variablesReference"].as_u64().unwrap_or_default();
+                let variables = self
+                    .paused
+                    .as_ref()
+                    .and_then(|paused| match reference {
+                        GLOBALS_REFERENCE => Some(&paused.globals),
+                        reference => paused
+                            .frames
+                            .get(reference.checked_sub(2)? as usize)
+                            .map(|frame| &frame.locals),
+                    })
+                    .into_iter()
+                    .flatten()
+                    .map(|variable| {
+                        json!({"name": variable.name, "value": variable.value, "variablesReference": 0})
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!({"variables": variables}))
+            }
+            "continue" => self
+                .resume(DebugAction::Continue)
+                .map(|_| json!({"allThreadsContinued": true})),
+            "next" => self.resume(DebugAction::Next),
+            "stepIn" => self.resume(DebugAction::StepIn),
+            "stepOut" => self.resume(DebugAction::StepOut),
+            "disconnect" | "terminate" => {
+                if let Some(actions) = self.actions.take() {
+                    let _ = actions.send(DebugAction::Stop);
+                }
+                self.respond(request, Ok(json!({})))?;
+                return Ok(command == "terminate");
+            }
+            command => Err(format!("Unsupported request '{command}'.")),
+        };
+        self.respond(request, body)?;
+        Ok(true)
+    }
+
+    // Run the launched script on its own thread.
+    fn start(&mut self) {
+        let Some(program) = self.program.clone() else {
+            return;
+        };
+        let (actions, receiver) = mpsc::channel();
+        self.actions = Some(actions);
+        let events = self.events.clone();
+        let new_vm = self.new_vm.clone();
+        let stop_on_entry = self.stop_on_entry;
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        let handle = Handle::current();
+        thread::spawn(move || {
+            let _guard = handle.enter();
+            let mut debugger = Debugger::new(ChannelHandler {
+                events: events.clone(),
+                actions: receiver,
+            });
+            if stop_on_entry {
+                debugger = debugger.stop_on_entry();
+            }
+            for (file, lines) in breakpoints {
+                debugger.set_breakpoints(&file, lines);
+            }
+            let mut vm = new_vm();
+            vm.set_debugger(debugger);
+            let result = vm.try_run_file(&program).map_err(|err| match err {
+                VmError::CompileError => "Failed to compile the program.".to_string(),
+                VmError::RuntimeError(message) => message,
+                VmError::Traced(error) => error.to_string(),
+                err => err.to_string(),
+            });
+            let _ = events.send(Event::Ended(result));
+        });
+    }
+
+    fn resume(&mut self, action: DebugAction) -> Result<Value, String> {
+        self.paused = None;
+        self.actions
+            .as_ref()
+            .and_then(|actions| actions.send(action).ok())
+            .map(|_| json!({}))
+            .ok_or_else(|| "The program isn't running.".to_string())
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+        self.send(response)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.send(json!({"type": "event", "event": event, "body": body}))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let content = message.to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{content}",
+            content.len()
+        )?;
+        self.writer.flush()
+    }
+}
+
+// Read the messages framed by their `Content-Length` header.
+fn read_messages(mut reader: BufReader<TcpStream>, events: Sender<Event>) {
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            match reader.read_line(&mut header) {
+                Ok(0) | Err(_) => {
+                    let _ = events.send(Event::Closed);
+                    return;
+                }
+                Ok(_) => {}
+            }
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        if length > MAX_MESSAGE_LENGTH {
+            let _ = events.send(Event::Closed);
+            return;
+        }
+        let mut content = vec![0; length];
+        if reader.read_exact(&mut content).is_err() {
+            let _ = events.send(Event::Closed);
+            return;
+        }
+        if let Ok(request) = serde_json::from_slice(&content)
+            && events.send(Event::Request(request)).is_err()
+        {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use super::*;
+
+    fn send(stream: &mut TcpStream, message: Value) {
+        let content = message.to_string();
+        write!(stream, "Content-Length: {}\r\n\r\n{content}", content.len()).unwrap();
+    }
+
+    fn receive(reader: &mut BufReader<TcpStream>) -> Value {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            match header.trim_end() {
+                "" => break,
+                header => {
+                    length = header["Content-Length:".len()..].trim().parse().unwrap();
+                }
+            }
+        }
+        let mut content = vec![0; length];
+        reader.read_exact(&mut content).unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+
+    // The next message of the type and name, the others are skipped.
+    fn expect(reader: &mut BufReader<TcpStream>, kind: &str, name: &str) -> Value {
+        loop {
+            let message = receive(reader);
+            let key = if kind == "event" { "event" } else { "command" };
+            if message["type"] == kind && message[key] == name {
+                return message;
+            }
+        }
+    }
+
+    fn connect() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("main.ai");
+        fs::write(&program, "let a = 1;\nlet b = a + 1;\nprint(b);\n").unwrap();
+
+        let (mut client, server) = connect();
+        let session = tokio::task::spawn_blocking(move || {
+            Session::new(server, Arc::new(Vm::default)).unwrap().run()
+        });
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+
+        send(&mut client, json!({"seq": 1, "command": "initialize"}));
+        expect(&mut reader, "event", "initialized");
+        send(
+            &mut client,
+            json!({"seq": 2, "command": "launch", "arguments": {"program": program}}),
+        );
+        assert_eq!(expect(&mut reader, "response", "launch")["success"], true);
+        send(
+            &mut client,
+            json!({"seq": 3, "command": "setBreakpoints", "arguments": {
+                "source": {"path": program},
+                "breakpoints": [{"line": 3}],
+            }}),
+        );
+        let breakpoints = expect(&mut reader, "response", "setBreakpoints");
+        assert_eq!(
+            breakpoints["body"]["breakpoints"],
+            json!([{"verified": true, "line": 3}])
+        );
+        send(
+            &mut client,
+            json!({"seq": 4, "command": "configurationDone"}),
+        );
+
+        let stopped = expect(&mut reader, "event", "stopped");
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+        send(
+            &mut client,
+            json!({"seq": 5, "command": "variables", "arguments": {"variablesReference": GLOBALS_REFERENCE}}),
+        );
+        let variables = expect(&mut reader, "response", "variables");
+        let b = variables["body"]["variables"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|variable| variable["name"] == "b")
+            .cloned();
+        assert_eq!(b.unwrap()["value"], "2");
+
+        send(&mut client, json!({"seq": 6, "command": "continue"}));
+        let exited = expect(&mut reader, "event", "exited");
+        assert_eq!(exited["body"]["exitCode"], 0);
+        send(&mut client, json!({"seq": 7, "command": "terminate"}));
+        expect(&mut reader, "response", "terminate");
+        send(&mut client, json!({"seq": 8, "command": "disconnect"}));
+        expect(&mut reader, "response", "disconnect");
+        session.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_message_too_long() {
+        let (mut client, server) = connect();
+        let (events, receiver) = mpsc::channel();
+        let reader = BufReader::new(server);
+        let handle = thread::spawn(move || read_messages(reader, events));
+        write!(client, "Content-Length: {}\r\n\r\n", usize::MAX).unwrap();
+        assert!(matches!(receiver.recv().unwrap(), Event::Closed));
+        handle.join().unwrap();
+    }
+}
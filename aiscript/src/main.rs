@@ -1,16 +1,22 @@
-use std::{path::PathBuf, process};
+use std::{env, path::PathBuf, process, time::Duration};
 
-use aiscript_runtime::Config;
-use aiscript_vm::Vm;
+use aiscript_runtime::{Config, LoadTest, Workspace};
+use aiscript_vm::{Vm, VmOptions};
 
 use clap::{Parser, Subcommand};
 use repr::Repl;
 use tokio::task;
 
+mod coverage;
+mod dap;
 mod project;
+mod prompts;
 mod repr;
+mod workspace;
 
+use coverage::{CoverageCommand, CoverageFormat};
 use project::ProjectGenerator;
+use prompts::PromptsCommand;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -18,6 +24,41 @@ struct AIScriptCli {
     /// Sets a custom config file
     #[arg(value_name = "FILE")]
     file: Option<PathBuf>,
+    /// The arguments of the script, available as `std.os.args`.
+    #[arg(
+        value_name = "ARGS",
+        requires = "file",
+        trailing_var_arg = true,
+        allow_hyphen_values = true
+    )]
+    args: Vec<String>,
+    /// Record provider responses and tool results of the run into a trace file.
+    #[arg(long, value_name = "TRACE", conflicts_with = "replay")]
+    record: Option<PathBuf>,
+    /// Replay the run from a recorded trace file without calling the provider.
+    #[arg(long, value_name = "TRACE")]
+    replay: Option<PathBuf>,
+    /// Type check the file and report the compile-time warnings without running it.
+    #[arg(long, conflicts_with = "strict")]
+    check: bool,
+    /// Type check the file before running it, the run is aborted on type errors
+    /// and on matches missing some variants of an enum.
+    #[arg(long)]
+    strict: bool,
+    /// Run the file without network, file system, environment and process access.
+    #[arg(long)]
+    sandbox: bool,
+    /// Log every executed opcode with its function and line to stderr.
+    #[arg(long)]
+    trace: bool,
+    /// Profile the run and write the time of each call stack to the file in the
+    /// folded stacks format, e.g. for `inferno-flamegraph`.
+    #[arg(long, value_name = "OUT")]
+    profile: Option<PathBuf>,
+    /// Start a debug adapter on the port, editors attach to it with the
+    /// Debug Adapter Protocol to launch and debug the scripts.
+    #[arg(long, value_name = "PORT", num_args = 0..=1, default_missing_value = "4711")]
+    debug_adapter: Option<u16>,
     /// Subcommands
     #[command(subcommand)]
     command: Option<Commands>,
@@ -36,6 +77,67 @@ enum Commands {
         /// Reload the file on change
         #[arg(short, long, default_value_t = false)]
         reload: bool,
+        /// Serve the @mock examples of the endpoints without running their handlers.
+        #[arg(long, default_value_t = false)]
+        mock: bool,
+        /// Inject the latencies and errors of the [chaos] section of project.toml
+        /// into the AI, HTTP and database calls, for development only.
+        #[arg(long, default_value_t = false)]
+        chaos: bool,
+        /// Check the AI providers, the database and Redis of project.toml
+        /// are available before accepting traffic, exit if one isn't.
+        #[arg(long, default_value_t = false)]
+        preflight: bool,
+        /// Serve the member of the workspace rather than the current project.
+        #[arg(long, value_name = "NAME")]
+        member: Option<String>,
+    },
+    /// Test the routes.
+    Test {
+        /// The route file to test, all the routes by default.
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+        /// Send the examples of the OpenAPI document to the endpoints and
+        /// check their responses against the documented ones.
+        #[arg(long, default_value_t = false)]
+        contract: bool,
+        /// Test the member of the workspace, all the members by default at
+        /// the root of the workspace.
+        #[arg(long, value_name = "NAME")]
+        member: Option<String>,
+    },
+    /// Compile the routes and the agents of the project without serving them.
+    Check {
+        /// Check the member of the workspace, all the members by default at
+        /// the root of the workspace.
+        #[arg(long, value_name = "NAME")]
+        member: Option<String>,
+    },
+    /// Send requests at a constant rate to a route, or to a remote server,
+    /// and report the latency percentiles and the error rate.
+    Loadtest {
+        /// The path of the route served in process, e.g. `/users/1`, or the
+        /// URL of a remote server.
+        #[arg(value_name = "TARGET")]
+        target: String,
+        /// The request method.
+        #[arg(short = 'X', long, default_value = "GET")]
+        method: String,
+        /// The JSON body of the requests.
+        #[arg(short, long)]
+        body: Option<String>,
+        /// The requests sent per second.
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        rps: u32,
+        /// How long to send requests, e.g. `30s`, `500ms` or `5m`.
+        #[arg(long, default_value = "10s", value_parser = parse_duration)]
+        duration: Duration,
+    },
+    /// Print the compiled bytecode of the file without running it.
+    Disasm {
+        /// The file to disassemble.
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
     },
     /// Create a new AIScript project with a standard directory structure.
     New {
@@ -43,18 +145,126 @@ enum Commands {
         #[arg(value_name = "PROJECT_NAME")]
         name: String,
     },
+    /// Report the lines run by the scripts and the contract tests, recorded
+    /// into the coverage trace by a build with the `coverage` feature.
+    Coverage {
+        /// The coverage trace, `$AISCRIPT_COVERAGE` or `.aiscript/coverage.json` by default.
+        #[arg(long, value_name = "TRACE")]
+        trace: Option<PathBuf>,
+        /// The format of the report.
+        #[arg(long, value_enum, default_value_t = CoverageFormat::Lcov)]
+        format: CoverageFormat,
+        /// The report file, `lcov.info` or `coverage.html` by default.
+        #[arg(short, long, value_name = "OUT")]
+        output: Option<PathBuf>,
+    },
+    /// Inspect the recorded versions of agent instructions.
+    Prompts {
+        #[command(subcommand)]
+        command: PromptsCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum PromptsCommands {
+    /// List the instruction versions of an agent and the runs each one served.
+    Log {
+        /// The agent name.
+        #[arg(value_name = "AGENT")]
+        agent: String,
+    },
+    /// Show the diff between two instruction versions of an agent.
+    Diff {
+        /// The agent name.
+        #[arg(value_name = "AGENT")]
+        agent: String,
+        /// The old version, defaults to the one before the latest.
+        #[arg(value_name = "FROM")]
+        from: Option<String>,
+        /// The new version, defaults to the latest.
+        #[arg(value_name = "TO")]
+        to: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = AIScriptCli::parse();
+    let workspace = enter_workspace(&cli.command);
     dotenv::dotenv().ok();
     let config = Config::load();
+    aiscript_vm::set_experimental(config.language.experimental);
+    aiscript_vm::set_kv_path(config.kv.path.clone());
+    aiscript_vm::set_template_dir(config.template.dir.clone());
 
-    let cli = AIScriptCli::parse();
     match cli.command {
-        Some(Commands::Serve { file, port, reload }) => {
+        Some(Commands::Serve {
+            file,
+            port,
+            reload,
+            mock,
+            chaos,
+            preflight,
+            ..
+        }) => {
+            if preflight && !aiscript_runtime::run_preflight().await {
+                process::exit(1);
+            }
             let port = port.unwrap_or(config.network.port);
-            aiscript_runtime::run(file, port, reload).await;
+            aiscript_runtime::run(file, port, reload, mock, chaos).await;
+        }
+        Some(Commands::Test { file, contract, .. }) => {
+            if !contract {
+                eprintln!("Nothing to test, pass --contract to run the contract tests.");
+                process::exit(2);
+            }
+            let passed = match workspace {
+                Some(workspace) if file.is_none() => {
+                    workspace::run_in_members(&workspace, &["test", "--contract"])
+                }
+                Some(_) => {
+                    eprintln!("Pass --member to test a file of a member of the workspace.");
+                    process::exit(2);
+                }
+                None => aiscript_runtime::run_contract_tests(file).await,
+            };
+            if !passed {
+                process::exit(1);
+            }
+        }
+        Some(Commands::Check { .. }) => {
+            let passed = match workspace {
+                Some(workspace) => workspace::run_in_members(&workspace, &["check"]),
+                None => aiscript_runtime::check_routes(),
+            };
+            if !passed {
+                process::exit(1);
+            }
+        }
+        Some(Commands::Loadtest {
+            target,
+            method,
+            body,
+            rps,
+            duration,
+        }) => {
+            let Ok(method) = method.to_uppercase().parse() else {
+                eprintln!("Invalid request method: {method}");
+                process::exit(2);
+            };
+            let test = LoadTest {
+                target,
+                method,
+                body,
+                rps,
+                duration,
+            };
+            if !aiscript_runtime::run_load_test(test).await {
+                process::exit(1);
+            }
+        }
+        Some(Commands::Disasm { file }) => {
+            Vm::default().disassemble_file(file);
         }
         Some(Commands::New { name }) => {
             let generator = ProjectGenerator::new(&name);
@@ -63,8 +273,67 @@ async fn main() {
                 process::exit(1);
             }
         }
+        Some(Commands::Coverage {
+            trace,
+            format,
+            output,
+        }) => {
+            let trace = trace.unwrap_or_else(aiscript_vm::coverage_trace_path);
+            match CoverageCommand::new(&trace).and_then(|command| command.report(format, output)) {
+                Ok(summary) => println!("{}", summary),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Prompts { command }) => {
+            let result =
+                PromptsCommand::new(config.ai.prompt_history.clone()).and_then(|prompts| {
+                    match command {
+                        PromptsCommands::Log { agent } => prompts.log(&agent),
+                        PromptsCommands::Diff { agent, from, to } => {
+                            prompts.diff(&agent, from.as_deref(), to.as_deref())
+                        }
+                    }
+                });
+            match result {
+                Ok(output) => println!("{}", output),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
         None => {
-            if let Some(path) = cli.file {
+            if let Some(port) = cli.debug_adapter {
+                let pg_connection = aiscript_runtime::get_pg_connection().await;
+                let sqlite_connection = aiscript_runtime::get_sqlite_connection().await;
+                let redis_connection = aiscript_runtime::get_redis_connection().await;
+                let result = task::spawn_blocking(move || {
+                    dap::serve(port, move || {
+                        let mut vm = Vm::new(
+                            pg_connection.clone(),
+                            sqlite_connection.clone(),
+                            redis_connection.clone(),
+                            config.ai.clone(),
+                        );
+                        vm.set_strict(cli.strict);
+                        if cli.sandbox {
+                            vm.set_options(VmOptions::sandboxed());
+                        }
+                        vm
+                    })
+                })
+                .await
+                .unwrap();
+                if let Err(e) = result {
+                    eprintln!("Debug adapter failed: {}", e);
+                    process::exit(1);
+                }
+            } else if let Some(path) = cli.file.clone().filter(|_| cli.check) {
+                Vm::default().check_file(path);
+            } else if let Some(path) = cli.file {
                 let pg_connection = aiscript_runtime::get_pg_connection().await;
                 let sqlite_connection = aiscript_runtime::get_sqlite_connection().await;
                 let redis_connection = aiscript_runtime::get_redis_connection().await;
@@ -75,14 +344,30 @@ async fn main() {
                         redis_connection,
                         config.ai.clone(),
                     );
+                    vm.set_strict(cli.strict);
+                    vm.set_trace(cli.trace);
+                    vm.set_args(cli.args);
+                    if cli.sandbox {
+                        vm.set_options(VmOptions::sandboxed());
+                    }
+                    if let Some(out) = cli.profile {
+                        vm.profile(out);
+                    }
+                    if let Some(trace) = cli.record {
+                        vm.record_trace(trace);
+                    }
+                    if let Some(Err(e)) = cli.replay.map(|trace| vm.replay_trace(trace)) {
+                        eprintln!("Failed to load replay trace: {}", e);
+                        process::exit(1);
+                    }
                     vm.run_file(path);
                 })
                 .await // must use await to wait for the thread to finish
                 .unwrap();
             } else {
-                // Run the repl
-                let mut repl = Repl::new();
-                if let Err(e) = repl.run() {
+                // Run the repl off the async main thread, the natives block on the runtime
+                let result = task::spawn_blocking(|| Repl::new().run()).await.unwrap();
+                if let Err(e) = result {
                     eprintln!("Error: {}", e);
                     process::exit(1);
                 }
@@ -90,3 +375,83 @@ async fn main() {
         }
     }
 }
+
+// Enter the directory of the member of the workspace given with `--member`,
+// and share the modules of the workspace with the project. Returns the
+// workspace to run the command in all of its members, when run from its
+// root without a member. Only `serve`, `test` and `check` look up the
+// workspace, an unrelated workspace.toml doesn't get in the way of the
+// other commands.
+fn enter_workspace(command: &Option<Commands>) -> Option<Workspace> {
+    let member = match command {
+        Some(Commands::Serve { member, .. })
+        | Some(Commands::Test { member, .. })
+        | Some(Commands::Check { member, .. }) => member.as_deref(),
+        _ => return None,
+    };
+    let workspace = match Workspace::current() {
+        Some(Ok(workspace)) => workspace,
+        Some(Err(e)) => {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        None if member.is_some() => {
+            eprintln!("--member is only allowed in a workspace, no workspace.toml found.");
+            process::exit(2);
+        }
+        None => return None,
+    };
+    aiscript_vm::set_module_paths(vec![workspace.root.clone()]);
+
+    if let Some(name) = member {
+        let dir = workspace
+            .member(name)
+            .map(|member| workspace.member_dir(member))
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                process::exit(2);
+            });
+        if let Err(e) = env::set_current_dir(&dir) {
+            eprintln!("Failed to enter the member '{name}': {e}");
+            process::exit(1);
+        }
+        return None;
+    }
+    let at_root = env::current_dir().is_ok_and(|dir| workspace.is_root(&dir));
+    if at_root && matches!(command, Some(Commands::Serve { .. })) {
+        let names: Vec<_> = workspace.members.iter().map(|m| m.name.as_str()).collect();
+        eprintln!(
+            "Pass --member to serve a member of the workspace: {}",
+            names.join(", ")
+        );
+        process::exit(2);
+    }
+    let runs_in_members = matches!(
+        command,
+        Some(Commands::Test { .. }) | Some(Commands::Check { .. })
+    );
+    (at_root && runs_in_members).then_some(workspace)
+}
+
+// Parse a duration of the command line, e.g. "30s", "500ms", "5m" or "1h".
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration '{value}'"))?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => {
+            return Err(format!(
+                "invalid duration unit '{unit}', expect ms, s, m or h"
+            ));
+        }
+    };
+    Ok(Duration::from_secs_f64(secs))
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use aiscript_vm::{PromptHistory, PromptVersion};
+use similar::{ChangeTag, TextDiff};
+
+pub struct PromptsCommand {
+    history: PromptHistory,
+}
+
+impl PromptsCommand {
+    pub fn new(dir: Option<PathBuf>) -> Result<Self, String> {
+        let dir = dir.ok_or_else(|| {
+            "Prompt history isn't enabled, set `prompt_history` in the [ai] section of project.toml"
+                .to_string()
+        })?;
+        Ok(Self {
+            history: PromptHistory::new(dir),
+        })
+    }
+
+    fn versions(&self, agent: &str) -> Result<Vec<PromptVersion>, String> {
+        let versions = self
+            .history
+            .versions(agent)
+            .map_err(|e| format!("Failed to read the prompt history of '{}': {}", agent, e))?;
+        if versions.is_empty() {
+            return Err(format!("No recorded instructions of agent '{}'", agent));
+        }
+        Ok(versions)
+    }
+
+    /// List the recorded instruction versions of an agent and the number of runs they served.
+    pub fn log(&self, agent: &str) -> Result<String, String> {
+        let served = self
+            .history
+            .served()
+            .map_err(|e| format!("Failed to read the served prompts: {}", e))?;
+        let versions = self.versions(agent)?;
+        let latest = versions.len() - 1;
+        let lines = versions
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, version)| {
+                let runs = served
+                    .iter()
+                    .filter(|s| s.agent == agent && s.version == version.version)
+                    .count();
+                format!(
+                    "{}  {}  served {}{}",
+                    version.version,
+                    version.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    runs,
+                    if i == latest { "  (latest)" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>();
+        Ok(lines.join("\n"))
+    }
+
+    /// Diff two instruction versions of an agent, matched by version prefix.
+    /// Compare the previous version with the latest one by default.
+    pub fn diff(
+        &self,
+        agent: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<String, String> {
+        let versions = self.versions(agent)?;
+        let find = |prefix: &str| {
+            versions
+                .iter()
+                .find(|v| v.version.starts_with(prefix))
+                .ok_or_else(|| format!("No version '{}' of agent '{}'", prefix, agent))
+        };
+        let to = match to {
+            Some(to) => find(to)?,
+            None => versions.last().unwrap(),
+        };
+        let from = match from {
+            Some(from) => find(from)?,
+            None if versions.len() > 1 => &versions[versions.len() - 2],
+            None => return Err(format!("Agent '{}' has only one version", agent)),
+        };
+
+        let mut output = format!("--- {}\n+++ {}\n", from.version, to.version);
+        for change in TextDiff::from_lines(&from.instructions, &to.instructions).iter_all_changes()
+        {
+            let sign = match change.tag() {
+                ChangeTag::Delete => "-",
+                ChangeTag::Insert => "+",
+                ChangeTag::Equal => " ",
+            };
+            output.push_str(sign);
+            output.push_str(change.as_str().unwrap_or_default().trim_end_matches('\n'));
+            output.push('\n');
+        }
+        Ok(output.trim_end().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_prompts_log_and_diff() {
+        let temp_dir = tempdir().unwrap();
+        let command = PromptsCommand::new(Some(temp_dir.path().to_path_buf())).unwrap();
+        let v1 = command
+            .history
+            .serve("Triage", "You are a triage agent.\nBe brief.", None)
+            .unwrap();
+        let v2 = command
+            .history
+            .serve("Triage", "You are a triage agent.\nBe polite.", None)
+            .unwrap();
+
+        let log = command.log("Triage").unwrap();
+        let lines = log.lines().collect::<Vec<_>>();
+        assert!(lines[0].starts_with(&v2) && lines[0].ends_with("served 1  (latest)"));
+        assert!(lines[1].starts_with(&v1) && lines[1].ends_with("served 1"));
+
+        assert_eq!(
+            command.diff("Triage", None, None).unwrap(),
+            format!("--- {v1}\n+++ {v2}\n You are a triage agent.\n-Be brief.\n+Be polite.")
+        );
+        assert!(command.diff("Triage", Some("zzz"), None).is_err());
+        assert!(command.log("Unknown").is_err());
+        assert!(PromptsCommand::new(None).is_err());
+    }
+}
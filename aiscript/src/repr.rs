@@ -144,26 +144,17 @@ impl Repl {
     }
 
     fn execute_code(&mut self, code: &str) -> Result<(), VmError> {
-        // Wrap the code in a function if it's an expression
-        let code = if !code.contains(';') && !code.contains("fn ") && !code.contains("class ") {
-            format!("return {};", code)
-        } else {
-            code.to_string()
-        };
-
-        match self.vm.compile(Box::leak(code.into_boxed_str())) {
-            Ok(()) => {
-                match self.vm.interpret() {
-                    Ok(value) => {
-                        // Only print non-nil values
-                        if !matches!(value, ReturnValue::Nil) {
-                            println!("{}", value);
-                        }
-                    }
-                    Err(e) => eprintln!("Runtime error: {}", e),
+        // The globals of the previous inputs stay defined
+        match self.vm.eval_incremental(code) {
+            Ok(value) => {
+                // Only print non-nil values
+                if !matches!(value, ReturnValue::Nil) {
+                    println!("{}", value);
                 }
             }
-            Err(e) => eprintln!("Compile error: {}", e),
+            // The compile errors are already reported
+            Err(VmError::CompileError) => {}
+            Err(e) => eprintln!("Runtime error: {}", e),
         }
 
         Ok(())
@@ -0,0 +1,46 @@
+use std::{env, process::Command};
+
+use aiscript_runtime::Workspace;
+
+/// Run the command in each member of the workspace, e.g. `check` or
+/// `test --contract`, in a process of its own so every member loads its
+/// own project.toml. Returns whether it succeeded in all of them.
+pub fn run_in_members(workspace: &Workspace, args: &[&str]) -> bool {
+    if workspace.members.is_empty() {
+        eprintln!("No members in the workspace, list them in `members` of workspace.toml.");
+        return false;
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("Failed to find the aiscript executable: {e}");
+            return false;
+        }
+    };
+
+    let mut failed = Vec::new();
+    for member in &workspace.members {
+        println!("==> {} ({})", member.name, member.path.display());
+        let status = Command::new(&exe)
+            .args(args)
+            .current_dir(workspace.member_dir(member))
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(_) => failed.push(member.name.as_str()),
+            Err(e) => {
+                eprintln!("Failed to run the member '{}': {e}", member.name);
+                failed.push(member.name.as_str());
+            }
+        }
+        println!();
+    }
+
+    let passed = workspace.members.len() - failed.len();
+    if failed.is_empty() {
+        println!("{passed} member(s) passed");
+    } else {
+        println!("{passed} member(s) passed, failed: {}", failed.join(", "));
+    }
+    failed.is_empty()
+}
@@ -186,3 +186,157 @@ fn unescape_string(s: &str) -> String {
     }
     result
 }
+
+#[test]
+fn run_repl_blocking_natives() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let dir = env::temp_dir().join(format!("aiscript-repl-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("hello.txt");
+    fs::write(&file, "hello").unwrap();
+
+    // The natives blocking on the runtime run in the REPL too
+    let mut child = test_command()
+        .current_dir(&dir)
+        .env("HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = format!(
+        "use std.io;\nprint(io.read_file(\"{}\"));\n.exit\n",
+        file.display()
+    );
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_dir_all(&dir).ok();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "REPL failed: {stdout}");
+    assert!(stdout.lines().any(|line| line == "hello"), "{stdout}");
+}
+
+// A workspace of the members `api` and `worker` with a route each.
+fn create_workspace(name: &str) -> PathBuf {
+    let root = env::temp_dir().join(format!("aiscript-{name}-{}", std::process::id()));
+    fs::remove_dir_all(&root).ok();
+    for member in ["api", "worker"] {
+        let routes = root.join("services").join(member).join("routes");
+        fs::create_dir_all(&routes).unwrap();
+        fs::write(
+            routes.join("hello.ai"),
+            format!("get /{member} {{\n    return \"hi\";\n}}\n"),
+        )
+        .unwrap();
+    }
+    fs::write(
+        root.join("workspace.toml"),
+        "[workspace]\nmembers = [\"services/api\", \"services/worker\"]\n",
+    )
+    .unwrap();
+    root
+}
+
+#[test]
+fn run_workspace_check_members() {
+    let root = create_workspace("check");
+    let output = test_command()
+        .arg("check")
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("2 member(s) passed"), "{stdout}");
+
+    // A single member, from any directory of the workspace
+    let output = test_command()
+        .args(["check", "--member", "api"])
+        .current_dir(root.join("services"))
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{stdout}");
+    assert_eq!(stdout.trim(), "1 endpoint(s) of 1 route file(s) compiled");
+
+    let output = test_command()
+        .args(["check", "--member", "unknown"])
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    fs::write(
+        root.join("services/worker/routes/broken.ai"),
+        "get /broken {\n    return 1 +;\n}\n",
+    )
+    .unwrap();
+    let output = test_command()
+        .arg("check")
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    fs::remove_dir_all(&root).ok();
+    assert!(!output.status.success(), "{stdout}");
+    assert!(
+        stdout.contains("1 member(s) passed, failed: worker"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn run_workspace_test_members() {
+    let root = create_workspace("test");
+    let output = test_command()
+        .args(["test", "--contract"])
+        .current_dir(&root)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    fs::remove_dir_all(&root).ok();
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("PASS GET /api"), "{stdout}");
+    assert!(stdout.contains("PASS GET /worker"), "{stdout}");
+    assert!(stdout.contains("2 member(s) passed"), "{stdout}");
+}
+
+#[test]
+fn run_outside_workspace() {
+    // A broken workspace.toml of a parent only matters to the workspace commands
+    let root = env::temp_dir().join(format!("aiscript-broken-{}", std::process::id()));
+    let dir = root.join("sub");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(root.join("workspace.toml"), "garbage").unwrap();
+    fs::write(dir.join("a.ai"), "print(1);\n").unwrap();
+    let output = test_command()
+        .arg("a.ai")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let check = test_command()
+        .arg("check")
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&root).ok();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert_eq!(check.status.code(), Some(1));
+
+    let dir = env::temp_dir();
+    let output = test_command()
+        .args(["check", "--member", "api"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}